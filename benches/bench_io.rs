@@ -229,6 +229,87 @@ fn bench_ms_write_mwax_part_1247842824(crt: &mut Criterion) {
     );
 }
 
+/// Compare preparing the main table rows of an averaged MS on a single thread
+/// with preparing them on the global rayon pool (the rows are always written
+/// serially). Scaling is only expected with the "rayon" feature, and is
+/// limited by the serial writes.
+fn bench_ms_write_averaging_mwax_part_1247842824(crt: &mut Criterion) {
+    let corr_ctx = get_context_mwax_half_1247842824();
+
+    let mut vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+    vis_sel.timestep_range = vis_sel.timestep_range.start
+        ..min(
+            vis_sel.timestep_range.start + TIMESTEP_LIMIT,
+            vis_sel.timestep_range.end,
+        );
+
+    let obs_ctx = ObsContext::from_mwalib(&corr_ctx.metafits_context);
+    let mwa_ctx = MwaObsContext::from_mwalib(&corr_ctx.metafits_context);
+
+    let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+    let shape = vis_sel.get_shape(fine_chans_per_coarse);
+    let (jones_array, weight_array, _) = synthesize_test_data(shape);
+
+    let vis_ctx = VisContext::from_mwalib(
+        &corr_ctx,
+        &vis_sel.timestep_range,
+        &vis_sel.coarse_chan_range,
+        &vis_sel.baseline_idxs,
+        2,
+        4,
+    );
+
+    let single_thread = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+
+    let mut group = crt.benchmark_group(format!(
+        "ms_write_averaging - mwax_half_1247842824 {shape:?}"
+    ));
+    for (name, pool) in [("single thread", Some(&single_thread)), ("rayon", None)] {
+        group.bench_function(name, |bch| {
+            bch.iter_batched(
+                || {
+                    let tmp_dir = tempdir().unwrap();
+                    let ms_path = tmp_dir.path().join("vis.ms");
+                    let mut ms_writer = MeasurementSetWriter::new(
+                        ms_path,
+                        obs_ctx.phase_centre,
+                        obs_ctx.array_pos,
+                        obs_ctx.ant_positions_geodetic().collect(),
+                        Duration::from_total_nanoseconds(0),
+                        true,
+                    );
+                    ms_writer
+                        .initialize_mwa(
+                            &vis_ctx,
+                            &obs_ctx,
+                            &mwa_ctx,
+                            None,
+                            &vis_sel.coarse_chan_range,
+                        )
+                        .unwrap();
+                    (tmp_dir, ms_writer)
+                },
+                |(_tmp_dir, mut ms_writer)| {
+                    let mut write = || {
+                        ms_writer
+                            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx)
+                            .unwrap()
+                    };
+                    match pool {
+                        Some(pool) => pool.install(write),
+                        None => write(),
+                    }
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
 fn bench_uvfits_write_mwax_part_1247842824(crt: &mut Criterion) {
     let corr_ctx = get_context_mwax_half_1247842824();
 
@@ -282,6 +363,42 @@ fn bench_uvfits_write_mwax_part_1247842824(crt: &mut Criterion) {
     );
 }
 
+/// Compare reading every image HDU of a gpubox file with [`MmapFits`] against
+/// reading them with fitsio's `read_image`. Needs the "mmap" feature.
+///
+/// [`MmapFits`]: marlu::io::MmapFits
+#[cfg(feature = "mmap")]
+fn bench_gpubox_read_1196175296(crt: &mut Criterion) {
+    use marlu::{fitsio::FitsFile, io::MmapFits};
+
+    let path = "tests/data/1196175296_mwa_ord/1196175296_20171201145440_gpubox01_00.fits";
+    let num_hdus = MmapFits::open(path).unwrap().hdus().len();
+
+    let mut group = crt.benchmark_group("gpubox_read - 1196175296");
+    group.bench_function("fitsio read_image", |bch| {
+        bch.iter(|| {
+            let mut fptr = FitsFile::open(path).unwrap();
+            for hdu_idx in 1..num_hdus {
+                let hdu = fptr.hdu(hdu_idx).unwrap();
+                let image: Vec<f32> = hdu.read_image(&mut fptr).unwrap();
+                black_box(image);
+            }
+        })
+    });
+    group.bench_function("MmapFits read_image_f32", |bch| {
+        bch.iter(|| {
+            let fits = MmapFits::open(path).unwrap();
+            for hdu_idx in 1..num_hdus {
+                black_box(fits.read_image_f32(hdu_idx).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+#[cfg(not(feature = "mmap"))]
+fn bench_gpubox_read_1196175296(_: &mut Criterion) {}
+
 criterion_group!(
     name = io;
     config = Criterion::default().sample_size(60);
@@ -289,7 +406,9 @@ criterion_group!(
         bench_ms_init_mwax_half_1247842824,
         bench_uvfits_init_mwax_half_1247842824,
         bench_ms_write_mwax_part_1247842824,
+        bench_ms_write_averaging_mwax_part_1247842824,
         bench_uvfits_write_mwax_part_1247842824,
+        bench_gpubox_read_1196175296,
 );
 
 criterion_main!(io);
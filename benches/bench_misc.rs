@@ -6,14 +6,16 @@
 
 use criterion::*;
 use hifitime::{Duration, Epoch};
+use rayon::ThreadPoolBuilder;
 
 use marlu::{
+    averaging::{average_visibilities, average_visibilities_with_options, AveragingOptions},
     c64,
     constants::{MWA_LAT_RAD, MWA_LONG_RAD},
-    ndarray::{Array1, Array3},
+    ndarray::{Array1, Array3, Array4},
     pos::xyz,
     precession::precess_time,
-    HADec, Jones, RADec, XyzGeodetic,
+    Complex, HADec, Jones, RADec, XyzGeodetic,
 };
 
 // /////////////////////// //
@@ -125,5 +127,94 @@ fn misc(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, misc);
+/// Average 8128 baselines (128 tiles, with autocorrelations) by 4 in time and
+/// frequency, with different numbers of threads. Scaling is only expected with
+/// the "rayon" feature.
+fn averaging(c: &mut Criterion) {
+    let shape = (4, 32, 8128, 4);
+    let jones_array = Array3::from_shape_fn((shape.0, shape.1, shape.2), |(t, c, b)| {
+        Jones::from([
+            Complex::new(t as f32, c as f32),
+            Complex::new(b as f32, 0.),
+            Complex::new(0., b as f32),
+            Complex::new(1., 1.),
+        ])
+    });
+    let weight_array = Array4::from_elem(shape, 1_f32);
+    let flag_array = Array4::from_shape_fn(shape, |(t, c, b, _)| (t + c + b) % 7 == 0);
+
+    let mut group = c.benchmark_group("average_visibilities 8128 baselines");
+    for num_threads in [1, 2, 4, 8] {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_threads),
+            &num_threads,
+            |b, _| {
+                b.iter(|| {
+                    pool.install(|| {
+                        black_box(
+                            average_visibilities(
+                                jones_array.view(),
+                                weight_array.view(),
+                                flag_array.view(),
+                                4,
+                                4,
+                            )
+                            .unwrap(),
+                        )
+                    })
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Compare the vectorised accumulation of the `simd` feature with the scalar
+/// loop. The default options take the vectorised path, while `skip_nan` always
+/// takes the scalar loop; there are no NaNs, so both average identically.
+/// Without the `simd` feature, both are scalar, which gives the (small) cost of
+/// the NaN checks.
+fn averaging_lanes(c: &mut Criterion) {
+    let shape = (8, 32, 2016, 4);
+    let jones_array = Array3::from_shape_fn((shape.0, shape.1, shape.2), |(t, c, b)| {
+        Jones::from([
+            Complex::new(t as f32, c as f32),
+            Complex::new(b as f32, 0.),
+            Complex::new(0., b as f32),
+            Complex::new(1., 1.),
+        ])
+    });
+    let weight_array = Array4::from_shape_fn(shape, |(t, c, b, p)| ((t + c + b + p) % 5) as f32);
+    let flag_array = Array4::from_shape_fn(shape, |(t, c, b, _)| (t + c + b) % 7 == 0);
+
+    let mut group = c.benchmark_group("average_visibilities_with_options lanes");
+    for (name, skip_nan) in [("default", false), ("skip_nan", true)] {
+        let options = AveragingOptions {
+            skip_nan,
+            ..AveragingOptions::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                black_box(
+                    average_visibilities_with_options(
+                        jones_array.view(),
+                        weight_array.view(),
+                        flag_array.view(),
+                        8,
+                        4,
+                        &options,
+                    )
+                    .unwrap(),
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, misc, averaging, averaging_lanes);
 criterion_main!(benches);
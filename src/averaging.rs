@@ -5,11 +5,22 @@
 //! Spectral and Temporal averaging
 
 use crate::Complex;
-use itertools::izip;
-use ndarray::prelude::*;
+use hifitime::{Duration, Epoch};
+use itertools::{izip, Either};
+use ndarray::{concatenate, prelude::*, Data, RemoveAxis};
+use num_traits::Float;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::{
+    f64::consts::PI,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use thiserror::Error;
 
-use crate::Jones;
+use crate::{
+    constants::{EARTH_ROTATION_RAD_S, VEL_C},
+    Jones, RADec, VisSelection, WeightScaling, LMN, UVW,
+};
 
 #[derive(Error, Debug)]
 pub enum AveragingError {
@@ -20,6 +31,28 @@ pub enum AveragingError {
         expected: String,
         received: String,
     },
+    #[error("the requested {axis} resolution {requested} is not a positive integer multiple of the native {axis} resolution {native}")]
+    NonIntegerResolution {
+        axis: &'static str,
+        requested: String,
+        native: String,
+    },
+    #[error("{num_chans} channels can't be divided into coarse channels of {fine_chans_per_coarse} fine channels")]
+    UnevenCoarseChans {
+        num_chans: usize,
+        fine_chans_per_coarse: usize,
+    },
+    #[error("the {axis} averaging factor given to {function} must be at least 1")]
+    ZeroAveragingFactor {
+        axis: &'static str,
+        function: String,
+    },
+    #[error("redundant group {group_idx} contains baseline {baseline_idx}, but there are only {num_baselines} baselines")]
+    BadBaselineIndex {
+        group_idx: usize,
+        baseline_idx: usize,
+        num_baselines: usize,
+    },
     // TODO: https://github.com/pkgw/rubbl/pull/148
     // #[error("{0}")]
     // RubblError(#[from] CasacoreError)
@@ -49,12 +82,7 @@ macro_rules! average_chunk_for_pols_f64 {
         $avg_weight_view:expr,
         $avg_flag_view:expr
     ) => {
-        let chunk_size = $jones_chunk.len();
-
-        let mut weight_sum = [0_f64; 4];
-        let mut jones_sum = Jones::<f64>::default();
-        let mut jones_weighted_sum = Jones::<f64>::default();
-        let mut all_flagged = true;
+        let mut accumulator = $crate::averaging::JonesAccumulator::new();
 
         for (jones_chunk, weight_chunk, flag) in izip!(
             $jones_chunk.axis_iter(Axis(0)),
@@ -66,48 +94,21 @@ macro_rules! average_chunk_for_pols_f64 {
                 weight_chunk.axis_iter(Axis(0)),
                 flag.axis_iter(Axis(0))
             ) {
-                let jones_c64 = Jones::<f64>::from(*jones);
-                jones_sum += jones_c64;
-                for (jones_elem, weight_elem, flag_elem, weighted_vis_sum, weight_sum) in izip!(
-                    jones_c64.iter(),
-                    weight.iter(),
-                    flag.iter(),
-                    jones_weighted_sum.iter_mut(),
-                    weight_sum.iter_mut(),
-                ) {
-                    let weight_f64: f64 = *weight_elem as _;
-
-                    if !flag_elem && *weight_elem >= 0. {
-                        *weighted_vis_sum += jones_elem * weight_f64;
-                        *weight_sum += weight_f64;
-                        all_flagged = false;
-                    }
-                }
+                accumulator.push(Jones::<f64>::from(*jones), weight, flag);
             }
         }
 
-        for (jones_weighted_sum, jones_sum, avg_weight_view, avg_jones, weight_sum) in izip!(
-            jones_weighted_sum.iter(),
-            jones_sum.iter(),
+        for (avg, avg_weight_view, avg_jones, weight_sum) in izip!(
+            accumulator.finalise().iter(),
             $avg_weight_view.iter_mut(),
             $avg_jones.iter_mut(),
-            weight_sum.iter()
+            accumulator.weight_sum().iter()
         ) {
-            *avg_jones = if !all_flagged {
-                Complex::<f32>::new(
-                    (jones_weighted_sum.re / weight_sum) as f32,
-                    (jones_weighted_sum.im / weight_sum) as f32,
-                )
-            } else {
-                Complex::<f32>::new(
-                    (jones_sum.re / chunk_size as f64) as f32,
-                    (jones_sum.im / chunk_size as f64) as f32,
-                )
-            };
+            *avg_jones = Complex::<f32>::new(avg.re as f32, avg.im as f32);
             *avg_weight_view = *weight_sum as f32;
         }
 
-        $avg_flag_view.fill(all_flagged);
+        $avg_flag_view.fill(accumulator.all_flagged());
     };
 }
 
@@ -178,8 +179,782 @@ macro_rules! average_chunk_f64 {
     };
 }
 
+/// Compute the weighted average of unflagged visibilities for each pol in a
+/// chunk. This is the function equivalent of [`average_chunk_for_pols_f64`].
+///
+/// If all visibilities in the chunk are flagged, then the unweighted average
+/// is used, and all pols of the output are flagged.
+///
+/// dimensions:
+/// - `jones_chunk` -> [at, af]
+/// - `weight_chunk` -> [at, af, pol]
+/// - `flag_chunk` -> [at, af, pol]
+/// - `avg_weights` -> [pol]
+/// - `avg_flags` -> [pol]
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the dimensions don't match.
+#[inline]
+pub fn average_chunk_for_pols(
+    jones_chunk: ArrayView2<Jones<f32>>,
+    weight_chunk: ArrayView3<f32>,
+    flag_chunk: ArrayView3<bool>,
+    avg_jones: &mut Jones<f32>,
+    mut avg_weights: ArrayViewMut1<f32>,
+    mut avg_flags: ArrayViewMut1<bool>,
+) -> Result<(), AveragingError> {
+    let (at, af) = jones_chunk.dim();
+    for (argument, received, expected) in [
+        ("weight_chunk", weight_chunk.shape(), [at, af, 4].as_slice()),
+        ("flag_chunk", flag_chunk.shape(), [at, af, 4].as_slice()),
+        ("avg_weights", avg_weights.shape(), [4].as_slice()),
+        ("avg_flags", avg_flags.shape(), [4].as_slice()),
+    ] {
+        if received != expected {
+            return Err(AveragingError::BadArrayShape {
+                argument: argument.to_string(),
+                function: "average_chunk_for_pols".to_string(),
+                expected: format!("{expected:?}"),
+                received: format!("{received:?}"),
+            });
+        }
+    }
+    average_chunk_for_pols_f64!(
+        jones_chunk,
+        weight_chunk,
+        flag_chunk,
+        avg_jones,
+        avg_weights,
+        avg_flags
+    );
+    Ok(())
+}
+
+/// Compute the weighted average of unflagged visibilities in a chunk, where a
+/// negative or zero weight is a flag. This is the function equivalent of
+/// [`average_chunk_f64`].
+///
+/// If all visibilities in the chunk are flagged, then the unweighted average
+/// is used, and the output is flagged.
+///
+/// dimensions:
+/// - `jones_chunk` -> [at, af]
+/// - `weight_chunk` -> [at, af]
+///
+/// Returns the averaged visibility, the sum of the unflagged weights, and the
+/// averaged flag.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the dimensions don't match.
+#[inline]
+pub fn average_chunk(
+    jones_chunk: ArrayView2<Jones<f32>>,
+    weight_chunk: ArrayView2<f32>,
+) -> Result<(Jones<f32>, f32, bool), AveragingError> {
+    if jones_chunk.dim() != weight_chunk.dim() {
+        return Err(AveragingError::BadArrayShape {
+            argument: "weight_chunk".to_string(),
+            function: "average_chunk".to_string(),
+            expected: format!("{:?}", jones_chunk.dim()),
+            received: format!("{:?}", weight_chunk.dim()),
+        });
+    }
+    let mut avg_jones = Jones::default();
+    let avg_weight: f32;
+    let mut avg_flag: bool;
+    average_chunk_f64!(jones_chunk, weight_chunk, avg_jones, avg_weight, avg_flag);
+    Ok((avg_jones, avg_weight, avg_flag))
+}
+
+/// Convert a target time resolution into a time averaging factor, given the
+/// native integration time. The conversion is exact (to the nanosecond), e.g.
+/// 8s of 2s integrations is a factor of 4, but 3s of 2s integrations is an
+/// error.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::NonIntegerResolution`] if `resolution` isn't a
+/// positive integer multiple of `int_time`.
+pub(crate) fn time_factor_from_resolution(
+    int_time: Duration,
+    resolution: Duration,
+) -> Result<usize, AveragingError> {
+    let native_ns = int_time.total_nanoseconds();
+    let requested_ns = resolution.total_nanoseconds();
+    if native_ns <= 0 || requested_ns <= 0 || requested_ns % native_ns != 0 {
+        return Err(AveragingError::NonIntegerResolution {
+            axis: "time",
+            requested: format!("{resolution}"),
+            native: format!("{int_time}"),
+        });
+    }
+    Ok((requested_ns / native_ns) as usize)
+}
+
+/// Convert a target frequency resolution \[Hz\] into a frequency averaging
+/// factor, given the native frequency resolution \[Hz\]. The ratio must be
+/// within a part per million of a positive integer.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::NonIntegerResolution`] if `resolution_hz`
+/// isn't a positive integer multiple of `freq_resolution_hz`.
+pub(crate) fn freq_factor_from_resolution(
+    freq_resolution_hz: f64,
+    resolution_hz: f64,
+) -> Result<usize, AveragingError> {
+    let ratio = resolution_hz / freq_resolution_hz;
+    let factor = ratio.round();
+    if !ratio.is_finite() || factor < 1.0 || (ratio - factor).abs() > 1e-6 * factor {
+        return Err(AveragingError::NonIntegerResolution {
+            axis: "frequency",
+            requested: format!("{resolution_hz}Hz"),
+            native: format!("{freq_resolution_hz}Hz"),
+        });
+    }
+    Ok(factor as usize)
+}
+
 pub type VisData344 = (Array3<Jones<f32>>, Array4<f32>, Array4<bool>);
 pub type VisData33 = (Array3<Jones<f32>>, Array3<f32>);
+pub type VisData333 = (Array3<Jones<f32>>, Array3<f32>, Array3<bool>);
+pub type VisData344F64 = (Array3<Jones<f64>>, Array4<f32>, Array4<bool>);
+
+/// The inputs and outputs of a single baseline of a chunk being averaged.
+type BaselineChunk<'a, F> = (
+    ArrayView2<'a, Jones<F>>,
+    ArrayView3<'a, f32>,
+    ArrayView3<'a, bool>,
+    ArrayViewMut0<'a, Jones<F>>,
+    ArrayViewMut1<'a, f32>,
+    ArrayViewMut1<'a, bool>,
+    Option<ArrayViewMut1<'a, u32>>,
+);
+
+/// Options for [`average_visibilities_with_options`]. The default options
+/// average identically to [`average_visibilities`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AveragingOptions<'a> {
+    /// Treat unflagged samples with a NaN visibility as flagged, rather than
+    /// propagating the NaN into the average. MWAX occasionally produces these.
+    pub skip_nan: bool,
+
+    /// Flag an averaged sample (for each pol) if fewer than this fraction of
+    /// its input samples were unflagged, like CASA's `minfrac`. The default of
+    /// 0 only flags averaged samples whose inputs are all flagged.
+    pub min_unflagged_fraction: f64,
+
+    /// Count the unflagged input samples of each averaged sample, and their
+    /// effective number of integrations. See [`AveragedVis::num_unflagged`].
+    pub sample_counts: bool,
+
+    /// Reject outlying samples from each chunk before averaging. See
+    /// [`SigmaClip`].
+    pub sigma_clip: Option<SigmaClip>,
+
+    /// Down-weight averaged samples by their expected time decorrelation. See
+    /// [`DecorrelationWeighting`]. This is ignored with
+    /// [`WeightMode::UnflaggedSampleCount`], as the weights are then counts.
+    pub decorrelation: Option<DecorrelationWeighting<'a>>,
+
+    /// Report progress while averaging, e.g. to drive a progress bar.
+    pub progress: Option<ProgressCallback<'a>>,
+
+    /// Average autocorrelations with their own rules, or skip them. See
+    /// [`AutoCorrelations`].
+    pub autos: Option<AutoCorrelations<'a>>,
+
+    /// Summarise the occupancy of the input visibilities. See
+    /// [`AveragedVis::stats`].
+    pub stats: bool,
+
+    /// What an averaged sample is when all of its inputs are flagged.
+    pub all_flagged: AllFlaggedPolicy,
+
+    /// What the averaged weights mean.
+    pub weight_mode: WeightMode,
+
+    /// The normalisation of the input weights. With
+    /// [`WeightMode::SumOfWeights`], summed [`WeightScaling::Unit`] weights are
+    /// corrected with [`WeightScaling::averaging_correction`] (for the actual
+    /// size of each chunk), so that they're still unit weights; the other
+    /// scalings are preserved by summing.
+    pub weight_scaling: WeightScaling,
+}
+
+/// The weight given to an averaged sample. Different consumers expect averaged
+/// weights to mean different things.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WeightMode {
+    /// The sum of the weights of the unflagged inputs, like Cotter (and CASA).
+    #[default]
+    SumOfWeights,
+
+    /// The mean weight of the unflagged inputs, zero if there are none.
+    MeanWeight,
+
+    /// The number of unflagged inputs, like the `nsample` array of pyuvdata.
+    UnflaggedSampleCount,
+}
+
+/// The visibility given to an averaged sample whose inputs are all flagged.
+/// The sample is flagged and zero-weighted regardless.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AllFlaggedPolicy {
+    /// The unweighted mean of the flagged inputs, like Cotter.
+    #[default]
+    GeometricMean,
+
+    /// Zero.
+    Zero,
+
+    /// NaN.
+    Nan,
+
+    /// The averaged visibility of the previous averaged timestep of the same
+    /// channel and baseline (which may itself have been propagated). The first
+    /// averaged timestep is zero.
+    PropagateLast,
+}
+
+/// The autocorrelations of visibilities being averaged, which are treated
+/// differently to cross-correlations.
+///
+/// An autocorrelation whose chunk is entirely flagged is averaged to zero,
+/// rather than the mean of the flagged samples (with
+/// [`AllFlaggedPolicy::GeometricMean`]), as a flagged auto is usually garbage
+/// (e.g. a dead receiver). The parallel-hand pols (XX and YY) of an
+/// averaged auto are real powers, so their imaginary parts are discarded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AutoCorrelations<'a> {
+    /// The tile pair of each baseline of the visibilities, e.g.
+    /// [`crate::VisContext::sel_baselines`]. Baselines of a tile with itself
+    /// are autocorrelations.
+    pub baselines: &'a [(usize, usize)],
+    /// Don't average the autocorrelations at all. Their averaged samples are
+    /// zero, zero-weighted and flagged.
+    pub skip: bool,
+}
+
+/// A callback reporting the progress of averaging. It is called with the
+/// number of completed and total chunks (of `avg_time` timesteps and
+/// `avg_freq` channels, for all baselines) each time a chunk is averaged.
+#[derive(Clone, Copy)]
+pub struct ProgressCallback<'a>(pub &'a (dyn Fn(usize, usize) + Sync));
+
+impl std::fmt::Debug for ProgressCallback<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+impl PartialEq for ProgressCallback<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(
+            self.0 as *const _ as *const u8,
+            other.0 as *const _ as *const u8,
+        )
+    }
+}
+
+/// The information needed to down-weight time-averaged visibilities by the
+/// amplitude lost to decorrelation, so that imaging weights are honest.
+///
+/// Phasing stops the fringes at the phase centre, so only emission away from
+/// it decorrelates. Over an averaging interval `Δt`, the phase of a source in
+/// `direction` winds at its residual fringe rate, so its amplitude is reduced
+/// by `sinc(π × rate × Δt)` (see [`time_decorrelation_factor`]). The weights of
+/// each averaged sample are multiplied by this factor, using the mean
+/// frequency and actual length of its chunk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecorrelationWeighting<'a> {
+    /// The UVWs \[metres\] of each baseline of the visibilities, e.g. at the
+    /// middle of the selection.
+    pub uvws: &'a [UVW],
+    /// The phase centre of the visibilities.
+    pub phase_centre: RADec,
+    /// The direction whose decorrelation is corrected for, e.g. the edge of
+    /// the field of interest.
+    pub direction: RADec,
+    /// The integration time of the input visibilities.
+    pub int_time: Duration,
+    /// The frequency \[Hz\] of each input channel.
+    pub freqs_hz: &'a [f64],
+}
+
+/// Iterative sigma-clipping of the samples of a chunk being averaged, for a
+/// cheap clean-up of RFI left over after flagging.
+///
+/// For each pol, the weighted mean and standard deviation (of the distance
+/// from the mean in the complex plane) of the unflagged samples are computed,
+/// and samples further than `threshold` standard deviations from the mean are
+/// treated as flagged. This is repeated until no more samples are rejected, or
+/// `max_iterations` is reached. Chunks with fewer than 3 unflagged samples
+/// aren't clipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SigmaClip {
+    /// The number of standard deviations beyond which a sample is rejected.
+    pub threshold: f64,
+    /// The maximum number of rejection passes.
+    pub max_iterations: usize,
+}
+
+impl Default for SigmaClip {
+    fn default() -> Self {
+        Self {
+            threshold: 3.0,
+            max_iterations: 5,
+        }
+    }
+}
+
+impl SigmaClip {
+    /// Reject outliers from `samples` of (visibility, weight), returning the
+    /// number rejected.
+    fn clip(&self, samples: &mut Vec<(Complex<f64>, f64)>) -> usize {
+        let mut num_clipped = 0;
+        for _ in 0..self.max_iterations {
+            let weight_sum: f64 = samples.iter().map(|&(_, w)| w).sum();
+            if samples.len() < 3 || weight_sum <= 0.0 {
+                break;
+            }
+            let mean = samples.iter().map(|&(v, w)| v * w).sum::<Complex<f64>>() / weight_sum;
+            let variance = samples
+                .iter()
+                .map(|&(v, w)| (v - mean).norm_sqr() * w)
+                .sum::<f64>()
+                / weight_sum;
+            let limit = self.threshold * variance.sqrt();
+
+            let num_before = samples.len();
+            samples.retain(|&(v, _)| (v - mean).norm() <= limit);
+            let num_rejected = num_before - samples.len();
+            if num_rejected == 0 {
+                break;
+            }
+            num_clipped += num_rejected;
+        }
+        num_clipped
+    }
+}
+
+/// The outputs of [`average_visibilities_with_options`].
+#[derive(Debug, Clone)]
+pub struct AveragedVis {
+    /// Averaged visibilities, `[timestep][channel][baseline]`.
+    pub jones: Array3<Jones<f32>>,
+    /// Averaged weights, `[timestep][channel][baseline][pol]`.
+    pub weights: Array4<f32>,
+    /// Averaged flags, `[timestep][channel][baseline][pol]`.
+    pub flags: Array4<bool>,
+    /// The number of unflagged input samples (counting each pol separately)
+    /// that were treated as flagged because they were NaN. See
+    /// [`AveragingOptions::skip_nan`].
+    pub num_nan_skipped: usize,
+    /// The number of unflagged input samples (counting each pol separately)
+    /// that were rejected by [`AveragingOptions::sigma_clip`].
+    pub num_clipped: usize,
+    /// The number of unflagged input samples of each averaged sample,
+    /// `[timestep][channel][baseline][pol]`, if
+    /// [`AveragingOptions::sample_counts`] was set.
+    pub num_unflagged: Option<Array4<u32>>,
+    /// The effective number of input integrations of each averaged sample,
+    /// `[timestep][channel][baseline][pol]`, if
+    /// [`AveragingOptions::sample_counts`] was set. This is the number of
+    /// unflagged input samples divided by the number of channels averaged
+    /// together. See [`AveragedVis::effective_int_times`].
+    pub effective_integrations: Option<Array4<f32>>,
+    /// The occupancy of the input visibilities, if [`AveragingOptions::stats`]
+    /// was set.
+    pub stats: Option<AveragingStats>,
+}
+
+/// Occupancy statistics of visibilities being averaged, e.g. for QA reports.
+///
+/// A sample (each pol of each visibility) counts as flagged if it's flagged,
+/// or if its weight is negative or NaN, as these are ignored when averaging.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AveragingStats {
+    /// The fraction of flagged samples of each input timestep.
+    pub flagged_fraction_per_timestep: Array1<f64>,
+    /// The fraction of flagged samples of each input channel.
+    pub flagged_fraction_per_chan: Array1<f64>,
+    /// The fraction of flagged samples of each baseline.
+    pub flagged_fraction_per_baseline: Array1<f64>,
+    /// The fraction of all samples that are flagged.
+    pub flagged_fraction: f64,
+}
+
+impl AveragingStats {
+    /// Compute the statistics of visibilities from their weights and flags,
+    /// both with dimensions `[timestep][channel][baseline][pol]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the dimensions of `weight_array` and `flag_array` differ.
+    pub fn from_weights_and_flags(
+        weight_array: ArrayView4<f32>,
+        flag_array: ArrayView4<bool>,
+    ) -> Self {
+        assert_eq!(weight_array.dim(), flag_array.dim());
+        let (num_timesteps, num_chans, num_baselines, num_pols) = flag_array.dim();
+        let mut per_timestep = Array1::<u64>::zeros(num_timesteps);
+        let mut per_chan = Array1::<u64>::zeros(num_chans);
+        let mut per_baseline = Array1::<u64>::zeros(num_baselines);
+        for (((t, c, b, _), &weight), &flag) in weight_array.indexed_iter().zip(flag_array.iter()) {
+            if flag || weight < 0. || weight.is_nan() {
+                per_timestep[t] += 1;
+                per_chan[c] += 1;
+                per_baseline[b] += 1;
+            }
+        }
+
+        let fraction = |count: u64, total: usize| {
+            if total == 0 {
+                0.
+            } else {
+                count as f64 / total as f64
+            }
+        };
+        let num_samples = flag_array.len();
+        Self {
+            flagged_fraction: fraction(per_timestep.sum(), num_samples),
+            flagged_fraction_per_timestep: per_timestep
+                .mapv(|count| fraction(count, num_chans * num_baselines * num_pols)),
+            flagged_fraction_per_chan: per_chan
+                .mapv(|count| fraction(count, num_timesteps * num_baselines * num_pols)),
+            flagged_fraction_per_baseline: per_baseline
+                .mapv(|count| fraction(count, num_timesteps * num_chans * num_pols)),
+        }
+    }
+}
+
+impl AveragedVis {
+    /// The effective integration time \[seconds\] of each averaged sample
+    /// after partial flagging, given the integration time of the input
+    /// visibilities. This is suitable for the `EXPOSURE` column of a
+    /// measurement set, or `INTTIM` of a uvfits file. Returns `None` unless
+    /// [`AveragingOptions::sample_counts`] was set.
+    pub fn effective_int_times(&self, int_time: Duration) -> Option<Array4<f64>> {
+        let int_time_s = int_time.to_seconds();
+        self.effective_integrations
+            .as_ref()
+            .map(|integrations| integrations.mapv(|n| n as f64 * int_time_s))
+    }
+}
+
+impl From<AveragedVis> for VisData344 {
+    fn from(averaged: AveragedVis) -> Self {
+        (averaged.jones, averaged.weights, averaged.flags)
+    }
+}
+
+/// Running sums of visibilities for a weighted average of each pol, e.g. over
+/// a chunk of timesteps and channels. All of the averaging code accumulates
+/// samples with this, so the rules for flags are the same everywhere.
+///
+/// Samples are [`push`](Self::push)ed in, and the average is
+/// [`finalise`](Self::finalise)d out. A sample of a pol is flagged if its flag
+/// is set, or if its weight is negative (or NaN); flagged samples only count
+/// towards the unweighted average, which is used if every sample is flagged.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct JonesAccumulator {
+    /// The sum of all samples, flagged or not.
+    sum: Jones<f64>,
+    /// The number of samples in `sum`.
+    num_samples: usize,
+    /// The sum of the weighted unflagged samples of each pol.
+    weighted_sum: Jones<f64>,
+    /// The sum of the weights of the unflagged samples of each pol.
+    weight_sum: [f64; 4],
+    /// The number of unflagged samples of each pol.
+    num_unflagged: [usize; 4],
+}
+
+impl JonesAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a visibility with the weights and flags of its pols.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn push(&mut self, jones: Jones<f64>, weights: ArrayView1<f32>, flags: ArrayView1<bool>) {
+        self.push_flagged(jones);
+        for (pol, (&vis, &weight, &flag)) in izip!(jones.iter(), weights, flags).enumerate() {
+            if flag || weight < 0. || weight.is_nan() {
+                continue;
+            }
+            self.push_pol(pol, vis, weight as f64);
+        }
+    }
+
+    /// Add a visibility to the unweighted average only, as if all of its pols
+    /// are flagged.
+    pub fn push_flagged(&mut self, jones: Jones<f64>) {
+        self.sum += jones;
+        self.num_samples += 1;
+    }
+
+    /// Add an unflagged sample of a single pol (the index into a [`Jones`])
+    /// to the weighted average. The caller is responsible for checking flags;
+    /// this is for when the samples are inspected first, e.g. for
+    /// sigma-clipping.
+    pub fn push_pol(&mut self, pol: usize, vis: Complex<f64>, weight: f64) {
+        self.weighted_sum[pol] += vis * weight;
+        self.weight_sum[pol] += weight;
+        self.num_unflagged[pol] += 1;
+    }
+
+    /// Are all of the samples of every pol flagged (or are there no samples)?
+    pub fn all_flagged(&self) -> bool {
+        self.num_unflagged.iter().all(|&n| n == 0)
+    }
+
+    /// The sum of the weights of the unflagged samples of each pol.
+    pub fn weight_sum(&self) -> [f64; 4] {
+        self.weight_sum
+    }
+
+    /// The number of unflagged samples of each pol.
+    pub fn num_unflagged(&self) -> [usize; 4] {
+        self.num_unflagged
+    }
+
+    /// The unweighted average of all samples, flagged or not.
+    pub fn unweighted_mean(&self) -> Jones<f64> {
+        self.sum / self.num_samples as f64
+    }
+
+    /// Get the weighted average of the unflagged samples of each pol, or the
+    /// [`unweighted_mean`](Self::unweighted_mean) if all samples are flagged.
+    /// Pols with unflagged samples which sum to a weight of 0 are NaN.
+    pub fn finalise(&self) -> Jones<f64> {
+        if self.all_flagged() {
+            return self.unweighted_mean();
+        }
+        let mut mean = self.weighted_sum;
+        for (mean, &weight_sum) in mean.iter_mut().zip(self.weight_sum.iter()) {
+            *mean /= weight_sum;
+        }
+        mean
+    }
+}
+
+/// The equivalent of [`average_chunk_for_pols_f64`] which respects
+/// [`AveragingOptions`], for `f32` or `f64` visibilities. `is_auto` applies the
+/// rules of [`AutoCorrelations`]. The number of
+/// unflagged samples of each pol are written to `avg_counts`, if given. Returns
+/// the number of NaN samples skipped and the number of samples rejected by
+/// sigma-clipping.
+#[allow(clippy::too_many_arguments)]
+fn average_chunk_for_pols_with_options<F>(
+    jones_chunk: ArrayView2<Jones<F>>,
+    weight_chunk: ArrayView3<f32>,
+    flag_chunk: ArrayView3<bool>,
+    options: &AveragingOptions,
+    avg_jones: &mut Jones<F>,
+    mut avg_weights: ArrayViewMut1<f32>,
+    mut avg_flags: ArrayViewMut1<bool>,
+    avg_counts: Option<ArrayViewMut1<u32>>,
+    is_auto: bool,
+) -> (usize, usize)
+where
+    F: Float,
+    Jones<f64>: From<Jones<F>>,
+    Jones<F>: From<Jones<f64>>,
+{
+    let chunk_size = jones_chunk.len();
+
+    let mut accumulator = JonesAccumulator::new();
+    let mut num_nan_skipped = 0;
+    // with sigma-clipping, the unflagged samples of each pol are collected
+    // and clipped before being accumulated.
+    let mut clip_samples: [Vec<(Complex<f64>, f64)>; 4] = std::array::from_fn(|_| vec![]);
+
+    if cfg!(feature = "simd") && !options.skip_nan && options.sigma_clip.is_none() {
+        accumulate_chunk_lanes(&jones_chunk, &weight_chunk, &flag_chunk, &mut accumulator);
+    } else {
+        for (jones, weights, flags) in
+            izip!(jones_chunk.iter(), weight_chunk.rows(), flag_chunk.rows())
+        {
+            let jones_c64 = Jones::<f64>::from(*jones);
+            accumulator.push_flagged(jones_c64);
+            for (pol, (jones_elem, &weight, &flag, samples)) in izip!(
+                jones_c64.iter(),
+                weights.iter(),
+                flags.iter(),
+                clip_samples.iter_mut(),
+            )
+            .enumerate()
+            {
+                if flag || weight < 0. || weight.is_nan() {
+                    continue;
+                }
+                if options.skip_nan && jones_elem.is_nan() {
+                    num_nan_skipped += 1;
+                    continue;
+                }
+                let weight_f64 = weight as f64;
+                if options.sigma_clip.is_some() {
+                    samples.push((*jones_elem, weight_f64));
+                    continue;
+                }
+                accumulator.push_pol(pol, *jones_elem, weight_f64);
+            }
+        }
+    }
+
+    let mut num_clipped = 0;
+    if let Some(sigma_clip) = options.sigma_clip.as_ref() {
+        for (pol, mut samples) in clip_samples.into_iter().enumerate() {
+            num_clipped += sigma_clip.clip(&mut samples);
+            for (vis, weight) in samples {
+                accumulator.push_pol(pol, vis, weight);
+            }
+        }
+    }
+
+    let all_flagged = accumulator.all_flagged();
+    let min_unflagged = options.min_unflagged_fraction * chunk_size as f64;
+    let mut result = Jones::<f64>::default();
+    for (mean, avg_weight, result, avg_flag, weight_sum, &num_unflagged) in izip!(
+        accumulator.finalise().iter(),
+        avg_weights.iter_mut(),
+        result.iter_mut(),
+        avg_flags.iter_mut(),
+        accumulator.weight_sum().iter(),
+        accumulator.num_unflagged().iter()
+    ) {
+        *result = if all_flagged {
+            match options.all_flagged {
+                AllFlaggedPolicy::GeometricMean if !is_auto => *mean,
+                AllFlaggedPolicy::Nan => Complex::new(f64::NAN, f64::NAN),
+                // propagated values are filled in after averaging.
+                _ => Complex::default(),
+            }
+        } else {
+            *mean
+        };
+        *avg_weight = match options.weight_mode {
+            WeightMode::SumOfWeights => *weight_sum as f32,
+            WeightMode::MeanWeight if num_unflagged > 0 => {
+                (*weight_sum / num_unflagged as f64) as f32
+            }
+            WeightMode::MeanWeight => 0.,
+            WeightMode::UnflaggedSampleCount => num_unflagged as f32,
+        };
+        *avg_flag = all_flagged || (num_unflagged as f64) < min_unflagged;
+    }
+    if is_auto {
+        result[0].im = 0.;
+        result[3].im = 0.;
+    }
+    *avg_jones = Jones::from(result);
+    if let Some(mut avg_counts) = avg_counts {
+        for (avg_count, &num_unflagged) in avg_counts
+            .iter_mut()
+            .zip(accumulator.num_unflagged().iter())
+        {
+            *avg_count = num_unflagged as u32;
+        }
+    }
+
+    (num_nan_skipped, num_clipped)
+}
+
+/// Four `f64` lanes, operated on element-wise. Without `std::simd` (which isn't
+/// stable), this is laid out so that the compiler emits packed instructions
+/// (e.g. AVX `vaddpd`/`vmulpd`) for the arithmetic.
+#[derive(Clone, Copy, Debug, Default)]
+struct F64x4([f64; 4]);
+
+impl F64x4 {
+    #[inline(always)]
+    fn from_fn(f: impl FnMut(usize) -> f64) -> Self {
+        Self(std::array::from_fn(f))
+    }
+}
+
+impl std::ops::Add for F64x4 {
+    type Output = Self;
+
+    #[inline(always)]
+    fn add(self, rhs: Self) -> Self {
+        Self::from_fn(|i| self.0[i] + rhs.0[i])
+    }
+}
+
+impl std::ops::Mul for F64x4 {
+    type Output = Self;
+
+    #[inline(always)]
+    fn mul(self, rhs: Self) -> Self {
+        Self::from_fn(|i| self.0[i] * rhs.0[i])
+    }
+}
+
+/// Accumulate a chunk of visibilities for [`average_chunk_for_pols_with_options`]
+/// with the `simd` feature, when neither NaN-skipping nor sigma-clipping are
+/// requested.
+///
+/// The real and imaginary parts of the four pols are held in [`F64x4`] lanes,
+/// and flagged (or negatively weighted) samples are masked to zero rather than
+/// branched over, so the whole inner loop is vectorised. The results are
+/// identical to the scalar loop.
+fn accumulate_chunk_lanes<F>(
+    jones_chunk: &ArrayView2<Jones<F>>,
+    weight_chunk: &ArrayView3<f32>,
+    flag_chunk: &ArrayView3<bool>,
+    accumulator: &mut JonesAccumulator,
+) where
+    F: Float,
+    Jones<f64>: From<Jones<F>>,
+{
+    let mut re_sum = F64x4::default();
+    let mut im_sum = F64x4::default();
+    let mut re_weighted_sum = F64x4::default();
+    let mut im_weighted_sum = F64x4::default();
+    let mut weight_lanes = F64x4::default();
+    let mut count_lanes = F64x4::default();
+
+    for (jones, weights, flags) in izip!(jones_chunk.iter(), weight_chunk.rows(), flag_chunk.rows())
+    {
+        let jones = Jones::<f64>::from(*jones);
+        let unflagged: [bool; 4] = std::array::from_fn(|i| !flags[i] && weights[i] >= 0.);
+        let mask = F64x4::from_fn(|i| if unflagged[i] { 1. } else { 0. });
+        let weight = F64x4::from_fn(|i| if unflagged[i] { weights[i] as f64 } else { 0. });
+        let re = F64x4::from_fn(|i| jones[i].re);
+        let im = F64x4::from_fn(|i| jones[i].im);
+        // flagged samples may be NaN, so they're masked out rather than
+        // multiplied by a zero weight.
+        let masked_re = F64x4::from_fn(|i| if unflagged[i] { re.0[i] } else { 0. });
+        let masked_im = F64x4::from_fn(|i| if unflagged[i] { im.0[i] } else { 0. });
+
+        re_sum = re_sum + re;
+        im_sum = im_sum + im;
+        re_weighted_sum = re_weighted_sum + masked_re * weight;
+        im_weighted_sum = im_weighted_sum + masked_im * weight;
+        weight_lanes = weight_lanes + weight;
+        count_lanes = count_lanes + mask;
+    }
+
+    accumulator.num_samples += jones_chunk.len();
+    for (i, (jones_sum, jones_weighted_sum, weight_sum, num_unflagged)) in izip!(
+        accumulator.sum.iter_mut(),
+        accumulator.weighted_sum.iter_mut(),
+        accumulator.weight_sum.iter_mut(),
+        accumulator.num_unflagged.iter_mut(),
+    )
+    .enumerate()
+    {
+        *jones_sum += Complex::new(re_sum.0[i], im_sum.0[i]);
+        *jones_weighted_sum += Complex::new(re_weighted_sum.0[i], im_weighted_sum.0[i]);
+        *weight_sum += weight_lanes.0[i];
+        *num_unflagged += count_lanes.0[i] as usize;
+    }
+}
 
 /// Average a section (`timestep_range`, `coarse_chan_range`) of the visibilities
 /// (`jones_array`, `weight_array`, `flag_array`) in time or frequency (`time_factor`, `frequency_factor`).
@@ -197,43 +972,71 @@ pub type VisData33 = (Array3<Jones<f32>>, Array3<f32>);
 ///
 /// `frequency_factor` - the factor by which to average the frequency axis.
 ///
+/// The input arrays may use any [`ndarray::Data`] storage, so views, owned
+/// arrays, [`ndarray::ArcArray`]s and [`ndarray::CowArray`]s can all be given
+/// without copying.
+///
 /// # Gorey details
 ///
 /// Averaging is done "Cotter-style". For each `time_factor` * `frequency_factor`
 /// chunk of input visibilities:
-/// - unflagged weights are added together
+/// - unflagged weights are added together. This preserves
+///     [`WeightScaling::Cotter`] and `Physical` weights; to average `Unit`
+///     weights, use [`average_visibilities_with_options`] with
+///     [`AveragingOptions::weight_scaling`].
 /// - if all visibilities in a chunk are flagged, then the result is the geometric
 ///     mean of the chunk.
 /// - otherwise the visibility is the weighted mean of the unflagged visibilities.
 ///
 /// This has been validated thoroughly against Cotter.
 ///
-pub fn average_visibilities(
-    jones_array: ArrayView3<Jones<f32>>,
-    weight_array: ArrayView4<f32>,
-    flag_array: ArrayView4<bool>,
+// The arrays are taken by value so that existing callers can keep passing views.
+#[allow(clippy::needless_pass_by_value)]
+pub fn average_visibilities<SJ, SW, SF>(
+    jones_array: ArrayBase<SJ, Ix3>,
+    weight_array: ArrayBase<SW, Ix4>,
+    flag_array: ArrayBase<SF, Ix4>,
+    avg_time: usize,
+    avg_freq: usize,
+) -> Result<VisData344, AveragingError>
+where
+    SJ: Data<Elem = Jones<f32>>,
+    SW: Data<Elem = f32>,
+    SF: Data<Elem = bool>,
+{
+    average_visibilities_with_options(
+        jones_array,
+        weight_array,
+        flag_array,
+        avg_time,
+        avg_freq,
+        &AveragingOptions::default(),
+    )
+    .map(VisData344::from)
+}
+
+/// Average visibilities like [`average_visibilities`], with the behaviour
+/// modified by `options`.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the array dimensions don't
+/// match.
+#[allow(clippy::needless_pass_by_value)]
+pub fn average_visibilities_with_options<SJ, SW, SF>(
+    jones_array: ArrayBase<SJ, Ix3>,
+    weight_array: ArrayBase<SW, Ix4>,
+    flag_array: ArrayBase<SF, Ix4>,
     avg_time: usize,
     avg_freq: usize,
-) -> Result<VisData344, AveragingError> {
+    options: &AveragingOptions,
+) -> Result<AveragedVis, AveragingError>
+where
+    SJ: Data<Elem = Jones<f32>>,
+    SW: Data<Elem = f32>,
+    SF: Data<Elem = bool>,
+{
     let jones_dims = jones_array.dim();
-    let weight_dims = weight_array.dim();
-    if weight_dims != (jones_dims.0, jones_dims.1, jones_dims.2, 4) {
-        return Err(AveragingError::BadArrayShape {
-            argument: "weight_array".to_string(),
-            function: "average_visibilities".to_string(),
-            expected: format!("({}, {}, {}, 4)", jones_dims.0, jones_dims.1, jones_dims.2),
-            received: format!("{weight_dims:?}"),
-        });
-    }
-    let flag_dims = flag_array.dim();
-    if flag_dims != (jones_dims.0, jones_dims.1, jones_dims.2, 4) {
-        return Err(AveragingError::BadArrayShape {
-            argument: "flag_array".to_string(),
-            function: "average_visibilities".to_string(),
-            expected: format!("({}, {}, {}, 4)", jones_dims.0, jones_dims.1, jones_dims.2),
-            received: format!("{flag_dims:?}"),
-        });
-    }
     let averaged_dims = (
         (jones_dims.0 as f64 / avg_time as f64).ceil() as usize,
         (jones_dims.1 as f64 / avg_freq as f64).ceil() as usize,
@@ -246,6 +1049,220 @@ pub fn average_visibilities(
         (averaged_dims.0, averaged_dims.1, averaged_dims.2, 4),
         false,
     );
+    let mut averaged_count_array = if options.sample_counts {
+        Some(Array4::<u32>::zeros((
+            averaged_dims.0,
+            averaged_dims.1,
+            averaged_dims.2,
+            4,
+        )))
+    } else {
+        None
+    };
+
+    let (num_nan_skipped, num_clipped) = average_visibilities_into_inner(
+        jones_array.view(),
+        weight_array.view(),
+        flag_array.view(),
+        avg_time,
+        avg_freq,
+        options,
+        averaged_jones_array.view_mut(),
+        averaged_weight_array.view_mut(),
+        averaged_flag_array.view_mut(),
+        averaged_count_array.as_mut().map(|a| a.view_mut()),
+        "average_visibilities_with_options",
+    )?;
+
+    let effective_integrations = averaged_count_array.as_ref().map(|averaged_count_array| {
+        // the last chunk of channels may be smaller than `avg_freq`.
+        let num_chans = jones_dims.1;
+        Array4::from_shape_fn(averaged_count_array.dim(), |(t, c, b, p)| {
+            let chunk_chans = avg_freq.min(num_chans - c * avg_freq);
+            averaged_count_array[(t, c, b, p)] as f32 / chunk_chans as f32
+        })
+    });
+
+    let stats = if options.stats {
+        Some(AveragingStats::from_weights_and_flags(
+            weight_array.view(),
+            flag_array.view(),
+        ))
+    } else {
+        None
+    };
+
+    Ok(AveragedVis {
+        jones: averaged_jones_array,
+        weights: averaged_weight_array,
+        flags: averaged_flag_array,
+        num_nan_skipped,
+        num_clipped,
+        num_unflagged: averaged_count_array,
+        effective_integrations,
+        stats,
+    })
+}
+
+/// Average visibilities like [`average_visibilities`], but write the results
+/// into the caller's `averaged_jones`, `averaged_weights` and `averaged_flags`
+/// instead of allocating new arrays. This lets streaming pipelines reuse the
+/// same output buffers for every chunk.
+///
+/// The outputs must have the averaged dimensions, i.e. the number of
+/// timesteps and channels divided by `avg_time` and `avg_freq` (rounded up).
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if any of the input or output
+/// dimensions don't match.
+#[allow(clippy::too_many_arguments)]
+pub fn average_visibilities_into(
+    jones_array: ArrayView3<Jones<f32>>,
+    weight_array: ArrayView4<f32>,
+    flag_array: ArrayView4<bool>,
+    avg_time: usize,
+    avg_freq: usize,
+    averaged_jones: ArrayViewMut3<Jones<f32>>,
+    averaged_weights: ArrayViewMut4<f32>,
+    averaged_flags: ArrayViewMut4<bool>,
+) -> Result<(), AveragingError> {
+    average_visibilities_into_inner(
+        jones_array,
+        weight_array,
+        flag_array,
+        avg_time,
+        avg_freq,
+        &AveragingOptions::default(),
+        averaged_jones,
+        averaged_weights,
+        averaged_flags,
+        None,
+        "average_visibilities_into",
+    )
+    .map(|_| ())
+}
+
+/// Iterate over the outer axis of an optional array, or yield `None` forever.
+fn optional_outer_iter_mut<'a, A: 'a, D>(
+    array: Option<&'a mut ArrayViewMut<'_, A, D>>,
+) -> impl Iterator<Item = Option<ArrayViewMut<'a, A, D::Smaller>>>
+where
+    D: RemoveAxis,
+{
+    match array {
+        Some(array) => Either::Left(array.outer_iter_mut().map(Some)),
+        None => Either::Right(std::iter::repeat_with(|| None)),
+    }
+}
+
+/// The implementation of [`average_visibilities_with_options`] and
+/// [`average_visibilities_into`]. The number of unflagged samples are written
+/// to `averaged_counts`, if given. Returns the number of NaN samples skipped
+/// and the number of samples rejected by sigma-clipping.
+#[allow(clippy::too_many_arguments)]
+fn average_visibilities_into_inner<F>(
+    jones_array: ArrayView3<Jones<F>>,
+    weight_array: ArrayView4<f32>,
+    flag_array: ArrayView4<bool>,
+    avg_time: usize,
+    avg_freq: usize,
+    options: &AveragingOptions,
+    mut averaged_jones_array: ArrayViewMut3<Jones<F>>,
+    mut averaged_weight_array: ArrayViewMut4<f32>,
+    mut averaged_flag_array: ArrayViewMut4<bool>,
+    averaged_count_array: Option<ArrayViewMut4<u32>>,
+    function: &str,
+) -> Result<(usize, usize), AveragingError>
+where
+    F: Float + Send + Sync,
+    Jones<f64>: From<Jones<F>>,
+    Jones<F>: From<Jones<f64>>,
+{
+    let jones_dims = jones_array.dim();
+    let averaged_dims = (
+        (jones_dims.0 as f64 / avg_time as f64).ceil() as usize,
+        (jones_dims.1 as f64 / avg_freq as f64).ceil() as usize,
+        jones_dims.2,
+    );
+    let input_shape = [jones_dims.0, jones_dims.1, jones_dims.2, 4];
+    let averaged_shape = [averaged_dims.0, averaged_dims.1, averaged_dims.2, 4];
+    for (argument, received, expected) in [
+        ("weight_array", weight_array.shape(), &input_shape[..]),
+        ("flag_array", flag_array.shape(), &input_shape[..]),
+        (
+            "averaged_jones",
+            averaged_jones_array.shape(),
+            &averaged_shape[..3],
+        ),
+        (
+            "averaged_weights",
+            averaged_weight_array.shape(),
+            &averaged_shape[..],
+        ),
+        (
+            "averaged_flags",
+            averaged_flag_array.shape(),
+            &averaged_shape[..],
+        ),
+    ] {
+        if received != expected {
+            return Err(AveragingError::BadArrayShape {
+                argument: argument.to_string(),
+                function: function.to_string(),
+                expected: format!("{expected:?}"),
+                received: format!("{received:?}"),
+            });
+        }
+    }
+    if let Some(decorrelation) = options.decorrelation.as_ref() {
+        for (argument, received, expected) in [
+            ("uvws", decorrelation.uvws.len(), jones_dims.2),
+            ("freqs_hz", decorrelation.freqs_hz.len(), jones_dims.1),
+        ] {
+            if received != expected {
+                return Err(AveragingError::BadArrayShape {
+                    argument: argument.to_string(),
+                    function: function.to_string(),
+                    expected: format!("({expected},)"),
+                    received: format!("({received},)"),
+                });
+            }
+        }
+    }
+    let is_auto: Vec<bool> = match options.autos.as_ref() {
+        Some(autos) => {
+            if autos.baselines.len() != jones_dims.2 {
+                return Err(AveragingError::BadArrayShape {
+                    argument: "baselines".to_string(),
+                    function: function.to_string(),
+                    expected: format!("({},)", jones_dims.2),
+                    received: format!("({},)", autos.baselines.len()),
+                });
+            }
+            autos
+                .baselines
+                .iter()
+                .map(|&(tile1, tile2)| tile1 == tile2)
+                .collect()
+        }
+        None => vec![false; jones_dims.2],
+    };
+    let skip_autos = options.autos.map_or(false, |autos| autos.skip);
+    // propagating the last average needs to know which chunks were all
+    // flagged, which the counts tell us.
+    let propagate_last = options.all_flagged == AllFlaggedPolicy::PropagateLast;
+    let mut propagate_count_array = if propagate_last && averaged_count_array.is_none() {
+        Some(Array4::<u32>::zeros(averaged_shape))
+    } else {
+        None
+    };
+    let mut averaged_count_array =
+        averaged_count_array.or_else(|| propagate_count_array.as_mut().map(|a| a.view_mut()));
+    let num_nan_skipped = AtomicUsize::new(0);
+    let num_clipped = AtomicUsize::new(0);
+    let num_chunks = averaged_dims.0 * averaged_dims.1;
+    let mut num_chunks_done = 0;
 
     // iterate through the time dimension of the arrays in chunks of size `time_factor`.
     for (
@@ -255,6 +1272,7 @@ pub fn average_visibilities(
         mut averaged_jones_timestep_view,
         mut averaged_weight_timestep_view,
         mut averaged_flag_timestep_view,
+        mut averaged_count_timestep_view,
     ) in izip!(
         jones_array.axis_chunks_iter(Axis(0), avg_time),
         weight_array.axis_chunks_iter(Axis(0), avg_time),
@@ -262,6 +1280,7 @@ pub fn average_visibilities(
         averaged_jones_array.outer_iter_mut(),
         averaged_weight_array.outer_iter_mut(),
         averaged_flag_array.outer_iter_mut(),
+        optional_outer_iter_mut(averaged_count_array.as_mut()),
     ) {
         // iterate through the channel dimension of the arrays in chunks of size `frequency_factor`.
         for (
@@ -271,6 +1290,7 @@ pub fn average_visibilities(
             mut averaged_jones_channel_view,
             mut averaged_weight_channel_view,
             mut averaged_flag_channel_view,
+            mut averaged_count_channel_view,
         ) in izip!(
             jones_timestep_chunk.axis_chunks_iter(Axis(1), avg_freq),
             weight_timestep_chunk.axis_chunks_iter(Axis(1), avg_freq),
@@ -278,49 +1298,1111 @@ pub fn average_visibilities(
             averaged_jones_timestep_view.outer_iter_mut(),
             averaged_weight_timestep_view.outer_iter_mut(),
             averaged_flag_timestep_view.outer_iter_mut(),
+            optional_outer_iter_mut(averaged_count_timestep_view.as_mut()),
         ) {
-            // iterate through the baseline dimension of the arrays.
-            for (
-                jones_chunk,
-                weight_chunk,
-                flag_chunk,
-                mut averaged_jones_view,
-                mut averaged_weight_view,
-                mut averaged_flag_view,
-            ) in izip!(
+            // iterate through the baseline dimension of the arrays. With the
+            // "rayon" feature, baselines are averaged in parallel.
+            let baseline_chunks = is_auto.iter().copied().zip(izip!(
                 jones_channel_chunk.axis_iter(Axis(2)),
                 weight_channel_chunk.axis_iter(Axis(2)),
                 flag_channel_chunk.axis_iter(Axis(2)),
                 averaged_jones_channel_view.outer_iter_mut(),
                 averaged_weight_channel_view.outer_iter_mut(),
                 averaged_flag_channel_view.outer_iter_mut(),
-            ) {
-                average_chunk_for_pols_f64!(
+                optional_outer_iter_mut(averaged_count_channel_view.as_mut()),
+            ));
+            let average_baseline = |(
+                is_auto,
+                (
                     jones_chunk,
                     weight_chunk,
                     flag_chunk,
-                    averaged_jones_view[()],
+                    mut averaged_jones_view,
+                    mut averaged_weight_view,
+                    mut averaged_flag_view,
+                    averaged_count_view,
+                ),
+            ): (bool, BaselineChunk<F>)| {
+                if is_auto && skip_autos {
+                    averaged_jones_view[()] = Jones::default();
+                    averaged_weight_view.fill(0.);
+                    averaged_flag_view.fill(true);
+                    if let Some(mut averaged_count_view) = averaged_count_view {
+                        averaged_count_view.fill(0);
+                    }
+                    return;
+                }
+                let (chunk_nan_skipped, chunk_clipped) = average_chunk_for_pols_with_options(
+                    jones_chunk,
+                    weight_chunk,
+                    flag_chunk,
+                    options,
+                    &mut averaged_jones_view[()],
                     averaged_weight_view,
-                    averaged_flag_view
+                    averaged_flag_view,
+                    averaged_count_view,
+                    is_auto,
                 );
+                num_nan_skipped.fetch_add(chunk_nan_skipped, Ordering::Relaxed);
+                num_clipped.fetch_add(chunk_clipped, Ordering::Relaxed);
+            };
+            #[cfg(feature = "rayon")]
+            baseline_chunks
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .for_each(average_baseline);
+            #[cfg(not(feature = "rayon"))]
+            baseline_chunks.for_each(average_baseline);
+
+            num_chunks_done += 1;
+            if let Some(ProgressCallback(progress)) = options.progress {
+                progress(num_chunks_done, num_chunks);
             }
         }
     }
 
-    Ok((
+    if let (true, Some(averaged_count_array)) = (propagate_last, averaged_count_array.as_ref()) {
+        let all_flagged =
+            averaged_count_array.map_axis(Axis(3), |counts| counts.iter().all(|&n| n == 0));
+        // in time order, so that propagated values propagate further.
+        for ((t, c, b), &all_flagged) in all_flagged.indexed_iter() {
+            if t > 0 && all_flagged {
+                averaged_jones_array[(t, c, b)] = averaged_jones_array[(t - 1, c, b)];
+            }
+        }
+    }
+
+    if let (Some(decorrelation), false) = (
+        options.decorrelation.as_ref(),
+        options.weight_mode == WeightMode::UnflaggedSampleCount,
+    ) {
+        // the last chunks of timesteps and channels may be short.
+        let int_time_s = decorrelation.int_time.to_seconds();
+        let intervals_s: Vec<f64> = jones_array
+            .axis_chunks_iter(Axis(0), avg_time)
+            .map(|chunk| chunk.len_of(Axis(0)) as f64 * int_time_s)
+            .collect();
+        let mean_freqs_hz: Vec<f64> = decorrelation
+            .freqs_hz
+            .chunks(avg_freq)
+            .map(|chunk| chunk.iter().sum::<f64>() / chunk.len() as f64)
+            .collect();
+        let lmn = decorrelation.direction.to_lmn(decorrelation.phase_centre);
+        let dec_rad = decorrelation.phase_centre.dec;
+        for ((t, c, b, _), weight) in averaged_weight_array.indexed_iter_mut() {
+            let factor = time_decorrelation_factor(
+                decorrelation.uvws[b],
+                lmn,
+                dec_rad,
+                mean_freqs_hz[c],
+                intervals_s[t],
+            );
+            *weight *= factor as f32;
+        }
+    }
+
+    if options.weight_mode == WeightMode::SumOfWeights
+        && options
+            .weight_scaling
+            .averaging_correction(avg_time, avg_freq)
+            != 1.0
+    {
+        // the last chunks of timesteps and channels may be short.
+        let chunk_lens = |len: usize, factor: usize| -> Vec<usize> {
+            (0..len)
+                .step_by(factor)
+                .map(|i| factor.min(len - i))
+                .collect()
+        };
+        let time_lens = chunk_lens(jones_dims.0, avg_time);
+        let chan_lens = chunk_lens(jones_dims.1, avg_freq);
+        for ((t, c, _, _), weight) in averaged_weight_array.indexed_iter_mut() {
+            *weight *= options
+                .weight_scaling
+                .averaging_correction(time_lens[t], chan_lens[c]) as f32;
+        }
+    }
+
+    Ok((num_nan_skipped.into_inner(), num_clipped.into_inner()))
+}
+
+/// Average visibilities with a single weight and flag per visibility, rather
+/// than per pol, like [`average_visibilities`].
+///
+/// `weight_array` and `flag_array` have dimensions
+/// `[timestep][channel][baseline]`, as do the averaged weights and flags. The
+/// pols of each visibility are averaged with the same weight and flag.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the array dimensions don't
+/// match.
+#[allow(clippy::needless_pass_by_value)]
+pub fn average_visibilities_scalar<SJ, SW, SF>(
+    jones_array: ArrayBase<SJ, Ix3>,
+    weight_array: ArrayBase<SW, Ix3>,
+    flag_array: ArrayBase<SF, Ix3>,
+    avg_time: usize,
+    avg_freq: usize,
+) -> Result<VisData333, AveragingError>
+where
+    SJ: Data<Elem = Jones<f32>>,
+    SW: Data<Elem = f32>,
+    SF: Data<Elem = bool>,
+{
+    let jones_dims = jones_array.dim();
+    let pol_dims = (jones_dims.0, jones_dims.1, jones_dims.2, 4);
+    for (argument, received) in [
+        ("weight_array", weight_array.dim()),
+        ("flag_array", flag_array.dim()),
+    ] {
+        if received != jones_dims {
+            return Err(AveragingError::BadArrayShape {
+                argument: argument.to_string(),
+                function: "average_visibilities_scalar".to_string(),
+                expected: format!("{jones_dims:?}"),
+                received: format!("{received:?}"),
+            });
+        }
+    }
+    // broadcasting the pol axis doesn't copy.
+    let weight_view = weight_array.view().insert_axis(Axis(3));
+    let flag_view = flag_array.view().insert_axis(Axis(3));
+    let (averaged_jones, averaged_weights, averaged_flags) = average_visibilities(
+        jones_array.view(),
+        weight_view
+            .broadcast(pol_dims)
+            .expect("a length-1 axis can always be broadcast"),
+        flag_view
+            .broadcast(pol_dims)
+            .expect("a length-1 axis can always be broadcast"),
+        avg_time,
+        avg_freq,
+    )?;
+    Ok((
+        averaged_jones,
+        averaged_weights.index_axis_move(Axis(3), 0),
+        averaged_flags.index_axis_move(Axis(3), 0),
+    ))
+}
+
+/// Average `f64` visibilities like [`average_visibilities`], without losing
+/// precision to `f32`. Weights and flags are the same as for `f32`
+/// visibilities.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the array dimensions don't
+/// match.
+#[allow(clippy::needless_pass_by_value)]
+pub fn average_visibilities_f64<SJ, SW, SF>(
+    jones_array: ArrayBase<SJ, Ix3>,
+    weight_array: ArrayBase<SW, Ix4>,
+    flag_array: ArrayBase<SF, Ix4>,
+    avg_time: usize,
+    avg_freq: usize,
+) -> Result<VisData344F64, AveragingError>
+where
+    SJ: Data<Elem = Jones<f64>>,
+    SW: Data<Elem = f32>,
+    SF: Data<Elem = bool>,
+{
+    let jones_dims = jones_array.dim();
+    let averaged_dims = (
+        (jones_dims.0 as f64 / avg_time as f64).ceil() as usize,
+        (jones_dims.1 as f64 / avg_freq as f64).ceil() as usize,
+        jones_dims.2,
+    );
+    let mut averaged_jones_array = Array3::<Jones<f64>>::zeros(averaged_dims);
+    let mut averaged_weight_array =
+        Array4::<f32>::zeros((averaged_dims.0, averaged_dims.1, averaged_dims.2, 4));
+    let mut averaged_flag_array = Array4::<bool>::from_elem(
+        (averaged_dims.0, averaged_dims.1, averaged_dims.2, 4),
+        false,
+    );
+    average_visibilities_into_inner(
+        jones_array.view(),
+        weight_array.view(),
+        flag_array.view(),
+        avg_time,
+        avg_freq,
+        &AveragingOptions::default(),
+        averaged_jones_array.view_mut(),
+        averaged_weight_array.view_mut(),
+        averaged_flag_array.view_mut(),
+        None,
+        "average_visibilities_f64",
+    )?;
+    Ok((
         averaged_jones_array,
         averaged_weight_array,
         averaged_flag_array,
     ))
 }
 
+/// Average visibilities like [`average_visibilities`], but never average fine
+/// channels from different coarse channels together.
+///
+/// The frequency axis is made of consecutive coarse channels of
+/// `fine_chans_per_coarse` fine channels each, e.g.
+/// `vis_ctx.num_sel_chans / mwa_obs_ctx.coarse_chan_recs.len()`. Each coarse
+/// channel is averaged separately, so if `avg_freq` doesn't divide
+/// `fine_chans_per_coarse`, then the last averaged channel of every coarse
+/// channel is made of fewer fine channels. This keeps the PFB edge channels of
+/// each coarse channel at the edges.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::UnevenCoarseChans`] if the number of channels
+/// isn't a multiple of `fine_chans_per_coarse`, or any error from
+/// [`average_visibilities`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn average_visibilities_by_coarse_chan<SJ, SW, SF>(
+    jones_array: ArrayBase<SJ, Ix3>,
+    weight_array: ArrayBase<SW, Ix4>,
+    flag_array: ArrayBase<SF, Ix4>,
+    avg_time: usize,
+    avg_freq: usize,
+    fine_chans_per_coarse: usize,
+) -> Result<VisData344, AveragingError>
+where
+    SJ: Data<Elem = Jones<f32>>,
+    SW: Data<Elem = f32>,
+    SF: Data<Elem = bool>,
+{
+    let num_chans = jones_array.len_of(Axis(1));
+    if fine_chans_per_coarse == 0 || num_chans % fine_chans_per_coarse != 0 {
+        return Err(AveragingError::UnevenCoarseChans {
+            num_chans,
+            fine_chans_per_coarse,
+        });
+    }
+
+    let mut averaged_coarse_chans = vec![];
+    for (jones_coarse_chan, weight_coarse_chan, flag_coarse_chan) in izip!(
+        jones_array.axis_chunks_iter(Axis(1), fine_chans_per_coarse),
+        weight_array.axis_chunks_iter(Axis(1), fine_chans_per_coarse),
+        flag_array.axis_chunks_iter(Axis(1), fine_chans_per_coarse),
+    ) {
+        averaged_coarse_chans.push(average_visibilities(
+            jones_coarse_chan,
+            weight_coarse_chan,
+            flag_coarse_chan,
+            avg_time,
+            avg_freq,
+        )?);
+    }
+
+    let jones: Vec<_> = averaged_coarse_chans
+        .iter()
+        .map(|(j, _, _)| j.view())
+        .collect();
+    let weights: Vec<_> = averaged_coarse_chans
+        .iter()
+        .map(|(_, w, _)| w.view())
+        .collect();
+    let flags: Vec<_> = averaged_coarse_chans
+        .iter()
+        .map(|(_, _, f)| f.view())
+        .collect();
+    let concatenate_chans =
+        |argument: &str, err: ndarray::ShapeError| AveragingError::BadArrayShape {
+            argument: argument.to_string(),
+            function: "average_visibilities_by_coarse_chan".to_string(),
+            expected: "consistent coarse channel shapes".to_string(),
+            received: err.to_string(),
+        };
+    Ok((
+        concatenate(Axis(1), &jones).map_err(|e| concatenate_chans("jones_array", e))?,
+        concatenate(Axis(1), &weights).map_err(|e| concatenate_chans("weight_array", e))?,
+        concatenate(Axis(1), &flags).map_err(|e| concatenate_chans("flag_array", e))?,
+    ))
+}
+
+/// The resolution of averaged visibilities, from
+/// [`average_visibilities_to_resolution`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AveragedResolution {
+    /// The time averaging factor used.
+    pub avg_time: usize,
+    /// The frequency averaging factor used.
+    pub avg_freq: usize,
+    /// The integration time of the averaged visibilities.
+    pub int_time: Duration,
+    /// The frequency resolution of the averaged visibilities \[Hz\].
+    pub freq_resolution_hz: f64,
+}
+
+/// Average visibilities to a target time and frequency resolution, rather than
+/// by integer factors. `None` leaves that axis unaveraged.
+///
+/// `int_time` and `freq_resolution_hz` are the native resolutions of the
+/// visibilities. The targets must be integer multiples of these; the time
+/// resolution exactly (to the nanosecond), and the frequency resolution to
+/// within a part per million. To write averaged visibilities, use
+/// `VisContext::from_mwalib_with_resolution`.
+///
+/// Array dimensions and averaging are the same as [`average_visibilities`].
+/// The achieved resolution is returned alongside the averaged data.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::NonIntegerResolution`] if a target resolution
+/// isn't commensurate with the native resolution, or any error from
+/// [`average_visibilities`].
+#[allow(clippy::too_many_arguments)]
+pub fn average_visibilities_to_resolution<SJ, SW, SF>(
+    jones_array: ArrayBase<SJ, Ix3>,
+    weight_array: ArrayBase<SW, Ix4>,
+    flag_array: ArrayBase<SF, Ix4>,
+    int_time: Duration,
+    freq_resolution_hz: f64,
+    target_time_res: Option<Duration>,
+    target_freq_res_hz: Option<f64>,
+) -> Result<(VisData344, AveragedResolution), AveragingError>
+where
+    SJ: Data<Elem = Jones<f32>>,
+    SW: Data<Elem = f32>,
+    SF: Data<Elem = bool>,
+{
+    let avg_time = match target_time_res {
+        Some(res) => time_factor_from_resolution(int_time, res)?,
+        None => 1,
+    };
+    let avg_freq = match target_freq_res_hz {
+        Some(res) => freq_factor_from_resolution(freq_resolution_hz, res)?,
+        None => 1,
+    };
+    let averaged = average_visibilities(jones_array, weight_array, flag_array, avg_time, avg_freq)?;
+    Ok((
+        averaged,
+        AveragedResolution {
+            avg_time,
+            avg_freq,
+            int_time: int_time * (avg_time as i64),
+            freq_resolution_hz: freq_resolution_hz * avg_freq as f64,
+        },
+    ))
+}
+
+/// The indices of a chunk of `at` timesteps and `af` channels, in order of
+/// distance from the centre of the chunk. Ties are broken by time, then
+/// frequency.
+fn indices_from_centre(at: usize, af: usize) -> Vec<(usize, usize)> {
+    let mut indices: Vec<(usize, usize)> =
+        (0..at).flat_map(|t| (0..af).map(move |f| (t, f))).collect();
+    // distances are doubled to keep them integers.
+    let distance_sqr = |(t, f): (usize, usize)| {
+        let dt = 2 * t as i64 - (at as i64 - 1);
+        let df = 2 * f as i64 - (af as i64 - 1);
+        dt * dt + df * df
+    };
+    indices.sort_by_key(|&index| (distance_sqr(index), index));
+    indices
+}
+
+/// Decimate visibilities in time and frequency by picking a single sample from
+/// each chunk of `avg_time` timesteps and `avg_freq` channels, rather than
+/// averaging. This avoids the smearing of averaging, e.g. for quick-look
+/// pipelines.
+///
+/// For each baseline, the sample closest to the centre of the chunk with at
+/// least one unflagged pol is picked, and its visibility, weights and flags
+/// are kept as-is. If every sample in the chunk is flagged, the central sample
+/// is used.
+///
+/// Array dimensions are the same as [`average_visibilities`].
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the array dimensions don't
+/// match.
+#[allow(clippy::needless_pass_by_value)]
+pub fn decimate_visibilities<SJ, SW, SF>(
+    jones_array: ArrayBase<SJ, Ix3>,
+    weight_array: ArrayBase<SW, Ix4>,
+    flag_array: ArrayBase<SF, Ix4>,
+    avg_time: usize,
+    avg_freq: usize,
+) -> Result<VisData344, AveragingError>
+where
+    SJ: Data<Elem = Jones<f32>>,
+    SW: Data<Elem = f32>,
+    SF: Data<Elem = bool>,
+{
+    let jones_dims = jones_array.dim();
+    let pol_dims = [jones_dims.0, jones_dims.1, jones_dims.2, 4];
+    for (argument, received) in [
+        ("weight_array", weight_array.shape()),
+        ("flag_array", flag_array.shape()),
+    ] {
+        if received != pol_dims {
+            return Err(AveragingError::BadArrayShape {
+                argument: argument.to_string(),
+                function: "decimate_visibilities".to_string(),
+                expected: format!("{pol_dims:?}"),
+                received: format!("{received:?}"),
+            });
+        }
+    }
+    let averaged_dims = (
+        (jones_dims.0 as f64 / avg_time as f64).ceil() as usize,
+        (jones_dims.1 as f64 / avg_freq as f64).ceil() as usize,
+        jones_dims.2,
+    );
+    let mut decimated_jones_array = Array3::<Jones<f32>>::zeros(averaged_dims);
+    let mut decimated_weight_array =
+        Array4::<f32>::zeros((averaged_dims.0, averaged_dims.1, averaged_dims.2, 4));
+    let mut decimated_flag_array =
+        Array4::<bool>::from_elem((averaged_dims.0, averaged_dims.1, averaged_dims.2, 4), true);
+
+    for (avg_timestep_idx, jones_timestep_chunk) in
+        jones_array.axis_chunks_iter(Axis(0), avg_time).enumerate()
+    {
+        let t_start = avg_timestep_idx * avg_time;
+        for (avg_chan_idx, jones_channel_chunk) in jones_timestep_chunk
+            .axis_chunks_iter(Axis(1), avg_freq)
+            .enumerate()
+        {
+            let c_start = avg_chan_idx * avg_freq;
+            let (at, af, _) = jones_channel_chunk.dim();
+            let candidates = indices_from_centre(at, af);
+            for baseline_idx in 0..jones_dims.2 {
+                let (t, c) = candidates
+                    .iter()
+                    .map(|&(t, f)| (t_start + t, c_start + f))
+                    .find(|&(t, c)| {
+                        flag_array
+                            .slice(s![t, c, baseline_idx, ..])
+                            .iter()
+                            .any(|&f| !f)
+                    })
+                    .unwrap_or((t_start + candidates[0].0, c_start + candidates[0].1));
+                let out = (avg_timestep_idx, avg_chan_idx, baseline_idx);
+                decimated_jones_array[out] = jones_array[(t, c, baseline_idx)];
+                decimated_weight_array
+                    .slice_mut(s![out.0, out.1, out.2, ..])
+                    .assign(&weight_array.slice(s![t, c, baseline_idx, ..]));
+                decimated_flag_array
+                    .slice_mut(s![out.0, out.1, out.2, ..])
+                    .assign(&flag_array.slice(s![t, c, baseline_idx, ..]));
+            }
+        }
+    }
+
+    Ok((
+        decimated_jones_array,
+        decimated_weight_array,
+        decimated_flag_array,
+    ))
+}
+
+/// An iterator which reads and averages chunks of the timesteps of a
+/// [`VisSelection`]. See [`average_selection_chunks`].
+pub struct AveragedChunks<R> {
+    vis_sel: VisSelection,
+    chunk_timesteps: usize,
+    avg_time: usize,
+    avg_freq: usize,
+    next_timestep: usize,
+    read: R,
+}
+
+/// Lazily read and average the visibilities of `vis_sel`, `chunk_timesteps`
+/// timesteps at a time, so that a whole observation never needs to be in
+/// memory.
+///
+/// `read` is called with the selection of each chunk, and must return its
+/// visibilities, weights and flags with the dimensions given to
+/// [`average_visibilities`], e.g. from
+/// [`VisSelection::allocate_jones`]-shaped arrays. The iterator yields the
+/// selection of each chunk alongside its averaged visibilities.
+///
+/// `chunk_timesteps` is rounded up to a multiple of `avg_time`, so that
+/// averaging chunks aren't split between reads; only the last chunk may be
+/// shorter.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::ZeroAveragingFactor`] if `avg_time` or
+/// `avg_freq` is 0.
+pub fn average_selection_chunks<R, E>(
+    vis_sel: &VisSelection,
+    chunk_timesteps: usize,
+    avg_time: usize,
+    avg_freq: usize,
+    read: R,
+) -> Result<AveragedChunks<R>, AveragingError>
+where
+    R: FnMut(&VisSelection) -> Result<VisData344, E>,
+    E: From<AveragingError>,
+{
+    check_averaging_factors(avg_time, avg_freq, "average_selection_chunks")?;
+    let num_avg_timesteps = ((chunk_timesteps as f64 / avg_time as f64).ceil() as usize).max(1);
+    Ok(AveragedChunks {
+        vis_sel: vis_sel.clone(),
+        chunk_timesteps: num_avg_timesteps * avg_time,
+        avg_time,
+        avg_freq,
+        next_timestep: vis_sel.timestep_range.start,
+        read,
+    })
+}
+
+/// Check that the time and frequency averaging factors given to `function` are
+/// non-zero.
+fn check_averaging_factors(
+    avg_time: usize,
+    avg_freq: usize,
+    function: &str,
+) -> Result<(), AveragingError> {
+    for (axis, factor) in [("time", avg_time), ("frequency", avg_freq)] {
+        if factor == 0 {
+            return Err(AveragingError::ZeroAveragingFactor {
+                axis,
+                function: function.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+impl<R, E> Iterator for AveragedChunks<R>
+where
+    R: FnMut(&VisSelection) -> Result<VisData344, E>,
+    E: From<AveragingError>,
+{
+    type Item = Result<(VisSelection, VisData344), E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let end_timestep = self.vis_sel.timestep_range.end;
+        if self.next_timestep >= end_timestep {
+            return None;
+        }
+        let chunk_end = (self.next_timestep + self.chunk_timesteps).min(end_timestep);
+        let chunk_sel = VisSelection {
+            timestep_range: self.next_timestep..chunk_end,
+            ..self.vis_sel.clone()
+        };
+        self.next_timestep = chunk_end;
+
+        let averaged = (self.read)(&chunk_sel).and_then(|(jones, weights, flags)| {
+            average_visibilities(jones, weights, flags, self.avg_time, self.avg_freq)
+                .map_err(E::from)
+        });
+        Some(averaged.map(|averaged| (chunk_sel, averaged)))
+    }
+}
+
+/// Coherently average visibilities over groups of redundant baselines, e.g. of
+/// a hex configuration.
+///
+/// `groups` contains the indices (into the baseline axis of the arrays) of the
+/// baselines of each group, which must all have the same orientation (i.e. no
+/// conjugation is done). For each timestep and channel, the baselines in a
+/// group are averaged like the chunks of [`average_visibilities`]: the weighted
+/// mean of the unflagged visibilities, with their weights summed. The averaged
+/// arrays have a baseline axis of length `groups.len()`; an empty group is
+/// zero and flagged.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the array dimensions don't
+/// match, or [`AveragingError::BadBaselineIndex`] if a group contains a
+/// baseline that doesn't exist.
+#[allow(clippy::needless_pass_by_value)]
+pub fn average_redundant_baselines<SJ, SW, SF>(
+    jones_array: ArrayBase<SJ, Ix3>,
+    weight_array: ArrayBase<SW, Ix4>,
+    flag_array: ArrayBase<SF, Ix4>,
+    groups: &[Vec<usize>],
+) -> Result<VisData344, AveragingError>
+where
+    SJ: Data<Elem = Jones<f32>>,
+    SW: Data<Elem = f32>,
+    SF: Data<Elem = bool>,
+{
+    let jones_dims = jones_array.dim();
+    let pol_dims = [jones_dims.0, jones_dims.1, jones_dims.2, 4];
+    for (argument, received) in [
+        ("weight_array", weight_array.shape()),
+        ("flag_array", flag_array.shape()),
+    ] {
+        if received != pol_dims {
+            return Err(AveragingError::BadArrayShape {
+                argument: argument.to_string(),
+                function: "average_redundant_baselines".to_string(),
+                expected: format!("{pol_dims:?}"),
+                received: format!("{received:?}"),
+            });
+        }
+    }
+    let num_baselines = jones_dims.2;
+    for (group_idx, group) in groups.iter().enumerate() {
+        if let Some(&baseline_idx) = group.iter().find(|&&b| b >= num_baselines) {
+            return Err(AveragingError::BadBaselineIndex {
+                group_idx,
+                baseline_idx,
+                num_baselines,
+            });
+        }
+    }
+
+    let averaged_dims = (jones_dims.0, jones_dims.1, groups.len());
+    let mut averaged_jones_array = Array3::<Jones<f32>>::zeros(averaged_dims);
+    let mut averaged_weight_array =
+        Array4::<f32>::zeros((averaged_dims.0, averaged_dims.1, averaged_dims.2, 4));
+    let mut averaged_flag_array =
+        Array4::<bool>::from_elem((averaged_dims.0, averaged_dims.1, averaged_dims.2, 4), true);
+    let options = AveragingOptions::default();
+
+    for (group, mut averaged_jones_view, mut averaged_weight_view, mut averaged_flag_view) in izip!(
+        groups,
+        averaged_jones_array.axis_iter_mut(Axis(2)),
+        averaged_weight_array.axis_iter_mut(Axis(2)),
+        averaged_flag_array.axis_iter_mut(Axis(2)),
+    ) {
+        if group.is_empty() {
+            continue;
+        }
+        let group_jones = jones_array.select(Axis(2), group);
+        let group_weights = weight_array.select(Axis(2), group);
+        let group_flags = flag_array.select(Axis(2), group);
+        for ((timestep_idx, chan_idx), averaged_jones) in averaged_jones_view.indexed_iter_mut() {
+            average_chunk_for_pols_with_options(
+                group_jones.slice(s![timestep_idx..=timestep_idx, chan_idx, ..]),
+                group_weights.slice(s![timestep_idx..=timestep_idx, chan_idx, .., ..]),
+                group_flags.slice(s![timestep_idx..=timestep_idx, chan_idx, .., ..]),
+                &options,
+                averaged_jones,
+                averaged_weight_view.slice_mut(s![timestep_idx, chan_idx, ..]),
+                averaged_flag_view.slice_mut(s![timestep_idx, chan_idx, ..]),
+                None,
+                false,
+            );
+        }
+    }
+
+    Ok((
+        averaged_jones_array,
+        averaged_weight_array,
+        averaged_flag_array,
+    ))
+}
+
+/// The weighted centroid times and frequencies of averaged visibilities.
+///
+/// Averaging (see [`average_visibilities`]) only uses unflagged samples, so
+/// when part of a chunk is flagged, the averaged visibility is no longer
+/// centred on the middle of the chunk. For each averaged sample
+/// `[timestep][channel][baseline]`, this gives the mean of `timestamps` and
+/// `freqs_hz` (of the input timesteps and channels), weighted by the sum of the
+/// weights of the unflagged pols of each input sample. If every sample in a
+/// chunk is flagged, the unweighted mean (the middle of the chunk) is used.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if the array dimensions don't
+/// match.
+#[allow(clippy::needless_pass_by_value)]
+pub fn averaged_centroids<SW, SF>(
+    timestamps: &[Epoch],
+    freqs_hz: &[f64],
+    weight_array: ArrayBase<SW, Ix4>,
+    flag_array: ArrayBase<SF, Ix4>,
+    avg_time: usize,
+    avg_freq: usize,
+) -> Result<(Array3<Epoch>, Array3<f64>), AveragingError>
+where
+    SW: Data<Elem = f32>,
+    SF: Data<Elem = bool>,
+{
+    let weight_dims = weight_array.dim();
+    let pol_dims = [timestamps.len(), freqs_hz.len(), weight_dims.2, 4];
+    for (argument, received) in [
+        ("weight_array", weight_array.shape()),
+        ("flag_array", flag_array.shape()),
+    ] {
+        if received != pol_dims {
+            return Err(AveragingError::BadArrayShape {
+                argument: argument.to_string(),
+                function: "averaged_centroids".to_string(),
+                expected: format!("{pol_dims:?}"),
+                received: format!("{received:?}"),
+            });
+        }
+    }
+    let averaged_dims = (
+        (timestamps.len() as f64 / avg_time as f64).ceil() as usize,
+        (freqs_hz.len() as f64 / avg_freq as f64).ceil() as usize,
+        weight_dims.2,
+    );
+    // the fill value is irrelevant; every element is written below.
+    let fill = timestamps
+        .first()
+        .copied()
+        .unwrap_or_else(|| Epoch::from_gpst_seconds(0.));
+    let mut centroid_times = Array3::from_elem(averaged_dims, fill);
+    let mut centroid_freqs = Array3::<f64>::zeros(averaged_dims);
+
+    for (avg_timestep_idx, (chunk_timestamps, weight_timestep_chunk, flag_timestep_chunk)) in izip!(
+        timestamps.chunks(avg_time),
+        weight_array.axis_chunks_iter(Axis(0), avg_time),
+        flag_array.axis_chunks_iter(Axis(0), avg_time),
+    )
+    .enumerate()
+    {
+        // time offsets are relative to the first timestamp of the chunk.
+        let chunk_start = chunk_timestamps[0];
+        let offsets_s: Vec<f64> = chunk_timestamps
+            .iter()
+            .map(|&t| (t - chunk_start).to_seconds())
+            .collect();
+        for (avg_chan_idx, (chunk_freqs_hz, weight_chunk, flag_chunk)) in izip!(
+            freqs_hz.chunks(avg_freq),
+            weight_timestep_chunk.axis_chunks_iter(Axis(1), avg_freq),
+            flag_timestep_chunk.axis_chunks_iter(Axis(1), avg_freq),
+        )
+        .enumerate()
+        {
+            let num_chunk_timesteps = chunk_timestamps.len();
+            let num_chunk_chans = chunk_freqs_hz.len();
+            for (baseline_idx, (weights, flags)) in izip!(
+                weight_chunk.axis_iter(Axis(2)),
+                flag_chunk.axis_iter(Axis(2)),
+            )
+            .enumerate()
+            {
+                let mut weight_sum = 0.0;
+                let mut time_sum = 0.0;
+                let mut freq_sum = 0.0;
+                for (t, &offset_s) in offsets_s.iter().enumerate() {
+                    for (f, &freq_hz) in chunk_freqs_hz.iter().enumerate() {
+                        let sample_weight: f64 = izip!(
+                            weights.slice(s![t, f, ..]).iter(),
+                            flags.slice(s![t, f, ..]).iter()
+                        )
+                        .filter(|(&weight, &flag)| !flag && weight >= 0.)
+                        .map(|(&weight, _)| weight as f64)
+                        .sum();
+                        weight_sum += sample_weight;
+                        time_sum += sample_weight * offset_s;
+                        freq_sum += sample_weight * freq_hz;
+                    }
+                }
+                let (offset_s, freq_hz) = if weight_sum > 0.0 {
+                    (time_sum / weight_sum, freq_sum / weight_sum)
+                } else {
+                    (
+                        offsets_s.iter().sum::<f64>() / num_chunk_timesteps as f64,
+                        chunk_freqs_hz.iter().sum::<f64>() / num_chunk_chans as f64,
+                    )
+                };
+                let out = (avg_timestep_idx, avg_chan_idx, baseline_idx);
+                centroid_times[out] = chunk_start + Duration::from_seconds(offset_s);
+                centroid_freqs[out] = freq_hz;
+            }
+        }
+    }
+
+    Ok((centroid_times, centroid_freqs))
+}
+
+/// The fractional amplitude lost (decorrelation) when averaging a visibility
+/// over `interval_s` seconds, on a baseline of length `baseline_m` \[metres\]
+/// at `freq_hz` \[Hz\].
+///
+/// This is the worst case, where the fringe rate is the maximum possible for
+/// the baseline (`ωₑ |b| / λ`), so the phase winds by `Δφ = 2π ωₑ |b| Δt / λ`
+/// over the interval, and the amplitude is reduced by `sinc(Δφ / 2)`.
+pub fn time_decorrelation(baseline_m: f64, freq_hz: f64, interval_s: f64) -> f64 {
+    let x = PI * EARTH_ROTATION_RAD_S * baseline_m * interval_s * freq_hz / VEL_C;
+    if x.abs() < f64::EPSILON {
+        0.0
+    } else {
+        1.0 - x.sin() / x
+    }
+}
+
+/// The fraction of amplitude retained when averaging a visibility over
+/// `interval_s` seconds at `freq_hz` \[Hz\], for a source at `lmn` relative to
+/// a phase centre at declination `dec_rad`, on a baseline `uvw` \[metres\].
+///
+/// As the Earth rotates, `du/dt = ωₑ (w cos(δ) - v sin(δ))`,
+/// `dv/dt = ωₑ u sin(δ)` and `dw/dt = -ωₑ u cos(δ)`, so the phase of the source
+/// after phasing winds at the residual fringe rate
+/// `2π (l du/dt + m dv/dt + (n - 1) dw/dt) / λ`. This is `1 -
+/// time_decorrelation` of the equivalent baseline; the phase centre itself
+/// (`l = m = 0`) doesn't decorrelate.
+pub fn time_decorrelation_factor(
+    uvw: UVW,
+    lmn: LMN,
+    dec_rad: f64,
+    freq_hz: f64,
+    interval_s: f64,
+) -> f64 {
+    let (s_dec, c_dec) = dec_rad.sin_cos();
+    let rate_m = lmn.l * (uvw.w * c_dec - uvw.v * s_dec) + lmn.m * uvw.u * s_dec
+        - (lmn.n - 1.0) * uvw.u * c_dec;
+    1.0 - time_decorrelation(rate_m, freq_hz, interval_s)
+}
+
+/// Choose a time averaging factor for each baseline for baseline-dependent
+/// averaging: the largest factor (up to `max_factor`) for which the
+/// [`time_decorrelation`] at `max_freq_hz` is at most `max_decorrelation`
+/// (e.g. 0.01 for 1%). Baselines always get a factor of at least 1.
+pub fn bda_time_factors(
+    baseline_lengths_m: &[f64],
+    int_time: Duration,
+    max_freq_hz: f64,
+    max_decorrelation: f64,
+    max_factor: usize,
+) -> Vec<usize> {
+    let int_time_s = int_time.to_seconds();
+    baseline_lengths_m
+        .iter()
+        .map(|&length_m| {
+            (1..=max_factor.max(1))
+                .take_while(|&factor| {
+                    factor == 1
+                        || time_decorrelation(length_m, max_freq_hz, factor as f64 * int_time_s)
+                            <= max_decorrelation
+                })
+                .last()
+                .unwrap_or(1)
+        })
+        .collect()
+}
+
+/// The averaged visibilities of a single baseline from
+/// [`average_visibilities_bda`].
+#[derive(Debug, Clone)]
+pub struct BdaBaseline {
+    /// The time averaging factor used for this baseline.
+    pub avg_time: usize,
+    /// Averaged visibilities, `[timestep][channel]`.
+    pub jones: Array2<Jones<f32>>,
+    /// Averaged weights, `[timestep][channel][pol]`.
+    pub weights: Array3<f32>,
+    /// Averaged flags, `[timestep][channel][pol]`.
+    pub flags: Array3<bool>,
+}
+
+/// Baseline-dependent averaging: average each baseline of the visibilities in
+/// time by its own factor in `time_factors` (e.g. from [`bda_time_factors`]),
+/// and all baselines in frequency by `avg_freq`.
+///
+/// Short baselines decorrelate slowly, so they can be averaged much more than
+/// long baselines. Because the number of averaged timesteps differs between
+/// baselines, the result is ragged; one [`BdaBaseline`] per input baseline.
+///
+/// Array dimensions are the same as [`average_visibilities`], and each
+/// baseline is averaged in the same way.
+///
+/// # Errors
+///
+/// Will return [`AveragingError::BadArrayShape`] if there isn't a time factor
+/// for every baseline, or [`AveragingError::ZeroAveragingFactor`] if any time
+/// factor or `avg_freq` is 0.
+#[allow(clippy::needless_pass_by_value)]
+pub fn average_visibilities_bda<SJ, SW, SF>(
+    jones_array: ArrayBase<SJ, Ix3>,
+    weight_array: ArrayBase<SW, Ix4>,
+    flag_array: ArrayBase<SF, Ix4>,
+    time_factors: &[usize],
+    avg_freq: usize,
+) -> Result<Vec<BdaBaseline>, AveragingError>
+where
+    SJ: Data<Elem = Jones<f32>>,
+    SW: Data<Elem = f32>,
+    SF: Data<Elem = bool>,
+{
+    let num_baselines = jones_array.len_of(Axis(2));
+    if time_factors.len() != num_baselines {
+        return Err(AveragingError::BadArrayShape {
+            argument: "time_factors".to_string(),
+            function: "average_visibilities_bda".to_string(),
+            expected: format!("({num_baselines},)"),
+            received: format!("({},)", time_factors.len()),
+        });
+    }
+    for &avg_time in time_factors {
+        check_averaging_factors(avg_time, avg_freq, "average_visibilities_bda")?;
+    }
+
+    time_factors
+        .iter()
+        .enumerate()
+        .map(|(baseline_idx, &avg_time)| {
+            let bl = baseline_idx..baseline_idx + 1;
+            let (jones, weights, flags) = average_visibilities(
+                jones_array.slice(s![.., .., bl.clone()]),
+                weight_array.slice(s![.., .., bl.clone(), ..]),
+                flag_array.slice(s![.., .., bl, ..]),
+                avg_time,
+                avg_freq,
+            )?;
+            Ok(BdaBaseline {
+                avg_time,
+                jones: jones.index_axis_move(Axis(2), 0),
+                weights: weights.index_axis_move(Axis(2), 0),
+                flags: flags.index_axis_move(Axis(2), 0),
+            })
+        })
+        .collect()
+}
+
+/// An incremental averager, which is fed visibilities one timestep at a time
+/// and produces averaged timesteps as they complete, so the whole selection
+/// doesn't need to be in memory.
+///
+/// Only `avg_time` timesteps are buffered at once. Averaging is identical to
+/// [`average_visibilities`].
+pub struct Averager {
+    avg_time: usize,
+    avg_freq: usize,
+    jones_buffer: Array3<Jones<f32>>,
+    weight_buffer: Array4<f32>,
+    flag_buffer: Array4<bool>,
+    /// The number of timesteps currently in the buffers.
+    num_buffered: usize,
+}
+
+impl Averager {
+    /// Create an averager for timesteps of `num_chans` channels and
+    /// `num_baselines` baselines.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`AveragingError::ZeroAveragingFactor`] if `avg_time` or
+    /// `avg_freq` is 0.
+    pub fn new(
+        avg_time: usize,
+        avg_freq: usize,
+        num_chans: usize,
+        num_baselines: usize,
+    ) -> Result<Self, AveragingError> {
+        check_averaging_factors(avg_time, avg_freq, "Averager::new")?;
+        Ok(Self {
+            avg_time,
+            avg_freq,
+            jones_buffer: Array3::zeros((avg_time, num_chans, num_baselines)),
+            weight_buffer: Array4::zeros((avg_time, num_chans, num_baselines, 4)),
+            flag_buffer: Array4::from_elem((avg_time, num_chans, num_baselines, 4), false),
+            num_buffered: 0,
+        })
+    }
+
+    /// The number of timesteps pushed since the last averaged timestep was
+    /// produced.
+    pub fn num_buffered(&self) -> usize {
+        self.num_buffered
+    }
+
+    /// Add a timestep of visibilities.
+    ///
+    /// `jones` has dimensions `[channel][baseline]`, and `weights` and `flags`
+    /// have dimensions `[channel][baseline][pol]`.
+    ///
+    /// Once `avg_time` timesteps have been pushed, they are averaged and
+    /// returned as a single averaged timestep, with the same dimensions as the
+    /// output of [`average_visibilities`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`AveragingError::BadArrayShape`] if the arrays don't match
+    /// the dimensions given to [`Averager::new`].
+    pub fn push_timestep(
+        &mut self,
+        jones: ArrayView2<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        flags: ArrayView3<bool>,
+    ) -> Result<Option<VisData344>, AveragingError> {
+        let (_, num_chans, num_baselines) = self.jones_buffer.dim();
+        for (argument, received, expected) in [
+            ("jones", jones.shape(), vec![num_chans, num_baselines]),
+            (
+                "weights",
+                weights.shape(),
+                vec![num_chans, num_baselines, 4],
+            ),
+            ("flags", flags.shape(), vec![num_chans, num_baselines, 4]),
+        ] {
+            if received != expected.as_slice() {
+                return Err(AveragingError::BadArrayShape {
+                    argument: argument.to_string(),
+                    function: "Averager::push_timestep".to_string(),
+                    expected: format!("{expected:?}"),
+                    received: format!("{received:?}"),
+                });
+            }
+        }
+
+        let idx = self.num_buffered;
+        self.jones_buffer
+            .index_axis_mut(Axis(0), idx)
+            .assign(&jones);
+        self.weight_buffer
+            .index_axis_mut(Axis(0), idx)
+            .assign(&weights);
+        self.flag_buffer.index_axis_mut(Axis(0), idx).assign(&flags);
+        self.num_buffered += 1;
+
+        if self.num_buffered == self.avg_time {
+            self.flush()
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Average any buffered timesteps, even if there are fewer than
+    /// `avg_time` of them (e.g. at the end of an observation). Returns `None`
+    /// if nothing is buffered.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error from [`average_visibilities`].
+    pub fn flush(&mut self) -> Result<Option<VisData344>, AveragingError> {
+        if self.num_buffered == 0 {
+            return Ok(None);
+        }
+        let num_buffered = self.num_buffered;
+        self.num_buffered = 0;
+        average_visibilities(
+            self.jones_buffer.slice(s![..num_buffered, .., ..]),
+            self.weight_buffer.slice(s![..num_buffered, .., .., ..]),
+            self.flag_buffer.slice(s![..num_buffered, .., .., ..]),
+            num_buffered,
+            self.avg_freq,
+        )
+        .map(Some)
+    }
+}
+
 #[cfg(test)]
 mod tess {
-    use crate::Complex;
+    use crate::{
+        constants::{EARTH_ROTATION_RAD_S, VEL_C},
+        Complex, HADec, RADec, VisSelection, WeightScaling, XyzGeodetic, UVW,
+    };
     use approx::assert_abs_diff_eq;
-    use ndarray::prelude::*;
+    use itertools::izip;
+    use ndarray::{prelude::*, ArcArray, CowArray};
+    use std::f64::consts::PI;
 
-    use super::{average_visibilities, Jones};
+    use hifitime::{Duration, Epoch};
+
+    use super::{
+        accumulate_chunk_lanes, average_chunk, average_chunk_for_pols, average_redundant_baselines,
+        average_selection_chunks, average_visibilities, average_visibilities_bda,
+        average_visibilities_by_coarse_chan, average_visibilities_f64, average_visibilities_into,
+        average_visibilities_scalar, average_visibilities_to_resolution,
+        average_visibilities_with_options, averaged_centroids, bda_time_factors,
+        decimate_visibilities, freq_factor_from_resolution, time_decorrelation,
+        time_decorrelation_factor, time_factor_from_resolution, AllFlaggedPolicy, AutoCorrelations,
+        Averager, AveragingError, AveragingOptions, AveragingStats, DecorrelationWeighting, Jones,
+        ProgressCallback, SigmaClip, VisData344, WeightMode,
+    };
 
     fn synthesize_test_data(
         shape: (usize, usize, usize, usize),
@@ -473,5 +2555,1499 @@ mod tess {
         averaged_flag_array.for_each(|&v| assert!(!v));
     }
 
+    #[test]
+    fn test_averaging_generic_storage() {
+        let shape = (4, 6, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+
+        let (expected_vis, expected_weights, expected_flags) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            3,
+        )
+        .unwrap();
+
+        // shared and copy-on-write storage should not need to be copied into a
+        // view to be averaged.
+        let vis_arc: ArcArray<Jones<f32>, Ix3> = vis_array.into_shared();
+        let weight_cow = CowArray::from(weight_array.view());
+        let (averaged_vis, averaged_weights, averaged_flags) =
+            average_visibilities(vis_arc, weight_cow, flag_array, 2, 3).unwrap();
+
+        assert_abs_diff_eq!(averaged_vis, expected_vis);
+        assert_abs_diff_eq!(averaged_weights, expected_weights);
+        assert_eq!(averaged_flags, expected_flags);
+    }
+
+    #[test]
+    fn test_factors_from_resolution() {
+        let int_time = Duration::from_seconds(2.);
+        assert_eq!(
+            time_factor_from_resolution(int_time, Duration::from_seconds(8.)).unwrap(),
+            4
+        );
+        assert_eq!(time_factor_from_resolution(int_time, int_time).unwrap(), 1);
+        assert!(matches!(
+            time_factor_from_resolution(int_time, Duration::from_seconds(3.)),
+            Err(AveragingError::NonIntegerResolution { axis: "time", .. })
+        ));
+        assert!(matches!(
+            time_factor_from_resolution(int_time, Duration::from_seconds(0.)),
+            Err(AveragingError::NonIntegerResolution { .. })
+        ));
+
+        assert_eq!(freq_factor_from_resolution(10e3, 40e3).unwrap(), 4);
+        assert_eq!(freq_factor_from_resolution(40e3, 40e3).unwrap(), 1);
+        assert!(matches!(
+            freq_factor_from_resolution(40e3, 100e3),
+            Err(AveragingError::NonIntegerResolution {
+                axis: "frequency",
+                ..
+            })
+        ));
+        assert!(freq_factor_from_resolution(40e3, 20e3).is_err());
+    }
+
+    #[test]
+    fn test_bda() {
+        // 1% decorrelation at 200MHz with 2s integrations.
+        let int_time = Duration::from_seconds(2.);
+        let lengths_m = [0., 100., 200., 400., 1000.];
+        let factors = bda_time_factors(&lengths_m, int_time, 200e6, 0.01, 8);
+        assert_eq!(factors, vec![8, 8, 4, 2, 1]);
+        for (&length_m, &factor) in lengths_m.iter().zip(factors.iter()) {
+            assert!(factor == 1 || time_decorrelation(length_m, 200e6, factor as f64 * 2.) <= 0.01);
+            assert!(
+                factor == 8 || time_decorrelation(length_m, 200e6, (factor + 1) as f64 * 2.) > 0.01
+            );
+        }
+
+        let shape = (8, 4, 2, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let bda = average_visibilities_bda(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            &[4, 1],
+            2,
+        )
+        .unwrap();
+        assert_eq!(bda[0].jones.dim(), (2, 2));
+        assert_eq!(bda[1].jones.dim(), (8, 2));
+        assert_eq!(bda[1].weights.dim(), (8, 2, 4));
+
+        // each baseline matches uniform averaging by that baseline's factor.
+        let (expected_vis, expected_weights, _) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            4,
+            2,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(bda[0].jones, expected_vis.index_axis(Axis(2), 0));
+        assert_abs_diff_eq!(bda[0].weights, expected_weights.index_axis(Axis(2), 0));
+
+        assert!(matches!(
+            average_visibilities_bda(
+                vis_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                &[1],
+                1
+            ),
+            Err(AveragingError::BadArrayShape { .. })
+        ));
+        for (time_factors, avg_freq, expected_axis) in
+            [([4, 0], 2, "time"), ([4, 1], 0, "frequency")]
+        {
+            assert!(matches!(
+                average_visibilities_bda(
+                    vis_array.view(),
+                    weight_array.view(),
+                    flag_array.view(),
+                    &time_factors,
+                    avg_freq
+                ),
+                Err(AveragingError::ZeroAveragingFactor { axis, .. }) if axis == expected_axis
+            ));
+        }
+    }
+
+    #[test]
+    fn test_averager_matches_average_visibilities() {
+        let shape = (7, 6, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let (expected_vis, expected_weights, expected_flags) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            3,
+            2,
+        )
+        .unwrap();
+
+        let mut averager = Averager::new(3, 2, 6, 3).unwrap();
+        let mut outputs = vec![];
+        for (jones, weights, flags) in izip!(
+            vis_array.outer_iter(),
+            weight_array.outer_iter(),
+            flag_array.outer_iter()
+        ) {
+            if let Some(output) = averager.push_timestep(jones, weights, flags).unwrap() {
+                outputs.push(output);
+            }
+        }
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(averager.num_buffered(), 1);
+        // the last, partial chunk.
+        outputs.push(averager.flush().unwrap().unwrap());
+        assert!(averager.flush().unwrap().is_none());
+
+        for (avg_idx, (vis, weights, flags)) in outputs.iter().enumerate() {
+            assert_eq!(vis.dim(), (1, 3, 3));
+            assert_abs_diff_eq!(
+                vis.index_axis(Axis(0), 0),
+                expected_vis.index_axis(Axis(0), avg_idx)
+            );
+            assert_abs_diff_eq!(
+                weights.index_axis(Axis(0), 0),
+                expected_weights.index_axis(Axis(0), avg_idx)
+            );
+            assert_eq!(
+                flags.index_axis(Axis(0), 0),
+                expected_flags.index_axis(Axis(0), avg_idx)
+            );
+        }
+
+        assert!(matches!(
+            averager.push_timestep(
+                vis_array.slice(s![0, .., ..2]),
+                weight_array.slice(s![0, .., .., ..]),
+                flag_array.slice(s![0, .., .., ..]),
+            ),
+            Err(AveragingError::BadArrayShape { .. })
+        ));
+
+        for (avg_time, avg_freq) in [(0, 2), (3, 0)] {
+            assert!(matches!(
+                Averager::new(avg_time, avg_freq, 6, 3),
+                Err(AveragingError::ZeroAveragingFactor { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_average_chunk_functions() {
+        let shape = (2, 3, 1, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let (expected_vis, expected_weights, expected_flags) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            3,
+        )
+        .unwrap();
+
+        let mut avg_jones = Jones::default();
+        let mut avg_weights = Array1::zeros(4);
+        let mut avg_flags = Array1::from_elem(4, false);
+        average_chunk_for_pols(
+            vis_array.slice(s![.., .., 0]),
+            weight_array.slice(s![.., .., 0, ..]),
+            flag_array.slice(s![.., .., 0, ..]),
+            &mut avg_jones,
+            avg_weights.view_mut(),
+            avg_flags.view_mut(),
+        )
+        .unwrap();
+        assert_abs_diff_eq!(avg_jones, expected_vis[(0, 0, 0)]);
+        assert_abs_diff_eq!(avg_weights, expected_weights.slice(s![0, 0, 0, ..]));
+        assert_eq!(avg_flags, expected_flags.slice(s![0, 0, 0, ..]));
+
+        assert!(matches!(
+            average_chunk_for_pols(
+                vis_array.slice(s![.., .., 0]),
+                weight_array.slice(s![.., ..2, 0, ..]),
+                flag_array.slice(s![.., .., 0, ..]),
+                &mut avg_jones,
+                avg_weights.view_mut(),
+                avg_flags.view_mut(),
+            ),
+            Err(AveragingError::BadArrayShape { .. })
+        ));
+
+        // a flagged (negative) weight is ignored.
+        let jones = array![[Jones::identity(), Jones::identity() * 3.0]];
+        let (avg_jones, avg_weight, avg_flag) =
+            average_chunk(jones.view(), array![[2_f32, -1.]].view()).unwrap();
+        assert_abs_diff_eq!(avg_jones, Jones::identity());
+        assert_abs_diff_eq!(avg_weight, 2.);
+        assert!(!avg_flag);
+        // all flagged: unweighted mean.
+        let (avg_jones, _, avg_flag) =
+            average_chunk(jones.view(), array![[-2_f32, -1.]].view()).unwrap();
+        assert_abs_diff_eq!(avg_jones, Jones::identity() * 2.0);
+        assert!(avg_flag);
+
+        assert!(average_chunk(jones.view(), array![[1_f32]].view()).is_err());
+    }
+
+    #[test]
+    fn test_average_visibilities_to_resolution() {
+        let shape = (4, 8, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let int_time = Duration::from_seconds(0.5);
+
+        let ((averaged_vis, _, _), res) = average_visibilities_to_resolution(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            int_time,
+            10e3,
+            Some(Duration::from_seconds(2.)),
+            Some(40e3),
+        )
+        .unwrap();
+        assert_eq!((res.avg_time, res.avg_freq), (4, 4));
+        assert_eq!(res.int_time, Duration::from_seconds(2.));
+        assert_abs_diff_eq!(res.freq_resolution_hz, 40e3);
+        let (expected_vis, _, _) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            4,
+            4,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(averaged_vis, expected_vis);
+
+        let (_, res) = average_visibilities_to_resolution(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            int_time,
+            10e3,
+            None,
+            Some(20e3),
+        )
+        .unwrap();
+        assert_eq!((res.avg_time, res.avg_freq), (1, 2));
+
+        assert!(matches!(
+            average_visibilities_to_resolution(
+                vis_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                int_time,
+                10e3,
+                Some(Duration::from_seconds(0.75)),
+                None,
+            ),
+            Err(AveragingError::NonIntegerResolution { axis: "time", .. })
+        ));
+    }
+
+    #[test]
+    fn test_average_visibilities_skip_nan() {
+        let shape = (2, 2, 2, 4);
+        let (mut vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        // the default options match average_visibilities exactly.
+        let expected = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+        )
+        .unwrap();
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions::default(),
+        )
+        .unwrap();
+        assert_abs_diff_eq!(averaged.jones, expected.0);
+        assert_abs_diff_eq!(averaged.weights, expected.1);
+        assert_eq!(averaged.flags, expected.2);
+        assert_eq!(averaged.num_nan_skipped, 0);
+
+        // poison the XX pol of one sample of the first baseline.
+        vis_array[(1, 0, 0)][0] = Complex::new(f32::NAN, 0.);
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions::default(),
+        )
+        .unwrap();
+        assert!(averaged.jones[(0, 0, 0)][0].is_nan());
+
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions {
+                skip_nan: true,
+                ..AveragingOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(averaged.num_nan_skipped, 1);
+        assert!(!averaged.jones[(0, 0, 0)][0].is_nan());
+        assert_abs_diff_eq!(
+            averaged.weights[(0, 0, 0, 0)],
+            expected.1[(0, 0, 0, 0)] - weight_array[(1, 0, 0, 0)]
+        );
+        // the other pols and baselines are unaffected.
+        assert_abs_diff_eq!(averaged.jones[(0, 0, 0)][3], expected.0[(0, 0, 0)][3]);
+        assert_abs_diff_eq!(averaged.jones[(0, 0, 1)], expected.0[(0, 0, 1)]);
+    }
+
+    #[test]
+    fn test_average_visibilities_min_unflagged_fraction() {
+        let shape = (2, 2, 1, 4);
+        let (vis_array, weight_array, mut flag_array) = synthesize_test_data(shape);
+        flag_array.fill(false);
+        // flag 1 of the 4 XX samples, and 3 of the 4 YY samples.
+        flag_array[(0, 0, 0, 0)] = true;
+        flag_array.slice_mut(s![.., .., 0, 3]).fill(true);
+        flag_array[(1, 1, 0, 3)] = false;
+
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(averaged.flags, array![[[[false, false, false, false]]]]);
+
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions {
+                min_unflagged_fraction: 0.5,
+                ..AveragingOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(averaged.flags, array![[[[false, false, false, true]]]]);
+        // the averaged values are unchanged; only the flags differ.
+        let (expected_vis, expected_weights, _) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(averaged.jones, expected_vis);
+        assert_abs_diff_eq!(averaged.weights, expected_weights);
+    }
+
+    #[test]
+    fn test_average_visibilities_sample_counts() {
+        let shape = (4, 3, 1, 4);
+        let (vis_array, weight_array, mut flag_array) = synthesize_test_data(shape);
+        flag_array.fill(false);
+        // flag the first timestep, and one XX sample of the last channel.
+        flag_array.slice_mut(s![0, .., .., ..]).fill(true);
+        flag_array[(3, 2, 0, 0)] = true;
+
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            4,
+            2,
+            &AveragingOptions::default(),
+        )
+        .unwrap();
+        assert!(averaged.num_unflagged.is_none());
+        assert!(averaged
+            .effective_int_times(Duration::from_seconds(2.))
+            .is_none());
+
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            4,
+            2,
+            &AveragingOptions {
+                sample_counts: true,
+                ..AveragingOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            *averaged.num_unflagged.as_ref().unwrap(),
+            array![[[[6_u32, 6, 6, 6]], [[2, 3, 3, 3]]]]
+        );
+        // 3 of 4 timesteps are unflagged, so 6s of 8s.
+        let int_times = averaged
+            .effective_int_times(Duration::from_seconds(2.))
+            .unwrap();
+        assert_abs_diff_eq!(int_times, array![[[[6., 6., 6., 6.]], [[4., 6., 6., 6.]]]]);
+    }
+
+    #[test]
+    fn test_average_visibilities_weight_scaling() {
+        // 3 timesteps and channels, so the last chunks are short.
+        let shape = (3, 3, 2, 4);
+        let (vis_array, mut weight_array, mut flag_array) = synthesize_test_data(shape);
+        weight_array.fill(1.);
+        flag_array.fill(false);
+
+        let average = |weight_scaling| {
+            average_visibilities_with_options(
+                vis_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                2,
+                2,
+                &AveragingOptions {
+                    weight_scaling,
+                    ..AveragingOptions::default()
+                },
+            )
+            .unwrap()
+        };
+        let cotter = average(WeightScaling::Cotter);
+        assert_abs_diff_eq!(
+            cotter.weights.slice(s![.., .., 0, 0]),
+            array![[4., 2.], [2., 1.]]
+        );
+        let unit = average(WeightScaling::Unit);
+        assert_abs_diff_eq!(unit.weights, Array4::ones(unit.weights.dim()));
+        assert_abs_diff_eq!(unit.jones, cotter.jones);
+        assert_eq!(average(WeightScaling::Physical).weights, cotter.weights);
+    }
+
+    #[test]
+    fn test_average_visibilities_sigma_clip() {
+        let shape = (4, 4, 2, 4);
+        let (mut vis_array, mut weight_array, mut flag_array) = synthesize_test_data(shape);
+        weight_array.fill(1.);
+        flag_array.fill(false);
+        let (expected_vis, expected_weights, _) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            4,
+            4,
+        )
+        .unwrap();
+        // an RFI spike in the XX pol of one sample of the first baseline.
+        vis_array[(2, 1, 0)][0] = Complex::new(1000., 1000.);
+
+        let options = AveragingOptions {
+            sigma_clip: Some(SigmaClip::default()),
+            sample_counts: true,
+            ..AveragingOptions::default()
+        };
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            4,
+            4,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(averaged.num_clipped, 1);
+        assert_eq!(
+            *averaged.num_unflagged.as_ref().unwrap(),
+            array![[[[15_u32, 16, 16, 16], [16, 16, 16, 16]]]]
+        );
+        assert_abs_diff_eq!(averaged.weights[(0, 0, 0, 0)], 15.);
+        assert!(averaged.jones[(0, 0, 0)][0].norm() < 10.);
+        // without outliers, clipping changes nothing.
+        assert_abs_diff_eq!(averaged.jones[(0, 0, 1)], expected_vis[(0, 0, 1)]);
+        assert_abs_diff_eq!(
+            averaged.weights.slice(s![.., .., 1, ..]),
+            expected_weights.slice(s![.., .., 1, ..])
+        );
+    }
+
+    #[test]
+    fn test_time_decorrelation_factor() {
+        // the phase centre doesn't decorrelate.
+        let uvw = UVW {
+            u: 1000.,
+            v: 500.,
+            w: 10.,
+        };
+        let phase_centre = RADec::from_degrees(0., -27.);
+        let lmn = phase_centre.to_lmn(phase_centre);
+        assert_abs_diff_eq!(
+            time_decorrelation_factor(uvw, lmn, phase_centre.dec, 150e6, 120.),
+            1.0
+        );
+
+        // average the phase of an offset source over the hour angles of the
+        // interval, and compare with the sinc.
+        let xyz = XyzGeodetic {
+            x: 300.,
+            y: 800.,
+            z: 200.,
+        };
+        let (ha, dec) = (15_f64.to_radians(), -27_f64.to_radians());
+        let lmn = RADec::from_degrees(5., -12.).to_lmn(RADec::from_degrees(0., -27.));
+        let (freq_hz, interval_s) = (150e6, 120.);
+        let num_samples = 1001;
+        let sum: Complex<f64> = (0..num_samples)
+            .map(|i| {
+                let offset_s = interval_s * (i as f64 / (num_samples - 1) as f64 - 0.5);
+                let uvw = UVW::from_xyz(
+                    xyz,
+                    HADec::from_radians(ha + EARTH_ROTATION_RAD_S * offset_s, dec),
+                );
+                Complex::cis(lmn.dot(uvw) * freq_hz / VEL_C)
+            })
+            .sum();
+        let factor = time_decorrelation_factor(
+            UVW::from_xyz(xyz, HADec::from_radians(ha, dec)),
+            lmn,
+            dec,
+            freq_hz,
+            interval_s,
+        );
+        assert!(factor < 0.9);
+        assert_abs_diff_eq!(factor, sum.norm() / num_samples as f64, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_average_visibilities_decorrelation_weights() {
+        let shape = (8, 4, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let uvws = [
+            UVW {
+                u: 0.,
+                v: 10.,
+                w: 0.,
+            },
+            UVW {
+                u: 100.,
+                v: 0.,
+                w: 0.,
+            },
+            UVW {
+                u: 1000.,
+                v: 0.,
+                w: 0.,
+            },
+        ];
+        let freqs_hz = [150e6, 150.04e6, 150.08e6, 150.12e6];
+        let phase_centre = RADec::from_degrees(0., -27.);
+        // 20 degrees north of the phase centre, so l = 0.
+        let direction = RADec::from_degrees(0., -7.);
+        let int_time = Duration::from_seconds(30.);
+        let decorrelation = DecorrelationWeighting {
+            uvws: &uvws,
+            phase_centre,
+            direction,
+            int_time,
+            freqs_hz: &freqs_hz,
+        };
+        let (_, expected_weights, _) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            4,
+            2,
+        )
+        .unwrap();
+
+        let average = |decorrelation, weight_mode| {
+            average_visibilities_with_options(
+                vis_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                4,
+                2,
+                &AveragingOptions {
+                    decorrelation: Some(decorrelation),
+                    weight_mode,
+                    ..AveragingOptions::default()
+                },
+            )
+        };
+        let averaged = average(decorrelation, WeightMode::SumOfWeights).unwrap();
+        // with l = 0, baselines with no u component don't decorrelate.
+        assert_abs_diff_eq!(
+            averaged.weights.slice(s![.., .., 0, ..]),
+            expected_weights.slice(s![.., .., 0, ..])
+        );
+        // the first chunk of the longest baseline, against the analytic sinc of
+        // its residual fringe rate, u (m sin(δ) - (n - 1) cos(δ)).
+        let lmn = direction.to_lmn(phase_centre);
+        let rate_m =
+            1000. * (lmn.m * phase_centre.dec.sin() - (lmn.n - 1.0) * phase_centre.dec.cos());
+        let x = PI * EARTH_ROTATION_RAD_S * rate_m * 120. * 150.02e6 / VEL_C;
+        let factor = x.sin() / x;
+        assert!(factor < 0.9);
+        assert_abs_diff_eq!(
+            averaged.weights[(0, 0, 2, 0)],
+            expected_weights[(0, 0, 2, 0)] * factor as f32,
+            epsilon = 1e-3
+        );
+        // longer baselines are down-weighted more.
+        assert!(
+            averaged.weights[(0, 0, 1, 0)] / expected_weights[(0, 0, 1, 0)]
+                > averaged.weights[(0, 0, 2, 0)] / expected_weights[(0, 0, 2, 0)]
+        );
+
+        // nothing decorrelates at the phase centre.
+        let averaged = average(
+            DecorrelationWeighting {
+                direction: phase_centre,
+                ..decorrelation
+            },
+            WeightMode::SumOfWeights,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(averaged.weights, expected_weights);
+
+        // sample counts aren't rescaled.
+        let counts = average(decorrelation, WeightMode::UnflaggedSampleCount).unwrap();
+        let expected_counts = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            4,
+            2,
+            &AveragingOptions {
+                weight_mode: WeightMode::UnflaggedSampleCount,
+                ..AveragingOptions::default()
+            },
+        )
+        .unwrap();
+        assert_abs_diff_eq!(counts.weights, expected_counts.weights);
+
+        assert!(matches!(
+            average(
+                DecorrelationWeighting {
+                    uvws: &uvws[..2],
+                    ..decorrelation
+                },
+                WeightMode::SumOfWeights
+            ),
+            Err(AveragingError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_average_visibilities_by_coarse_chan() {
+        // 2 coarse channels of 5 fine channels.
+        let shape = (2, 10, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+
+        let (averaged_vis, averaged_weights, averaged_flags) = average_visibilities_by_coarse_chan(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            5,
+        )
+        .unwrap();
+        // each coarse channel averages to 3 channels, the last of 1 fine channel.
+        assert_eq!(averaged_vis.dim(), (1, 6, 3));
+        assert_eq!(averaged_weights.dim(), (1, 6, 3, 4));
+        assert_eq!(averaged_flags.dim(), (1, 6, 3, 4));
+
+        for (coarse_chan_idx, fine_chans) in [(0, 0..5), (1, 5..10)] {
+            let (expected_vis, expected_weights, expected_flags) = average_visibilities(
+                vis_array.slice(s![.., fine_chans.clone(), ..]),
+                weight_array.slice(s![.., fine_chans.clone(), .., ..]),
+                flag_array.slice(s![.., fine_chans, .., ..]),
+                2,
+                2,
+            )
+            .unwrap();
+            let avg_chans = coarse_chan_idx * 3..(coarse_chan_idx + 1) * 3;
+            assert_abs_diff_eq!(
+                averaged_vis.slice(s![.., avg_chans.clone(), ..]),
+                expected_vis
+            );
+            assert_abs_diff_eq!(
+                averaged_weights.slice(s![.., avg_chans.clone(), .., ..]),
+                expected_weights
+            );
+            assert_eq!(
+                averaged_flags.slice(s![.., avg_chans, .., ..]),
+                expected_flags
+            );
+        }
+
+        assert!(matches!(
+            average_visibilities_by_coarse_chan(
+                vis_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                2,
+                2,
+                4,
+            ),
+            Err(AveragingError::UnevenCoarseChans { .. })
+        ));
+    }
+
+    #[test]
+    fn test_average_visibilities_into() {
+        let shape = (6, 5, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let (expected_vis, expected_weights, expected_flags) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            3,
+            2,
+        )
+        .unwrap();
+
+        // the same buffers can be reused for each chunk.
+        let mut averaged_vis = Array3::zeros((1, 3, 3));
+        let mut averaged_weights = Array4::zeros((1, 3, 3, 4));
+        let mut averaged_flags = Array4::from_elem((1, 3, 3, 4), false);
+        for avg_timestep_idx in 0..2 {
+            let timesteps = avg_timestep_idx * 3..(avg_timestep_idx + 1) * 3;
+            average_visibilities_into(
+                vis_array.slice(s![timesteps.clone(), .., ..]),
+                weight_array.slice(s![timesteps.clone(), .., .., ..]),
+                flag_array.slice(s![timesteps, .., .., ..]),
+                3,
+                2,
+                averaged_vis.view_mut(),
+                averaged_weights.view_mut(),
+                averaged_flags.view_mut(),
+            )
+            .unwrap();
+            assert_abs_diff_eq!(
+                averaged_vis.index_axis(Axis(0), 0),
+                expected_vis.index_axis(Axis(0), avg_timestep_idx)
+            );
+            assert_abs_diff_eq!(
+                averaged_weights.index_axis(Axis(0), 0),
+                expected_weights.index_axis(Axis(0), avg_timestep_idx)
+            );
+            assert_eq!(
+                averaged_flags.index_axis(Axis(0), 0),
+                expected_flags.index_axis(Axis(0), avg_timestep_idx)
+            );
+        }
+
+        assert!(matches!(
+            average_visibilities_into(
+                vis_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                3,
+                2,
+                averaged_vis.view_mut(),
+                averaged_weights.view_mut(),
+                averaged_flags.view_mut(),
+            ),
+            Err(AveragingError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_average_visibilities_f64() {
+        let shape = (4, 6, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let (expected_vis, expected_weights, expected_flags) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            3,
+        )
+        .unwrap();
+        let vis_array_f64 = vis_array.mapv(Jones::<f64>::from);
+        let (averaged_vis, averaged_weights, averaged_flags) = average_visibilities_f64(
+            vis_array_f64.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            3,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(
+            averaged_vis.mapv(Jones::<f32>::from),
+            expected_vis,
+            epsilon = 1e-6
+        );
+        assert_abs_diff_eq!(averaged_weights, expected_weights);
+        assert_eq!(averaged_flags, expected_flags);
+
+        // f64 keeps precision that f32 can't represent.
+        let mut vis_array_f64 = Array3::from_elem((2, 1, 1), Jones::<f64>::identity() * 1e9);
+        vis_array_f64[(1, 0, 0)] = Jones::identity() * (1e9 + 1.0);
+        let weight_array = Array4::from_elem((2, 1, 1, 4), 1.0);
+        let flag_array = Array4::from_elem((2, 1, 1, 4), false);
+        let (averaged_vis, _, _) = average_visibilities_f64(
+            vis_array_f64.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            1,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(averaged_vis[(0, 0, 0)][0].re, 1e9 + 0.5);
+    }
+
+    #[test]
+    fn test_average_visibilities_scalar() {
+        let shape = (4, 6, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let weight_array = weight_array.index_axis_move(Axis(3), 1);
+        let flag_array = flag_array.index_axis_move(Axis(3), 1);
+
+        let (averaged_vis, averaged_weights, averaged_flags) = average_visibilities_scalar(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            3,
+        )
+        .unwrap();
+        assert_eq!(averaged_weights.dim(), (2, 2, 3));
+        assert_eq!(averaged_flags.dim(), (2, 2, 3));
+
+        // the same as giving every pol the visibility's weight and flag.
+        let pol_weights = Array4::from_shape_fn(shape, |(t, c, b, _)| weight_array[(t, c, b)]);
+        let pol_flags = Array4::from_shape_fn(shape, |(t, c, b, _)| flag_array[(t, c, b)]);
+        let (expected_vis, expected_weights, expected_flags) =
+            average_visibilities(vis_array.view(), pol_weights.view(), pol_flags.view(), 2, 3)
+                .unwrap();
+        assert_abs_diff_eq!(averaged_vis, expected_vis);
+        assert_abs_diff_eq!(averaged_weights, expected_weights.index_axis(Axis(3), 0));
+        assert_eq!(averaged_flags, expected_flags.index_axis(Axis(3), 0));
+
+        assert!(matches!(
+            average_visibilities_scalar(
+                vis_array.view(),
+                weight_array.slice(s![..2, .., ..]),
+                flag_array.view(),
+                2,
+                3,
+            ),
+            Err(AveragingError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_average_visibilities_progress() {
+        let shape = (5, 4, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let reports = std::sync::Mutex::new(vec![]);
+        let progress = |done: usize, total: usize| reports.lock().unwrap().push((done, total));
+        average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions {
+                progress: Some(ProgressCallback(&progress)),
+                ..AveragingOptions::default()
+            },
+        )
+        .unwrap();
+        // 3 averaged timesteps of 2 averaged channels.
+        assert_eq!(
+            reports.into_inner().unwrap(),
+            vec![(1, 6), (2, 6), (3, 6), (4, 6), (5, 6), (6, 6)]
+        );
+    }
+
+    #[test]
+    fn test_decimate_visibilities() {
+        let shape = (4, 3, 2, 4);
+        let (vis_array, weight_array, mut flag_array) = synthesize_test_data(shape);
+        flag_array.fill(false);
+        // flag every pol of the central sample of the first chunk of the
+        // second baseline.
+        flag_array.slice_mut(s![0, 1, 1, ..]).fill(true);
+        // flag everything in the last chunk of the first baseline.
+        flag_array.slice_mut(s![2.., .., 0, ..]).fill(true);
+
+        let (decimated_vis, decimated_weights, decimated_flags) = decimate_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            3,
+        )
+        .unwrap();
+        assert_eq!(decimated_vis.dim(), (2, 1, 2));
+
+        // the centre of a 2x3 chunk is timestep 0, channel 1.
+        assert_abs_diff_eq!(decimated_vis[(0, 0, 0)], vis_array[(0, 1, 0)]);
+        assert_abs_diff_eq!(
+            decimated_weights.slice(s![0, 0, 0, ..]),
+            weight_array.slice(s![0, 1, 0, ..])
+        );
+        // the next closest to the centre is timestep 1, channel 1.
+        assert_abs_diff_eq!(decimated_vis[(0, 0, 1)], vis_array[(1, 1, 1)]);
+        // all flagged, so the central sample is flagged.
+        assert_abs_diff_eq!(decimated_vis[(1, 0, 0)], vis_array[(2, 1, 0)]);
+        assert!(decimated_flags.slice(s![1, 0, 0, ..]).iter().all(|&f| f));
+        assert!(decimated_flags.slice(s![.., .., 1, ..]).iter().all(|&f| !f));
+    }
+
+    #[test]
+    fn test_averaged_centroids() {
+        let shape = (4, 2, 2, 4);
+        let (_, mut weight_array, mut flag_array) = synthesize_test_data(shape);
+        weight_array.fill(1.);
+        flag_array.fill(false);
+        let start = Epoch::from_gpst_seconds(1090008640.);
+        let timestamps: Vec<Epoch> = (0..4)
+            .map(|t| start + Duration::from_seconds(2. * t as f64))
+            .collect();
+        let freqs_hz = [150e6, 150.04e6];
+        // flag the second half of the first baseline.
+        flag_array.slice_mut(s![2.., .., 0, ..]).fill(true);
+        // flag the second channel of the second baseline.
+        flag_array.slice_mut(s![.., 1, 1, ..]).fill(true);
+
+        let (times, freqs) = averaged_centroids(
+            &timestamps,
+            &freqs_hz,
+            weight_array.view(),
+            flag_array.view(),
+            4,
+            2,
+        )
+        .unwrap();
+        assert_eq!(times.dim(), (1, 1, 2));
+        assert_abs_diff_eq!((times[(0, 0, 0)] - start).to_seconds(), 1., epsilon = 1e-6);
+        assert_abs_diff_eq!(freqs[(0, 0, 0)], 150.02e6, epsilon = 1e-3);
+        assert_abs_diff_eq!((times[(0, 0, 1)] - start).to_seconds(), 3., epsilon = 1e-6);
+        assert_abs_diff_eq!(freqs[(0, 0, 1)], 150e6, epsilon = 1e-3);
+
+        // everything flagged gives the middle of the chunk.
+        flag_array.fill(true);
+        let (times, freqs) = averaged_centroids(
+            &timestamps,
+            &freqs_hz,
+            weight_array.view(),
+            flag_array.view(),
+            4,
+            2,
+        )
+        .unwrap();
+        assert_abs_diff_eq!((times[(0, 0, 0)] - start).to_seconds(), 3., epsilon = 1e-6);
+        assert_abs_diff_eq!(freqs[(0, 0, 0)], 150.02e6, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_accumulate_chunk_lanes_matches_scalar() {
+        let shape = (2, 3, 1, 4);
+        let (mut vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        // a NaN in a flagged sample mustn't leak into the weighted sum.
+        assert!(flag_array[(0, 0, 0, 0)]);
+        vis_array[(0, 0, 0)][0] = Complex::new(f32::NAN, 0.);
+        let jones_chunk = vis_array.slice(s![.., .., 0]);
+        let weight_chunk = weight_array.slice(s![.., .., 0, ..]);
+        let flag_chunk = flag_array.slice(s![.., .., 0, ..]);
+
+        let mut accumulator = JonesAccumulator::new();
+        accumulate_chunk_lanes(&jones_chunk, &weight_chunk, &flag_chunk, &mut accumulator);
+
+        let mut expected_weighted_sum = Jones::<f64>::default();
+        let mut expected_weight_sum = [0.; 4];
+        let mut expected_num_unflagged = [0; 4];
+        for (jones, weights, flags) in
+            izip!(jones_chunk.iter(), weight_chunk.rows(), flag_chunk.rows())
+        {
+            for (jones_elem, &weight, &flag, weighted_sum, weight_sum, num_unflagged) in izip!(
+                jones.iter(),
+                weights.iter(),
+                flags.iter(),
+                expected_weighted_sum.iter_mut(),
+                expected_weight_sum.iter_mut(),
+                expected_num_unflagged.iter_mut(),
+            ) {
+                if !flag {
+                    *weighted_sum +=
+                        Complex::new(jones_elem.re as f64, jones_elem.im as f64) * weight as f64;
+                    *weight_sum += weight as f64;
+                    *num_unflagged += 1;
+                }
+            }
+        }
+        assert!(accumulator.sum[0].re.is_nan());
+        assert_eq!(accumulator.num_samples, jones_chunk.len());
+        assert_abs_diff_eq!(accumulator.weighted_sum, expected_weighted_sum);
+        assert_abs_diff_eq!(accumulator.weight_sum[..], expected_weight_sum[..]);
+        assert_eq!(accumulator.num_unflagged, expected_num_unflagged);
+
+        // The same sums come from pushing the samples one at a time.
+        let mut scalar = JonesAccumulator::new();
+        for (jones, weights, flags) in
+            izip!(jones_chunk.iter(), weight_chunk.rows(), flag_chunk.rows())
+        {
+            scalar.push(Jones::from(*jones), weights, flags);
+        }
+        assert_abs_diff_eq!(scalar.weighted_sum, accumulator.weighted_sum);
+        assert_eq!(scalar.num_unflagged(), accumulator.num_unflagged());
+    }
+
+    #[test]
+    fn test_jones_accumulator() {
+        let mut accumulator = JonesAccumulator::new();
+        let vis = |re| Jones::identity() * re;
+        accumulator.push(
+            vis(1.0),
+            array![1.0, 1.0, 1.0, -1.0].view(),
+            array![false, true, false, false].view(),
+        );
+        accumulator.push(
+            vis(4.0),
+            array![3.0, 1.0, f32::NAN, 2.0].view(),
+            array![false, false, false, false].view(),
+        );
+        assert!(!accumulator.all_flagged());
+        assert_eq!(accumulator.num_unflagged(), [2, 1, 1, 1]);
+        assert_abs_diff_eq!(accumulator.weight_sum()[..], [4.0, 1.0, 1.0, 2.0][..]);
+        let mean = accumulator.finalise();
+        assert_abs_diff_eq!(mean[0], Complex::new(3.25, 0.0));
+        assert_abs_diff_eq!(mean[3], Complex::new(4.0, 0.0));
+        assert_abs_diff_eq!(accumulator.unweighted_mean()[0], Complex::new(2.5, 0.0));
+
+        // Everything flagged.
+        let mut accumulator = JonesAccumulator::new();
+        accumulator.push_flagged(vis(1.0));
+        accumulator.push(
+            vis(2.0),
+            array![1.0, 1.0, 1.0, 1.0].view(),
+            array![true, true, true, true].view(),
+        );
+        assert!(accumulator.all_flagged());
+        assert_abs_diff_eq!(accumulator.finalise(), vis(1.5));
+    }
+
+    #[test]
+    fn test_average_visibilities_autos() {
+        let shape = (2, 2, 3, 4);
+        let (vis_array, weight_array, mut flag_array) = synthesize_test_data(shape);
+        // flag everything on an auto and a cross.
+        flag_array.slice_mut(s![.., .., 1.., ..]).fill(true);
+        let baselines = [(0, 1), (1, 1), (1, 2)];
+
+        let crosses_only = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions::default(),
+        )
+        .unwrap();
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions {
+                autos: Some(AutoCorrelations {
+                    baselines: &baselines,
+                    skip: false,
+                }),
+                ..AveragingOptions::default()
+            },
+        )
+        .unwrap();
+        // crosses are unaffected.
+        for b in [0, 2] {
+            assert_abs_diff_eq!(
+                averaged.jones.slice(s![.., .., b]),
+                crosses_only.jones.slice(s![.., .., b])
+            );
+        }
+        // the flagged cross is the mean of its samples, but the flagged auto is
+        // zero.
+        assert_ne!(averaged.jones[(0, 0, 2)], Jones::default());
+        assert_eq!(averaged.jones[(0, 0, 1)], Jones::default());
+        assert!(averaged.flags.slice(s![.., .., 1, ..]).iter().all(|&f| f));
+
+        // the parallel-hand pols of an unflagged auto are real.
+        flag_array.fill(false);
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions {
+                autos: Some(AutoCorrelations {
+                    baselines: &baselines,
+                    skip: false,
+                }),
+                ..AveragingOptions::default()
+            },
+        )
+        .unwrap();
+        let auto = averaged.jones[(0, 0, 1)];
+        assert_abs_diff_eq!(auto[0].im, 0.);
+        assert!(auto[1].im > 0.);
+        assert_abs_diff_eq!(auto[3].im, 0.);
+
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions {
+                autos: Some(AutoCorrelations {
+                    baselines: &baselines,
+                    skip: true,
+                }),
+                sample_counts: true,
+                ..AveragingOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(averaged.jones[(0, 0, 1)], Jones::default());
+        assert!(averaged.flags.slice(s![.., .., 1, ..]).iter().all(|&f| f));
+        assert!(averaged
+            .weights
+            .slice(s![.., .., 1, ..])
+            .iter()
+            .all(|&w| w <= 0.));
+        assert_eq!(averaged.num_unflagged.unwrap()[(0, 0, 1, 0)], 0);
+        assert!(!averaged.flags[(0, 0, 0, 0)]);
+
+        assert!(matches!(
+            average_visibilities_with_options(
+                vis_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                2,
+                2,
+                &AveragingOptions {
+                    autos: Some(AutoCorrelations {
+                        baselines: &baselines[..2],
+                        skip: true,
+                    }),
+                    ..AveragingOptions::default()
+                },
+            ),
+            Err(AveragingError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_average_redundant_baselines() {
+        let shape = (2, 2, 3, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let groups = [vec![0, 2], vec![1], vec![]];
+
+        let (jones, weights, flags) = average_redundant_baselines(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            &groups,
+        )
+        .unwrap();
+        assert_eq!(jones.dim(), (2, 2, 3));
+        assert_eq!(weights.dim(), (2, 2, 3, 4));
+
+        // a group of one baseline is unchanged, besides its flagged pols.
+        assert_abs_diff_eq!(jones.slice(s![1.., .., 1]), vis_array.slice(s![1.., .., 1]));
+        assert_abs_diff_eq!(
+            weights.slice(s![.., .., 1, ..]),
+            weight_array.slice(s![.., .., 1, ..])
+        );
+
+        // the first group is the weighted mean of its baselines.
+        let (t, c) = (1, 1);
+        for pol in 0..4 {
+            let w0 = weight_array[(t, c, 0, pol)];
+            let w2 = weight_array[(t, c, 2, pol)];
+            let expected =
+                (vis_array[(t, c, 0)][pol] * w0 + vis_array[(t, c, 2)][pol] * w2) / (w0 + w2);
+            assert_abs_diff_eq!(jones[(t, c, 0)][pol], expected, epsilon = 1e-6);
+            assert_abs_diff_eq!(weights[(t, c, 0, pol)], w0 + w2);
+            assert!(!flags[(t, c, 0, pol)]);
+        }
+
+        // empty groups are flagged.
+        assert!(flags.slice(s![.., .., 2, ..]).iter().all(|&f| f));
+        assert_eq!(jones[(0, 0, 2)], Jones::default());
+
+        assert!(matches!(
+            average_redundant_baselines(
+                vis_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                &[vec![0, 3]],
+            ),
+            Err(AveragingError::BadBaselineIndex {
+                group_idx: 0,
+                baseline_idx: 3,
+                num_baselines: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_averaging_stats() {
+        let shape = (2, 2, 2, 4);
+        let (vis_array, mut weight_array, mut flag_array) = synthesize_test_data(shape);
+        flag_array.fill(false);
+        // flag a whole timestep of one baseline, and negatively weight one
+        // sample of the other.
+        flag_array.slice_mut(s![1, .., 0, ..]).fill(true);
+        weight_array[(0, 1, 1, 3)] = -1.;
+
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions {
+                stats: true,
+                ..AveragingOptions::default()
+            },
+        )
+        .unwrap();
+        let stats = averaged.stats.unwrap();
+        assert_eq!(
+            stats,
+            AveragingStats::from_weights_and_flags(weight_array.view(), flag_array.view())
+        );
+        assert_abs_diff_eq!(stats.flagged_fraction, 9. / 32.);
+        assert_abs_diff_eq!(
+            stats.flagged_fraction_per_timestep,
+            array![1. / 16., 8. / 16.]
+        );
+        assert_abs_diff_eq!(stats.flagged_fraction_per_chan, array![4. / 16., 5. / 16.]);
+        assert_abs_diff_eq!(
+            stats.flagged_fraction_per_baseline,
+            array![8. / 16., 1. / 16.]
+        );
+
+        let averaged = average_visibilities_with_options(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+            &AveragingOptions::default(),
+        )
+        .unwrap();
+        assert!(averaged.stats.is_none());
+    }
+
+    #[test]
+    fn test_average_selection_chunks() {
+        let vis_sel = VisSelection {
+            timestep_range: 2..9,
+            coarse_chan_range: 0..1,
+            baseline_idxs: vec![0, 1, 2],
+        };
+        let fine_chans_per_coarse = 4;
+        let (num_timesteps, num_chans, num_baselines) = vis_sel.get_shape(fine_chans_per_coarse);
+        let (vis_array, weight_array, flag_array) =
+            synthesize_test_data((num_timesteps, num_chans, num_baselines, 4));
+        let (expected_jones, expected_weights, expected_flags) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+        )
+        .unwrap();
+
+        // read each chunk out of the full arrays.
+        let mut chunk_ranges = vec![];
+        let read = |chunk_sel: &VisSelection| -> Result<VisData344, AveragingError> {
+            chunk_ranges.push(chunk_sel.timestep_range.clone());
+            let offset = vis_sel.timestep_range.start;
+            let range =
+                chunk_sel.timestep_range.start - offset..chunk_sel.timestep_range.end - offset;
+            Ok((
+                vis_array.slice(s![range.clone(), .., ..]).to_owned(),
+                weight_array.slice(s![range.clone(), .., .., ..]).to_owned(),
+                flag_array.slice(s![range, .., .., ..]).to_owned(),
+            ))
+        };
+        // 3 timesteps per chunk are rounded up to 4.
+        let chunks: Vec<_> = average_selection_chunks(&vis_sel, 3, 2, 2, read)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(chunk_ranges, vec![2..6, 6..9]);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].0.timestep_range, 6..9);
+        assert_eq!(chunks[1].0.baseline_idxs, vis_sel.baseline_idxs);
+
+        let jones_views: Vec<_> = chunks.iter().map(|(_, (j, _, _))| j.view()).collect();
+        let weight_views: Vec<_> = chunks.iter().map(|(_, (_, w, _))| w.view()).collect();
+        let flag_views: Vec<_> = chunks.iter().map(|(_, (_, _, f))| f.view()).collect();
+        assert_abs_diff_eq!(
+            ndarray::concatenate(Axis(0), &jones_views).unwrap(),
+            expected_jones
+        );
+        assert_abs_diff_eq!(
+            ndarray::concatenate(Axis(0), &weight_views).unwrap(),
+            expected_weights
+        );
+        assert_eq!(
+            ndarray::concatenate(Axis(0), &flag_views).unwrap(),
+            expected_flags
+        );
+    }
+
+    #[test]
+    fn test_average_selection_chunks_zero_factor() {
+        let vis_sel = VisSelection {
+            timestep_range: 0..4,
+            coarse_chan_range: 0..1,
+            baseline_idxs: vec![0],
+        };
+        let read = |_: &VisSelection| -> Result<VisData344, AveragingError> {
+            unreachable!("nothing should be read with a zero averaging factor")
+        };
+        for (avg_time, avg_freq, expected_axis) in [(0, 1, "time"), (1, 0, "frequency")] {
+            assert!(matches!(
+                average_selection_chunks(&vis_sel, 2, avg_time, avg_freq, read),
+                Err(AveragingError::ZeroAveragingFactor { axis, .. }) if axis == expected_axis
+            ));
+        }
+    }
+
+    #[test]
+    fn test_average_visibilities_all_flagged_policy() {
+        let shape = (6, 2, 2, 4);
+        let (vis_array, weight_array, mut flag_array) = synthesize_test_data(shape);
+        flag_array.fill(false);
+        // flag the last two averaged timesteps of the first baseline.
+        flag_array.slice_mut(s![2.., .., 0, ..]).fill(true);
+        let average = |all_flagged| {
+            average_visibilities_with_options(
+                vis_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                2,
+                2,
+                &AveragingOptions {
+                    all_flagged,
+                    ..AveragingOptions::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let geometric = average(AllFlaggedPolicy::GeometricMean);
+        let (expected, _, _) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(geometric.jones, expected);
+        assert_ne!(geometric.jones[(1, 0, 0)], Jones::default());
+
+        let zero = average(AllFlaggedPolicy::Zero);
+        assert_eq!(zero.jones[(1, 0, 0)], Jones::default());
+        assert_eq!(zero.jones[(2, 0, 0)], Jones::default());
+        assert_abs_diff_eq!(zero.jones[(0, 0, 0)], expected[(0, 0, 0)]);
+
+        let nan = average(AllFlaggedPolicy::Nan);
+        assert!(nan.jones[(1, 0, 0)].iter().all(|j| j.is_nan()));
+        assert!(!nan.jones[(1, 0, 1)].iter().any(|j| j.is_nan()));
+
+        let propagated = average(AllFlaggedPolicy::PropagateLast);
+        assert_abs_diff_eq!(propagated.jones[(1, 0, 0)], expected[(0, 0, 0)]);
+        assert_abs_diff_eq!(propagated.jones[(2, 0, 0)], expected[(0, 0, 0)]);
+        assert_abs_diff_eq!(
+            propagated.jones.slice(s![.., .., 1]),
+            expected.slice(s![.., .., 1])
+        );
+        assert!(propagated.num_unflagged.is_none());
+
+        for averaged in [geometric, zero, nan, propagated] {
+            assert!(averaged.flags.slice(s![1.., .., 0, ..]).iter().all(|&f| f));
+            assert!(averaged
+                .weights
+                .slice(s![1.., .., 0, ..])
+                .iter()
+                .all(|&w| w <= 0.));
+        }
+    }
+
+    #[test]
+    fn test_average_visibilities_weight_mode() {
+        let shape = (2, 2, 2, 4);
+        let (vis_array, weight_array, flag_array) = synthesize_test_data(shape);
+        let average = |weight_mode| {
+            average_visibilities_with_options(
+                vis_array.view(),
+                weight_array.view(),
+                flag_array.view(),
+                2,
+                2,
+                &AveragingOptions {
+                    weight_mode,
+                    sample_counts: true,
+                    ..AveragingOptions::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let sum = average(WeightMode::SumOfWeights);
+        let (_, expected_weights, _) = average_visibilities(
+            vis_array.view(),
+            weight_array.view(),
+            flag_array.view(),
+            2,
+            2,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(sum.weights, expected_weights);
+        let counts = sum.num_unflagged.unwrap();
+        // the first baseline has two flagged samples.
+        assert_eq!(counts[(0, 0, 0, 0)], 3);
+
+        let mean = average(WeightMode::MeanWeight);
+        assert_abs_diff_eq!(
+            mean.weights,
+            Array4::from_shape_fn(counts.dim(), |i| expected_weights[i] / counts[i] as f32)
+        );
+        // visibilities are unchanged.
+        assert_abs_diff_eq!(mean.jones, sum.jones);
+
+        let nsamples = average(WeightMode::UnflaggedSampleCount);
+        assert_abs_diff_eq!(nsamples.weights, counts.mapv(|n| n as f32));
+    }
+
     // TODO: test unflagged with zero weight.
 }
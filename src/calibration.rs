@@ -0,0 +1,590 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Apply calibration solutions (and polarisation leakage) to visibilities, and
+//! interpolate solutions onto other time and frequency resolutions.
+
+use std::f64::consts::TAU;
+
+use ndarray::prelude::*;
+use thiserror::Error;
+
+use crate::{c64, Jones};
+
+#[derive(Error, Debug)]
+pub enum CalibrationError {
+    #[error("bad array shape supplied to argument {argument} of function {function}. expected {expected}, received {received}")]
+    BadArrayShape {
+        function: &'static str,
+        argument: &'static str,
+        expected: String,
+        received: String,
+    },
+
+    #[error("baseline {baseline} uses antenna {antenna}, but there are only solutions for {num_antennas} antennas")]
+    BadAntenna {
+        baseline: usize,
+        antenna: usize,
+        num_antennas: usize,
+    },
+}
+
+/// Apply direction-independent calibration solutions to visibilities
+/// in-place, i.e. `V'_pq = J_p V_pq J_q^H` for each visibility
+/// (`[timestep][channel][baseline]`), where `J_p` and `J_q` are the solutions
+/// (`[antenna][channel]`) of the antennas in `ant_pairs` (one pair per
+/// baseline). The arithmetic is done in double precision, and timesteps are
+/// calibrated in parallel.
+///
+/// Visibilities for which either solution is NaN can't be calibrated; they
+/// are set to zero and flagged by making their weights negative (or -0).
+///
+/// # Errors
+///
+/// Will return [`CalibrationError::BadArrayShape`] if the dimensions of
+/// `weight_array`, `solutions` or `ant_pairs` don't match `jones_array`, or
+/// [`CalibrationError::BadAntenna`] if `ant_pairs` refers to an antenna
+/// without solutions.
+pub fn apply_di_solutions(
+    jones_array: ArrayViewMut3<Jones<f32>>,
+    weight_array: ArrayViewMut3<f32>,
+    solutions: ArrayView2<Jones<f64>>,
+    ant_pairs: &[(usize, usize)],
+) -> Result<(), CalibrationError> {
+    apply_antenna_jones(
+        ("apply_di_solutions", "solutions"),
+        jones_array,
+        weight_array,
+        solutions,
+        ant_pairs,
+    )
+}
+
+/// The Jones matrix `[[1, d_XY], [d_YX, 1]]` of an antenna with polarisation
+/// leakage (d-terms) `[d_XY, d_YX]`, i.e. `d_XY` is the fraction of the Y
+/// signal which leaks into X.
+pub fn leakage_jones(dterms: [c64; 2]) -> Jones<f64> {
+    let one = c64::new(1.0, 0.0);
+    Jones::from([one, dterms[0], dterms[1], one])
+}
+
+/// Apply polarisation leakage to visibilities (`[timestep][channel][baseline]`)
+/// in-place, i.e. `V'_pq = D_p V_pq D_q^H`, where `D_p` is the
+/// [`leakage_jones`] of the d-terms (`[antenna][channel]`) of antenna `p`.
+/// This is useful for simulating visibilities.
+///
+/// Visibilities of antennas with NaN d-terms are set to zero and flagged, as
+/// in [`apply_di_solutions`].
+///
+/// # Errors
+///
+/// Will return the same errors as [`apply_di_solutions`].
+pub fn apply_dterms(
+    jones_array: ArrayViewMut3<Jones<f32>>,
+    weight_array: ArrayViewMut3<f32>,
+    dterms: ArrayView2<[c64; 2]>,
+    ant_pairs: &[(usize, usize)],
+) -> Result<(), CalibrationError> {
+    apply_antenna_jones(
+        ("apply_dterms", "dterms"),
+        jones_array,
+        weight_array,
+        dterms.mapv(leakage_jones).view(),
+        ant_pairs,
+    )
+}
+
+/// Remove polarisation leakage from visibilities in-place; the inverse of
+/// [`apply_dterms`], i.e. `V_pq = D_p^-1 V'_pq D_q^-H`.
+///
+/// Visibilities of antennas whose leakage can't be inverted (e.g. with
+/// `d_XY d_YX = 1`), or has NaN d-terms, are set to zero and flagged.
+///
+/// # Errors
+///
+/// Will return the same errors as [`apply_di_solutions`].
+pub fn remove_dterms(
+    jones_array: ArrayViewMut3<Jones<f32>>,
+    weight_array: ArrayViewMut3<f32>,
+    dterms: ArrayView2<[c64; 2]>,
+    ant_pairs: &[(usize, usize)],
+) -> Result<(), CalibrationError> {
+    let inverses = dterms.mapv(|d| leakage_jones(d).try_inv().unwrap_or_else(|_| Jones::nan()));
+    apply_antenna_jones(
+        ("remove_dterms", "dterms"),
+        jones_array,
+        weight_array,
+        inverses.view(),
+        ant_pairs,
+    )
+}
+
+/// Apply per-antenna Jones matrices (`[antenna][channel]`) to visibilities;
+/// see [`apply_di_solutions`]. `(function, argument)` name the public function
+/// and its argument with the Jones matrices for errors.
+#[allow(clippy::needless_pass_by_value)]
+fn apply_antenna_jones(
+    (function, sol_argument): (&'static str, &'static str),
+    mut jones_array: ArrayViewMut3<Jones<f32>>,
+    mut weight_array: ArrayViewMut3<f32>,
+    solutions: ArrayView2<Jones<f64>>,
+    ant_pairs: &[(usize, usize)],
+) -> Result<(), CalibrationError> {
+    let (_, num_chans, num_baselines) = jones_array.dim();
+    let (num_antennas, num_sol_chans) = solutions.dim();
+    for (argument, expected, received) in [
+        (
+            "weight_array",
+            format!("{:?}", jones_array.dim()),
+            format!("{:?}", weight_array.dim()),
+        ),
+        (
+            sol_argument,
+            format!("(_, {num_chans})"),
+            format!("(_, {num_sol_chans})"),
+        ),
+        (
+            "ant_pairs",
+            format!("{num_baselines}"),
+            format!("{}", ant_pairs.len()),
+        ),
+    ] {
+        if expected != received {
+            return Err(CalibrationError::BadArrayShape {
+                function,
+                argument,
+                expected,
+                received,
+            });
+        }
+    }
+    for (baseline, &(ant1, ant2)) in ant_pairs.iter().enumerate() {
+        if let Some(antenna) = [ant1, ant2].into_iter().find(|&a| a >= num_antennas) {
+            return Err(CalibrationError::BadAntenna {
+                baseline,
+                antenna,
+                num_antennas,
+            });
+        }
+    }
+
+    Zip::from(jones_array.outer_iter_mut())
+        .and(weight_array.outer_iter_mut())
+        .par_for_each(|mut jones_array, mut weight_array| {
+            for ((mut jones, mut weights), sols) in jones_array
+                .outer_iter_mut()
+                .zip(weight_array.outer_iter_mut())
+                .zip(solutions.axis_iter(Axis(1)))
+            {
+                for ((jones, weight), &(ant1, ant2)) in
+                    jones.iter_mut().zip(weights.iter_mut()).zip(ant_pairs)
+                {
+                    let (sol1, sol2) = (sols[ant1], sols[ant2]);
+                    if sol1.any_nan() || sol2.any_nan() {
+                        *jones = Jones::default();
+                        *weight = -weight.abs();
+                        continue;
+                    }
+                    *jones = Jones::from(sol1 * Jones::<f64>::from(*jones) * sol2.h());
+                }
+            }
+        });
+
+    Ok(())
+}
+
+/// How to interpolate [`Jones`] matrices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JonesInterpolation {
+    /// Use the nearest value.
+    Nearest,
+
+    /// Linearly interpolate the real and imaginary parts.
+    Linear,
+
+    /// Linearly interpolate the amplitudes and phases, taking the shorter way
+    /// around the circle for the phases. This avoids the drop in amplitude
+    /// that linear interpolation gives between values with different phases.
+    AmpPhase,
+}
+
+/// Interpolate calibration solutions (`[timestep][antenna][channel]`) at
+/// `times` and `freqs_hz` onto `new_times` and `new_freqs_hz`, first in
+/// frequency and then in time. The times can have any units (e.g. GPS
+/// seconds) but must be sorted, as must the frequencies.
+///
+/// Solutions with NaNs are flagged, and are ignored; the nearest unflagged
+/// solutions are used instead. Points outside the range of unflagged
+/// solutions get the value of the nearest one (there's no extrapolation), and
+/// if all of the solutions of an antenna are flagged, the results are NaN.
+///
+/// # Errors
+///
+/// Will return [`CalibrationError::BadArrayShape`] if the lengths of `times`
+/// or `freqs_hz` don't match `solutions`.
+#[allow(clippy::needless_pass_by_value)]
+pub fn interpolate_solutions(
+    solutions: ArrayView3<Jones<f64>>,
+    times: &[f64],
+    freqs_hz: &[f64],
+    new_times: &[f64],
+    new_freqs_hz: &[f64],
+    method: JonesInterpolation,
+) -> Result<Array3<Jones<f64>>, CalibrationError> {
+    let (num_times, num_antennas, num_freqs) = solutions.dim();
+    for (argument, expected, received) in [
+        ("times", num_times, times.len()),
+        ("freqs_hz", num_freqs, freqs_hz.len()),
+    ] {
+        if expected != received {
+            return Err(CalibrationError::BadArrayShape {
+                function: "interpolate_solutions",
+                argument,
+                expected: format!("{expected}"),
+                received: format!("{received}"),
+            });
+        }
+    }
+
+    let in_freq = Array3::from_shape_fn(
+        (num_times, num_antennas, new_freqs_hz.len()),
+        |(t, a, f)| {
+            interpolate(
+                freqs_hz,
+                solutions.slice(s![t, a, ..]),
+                new_freqs_hz[f],
+                method,
+            )
+        },
+    );
+    Ok(Array3::from_shape_fn(
+        (new_times.len(), num_antennas, new_freqs_hz.len()),
+        |(t, a, f)| interpolate(times, in_freq.slice(s![.., a, f]), new_times[t], method),
+    ))
+}
+
+/// Interpolate `values` at (sorted) `xs` to `x`, ignoring values with NaNs.
+fn interpolate(
+    xs: &[f64],
+    values: ArrayView1<Jones<f64>>,
+    x: f64,
+    method: JonesInterpolation,
+) -> Jones<f64> {
+    let valid = |i: &usize| !values[*i].any_nan();
+    let left = (0..xs.len()).rev().filter(valid).find(|&i| xs[i] <= x);
+    let right = (0..xs.len()).filter(valid).find(|&i| xs[i] >= x);
+    let (i, j) = match (left, right) {
+        (Some(i), Some(j)) => (i, j),
+        // Outside the range of valid values.
+        (Some(i), None) | (None, Some(i)) => return values[i],
+        (None, None) => return Jones::nan(),
+    };
+    if i == j {
+        return values[i];
+    }
+
+    let frac = (x - xs[i]) / (xs[j] - xs[i]);
+    let (a, b) = (values[i], values[j]);
+    match method {
+        JonesInterpolation::Nearest => {
+            if frac <= 0.5 {
+                a
+            } else {
+                b
+            }
+        }
+        JonesInterpolation::Linear => a + (b - a) * frac,
+        JonesInterpolation::AmpPhase => {
+            let mut out = a;
+            for (o, (a, b)) in out.iter_mut().zip(a.iter().zip(b.iter())) {
+                let (a_amp, a_phase) = a.to_polar();
+                let (b_amp, b_phase) = b.to_polar();
+                // The phase difference in the range [-π, π).
+                let d_phase = (b_phase - a_phase + TAU / 2.0).rem_euclid(TAU) - TAU / 2.0;
+                *o = c64::from_polar(a_amp + (b_amp - a_amp) * frac, a_phase + d_phase * frac);
+            }
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn test_vis() -> Array3<Jones<f32>> {
+        Array3::from_shape_fn((2, 3, 3), |(t, c, b)| {
+            Jones::from([
+                1.0 + t as f32,
+                0.5,
+                c as f32 * 0.1,
+                0.0,
+                -(b as f32) * 0.2,
+                0.3,
+                2.0,
+                -1.0,
+            ])
+        })
+    }
+
+    #[test]
+    fn test_apply_di_solutions() {
+        let ant_pairs = [(0, 1), (0, 2), (1, 2)];
+        let mut vis = test_vis();
+        let mut weights = Array3::from_elem(vis.dim(), 8.0);
+        let mut solutions = Array2::from_shape_fn((3, 3), |(a, c)| {
+            Jones::from([
+                c64::new(1.0 + a as f64, 0.1 * c as f64),
+                c64::new(0.0, 0.2),
+                c64::new(-0.1, 0.0),
+                c64::new(2.0, -(a as f64)),
+            ])
+        });
+        // No solutions for antenna 2 on the last channel.
+        solutions[(2, 2)] = Jones::nan();
+
+        apply_di_solutions(
+            vis.view_mut(),
+            weights.view_mut(),
+            solutions.view(),
+            &ant_pairs,
+        )
+        .unwrap();
+
+        let original = test_vis();
+        for ((t, c, b), &jones) in vis.indexed_iter() {
+            let (ant1, ant2) = ant_pairs[b];
+            if c == 2 && b > 0 {
+                assert_abs_diff_eq!(jones, Jones::default());
+                assert_abs_diff_eq!(weights[(t, c, b)], -8.0);
+                continue;
+            }
+            let expected = solutions[(ant1, c)]
+                * Jones::<f64>::from(original[(t, c, b)])
+                * solutions[(ant2, c)].h();
+            assert_abs_diff_eq!(jones, Jones::from(expected), epsilon = 1e-5);
+            assert_abs_diff_eq!(weights[(t, c, b)], 8.0);
+        }
+    }
+
+    #[test]
+    fn test_dterms() {
+        let ant_pairs = [(0, 1), (0, 2), (1, 2)];
+        let mut vis = test_vis();
+        let mut weights = Array3::from_elem(vis.dim(), 1.0);
+        let mut dterms = Array2::from_shape_fn((3, 3), |(a, c)| {
+            [
+                c64::new(0.01 * a as f64, -0.02),
+                c64::new(-0.03, 0.005 * c as f64),
+            ]
+        });
+        // Antenna 1's leakage can't be removed on the first channel.
+        dterms[(1, 0)] = [c64::new(2.0, 0.0), c64::new(0.5, 0.0)];
+
+        apply_dterms(
+            vis.view_mut(),
+            weights.view_mut(),
+            dterms.view(),
+            &ant_pairs,
+        )
+        .unwrap();
+        let original = test_vis();
+        let (ant1, ant2) = ant_pairs[2];
+        let expected = leakage_jones(dterms[(ant1, 1)])
+            * Jones::<f64>::from(original[(1, 1, 2)])
+            * leakage_jones(dterms[(ant2, 1)]).h();
+        assert_abs_diff_eq!(vis[(1, 1, 2)], Jones::from(expected), epsilon = 1e-5);
+
+        remove_dterms(
+            vis.view_mut(),
+            weights.view_mut(),
+            dterms.view(),
+            &ant_pairs,
+        )
+        .unwrap();
+        for ((t, c, b), &jones) in vis.indexed_iter() {
+            let (ant1, ant2) = ant_pairs[b];
+            if c == 0 && (ant1 == 1 || ant2 == 1) {
+                assert_abs_diff_eq!(jones, Jones::default());
+                assert_abs_diff_eq!(weights[(t, c, b)], -1.0);
+            } else {
+                assert_abs_diff_eq!(jones, original[(t, c, b)], epsilon = 1e-5);
+                assert_abs_diff_eq!(weights[(t, c, b)], 1.0);
+            }
+        }
+
+        let result = remove_dterms(
+            vis.view_mut(),
+            weights.view_mut(),
+            dterms.slice(s![.., ..1]),
+            &ant_pairs,
+        );
+        assert!(matches!(
+            result,
+            Err(CalibrationError::BadArrayShape {
+                function: "remove_dterms",
+                argument: "dterms",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_apply_di_solutions_bad_inputs() {
+        let mut vis = test_vis();
+        let mut weights = Array3::from_elem(vis.dim(), 1.0);
+        let solutions = Array2::from_elem((3, 3), Jones::identity());
+
+        let result = apply_di_solutions(
+            vis.view_mut(),
+            weights.view_mut(),
+            solutions.slice(s![.., ..2]),
+            &[(0, 1), (0, 2), (1, 2)],
+        );
+        assert!(matches!(
+            result,
+            Err(CalibrationError::BadArrayShape {
+                argument: "solutions",
+                ..
+            })
+        ));
+
+        let result = apply_di_solutions(
+            vis.view_mut(),
+            weights.view_mut(),
+            solutions.view(),
+            &[(0, 1), (0, 2)],
+        );
+        assert!(matches!(
+            result,
+            Err(CalibrationError::BadArrayShape {
+                argument: "ant_pairs",
+                ..
+            })
+        ));
+
+        let result = apply_di_solutions(
+            vis.view_mut(),
+            weights.view_mut(),
+            solutions.view(),
+            &[(0, 1), (0, 3), (1, 2)],
+        );
+        assert!(matches!(
+            result,
+            Err(CalibrationError::BadAntenna {
+                baseline: 1,
+                antenna: 3,
+                num_antennas: 3
+            })
+        ));
+        // Nothing was changed.
+        assert_eq!(vis, test_vis());
+    }
+
+    #[test]
+    fn test_interpolate_solutions() {
+        let times = [0.0, 10.0, 20.0];
+        let freqs = [100e6, 200e6];
+        // [timestep][antenna][channel]
+        let mut solutions = Array3::from_shape_fn((3, 2, 2), |(t, a, f)| {
+            Jones::identity() * c64::new(1.0 + t as f64 + 10.0 * a as f64, f as f64)
+        });
+        // Antenna 1 is flagged at the middle timestep.
+        solutions.slice_mut(s![1, 1, ..]).fill(Jones::nan());
+
+        let new_times = [-5.0, 5.0, 12.0, 30.0];
+        let new_freqs = [150e6];
+        let result = interpolate_solutions(
+            solutions.view(),
+            &times,
+            &freqs,
+            &new_times,
+            &new_freqs,
+            JonesInterpolation::Linear,
+        )
+        .unwrap();
+        assert_eq!(result.dim(), (4, 2, 1));
+        // Clamped at the first timestep, and halfway between the channels.
+        assert_abs_diff_eq!(result[(0, 0, 0)][0], c64::new(1.0, 0.5));
+        assert_abs_diff_eq!(result[(1, 0, 0)][0], c64::new(1.5, 0.5));
+        assert_abs_diff_eq!(result[(2, 0, 0)][0], c64::new(2.2, 0.5), epsilon = 1e-12);
+        assert_abs_diff_eq!(result[(3, 0, 0)][0], c64::new(3.0, 0.5));
+        // The flagged timestep is skipped.
+        assert_abs_diff_eq!(result[(1, 1, 0)][0], c64::new(11.5, 0.5));
+        assert_abs_diff_eq!(result[(1, 1, 0)][1], c64::default());
+
+        let result = interpolate_solutions(
+            solutions.view(),
+            &times,
+            &freqs,
+            &new_times,
+            &new_freqs,
+            JonesInterpolation::Nearest,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(result[(2, 0, 0)][0], c64::new(2.0, 0.0));
+        assert_abs_diff_eq!(result[(2, 1, 0)][0], c64::new(13.0, 0.0));
+
+        // Everything flagged.
+        solutions.slice_mut(s![.., 1, ..]).fill(Jones::nan());
+        let result = interpolate_solutions(
+            solutions.view(),
+            &times,
+            &freqs,
+            &new_times,
+            &new_freqs,
+            JonesInterpolation::Nearest,
+        )
+        .unwrap();
+        assert!(result[(0, 1, 0)].any_nan());
+
+        let result = interpolate_solutions(
+            solutions.view(),
+            &times[..2],
+            &freqs,
+            &[],
+            &[],
+            JonesInterpolation::Linear,
+        );
+        assert!(matches!(
+            result,
+            Err(CalibrationError::BadArrayShape {
+                argument: "times",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_interpolate_amp_phase() {
+        let solutions = Array3::from_shape_fn((2, 1, 1), |(t, _, _)| {
+            let phase = if t == 0 { 170_f64 } else { -170_f64 };
+            Jones::identity() * c64::from_polar(1.0, phase.to_radians())
+        });
+        let interpolate = |method| {
+            interpolate_solutions(
+                solutions.view(),
+                &[0.0, 1.0],
+                &[150e6],
+                &[0.5],
+                &[150e6],
+                method,
+            )
+            .unwrap()[(0, 0, 0)][0]
+        };
+        // Linear interpolation loses amplitude...
+        assert_abs_diff_eq!(
+            interpolate(JonesInterpolation::Linear).norm(),
+            10_f64.to_radians().cos(),
+            epsilon = 1e-12
+        );
+        // ... but amplitude and phase interpolation goes the short way around.
+        assert_abs_diff_eq!(
+            interpolate(JonesInterpolation::AmpPhase),
+            c64::new(-1.0, 0.0),
+            epsilon = 1e-12
+        );
+    }
+}
@@ -17,6 +17,8 @@ pub const DS2R: f64 = erfa::constants::ERFA_DS2R;
 pub const DH2R: f64 = 15.0 / 180.0 * PI;
 /// Ratio of a solar day to a sidereal day (24/23.9344696 = 1.002737909).
 pub const SOLAR2SIDEREAL: f64 = 24.0 / 23.9344696;
+/// The angular velocity of the Earth's rotation \[radians / second\].
+pub const EARTH_ROTATION_RAD_S: f64 = 2.0 * PI * SOLAR2SIDEREAL / DAYSEC;
 
 /// MWA latitude \[radians\]
 pub const MWA_LAT_RAD: f64 = -0.4660608448386394;
@@ -29,6 +31,18 @@ pub const MWA_LONG_DEG: f64 = MWA_LONG_RAD * 180.0 / PI;
 /// MWA height (a.k.a. altitude) \[metres\]
 pub const MWA_HEIGHT_M: f64 = 377.827;
 
+/// The separation of adjacent dipoles in an MWA tile \[metres\]
+pub const MWA_DIPOLE_SEPARATION_M: f64 = 1.1;
+/// The number of dipoles in an MWA tile. They are numbered from the
+/// north-west corner, in rows of 4 from west to east.
+pub const MWA_NUM_DIPOLES: usize = 16;
+/// The delay added by one step of an MWA analogue beamformer \[seconds\]
+pub const MWA_DELAY_STEP_S: f64 = 435e-12;
+/// The largest delay step of an MWA analogue beamformer.
+pub const MWA_MAX_DELAY: u32 = 31;
+/// The delay given to dipoles that are flagged as dead in MWA metadata.
+pub const MWA_DEAD_DIPOLE_DELAY: u32 = 32;
+
 /// The weight given to time when calculating a weight factor. When combined
 /// with [`FREQ_WEIGHT_FACTOR`], a visibility weight can be calculated.
 pub const TIME_WEIGHT_FACTOR: f64 = 1.0;
@@ -36,6 +50,15 @@ pub const TIME_WEIGHT_FACTOR: f64 = 1.0;
 /// combined with [`TIME_WEIGHT_FACTOR`], a visibility weight can be calculated.
 pub const FREQ_WEIGHT_FACTOR: f64 = 10000.0;
 
+/// The bandwidth of an MWA receiver coarse channel \[Hz\]
+pub const MWA_COARSE_CHAN_WIDTH_HZ: f64 = 1_280_000.0;
+/// The number of coarse channels that the MWA receivers channelise the band into.
+/// Receiver coarse channel numbers are in the range `0..MWA_NUM_REC_COARSE_CHANS`.
+pub const MWA_NUM_REC_COARSE_CHANS: usize = 256;
+/// Receiver coarse channel numbers greater than this are presented in reverse
+/// order by the legacy MWA correlator.
+pub const MWA_COARSE_CHAN_FLIP: usize = 128;
+
 // cotter's constants. Useful for being more precise when converting geocentric
 // XYZ to geodetic XYZ!
 /// cotter's MWA latitude on Earth in radians. Use [`MWA_LAT_RAD`] unless you know
@@ -3,9 +3,11 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use hifitime::{Duration, Epoch, TimeSeries};
-use ndarray::Array2;
+use ndarray::{Array2, ArrayBase, DataMut, Dimension};
 
-use crate::{LatLngHeight, RADec, XyzGeocentric, XyzGeodetic, ENH};
+use crate::{
+    precession::precess_time, AzEl, LatLngHeight, RADec, XyzGeocentric, XyzGeodetic, ENH, UVW,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "mwalib")] {
@@ -14,6 +16,10 @@ cfg_if::cfg_if! {
         use hifitime::Unit::Millisecond;
         use itertools::izip;
         use ndarray::array;
+        use crate::{
+            averaging::{freq_factor_from_resolution, time_factor_from_resolution, AveragingError},
+            VisSelection,
+        };
     }
 }
 
@@ -116,22 +122,112 @@ impl ObsContext {
     }
 }
 
+/// A field (phase centre) of a multi-field observation, e.g. one repointing of
+/// a drift scan.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldContext {
+    /// The name of the field.
+    pub name: String,
+
+    /// The phase centre of the field.
+    pub phase_centre: RADec,
+
+    /// The start of the first timestep phased to this field. All timesteps
+    /// from this until the start of the next field are phased to this field.
+    pub start_timestamp: Epoch,
+}
+
+impl FieldContext {
+    /// Get the index of the field that the timestep with (centroid)
+    /// `timestamp` is phased to, given fields sorted by start time. Timesteps
+    /// before the start of the first field are phased to the first field.
+    pub fn index_at(fields: &[FieldContext], timestamp: Epoch) -> usize {
+        fields
+            .iter()
+            .rposition(|field| field.start_timestamp <= timestamp)
+            .unwrap_or(0)
+    }
+}
+
 /// A container for metadata about how a visibility file was created.
+///
+/// Every writer records this the same way, so that a file can be traced back
+/// to what made it: the `SOFTWARE` key is [`History::software`], the measurement
+/// set `HISTORY` table has a row with the same fields as the uvfits `HISTORY`
+/// cards ([`History::as_history_cards`]), and cotter-style `COMMENT`s are kept
+/// ([`History::as_comments`]).
+///
+/// Fields may be added to this, so outside of this crate, make one with
+/// [`History::new`] (or [`Default`]).
 #[derive(Debug, Clone, Default)]
+#[non_exhaustive]
 pub struct History<'a> {
-    /// The application (and version) used to create the file
+    /// The application used to create the file
     pub application: Option<&'a str>,
+    /// The version of the application
+    pub version: Option<&'a str>,
     /// The command line arguments used to create the file
     pub cmd_line: Option<&'a str>,
     /// What the application did (human readable)
     pub message: Option<&'a str>,
+    /// When the file was created. If this is `None`, the time that the file is
+    /// written is used.
+    pub time: Option<Epoch>,
 }
 
 impl<'a> History<'a> {
+    /// Make a [`History`] with no `version` or `time`; set these with
+    /// [`History::with_version`] and [`History::with_time`].
+    pub const fn new(
+        application: Option<&'a str>,
+        cmd_line: Option<&'a str>,
+        message: Option<&'a str>,
+    ) -> Self {
+        Self {
+            application,
+            version: None,
+            cmd_line,
+            message,
+            time: None,
+        }
+    }
+
+    /// Set the version of the application.
+    pub const fn with_version(mut self, version: &'a str) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Set when the file was created, rather than when it's written.
+    pub const fn with_time(mut self, time: Epoch) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// The application and version that created the file, or this crate's name
+    /// and version if there's no application.
+    pub fn software(&self) -> String {
+        match (self.application, self.version) {
+            (Some(app), Some(version)) => format!("{app} {version}"),
+            (Some(app), None) => app.to_string(),
+            (None, _) => format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        }
+    }
+
+    /// When the file was created; now if `time` is `None`.
+    pub fn time_or_now(&self) -> Epoch {
+        self.time.unwrap_or_else(|| {
+            let unix_time = std::time::SystemTime::now()
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            Epoch::from_unix_seconds(unix_time.as_secs_f64())
+        })
+    }
+
     /// Format history as a series of uvfits COMMENTs
     pub fn as_comments(&self) -> Vec<String> {
         [
-            self.application.map(|s| format!("Created by {s}")),
+            Some(format!("Created by {}", self.software())),
             self.cmd_line.map(|s| format!("CmdLine: {s}")),
             self.message.map(|s| format!("Msg: {s}")),
         ]
@@ -139,6 +235,21 @@ impl<'a> History<'a> {
         .flatten()
         .collect::<Vec<_>>()
     }
+
+    /// Format history as a series of uvfits HISTORY cards, with the fields of
+    /// a measurement set `HISTORY` table row. `time` should come from
+    /// [`History::time_or_now`].
+    pub fn as_history_cards(&self, time: Epoch) -> Vec<String> {
+        [
+            Some(format!("APPLICATION: {}", self.software())),
+            self.cmd_line.map(|s| format!("CLI_COMMAND: {s}")),
+            self.message.map(|s| format!("MESSAGE: {s}")),
+            Some(format!("TIME: {time}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+    }
 }
 
 /// An extension of [`ObsContext`] that for MWA-specific metadata that is not
@@ -170,6 +281,10 @@ pub struct MwaObsContext {
 
     /// Tile pointing delays
     pub delays: Vec<u32>,
+
+    /// The azimuth and elevation that the tiles point at, which is fixed by
+    /// the delays for the whole observation.
+    pub pointing_azel: AzEl,
 }
 
 // TODO: impl Default for MwaObsContext {}
@@ -193,6 +308,7 @@ impl MwaObsContext {
             has_calibrator: meta_ctx.calibrator,
             mode: meta_ctx.mode.to_string(),
             delays: meta_ctx.delays.clone(),
+            pointing_azel: AzEl::from_radians(meta_ctx.az_rad, meta_ctx.alt_rad),
         };
 
         #[allow(unused_mut)]
@@ -219,6 +335,82 @@ impl MwaObsContext {
     }
 }
 
+/// The normalisation of visibility weights.
+///
+/// Different tools normalise weights differently, and mixing normalisations
+/// silently breaks imaging weights and noise estimates downstream. Averaging and
+/// the writers sum weights, which preserves [`WeightScaling::Cotter`] and
+/// [`WeightScaling::Physical`] weights at the averaged resolution, but
+/// [`WeightScaling::Unit`] weights need to be corrected with
+/// [`WeightScaling::averaging_correction`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WeightScaling {
+    /// A sample's weight is its resolution relative to the legacy MWA
+    /// correlator's (1s / 10kHz), as written by cotter. See
+    /// [`VisContext::weight_factor`].
+    #[default]
+    Cotter,
+
+    /// A fully unflagged visibility has a weight of 1, regardless of its
+    /// resolution.
+    Unit,
+
+    /// A sample's weight is its time-bandwidth product \[s Hz\], i.e. the
+    /// number of independent samples that went into it.
+    Physical,
+}
+
+impl WeightScaling {
+    /// The weight of a fully unflagged visibility with the given integration
+    /// time and frequency resolution \[Hz\].
+    pub fn weight(self, int_time: Duration, freq_resolution_hz: f64) -> f64 {
+        match self {
+            Self::Cotter => {
+                int_time.to_seconds() / crate::constants::TIME_WEIGHT_FACTOR * freq_resolution_hz
+                    / crate::constants::FREQ_WEIGHT_FACTOR
+            }
+            Self::Unit => 1.0,
+            Self::Physical => int_time.to_seconds() * freq_resolution_hz,
+        }
+    }
+
+    /// The factor to multiply weights of this scaling by to convert them to the
+    /// `to` scaling, for visibilities with the given integration time and
+    /// frequency resolution \[Hz\].
+    pub fn conversion_factor(self, to: Self, int_time: Duration, freq_resolution_hz: f64) -> f64 {
+        to.weight(int_time, freq_resolution_hz) / self.weight(int_time, freq_resolution_hz)
+    }
+
+    /// Convert weights of this scaling to the `to` scaling in place. The sign
+    /// of each weight (which may encode a flag) is preserved.
+    pub fn rescale_weights<S, D>(
+        self,
+        to: Self,
+        weights: &mut ArrayBase<S, D>,
+        int_time: Duration,
+        freq_resolution_hz: f64,
+    ) where
+        S: DataMut<Elem = f32>,
+        D: Dimension,
+    {
+        if self == to {
+            return;
+        }
+        let factor = self.conversion_factor(to, int_time, freq_resolution_hz) as f32;
+        weights.mapv_inplace(|w| w * factor);
+    }
+
+    /// The factor to multiply summed weights by after averaging `avg_time`
+    /// timesteps and `avg_freq` channels together, such that they keep this
+    /// scaling at the averaged resolution.
+    pub fn averaging_correction(self, avg_time: usize, avg_freq: usize) -> f64 {
+        match self {
+            Self::Cotter | Self::Physical => 1.0,
+            Self::Unit => 1.0 / (avg_time * avg_freq) as f64,
+        }
+    }
+}
+
 /// A lightweight container for correlator visibility metadata used in Marlu operations.
 ///
 /// This is intended to describe an accompanying visibility and weight ndarray.
@@ -256,6 +448,9 @@ pub struct VisContext {
 // TODO: impl Default for VisContext {}
 
 impl VisContext {
+    /// Like [`VisContext::from_mwalib_with_resolution`], but averaged by
+    /// integer factors rather than to target resolutions. The factors aren't
+    /// checked; prefer [`VisContext::from_mwalib_with_resolution`].
     #[cfg(feature = "mwalib")]
     pub fn from_mwalib(
         corr_ctx: &CorrelatorContext,
@@ -265,6 +460,38 @@ impl VisContext {
         avg_time: usize,
         avg_freq: usize,
     ) -> Self {
+        let vis_sel = VisSelection {
+            timestep_range: timestep_range.clone(),
+            coarse_chan_range: coarse_chan_range.clone(),
+            baseline_idxs: baseline_idxs.to_vec(),
+        };
+        let vis_ctx = Self::from_mwalib_with_resolution(corr_ctx, &vis_sel, None, None)
+            .expect("the native resolutions are always valid");
+        VisContext {
+            avg_time,
+            avg_freq,
+            ..vis_ctx
+        }
+    }
+
+    /// Describe the visibilities of `vis_sel`, averaged to target time and
+    /// frequency resolutions. `None` leaves that axis unaveraged.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`AveragingError::NonIntegerResolution`] if a resolution is
+    /// not a positive integer multiple of the correlator's integration time or
+    /// fine channel width.
+    #[cfg(feature = "mwalib")]
+    pub fn from_mwalib_with_resolution(
+        corr_ctx: &CorrelatorContext,
+        vis_sel: &VisSelection,
+        time_resolution: Option<Duration>,
+        freq_resolution_hz: Option<f64>,
+    ) -> Result<Self, AveragingError> {
+        let timestep_range = &vis_sel.timestep_range;
+        let coarse_chan_range = &vis_sel.coarse_chan_range;
+
         // Time axis
         let num_sel_timesteps = timestep_range.len();
 
@@ -274,6 +501,10 @@ impl VisContext {
 
         let int_time =
             Duration::from_f64(corr_ctx.metafits_context.corr_int_time_ms as _, Millisecond);
+        let avg_time = match time_resolution {
+            Some(res) => time_factor_from_resolution(int_time, res)?,
+            None => 1,
+        };
 
         // Frequency axis
         let num_sel_coarse_chans = coarse_chan_range.len();
@@ -281,10 +512,15 @@ impl VisContext {
         let num_sel_chans = fine_chans_per_coarse * num_sel_coarse_chans;
         let start_freq_hz = corr_ctx.metafits_context.metafits_fine_chan_freqs_hz
             [coarse_chan_range.start * fine_chans_per_coarse];
-        let freq_resolution_hz = corr_ctx.metafits_context.corr_fine_chan_width_hz as f64;
+        let freq_resolution_hz_native = corr_ctx.metafits_context.corr_fine_chan_width_hz as f64;
+        let avg_freq = match freq_resolution_hz {
+            Some(res) => freq_factor_from_resolution(freq_resolution_hz_native, res)?,
+            None => 1,
+        };
 
         // baseline axis
-        let sel_baselines = baseline_idxs
+        let sel_baselines = vis_sel
+            .baseline_idxs
             .iter()
             .map(|&idx| {
                 let baseline = &corr_ctx.metafits_context.baselines[idx];
@@ -294,18 +530,18 @@ impl VisContext {
 
         let num_vis_pols = corr_ctx.metafits_context.num_visibility_pols;
 
-        VisContext {
+        Ok(VisContext {
             num_sel_timesteps,
             start_timestamp,
             int_time,
             num_sel_chans,
             start_freq_hz,
-            freq_resolution_hz,
+            freq_resolution_hz: freq_resolution_hz_native,
             sel_baselines,
             avg_time,
             avg_freq,
             num_vis_pols,
-        }
+        })
     }
 
     /// The expected dimensions of the visibility and weight ndarray selection.
@@ -354,6 +590,43 @@ impl VisContext {
         TimeSeries::exclusive(start_timestamp, end_timestamp, int_time)
     }
 
+    /// The [`UVW`]s \[metres\] of the selected baselines (`[timestep][baseline]`)
+    /// at the centroid of each timestep, phased to `phase_centre`.
+    ///
+    /// Like the visibility writers, the phase centre is precessed to the epoch
+    /// of each timestep (with `dut1`, UT1 - UTC), and the `tile_xyzs` are
+    /// rotated to match. If `averaging` is true, there is one row of UVWs per
+    /// post-averaging timestep.
+    pub fn uvws(
+        &self,
+        tile_xyzs: &[XyzGeodetic],
+        phase_centre: RADec,
+        array_pos: LatLngHeight,
+        dut1: Duration,
+        averaging: bool,
+    ) -> Array2<UVW> {
+        let timestamps: Vec<Epoch> = self.timeseries(averaging, true).collect();
+        let mut uvws =
+            Array2::from_elem((timestamps.len(), self.sel_baselines.len()), UVW::default());
+        for (mut uvws, &timestamp) in uvws.outer_iter_mut().zip(timestamps.iter()) {
+            let prec_info = precess_time(
+                array_pos.longitude_rad,
+                array_pos.latitude_rad,
+                phase_centre,
+                timestamp,
+                dut1,
+            );
+            let precessed_xyzs = prec_info.precess_xyz(tile_xyzs);
+            for (uvw, &(ant1, ant2)) in uvws.iter_mut().zip(self.sel_baselines.iter()) {
+                *uvw = UVW::from_xyz(
+                    precessed_xyzs[ant1] - precessed_xyzs[ant2],
+                    prec_info.hadec_j2000,
+                );
+            }
+        }
+        uvws
+    }
+
     /// The number of channels in the post-averaging frequency dimension
     pub fn num_avg_chans(&self) -> usize {
         (self.num_sel_chans as f64 / self.avg_freq as f64).ceil() as usize
@@ -390,18 +663,79 @@ impl VisContext {
     /// value is a multiple of the frequency resolution (relative to 10kHz), and
     /// the time averaging factor (relative to 1s).
     pub fn weight_factor(&self) -> f64 {
-        self.int_time.to_seconds() / crate::constants::TIME_WEIGHT_FACTOR * self.freq_resolution_hz
-            / crate::constants::FREQ_WEIGHT_FACTOR
+        WeightScaling::Cotter.weight(self.int_time, self.freq_resolution_hz)
+    }
+
+    /// The weight of a fully unflagged pre-averaging visibility, under the given
+    /// [`WeightScaling`].
+    pub fn scaled_weight(&self, weight_scaling: WeightScaling) -> f64 {
+        weight_scaling.weight(self.int_time, self.freq_resolution_hz)
+    }
+
+    /// The weight of a fully unflagged post-averaging visibility, under the
+    /// given [`WeightScaling`].
+    pub fn avg_scaled_weight(&self, weight_scaling: WeightScaling) -> f64 {
+        weight_scaling.weight(self.avg_int_time(), self.avg_freq_resolution_hz())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use approx::assert_abs_diff_eq;
     use hifitime::Unit;
 
     use crate::constants::VEL_C;
 
     use super::*;
+
+    #[test]
+    fn history_formatting() {
+        let time = Epoch::from_gpst_seconds(1090008640.);
+        let history = History::new(Some("Birli"), Some("birli -u out.uvfits"), None)
+            .with_version("0.9.0")
+            .with_time(time);
+        assert_eq!(history.software(), "Birli 0.9.0");
+        assert_eq!(history.time_or_now(), time);
+        assert_eq!(
+            history.as_comments(),
+            ["Created by Birli 0.9.0", "CmdLine: birli -u out.uvfits"]
+        );
+        assert_eq!(
+            history.as_history_cards(time),
+            [
+                "APPLICATION: Birli 0.9.0".to_string(),
+                "CLI_COMMAND: birli -u out.uvfits".to_string(),
+                format!("TIME: {time}"),
+            ]
+        );
+
+        let default_software = format!("marlu v{}", env!("CARGO_PKG_VERSION"));
+        assert_eq!(History::default().software(), default_software);
+        assert_eq!(
+            History::default().as_comments(),
+            [format!("Created by {default_software}")]
+        );
+    }
+
+    #[test]
+    fn field_index_at() {
+        let start = Epoch::from_gpst_seconds(1090008640.);
+        let fields: Vec<_> = (0..3)
+            .map(|i| FieldContext {
+                name: format!("field{i}"),
+                phase_centre: RADec::from_degrees(i as f64, -27.),
+                start_timestamp: start + Duration::from_f64(10. * i as f64, Unit::Second),
+            })
+            .collect();
+        let at =
+            |s: f64| FieldContext::index_at(&fields, start + Duration::from_f64(s, Unit::Second));
+        assert_eq!(at(-1.), 0);
+        assert_eq!(at(0.), 0);
+        assert_eq!(at(9.5), 0);
+        assert_eq!(at(10.), 1);
+        assert_eq!(at(25.), 2);
+    }
+
     #[test]
     // TODO: these collects are needed because of https://github.com/nyx-space/hifitime/issues/131
     #[allow(clippy::needless_collect)]
@@ -448,4 +782,157 @@ mod tests {
         let times: Vec<_> = vis_ctx.timeseries(true, true).collect();
         assert_eq!(times.len(), 1);
     }
+
+    #[test]
+    #[cfg(feature = "mwalib")]
+    fn vis_ctx_from_mwalib_with_resolution() {
+        let corr_ctx = CorrelatorContext::new(
+            "tests/data/1196175296_mwa_ord/1196175296.metafits",
+            &[
+                "tests/data/1196175296_mwa_ord/1196175296_20171201145440_gpubox01_00.fits",
+                "tests/data/1196175296_mwa_ord/1196175296_20171201145440_gpubox02_00.fits",
+            ],
+        )
+        .unwrap();
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let native = VisContext::from_mwalib(
+            &corr_ctx,
+            &vis_sel.timestep_range,
+            &vis_sel.coarse_chan_range,
+            &vis_sel.baseline_idxs,
+            1,
+            1,
+        );
+
+        let vis_ctx = VisContext::from_mwalib_with_resolution(
+            &corr_ctx,
+            &vis_sel,
+            Some(native.int_time * 2_i64),
+            Some(native.freq_resolution_hz * 4.),
+        )
+        .unwrap();
+        assert_eq!((vis_ctx.avg_time, vis_ctx.avg_freq), (2, 4));
+        assert_eq!(vis_ctx.avg_int_time(), native.int_time * 2_i64);
+        assert_eq!(vis_ctx.sel_dims(), native.sel_dims());
+
+        let vis_ctx =
+            VisContext::from_mwalib_with_resolution(&corr_ctx, &vis_sel, None, None).unwrap();
+        assert_eq!((vis_ctx.avg_time, vis_ctx.avg_freq), (1, 1));
+
+        assert!(matches!(
+            VisContext::from_mwalib_with_resolution(
+                &corr_ctx,
+                &vis_sel,
+                None,
+                Some(native.freq_resolution_hz * 1.5),
+            ),
+            Err(AveragingError::NonIntegerResolution { .. })
+        ));
+    }
+
+    #[test]
+    fn test_vis_ctx_uvws() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 4,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(2., Unit::Second),
+            num_sel_chans: 1,
+            start_freq_hz: 128_000_000.,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 2,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+        let tile_xyzs = [
+            XyzGeodetic {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            XyzGeodetic {
+                x: 100.0,
+                y: -200.0,
+                z: 50.0,
+            },
+            XyzGeodetic {
+                x: -30.0,
+                y: 10.0,
+                z: 500.0,
+            },
+        ];
+        let phase_centre = RADec::from_degrees(0.0, -27.0);
+        let array_pos = LatLngHeight::mwa();
+        let dut1 = Duration::from_f64(0.0, Unit::Second);
+
+        let uvws = vis_ctx.uvws(&tile_xyzs, phase_centre, array_pos, dut1, false);
+        assert_eq!(uvws.dim(), (4, 3));
+        let avg_uvws = vis_ctx.uvws(&tile_xyzs, phase_centre, array_pos, dut1, true);
+        assert_eq!(avg_uvws.dim(), (2, 3));
+
+        // The second averaged timestep is centred on the start of the fourth
+        // pre-averaging timestep.
+        let timestamp = vis_ctx.start_timestamp + 3.0 * vis_ctx.int_time;
+        let prec_info = precess_time(
+            array_pos.longitude_rad,
+            array_pos.latitude_rad,
+            phase_centre,
+            timestamp,
+            dut1,
+        );
+        let xyzs = prec_info.precess_xyz(&tile_xyzs);
+        let expected = UVW::from_xyz(xyzs[1] - xyzs[2], prec_info.hadec_j2000);
+        assert_abs_diff_eq!(avg_uvws[(1, 2)], expected, epsilon = 1e-10);
+
+        // Baseline UVWs are additive, and rotate with the Earth.
+        for uvws in uvws.outer_iter() {
+            assert_abs_diff_eq!(uvws[1] - uvws[0], uvws[2], epsilon = 1e-10);
+        }
+        assert!(uvws[(0, 0)] != uvws[(3, 0)]);
+    }
+
+    #[test]
+    fn test_weight_scaling() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 4,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_f64(2., Unit::Second),
+            num_sel_chans: 4,
+            start_freq_hz: 128_000_000.,
+            freq_resolution_hz: 40_000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 2,
+            avg_freq: 4,
+            num_vis_pols: 4,
+        };
+        assert_abs_diff_eq!(vis_ctx.scaled_weight(WeightScaling::Cotter), 8.);
+        assert_abs_diff_eq!(vis_ctx.weight_factor(), 8.);
+        assert_abs_diff_eq!(vis_ctx.scaled_weight(WeightScaling::Unit), 1.);
+        assert_abs_diff_eq!(vis_ctx.scaled_weight(WeightScaling::Physical), 80_000.);
+
+        // summing the weights of the averaged samples, then applying the
+        // correction, gives the weight at the averaged resolution.
+        let num_avg = (vis_ctx.avg_time * vis_ctx.avg_freq) as f64;
+        for scaling in [
+            WeightScaling::Cotter,
+            WeightScaling::Unit,
+            WeightScaling::Physical,
+        ] {
+            assert_abs_diff_eq!(
+                vis_ctx.scaled_weight(scaling)
+                    * num_avg
+                    * scaling.averaging_correction(vis_ctx.avg_time, vis_ctx.avg_freq),
+                vis_ctx.avg_scaled_weight(scaling)
+            );
+        }
+
+        let mut weights = ndarray::array![8_f32, -8.];
+        WeightScaling::Cotter.rescale_weights(
+            WeightScaling::Unit,
+            &mut weights,
+            vis_ctx.int_time,
+            vis_ctx.freq_resolution_hz,
+        );
+        assert_abs_diff_eq!(weights, ndarray::array![1_f32, -1.]);
+    }
 }
@@ -0,0 +1,410 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Semantic comparison of observation metadata.
+//!
+//! When processing data from several observations together (e.g. the same
+//! field on multiple nights), configuration drift in pointing, channel layout,
+//! integration time or flagged tiles silently corrupts the result. These
+//! functions report such differences in a structured form, which can be
+//! serialised with the `serde` feature.
+
+use crate::{ObsContext, RADec, VisContext};
+
+/// The tolerances used when comparing floating point metadata.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiffTolerances {
+    /// The largest separation between two directions that is considered equal
+    /// \[radians\].
+    pub angle_rad: f64,
+
+    /// The largest distance between two positions that is considered equal
+    /// \[metres\].
+    pub position_m: f64,
+
+    /// The largest difference between two frequencies that is considered
+    /// equal \[Hz\].
+    pub freq_hz: f64,
+
+    /// The largest difference between two times or durations that is
+    /// considered equal \[seconds\].
+    pub time_s: f64,
+}
+
+impl Default for DiffTolerances {
+    fn default() -> Self {
+        Self {
+            // 1 arcsecond
+            angle_rad: (1.0_f64 / 3600.0).to_radians(),
+            position_m: 1e-3,
+            freq_hz: 1.0,
+            time_s: 1e-6,
+        }
+    }
+}
+
+/// A single semantic difference between two contexts, "left" and "right".
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ContextDiff {
+    /// The phase centres differ.
+    PhaseCentre {
+        left: RADec,
+        right: RADec,
+        separation_rad: f64,
+    },
+
+    /// The pointing centres differ, or only one context has one.
+    PointingCentre {
+        left: Option<RADec>,
+        right: Option<RADec>,
+    },
+
+    /// The array positions differ \[radians, radians, metres\].
+    ArrayPosition {
+        left: (f64, f64, f64),
+        right: (f64, f64, f64),
+    },
+
+    /// Some antennas (by name) are only present in one context.
+    Antennas {
+        only_left: Vec<String>,
+        only_right: Vec<String>,
+    },
+
+    /// An antenna present in both contexts has moved.
+    AntennaPosition { name: String, offset_m: f64 },
+
+    /// Some antennas (by name) are only flagged in one context.
+    TileFlags {
+        only_left: Vec<String>,
+        only_right: Vec<String>,
+    },
+
+    /// The (pre-averaging) integration times differ \[seconds\].
+    IntTime { left_s: f64, right_s: f64 },
+
+    /// The (pre-averaging) frequency resolutions differ \[Hz\].
+    FreqResolution { left_hz: f64, right_hz: f64 },
+
+    /// The selected channels differ, i.e. they start at different frequencies
+    /// \[Hz\], or there are a different number of them.
+    Channels {
+        left_start_hz: f64,
+        right_start_hz: f64,
+        left_num_chans: usize,
+        right_num_chans: usize,
+    },
+
+    /// The selected baselines differ.
+    Baselines {
+        left_num_baselines: usize,
+        right_num_baselines: usize,
+        num_common: usize,
+    },
+
+    /// The averaging factors differ, as `(avg_time, avg_freq)`.
+    Averaging {
+        left: (usize, usize),
+        right: (usize, usize),
+    },
+
+    /// The number of visibility pols differ.
+    NumVisPols { left: usize, right: usize },
+}
+
+fn diff_names<'a>(
+    left: impl IntoIterator<Item = &'a String>,
+    right: impl IntoIterator<Item = &'a String>,
+) -> (Vec<String>, Vec<String>) {
+    let left: Vec<&String> = left.into_iter().collect();
+    let right: Vec<&String> = right.into_iter().collect();
+    let only_left = left
+        .iter()
+        .filter(|&name| !right.contains(name))
+        .map(|&name| name.clone())
+        .collect();
+    let only_right = right
+        .iter()
+        .filter(|&name| !left.contains(name))
+        .map(|&name| name.clone())
+        .collect();
+    (only_left, only_right)
+}
+
+/// Compare the observation-level metadata of two [`ObsContext`]s: pointing,
+/// array position and antenna layout. Antennas are matched by name, as their
+/// indices are not stable between observations.
+pub fn diff_obs_contexts(
+    left: &ObsContext,
+    right: &ObsContext,
+    tolerances: DiffTolerances,
+) -> Vec<ContextDiff> {
+    let mut diffs = vec![];
+
+    let separation_rad = left.phase_centre.separation(right.phase_centre);
+    if separation_rad > tolerances.angle_rad {
+        diffs.push(ContextDiff::PhaseCentre {
+            left: left.phase_centre,
+            right: right.phase_centre,
+            separation_rad,
+        });
+    }
+
+    let pointing_differs = match (left.pointing_centre, right.pointing_centre) {
+        (Some(l), Some(r)) => l.separation(r) > tolerances.angle_rad,
+        (None, None) => false,
+        _ => true,
+    };
+    if pointing_differs {
+        diffs.push(ContextDiff::PointingCentre {
+            left: left.pointing_centre,
+            right: right.pointing_centre,
+        });
+    }
+
+    let (l, r) = (left.array_pos, right.array_pos);
+    let (l_xyz, r_xyz) = (l.to_geocentric_wgs84(), r.to_geocentric_wgs84());
+    let (dx, dy, dz) = (l_xyz.x - r_xyz.x, l_xyz.y - r_xyz.y, l_xyz.z - r_xyz.z);
+    let array_offset_m = (dx * dx + dy * dy + dz * dz).sqrt();
+    if array_offset_m > tolerances.position_m {
+        diffs.push(ContextDiff::ArrayPosition {
+            left: (l.longitude_rad, l.latitude_rad, l.height_metres),
+            right: (r.longitude_rad, r.latitude_rad, r.height_metres),
+        });
+    }
+
+    let (only_left, only_right) = diff_names(&left.ant_names, &right.ant_names);
+    if !only_left.is_empty() || !only_right.is_empty() {
+        diffs.push(ContextDiff::Antennas {
+            only_left,
+            only_right,
+        });
+    }
+
+    for (name, l_enh) in left.ant_names.iter().zip(left.ant_positions_enh.iter()) {
+        let r_enh = match right.ant_names.iter().position(|r_name| r_name == name) {
+            Some(r_idx) => right.ant_positions_enh[r_idx],
+            None => continue,
+        };
+        let (de, dn, dh) = (l_enh.e - r_enh.e, l_enh.n - r_enh.n, l_enh.h - r_enh.h);
+        let offset_m = (de * de + dn * dn + dh * dh).sqrt();
+        if offset_m > tolerances.position_m {
+            diffs.push(ContextDiff::AntennaPosition {
+                name: name.clone(),
+                offset_m,
+            });
+        }
+    }
+
+    diffs
+}
+
+/// Compare the flagged tiles of two observations. Tiles are given as indices
+/// into the [`ObsContext::ant_names`] of their observation, and compared by
+/// name.
+pub fn diff_tile_flags(
+    left: &ObsContext,
+    left_flagged_ants: &[usize],
+    right: &ObsContext,
+    right_flagged_ants: &[usize],
+) -> Option<ContextDiff> {
+    let (only_left, only_right) = diff_names(
+        left_flagged_ants.iter().map(|&i| &left.ant_names[i]),
+        right_flagged_ants.iter().map(|&i| &right.ant_names[i]),
+    );
+    if only_left.is_empty() && only_right.is_empty() {
+        None
+    } else {
+        Some(ContextDiff::TileFlags {
+            only_left,
+            only_right,
+        })
+    }
+}
+
+/// Compare the visibility layout of two [`VisContext`]s: integration time,
+/// channel layout, baselines, averaging and pols. The timestamps of the
+/// contexts are not compared, as they are expected to differ between
+/// observations.
+pub fn diff_vis_contexts(
+    left: &VisContext,
+    right: &VisContext,
+    tolerances: DiffTolerances,
+) -> Vec<ContextDiff> {
+    let mut diffs = vec![];
+
+    let (left_s, right_s) = (left.int_time.to_seconds(), right.int_time.to_seconds());
+    if (left_s - right_s).abs() > tolerances.time_s {
+        diffs.push(ContextDiff::IntTime { left_s, right_s });
+    }
+
+    if (left.freq_resolution_hz - right.freq_resolution_hz).abs() > tolerances.freq_hz {
+        diffs.push(ContextDiff::FreqResolution {
+            left_hz: left.freq_resolution_hz,
+            right_hz: right.freq_resolution_hz,
+        });
+    }
+
+    if (left.start_freq_hz - right.start_freq_hz).abs() > tolerances.freq_hz
+        || left.num_sel_chans != right.num_sel_chans
+    {
+        diffs.push(ContextDiff::Channels {
+            left_start_hz: left.start_freq_hz,
+            right_start_hz: right.start_freq_hz,
+            left_num_chans: left.num_sel_chans,
+            right_num_chans: right.num_sel_chans,
+        });
+    }
+
+    if left.sel_baselines != right.sel_baselines {
+        let num_common = left
+            .sel_baselines
+            .iter()
+            .filter(|&b| right.sel_baselines.contains(b))
+            .count();
+        diffs.push(ContextDiff::Baselines {
+            left_num_baselines: left.sel_baselines.len(),
+            right_num_baselines: right.sel_baselines.len(),
+            num_common,
+        });
+    }
+
+    if (left.avg_time, left.avg_freq) != (right.avg_time, right.avg_freq) {
+        diffs.push(ContextDiff::Averaging {
+            left: (left.avg_time, left.avg_freq),
+            right: (right.avg_time, right.avg_freq),
+        });
+    }
+
+    if left.num_vis_pols != right.num_vis_pols {
+        diffs.push(ContextDiff::NumVisPols {
+            left: left.num_vis_pols,
+            right: right.num_vis_pols,
+        });
+    }
+
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use hifitime::{Duration, Epoch};
+
+    use super::*;
+    use crate::{LatLngHeight, ENH};
+
+    fn get_obs_ctx() -> ObsContext {
+        ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            sched_duration: Duration::from_seconds(112.),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::from_degrees(0., -27.),
+            pointing_centre: None,
+            array_pos: LatLngHeight::mwa(),
+            ant_positions_enh: vec![
+                ENH {
+                    e: 0.,
+                    n: 0.,
+                    h: 0.,
+                },
+                ENH {
+                    e: 10.,
+                    n: 0.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["Tile011".into(), "Tile012".into()],
+        }
+    }
+
+    fn get_vis_ctx() -> VisContext {
+        VisContext {
+            num_sel_timesteps: 4,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_seconds(2.),
+            num_sel_chans: 32,
+            start_freq_hz: 167.68e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        }
+    }
+
+    #[test]
+    fn test_diff_obs_contexts() {
+        let left = get_obs_ctx();
+        assert!(diff_obs_contexts(&left, &left, DiffTolerances::default()).is_empty());
+
+        let mut right = get_obs_ctx();
+        right.phase_centre = RADec::from_degrees(1., -27.);
+        right.ant_names[0] = "Tile013".into();
+        right.ant_positions_enh[1].e = 10.5;
+        let diffs = diff_obs_contexts(&left, &right, DiffTolerances::default());
+        assert_eq!(diffs.len(), 3);
+        assert!(matches!(diffs[0], ContextDiff::PhaseCentre { .. }));
+        assert_eq!(
+            diffs[1],
+            ContextDiff::Antennas {
+                only_left: vec!["Tile011".into()],
+                only_right: vec!["Tile013".into()],
+            }
+        );
+        assert!(matches!(
+            &diffs[2],
+            ContextDiff::AntennaPosition { name, .. } if name == "Tile012"
+        ));
+
+        assert_eq!(diff_tile_flags(&left, &[1], &left, &[1]), None);
+        assert_eq!(
+            diff_tile_flags(&left, &[0, 1], &right, &[1]),
+            Some(ContextDiff::TileFlags {
+                only_left: vec!["Tile011".into()],
+                only_right: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn test_diff_vis_contexts() {
+        let left = get_vis_ctx();
+        let mut right = get_vis_ctx();
+        // a different start time is not a difference in layout.
+        right.start_timestamp = left.start_timestamp + Duration::from_seconds(86400.);
+        assert!(diff_vis_contexts(&left, &right, DiffTolerances::default()).is_empty());
+
+        right.int_time = Duration::from_seconds(0.5);
+        right.start_freq_hz += 1.28e6;
+        right.sel_baselines.push((0, 2));
+        let diffs = diff_vis_contexts(&left, &right, DiffTolerances::default());
+        assert_eq!(diffs.len(), 3);
+        assert!(matches!(diffs[0], ContextDiff::IntTime { .. }));
+        assert!(matches!(diffs[1], ContextDiff::Channels { .. }));
+        assert_eq!(
+            diffs[2],
+            ContextDiff::Baselines {
+                left_num_baselines: 1,
+                right_num_baselines: 2,
+                num_common: 1
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_diff_serde() {
+        let diff = ContextDiff::IntTime {
+            left_s: 2.,
+            right_s: 0.5,
+        };
+        let json = serde_json::to_string(&diff).unwrap();
+        let round_trip: ContextDiff = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_trip, diff);
+    }
+}
@@ -0,0 +1,126 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Conversion between per-polarisation and per-visibility flags.
+//!
+//! Flaggers and [`crate::averaging::average_visibilities`] work with flags for
+//! each polarisation (`[timestep][channel][baseline][pol]`), whereas the
+//! writers (via [`crate::VisWrite`]) take a single flag per visibility
+//! (`[timestep][channel][baseline]`). These helpers make the policy used to
+//! collapse the polarisation axis explicit, so that it is the same everywhere.
+
+use ndarray::{prelude::*, Data};
+
+/// How per-pol flags are combined into a single flag per visibility.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PolFlagPolicy {
+    /// A visibility is flagged if any of its pols are flagged.
+    #[default]
+    Any,
+
+    /// A visibility is flagged only if all of its pols are flagged.
+    All,
+
+    /// A visibility is flagged if either of its parallel-hand pols (XX or YY)
+    /// are flagged. Cross-hand pols are ignored. The parallel-hand pols are
+    /// assumed to be the first and last pol, as in XX,XY,YX,YY or XX,YY.
+    ParallelHand,
+}
+
+impl PolFlagPolicy {
+    /// Combine the flags of the pols of a single visibility.
+    pub fn collapse<'a, I>(self, pol_flags: I) -> bool
+    where
+        I: IntoIterator<Item = &'a bool>,
+        I::IntoIter: DoubleEndedIterator,
+    {
+        let mut pol_flags = pol_flags.into_iter();
+        match self {
+            Self::Any => pol_flags.any(|&f| f),
+            Self::All => match pol_flags.next() {
+                Some(&first) => first && pol_flags.all(|&f| f),
+                None => false,
+            },
+            Self::ParallelHand => {
+                let first = pol_flags.next().copied().unwrap_or(false);
+                let last = pol_flags.next_back().copied().unwrap_or(first);
+                first || last
+            }
+        }
+    }
+}
+
+/// Collapse per-pol flags with dimensions `[timestep][channel][baseline][pol]`
+/// into per-visibility flags with dimensions `[timestep][channel][baseline]`,
+/// according to `policy`.
+pub fn collapse_pol_flags<S>(flags: &ArrayBase<S, Ix4>, policy: PolFlagPolicy) -> Array3<bool>
+where
+    S: Data<Elem = bool>,
+{
+    flags.map_axis(Axis(3), |pol_flags| policy.collapse(pol_flags))
+}
+
+/// Expand per-visibility flags with dimensions `[timestep][channel][baseline]`
+/// into per-pol flags with dimensions `[timestep][channel][baseline][pol]`,
+/// where each of the `num_pols` pols of a visibility has the visibility's flag.
+pub fn expand_pol_flags<S>(flags: &ArrayBase<S, Ix3>, num_pols: usize) -> Array4<bool>
+where
+    S: Data<Elem = bool>,
+{
+    let (num_timesteps, num_chans, num_baselines) = flags.dim();
+    flags
+        .view()
+        .insert_axis(Axis(3))
+        .broadcast((num_timesteps, num_chans, num_baselines, num_pols))
+        .expect("a length-1 axis can always be broadcast")
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collapse_pol_flags() {
+        // XX, XY, YX, YY for four visibilities.
+        let flags = Array4::from_shape_vec(
+            (1, 1, 4, 4),
+            vec![
+                false, false, false, false, //
+                false, true, false, false, //
+                false, false, false, true, //
+                true, true, true, true, //
+            ],
+        )
+        .unwrap();
+
+        let any = collapse_pol_flags(&flags, PolFlagPolicy::Any);
+        assert_eq!(any, array![[[false, true, true, true]]]);
+        let all = collapse_pol_flags(&flags, PolFlagPolicy::All);
+        assert_eq!(all, array![[[false, false, false, true]]]);
+        let parallel = collapse_pol_flags(&flags.view(), PolFlagPolicy::ParallelHand);
+        assert_eq!(parallel, array![[[false, false, true, true]]]);
+
+        // two pols: XX, YY
+        let flags = Array4::from_shape_vec((1, 1, 2, 2), vec![false, true, false, false]).unwrap();
+        assert_eq!(
+            collapse_pol_flags(&flags, PolFlagPolicy::ParallelHand),
+            array![[[true, false]]]
+        );
+    }
+
+    #[test]
+    fn test_expand_pol_flags() {
+        let flags = array![[[false, true], [true, false]]];
+        let expanded = expand_pol_flags(&flags, 4);
+        assert_eq!(expanded.dim(), (1, 2, 2, 4));
+        for policy in [
+            PolFlagPolicy::Any,
+            PolFlagPolicy::All,
+            PolFlagPolicy::ParallelHand,
+        ] {
+            assert_eq!(collapse_pol_flags(&expanded, policy), flags);
+        }
+    }
+}
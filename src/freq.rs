@@ -0,0 +1,383 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! MWA coarse channel and sky frequency bookkeeping.
+//!
+//! The MWA receivers channelise the band into [`MWA_NUM_REC_COARSE_CHANS`]
+//! coarse channels of width [`MWA_COARSE_CHAN_WIDTH_HZ`]; receiver coarse
+//! channel `n` is centred on `n × 1.28 MHz`.
+
+use std::ops::Range;
+
+use ndarray::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    constants::{MWA_COARSE_CHAN_FLIP, MWA_COARSE_CHAN_WIDTH_HZ, MWA_NUM_REC_COARSE_CHANS},
+    Jones, VisContext,
+};
+
+#[derive(Error, Debug)]
+pub enum FreqError {
+    #[error("bad array shape supplied to argument {argument} of function {function}. expected {expected}, received {received}")]
+    BadArrayShape {
+        argument: &'static str,
+        function: &'static str,
+        expected: String,
+        received: String,
+    },
+
+    #[error("receiver coarse channels must be unique and in ascending order, received {0:?}")]
+    UnsortedCoarseChans(Vec<usize>),
+
+    #[error(
+        "{num_chans} channels can't be evenly divided between {num_coarse_chans} coarse channels"
+    )]
+    UnevenCoarseChans {
+        num_chans: usize,
+        num_coarse_chans: usize,
+    },
+}
+
+/// The centre sky frequency \[Hz\] of a receiver coarse channel number.
+#[inline]
+pub fn rec_chan_centre_hz(rec_chan: usize) -> f64 {
+    rec_chan as f64 * MWA_COARSE_CHAN_WIDTH_HZ
+}
+
+/// The lower and upper band edges \[Hz\] of a receiver coarse channel number.
+#[inline]
+pub fn rec_chan_edges_hz(rec_chan: usize) -> (f64, f64) {
+    let centre = rec_chan_centre_hz(rec_chan);
+    (
+        centre - MWA_COARSE_CHAN_WIDTH_HZ / 2.0,
+        centre + MWA_COARSE_CHAN_WIDTH_HZ / 2.0,
+    )
+}
+
+/// The receiver coarse channel number containing the given sky frequency
+/// \[Hz\], if there is one.
+#[inline]
+pub fn rec_chan_from_freq_hz(freq_hz: f64) -> Option<usize> {
+    let rec_chan = (freq_hz / MWA_COARSE_CHAN_WIDTH_HZ).round();
+    if (0.0..MWA_NUM_REC_COARSE_CHANS as f64).contains(&rec_chan) {
+        Some(rec_chan as usize)
+    } else {
+        None
+    }
+}
+
+/// Whether the legacy MWA correlator presents this receiver coarse channel in
+/// reverse order, i.e. whether it is above channel [`MWA_COARSE_CHAN_FLIP`].
+#[inline]
+pub fn is_legacy_flipped(rec_chan: usize) -> bool {
+    rec_chan > MWA_COARSE_CHAN_FLIP
+}
+
+/// Sort receiver coarse channel numbers into the order that the legacy MWA
+/// correlator presents them: channels up to and including
+/// [`MWA_COARSE_CHAN_FLIP`] in ascending order, followed by the remaining
+/// channels in descending order.
+///
+/// e.g. `[127, 128, 129, 130]` -> `[127, 128, 130, 129]`
+pub fn legacy_correlator_order(rec_chans: &[usize]) -> Vec<usize> {
+    let mut result = rec_chans.to_vec();
+    result.sort_unstable();
+    let num_unflipped = result.iter().filter(|&&c| !is_legacy_flipped(c)).count();
+    result[num_unflipped..].reverse();
+    result
+}
+
+/// The centre sky frequencies \[Hz\] of the fine channels in a receiver coarse
+/// channel, when it is divided into `num_fine_chans` fine channels.
+pub fn fine_chan_centres_hz(rec_chan: usize, num_fine_chans: usize) -> Vec<f64> {
+    let (start_hz, _) = rec_chan_edges_hz(rec_chan);
+    let fine_chan_width_hz = MWA_COARSE_CHAN_WIDTH_HZ / num_fine_chans as f64;
+    (0..num_fine_chans)
+        .map(|i| start_hz + (i as f64 + 0.5) * fine_chan_width_hz)
+        .collect()
+}
+
+/// Find the receiver coarse channel numbers missing from between the lowest and
+/// highest of `rec_chans`, as a list of contiguous ranges.
+///
+/// e.g. `[131, 132, 135, 137]` -> `[133..135, 136..137]`
+pub fn find_coarse_chan_gaps(rec_chans: &[usize]) -> Vec<Range<usize>> {
+    let mut sorted = rec_chans.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    sorted
+        .windows(2)
+        .filter(|w| w[1] > w[0] + 1)
+        .map(|w| w[0] + 1..w[1])
+        .collect()
+}
+
+/// Check that the frequency axis of `vis_ctx` can be divided evenly between
+/// `rec_chans`, and return the number of fine channels per coarse channel.
+fn fine_chans_per_coarse(vis_ctx: &VisContext, rec_chans: &[usize]) -> Result<usize, FreqError> {
+    if rec_chans.windows(2).any(|w| w[1] <= w[0]) {
+        return Err(FreqError::UnsortedCoarseChans(rec_chans.to_vec()));
+    }
+    if rec_chans.is_empty() || vis_ctx.num_sel_chans % rec_chans.len() != 0 {
+        return Err(FreqError::UnevenCoarseChans {
+            num_chans: vis_ctx.num_sel_chans,
+            num_coarse_chans: rec_chans.len(),
+        });
+    }
+    Ok(vis_ctx.num_sel_chans / rec_chans.len())
+}
+
+/// The "compact" frequency axis \[Hz\] of visibilities whose channels come from
+/// the (possibly non-contiguous) receiver coarse channels `rec_chans`.
+///
+/// Unlike [`VisContext::frequencies_hz`], this accounts for any gaps between
+/// coarse channels. `rec_chans` are the receiver coarse channel numbers of each
+/// coarse channel in the frequency axis of `vis_ctx`, in ascending order.
+pub fn compact_frequencies_hz(
+    vis_ctx: &VisContext,
+    rec_chans: &[usize],
+) -> Result<Vec<f64>, FreqError> {
+    let fine_chans_per_coarse = fine_chans_per_coarse(vis_ctx, rec_chans)?;
+    Ok(rec_chans
+        .iter()
+        .flat_map(|&rec_chan| {
+            let coarse_offset_hz = (rec_chan - rec_chans[0]) as f64 * MWA_COARSE_CHAN_WIDTH_HZ;
+            (0..fine_chans_per_coarse).map(move |fine_chan_idx| {
+                vis_ctx.start_freq_hz
+                    + coarse_offset_hz
+                    + fine_chan_idx as f64 * vis_ctx.freq_resolution_hz
+            })
+        })
+        .collect())
+}
+
+/// Visibilities with a reshaped axis and their updated context, produced by
+/// e.g. [`fill_coarse_chan_gaps`], [`crate::time::fill_timestep_gaps`] and
+/// [`crate::pad::pad_vis`].
+pub struct FilledVis {
+    /// The context of the contiguous visibilities.
+    pub vis_ctx: VisContext,
+    /// Visibilities, `[timestep][channel][baseline]`. Zero in gaps.
+    pub jones: Array3<Jones<f32>>,
+    /// Weights, `[timestep][channel][baseline]`. Zero in gaps.
+    pub weights: Array3<f32>,
+    /// Flags, `[timestep][channel][baseline]`. Flagged in gaps.
+    pub flags: Array3<bool>,
+}
+
+/// Expand visibilities from non-contiguous receiver coarse channels
+/// (`rec_chans`, ascending) onto a contiguous frequency axis, as some imagers
+/// require. Channels in missing coarse channels are zero-filled and flagged, and
+/// the returned [`VisContext`] describes the expanded axis.
+///
+/// `jones`, `weights` and `flags` have dimensions `[timestep][channel][baseline]`
+/// matching [`VisContext::sel_dims`].
+pub fn fill_coarse_chan_gaps(
+    vis_ctx: &VisContext,
+    rec_chans: &[usize],
+    jones: ArrayView3<Jones<f32>>,
+    weights: ArrayView3<f32>,
+    flags: ArrayView3<bool>,
+) -> Result<FilledVis, FreqError> {
+    let sel_dims = vis_ctx.sel_dims();
+    for (argument, received) in [
+        ("jones", jones.dim()),
+        ("weights", weights.dim()),
+        ("flags", flags.dim()),
+    ] {
+        if received != sel_dims {
+            return Err(FreqError::BadArrayShape {
+                argument,
+                function: "fill_coarse_chan_gaps",
+                expected: format!("{sel_dims:?}"),
+                received: format!("{received:?}"),
+            });
+        }
+    }
+    let fine_chans_per_coarse = fine_chans_per_coarse(vis_ctx, rec_chans)?;
+
+    let num_filled_coarse_chans = rec_chans[rec_chans.len() - 1] - rec_chans[0] + 1;
+    let mut filled_ctx = vis_ctx.clone();
+    filled_ctx.num_sel_chans = num_filled_coarse_chans * fine_chans_per_coarse;
+    let filled_dims = filled_ctx.sel_dims();
+
+    let mut filled = FilledVis {
+        jones: Array3::zeros(filled_dims),
+        weights: Array3::zeros(filled_dims),
+        flags: Array3::from_elem(filled_dims, true),
+        vis_ctx: filled_ctx,
+    };
+    for (coarse_idx, &rec_chan) in rec_chans.iter().enumerate() {
+        let src = s![
+            ..,
+            coarse_idx * fine_chans_per_coarse..(coarse_idx + 1) * fine_chans_per_coarse,
+            ..
+        ];
+        let filled_idx = rec_chan - rec_chans[0];
+        let dst = s![
+            ..,
+            filled_idx * fine_chans_per_coarse..(filled_idx + 1) * fine_chans_per_coarse,
+            ..
+        ];
+        filled.jones.slice_mut(dst).assign(&jones.slice(src));
+        filled.weights.slice_mut(dst).assign(&weights.slice(src));
+        filled.flags.slice_mut(dst).assign(&flags.slice(src));
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_rec_chan_freqs() {
+        assert_abs_diff_eq!(rec_chan_centre_hz(133), 170.24e6);
+        let (lower, upper) = rec_chan_edges_hz(133);
+        assert_abs_diff_eq!(lower, 169.6e6);
+        assert_abs_diff_eq!(upper, 170.88e6);
+
+        assert_eq!(rec_chan_from_freq_hz(170.24e6), Some(133));
+        assert_eq!(rec_chan_from_freq_hz(169.7e6), Some(133));
+        assert_eq!(rec_chan_from_freq_hz(170.8e6), Some(133));
+        assert_eq!(rec_chan_from_freq_hz(-1e6), None);
+        assert_eq!(rec_chan_from_freq_hz(400e6), None);
+    }
+
+    #[test]
+    fn test_legacy_correlator_order() {
+        assert!(!is_legacy_flipped(128));
+        assert!(is_legacy_flipped(129));
+        assert_eq!(
+            legacy_correlator_order(&[130, 127, 129, 128]),
+            vec![127, 128, 130, 129]
+        );
+        assert_eq!(
+            legacy_correlator_order(&[131, 133, 132]),
+            vec![133, 132, 131]
+        );
+        assert_eq!(legacy_correlator_order(&[63, 62]), vec![62, 63]);
+    }
+
+    fn get_vis_ctx(num_sel_chans: usize) -> VisContext {
+        VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: hifitime::Epoch::from_gpst_seconds(1090008640.),
+            int_time: hifitime::Duration::from_seconds(1.),
+            num_sel_chans,
+            start_freq_hz: rec_chan_edges_hz(131).0 + 320e3,
+            freq_resolution_hz: 640e3,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        }
+    }
+
+    #[test]
+    fn test_find_coarse_chan_gaps() {
+        assert_eq!(
+            find_coarse_chan_gaps(&[137, 131, 132, 135]),
+            vec![133..135, 136..137]
+        );
+        assert!(find_coarse_chan_gaps(&[131, 132, 133]).is_empty());
+        assert!(find_coarse_chan_gaps(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_compact_frequencies() {
+        let vis_ctx = get_vis_ctx(4);
+        let freqs = compact_frequencies_hz(&vis_ctx, &[131, 133]).unwrap();
+        let expected = [
+            rec_chan_centre_hz(131) - 320e3,
+            rec_chan_centre_hz(131) + 320e3,
+            rec_chan_centre_hz(133) - 320e3,
+            rec_chan_centre_hz(133) + 320e3,
+        ];
+        for (f, e) in freqs.iter().zip(expected) {
+            assert_abs_diff_eq!(*f, e, epsilon = 1e-6);
+        }
+
+        assert!(matches!(
+            compact_frequencies_hz(&vis_ctx, &[133, 131]),
+            Err(FreqError::UnsortedCoarseChans(_))
+        ));
+        assert!(matches!(
+            compact_frequencies_hz(&vis_ctx, &[131, 132, 133]),
+            Err(FreqError::UnevenCoarseChans { .. })
+        ));
+    }
+
+    #[test]
+    fn test_fill_coarse_chan_gaps() {
+        let vis_ctx = get_vis_ctx(4);
+        let dims = vis_ctx.sel_dims();
+        let jones = Array3::from_elem(dims, Jones::identity());
+        let weights = Array3::from_elem(dims, 1.0);
+        let flags = Array3::from_elem(dims, false);
+
+        let filled = fill_coarse_chan_gaps(
+            &vis_ctx,
+            &[131, 133],
+            jones.view(),
+            weights.view(),
+            flags.view(),
+        )
+        .unwrap();
+
+        assert_eq!(filled.vis_ctx.num_sel_chans, 6);
+        assert_eq!(filled.jones.dim(), (2, 6, 1));
+        let freqs = filled.vis_ctx.frequencies_hz();
+        assert_abs_diff_eq!(freqs[4], rec_chan_centre_hz(133) - 320e3, epsilon = 1e-6);
+
+        for chan_idx in [0, 1, 4, 5] {
+            assert!(filled.flags.slice(s![.., chan_idx, ..]).iter().all(|&f| !f));
+            assert!(filled
+                .weights
+                .slice(s![.., chan_idx, ..])
+                .iter()
+                .all(|&w| w > 0.));
+        }
+        for chan_idx in [2, 3] {
+            assert!(filled.flags.slice(s![.., chan_idx, ..]).iter().all(|&f| f));
+            assert!(filled
+                .weights
+                .slice(s![.., chan_idx, ..])
+                .iter()
+                .all(|&w| w <= 0.));
+            assert!(filled
+                .jones
+                .slice(s![.., chan_idx, ..])
+                .iter()
+                .all(|&j| j == Jones::default()));
+        }
+
+        assert!(matches!(
+            fill_coarse_chan_gaps(
+                &vis_ctx,
+                &[131, 133],
+                jones.slice(s![.., ..2, ..]),
+                weights.view(),
+                flags.view()
+            ),
+            Err(FreqError::BadArrayShape {
+                argument: "jones",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_fine_chan_centres() {
+        let freqs = fine_chan_centres_hz(133, 32);
+        assert_eq!(freqs.len(), 32);
+        assert_abs_diff_eq!(freqs[0], 169.6e6 + 20e3);
+        assert_abs_diff_eq!(freqs[31], 170.88e6 - 20e3);
+        assert_abs_diff_eq!((freqs[15] + freqs[16]) / 2.0, rec_chan_centre_hz(133));
+    }
+}
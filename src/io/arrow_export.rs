@@ -0,0 +1,262 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Export visibilities to Apache Arrow record batches and Parquet files, so
+//! that subsets can be inspected with SQL or dataframe libraries.
+//!
+//! Each row is a single polarisation of a single visibility, with the columns:
+//!
+//! | column     | type    | description                                  |
+//! |------------|---------|----------------------------------------------|
+//! | `time_gps` | Float64 | the centroid of the timestep \[GPS seconds\] |
+//! | `ant1`     | UInt32  | the first antenna of the baseline            |
+//! | `ant2`     | UInt32  | the second antenna of the baseline           |
+//! | `channel`  | UInt32  | the index of the (selected) channel          |
+//! | `freq_hz`  | Float64 | the centre frequency of the channel \[Hz\]   |
+//! | `pol`      | Utf8    | the polarisation, e.g. "XX"                  |
+//! | `re`       | Float32 | the real part of the visibility              |
+//! | `im`       | Float32 | the imaginary part of the visibility         |
+//! | `weight`   | Float32 | the weight of the visibility                 |
+//! | `flag`     | Boolean | whether the visibility is flagged            |
+//!
+//! Rows are ordered by time, channel, baseline, then pol, and there is a record
+//! batch (Parquet row group) per timestep.
+
+use std::{fs::File, path::Path, sync::Arc};
+
+use arrow::{
+    array::{ArrayRef, BooleanArray, Float32Array, Float64Array, StringArray, UInt32Array},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    record_batch::RecordBatch,
+};
+use ndarray::prelude::*;
+use parquet::arrow::ArrowWriter;
+
+use super::error::ArrowExportError;
+use crate::{pol::PolOrder, vis_array::VisArray, VisContext};
+
+/// The schema of exported visibilities.
+pub fn vis_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("time_gps", DataType::Float64, false),
+        Field::new("ant1", DataType::UInt32, false),
+        Field::new("ant2", DataType::UInt32, false),
+        Field::new("channel", DataType::UInt32, false),
+        Field::new("freq_hz", DataType::Float64, false),
+        Field::new("pol", DataType::Utf8, false),
+        Field::new("re", DataType::Float32, false),
+        Field::new("im", DataType::Float32, false),
+        Field::new("weight", DataType::Float32, false),
+        Field::new("flag", DataType::Boolean, false),
+    ]))
+}
+
+/// Convert visibilities, contextualised by a [`VisContext`], to a record batch
+/// per timestep. Only the timesteps, channels and baselines of the selection
+/// described by `vis_ctx` are exported, so a subset can be exported by
+/// slicing the visibilities and adjusting the context.
+///
+/// # Errors
+///
+/// Will return [`ArrowExportError::BadArrayShape`] if `vis` doesn't have the
+/// dimensions of the selection in `vis_ctx`, or an error if a record batch
+/// can't be made.
+pub fn vis_to_record_batches(
+    vis: &VisArray,
+    vis_ctx: &VisContext,
+) -> Result<Vec<RecordBatch>, ArrowExportError> {
+    let sel_dims = vis_ctx.sel_dims();
+    if vis.dim() != sel_dims {
+        return Err(ArrowExportError::BadArrayShape {
+            expected: format!("{sel_dims:?}"),
+            received: format!("{:?}", vis.dim()),
+        });
+    }
+    let schema = vis_schema();
+    let freqs_hz = vis_ctx.frequencies_hz();
+    let pols = PolOrder::Jones.pols();
+    let pol_names: Vec<String> = pols.iter().map(ToString::to_string).collect();
+
+    vis_ctx
+        .timeseries(false, true)
+        .enumerate()
+        .map(|(timestep_idx, timestamp)| {
+            let jones = vis.jones();
+            let jones = jones.index_axis(Axis(0), timestep_idx);
+            let weights = vis.weights();
+            let weights = weights.index_axis(Axis(0), timestep_idx);
+            let flags = vis.flags();
+            let flags = flags.index_axis(Axis(0), timestep_idx);
+
+            let num_rows = jones.len() * pols.len();
+            let mut ant1s = Vec::with_capacity(num_rows);
+            let mut ant2s = Vec::with_capacity(num_rows);
+            let mut chans = Vec::with_capacity(num_rows);
+            let mut freqs = Vec::with_capacity(num_rows);
+            let mut pol_col = Vec::with_capacity(num_rows);
+            let mut res = Vec::with_capacity(num_rows);
+            let mut ims = Vec::with_capacity(num_rows);
+            for ((chan_idx, baseline_idx), j) in jones.indexed_iter() {
+                let (ant1, ant2) = vis_ctx.sel_baselines[baseline_idx];
+                for (pol, pol_name) in pols.iter().zip(&pol_names) {
+                    let c = j.get_pol(*pol);
+                    ant1s.push(ant1 as u32);
+                    ant2s.push(ant2 as u32);
+                    chans.push(chan_idx as u32);
+                    freqs.push(freqs_hz[chan_idx]);
+                    pol_col.push(pol_name.as_str());
+                    res.push(c.re);
+                    ims.push(c.im);
+                }
+            }
+
+            let columns: Vec<ArrayRef> = vec![
+                Arc::new(Float64Array::from(vec![
+                    timestamp.to_gpst_seconds();
+                    num_rows
+                ])),
+                Arc::new(UInt32Array::from(ant1s)),
+                Arc::new(UInt32Array::from(ant2s)),
+                Arc::new(UInt32Array::from(chans)),
+                Arc::new(Float64Array::from(freqs)),
+                Arc::new(StringArray::from(pol_col)),
+                Arc::new(Float32Array::from(res)),
+                Arc::new(Float32Array::from(ims)),
+                Arc::new(Float32Array::from_iter_values(weights.iter().copied())),
+                Arc::new(BooleanArray::from(
+                    flags.iter().copied().collect::<Vec<_>>(),
+                )),
+            ];
+            Ok(RecordBatch::try_new(schema.clone(), columns)?)
+        })
+        .collect()
+}
+
+/// Write visibilities, contextualised by a [`VisContext`], to a Parquet file.
+/// See [`vis_to_record_batches`].
+///
+/// This will destroy any existing file at `path`.
+///
+/// # Errors
+///
+/// Will return an [`ArrowExportError`] if the visibilities can't be converted
+/// or the file can't be written.
+pub fn write_vis_parquet<T: AsRef<Path>>(
+    path: T,
+    vis: &VisArray,
+    vis_ctx: &VisContext,
+) -> Result<(), ArrowExportError> {
+    let batches = vis_to_record_batches(vis, vis_ctx)?;
+    let file = File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, vis_schema(), None)?;
+    for batch in &batches {
+        writer.write(batch)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hifitime::{Duration, Epoch},
+        Jones,
+    };
+    use approx::assert_abs_diff_eq;
+    use arrow::array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use tempfile::tempdir;
+
+    fn synthesize() -> (VisArray, VisContext) {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: Epoch::from_gpst_seconds(1196175296.0),
+            int_time: Duration::from_seconds(2.0),
+            num_sel_chans: 3,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1), (0, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+        let dims = vis_ctx.sel_dims();
+        let pol_dims = (dims.0, dims.1, dims.2, 4);
+        let vis = VisArray::new(
+            Array3::from_shape_fn(dims, |(t, c, b)| {
+                Jones::from([t as f32, c as f32, b as f32, 1.0, 2.0, 3.0, 4.0, 5.0])
+            }),
+            Array4::from_elem(pol_dims, 1.0),
+            Array4::from_shape_fn(pol_dims, |(_, c, _, p)| c == 1 && p == 3),
+        )
+        .unwrap();
+        (vis, vis_ctx)
+    }
+
+    #[test]
+    fn test_record_batches() {
+        let (vis, vis_ctx) = synthesize();
+        let batches = vis_to_record_batches(&vis, &vis_ctx).unwrap();
+        assert_eq!(batches.len(), 2);
+        let batch = &batches[1];
+        assert_eq!(batch.num_rows(), 3 * 2 * 4);
+
+        let time = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        // The centroid of the second timestep.
+        assert_abs_diff_eq!(time.value(0), 1196175296.0 + 3.0, epsilon = 1e-6);
+        let pol = batch
+            .column(5)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(pol.value(0), "XX");
+        assert_eq!(pol.value(3), "YY");
+        // Channel 1, baseline 0, XY.
+        let re = batch
+            .column(6)
+            .as_any()
+            .downcast_ref::<Float32Array>()
+            .unwrap();
+        assert_abs_diff_eq!(re.value(8 + 1), 0.0);
+        assert_abs_diff_eq!(re.value(4), 1.0);
+        let flag = batch
+            .column(9)
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap();
+        assert_eq!(
+            (0..flag.len())
+                .filter(|&i| flag.value(i))
+                .collect::<Vec<_>>(),
+            [11, 15]
+        );
+
+        let mut wrong_ctx = vis_ctx;
+        wrong_ctx.num_sel_chans = 2;
+        assert!(matches!(
+            vis_to_record_batches(&vis, &wrong_ctx),
+            Err(ArrowExportError::BadArrayShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_write_parquet() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("vis.parquet");
+        let (vis, vis_ctx) = synthesize();
+        write_vis_parquet(&path, &vis, &vis_ctx).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let num_rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(num_rows, 2 * 3 * 2 * 4);
+    }
+}
@@ -0,0 +1,642 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Reading and writing MWA calibration solution files.
+//!
+//! Two formats are supported:
+//!
+//! - hyperdrive FITS (`.fits`), which needs the `cfitsio` feature. The
+//!   `SOLUTIONS` image HDU holds the solutions as 8 `f64`s per Jones matrix
+//!   (`[timeblock][tile][chanblock][8]`), the `TIMEBLOCKS` table the GPS
+//!   start, end and average time of each timeblock, the `TILES` table the
+//!   name and flag of each tile, and the `CHANBLOCKS` table the index, flag
+//!   and frequency \[Hz\] of each chanblock.
+//! - André Offringa's binary format (`.bin`), as written by `calibrate`. All
+//!   values are little endian. The header is the magic bytes `MWAOCAL\0`, the
+//!   file type and structure type (`i32`s, both 0), the number of intervals,
+//!   antennas, channels and pols (`i32`s), and the start and end times of the
+//!   solutions (`f64` MJD seconds). Then the solutions follow as complex
+//!   `f64`s in `[interval][antenna][channel][pol]` order.
+//!
+//! Flagged tiles and chanblocks have NaN solutions in both formats; the binary
+//! format has no other way to flag them.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use ndarray::prelude::*;
+
+use super::error::CalSolError;
+use crate::{
+    hifitime::{Epoch, Unit},
+    History, Jones,
+};
+
+/// The bytes at the start of every André Offringa binary file.
+const AO_MAGIC: &[u8; 8] = b"MWAOCAL\0";
+
+/// The size of the header of André Offringa binary files \[bytes\].
+const AO_HEADER_SIZE: usize = 48;
+
+/// Direction-independent calibration solutions, with their metadata.
+#[derive(Clone, Debug, Default)]
+pub struct CalibrationSolutions {
+    /// The solutions, with dimensions `[timeblock][tile][chanblock]`.
+    pub di_jones: Array3<Jones<f64>>,
+
+    /// The indices of the flagged tiles.
+    pub flagged_tiles: Vec<usize>,
+
+    /// The indices of the flagged chanblocks.
+    pub flagged_chanblocks: Vec<usize>,
+
+    /// The observation ID.
+    pub obsid: Option<u32>,
+
+    /// The start time of each timeblock.
+    pub start_timestamps: Option<Vec<Epoch>>,
+
+    /// The end time of each timeblock.
+    pub end_timestamps: Option<Vec<Epoch>>,
+
+    /// The name of each tile.
+    pub tile_names: Option<Vec<String>>,
+
+    /// The centre frequency of each chanblock \[Hz\].
+    pub chanblock_freqs: Option<Vec<f64>>,
+}
+
+impl CalibrationSolutions {
+    /// Read calibration solutions from `path`, with the format determined by
+    /// its extension (`fits` or `bin`).
+    ///
+    /// # Errors
+    ///
+    /// Will return [`CalSolError::UnsupportedExtension`] if the extension isn't
+    /// recognised (or `fits` without the `cfitsio` feature), or an error if
+    /// the file can't be read.
+    pub fn read<T: AsRef<Path>>(path: T) -> Result<Self, CalSolError> {
+        let path = path.as_ref();
+        match extension(path).as_deref() {
+            #[cfg(feature = "cfitsio")]
+            Some("fits") => Self::read_hyperdrive_fits(path),
+            Some("bin") => Self::read_andre_binary(path),
+            _ => Err(CalSolError::UnsupportedExtension {
+                path: path.to_path_buf(),
+            }),
+        }
+    }
+
+    /// Write calibration solutions to `path`, with the format determined by
+    /// its extension (`fits` or `bin`). `history` is only written to FITS
+    /// files (see [`CalibrationSolutions::write_hyperdrive_fits`]).
+    ///
+    /// # Errors
+    ///
+    /// See [`CalibrationSolutions::read`].
+    pub fn write<T: AsRef<Path>>(
+        &self,
+        path: T,
+        history: Option<&History>,
+    ) -> Result<(), CalSolError> {
+        let path = path.as_ref();
+        match extension(path).as_deref() {
+            #[cfg(feature = "cfitsio")]
+            Some("fits") => self.write_hyperdrive_fits(path, history),
+            Some("bin") => self.write_andre_binary(path),
+            _ => Err(CalSolError::UnsupportedExtension {
+                path: path.to_path_buf(),
+            }),
+        }
+    }
+
+    /// The solutions, with flagged tiles and chanblocks set to NaN.
+    fn flagged_di_jones(&self) -> Array3<Jones<f64>> {
+        let mut di_jones = self.di_jones.clone();
+        for &tile in &self.flagged_tiles {
+            if tile < di_jones.len_of(Axis(1)) {
+                di_jones.slice_mut(s![.., tile, ..]).fill(Jones::nan());
+            }
+        }
+        for &chanblock in &self.flagged_chanblocks {
+            if chanblock < di_jones.len_of(Axis(2)) {
+                di_jones.slice_mut(s![.., .., chanblock]).fill(Jones::nan());
+            }
+        }
+        di_jones
+    }
+
+    /// Flag the tiles and chanblocks whose solutions are all NaN.
+    fn flag_nan_solutions(&mut self) {
+        let all_nan = |solutions: ArrayView2<Jones<f64>>| {
+            !solutions.is_empty() && solutions.iter().all(|j| j.any_nan())
+        };
+        self.flagged_tiles = (0..self.di_jones.len_of(Axis(1)))
+            .filter(|&tile| all_nan(self.di_jones.index_axis(Axis(1), tile)))
+            .collect();
+        self.flagged_chanblocks = (0..self.di_jones.len_of(Axis(2)))
+            .filter(|&chanblock| all_nan(self.di_jones.index_axis(Axis(2), chanblock)))
+            .collect();
+    }
+
+    /// Read calibration solutions in André Offringa's binary format. Tiles and
+    /// chanblocks whose solutions are all NaN are flagged. The start and end
+    /// times are only kept if there is a single interval.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`CalSolError::BadAndreBinary`] if the file isn't in this
+    /// format, or an error if reading fails.
+    pub fn read_andre_binary<T: AsRef<Path>>(path: T) -> Result<Self, CalSolError> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut file = BufReader::new(file);
+        let mut header = [0; AO_HEADER_SIZE];
+        file.read_exact(&mut header)?;
+        if &header[..8] != AO_MAGIC {
+            return Err(CalSolError::BadAndreBinary {
+                message: "bad magic bytes".into(),
+            });
+        }
+        let int = |i: usize| i32::from_le_bytes(header[8 + 4 * i..12 + 4 * i].try_into().unwrap());
+        let float =
+            |i: usize| f64::from_le_bytes(header[32 + 8 * i..40 + 8 * i].try_into().unwrap());
+        if int(0) != 0 || int(1) != 0 {
+            return Err(CalSolError::BadAndreBinary {
+                message: format!(
+                    "unsupported file type {} or structure type {}",
+                    int(0),
+                    int(1)
+                ),
+            });
+        }
+        if int(5) != 4 {
+            return Err(CalSolError::BadAndreBinary {
+                message: format!("expected 4 pols, but the file has {}", int(5)),
+            });
+        }
+        let shape = match (
+            usize::try_from(int(2)),
+            usize::try_from(int(3)),
+            usize::try_from(int(4)),
+        ) {
+            (Ok(t), Ok(a), Ok(c)) => (t, a, c),
+            _ => {
+                return Err(CalSolError::BadAndreBinary {
+                    message: format!("bad dimensions ({}, {}, {})", int(2), int(3), int(4)),
+                })
+            }
+        };
+
+        // don't trust the dimensions to allocate more than the file holds.
+        let num_bytes = shape
+            .0
+            .checked_mul(shape.1)
+            .and_then(|n| n.checked_mul(shape.2))
+            .and_then(|n| n.checked_mul(8 * 8))
+            .filter(|&n| n as u64 <= file_len.saturating_sub(AO_HEADER_SIZE as u64))
+            .ok_or_else(|| CalSolError::BadAndreBinary {
+                message: format!(
+                    "dimensions {shape:?} need more data than the {file_len} byte file holds"
+                ),
+            })?;
+        let mut bytes = vec![0; num_bytes];
+        file.read_exact(&mut bytes)?;
+        let di_jones = bytes
+            .chunks_exact(8 * 8)
+            .map(|jones| {
+                let mut floats = [0.0; 8];
+                for (f, b) in floats.iter_mut().zip(jones.chunks_exact(8)) {
+                    *f = f64::from_le_bytes(b.try_into().unwrap());
+                }
+                Jones::from(floats)
+            })
+            .collect::<Vec<_>>();
+
+        let (start, end) = (float(0), float(1));
+        let (start_timestamps, end_timestamps) = if shape.0 == 1 && (start, end) != (0.0, 0.0) {
+            (
+                Some(vec![Epoch::from_mjd_utc(start / 86400.0)]),
+                Some(vec![Epoch::from_mjd_utc(end / 86400.0)]),
+            )
+        } else {
+            (None, None)
+        };
+        let mut sols = Self {
+            di_jones: Array3::from_shape_vec(shape, di_jones).unwrap(),
+            start_timestamps,
+            end_timestamps,
+            ..Default::default()
+        };
+        sols.flag_nan_solutions();
+        Ok(sols)
+    }
+
+    /// Write calibration solutions in André Offringa's binary format, with
+    /// flagged tiles and chanblocks set to NaN. The start time of the first
+    /// timeblock and the end time of the last are written, if known.
+    ///
+    /// This will destroy any existing file at that path.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`CalSolError::StdIo`] if writing fails.
+    pub fn write_andre_binary<T: AsRef<Path>>(&self, path: T) -> Result<(), CalSolError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let (num_timeblocks, num_tiles, num_chanblocks) = self.di_jones.dim();
+        file.write_all(AO_MAGIC)?;
+        for value in [0, 0, num_timeblocks, num_tiles, num_chanblocks, 4] {
+            file.write_all(&(value as i32).to_le_bytes())?;
+        }
+        let mjd_seconds = |t: Option<&Epoch>| t.map_or(0.0, |t| t.to_mjd_utc(Unit::Second));
+        let start = mjd_seconds(self.start_timestamps.as_ref().and_then(|t| t.first()));
+        let end = mjd_seconds(self.end_timestamps.as_ref().and_then(|t| t.last()));
+        file.write_all(&start.to_le_bytes())?;
+        file.write_all(&end.to_le_bytes())?;
+        for jones in &self.flagged_di_jones() {
+            for f in jones.to_float_array() {
+                file.write_all(&f.to_le_bytes())?;
+            }
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cfitsio")]
+impl CalibrationSolutions {
+    /// Read calibration solutions in the hyperdrive FITS format. Tile and
+    /// chanblock flags are read from the `TILES` and `CHANBLOCKS` tables, or
+    /// if they're missing, from which solutions are all NaN.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`CalSolError::BadSolutionsShape`] if the `SOLUTIONS` HDU
+    /// isn't a four dimensional image of 8 floats per Jones matrix, or an error
+    /// if a fits operation fails.
+    pub fn read_hyperdrive_fits<T: AsRef<Path>>(path: T) -> Result<Self, CalSolError> {
+        use fitsio::{hdu::HduInfo, FitsFile};
+
+        let mut fptr = FitsFile::open(path)?;
+        let hdu = fptr.primary_hdu()?;
+        let obsid = hdu
+            .read_key::<i64>(&mut fptr, "OBSID")
+            .ok()
+            .map(|o| o as u32);
+
+        let hdu = fptr.hdu("SOLUTIONS")?;
+        let shape = match &hdu.info {
+            HduInfo::ImageInfo { shape, .. } if shape.len() == 4 && shape[3] == 8 => {
+                (shape[0], shape[1], shape[2])
+            }
+            HduInfo::ImageInfo { shape, .. } => {
+                return Err(CalSolError::BadSolutionsShape {
+                    shape: shape.clone(),
+                })
+            }
+            _ => return Err(CalSolError::BadSolutionsShape { shape: vec![] }),
+        };
+        let floats: Vec<f64> = hdu.read_image(&mut fptr)?;
+        let di_jones = floats
+            .chunks_exact(8)
+            .map(|f| Jones::from(<[f64; 8]>::try_from(f).unwrap()))
+            .collect::<Vec<_>>();
+        let mut sols = Self {
+            di_jones: Array3::from_shape_vec(shape, di_jones).unwrap(),
+            obsid,
+            ..Default::default()
+        };
+        sols.flag_nan_solutions();
+
+        let to_epochs = |gps: Vec<f64>| -> Vec<Epoch> {
+            gps.into_iter().map(Epoch::from_gpst_seconds).collect()
+        };
+        if let Ok(hdu) = fptr.hdu("TIMEBLOCKS") {
+            sols.start_timestamps = hdu.read_col(&mut fptr, "Start").ok().map(to_epochs);
+            sols.end_timestamps = hdu.read_col(&mut fptr, "End").ok().map(to_epochs);
+        }
+        if let Ok(hdu) = fptr.hdu("TILES") {
+            if let Ok(flags) = hdu.read_col::<i32>(&mut fptr, "Flag") {
+                sols.flagged_tiles = flagged_indices(&flags);
+            }
+            sols.tile_names = hdu.read_col(&mut fptr, "TileName").ok();
+        }
+        if let Ok(hdu) = fptr.hdu("CHANBLOCKS") {
+            if let Ok(flags) = hdu.read_col::<i32>(&mut fptr, "Flag") {
+                sols.flagged_chanblocks = flagged_indices(&flags);
+            }
+            sols.chanblock_freqs = hdu.read_col(&mut fptr, "Freq").ok();
+        }
+        Ok(sols)
+    }
+
+    /// Write calibration solutions in the hyperdrive FITS format, with
+    /// flagged tiles and chanblocks set to NaN. The `TIMEBLOCKS` table is only
+    /// written if the start and end times are known. The `SOFTWARE` key and
+    /// `HISTORY` cards of the primary HDU come from `history`, as they do for
+    /// the uvfits writer.
+    ///
+    /// This will destroy any existing file at that path.
+    ///
+    /// # Errors
+    ///
+    /// Will return a [`CalSolError`] if an existing file can't be removed, or a
+    /// fits operation fails.
+    pub fn write_hyperdrive_fits<T: AsRef<Path>>(
+        &self,
+        path: T,
+        history: Option<&History>,
+    ) -> Result<(), CalSolError> {
+        use std::ffi::CString;
+
+        use fitsio::errors::check_status as fits_check_status;
+
+        use super::uvfits::{
+            create_table, fits_write_history, fits_write_int, fits_write_string, write_col_dbl,
+            write_col_int, write_col_str,
+        };
+
+        let path = path.as_ref();
+        // Delete any file that already exists.
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        let (num_timeblocks, num_tiles, num_chanblocks) = self.di_jones.dim();
+
+        let mut status = 0;
+        let c_path = CString::new(path.to_str().unwrap())?;
+        let mut fptr = std::ptr::null_mut();
+        unsafe {
+            // ffinit = fits_create_file
+            fitsio_sys::ffinit(
+                &mut fptr,       /* O - FITS file pointer                   */
+                c_path.as_ptr(), /* I - name of file to create              */
+                &mut status,     /* IO - error status                       */
+            );
+            fits_check_status(status)?;
+            // An empty primary HDU. ffphps = fits_write_imghdr
+            fitsio_sys::ffphps(
+                fptr,                 /* I - FITS file pointer                   */
+                8,                    /* I - number of bits per data value pixel */
+                0,                    /* I - number of axes in the data array    */
+                std::ptr::null_mut(), /* I - length of each data axis            */
+                &mut status,          /* IO - error status                       */
+            );
+        }
+        fits_check_status(status)?;
+        if let Some(obsid) = self.obsid {
+            fits_write_int(fptr, "OBSID", obsid.into(), Some("The observation ID"))?;
+        }
+        let history = history.cloned().unwrap_or_default();
+        fits_write_string(fptr, "SOFTWARE", &history.software(), None)?;
+        for card in &history.as_history_cards(history.time_or_now()) {
+            fits_write_history(fptr, card)?;
+        }
+
+        // SOLUTIONS
+        let mut floats = self
+            .flagged_di_jones()
+            .iter()
+            .flat_map(|j| j.to_float_array())
+            .collect::<Vec<_>>();
+        let mut naxes = [8, num_chanblocks as _, num_tiles as _, num_timeblocks as _];
+        unsafe {
+            // ffcrim = fits_create_img. DOUBLE_IMG is -64.
+            fitsio_sys::ffcrim(
+                fptr,               /* I - FITS file pointer           */
+                -64,                /* I - bits per pixel              */
+                4,                  /* I - number of axes in the array */
+                naxes.as_mut_ptr(), /* I - size of each axis           */
+                &mut status,        /* IO - error status               */
+            );
+            fits_check_status(status)?;
+            // ffpprd = fits_write_img_dbl
+            fitsio_sys::ffpprd(
+                fptr,                /* I - FITS file pointer                       */
+                0,                   /* I - group to write(1 = 1st group)           */
+                1,                   /* I - first vector element to write(1 = 1st)  */
+                floats.len() as i64, /* I - number of values to write               */
+                floats.as_mut_ptr(), /* I - array of values that are written        */
+                &mut status,         /* IO - error status                           */
+            );
+        }
+        fits_check_status(status)?;
+        fits_write_string(fptr, "EXTNAME", "SOLUTIONS", None)?;
+
+        // TIMEBLOCKS
+        if let (Some(starts), Some(ends)) = (&self.start_timestamps, &self.end_timestamps) {
+            create_table(
+                fptr,
+                "TIMEBLOCKS",
+                &[
+                    ("Start", "1D", ""),
+                    ("End", "1D", ""),
+                    ("Average", "1D", ""),
+                ],
+            )?;
+            for (row, (start, end)) in starts.iter().zip(ends).enumerate() {
+                let (start, end) = (start.to_gpst_seconds(), end.to_gpst_seconds());
+                write_col_dbl(fptr, 1, row, &[start])?;
+                write_col_dbl(fptr, 2, row, &[end])?;
+                write_col_dbl(fptr, 3, row, &[(start + end) / 2.0])?;
+            }
+        }
+
+        // TILES
+        let name_len = self
+            .tile_names
+            .iter()
+            .flatten()
+            .map(String::len)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        create_table(
+            fptr,
+            "TILES",
+            &[
+                ("Antenna", "1J", ""),
+                ("Flag", "1J", ""),
+                ("TileName", &format!("{name_len}A"), ""),
+            ],
+        )?;
+        for tile in 0..num_tiles {
+            let flag = self.flagged_tiles.contains(&tile);
+            write_col_int(fptr, 1, tile, &[tile as i32])?;
+            write_col_int(fptr, 2, tile, &[i32::from(flag)])?;
+            let name = self
+                .tile_names
+                .as_ref()
+                .and_then(|names| names.get(tile))
+                .map_or("", String::as_str);
+            write_col_str(fptr, 3, tile, name)?;
+        }
+
+        // CHANBLOCKS
+        let mut columns = vec![("Index", "1J", ""), ("Flag", "1J", "")];
+        if self.chanblock_freqs.is_some() {
+            columns.push(("Freq", "1D", "Hz"));
+        }
+        create_table(fptr, "CHANBLOCKS", &columns)?;
+        for chanblock in 0..num_chanblocks {
+            let flag = self.flagged_chanblocks.contains(&chanblock);
+            write_col_int(fptr, 1, chanblock, &[chanblock as i32])?;
+            write_col_int(fptr, 2, chanblock, &[i32::from(flag)])?;
+            if let Some(freq) = self.chanblock_freqs.as_ref().and_then(|f| f.get(chanblock)) {
+                write_col_dbl(fptr, 3, chanblock, &[*freq])?;
+            }
+        }
+
+        unsafe {
+            // ffclos = fits_close_file
+            fitsio_sys::ffclos(fptr, &mut status);
+        }
+        fits_check_status(status)?;
+        Ok(())
+    }
+}
+
+/// The lowercase extension of `path`.
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+}
+
+/// The indices of the non-zero flags.
+#[cfg(feature = "cfitsio")]
+fn flagged_indices(flags: &[i32]) -> Vec<usize> {
+    flags
+        .iter()
+        .enumerate()
+        .filter(|&(_, &f)| f != 0)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::c64;
+
+    fn test_solutions() -> CalibrationSolutions {
+        let di_jones = Array3::from_shape_fn((1, 3, 4), |(t, a, c)| {
+            Jones::from([
+                c64::new(1.0 + a as f64, c as f64),
+                c64::new(0.0, 0.1),
+                c64::new(t as f64, -0.1),
+                c64::new(1.0, -(a as f64)),
+            ])
+        });
+        CalibrationSolutions {
+            di_jones,
+            flagged_tiles: vec![1],
+            flagged_chanblocks: vec![3],
+            obsid: Some(1090008640),
+            start_timestamps: Some(vec![Epoch::from_gpst_seconds(1090008640.0)]),
+            end_timestamps: Some(vec![Epoch::from_gpst_seconds(1090008752.0)]),
+            tile_names: Some(vec!["Tile011".into(), "Tile012".into(), "Tile013".into()]),
+            chanblock_freqs: Some(vec![150e6, 150.04e6, 150.08e6, 150.12e6]),
+        }
+    }
+
+    /// Check that the unflagged solutions match, and the flagged ones are NaN.
+    fn check_solutions(read: &CalibrationSolutions, expected: &CalibrationSolutions) {
+        assert_eq!(read.di_jones.dim(), expected.di_jones.dim());
+        assert_eq!(read.flagged_tiles, expected.flagged_tiles);
+        assert_eq!(read.flagged_chanblocks, expected.flagged_chanblocks);
+        for ((_, tile, chanblock), jones) in read.di_jones.indexed_iter() {
+            if tile == 1 || chanblock == 3 {
+                assert!(jones.any_nan());
+            } else {
+                assert_abs_diff_eq!(*jones, expected.di_jones[(0, tile, chanblock)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_andre_binary_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sols.bin");
+        let sols = test_solutions();
+        sols.write(&path, None).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..8], AO_MAGIC);
+        assert_eq!(bytes.len(), AO_HEADER_SIZE + 3 * 4 * 8 * 8);
+
+        let read = CalibrationSolutions::read(&path).unwrap();
+        check_solutions(&read, &sols);
+        // The binary format only has times, and only to MJD-seconds precision.
+        assert_eq!(read.obsid, None);
+        assert_eq!(read.tile_names, None);
+        let start = read.start_timestamps.unwrap()[0];
+        assert_abs_diff_eq!(start.to_gpst_seconds(), 1090008640.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_bad_andre_binary() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sols.bin");
+        std::fs::write(&path, [0; AO_HEADER_SIZE]).unwrap();
+        assert!(matches!(
+            CalibrationSolutions::read(&path),
+            Err(CalSolError::BadAndreBinary { .. })
+        ));
+
+        // huge dimensions in the header of a truncated file.
+        test_solutions().write(&path, None).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        for dim in 0..3 {
+            bytes[16 + 4 * dim..20 + 4 * dim].copy_from_slice(&i32::MAX.to_le_bytes());
+        }
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            CalibrationSolutions::read(&path),
+            Err(CalSolError::BadAndreBinary { .. })
+        ));
+        // the file is one Jones matrix short.
+        test_solutions().write(&path, None).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 8 * 8]).unwrap();
+        assert!(matches!(
+            CalibrationSolutions::read(&path),
+            Err(CalSolError::BadAndreBinary { .. })
+        ));
+
+        assert!(matches!(
+            CalibrationSolutions::read(dir.path().join("sols.txt")),
+            Err(CalSolError::UnsupportedExtension { .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "cfitsio")]
+    fn test_hyperdrive_fits_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sols.fits");
+        let sols = test_solutions();
+        let history = History::new(Some("hyperdrive"), None, None).with_version("0.4.0");
+        sols.write(&path, Some(&history)).unwrap();
+
+        let mut fptr = fitsio::FitsFile::open(&path).unwrap();
+        let hdu = fptr.primary_hdu().unwrap();
+        let software: String = hdu.read_key(&mut fptr, "SOFTWARE").unwrap();
+        assert_eq!(software, "hyperdrive 0.4.0");
+
+        let read = CalibrationSolutions::read(&path).unwrap();
+        check_solutions(&read, &sols);
+        assert_eq!(read.obsid, sols.obsid);
+        assert_eq!(read.tile_names, sols.tile_names);
+        assert_eq!(read.chanblock_freqs, sols.chanblock_freqs);
+        assert_eq!(read.start_timestamps, sols.start_timestamps);
+        assert_eq!(read.end_timestamps, sols.end_timestamps);
+    }
+}
@@ -0,0 +1,560 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A pure-Rust implementation of the Dysco lossy compression codec for
+//! visibilities (Offringa 2016, A&A 595, A99).
+//!
+//! Each row (the visibilities of one baseline and timestep) is normalised by
+//! the RMS of each polarisation, and the real and imaginary parts are then
+//! quantised to a configurable number of bits with a non-linear quantiser,
+//! whose levels are the equal-probability quantiles of a (truncated) Gaussian.
+//! Weights are normalised by the maximum of the row and quantised linearly.
+//!
+//! The `MeasurementSetWriter` can quantise the visibilities and weights it
+//! writes with this codec (see `MeasurementSetWriter::set_dysco`). casacore's
+//! `DyscoStMan` can't be attached to a measurement set table through
+//! `rubbl_casatables`, so those columns are still stored uncompressed.
+//! Otherwise, this codec can be used to compress visibilities for caches and
+//! transfer.
+
+use super::error::DyscoError;
+use crate::Jones;
+
+/// The distribution that the quantiser of visibilities is optimised for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DyscoDistribution {
+    /// Equally-spaced levels.
+    Uniform,
+
+    /// Levels at the quantiles of a truncated Gaussian. This is best for
+    /// noise-dominated visibilities, like those of the MWA.
+    TruncatedGaussian,
+}
+
+/// The settings of a [`DyscoCodec`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DyscoConfig {
+    /// The number of bits of each real and imaginary part of a visibility.
+    pub data_bit_rate: u8,
+
+    /// The number of bits of each weight.
+    pub weight_bit_rate: u8,
+
+    /// The distribution the quantiser is optimised for.
+    pub distribution: DyscoDistribution,
+
+    /// The (normalised) value that visibilities are clipped to, in units of
+    /// the RMS of the row.
+    pub truncation: f64,
+}
+
+impl Default for DyscoConfig {
+    /// The defaults of casacore's `DyscoStMan`.
+    fn default() -> Self {
+        DyscoConfig {
+            data_bit_rate: 10,
+            weight_bit_rate: 12,
+            distribution: DyscoDistribution::TruncatedGaussian,
+            truncation: 2.5,
+        }
+    }
+}
+
+/// A row of compressed visibilities and weights.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DyscoRow {
+    /// The number of visibilities in the row.
+    pub num_vis: usize,
+
+    /// The RMS of each polarisation of the row.
+    pub scales: [f32; 4],
+
+    /// The largest weight magnitude in the row.
+    pub max_weight: f32,
+
+    /// The packed symbols of the visibilities, `[vis][pol][re, im]`.
+    pub data: Vec<u8>,
+
+    /// The packed symbols of the weights.
+    pub weights: Vec<u8>,
+}
+
+impl DyscoRow {
+    /// The size of this row when compressed \[bytes\].
+    pub fn compressed_size(&self) -> usize {
+        std::mem::size_of::<[f32; 5]>() + self.data.len() + self.weights.len()
+    }
+}
+
+/// A quantiser of values with a symmetric distribution.
+#[derive(Clone, Debug)]
+struct Quantiser {
+    /// The value of each symbol.
+    levels: Vec<f64>,
+
+    /// The boundaries between consecutive levels.
+    boundaries: Vec<f64>,
+}
+
+impl Quantiser {
+    /// Create a quantiser with `num_levels` levels between `-truncation` and
+    /// `truncation`.
+    fn new(num_levels: usize, distribution: DyscoDistribution, truncation: f64) -> Quantiser {
+        let levels: Vec<f64> = (0..num_levels)
+            .map(|k| {
+                let q = (k as f64 + 0.5) / num_levels as f64;
+                match distribution {
+                    DyscoDistribution::Uniform => truncation * (2.0 * q - 1.0),
+                    DyscoDistribution::TruncatedGaussian => {
+                        let low = norm_cdf(-truncation);
+                        let high = norm_cdf(truncation);
+                        norm_inv_cdf(low + q * (high - low))
+                    }
+                }
+            })
+            .collect();
+        let boundaries = levels.windows(2).map(|w| 0.5 * (w[0] + w[1])).collect();
+        Quantiser { levels, boundaries }
+    }
+
+    /// Get the symbol of the level nearest to `value`. Non-finite values have
+    /// the symbol one past the last level.
+    fn encode(&self, value: f64) -> u32 {
+        if value.is_finite() {
+            self.boundaries.partition_point(|&b| b < value) as u32
+        } else {
+            self.levels.len() as u32
+        }
+    }
+
+    fn decode(&self, symbol: u32) -> f64 {
+        self.levels
+            .get(symbol as usize)
+            .copied()
+            .unwrap_or(f64::NAN)
+    }
+}
+
+/// Compresses and decompresses rows of visibilities with the Dysco algorithm.
+#[derive(Clone, Debug)]
+pub struct DyscoCodec {
+    config: DyscoConfig,
+    quantiser: Quantiser,
+}
+
+impl DyscoCodec {
+    /// Create a new codec.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`DyscoError`] if either bit rate isn't between 2 and 16,
+    /// or the truncation isn't positive.
+    pub fn new(config: DyscoConfig) -> Result<DyscoCodec, DyscoError> {
+        for bits in [config.data_bit_rate, config.weight_bit_rate] {
+            if !(2..=16).contains(&bits) {
+                return Err(DyscoError::BadBitRate { bits });
+            }
+        }
+        if config.truncation.is_nan() || config.truncation <= 0.0 {
+            return Err(DyscoError::BadTruncation {
+                truncation: config.truncation,
+            });
+        }
+        // The last symbol is reserved for non-finite values.
+        let num_levels = (1 << config.data_bit_rate) - 1;
+        Ok(DyscoCodec {
+            config,
+            quantiser: Quantiser::new(num_levels, config.distribution, config.truncation),
+        })
+    }
+
+    /// The settings of this codec.
+    pub fn config(&self) -> DyscoConfig {
+        self.config
+    }
+
+    /// Compress a row of visibilities and their weights. The sign of a weight
+    /// (i.e. whether it's flagged) is kept.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`DyscoError::BadWeightsLength`] if `vis` and `weights`
+    /// have different lengths.
+    pub fn encode_row(&self, vis: &[Jones<f32>], weights: &[f32]) -> Result<DyscoRow, DyscoError> {
+        if vis.len() != weights.len() {
+            return Err(DyscoError::BadWeightsLength {
+                num_vis: vis.len(),
+                num_weights: weights.len(),
+            });
+        }
+        let mut scales = [0.0; 4];
+        for (pol, scale) in scales.iter_mut().enumerate() {
+            let (sum, count) = vis
+                .iter()
+                .flat_map(|j| [j[pol].re, j[pol].im])
+                .filter(|v| v.is_finite())
+                .fold((0.0, 0), |(sum, count), v| {
+                    (sum + f64::from(v).powi(2), count + 1)
+                });
+            if count > 0 {
+                *scale = (sum / f64::from(count)).sqrt() as f32;
+            }
+        }
+
+        let mut data = BitWriter::default();
+        for j in vis {
+            for (pol, &scale) in scales.iter().enumerate() {
+                for v in [j[pol].re, j[pol].im] {
+                    let normalised = if scale > 0.0 {
+                        f64::from(v / scale)
+                    } else {
+                        f64::from(v)
+                    };
+                    data.push(self.quantiser.encode(normalised), self.config.data_bit_rate);
+                }
+            }
+        }
+
+        let max_weight = weights
+            .iter()
+            .map(|w| w.abs())
+            .filter(|w| w.is_finite())
+            .fold(0.0, f32::max);
+        let magnitude_bits = self.config.weight_bit_rate - 1;
+        let max_symbol = (1 << magnitude_bits) - 1;
+        let mut weight_data = BitWriter::default();
+        for &w in weights {
+            let magnitude = if max_weight > 0.0 {
+                ((w.abs() / max_weight).min(1.0) * max_symbol as f32).round() as u32
+            } else {
+                0
+            };
+            let sign = u32::from(w.is_sign_negative());
+            weight_data.push(
+                magnitude | (sign << magnitude_bits),
+                self.config.weight_bit_rate,
+            );
+        }
+
+        Ok(DyscoRow {
+            num_vis: vis.len(),
+            scales,
+            max_weight,
+            data: data.finish(),
+            weights: weight_data.finish(),
+        })
+    }
+
+    /// Replace a row of visibilities and their weights with what they would
+    /// be after being compressed and decompressed.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`DyscoError::BadWeightsLength`] if `vis` and `weights`
+    /// have different lengths.
+    pub fn quantise_row(
+        &self,
+        vis: &mut [Jones<f32>],
+        weights: &mut [f32],
+    ) -> Result<(), DyscoError> {
+        let row = self.encode_row(vis, weights)?;
+        self.decode_row(&row, vis, weights)
+    }
+
+    /// Decompress a row into `vis` and `weights`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`DyscoError::BadRowLength`] if `vis` or `weights` don't
+    /// have the length of the compressed row, or [`DyscoError::Truncated`] if
+    /// the row doesn't have enough data.
+    pub fn decode_row(
+        &self,
+        row: &DyscoRow,
+        vis: &mut [Jones<f32>],
+        weights: &mut [f32],
+    ) -> Result<(), DyscoError> {
+        for received in [vis.len(), weights.len()] {
+            if received != row.num_vis {
+                return Err(DyscoError::BadRowLength {
+                    expected: row.num_vis,
+                    received,
+                });
+            }
+        }
+
+        let mut data = BitReader::new(&row.data);
+        for j in vis.iter_mut() {
+            for (pol, &scale) in row.scales.iter().enumerate() {
+                let scale = if scale > 0.0 { f64::from(scale) } else { 1.0 };
+                let re = self.quantiser.decode(data.pop(self.config.data_bit_rate)?);
+                let im = self.quantiser.decode(data.pop(self.config.data_bit_rate)?);
+                j[pol].re = (re * scale) as f32;
+                j[pol].im = (im * scale) as f32;
+            }
+        }
+
+        let magnitude_bits = self.config.weight_bit_rate - 1;
+        let max_symbol = (1 << magnitude_bits) - 1;
+        let mut weight_data = BitReader::new(&row.weights);
+        for w in weights.iter_mut() {
+            let symbol = weight_data.pop(self.config.weight_bit_rate)?;
+            let magnitude = (symbol & max_symbol) as f32 / max_symbol as f32 * row.max_weight;
+            *w = if symbol >> magnitude_bits == 1 {
+                -magnitude
+            } else {
+                magnitude
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Packs symbols into bytes, least-significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u32,
+    num_bits: u8,
+}
+
+impl BitWriter {
+    fn push(&mut self, symbol: u32, bits: u8) {
+        self.current |= symbol << self.num_bits;
+        self.num_bits += bits;
+        while self.num_bits >= 8 {
+            self.bytes.push(self.current as u8);
+            self.current >>= 8;
+            self.num_bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.num_bits > 0 {
+            self.bytes.push(self.current as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Unpacks symbols packed by a [`BitWriter`].
+struct BitReader<'a> {
+    bytes: std::slice::Iter<'a, u8>,
+    current: u32,
+    num_bits: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            bytes: bytes.iter(),
+            current: 0,
+            num_bits: 0,
+        }
+    }
+
+    fn pop(&mut self, bits: u8) -> Result<u32, DyscoError> {
+        while self.num_bits < bits {
+            let byte = self.bytes.next().ok_or(DyscoError::Truncated)?;
+            self.current |= u32::from(*byte) << self.num_bits;
+            self.num_bits += 8;
+        }
+        let symbol = self.current & ((1 << bits) - 1);
+        self.current >>= bits;
+        self.num_bits -= bits;
+        Ok(symbol)
+    }
+}
+
+/// The cumulative distribution function of the standard normal distribution.
+/// This uses the complementary error function of Numerical Recipes, which has
+/// a fractional error less than 1.2e-7.
+fn norm_cdf(x: f64) -> f64 {
+    let z = x.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = [
+        -1.265_512_23,
+        1.000_023_68,
+        0.374_091_96,
+        0.096_784_18,
+        -0.186_288_06,
+        0.278_868_07,
+        -1.135_203_98,
+        1.488_515_87,
+        -0.822_152_23,
+        0.170_872_77,
+    ]
+    .iter()
+    .rev()
+    .fold(0.0, |acc, &c| acc * t + c);
+    let erfc = t * (-z * z + poly).exp();
+    if x >= 0.0 {
+        1.0 - 0.5 * erfc
+    } else {
+        0.5 * erfc
+    }
+}
+
+/// The inverse of [`norm_cdf`], with Acklam's rational approximation (relative
+/// error less than 1.2e-9).
+fn norm_inv_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    let horner = |coeffs: &[f64], x: f64| coeffs.iter().fold(0.0, |acc, &c| acc * x + c);
+
+    let p_low = 0.02425;
+    if p < p_low {
+        let q = (-2.0 * p.ln()).sqrt();
+        horner(&C, q) / (horner(&D, q) * q + 1.0)
+    } else if p <= 1.0 - p_low {
+        let q = p - 0.5;
+        let r = q * q;
+        horner(&A, r) * q / (horner(&B, r) * r + 1.0)
+    } else {
+        let q = (-2.0 * (1.0 - p).ln()).sqrt();
+        -horner(&C, q) / (horner(&D, q) * q + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c32;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_norm_cdf() {
+        assert_abs_diff_eq!(norm_cdf(0.0), 0.5, epsilon = 1e-7);
+        assert_abs_diff_eq!(norm_cdf(1.0), 0.841_344_746, epsilon = 1e-7);
+        assert_abs_diff_eq!(norm_cdf(-2.5), 0.006_209_665, epsilon = 1e-7);
+        for p in [0.001, 0.01, 0.2, 0.5, 0.9, 0.999] {
+            assert_abs_diff_eq!(norm_cdf(norm_inv_cdf(p)), p, epsilon = 1e-7);
+        }
+    }
+
+    #[test]
+    fn test_quantiser() {
+        let q = Quantiser::new(3, DyscoDistribution::Uniform, 1.5);
+        assert_abs_diff_eq!(q.levels[..], [-1.0, 0.0, 1.0][..], epsilon = 1e-12);
+        assert_eq!(q.encode(-10.0), 0);
+        assert_eq!(q.encode(0.4), 1);
+        assert_eq!(q.encode(0.6), 2);
+        assert_eq!(q.encode(f64::NAN), 3);
+        assert!(q.decode(3).is_nan());
+
+        let q = Quantiser::new(1023, DyscoDistribution::TruncatedGaussian, 2.5);
+        assert_abs_diff_eq!(q.levels[511], 0.0, epsilon = 1e-9);
+        assert!(q.levels[0] > -2.5);
+        assert!(q.levels.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_codec_round_trip() {
+        assert!(matches!(
+            DyscoCodec::new(DyscoConfig {
+                data_bit_rate: 17,
+                ..DyscoConfig::default()
+            }),
+            Err(DyscoError::BadBitRate { bits: 17 })
+        ));
+
+        let codec = DyscoCodec::new(DyscoConfig::default()).unwrap();
+        // Pseudo-random noise.
+        let mut seed = 1_u32;
+        let mut noise = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        let mut vis: Vec<Jones<f32>> = (0..64)
+            .map(|_| {
+                Jones::from([
+                    noise(),
+                    noise(),
+                    noise(),
+                    noise(),
+                    noise(),
+                    noise(),
+                    noise(),
+                    noise(),
+                ])
+            })
+            .collect();
+        vis[3][1] = c32::new(f32::NAN, 0.0);
+        let mut weights: Vec<f32> = (0..64).map(|i| i as f32).collect();
+        weights[5] = -5.0;
+
+        let row = codec.encode_row(&vis, &weights).unwrap();
+        // 10 bits for each of 8 floats, and 12 bits for each weight.
+        assert_eq!(row.data.len(), 64 * 8 * 10 / 8);
+        assert_eq!(row.weights.len(), 64 * 12 / 8);
+        assert!(row.compressed_size() < std::mem::size_of_val(&vis[..]) / 2);
+
+        let mut decoded = vec![Jones::default(); 64];
+        let mut decoded_weights = vec![0.0; 64];
+        codec
+            .decode_row(&row, &mut decoded, &mut decoded_weights)
+            .unwrap();
+        assert!(decoded[3][1].re.is_nan());
+        decoded[3][1].re = 0.0;
+        vis[3][1].re = 0.0;
+        assert_abs_diff_eq!(decoded[..], vis[..], epsilon = 0.02);
+        assert_abs_diff_eq!(decoded_weights[..], weights[..], epsilon = 0.02);
+        assert!(decoded_weights[5] < 0.0);
+
+        assert!(matches!(
+            codec.decode_row(&row, &mut decoded[..2], &mut decoded_weights),
+            Err(DyscoError::BadRowLength {
+                expected: 64,
+                received: 2
+            })
+        ));
+        assert!(matches!(
+            codec.encode_row(&vis, &weights[..2]),
+            Err(DyscoError::BadWeightsLength {
+                num_vis: 64,
+                num_weights: 2
+            })
+        ));
+
+        // Quantising is the same as a round trip.
+        let mut quantised = vis.clone();
+        let mut quantised_weights = weights.clone();
+        codec
+            .quantise_row(&mut quantised, &mut quantised_weights)
+            .unwrap();
+        let row = codec.encode_row(&vis, &weights).unwrap();
+        codec
+            .decode_row(&row, &mut decoded, &mut decoded_weights)
+            .unwrap();
+        assert_eq!(quantised, decoded);
+        assert_eq!(quantised_weights, decoded_weights);
+    }
+}
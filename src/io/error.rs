@@ -39,9 +39,37 @@ pub enum MeasurementSetWriteError {
     #[error("cannot create directory, path={path} already exists and is not a directory")]
     NotADirectory { path: String },
 
+    /// An error when the channels can't be split into a spectral window per
+    /// coarse channel.
+    #[error("Can't split {num_chans} channels into spectral windows of {chans_per_spw} channels averaged by {avg_freq}")]
+    BadSpectralWindowChans {
+        /// The number of selected channels.
+        num_chans: usize,
+        /// The number of fine channels in each spectral window.
+        chans_per_spw: usize,
+        /// The frequency averaging factor.
+        avg_freq: usize,
+    },
+
+    /// An error when there isn't a coarse channel (or frequency) for each
+    /// spectral window.
+    #[error(
+        "Expected {num_spws} spectral windows, but got {received} coarse channels or frequencies"
+    )]
+    BadSpectralWindowCount {
+        /// The number of spectral windows.
+        num_spws: usize,
+        /// The number of coarse channels or frequencies given.
+        received: usize,
+    },
+
     #[error(transparent)]
     BadArrayShape(#[from] BadArrayShape),
 
+    /// An error from the Dysco codec.
+    #[error(transparent)]
+    Dysco(#[from] DyscoError),
+
     /// An IO error.
     #[error(transparent)]
     StdIo(#[from] std::io::Error),
@@ -71,6 +99,60 @@ pub enum UvfitsWriteError {
         total: usize,
     },
 
+    /// An error when the group order is changed after rows have been written.
+    #[error("The uvfits group order can only be set before any rows are written")]
+    GroupOrderAfterWrite,
+
+    /// An error when the fields are set after rows have been written, or more
+    /// than once.
+    #[error("The uvfits fields can only be set once, before any rows are written")]
+    FieldsAfterWrite,
+
+    /// An error when the IFs are set after rows have been written, or more
+    /// than once.
+    #[error("The uvfits IFs can only be set once, before any rows are written")]
+    IfsAfterWrite,
+
+    /// An error when the channels can't be split into IFs.
+    #[error("{num_chans} uvfits channels can't be split into {num_ifs} IFs")]
+    BadIfChans {
+        /// The number of channels.
+        num_chans: usize,
+        /// The number of IFs.
+        num_ifs: usize,
+    },
+
+    /// An error when there isn't a frequency offset for each IF.
+    #[error("Expected a frequency offset for each of {num_ifs} uvfits IFs, but got {received}")]
+    BadIfFreqs {
+        /// The number of IFs.
+        num_ifs: usize,
+        /// The number of frequency offsets given.
+        received: usize,
+    },
+
+    /// An error when visibilities are not given in time order.
+    #[error("uvfits rows must be written in time order, but {received} is not after {previous}")]
+    NonMonotonicTime {
+        /// The timestamp of the last rows written
+        previous: hifitime::Epoch,
+        /// The timestamp of the rows received
+        received: hifitime::Epoch,
+    },
+
+    /// An error when the baselines differ between chunks of visibilities.
+    #[error("The same baselines must be written for every timestep of a uvfits file")]
+    InconsistentBaselines,
+
+    /// An error when a uvfits file would have no rows.
+    #[error("A uvfits file needs at least one timestep and baseline, but got {num_timesteps} timesteps and {num_baselines} baselines")]
+    NoRows {
+        /// The number of timesteps.
+        num_timesteps: usize,
+        /// The number of baselines.
+        num_baselines: usize,
+    },
+
     /// An error associated with fitsio.
     #[error(transparent)]
     Fitsio(#[from] fitsio::errors::Error),
@@ -94,6 +176,352 @@ impl From<crate::io::uvfits::FitsioOrCStringError> for UvfitsWriteError {
     }
 }
 
+#[derive(Error, Debug)]
+#[cfg(feature = "cfitsio")]
+pub enum FitsIdiWriteError {
+    /// An error when trying to write to an unexpected row.
+    #[error("Tried to write to row number {row_num}, but only {num_rows} rows are expected")]
+    BadRowNum {
+        /// The row number (0-indexed)
+        row_num: usize,
+        /// Total number of rows expected.
+        num_rows: usize,
+    },
+
+    /// An error when less rows were written to the `UV_DATA` table than
+    /// expected.
+    #[error("Expected {total} FITS-IDI rows to be written, but only {current} were written")]
+    NotEnoughRowsWritten {
+        /// Number of rows written
+        current: usize,
+        /// Total number of rows expected.
+        total: usize,
+    },
+
+    /// An error when there are more antennas than FITS-IDI baselines can
+    /// encode.
+    #[error("FITS-IDI files can have at most 255 antennas, but {num_antennas} were given")]
+    TooManyAntennas {
+        /// The number of antennas given.
+        num_antennas: usize,
+    },
+
+    /// An error when a FITS-IDI file would have no rows.
+    #[error("A FITS-IDI file needs at least one timestep and baseline, but got {num_timesteps} timesteps and {num_baselines} baselines")]
+    NoRows {
+        /// The number of timesteps.
+        num_timesteps: usize,
+        /// The number of baselines.
+        num_baselines: usize,
+    },
+
+    /// An error associated with fitsio.
+    #[error(transparent)]
+    Fitsio(#[from] fitsio::errors::Error),
+
+    /// An error when converting a Rust string to a C string.
+    #[error(transparent)]
+    BadString(#[from] std::ffi::NulError),
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[cfg(feature = "cfitsio")]
+impl From<crate::io::uvfits::FitsioOrCStringError> for FitsIdiWriteError {
+    fn from(e: crate::io::uvfits::FitsioOrCStringError) -> Self {
+        match e {
+            super::uvfits::FitsioOrCStringError::Fitsio(e) => Self::Fitsio(e),
+            super::uvfits::FitsioOrCStringError::Nul(e) => Self::BadString(e),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[cfg(feature = "cfitsio")]
+pub enum MwafWriteError {
+    /// An error when the filename template has nowhere to put the gpubox
+    /// number.
+    #[error("MWAF filename template {template} doesn't contain \"%%\" for the gpubox number")]
+    InvalidTemplate {
+        /// The filename template.
+        template: String,
+    },
+
+    /// An error when the channels can't be split into the coarse channels.
+    #[error("{num_chans} channels can't be split into {num_coarse_chans} coarse channels of {num_fine_chans_per_coarse} fine channels")]
+    BadChanCount {
+        /// The number of channels in the flags.
+        num_chans: usize,
+        /// The number of coarse channels.
+        num_coarse_chans: usize,
+        /// The number of fine channels in each coarse channel.
+        num_fine_chans_per_coarse: usize,
+    },
+
+    /// An error when a baseline has an antenna outside the array.
+    #[error("Baseline ({ant1}, {ant2}) isn't in an array of {num_ants} antennas")]
+    BadBaseline {
+        /// The first antenna of the baseline.
+        ant1: usize,
+        /// The second antenna of the baseline.
+        ant2: usize,
+        /// The number of antennas in the array.
+        num_ants: usize,
+    },
+
+    #[error(transparent)]
+    BadArrayShape(#[from] BadArrayShape),
+
+    /// An error associated with fitsio.
+    #[error(transparent)]
+    Fitsio(#[from] fitsio::errors::Error),
+
+    /// An error when converting a Rust string to a C string.
+    #[error(transparent)]
+    BadString(#[from] std::ffi::NulError),
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[cfg(feature = "cfitsio")]
+impl From<crate::io::uvfits::FitsioOrCStringError> for MwafWriteError {
+    fn from(e: crate::io::uvfits::FitsioOrCStringError) -> Self {
+        match e {
+            super::uvfits::FitsioOrCStringError::Fitsio(e) => Self::Fitsio(e),
+            super::uvfits::FitsioOrCStringError::Nul(e) => Self::BadString(e),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[cfg(feature = "cfitsio")]
+pub enum MwafReadError {
+    /// An error when the MWAF version isn't one we know how to read.
+    #[error("MWAF file {path} has unsupported version {version}")]
+    UnsupportedVersion {
+        /// The path of the file.
+        path: std::path::PathBuf,
+        /// The version in the file.
+        version: String,
+    },
+
+    /// An error when the rows of the flag table don't make up whole scans.
+    #[error(
+        "MWAF file {path} has {num_rows} rows, which isn't a multiple of {num_baselines} baselines"
+    )]
+    BadRowCount {
+        /// The path of the file.
+        path: std::path::PathBuf,
+        /// The number of rows in the flag table.
+        num_rows: usize,
+        /// The number of baselines in each scan.
+        num_baselines: usize,
+    },
+
+    /// An error when an MWAF file doesn't match the observation.
+    #[error("MWAF file {path} has {key} {received}, but {expected} was expected")]
+    Mismatch {
+        /// The path of the file.
+        path: std::path::PathBuf,
+        /// The key that doesn't match.
+        key: &'static str,
+        /// The expected value.
+        expected: usize,
+        /// The value in the file.
+        received: usize,
+    },
+
+    /// An error when a baseline has an antenna outside the array.
+    #[error("Baseline ({ant1}, {ant2}) isn't in an array of {num_ants} antennas")]
+    BadBaseline {
+        /// The first antenna of the baseline.
+        ant1: usize,
+        /// The second antenna of the baseline.
+        ant2: usize,
+        /// The number of antennas in the array.
+        num_ants: usize,
+    },
+
+    #[error(transparent)]
+    BadArrayShape(#[from] BadArrayShape),
+
+    /// An error associated with fitsio.
+    #[error(transparent)]
+    Fitsio(#[from] fitsio::errors::Error),
+}
+
+#[derive(Error, Debug)]
+#[cfg(feature = "miriad")]
+pub enum MiriadWriteError {
+    /// An error when trying to write to an unexpected record.
+    #[error(
+        "Tried to write to record number {record_num}, but only {num_records} records are expected"
+    )]
+    BadRecordNum {
+        /// The record number (0-indexed)
+        record_num: usize,
+        /// Total number of records expected.
+        num_records: usize,
+    },
+
+    /// An error when less records were written than expected.
+    #[error("Expected {total} MIRIAD records to be written, but only {current} were written")]
+    NotEnoughRecordsWritten {
+        /// Number of records written
+        current: usize,
+        /// Total number of records expected.
+        total: usize,
+    },
+
+    /// An error when there are more antennas than MIRIAD baselines can encode.
+    #[error("MIRIAD datasets can have at most 2048 antennas, but {num_antennas} were given")]
+    TooManyAntennas {
+        /// The number of antennas given.
+        num_antennas: usize,
+    },
+
+    /// An error when a MIRIAD dataset would have no records.
+    #[error("A MIRIAD dataset needs at least one timestep and baseline, but got {num_timesteps} timesteps and {num_baselines} baselines")]
+    NoRecords {
+        /// The number of timesteps.
+        num_timesteps: usize,
+        /// The number of baselines.
+        num_baselines: usize,
+    },
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+/// All the errors that can occur when compressing with Dysco
+pub enum DyscoError {
+    #[error("Dysco bit rates must be between 2 and 16, but {bits} was given")]
+    BadBitRate { bits: u8 },
+
+    #[error("the Dysco truncation must be positive, but {truncation} was given")]
+    BadTruncation { truncation: f64 },
+
+    #[error("a compressed row has {expected} visibilities, but the output has {received}")]
+    BadRowLength { expected: usize, received: usize },
+
+    #[error(
+        "there must be a weight for each of {num_vis} visibilities, but {num_weights} were given"
+    )]
+    BadWeightsLength { num_vis: usize, num_weights: usize },
+
+    #[error("a compressed row doesn't have enough data")]
+    Truncated,
+}
+
+#[derive(Error, Debug)]
+/// All the errors that can occur when reading or writing calibration solutions
+pub enum CalSolError {
+    #[error("calibration solutions file {path} doesn't have a supported extension")]
+    UnsupportedExtension { path: std::path::PathBuf },
+
+    #[error("not an André Offringa calibration solutions file: {message}")]
+    BadAndreBinary { message: String },
+
+    #[error("the SOLUTIONS HDU has shape {shape:?}, but [timeblocks, tiles, chanblocks, 8] was expected")]
+    BadSolutionsShape { shape: Vec<usize> },
+
+    #[cfg(feature = "cfitsio")]
+    #[error(transparent)]
+    Fitsio(#[from] fitsio::errors::Error),
+
+    #[error(transparent)]
+    BadString(#[from] std::ffi::NulError),
+
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[cfg(feature = "cfitsio")]
+impl From<crate::io::uvfits::FitsioOrCStringError> for CalSolError {
+    fn from(e: crate::io::uvfits::FitsioOrCStringError) -> Self {
+        match e {
+            super::uvfits::FitsioOrCStringError::Fitsio(e) => Self::Fitsio(e),
+            super::uvfits::FitsioOrCStringError::Nul(e) => Self::BadString(e),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[cfg(feature = "zstd")]
+/// All the errors that can occur with zstd-compressed visibility containers
+pub enum ZstdVisError {
+    #[error("not a zstd visibility container, or it wasn't finalised")]
+    BadMagic,
+
+    #[error("unsupported zstd visibility container version {version}")]
+    UnsupportedVersion { version: u32 },
+
+    #[error("chunk has dimensions {received}, but the container expects {expected}")]
+    BadChunkShape { expected: String, received: String },
+
+    #[error("corrupt zstd visibility container index: {message}")]
+    BadIndex { message: String },
+
+    #[error("requested block {block_idx}, but the container only has {num_blocks} blocks")]
+    NoSuchBlock { block_idx: usize, num_blocks: usize },
+
+    #[error("block {block_idx} decompressed to {received} bytes, but {expected} were expected")]
+    BadBlockLength {
+        block_idx: usize,
+        expected: usize,
+        received: usize,
+    },
+
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+#[cfg(feature = "arrow")]
+/// All the errors that can occur when exporting visibilities to Arrow
+pub enum ArrowExportError {
+    #[error("bad array shape supplied to argument vis of vis_to_record_batches. expected {expected}, received {received}")]
+    BadArrayShape { expected: String, received: String },
+
+    #[error(transparent)]
+    Arrow(#[from] arrow::error::ArrowError),
+
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+#[cfg(feature = "mmap")]
+/// All the errors that can occur when reading a memory-mapped FITS file
+pub enum FitsMmapError {
+    #[error("couldn't parse the header of HDU {hdu_idx}: {message}")]
+    BadHeader { hdu_idx: usize, message: String },
+
+    #[error("requested HDU {hdu_idx}, but the file only has {num_hdus} HDUs")]
+    NoSuchHdu { hdu_idx: usize, num_hdus: usize },
+
+    #[error("HDU {hdu_idx} is not a two dimensional image (axes {naxes:?})")]
+    NotAnImage { hdu_idx: usize, naxes: Vec<usize> },
+
+    #[error("HDU {hdu_idx} has unsupported BITPIX {bitpix}")]
+    UnsupportedBitpix { hdu_idx: usize, bitpix: i64 },
+
+    #[error("output buffer has {received} elements, but the HDU has {expected}")]
+    BadBufferLength { expected: usize, received: usize },
+
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
 #[derive(Error, Debug)]
 #[allow(clippy::upper_case_acronyms)]
 /// All the errors that can occur in file io operations
@@ -118,10 +546,37 @@ pub enum IOError {
     /// Error derived from [`io::errors::UvfitsWriteError`]
     UvfitsWriteError(#[from] UvfitsWriteError),
 
+    #[error(transparent)]
+    #[cfg(feature = "cfitsio")]
+    /// Error derived from [`io::errors::FitsIdiWriteError`]
+    FitsIdiWriteError(#[from] FitsIdiWriteError),
+
+    #[error(transparent)]
+    #[cfg(feature = "miriad")]
+    /// Error derived from [`io::errors::MiriadWriteError`]
+    MiriadWriteError(#[from] MiriadWriteError),
+
+    #[error(transparent)]
+    #[cfg(feature = "mmap")]
+    /// Error derived from [`io::errors::FitsMmapError`]
+    FitsMmapError(#[from] FitsMmapError),
+
     #[error(transparent)]
     BadArrayShape(#[from] BadArrayShape),
 
     #[cfg(feature = "ms")]
     #[error(transparent)]
     Table(#[from] rubbl_casatables::TableError),
+
+    /// An error when an asynchronous writer is used after one of its writes
+    /// failed; that write's error has already been returned.
+    #[error("A previous write on the I/O thread failed, so nothing more can be written")]
+    AsyncWriteFailed,
+
+    /// An error when a FITS writer can't be moved to another thread.
+    #[cfg(feature = "cfitsio")]
+    #[error(
+        "cfitsio wasn't built to be reentrant, so FITS files can't be written on another thread"
+    )]
+    CfitsioNotReentrant,
 }
@@ -0,0 +1,934 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Module for writing the FITS-IDI (FITS Interferometry Data Interchange)
+//! format, as described in AIPS Memo 114. Unlike uvfits, the visibilities are
+//! stored in a binary table (`UV_DATA`), alongside the `ARRAY_GEOMETRY`,
+//! `FREQUENCY`, `SOURCE` and `ANTENNA` tables, which AIPS (`FITLD`) and difmap
+//! can read directly.
+
+use std::{
+    borrow::Cow,
+    ffi::CString,
+    path::{Path, PathBuf},
+};
+
+use erfa::{aliases::eraGst06a, constants::ERFA_DJM0};
+use fitsio::errors::check_status as fits_check_status;
+use fitsio_sys;
+use itertools::izip;
+use log::trace;
+
+use super::{
+    error::{BadArrayShape, FitsIdiWriteError, IOError},
+    uvfits::{
+        create_table, fits_write_comment, fits_write_double, fits_write_history, fits_write_int,
+        fits_write_string, write_col_dbl, write_col_flt, write_col_int, write_col_str,
+    },
+    VisWrite,
+};
+use crate::{
+    average_chunk_f64,
+    constants::VEL_C,
+    hifitime::{Duration, Epoch, Unit},
+    ndarray::{ArrayView3, Axis},
+    precession::{get_lmst, precess_time},
+    HADec, History, Jones, LatLngHeight, RADec, VisContext, WeightScaling, XyzGeodetic, UVW,
+};
+
+/// The number of floats per polarisation in the `FLUX` column; the real and
+/// imaginary parts, and the weight.
+const NUM_FLOATS_PER_POL: usize = 3;
+
+/// The number of polarisations written (XX, YY, XY, YX, as in uvfits).
+const NUM_POLS: usize = 4;
+
+/// The columns of the `UV_DATA` table, in order. The format of `FLUX` depends
+/// on the number of channels.
+const UV_DATA_COLUMNS: [(&str, &str); 11] = [
+    ("UU---SIN", "SECONDS"),
+    ("VV---SIN", "SECONDS"),
+    ("WW---SIN", "SECONDS"),
+    ("DATE", "DAYS"),
+    ("TIME", "DAYS"),
+    ("BASELINE", ""),
+    ("ARRAY", ""),
+    ("SOURCE", ""),
+    ("FREQID", ""),
+    ("INTTIM", "SECONDS"),
+    ("FLUX", "UNCALIB"),
+];
+
+/// Get the (one-indexed) number of a column of the `UV_DATA` table.
+fn uv_data_col(name: &str) -> i32 {
+    UV_DATA_COLUMNS
+        .iter()
+        .position(|&(n, _)| n == name)
+        .expect("column exists") as i32
+        + 1
+}
+
+/// The keywords shared by every FITS-IDI table.
+struct CommonKeys<'a> {
+    obs_code: &'a str,
+    num_chans: usize,
+    ref_freq_hz: f64,
+    chan_width_hz: f64,
+    ref_pixel: usize,
+}
+
+/// A helper struct to write out a FITS-IDI file.
+///
+/// Note: only a single source, band (spectral window) and array are
+/// supported, and the four linear polarisations are always written.
+pub struct FitsIdiWriter {
+    /// The path to the FITS-IDI file.
+    path: PathBuf,
+
+    /// The FITS file pointer. The current HDU is always `UV_DATA`.
+    fptr: *mut fitsio_sys::fitsfile,
+
+    /// A buffer for the `FLUX` of a row, so that we don't allocate every time
+    /// we write visibilities.
+    buffer: Vec<f32>,
+
+    /// The number of `UV_DATA` rows. This is equal to `num_timesteps` *
+    /// `num_baselines`.
+    total_num_rows: usize,
+
+    /// The number of `UV_DATA` rows that have currently been written.
+    current_num_rows: usize,
+
+    /// The normalisation of the weights given to `write_vis`, which is kept
+    /// when averaging.
+    weight_scaling: WeightScaling,
+
+    /// 0h UTC on the day of the first timestep; the `DATE` of every row, and
+    /// the epoch that `TIME`s are relative to.
+    ref_date: Epoch,
+
+    /// The integration time of each row \[seconds\].
+    int_time: f64,
+
+    /// The [`RADec`] where this observation is phased to
+    phase_centre: RADec,
+
+    /// Array Position [Latitude (radians), Longitude (radians), Height (m)]
+    array_pos: LatLngHeight,
+
+    /// The *unprecessed* positions of the antennas.
+    antenna_positions: Vec<XyzGeodetic>,
+
+    /// UT1 - UTC, a.k.a. DUT1.
+    dut1: Duration,
+
+    /// Are we going to write out precessed UVWs?
+    precess_uvws: bool,
+}
+
+impl FitsIdiWriter {
+    /// Create a new FITS-IDI file at the specified path, and write all of its
+    /// tables except the visibilities.
+    ///
+    /// This will destroy any existing file at that path.
+    ///
+    /// `num_timesteps`, `num_baselines` and `num_chans` are the number of
+    /// timesteps, baselines and channels in this file respectively. This is
+    /// counted after averaging.
+    ///
+    /// `start_epoch` is a [`hifitime::Epoch`] at the start of the first scan,
+    /// and `int_time` is the integration time of every timestep.
+    ///
+    /// `centre_freq_hz` is the centre frequency of the channel with (zero)
+    /// index `centre_freq_chan`, and `fine_chan_width_hz` is the width of the
+    /// channels.
+    ///
+    /// `phase_centre` is a [`RADec`] of the observation's phase centre, written
+    /// to the `SOURCE` table with the name `obs_name`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`FitsIdiWriteError`] if:
+    /// - there are more than 255 antennas, which FITS-IDI baselines can't
+    ///   encode.
+    /// - there are no timesteps or baselines.
+    /// - there is an existing file at `path` which cannot be removed.
+    /// - a fits operation fails.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<T: AsRef<Path>>(
+        path: T,
+        num_timesteps: usize,
+        num_baselines: usize,
+        num_chans: usize,
+        start_epoch: Epoch,
+        int_time: Duration,
+        fine_chan_width_hz: f64,
+        centre_freq_hz: f64,
+        centre_freq_chan: usize,
+        phase_centre: RADec,
+        obs_name: Option<&str>,
+        array_pos: LatLngHeight,
+        antenna_names: Vec<String>,
+        antenna_positions: Vec<XyzGeodetic>,
+        dut1: Duration,
+        precess_uvws: bool,
+        history: Option<&History>,
+    ) -> Result<FitsIdiWriter, FitsIdiWriteError> {
+        if antenna_names.len() > 255 {
+            return Err(FitsIdiWriteError::TooManyAntennas {
+                num_antennas: antenna_names.len(),
+            });
+        }
+        if num_timesteps == 0 || num_baselines == 0 {
+            return Err(FitsIdiWriteError::NoRows {
+                num_timesteps,
+                num_baselines,
+            });
+        }
+        let total_num_rows = num_timesteps * num_baselines;
+
+        let path = path.as_ref();
+        // Delete any file that already exists.
+        if path.exists() {
+            trace!("file {} exists, deleting", path.display());
+            std::fs::remove_file(path)?;
+        }
+
+        // Create a new fits file.
+        let mut status = 0;
+        let c_path = CString::new(path.to_str().unwrap())?;
+        let mut fptr = std::ptr::null_mut();
+        trace!("initialising fits file with fitsio_sys ({:?})", &path);
+        unsafe {
+            // ffinit = fits_create_file
+            fitsio_sys::ffinit(
+                &mut fptr,       /* O - FITS file pointer                   */
+                c_path.as_ptr(), /* I - name of file to create              */
+                &mut status,     /* IO - error status                       */
+            );
+            fits_check_status(status)?;
+            // An empty primary HDU. ffphps = fits_write_imghdr
+            fitsio_sys::ffphps(
+                fptr,                 /* I - FITS file pointer                   */
+                8,                    /* I - number of bits per data value pixel */
+                0,                    /* I - number of axes in the data array    */
+                std::ptr::null_mut(), /* I - length of each data axis            */
+                &mut status,          /* IO - error status                       */
+            );
+        }
+        fits_check_status(status)?;
+
+        // The primary header is the same as that of an (empty) random groups
+        // file.
+        fits_write_logical(fptr, "GROUPS", true)?;
+        fits_write_int(fptr, "GCOUNT", 0, None)?;
+        fits_write_int(fptr, "PCOUNT", 0, None)?;
+        fits_write_string(fptr, "CORRELAT", "MWA", None)?;
+        fits_write_string(fptr, "FXCORVER", "1", None)?;
+        fits_write_string(fptr, "OBJECT", obs_name.unwrap_or("Undefined"), None)?;
+        fits_write_string(fptr, "TELESCOP", "MWA", None)?;
+        fits_write_string(fptr, "INSTRUME", "MWA", None)?;
+        let history = history.cloned().unwrap_or_default();
+        for comment in &history.as_comments() {
+            fits_write_comment(fptr, comment)?;
+        }
+        for card in &history.as_history_cards(history.time_or_now()) {
+            fits_write_history(fptr, card)?;
+        }
+        fits_write_string(fptr, "SOFTWARE", &history.software(), None)?;
+
+        let ref_date = Epoch::from_jde_utc(start_epoch.to_jde_utc_days().floor() + 0.5);
+        let (year, month, day, _, _, _, _) = ref_date.to_gregorian_utc();
+        let rdate = format!("{year}-{month:02}-{day:02}");
+        let common = CommonKeys {
+            obs_code: obs_name.unwrap_or(""),
+            num_chans,
+            ref_freq_hz: centre_freq_hz,
+            chan_width_hz: fine_chan_width_hz,
+            ref_pixel: centre_freq_chan + 1,
+        };
+
+        // ARRAY_GEOMETRY
+        let array_xyz = array_pos.to_geocentric_wgs84();
+        create_table(
+            fptr,
+            "ARRAY_GEOMETRY",
+            &[
+                ("ANNAME", "8A", ""),
+                ("STABXYZ", "3D", "METERS"),
+                ("DERXYZ", "3E", "METERS/SEC"),
+                ("ORBPARM", "0D", ""),
+                ("NOSTA", "1J", ""),
+                ("MNTSTA", "1J", ""),
+                ("STAXOF", "3E", "METERS"),
+                ("DIAMETER", "1E", "METERS"),
+            ],
+        )?;
+        write_common_keys(fptr, &common, 1)?;
+        fits_write_int(fptr, "EXTVER", 1, None)?;
+        fits_write_string(fptr, "ARRNAM", "MWA", None)?;
+        fits_write_string(fptr, "FRAME", "GEOCENTRIC", None)?;
+        fits_write_double(fptr, "ARRAYX", array_xyz.x, None)?;
+        fits_write_double(fptr, "ARRAYY", array_xyz.y, None)?;
+        fits_write_double(fptr, "ARRAYZ", array_xyz.z, None)?;
+        fits_write_int(fptr, "NUMORB", 0, None)?;
+        fits_write_double(fptr, "FREQ", centre_freq_hz, None)?;
+        fits_write_string(fptr, "TIMSYS", "UTC", None)?;
+        fits_write_string(fptr, "RDATE", &rdate, None)?;
+        // Get the Greenwich apparent sidereal time from ERFA.
+        let mjd = ref_date.to_mjd_utc_days();
+        let gst = eraGst06a(ERFA_DJM0, mjd, ERFA_DJM0, mjd).to_degrees();
+        fits_write_double(fptr, "GSTIA0", gst, None)?;
+        fits_write_double(fptr, "DEGPDY", 3.60985e2, None)?; // Earth's rotation rate
+        fits_write_double(
+            fptr,
+            "UT1UTC",
+            dut1.to_seconds(),
+            Some("UT1 - UTC, a.k.a. DUT1"),
+        )?;
+        // TAI - UTC (the number of leap seconds) at the reference date.
+        let iat_utc = ref_date.leap_seconds(true).unwrap_or_default();
+        fits_write_double(fptr, "IATUTC", iat_utc, None)?;
+        fits_write_double(fptr, "POLARX", 0.0, None)?;
+        fits_write_double(fptr, "POLARY", 0.0, None)?;
+        for (i, (pos, name)) in antenna_positions
+            .iter()
+            .zip(antenna_names.iter())
+            .enumerate()
+        {
+            // Station positions are relative to the array centre, along the
+            // geocentric axes.
+            let xyz = pos.to_geocentric(array_pos);
+            write_col_str(fptr, 1, i, name)?;
+            write_col_dbl(
+                fptr,
+                2,
+                i,
+                &[
+                    xyz.x - array_xyz.x,
+                    xyz.y - array_xyz.y,
+                    xyz.z - array_xyz.z,
+                ],
+            )?;
+            write_col_flt(fptr, 3, i, &[0.0; 3])?;
+            write_col_int(fptr, 5, i, &[i as i32 + 1])?;
+            write_col_int(fptr, 6, i, &[0])?;
+            write_col_flt(fptr, 7, i, &[0.0; 3])?;
+            write_col_flt(fptr, 8, i, &[4.0])?;
+        }
+
+        // FREQUENCY
+        create_table(
+            fptr,
+            "FREQUENCY",
+            &[
+                ("FREQID", "1J", ""),
+                ("BANDFREQ", "1D", "HZ"),
+                ("CH_WIDTH", "1E", "HZ"),
+                ("TOTAL_BANDWIDTH", "1E", "HZ"),
+                ("SIDEBAND", "1J", ""),
+            ],
+        )?;
+        write_common_keys(fptr, &common, 2)?;
+        fits_write_int(fptr, "EXTVER", 1, None)?;
+        write_col_int(fptr, 1, 0, &[1])?;
+        write_col_dbl(fptr, 2, 0, &[0.0])?;
+        write_col_flt(fptr, 3, 0, &[fine_chan_width_hz as f32])?;
+        write_col_flt(
+            fptr,
+            4,
+            0,
+            &[(fine_chan_width_hz * num_chans as f64) as f32],
+        )?;
+        write_col_int(fptr, 5, 0, &[1])?;
+
+        // SOURCE
+        create_table(
+            fptr,
+            "SOURCE",
+            &[
+                ("SOURCE_ID", "1J", ""),
+                ("SOURCE", "16A", ""),
+                ("QUAL", "1J", ""),
+                ("CALCODE", "4A", ""),
+                ("FREQID", "1J", ""),
+                ("IFLUX", "1E", "JY"),
+                ("QFLUX", "1E", "JY"),
+                ("UFLUX", "1E", "JY"),
+                ("VFLUX", "1E", "JY"),
+                ("ALPHA", "1E", ""),
+                ("FREQOFF", "1D", "HZ"),
+                ("RAEPO", "1D", "DEGREES"),
+                ("DECEPO", "1D", "DEGREES"),
+                ("EQUINOX", "8A", ""),
+                ("RAAPP", "1D", "DEGREES"),
+                ("DECAPP", "1D", "DEGREES"),
+                ("SYSVEL", "1D", "M/SEC"),
+                ("VELTYP", "8A", ""),
+                ("VELDEF", "8A", ""),
+                ("RESTFREQ", "1D", "HZ"),
+                ("PMRA", "1D", "DEG/DAY"),
+                ("PMDEC", "1D", "DEG/DAY"),
+                ("PARALLAX", "1E", "ARCSEC"),
+                ("EPOCH", "1D", "YEARS"),
+            ],
+        )?;
+        write_common_keys(fptr, &common, 1)?;
+        fits_write_int(fptr, "EXTVER", 1, None)?;
+        write_col_int(fptr, 1, 0, &[1])?;
+        write_col_str(fptr, 2, 0, obs_name.unwrap_or("Undefined"))?;
+        write_col_int(fptr, 3, 0, &[0])?;
+        write_col_str(fptr, 4, 0, "")?;
+        write_col_int(fptr, 5, 0, &[1])?;
+        for col in 6..=10 {
+            write_col_flt(fptr, col, 0, &[0.0])?;
+        }
+        write_col_dbl(fptr, 11, 0, &[0.0])?;
+        write_col_dbl(fptr, 12, 0, &[phase_centre.ra.to_degrees()])?;
+        write_col_dbl(fptr, 13, 0, &[phase_centre.dec.to_degrees()])?;
+        write_col_str(fptr, 14, 0, "J2000")?;
+        // The apparent position isn't used by AIPS or difmap; the J2000
+        // position is written.
+        write_col_dbl(fptr, 15, 0, &[phase_centre.ra.to_degrees()])?;
+        write_col_dbl(fptr, 16, 0, &[phase_centre.dec.to_degrees()])?;
+        write_col_dbl(fptr, 17, 0, &[0.0])?;
+        write_col_str(fptr, 18, 0, "GEOCENTR")?;
+        write_col_str(fptr, 19, 0, "OPTICAL")?;
+        for col in 20..=22 {
+            write_col_dbl(fptr, col, 0, &[0.0])?;
+        }
+        write_col_flt(fptr, 23, 0, &[0.0])?;
+        write_col_dbl(fptr, 24, 0, &[2000.0])?;
+
+        // ANTENNA
+        create_table(
+            fptr,
+            "ANTENNA",
+            &[
+                ("TIME", "1D", "DAYS"),
+                ("TIME_INTERVAL", "1E", "DAYS"),
+                ("ANNAME", "8A", ""),
+                ("ANTENNA_NO", "1J", ""),
+                ("ARRAY", "1J", ""),
+                ("FREQID", "1J", ""),
+                ("NO_LEVELS", "1J", ""),
+                ("POLTYA", "1A", ""),
+                ("POLAA", "1E", "DEGREES"),
+                ("POLCALA", "0E", ""),
+                ("POLTYB", "1A", ""),
+                ("POLAB", "1E", "DEGREES"),
+                ("POLCALB", "0E", ""),
+            ],
+        )?;
+        write_common_keys(fptr, &common, 1)?;
+        fits_write_int(fptr, "EXTVER", 1, None)?;
+        fits_write_int(fptr, "NOPCAL", 0, None)?;
+        fits_write_string(fptr, "POLTYPE", "APPROX", None)?;
+        // The antenna properties are valid for the whole observation.
+        let duration = int_time * num_timesteps as i64;
+        let centre = ((start_epoch + duration * 0.5) - ref_date).to_unit(Unit::Day);
+        for (i, name) in antenna_names.iter().enumerate() {
+            write_col_dbl(fptr, 1, i, &[centre])?;
+            write_col_flt(fptr, 2, i, &[duration.to_unit(Unit::Day) as f32])?;
+            write_col_str(fptr, 3, i, name)?;
+            write_col_int(fptr, 4, i, &[i as i32 + 1])?;
+            write_col_int(fptr, 5, i, &[1])?;
+            write_col_int(fptr, 6, i, &[1])?;
+            write_col_int(fptr, 7, i, &[0])?;
+            write_col_str(fptr, 8, i, "X")?;
+            write_col_flt(fptr, 9, i, &[0.0])?;
+            write_col_str(fptr, 11, i, "Y")?;
+            write_col_flt(fptr, 12, i, &[90.0])?;
+        }
+
+        // UV_DATA. Rows are appended as visibilities are written.
+        let flux_format = format!("{}E", NUM_FLOATS_PER_POL * NUM_POLS * num_chans);
+        let columns: Vec<(&str, &str, &str)> = UV_DATA_COLUMNS
+            .iter()
+            .map(|&(name, unit)| {
+                let format = match name {
+                    "BASELINE" | "ARRAY" | "SOURCE" | "FREQID" => "1J",
+                    "FLUX" => flux_format.as_str(),
+                    _ => "1D",
+                };
+                (name, format, unit)
+            })
+            .collect();
+        create_table(fptr, "UV_DATA", &columns)?;
+        write_common_keys(fptr, &common, 2)?;
+        fits_write_int(fptr, "EXTVER", 1, None)?;
+        fits_write_string(fptr, "EQUINOX", "J2000", None)?;
+        fits_write_string(fptr, "WEIGHTYP", "NORMAL", None)?;
+        fits_write_string(fptr, "DATE-OBS", &rdate, None)?;
+        fits_write_string(fptr, "TELESCOP", "MWA", None)?;
+        fits_write_string(fptr, "OBSERVER", "", None)?;
+        fits_write_int(fptr, "NMATRIX", 1, None)?;
+        fits_write_int(fptr, "MAXIS", 6, None)?;
+        // The axes of the FLUX column.
+        for (i, (ctype, len, crval, cdelt, crpix)) in [
+            ("COMPLEX", NUM_FLOATS_PER_POL, 1.0, 1.0, 1.0),
+            ("STOKES", NUM_POLS, -5.0, -1.0, 1.0),
+            (
+                "FREQ",
+                num_chans,
+                centre_freq_hz,
+                fine_chan_width_hz,
+                common.ref_pixel as f64,
+            ),
+            ("BAND", 1, 1.0, 1.0, 1.0),
+            ("RA", 1, 0.0, 1.0, 1.0),
+            ("DEC", 1, 0.0, 1.0, 1.0),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let i = i + 1;
+            fits_write_int(fptr, &format!("MAXIS{i}"), len as i64, None)?;
+            fits_write_string(fptr, &format!("CTYPE{i}"), ctype, None)?;
+            fits_write_double(fptr, &format!("CDELT{i}"), cdelt, None)?;
+            fits_write_double(fptr, &format!("CRPIX{i}"), crpix, None)?;
+            fits_write_double(fptr, &format!("CRVAL{i}"), crval, None)?;
+        }
+        fits_write_logical(fptr, &format!("TMATX{}", uv_data_col("FLUX")), true)?;
+
+        Ok(FitsIdiWriter {
+            path: path.to_path_buf(),
+            fptr,
+            buffer: vec![0.0; NUM_FLOATS_PER_POL * NUM_POLS * num_chans],
+            total_num_rows,
+            current_num_rows: 0,
+            weight_scaling: WeightScaling::default(),
+            ref_date,
+            int_time: int_time.to_seconds(),
+            phase_centre,
+            array_pos,
+            antenna_positions,
+            dut1,
+            precess_uvws,
+        })
+    }
+
+    /// Create a new FITS-IDI file from a [`VisContext`]; see
+    /// [`FitsIdiWriter::new`].
+    ///
+    /// # Errors
+    ///
+    /// See [`FitsIdiWriter::new`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_marlu<T: AsRef<Path>>(
+        path: T,
+        vis_ctx: &VisContext,
+        array_pos: LatLngHeight,
+        phase_centre: RADec,
+        dut1: Duration,
+        obs_name: Option<&str>,
+        antenna_names: Vec<String>,
+        antenna_positions: Vec<XyzGeodetic>,
+        precess_uvws: bool,
+        history: Option<&History>,
+    ) -> Result<FitsIdiWriter, FitsIdiWriteError> {
+        let avg_freqs_hz: Vec<f64> = vis_ctx.avg_frequencies_hz();
+        let avg_centre_chan = avg_freqs_hz.len() / 2;
+        let avg_centre_freq_hz = avg_freqs_hz[avg_centre_chan];
+
+        Self::new(
+            path,
+            vis_ctx.num_avg_timesteps(),
+            vis_ctx.sel_baselines.len(),
+            vis_ctx.num_avg_chans(),
+            vis_ctx.start_timestamp,
+            vis_ctx.avg_int_time(),
+            vis_ctx.avg_freq_resolution_hz(),
+            avg_centre_freq_hz,
+            avg_centre_chan,
+            phase_centre,
+            obs_name,
+            array_pos,
+            antenna_names,
+            antenna_positions,
+            dut1,
+            precess_uvws,
+            history,
+        )
+    }
+
+    /// Set the normalisation of the weights given to `write_vis`. The written
+    /// weights have the same normalisation at the averaged resolution.
+    pub fn set_weight_scaling(&mut self, weight_scaling: WeightScaling) {
+        self.weight_scaling = weight_scaling;
+    }
+
+    /// Close this [`FitsIdiWriter`], checking that all of the rows have been
+    /// written.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`FitsIdiWriteError::NotEnoughRowsWritten`] if fewer rows
+    /// were written than expected, or an error if a fits operation fails.
+    pub fn close(&mut self) -> Result<(), FitsIdiWriteError> {
+        if self.current_num_rows != self.total_num_rows {
+            return Err(FitsIdiWriteError::NotEnoughRowsWritten {
+                current: self.current_num_rows,
+                total: self.total_num_rows,
+            });
+        }
+        trace!("closing fits file ({})", self.path.display());
+        let mut status = 0;
+        unsafe {
+            // ffclos = fits_close_file
+            fitsio_sys::ffclos(self.fptr, &mut status);
+        }
+        fits_check_status(status)?;
+        Ok(())
+    }
+}
+
+impl VisWrite for FitsIdiWriter {
+    fn write_vis(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        let sel_dims = vis_ctx.sel_dims();
+        for (argument, received) in [("vis", vis.dim()), ("weights", weights.dim())] {
+            if received != sel_dims {
+                return Err(IOError::BadArrayShape(BadArrayShape {
+                    argument,
+                    function: "FitsIdiWriter::write_vis",
+                    expected: format!("{sel_dims:?}"),
+                    received: format!("{received:?}"),
+                }));
+            }
+        }
+        let num_avg_rows = vis_ctx.num_avg_timesteps() * vis_ctx.sel_baselines.len();
+        if self.current_num_rows + num_avg_rows > self.total_num_rows {
+            return Err(FitsIdiWriteError::BadRowNum {
+                row_num: self.current_num_rows + num_avg_rows - 1,
+                num_rows: self.total_num_rows,
+            }
+            .into());
+        }
+
+        let mut avg_weight: f32;
+        let mut avg_flag: bool;
+        let mut avg_jones: Jones<f32>;
+        let date = self.ref_date.to_jde_utc_days();
+
+        for (avg_centroid_timestamp, jones_chunk, weight_chunk) in izip!(
+            vis_ctx.timeseries(true, true),
+            vis.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
+            weights.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
+        ) {
+            let time = (avg_centroid_timestamp - self.ref_date).to_unit(Unit::Day);
+
+            let (tile_xyzs, hadec): (Cow<[XyzGeodetic]>, HADec) = if self.precess_uvws {
+                let prec_info = precess_time(
+                    self.array_pos.longitude_rad,
+                    self.array_pos.latitude_rad,
+                    self.phase_centre,
+                    avg_centroid_timestamp,
+                    self.dut1,
+                );
+                (
+                    prec_info.precess_xyz(&self.antenna_positions).into(),
+                    prec_info.hadec_j2000,
+                )
+            } else {
+                let lmst = get_lmst(
+                    self.array_pos.longitude_rad,
+                    avg_centroid_timestamp,
+                    self.dut1,
+                );
+                let hadec = self.phase_centre.to_hadec(lmst);
+                (self.antenna_positions.as_slice().into(), hadec)
+            };
+
+            for ((ant1_idx, ant2_idx), jones_chunk, weight_chunk) in izip!(
+                vis_ctx.sel_baselines.iter().copied(),
+                jones_chunk.axis_iter(Axis(2)),
+                weight_chunk.axis_iter(Axis(2)),
+            ) {
+                let baseline_xyz = tile_xyzs[ant1_idx] - tile_xyzs[ant2_idx];
+                let uvw = UVW::from_xyz(baseline_xyz, hadec) / VEL_C;
+
+                // As in uvfits, the pols are written as XX,YY,XY,YX.
+                for (jones_chunk, weight_chunk, flux_chunk) in izip!(
+                    jones_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
+                    weight_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
+                    self.buffer.chunks_exact_mut(NUM_FLOATS_PER_POL * NUM_POLS),
+                ) {
+                    avg_weight = weight_chunk[[0, 0]];
+                    avg_jones = jones_chunk[[0, 0]];
+
+                    if !vis_ctx.trivial_averaging() {
+                        average_chunk_f64!(
+                            jones_chunk,
+                            weight_chunk,
+                            avg_jones,
+                            avg_weight,
+                            avg_flag
+                        );
+                        // The last chunks of timesteps and channels may be
+                        // short.
+                        avg_weight *= self.weight_scaling.averaging_correction(
+                            weight_chunk.len_of(Axis(0)),
+                            weight_chunk.len_of(Axis(1)),
+                        ) as f32;
+                    }
+
+                    flux_chunk.copy_from_slice(&[
+                        avg_jones[0].re,
+                        avg_jones[0].im,
+                        avg_weight,
+                        avg_jones[3].re,
+                        avg_jones[3].im,
+                        avg_weight,
+                        avg_jones[1].re,
+                        avg_jones[1].im,
+                        avg_weight,
+                        avg_jones[2].re,
+                        avg_jones[2].im,
+                        avg_weight,
+                    ]);
+                }
+
+                let row = self.current_num_rows;
+                let fptr = self.fptr;
+                let col = uv_data_col;
+                write_col_dbl(fptr, col("UU---SIN"), row, &[uvw.u])?;
+                write_col_dbl(fptr, col("VV---SIN"), row, &[uvw.v])?;
+                write_col_dbl(fptr, col("WW---SIN"), row, &[uvw.w])?;
+                write_col_dbl(fptr, col("DATE"), row, &[date])?;
+                write_col_dbl(fptr, col("TIME"), row, &[time])?;
+                write_col_int(
+                    fptr,
+                    col("BASELINE"),
+                    row,
+                    &[256 * (ant1_idx as i32 + 1) + ant2_idx as i32 + 1],
+                )?;
+                write_col_int(fptr, col("ARRAY"), row, &[1])?;
+                write_col_int(fptr, col("SOURCE"), row, &[1])?;
+                write_col_int(fptr, col("FREQID"), row, &[1])?;
+                write_col_dbl(fptr, col("INTTIM"), row, &[self.int_time])?;
+                write_col_flt(fptr, col("FLUX"), row, &self.buffer)?;
+                self.current_num_rows += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finalise(&mut self) -> Result<(), IOError> {
+        self.close()?;
+        Ok(())
+    }
+}
+
+/// Write the keywords that every FITS-IDI table has to the current HDU.
+fn write_common_keys(
+    fptr: *mut fitsio_sys::fitsfile,
+    common: &CommonKeys,
+    table_revision: i64,
+) -> Result<(), FitsIdiWriteError> {
+    fits_write_int(fptr, "TABREV", table_revision, None)?;
+    fits_write_string(fptr, "OBSCODE", common.obs_code, None)?;
+    fits_write_int(fptr, "NO_STKD", NUM_POLS as i64, None)?;
+    fits_write_int(fptr, "STK_1", -5, None)?;
+    fits_write_int(fptr, "NO_BAND", 1, None)?;
+    fits_write_int(fptr, "NO_CHAN", common.num_chans as i64, None)?;
+    fits_write_double(fptr, "REF_FREQ", common.ref_freq_hz, None)?;
+    fits_write_double(fptr, "CHAN_BW", common.chan_width_hz, None)?;
+    fits_write_double(fptr, "REF_PIXL", common.ref_pixel as f64, None)?;
+    Ok(())
+}
+
+fn fits_write_logical(
+    fptr: *mut fitsio_sys::fitsfile,
+    keyname: &str,
+    value: bool,
+) -> Result<(), FitsIdiWriteError> {
+    let mut status = 0;
+    let keyname = CString::new(keyname)?;
+    unsafe {
+        // ffukyl = fits_update_key_log
+        fitsio_sys::ffukyl(
+            fptr,             /* I - FITS file pointer  */
+            keyname.as_ptr(), /* I - keyword name       */
+            value.into(),     /* I - keyword value      */
+            std::ptr::null(), /* I - keyword comment    */
+            &mut status,      /* IO - error status      */
+        );
+    }
+    fits_check_status(status)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ndarray::Array3;
+    use approx::assert_abs_diff_eq;
+    use fitsio::FitsFile;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_fits_idi() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.fitsidi");
+
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: Epoch::from_gpst_seconds(1196175296.0),
+            int_time: Duration::from_seconds(2.0),
+            num_sel_chans: 3,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+        let antenna_positions = vec![
+            XyzGeodetic {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            XyzGeodetic {
+                x: 10.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            XyzGeodetic {
+                x: 0.0,
+                y: 20.0,
+                z: 5.0,
+            },
+        ];
+        let mut writer = FitsIdiWriter::from_marlu(
+            &path,
+            &vis_ctx,
+            LatLngHeight::mwa(),
+            RADec::from_degrees(0.0, -27.0),
+            Duration::default(),
+            Some("test"),
+            vec!["Tile011".into(), "Tile012".into(), "Tile013".into()],
+            antenna_positions,
+            true,
+            None,
+        )
+        .unwrap();
+
+        let vis = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            Jones::from([t as f32, c as f32, b as f32, 1.0, 2.0, 3.0, 4.0, 5.0])
+        });
+        let weights = Array3::from_elem(vis_ctx.sel_dims(), 8.0);
+        // Not all of the rows have been written yet.
+        assert!(matches!(
+            writer.close(),
+            Err(FitsIdiWriteError::NotEnoughRowsWritten {
+                current: 0,
+                total: 6
+            })
+        ));
+        writer
+            .write_vis(vis.view(), weights.view(), &vis_ctx)
+            .unwrap();
+        assert!(matches!(
+            writer.write_vis(vis.view(), weights.view(), &vis_ctx),
+            Err(IOError::FitsIdiWriteError(
+                FitsIdiWriteError::BadRowNum { .. }
+            ))
+        ));
+        writer.finalise().unwrap();
+
+        let mut fptr = FitsFile::open(&path).unwrap();
+        for extname in [
+            "ARRAY_GEOMETRY",
+            "FREQUENCY",
+            "SOURCE",
+            "ANTENNA",
+            "UV_DATA",
+        ] {
+            let hdu = fptr.hdu(extname).unwrap();
+            let no_chan: i64 = hdu.read_key(&mut fptr, "NO_CHAN").unwrap();
+            assert_eq!(no_chan, 3);
+        }
+
+        let hdu = fptr.hdu("ARRAY_GEOMETRY").unwrap();
+        let nosta: Vec<i32> = hdu.read_col(&mut fptr, "NOSTA").unwrap();
+        assert_eq!(nosta, [1, 2, 3]);
+
+        let hdu = fptr.hdu("UV_DATA").unwrap();
+        let maxis3: i64 = hdu.read_key(&mut fptr, "MAXIS3").unwrap();
+        assert_eq!(maxis3, 3);
+        let baselines: Vec<i32> = hdu.read_col(&mut fptr, "BASELINE").unwrap();
+        assert_eq!(baselines, [258, 259, 515, 258, 259, 515]);
+        let times: Vec<f64> = hdu.read_col(&mut fptr, "TIME").unwrap();
+        assert_abs_diff_eq!((times[3] - times[0]) * 86400.0, 2.0, epsilon = 1e-6);
+        let inttim: Vec<f64> = hdu.read_col(&mut fptr, "INTTIM").unwrap();
+        assert_abs_diff_eq!(inttim[0], 2.0);
+
+        // There have been 37 leap seconds since 2017.
+        let hdu = fptr.hdu("ARRAY_GEOMETRY").unwrap();
+        let iat_utc: f64 = hdu.read_key(&mut fptr, "IATUTC").unwrap();
+        assert_abs_diff_eq!(iat_utc, 37.0);
+    }
+
+    #[test]
+    fn test_no_rows() {
+        let dir = tempdir().unwrap();
+        for (num_timesteps, num_baselines) in [(0, 1), (1, 0)] {
+            let result = FitsIdiWriter::new(
+                dir.path().join("test.fitsidi"),
+                num_timesteps,
+                num_baselines,
+                1,
+                Epoch::from_gpst_seconds(1196175296.0),
+                Duration::from_seconds(1.0),
+                40e3,
+                150e6,
+                0,
+                RADec::from_degrees(0.0, -27.0),
+                None,
+                LatLngHeight::mwa(),
+                vec![String::new(); 2],
+                vec![XyzGeodetic::default(); 2],
+                Duration::default(),
+                false,
+                None,
+            );
+            assert!(matches!(result, Err(FitsIdiWriteError::NoRows { .. })));
+        }
+    }
+
+    #[test]
+    fn test_too_many_antennas() {
+        let dir = tempdir().unwrap();
+        let result = FitsIdiWriter::new(
+            dir.path().join("test.fitsidi"),
+            1,
+            1,
+            1,
+            Epoch::from_gpst_seconds(1196175296.0),
+            Duration::from_seconds(1.0),
+            40e3,
+            150e6,
+            0,
+            RADec::from_degrees(0.0, -27.0),
+            None,
+            LatLngHeight::mwa(),
+            vec![String::new(); 256],
+            vec![XyzGeodetic::default(); 256],
+            Duration::default(),
+            false,
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(FitsIdiWriteError::TooManyAntennas { num_antennas: 256 })
+        ));
+    }
+}
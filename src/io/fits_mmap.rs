@@ -0,0 +1,504 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Read-only, memory-mapped access to FITS files (e.g. gpubox and metafits
+//! files).
+//!
+//! cfitsio has a per-call overhead that dominates when ingesting many large
+//! image HDUs. This module instead maps the whole file into memory, parses
+//! just enough of each header to locate the HDU payloads, and exposes those
+//! payloads directly. Decoding from the FITS big-endian representation is
+//! left until the data is actually needed.
+//!
+//! Only the features of the FITS standard needed to locate payloads are
+//! supported; use cfitsio for anything more involved (e.g. compressed HDUs).
+
+use std::{fs::File, path::Path};
+
+use memmap2::Mmap;
+use ndarray::prelude::*;
+
+use super::error::FitsMmapError;
+
+/// The size of a FITS block \[bytes\]. Headers and data are padded to this.
+const FITS_BLOCK_SIZE: usize = 2880;
+/// The size of a FITS header card \[bytes\].
+const FITS_CARD_SIZE: usize = 80;
+
+/// The location and header of a single HDU within a [`MmapFits`].
+#[derive(Debug, Clone)]
+pub struct FitsHduInfo {
+    /// The header cards of this HDU as (keyword, value) pairs, in order.
+    /// String values have their quotes removed, and comments are discarded.
+    pub header: Vec<(String, String)>,
+
+    /// The number of bits per data value. Negative values are IEEE floats.
+    pub bitpix: i64,
+
+    /// The length of each data axis, in FITS order (`NAXIS1` first).
+    pub naxes: Vec<usize>,
+
+    /// The offset of the data from the start of the file \[bytes\].
+    data_offset: usize,
+
+    /// The length of the (unpadded) data \[bytes\].
+    data_len: usize,
+}
+
+impl FitsHduInfo {
+    /// Get the value of a header keyword, if it is present.
+    pub fn get(&self, keyword: &str) -> Option<&str> {
+        self.header
+            .iter()
+            .find(|(k, _)| k == keyword)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The number of bytes per data value.
+    pub fn bytes_per_value(&self) -> usize {
+        self.bitpix.unsigned_abs() as usize / 8
+    }
+}
+
+/// A memory-mapped FITS file.
+pub struct MmapFits {
+    mmap: Mmap,
+    hdus: Vec<FitsHduInfo>,
+}
+
+impl MmapFits {
+    /// Map the FITS file at `path` into memory, and locate all of its HDUs.
+    ///
+    /// # Errors
+    ///
+    /// Will return a [`FitsMmapError`] if the file can't be opened or mapped,
+    /// or if a header can't be parsed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FitsMmapError> {
+        let file = File::open(path.as_ref())?;
+        // Safety: the map is read-only. Like all memory maps, it is undefined
+        // behaviour for the file to be truncated or modified by another process
+        // while it is mapped; gpubox and metafits files are written once.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let hdus = parse_hdus(&mmap)?;
+        Ok(Self { mmap, hdus })
+    }
+
+    /// Information on each HDU in the file. HDU 0 is the primary HDU.
+    pub fn hdus(&self) -> &[FitsHduInfo] {
+        &self.hdus
+    }
+
+    fn hdu(&self, hdu_idx: usize) -> Result<&FitsHduInfo, FitsMmapError> {
+        self.hdus.get(hdu_idx).ok_or(FitsMmapError::NoSuchHdu {
+            hdu_idx,
+            num_hdus: self.hdus.len(),
+        })
+    }
+
+    /// The `BSCALE` and `BZERO` of an HDU (1 and 0 if they aren't present).
+    /// Physical values are `BZERO + BSCALE * (stored value)`.
+    pub fn scaling(&self, hdu_idx: usize) -> Result<(f64, f64), FitsMmapError> {
+        let hdu = self.hdu(hdu_idx)?;
+        let get_float = |key: &str, default: f64| match hdu.get(key) {
+            None => Ok(default),
+            // FITS allows a `D` exponent for double precision values.
+            Some(v) => v
+                .replace(['D', 'd'], "E")
+                .parse()
+                .map_err(|_| FitsMmapError::BadHeader {
+                    hdu_idx,
+                    message: format!("couldn't parse {key}={v} as a float"),
+                }),
+        };
+        Ok((get_float("BSCALE", 1.0)?, get_float("BZERO", 0.0)?))
+    }
+
+    /// The raw (big-endian) data payload of an HDU.
+    pub fn raw_data(&self, hdu_idx: usize) -> Result<&[u8], FitsMmapError> {
+        let hdu = self.hdu(hdu_idx)?;
+        Ok(&self.mmap[hdu.data_offset..hdu.data_offset + hdu.data_len])
+    }
+
+    /// The raw (big-endian) data payload of an image HDU as a two dimensional
+    /// view, where each row is the bytes of one `NAXIS1` row of the image;
+    /// i.e. the dimensions are `[NAXIS2 * NAXIS3 * ...][NAXIS1 * bytes per value]`.
+    pub fn raw_view(&self, hdu_idx: usize) -> Result<ArrayView2<u8>, FitsMmapError> {
+        let hdu = self.hdu(hdu_idx)?;
+        let data = self.raw_data(hdu_idx)?;
+        let row_len = hdu.naxes.first().copied().unwrap_or(0) * hdu.bytes_per_value();
+        let num_rows = if row_len == 0 {
+            0
+        } else {
+            data.len() / row_len
+        };
+        Ok(ArrayView2::from_shape((num_rows, row_len), data).expect("shape matches data length"))
+    }
+
+    /// Decode the data of an image HDU into `out`, which must have exactly as
+    /// many elements as the image. `BITPIX` -32 (float) and 32 (integer) are
+    /// supported, which covers MWA gpubox files. `BSCALE` and `BZERO` are
+    /// applied, as cfitsio does.
+    pub fn read_image_f32_into(
+        &self,
+        hdu_idx: usize,
+        out: &mut [f32],
+    ) -> Result<(), FitsMmapError> {
+        let hdu = self.hdu(hdu_idx)?;
+        let data = self.raw_data(hdu_idx)?;
+        let num_values = data.len() / hdu.bytes_per_value().max(1);
+        if out.len() != num_values {
+            return Err(FitsMmapError::BadBufferLength {
+                expected: num_values,
+                received: out.len(),
+            });
+        }
+        let (bscale, bzero) = self.scaling(hdu_idx)?;
+        let scaled = bscale != 1.0 || bzero != 0.0;
+        match hdu.bitpix {
+            -32 => {
+                for (o, chunk) in out.iter_mut().zip(data.chunks_exact(4)) {
+                    *o = f32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    if scaled {
+                        *o = (bzero + bscale * *o as f64) as f32;
+                    }
+                }
+            }
+            32 => {
+                // Scale in double precision, so large integers aren't rounded
+                // before they're scaled.
+                for (o, chunk) in out.iter_mut().zip(data.chunks_exact(4)) {
+                    let v = i32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    *o = (bzero + bscale * v as f64) as f32;
+                }
+            }
+            bitpix => return Err(FitsMmapError::UnsupportedBitpix { hdu_idx, bitpix }),
+        }
+        Ok(())
+    }
+
+    /// Decode a two dimensional image HDU into a new array with dimensions
+    /// `[NAXIS2][NAXIS1]`.
+    pub fn read_image_f32(&self, hdu_idx: usize) -> Result<Array2<f32>, FitsMmapError> {
+        let hdu = self.hdu(hdu_idx)?;
+        let shape = match hdu.naxes.as_slice() {
+            [naxis1, naxis2] => (*naxis2, *naxis1),
+            _ => {
+                return Err(FitsMmapError::NotAnImage {
+                    hdu_idx,
+                    naxes: hdu.naxes.clone(),
+                })
+            }
+        };
+        let mut result = Array2::zeros(shape);
+        self.read_image_f32_into(
+            hdu_idx,
+            result.as_slice_mut().expect("new arrays are contiguous"),
+        )?;
+        Ok(result)
+    }
+}
+
+/// Parse a single header card into a keyword and value.
+fn parse_card(card: &[u8]) -> (String, String) {
+    let card = String::from_utf8_lossy(card);
+    let keyword = card.get(..8).unwrap_or(&card).trim().to_string();
+    let value = match card.get(8..10) {
+        Some("= ") => {
+            let raw = card[10..].trim();
+            if let Some(quoted) = raw.strip_prefix('\'') {
+                // Quotes inside strings are escaped by doubling them.
+                let mut value = String::new();
+                let mut chars = quoted.chars().peekable();
+                while let Some(c) = chars.next() {
+                    if c == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    value.push(c);
+                }
+                value.trim_end().to_string()
+            } else {
+                raw.split('/').next().unwrap_or("").trim().to_string()
+            }
+        }
+        _ => String::new(),
+    };
+    (keyword, value)
+}
+
+fn parse_hdus(bytes: &[u8]) -> Result<Vec<FitsHduInfo>, FitsMmapError> {
+    let mut hdus = vec![];
+    let mut offset = 0;
+    while offset + FITS_BLOCK_SIZE <= bytes.len() {
+        let hdu_idx = hdus.len();
+        let mut header = vec![];
+        let mut found_end = false;
+        while offset + FITS_CARD_SIZE <= bytes.len() {
+            let (keyword, value) = parse_card(&bytes[offset..offset + FITS_CARD_SIZE]);
+            offset += FITS_CARD_SIZE;
+            if keyword == "END" {
+                found_end = true;
+                break;
+            }
+            if !keyword.is_empty() {
+                header.push((keyword, value));
+            }
+        }
+        if !found_end {
+            return Err(FitsMmapError::BadHeader {
+                hdu_idx,
+                message: "no END card".to_string(),
+            });
+        }
+        // Skip to the end of the header block.
+        offset = pad_to_block(offset);
+
+        let get_int = |key: &str| -> Result<Option<i64>, FitsMmapError> {
+            match header.iter().find(|(k, _)| k == key) {
+                None => Ok(None),
+                Some((_, v)) => v.parse().map(Some).map_err(|_| FitsMmapError::BadHeader {
+                    hdu_idx,
+                    message: format!("couldn't parse {key}={v} as an integer"),
+                }),
+            }
+        };
+        // Counts (e.g. axis lengths) can't be negative.
+        let get_count = |key: &str, default: usize| -> Result<usize, FitsMmapError> {
+            match get_int(key)? {
+                None => Ok(default),
+                Some(v) => usize::try_from(v).map_err(|_| FitsMmapError::BadHeader {
+                    hdu_idx,
+                    message: format!("{key}={v} is negative"),
+                }),
+            }
+        };
+        let bitpix = get_int("BITPIX")?.ok_or_else(|| FitsMmapError::BadHeader {
+            hdu_idx,
+            message: "missing BITPIX".to_string(),
+        })?;
+        let naxis = get_count("NAXIS", 0)?;
+        // The FITS standard allows at most 999 axes.
+        if naxis > 999 {
+            return Err(FitsMmapError::BadHeader {
+                hdu_idx,
+                message: format!("NAXIS={naxis} is more than 999"),
+            });
+        }
+        let mut naxes = Vec::with_capacity(naxis);
+        for i in 1..=naxis {
+            naxes.push(get_count(&format!("NAXIS{i}"), 0)?);
+        }
+        let pcount = get_count("PCOUNT", 0)?;
+        let gcount = get_count("GCOUNT", 1)?;
+
+        // Random groups have NAXIS1 = 0, which is excluded from the product.
+        let axes = match naxes.split_first() {
+            None => &naxes[..0],
+            Some((0, rest)) => rest,
+            Some(_) => &naxes[..],
+        };
+        let data_len = if naxes.is_empty() {
+            Some(0)
+        } else {
+            axes.iter()
+                .try_fold(1_usize, |product, &n| product.checked_mul(n))
+                .and_then(|num_values| num_values.checked_add(pcount))
+                .and_then(|n| n.checked_mul(gcount))
+                .and_then(|n| n.checked_mul(bitpix.unsigned_abs() as usize / 8))
+        };
+        let data_len = data_len
+            .filter(|&data_len| matches!(offset.checked_add(data_len), Some(end) if end <= bytes.len()))
+            .ok_or_else(|| FitsMmapError::BadHeader {
+                hdu_idx,
+                message: format!(
+                    "data (axes {naxes:?}, PCOUNT={pcount}, GCOUNT={gcount}, BITPIX={bitpix} at offset {offset}) extends beyond the end of the file ({} bytes)",
+                    bytes.len()
+                ),
+            })?;
+
+        hdus.push(FitsHduInfo {
+            header,
+            bitpix,
+            naxes,
+            data_offset: offset,
+            data_len,
+        });
+        offset = pad_to_block(offset + data_len);
+    }
+    Ok(hdus)
+}
+
+/// Round an offset up to the next FITS block boundary.
+fn pad_to_block(offset: usize) -> usize {
+    (offset + FITS_BLOCK_SIZE - 1) / FITS_BLOCK_SIZE * FITS_BLOCK_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn card(s: &str) -> Vec<u8> {
+        format!("{s:<80}").into_bytes()
+    }
+
+    fn pad(bytes: &mut Vec<u8>, fill: u8) {
+        bytes.resize(pad_to_block(bytes.len()), fill);
+    }
+
+    /// Write a primary HDU with no data, an image extension with a 3x2 float
+    /// image, and an image extension with a scaled 2x2 integer image.
+    fn synthesize_fits() -> NamedTempFile {
+        let mut bytes = vec![];
+        for c in [
+            "SIMPLE  =                    T",
+            "BITPIX  =                    8",
+            "NAXIS   =                    0",
+            "EXTEND  =                    T",
+            "OBJECT  = 'it''s a test'       / a comment",
+            "END",
+        ] {
+            bytes.extend(card(c));
+        }
+        pad(&mut bytes, b' ');
+        for c in [
+            "XTENSION= 'IMAGE   '",
+            "BITPIX  =                  -32",
+            "NAXIS   =                    2",
+            "NAXIS1  =                    3",
+            "NAXIS2  =                    2",
+            "PCOUNT  =                    0",
+            "GCOUNT  =                    1",
+            "END",
+        ] {
+            bytes.extend(card(c));
+        }
+        pad(&mut bytes, b' ');
+        for v in 0..6 {
+            bytes.extend((v as f32 * 1.5).to_be_bytes());
+        }
+        pad(&mut bytes, 0);
+        for c in [
+            "XTENSION= 'IMAGE   '",
+            "BITPIX  =                   32",
+            "NAXIS   =                    2",
+            "NAXIS1  =                    2",
+            "NAXIS2  =                    2",
+            "PCOUNT  =                    0",
+            "GCOUNT  =                    1",
+            "BSCALE  =                  0.5",
+            "BZERO   =               1.0D+1",
+            "END",
+        ] {
+            bytes.extend(card(c));
+        }
+        pad(&mut bytes, b' ');
+        for v in [-2_i32, 0, 3, 4] {
+            bytes.extend(v.to_be_bytes());
+        }
+        pad(&mut bytes, 0);
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&bytes).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_mmap_synthetic_fits() {
+        let file = synthesize_fits();
+        let fits = MmapFits::open(file.path()).unwrap();
+        assert_eq!(fits.hdus().len(), 3);
+
+        let primary = &fits.hdus()[0];
+        assert_eq!(primary.get("OBJECT"), Some("it's a test"));
+        assert_eq!(primary.get("NAXIS"), Some("0"));
+        assert!(fits.raw_data(0).unwrap().is_empty());
+
+        let image = &fits.hdus()[1];
+        assert_eq!(image.get("XTENSION"), Some("IMAGE"));
+        assert_eq!(image.naxes, vec![3, 2]);
+        assert_eq!(fits.raw_view(1).unwrap().dim(), (2, 12));
+        assert_eq!(
+            fits.read_image_f32(1).unwrap(),
+            array![[0.0, 1.5, 3.0], [4.5, 6.0, 7.5]]
+        );
+
+        assert_eq!(fits.scaling(2).unwrap(), (0.5, 10.0));
+        assert_eq!(
+            fits.read_image_f32(2).unwrap(),
+            array![[9.0, 10.0], [11.5, 12.0]]
+        );
+
+        assert!(matches!(
+            fits.raw_data(3),
+            Err(FitsMmapError::NoSuchHdu { hdu_idx: 3, .. })
+        ));
+        assert!(matches!(
+            fits.read_image_f32(0),
+            Err(FitsMmapError::NotAnImage { .. })
+        ));
+        let mut short = [0.0; 5];
+        assert!(matches!(
+            fits.read_image_f32_into(1, &mut short),
+            Err(FitsMmapError::BadBufferLength { .. })
+        ));
+    }
+
+    #[test]
+    fn test_mmap_bad_axes() {
+        for (naxis2, message) in [
+            ("-2", "NAXIS2=-2 is negative"),
+            ("9999999999", "beyond the end"),
+        ] {
+            let mut bytes = vec![];
+            let naxis2_card = format!("NAXIS2  = {naxis2:>20}");
+            for c in [
+                "SIMPLE  =                    T",
+                "BITPIX  =                  -32",
+                "NAXIS   =                    2",
+                "NAXIS1  =                    3",
+                naxis2_card.as_str(),
+                "END",
+            ] {
+                bytes.extend(card(c));
+            }
+            pad(&mut bytes, b' ');
+            bytes.extend([0; FITS_BLOCK_SIZE]);
+
+            let mut file = NamedTempFile::new().unwrap();
+            file.write_all(&bytes).unwrap();
+            match MmapFits::open(file.path()) {
+                Err(FitsMmapError::BadHeader {
+                    hdu_idx: 0,
+                    message: m,
+                }) => {
+                    assert!(m.contains(message), "{m}");
+                }
+                Err(e) => panic!("unexpected error {e}"),
+                Ok(_) => panic!("bad axes were accepted"),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "cfitsio")]
+    fn test_mmap_gpubox_matches_fitsio() {
+        let path = "tests/data/1196175296_mwa_ord/1196175296_20171201145440_gpubox01_00.fits";
+        let fits = MmapFits::open(path).unwrap();
+
+        let mut fptr = fitsio::FitsFile::open(path).unwrap();
+        let hdu = fptr.hdu(1).unwrap();
+        let expected: Vec<f32> = hdu.read_image(&mut fptr).unwrap();
+
+        let mut received = vec![0.0; expected.len()];
+        fits.read_image_f32_into(1, &mut received).unwrap();
+        assert_eq!(received, expected);
+    }
+}
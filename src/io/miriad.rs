@@ -0,0 +1,908 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Module for writing MIRIAD uv datasets.
+//!
+//! A MIRIAD dataset is a directory of "items". Small items live in the
+//! `header` file, and the visibilities are a stream of variable updates in
+//! `visdata`, described by `vartable`, with channel flags packed into `flags`.
+//! Each (timestep, baseline, polarisation) is a separate uv record. All values
+//! are big endian.
+//!
+//! MIRIAD has no per-visibility weights; visibilities with non-positive weights
+//! are flagged, and the magnitudes of weights are not written. Weights are
+//! still used to average visibilities, but a dataset can't be used to recover
+//! them (e.g. for imaging weights); use another format if they're needed.
+
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use itertools::izip;
+use log::trace;
+
+use super::{
+    error::{BadArrayShape, IOError, MiriadWriteError},
+    VisWrite,
+};
+use crate::{
+    average_chunk_f64,
+    constants::VEL_C,
+    hifitime::Duration,
+    ndarray::{ArrayView3, Axis},
+    pol::PolOrder,
+    precession::{get_lmst, precess_time},
+    HADec, History, Jones, LatLngHeight, RADec, VisContext, XyzGeodetic, UVW,
+};
+
+/// The size of the header of each entry of a uv stream.
+const UV_HDR_SIZE: usize = 4;
+
+/// The alignment of uv records.
+const UV_ALIGN: usize = 8;
+
+/// The kinds of entry in a uv stream.
+const VAR_SIZE: u8 = 0;
+const VAR_DATA: u8 = 1;
+const VAR_EOR: u8 = 2;
+
+/// The size of the name and length of a `header` item.
+const ITEM_HDR_SIZE: usize = 16;
+
+/// The number of flags packed into each integer of a flags item.
+const FLAG_BITS_PER_INT: usize = 31;
+
+/// The type prefixes of items.
+const INT_ITEM: [u8; 4] = [0, 0, 0, 2];
+const INT8_ITEM: [u8; 4] = [0, 0, 0, 8];
+const CHAR_ITEM: [u8; 4] = [0, 0, 0, 1];
+const BINARY_ITEM: [u8; 4] = [0, 0, 0, 0];
+
+/// The uv variables written, and their MIRIAD types (`a`: characters, `i`:
+/// 32-bit integers, `r`: 32-bit floats, `d`: 64-bit floats). The index of a
+/// variable is its position in this list (and in `vartable`).
+const UV_VARIABLES: [(&str, char); 27] = [
+    // The preamble.
+    ("coord", 'd'),
+    ("time", 'd'),
+    ("baseline", 'r'),
+    // Changes every record.
+    ("pol", 'i'),
+    ("corr", 'r'),
+    // Changes every timestep.
+    ("lst", 'd'),
+    ("ut", 'd'),
+    // Constant.
+    ("npol", 'i'),
+    ("nchan", 'i'),
+    ("nspect", 'i'),
+    ("ischan", 'i'),
+    ("nschan", 'i'),
+    ("sfreq", 'd'),
+    ("sdf", 'd'),
+    ("restfreq", 'd'),
+    ("freq", 'd'),
+    ("inttime", 'r'),
+    ("source", 'a'),
+    ("telescop", 'a'),
+    ("ra", 'd'),
+    ("dec", 'd'),
+    ("obsra", 'd'),
+    ("obsdec", 'd'),
+    ("epoch", 'r'),
+    ("latitud", 'd'),
+    ("longitu", 'd'),
+    ("nants", 'i'),
+    // "antpos" is added after these, as it's the only variable that isn't
+    // written once per record or less.
+];
+
+/// Get the index of a uv variable.
+fn var_index(name: &str) -> usize {
+    if name == "antpos" {
+        return UV_VARIABLES.len();
+    }
+    UV_VARIABLES
+        .iter()
+        .position(|&(n, _)| n == name)
+        .expect("variable exists")
+}
+
+/// Get the MIRIAD baseline number of two (zero-indexed) antennas. Like uvfits,
+/// antenna numbers start at 1, and more than 255 antennas use the 2048
+/// convention.
+fn encode_miriad_baseline(ant1: usize, ant2: usize) -> usize {
+    let (ant1, ant2) = (ant1 + 1, ant2 + 1);
+    if ant2 > 255 {
+        ant1 * 2048 + ant2 + 65_536
+    } else {
+        ant1 * 256 + ant2
+    }
+}
+
+/// The number of bytes needed to align `offset` to `align`.
+fn padding(offset: usize, align: usize) -> usize {
+    (align - offset % align) % align
+}
+
+/// A writer of the `visdata` uv stream.
+struct UvStream {
+    file: BufWriter<File>,
+
+    /// The current offset into the file \[bytes\].
+    offset: usize,
+
+    /// The lengths of the variables \[bytes\] last recorded in the stream.
+    lengths: Vec<Option<usize>>,
+}
+
+impl UvStream {
+    fn new(path: &Path) -> Result<UvStream, MiriadWriteError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&BINARY_ITEM)?;
+        file.write_all(&[0; UV_ALIGN - BINARY_ITEM.len()])?;
+        Ok(UvStream {
+            file,
+            offset: UV_ALIGN,
+            lengths: vec![None; UV_VARIABLES.len() + 1],
+        })
+    }
+
+    fn write_zeros(&mut self, n: usize) -> Result<(), MiriadWriteError> {
+        self.file.write_all(&vec![0; n])?;
+        self.offset += n;
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), MiriadWriteError> {
+        self.file.write_all(bytes)?;
+        self.offset += bytes.len();
+        Ok(())
+    }
+
+    /// Write the value of a variable, given as big-endian bytes of values of
+    /// size `align`.
+    fn write_var(
+        &mut self,
+        index: usize,
+        align: usize,
+        data: &[u8],
+    ) -> Result<(), MiriadWriteError> {
+        if self.lengths[index] != Some(data.len()) {
+            self.write_bytes(&[index as u8, 0, VAR_SIZE, 0])?;
+            self.write_bytes(&(data.len() as i32).to_be_bytes())?;
+            self.lengths[index] = Some(data.len());
+        }
+        self.write_bytes(&[index as u8, 0, VAR_DATA, 0])?;
+        self.write_zeros(padding(self.offset, align))?;
+        self.write_bytes(data)?;
+        self.write_zeros(padding(self.offset, UV_HDR_SIZE))
+    }
+
+    fn write_doubles(&mut self, name: &str, values: &[f64]) -> Result<(), MiriadWriteError> {
+        let data: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        self.write_var(var_index(name), 8, &data)
+    }
+
+    fn write_floats(&mut self, name: &str, values: &[f32]) -> Result<(), MiriadWriteError> {
+        let data: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        self.write_var(var_index(name), 4, &data)
+    }
+
+    fn write_ints(&mut self, name: &str, values: &[i32]) -> Result<(), MiriadWriteError> {
+        let data: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        self.write_var(var_index(name), 4, &data)
+    }
+
+    fn write_chars(&mut self, name: &str, value: &str) -> Result<(), MiriadWriteError> {
+        self.write_var(var_index(name), 1, value.as_bytes())
+    }
+
+    /// End the current record.
+    fn write_eor(&mut self) -> Result<(), MiriadWriteError> {
+        self.write_bytes(&[0, 0, VAR_EOR, 0])?;
+        self.write_zeros(padding(self.offset, UV_ALIGN))
+    }
+}
+
+/// A writer of packed flags.
+struct FlagStream {
+    file: BufWriter<File>,
+
+    /// Flags that haven't been written yet.
+    current: u32,
+
+    /// The number of flags in `current`.
+    num_bits: usize,
+}
+
+impl FlagStream {
+    fn new(path: &Path) -> Result<FlagStream, MiriadWriteError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&INT_ITEM)?;
+        Ok(FlagStream {
+            file,
+            current: 0,
+            num_bits: 0,
+        })
+    }
+
+    /// Write a flag; MIRIAD flags are `true` for good data.
+    fn push(&mut self, good: bool) -> Result<(), MiriadWriteError> {
+        if good {
+            self.current |= 1 << self.num_bits;
+        }
+        self.num_bits += 1;
+        if self.num_bits == FLAG_BITS_PER_INT {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write any partially-filled integer.
+    fn flush(&mut self) -> Result<(), MiriadWriteError> {
+        if self.num_bits > 0 {
+            self.file.write_all(&self.current.to_be_bytes())?;
+            self.current = 0;
+            self.num_bits = 0;
+        }
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// A helper struct to write out a MIRIAD uv dataset.
+///
+/// Note: only a single source and spectral window are supported, the four
+/// linear polarisations are always written, and weights are only written as
+/// flags (see the [module documentation](self)).
+pub struct MiriadWriter {
+    /// The path to the dataset (a directory).
+    path: PathBuf,
+
+    visdata: UvStream,
+
+    flags: FlagStream,
+
+    /// The number of uv records. This is equal to `num_timesteps` *
+    /// `num_baselines` * 4.
+    total_num_records: usize,
+
+    /// The number of uv records that have currently been written.
+    current_num_records: usize,
+
+    /// The number of channels in each record.
+    num_chans: usize,
+
+    /// A buffer for the `corr` of a record, so that we don't allocate every
+    /// time we write visibilities. The visibilities are `[chan][pol]`.
+    buffer: Vec<Jones<f32>>,
+
+    /// A buffer for the averaged weights, `[chan]`.
+    weight_buffer: Vec<f32>,
+
+    /// Values of variables that are written with the first record.
+    first_record: Option<FirstRecord>,
+
+    /// The [`RADec`] where this observation is phased to
+    phase_centre: RADec,
+
+    /// Array Position [Latitude (radians), Longitude (radians), Height (m)]
+    array_pos: LatLngHeight,
+
+    /// The *unprecessed* positions of the antennas.
+    antenna_positions: Vec<XyzGeodetic>,
+
+    /// UT1 - UTC, a.k.a. DUT1.
+    dut1: Duration,
+
+    /// Are we going to write out precessed UVWs?
+    precess_uvws: bool,
+}
+
+/// The values of the constant variables.
+struct FirstRecord {
+    start_freq_hz: f64,
+    freq_resolution_hz: f64,
+    centre_freq_hz: f64,
+    int_time: Duration,
+    source: String,
+}
+
+impl MiriadWriter {
+    /// Create a new MIRIAD uv dataset at the specified path (a directory).
+    ///
+    /// This will destroy any existing dataset at that path.
+    ///
+    /// `num_timesteps`, `num_baselines` and `num_chans` are the number of
+    /// timesteps, baselines and channels in this dataset respectively. This is
+    /// counted after averaging.
+    ///
+    /// `int_time` is the integration time of every timestep. `start_freq_hz`
+    /// is the centre frequency of the first channel, and `fine_chan_width_hz`
+    /// is the width of the channels.
+    ///
+    /// `phase_centre` is a [`RADec`] of the observation's phase centre, with
+    /// the source name `obs_name`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`MiriadWriteError`] if there are no timesteps or
+    /// baselines, there is an existing file at `path` which cannot be removed,
+    /// or the dataset can't be created.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<T: AsRef<Path>>(
+        path: T,
+        num_timesteps: usize,
+        num_baselines: usize,
+        num_chans: usize,
+        int_time: Duration,
+        fine_chan_width_hz: f64,
+        start_freq_hz: f64,
+        phase_centre: RADec,
+        obs_name: Option<&str>,
+        array_pos: LatLngHeight,
+        antenna_positions: Vec<XyzGeodetic>,
+        dut1: Duration,
+        precess_uvws: bool,
+        history: Option<&History>,
+    ) -> Result<MiriadWriter, MiriadWriteError> {
+        if antenna_positions.len() > 2048 {
+            return Err(MiriadWriteError::TooManyAntennas {
+                num_antennas: antenna_positions.len(),
+            });
+        }
+        if num_timesteps == 0 || num_baselines == 0 {
+            return Err(MiriadWriteError::NoRecords {
+                num_timesteps,
+                num_baselines,
+            });
+        }
+        let total_num_records = num_timesteps * num_baselines * 4;
+
+        let path = path.as_ref();
+        // Delete any dataset that already exists.
+        if path.is_dir() {
+            trace!("dataset {} exists, deleting", path.display());
+            std::fs::remove_dir_all(path)?;
+        } else if path.exists() {
+            trace!("file {} exists, deleting", path.display());
+            std::fs::remove_file(path)?;
+        }
+        std::fs::create_dir_all(path)?;
+
+        let mut vartable = String::new();
+        for (name, var_type) in UV_VARIABLES.iter().chain(&[("antpos", 'd')]) {
+            vartable.push_str(&format!("{var_type} {name}\n"));
+        }
+        std::fs::write(path.join("vartable"), vartable)?;
+
+        let history = history.cloned().unwrap_or_default();
+        let mut history_item = File::create(path.join("history"))?;
+        for line in history
+            .as_comments()
+            .into_iter()
+            .chain(history.as_history_cards(history.time_or_now()))
+        {
+            writeln!(history_item, "{line}")?;
+        }
+
+        Ok(MiriadWriter {
+            path: path.to_path_buf(),
+            visdata: UvStream::new(&path.join("visdata"))?,
+            flags: FlagStream::new(&path.join("flags"))?,
+            total_num_records,
+            current_num_records: 0,
+            num_chans,
+            buffer: vec![Jones::default(); num_chans],
+            weight_buffer: vec![0.0; num_chans],
+            first_record: Some(FirstRecord {
+                start_freq_hz,
+                freq_resolution_hz: fine_chan_width_hz,
+                centre_freq_hz: start_freq_hz + fine_chan_width_hz * (num_chans / 2) as f64,
+                int_time,
+                source: obs_name.unwrap_or("Undefined").to_string(),
+            }),
+            phase_centre,
+            array_pos,
+            antenna_positions,
+            dut1,
+            precess_uvws,
+        })
+    }
+
+    /// Create a new MIRIAD uv dataset from a [`VisContext`]; see
+    /// [`MiriadWriter::new`].
+    ///
+    /// # Errors
+    ///
+    /// See [`MiriadWriter::new`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_marlu<T: AsRef<Path>>(
+        path: T,
+        vis_ctx: &VisContext,
+        array_pos: LatLngHeight,
+        phase_centre: RADec,
+        dut1: Duration,
+        obs_name: Option<&str>,
+        antenna_positions: Vec<XyzGeodetic>,
+        precess_uvws: bool,
+        history: Option<&History>,
+    ) -> Result<MiriadWriter, MiriadWriteError> {
+        Self::new(
+            path,
+            vis_ctx.num_avg_timesteps(),
+            vis_ctx.sel_baselines.len(),
+            vis_ctx.num_avg_chans(),
+            vis_ctx.avg_int_time(),
+            vis_ctx.avg_freq_resolution_hz(),
+            vis_ctx.avg_frequencies_hz()[0],
+            phase_centre,
+            obs_name,
+            array_pos,
+            antenna_positions,
+            dut1,
+            precess_uvws,
+            history,
+        )
+    }
+
+    /// Write the variables that don't change.
+    fn write_constant_vars(&mut self, first: &FirstRecord) -> Result<(), MiriadWriteError> {
+        let num_chans = self.num_chans as i32;
+        let visdata = &mut self.visdata;
+        visdata.write_ints("npol", &[4])?;
+        visdata.write_ints("nchan", &[num_chans])?;
+        visdata.write_ints("nspect", &[1])?;
+        visdata.write_ints("ischan", &[1])?;
+        visdata.write_ints("nschan", &[num_chans])?;
+        // Frequencies are in GHz.
+        visdata.write_doubles("sfreq", &[first.start_freq_hz / 1e9])?;
+        visdata.write_doubles("sdf", &[first.freq_resolution_hz / 1e9])?;
+        visdata.write_doubles("restfreq", &[0.0])?;
+        visdata.write_doubles("freq", &[first.centre_freq_hz / 1e9])?;
+        visdata.write_floats("inttime", &[first.int_time.to_seconds() as f32])?;
+        visdata.write_chars("source", &first.source)?;
+        visdata.write_chars("telescop", "MWA")?;
+        visdata.write_doubles("ra", &[self.phase_centre.ra])?;
+        visdata.write_doubles("dec", &[self.phase_centre.dec])?;
+        visdata.write_doubles("obsra", &[self.phase_centre.ra])?;
+        visdata.write_doubles("obsdec", &[self.phase_centre.dec])?;
+        visdata.write_floats("epoch", &[2000.0])?;
+        visdata.write_doubles("latitud", &[self.array_pos.latitude_rad])?;
+        visdata.write_doubles("longitu", &[self.array_pos.longitude_rad])?;
+        visdata.write_ints("nants", &[self.antenna_positions.len() as i32])?;
+        // Antenna positions are in nanoseconds, all Xs, then all Ys, then all
+        // Zs.
+        let positions = &self.antenna_positions;
+        let antpos: Vec<f64> = positions
+            .iter()
+            .map(|p| p.x)
+            .chain(positions.iter().map(|p| p.y))
+            .chain(positions.iter().map(|p| p.z))
+            .map(|v| v / VEL_C * 1e9)
+            .collect();
+        visdata.write_doubles("antpos", &antpos)?;
+        Ok(())
+    }
+
+    /// Finish writing the dataset, checking that all of the records have been
+    /// written.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MiriadWriteError::NotEnoughRecordsWritten`] if fewer
+    /// records were written than expected, or an error if writing fails.
+    pub fn close(&mut self) -> Result<(), MiriadWriteError> {
+        if self.current_num_records != self.total_num_records {
+            return Err(MiriadWriteError::NotEnoughRecordsWritten {
+                current: self.current_num_records,
+                total: self.total_num_records,
+            });
+        }
+        trace!("closing miriad dataset ({})", self.path.display());
+        self.visdata.file.flush()?;
+        self.flags.flush()?;
+
+        let mut header = vec![];
+        for (name, value) in [
+            ("vislen", self.visdata.offset),
+            ("ncorr", self.total_num_records * self.num_chans),
+            ("nwcorr", 0),
+        ] {
+            // 64-bit integers are aligned to 8 bytes.
+            let mut data = INT8_ITEM.to_vec();
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&(value as i64).to_be_bytes());
+            write_header_item(&mut header, name, &data);
+        }
+        let mut data = CHAR_ITEM.to_vec();
+        data.extend_from_slice(b"crosscorrelation");
+        write_header_item(&mut header, "obstype", &data);
+        std::fs::write(self.path.join("header"), header)?;
+        Ok(())
+    }
+}
+
+/// Append a small item to the bytes of a `header`.
+fn write_header_item(header: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let mut entry = [0; ITEM_HDR_SIZE];
+    entry[..name.len()].copy_from_slice(name.as_bytes());
+    entry[ITEM_HDR_SIZE - 1] = data.len() as u8;
+    header.extend_from_slice(&entry);
+    header.extend_from_slice(data);
+    header.extend(std::iter::repeat(0).take(padding(header.len(), ITEM_HDR_SIZE)));
+}
+
+impl VisWrite for MiriadWriter {
+    fn write_vis(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        let sel_dims = vis_ctx.sel_dims();
+        for (argument, received) in [("vis", vis.dim()), ("weights", weights.dim())] {
+            if received != sel_dims {
+                return Err(IOError::BadArrayShape(BadArrayShape {
+                    argument,
+                    function: "MiriadWriter::write_vis",
+                    expected: format!("{sel_dims:?}"),
+                    received: format!("{received:?}"),
+                }));
+            }
+        }
+        let num_avg_records = vis_ctx.num_avg_timesteps() * vis_ctx.sel_baselines.len() * 4;
+        if self.current_num_records + num_avg_records > self.total_num_records {
+            return Err(MiriadWriteError::BadRecordNum {
+                record_num: self.current_num_records + num_avg_records - 1,
+                num_records: self.total_num_records,
+            }
+            .into());
+        }
+        if let Some(first) = self.first_record.take() {
+            self.write_constant_vars(&first)?;
+        }
+
+        let mut avg_weight: f32;
+        let mut avg_flag: bool;
+        let mut avg_jones: Jones<f32>;
+
+        for (avg_centroid_timestamp, jones_chunk, weight_chunk) in izip!(
+            vis_ctx.timeseries(true, true),
+            vis.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
+            weights.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
+        ) {
+            let lmst = get_lmst(
+                self.array_pos.longitude_rad,
+                avg_centroid_timestamp,
+                self.dut1,
+            );
+            let (tile_xyzs, hadec): (Cow<[XyzGeodetic]>, HADec) = if self.precess_uvws {
+                let prec_info = precess_time(
+                    self.array_pos.longitude_rad,
+                    self.array_pos.latitude_rad,
+                    self.phase_centre,
+                    avg_centroid_timestamp,
+                    self.dut1,
+                );
+                (
+                    prec_info.precess_xyz(&self.antenna_positions).into(),
+                    prec_info.hadec_j2000,
+                )
+            } else {
+                let hadec = self.phase_centre.to_hadec(lmst);
+                (self.antenna_positions.as_slice().into(), hadec)
+            };
+            let jd = avg_centroid_timestamp.to_jde_utc_days();
+            let ut = (jd - 0.5).fract() * std::f64::consts::TAU;
+            let mut new_timestep = true;
+
+            for ((ant1_idx, ant2_idx), jones_chunk, weight_chunk) in izip!(
+                vis_ctx.sel_baselines.iter().copied(),
+                jones_chunk.axis_iter(Axis(2)),
+                weight_chunk.axis_iter(Axis(2)),
+            ) {
+                let baseline_xyz = tile_xyzs[ant1_idx] - tile_xyzs[ant2_idx];
+                // MIRIAD UVWs are in nanoseconds.
+                let uvw = UVW::from_xyz(baseline_xyz, hadec) / VEL_C * 1e9;
+
+                for (jones_chunk, weight_chunk, jones, weight) in izip!(
+                    jones_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
+                    weight_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
+                    self.buffer.iter_mut(),
+                    self.weight_buffer.iter_mut(),
+                ) {
+                    avg_weight = weight_chunk[[0, 0]];
+                    avg_jones = jones_chunk[[0, 0]];
+
+                    if !vis_ctx.trivial_averaging() {
+                        average_chunk_f64!(
+                            jones_chunk,
+                            weight_chunk,
+                            avg_jones,
+                            avg_weight,
+                            avg_flag
+                        );
+                    }
+                    *jones = avg_jones;
+                    *weight = avg_weight;
+                }
+
+                // Each pol is a separate record.
+                for pol in PolOrder::Aips.pols() {
+                    let visdata = &mut self.visdata;
+                    visdata.write_doubles("coord", &[uvw.u, uvw.v, uvw.w])?;
+                    if new_timestep {
+                        visdata.write_doubles("time", &[jd])?;
+                        visdata.write_doubles("lst", &[lmst])?;
+                        visdata.write_doubles("ut", &[ut])?;
+                        new_timestep = false;
+                    }
+                    visdata.write_floats(
+                        "baseline",
+                        &[encode_miriad_baseline(ant1_idx, ant2_idx) as f32],
+                    )?;
+                    visdata.write_ints("pol", &[pol.aips_code()])?;
+                    let corr: Vec<f32> = self
+                        .buffer
+                        .iter()
+                        .flat_map(|j| {
+                            let c = j.get_pol(pol);
+                            [c.re, c.im]
+                        })
+                        .collect();
+                    visdata.write_floats("corr", &corr)?;
+                    visdata.write_eor()?;
+
+                    for &weight in &self.weight_buffer {
+                        self.flags.push(weight > 0.0)?;
+                    }
+                    self.current_num_records += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finalise(&mut self) -> Result<(), IOError> {
+        self.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hifitime::Epoch, ndarray::Array3};
+    use tempfile::tempdir;
+
+    /// Read the values of a variable in every record of a `visdata` stream.
+    fn read_var(visdata: &[u8], name: &str) -> Vec<Vec<u8>> {
+        let index = var_index(name);
+        let mut offset = UV_ALIGN;
+        let mut lengths = vec![0; UV_VARIABLES.len() + 1];
+        let mut values = vec![];
+        while offset < visdata.len() {
+            let header = &visdata[offset..offset + UV_HDR_SIZE];
+            offset += UV_HDR_SIZE;
+            let var = header[0] as usize;
+            match header[2] {
+                VAR_SIZE => {
+                    let bytes = visdata[offset..offset + 4].try_into().unwrap();
+                    lengths[var] = i32::from_be_bytes(bytes) as usize;
+                    offset += 4;
+                }
+                VAR_DATA => {
+                    let var_type = UV_VARIABLES.get(var).map_or('d', |&(_, t)| t);
+                    offset += padding(
+                        offset,
+                        match var_type {
+                            'a' => 1,
+                            'd' => 8,
+                            _ => 4,
+                        },
+                    );
+                    if var == index {
+                        values.push(visdata[offset..offset + lengths[var]].to_vec());
+                    }
+                    offset += lengths[var];
+                    offset += padding(offset, UV_HDR_SIZE);
+                }
+                VAR_EOR => offset += padding(offset, UV_ALIGN),
+                _ => panic!("bad uv entry {header:?}"),
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn test_write_miriad() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.uv");
+
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: Epoch::from_gpst_seconds(1196175296.0),
+            int_time: Duration::from_seconds(2.0),
+            num_sel_chans: 3,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+        let antenna_positions = vec![
+            XyzGeodetic {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            XyzGeodetic {
+                x: 10.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            XyzGeodetic {
+                x: 0.0,
+                y: 20.0,
+                z: 5.0,
+            },
+        ];
+        let mut writer = MiriadWriter::from_marlu(
+            &path,
+            &vis_ctx,
+            LatLngHeight::mwa(),
+            RADec::from_degrees(0.0, -27.0),
+            Duration::default(),
+            Some("test"),
+            antenna_positions,
+            true,
+            None,
+        )
+        .unwrap();
+
+        let vis = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| {
+            Jones::from([t as f32, c as f32, b as f32, 1.0, 2.0, 3.0, 4.0, 5.0])
+        });
+        let mut weights = Array3::from_elem(vis_ctx.sel_dims(), 8.0);
+        weights[(0, 1, 0)] = -8.0;
+        assert!(matches!(
+            writer.close(),
+            Err(MiriadWriteError::NotEnoughRecordsWritten {
+                current: 0,
+                total: 24
+            })
+        ));
+        writer
+            .write_vis(vis.view(), weights.view(), &vis_ctx)
+            .unwrap();
+        assert!(matches!(
+            writer.write_vis(vis.view(), weights.view(), &vis_ctx),
+            Err(IOError::MiriadWriteError(
+                MiriadWriteError::BadRecordNum { .. }
+            ))
+        ));
+        writer.finalise().unwrap();
+
+        let vartable = std::fs::read_to_string(path.join("vartable")).unwrap();
+        assert!(vartable.starts_with("d coord\nd time\nr baseline\n"));
+        assert!(vartable.ends_with("d antpos\n"));
+
+        let visdata = std::fs::read(path.join("visdata")).unwrap();
+        let header = std::fs::read(path.join("header")).unwrap();
+        assert_eq!(&header[..6], b"vislen");
+        assert_eq!(
+            i64::from_be_bytes(header[24..32].try_into().unwrap()),
+            visdata.len() as i64
+        );
+
+        let pols: Vec<i32> = read_var(&visdata, "pol")
+            .iter()
+            .map(|v| i32::from_be_bytes(v[..].try_into().unwrap()))
+            .collect();
+        assert_eq!(pols.len(), 24);
+        assert_eq!(&pols[..4], &[-5, -6, -7, -8]);
+
+        let baselines: Vec<f32> = read_var(&visdata, "baseline")
+            .iter()
+            .map(|v| f32::from_be_bytes(v[..].try_into().unwrap()))
+            .collect();
+        assert_eq!(baselines.len(), 24);
+        let first_baselines: Vec<f32> = baselines.iter().copied().step_by(4).collect();
+        assert_eq!(first_baselines, [258.0, 259.0, 515.0, 258.0, 259.0, 515.0]);
+        assert_eq!(read_var(&visdata, "time").len(), 2);
+        assert_eq!(read_var(&visdata, "antpos").len(), 1);
+
+        // The YY of the first baseline.
+        let corr: Vec<f32> = read_var(&visdata, "corr")[1]
+            .chunks_exact(4)
+            .map(|v| f32::from_be_bytes(v.try_into().unwrap()))
+            .collect();
+        assert_eq!(corr, [4.0, 5.0, 4.0, 5.0, 4.0, 5.0]);
+
+        // The second channel of the first baseline and timestep is flagged,
+        // for all 4 pols.
+        let flags = std::fs::read(path.join("flags")).unwrap();
+        assert_eq!(flags.len(), 4 + 3 * 4);
+        let first = u32::from_be_bytes(flags[4..8].try_into().unwrap());
+        assert_eq!(first, 0x7FFF_FFFF & !(1 << 1 | 1 << 4 | 1 << 7 | 1 << 10));
+    }
+
+    #[test]
+    fn test_weights_only_written_as_flags() {
+        let dir = tempdir().unwrap();
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp: Epoch::from_gpst_seconds(1196175296.0),
+            int_time: Duration::from_seconds(2.0),
+            num_sel_chans: 2,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+        let vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+
+        // Datasets whose weights only differ in magnitude are identical.
+        let mut datasets = vec![];
+        for weight in [1.0, 8.0] {
+            let path = dir.path().join(format!("{weight}.uv"));
+            let mut writer = MiriadWriter::from_marlu(
+                &path,
+                &vis_ctx,
+                LatLngHeight::mwa(),
+                RADec::from_degrees(0.0, -27.0),
+                Duration::default(),
+                None,
+                vec![XyzGeodetic::default(); 2],
+                false,
+                None,
+            )
+            .unwrap();
+            let mut weights = Array3::from_elem(vis_ctx.sel_dims(), weight);
+            weights[(0, 1, 0)] = -weight;
+            writer
+                .write_vis(vis.view(), weights.view(), &vis_ctx)
+                .unwrap();
+            writer.finalise().unwrap();
+            datasets.push(["visdata", "flags"].map(|item| std::fs::read(path.join(item)).unwrap()));
+        }
+        assert_eq!(datasets[0], datasets[1]);
+        // Only the second channel is flagged.
+        let flags = u32::from_be_bytes(datasets[0][1][4..8].try_into().unwrap());
+        assert_eq!(flags & 0b11, 0b01);
+    }
+
+    #[test]
+    fn test_no_records() {
+        let dir = tempdir().unwrap();
+        for (num_timesteps, num_baselines) in [(0, 1), (1, 0)] {
+            let result = MiriadWriter::new(
+                dir.path().join("test.uv"),
+                num_timesteps,
+                num_baselines,
+                1,
+                Duration::from_seconds(1.0),
+                40e3,
+                150e6,
+                RADec::from_degrees(0.0, -27.0),
+                None,
+                LatLngHeight::mwa(),
+                vec![XyzGeodetic::default(); 2],
+                Duration::default(),
+                false,
+                None,
+            );
+            assert!(matches!(result, Err(MiriadWriteError::NoRecords { .. })));
+        }
+    }
+}
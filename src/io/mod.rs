@@ -2,17 +2,29 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+pub mod calsol;
+pub mod dysco;
 pub mod error;
+pub mod write_async;
 use ndarray::prelude::*;
 
-use crate::{context::VisContext, Jones};
+use crate::{context::VisContext, flags::PolFlagPolicy, vis_array::VisArray, Jones};
+pub use calsol::CalibrationSolutions;
+pub use error::CalSolError;
 use error::IOError;
+#[cfg(feature = "cfitsio")]
+pub use write_async::ReentrantFitsWriter;
+pub use write_async::VisWriteAsync;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "cfitsio")] {
+        pub mod fits_idi;
+        pub mod mwaf;
         pub mod uvfits;
 
-        pub use error::UvfitsWriteError;
+        pub use error::{FitsIdiWriteError, MwafReadError, MwafWriteError, UvfitsWriteError};
+        pub use fits_idi::FitsIdiWriter;
+        pub use mwaf::{MwafFlags, MwafVersion, MwafWriter};
         pub use uvfits::UvfitsWriter;
     }
 }
@@ -22,7 +34,43 @@ cfg_if::cfg_if! {
         pub mod ms;
 
         pub use error::MeasurementSetWriteError;
-        pub use ms::MeasurementSetWriter;
+        pub use ms::{MeasurementSetWriter, MsDataColumn};
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "miriad")] {
+        pub mod miriad;
+
+        pub use error::MiriadWriteError;
+        pub use miriad::MiriadWriter;
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "zstd")] {
+        pub mod zstd_vis;
+
+        pub use error::ZstdVisError;
+        pub use zstd_vis::{ZstdVisReader, ZstdVisWriter};
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "arrow")] {
+        pub mod arrow_export;
+
+        pub use arrow_export::{vis_to_record_batches, write_vis_parquet};
+        pub use error::ArrowExportError;
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "mmap")] {
+        pub mod fits_mmap;
+
+        pub use error::FitsMmapError;
+        pub use fits_mmap::MmapFits;
     }
 }
 
@@ -49,6 +97,14 @@ pub trait VisWrite {
         vis_ctx: &VisContext,
     ) -> Result<(), IOError>;
 
+    /// Write a chunk of visibilities bundled with their weights and flags. See
+    /// [`VisWrite::write_vis`] and [`VisArray::writer_weights`]; a visibility
+    /// is flagged if any of its pols are flagged.
+    fn write_vis_array(&mut self, vis: &VisArray, vis_ctx: &VisContext) -> Result<(), IOError> {
+        let weights = vis.writer_weights(PolFlagPolicy::Any);
+        self.write_vis(vis.jones(), weights.view(), vis_ctx)
+    }
+
     /// When all visibilities have been given to this [`VisWrite`] implementor,
     /// calling this function will perform any remaining tasks before the writer
     /// can be dropped.
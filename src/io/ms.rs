@@ -7,14 +7,15 @@ use std::{
     f64::consts::FRAC_PI_2,
     ops::Range,
     path::{Path, PathBuf},
-    time::SystemTime,
 };
 
 use flate2::read::GzDecoder;
-use hifitime::{Duration, Unit};
-use itertools::izip;
+use hifitime::{Duration, Epoch, Unit};
+use itertools::{izip, Itertools};
 use lazy_static::lazy_static;
 use log::trace;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 use rubbl_casatables::{
     GlueDataType, Table, TableCreateMode, TableDesc, TableDescCreateMode, TableOpenMode,
     TableRecord,
@@ -22,17 +23,18 @@ use rubbl_casatables::{
 use tar::Archive;
 
 use super::{
-    error::{BadArrayShape, MeasurementSetWriteError},
+    dysco::{DyscoCodec, DyscoConfig},
+    error::{BadArrayShape, DyscoError, MeasurementSetWriteError},
     VisWrite,
 };
 use crate::{
     average_chunk_f64, c32,
     io::error::{IOError, MeasurementSetWriteError::MeasurementSetFull},
-    ndarray::{array, Array2, Array3, ArrayView, ArrayView3, Axis},
+    ndarray::{array, s, Array2, Array3, ArrayView, ArrayView2, ArrayView3, Axis},
     num_complex::Complex,
     precession::{get_lmst, precess_time},
-    HADec, History, Jones, LatLngHeight, MwaObsContext, ObsContext, RADec, VisContext, XyzGeodetic,
-    UVW,
+    FieldContext, HADec, History, Jones, LatLngHeight, MwaObsContext, ObsContext, RADec,
+    VisContext, Weather, WeightScaling, XyzGeodetic, UVW,
 };
 
 #[cfg(feature = "mwalib")]
@@ -47,6 +49,31 @@ lazy_static! {
 const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 
+/// A column of visibilities in the main table of a measurement set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MsDataColumn {
+    /// The observed visibilities.
+    #[default]
+    Data,
+
+    /// Model visibilities, e.g. predicted from a sky model for calibration.
+    ModelData,
+
+    /// Calibrated visibilities.
+    CorrectedData,
+}
+
+impl MsDataColumn {
+    /// The name of the column.
+    pub const fn name(self) -> &'static str {
+        match self {
+            MsDataColumn::Data => "DATA",
+            MsDataColumn::ModelData => "MODEL_DATA",
+            MsDataColumn::CorrectedData => "CORRECTED_DATA",
+        }
+    }
+}
+
 /// A helper struct to write out a CASA Measurement Set.
 pub struct MeasurementSetWriter {
     /// The path to the root of the measurement set (typically ends in .ms)
@@ -72,6 +99,89 @@ pub struct MeasurementSetWriter {
 
     /// Are we going to write out precessed UVWs?
     precess_uvws: bool,
+
+    /// The normalisation of the weights given to `write_vis`, which is kept
+    /// when averaging.
+    weight_scaling: WeightScaling,
+
+    /// If main table rows are appended as visibilities are written (see
+    /// [`MeasurementSetWriter::set_streaming`]), what has been written so far.
+    streaming: Option<StreamingState>,
+
+    /// If each coarse channel gets its own spectral window (see
+    /// [`MeasurementSetWriter::set_coarse_chan_spws`]), the number of
+    /// (unaveraged) fine channels per coarse channel.
+    coarse_chan_spws: Option<usize>,
+
+    /// The centre frequency \[Hz\] of the first (unaveraged) fine channel of
+    /// each spectral window (see
+    /// [`MeasurementSetWriter::set_spw_start_freqs_hz`]), if the spectral
+    /// windows aren't contiguous.
+    spw_start_freqs_hz: Option<Vec<f64>>,
+
+    /// The fields of a multi-field observation (see
+    /// [`MeasurementSetWriter::set_fields`]). If this is empty, everything is
+    /// phased to `phase_centre`.
+    fields: Vec<FieldContext>,
+
+    /// The columns of visibilities created by `initialize`, other than `DATA`.
+    extra_data_columns: Vec<MsDataColumn>,
+
+    /// The column that visibilities are written to.
+    data_column: MsDataColumn,
+
+    /// Whether the `WEIGHT_SPECTRUM` column is written (see
+    /// [`MeasurementSetWriter::set_weight_spectrum`]).
+    weight_spectrum: bool,
+
+    /// Whether the `POINTING`, `WEATHER` and `SYSCAL` tables are populated
+    /// (see [`MeasurementSetWriter::set_extra_subtables`]).
+    extra_subtables: bool,
+
+    /// The weather written to the `WEATHER` table (see
+    /// [`MeasurementSetWriter::set_weather`]).
+    weather: Option<Weather>,
+
+    /// The codec that visibilities and weights are quantised with before
+    /// they're written (see [`MeasurementSetWriter::set_dysco`]).
+    dysco: Option<DyscoCodec>,
+}
+
+/// What has been written to a measurement set whose main table rows are
+/// appended as visibilities are written.
+#[derive(Debug, Default)]
+struct StreamingState {
+    /// The time \[MJD UTC seconds\], field index and scan number of the last
+    /// timestep written.
+    last_timestep: Option<(f64, usize, i32)>,
+
+    /// The start and end \[MJD UTC seconds\] of all of the timesteps written.
+    time_range: Option<(f64, f64)>,
+}
+
+impl StreamingState {
+    /// Record a timestep with centroid `time` and length `interval`
+    /// \[seconds\], phased to the field with index `field_idx`, and get its
+    /// scan number. A new scan is started whenever there's a gap between
+    /// timesteps, or the field changes.
+    fn push_timestep(&mut self, time: f64, interval: f64, field_idx: usize) -> i32 {
+        let scan_number = match self.last_timestep {
+            Some((last_time, last_field_idx, scan_number))
+                if time - last_time <= 1.5 * interval && field_idx == last_field_idx =>
+            {
+                scan_number
+            }
+            Some((_, _, scan_number)) => scan_number + 1,
+            None => 1,
+        };
+        self.last_timestep = Some((time, field_idx, scan_number));
+        let (start, end) = (time - interval / 2., time + interval / 2.);
+        self.time_range = Some(match self.time_range {
+            Some((range_start, range_end)) => (range_start.min(start), range_end.max(end)),
+            None => (start, end),
+        });
+        scan_number
+    }
 }
 
 impl MeasurementSetWriter {
@@ -91,9 +201,202 @@ impl MeasurementSetWriter {
             antenna_positions,
             dut1,
             precess_uvws,
+            weight_scaling: WeightScaling::default(),
+            streaming: None,
+            coarse_chan_spws: None,
+            spw_start_freqs_hz: None,
+            fields: vec![],
+            extra_data_columns: vec![],
+            data_column: MsDataColumn::Data,
+            weight_spectrum: true,
+            extra_subtables: false,
+            weather: None,
+            dysco: None,
+        }
+    }
+
+    /// Append main table rows as each chunk of visibilities is written, rather
+    /// than allocating all of them in `initialize`. This must be set before
+    /// `initialize` is called.
+    ///
+    /// The total number of timesteps then doesn't need to be known up front.
+    /// The rows of each averaged timestep are added just before they're
+    /// written, and the table is flushed when `write_vis` returns, so if
+    /// writing stops (e.g. `write_vis` returns an error, or the process is
+    /// killed between calls) the table has the rows of the timesteps that were
+    /// written, plus at most one timestep of incomplete rows. Scan numbers start at
+    /// 1, and increase whenever there's a gap between timesteps or the field
+    /// changes (see [`MeasurementSetWriter::set_fields`]). `finalise`
+    /// sets the time range of the OBSERVATION table to that of the timesteps
+    /// that were written.
+    pub fn set_streaming(&mut self, streaming: bool) {
+        self.streaming = streaming.then(StreamingState::default);
+    }
+
+    /// Write a SPECTRAL_WINDOW row (and DATA_DESCRIPTION row) per coarse
+    /// channel, rather than a single band of all of the channels. This must be
+    /// set before `initialize` is called.
+    ///
+    /// `num_fine_chans_per_coarse` is the number of fine channels in each coarse
+    /// channel, before averaging; this must be a multiple of the frequency
+    /// averaging factor, and divide the number of selected channels. Each
+    /// timestep and baseline then has a main table row per coarse channel, with
+    /// a `DATA_DESC_ID` of the coarse channel's index into the selection.
+    /// `None` writes a single spectral window (the default).
+    pub fn set_coarse_chan_spws(&mut self, num_fine_chans_per_coarse: Option<usize>) {
+        self.coarse_chan_spws = num_fine_chans_per_coarse;
+    }
+
+    /// Set the centre frequency \[Hz\] of the first (unaveraged) fine channel
+    /// of each spectral window written with
+    /// [`MeasurementSetWriter::set_coarse_chan_spws`]. This must be set before
+    /// `initialize` is called.
+    ///
+    /// By default, the spectral windows are contiguous, starting at the
+    /// `start_freq_hz` of the [`VisContext`], which isn't the case for a
+    /// selection of coarse channels with gaps (e.g. a picket fence
+    /// observation). `initialize_mwa` places each spectral window at its coarse
+    /// channel's receiver channel number if this isn't set. There must be a
+    /// frequency per spectral window.
+    pub fn set_spw_start_freqs_hz(&mut self, spw_start_freqs_hz: Option<Vec<f64>>) {
+        self.spw_start_freqs_hz = spw_start_freqs_hz;
+    }
+
+    /// Write a FIELD row (and SOURCE row) per field, and phase each timestep to
+    /// its field (see [`FieldContext::index_at`]), rather than phasing
+    /// everything to the phase centre given to [`MeasurementSetWriter::new`].
+    /// The `FIELD_ID` of each main table row is the index of its field. This
+    /// must be set before `initialize` is called.
+    ///
+    /// `fields` must be sorted by start time. The visibilities given to
+    /// `write_vis` must already be phased to the field of their timestep.
+    pub fn set_fields(&mut self, fields: Vec<FieldContext>) {
+        self.fields = fields;
+    }
+
+    /// Create these columns of visibilities (e.g. `MODEL_DATA`) in
+    /// `initialize`, as well as `DATA`. This must be set before `initialize`
+    /// is called.
+    pub fn set_extra_data_columns(&mut self, columns: Vec<MsDataColumn>) {
+        self.extra_data_columns = columns;
+    }
+
+    /// Set the column that `write_vis` and `write_main_row` write visibilities
+    /// to. This is `DATA` by default, and the column must have been created.
+    ///
+    /// The other columns of a row (e.g. `UVW`, `FLAG` and `WEIGHT_SPECTRUM`)
+    /// are shared by all of the columns of visibilities, and are written
+    /// regardless. To populate several columns of the same rows, use
+    /// [`MeasurementSetWriter::write_vis_columns`].
+    pub fn set_data_column(&mut self, column: MsDataColumn) {
+        self.data_column = column;
+    }
+
+    /// Set whether the `WEIGHT_SPECTRUM` column is written (it is by
+    /// default). This must be set before `initialize` (or
+    /// [`MeasurementSetWriter::add_cotter_mods`]) is called.
+    ///
+    /// `WEIGHT` is a required column, so it's always written, as the sum of a
+    /// row's `WEIGHT_SPECTRUM` over its channels. Some older tools can't read a
+    /// measurement set with `WEIGHT_SPECTRUM`, and others require it.
+    pub fn set_weight_spectrum(&mut self, weight_spectrum: bool) {
+        self.weight_spectrum = weight_spectrum;
+    }
+
+    /// Populate the `POINTING` table, and create and populate the `WEATHER`
+    /// and `SYSCAL` tables, which some CASA tasks warn or fail without. This
+    /// must be set before `initialize` is called. The `SOURCE` table is always
+    /// populated, with a row per field.
+    ///
+    /// `POINTING` and `WEATHER` have a row per antenna per (averaged)
+    /// timestep, and `SYSCAL` has a row per antenna per spectral window,
+    /// spanning the selected timesteps.
+    ///
+    /// The `TARGET` of each row is the phase centre of the timestep's field
+    /// (see [`MeasurementSetWriter::set_fields`]). With `initialize_mwa`, the
+    /// `DIRECTION` is where the tiles point at the timestep; their delays fix
+    /// the pointing in azimuth and elevation for the whole observation, so it
+    /// drifts across the sky. Otherwise, the antennas track the pointing centre
+    /// of the [`ObsContext`] (or the phase centre, if it isn't known).
+    ///
+    /// The metafits doesn't record weather or system temperatures. The
+    /// `WEATHER` columns are flagged unless they're set with
+    /// [`MeasurementSetWriter::set_weather`], and `TSYS` is always flagged.
+    pub fn set_extra_subtables(&mut self, extra_subtables: bool) {
+        self.extra_subtables = extra_subtables;
+    }
+
+    /// Set the pressure, temperature and humidity written to the `WEATHER`
+    /// table (see [`MeasurementSetWriter::set_extra_subtables`]), e.g. from
+    /// the site's weather station. These apply to every antenna and timestep.
+    /// Without them, the `WEATHER` columns are zero and flagged. This must be
+    /// set before `initialize` is called.
+    pub fn set_weather(&mut self, weather: Option<Weather>) {
+        self.weather = weather;
+    }
+
+    /// Quantise the visibilities and weights of each row with the Dysco
+    /// codec (see [`crate::io::dysco`]) before they're written, with the data
+    /// and weight bit rates of `config`. Every column of visibilities is
+    /// quantised, and flags are kept.
+    ///
+    /// casacore's `DyscoStMan` can't be attached to the columns through
+    /// `rubbl_casatables`, so the quantised values are still stored with the
+    /// default storage manager; this gives the same visibilities as a
+    /// Dysco-compressed measurement set, but not the smaller files.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the bit rates or truncation of `config` are
+    /// invalid.
+    pub fn set_dysco(
+        &mut self,
+        config: Option<DyscoConfig>,
+    ) -> Result<(), MeasurementSetWriteError> {
+        self.dysco = config.map(DyscoCodec::new).transpose()?;
+        Ok(())
+    }
+
+    /// Get the index and phase centre of the field that the timestep with
+    /// (centroid) `timestamp` is phased to.
+    fn field_at(&self, timestamp: Epoch) -> (usize, RADec) {
+        if self.fields.is_empty() {
+            (0, self.phase_centre)
+        } else {
+            let field_idx = FieldContext::index_at(&self.fields, timestamp);
+            (field_idx, self.fields[field_idx].phase_centre)
+        }
+    }
+
+    /// Get the number of spectral windows to write, and the number of
+    /// (averaged) channels in each.
+    fn spw_dims(&self, vis_ctx: &VisContext) -> Result<(usize, usize), MeasurementSetWriteError> {
+        match self.coarse_chan_spws {
+            None => Ok((1, vis_ctx.num_avg_chans())),
+            Some(chans_per_spw)
+                if chans_per_spw > 0
+                    && chans_per_spw % vis_ctx.avg_freq == 0
+                    && vis_ctx.num_sel_chans % chans_per_spw == 0 =>
+            {
+                Ok((
+                    vis_ctx.num_sel_chans / chans_per_spw,
+                    chans_per_spw / vis_ctx.avg_freq,
+                ))
+            }
+            Some(chans_per_spw) => Err(MeasurementSetWriteError::BadSpectralWindowChans {
+                num_chans: vis_ctx.num_sel_chans,
+                chans_per_spw,
+                avg_freq: vis_ctx.avg_freq,
+            }),
         }
     }
 
+    /// Set the normalisation of the weights given to `write_vis`. The written
+    /// weights have the same normalisation at the averaged resolution.
+    pub fn set_weight_scaling(&mut self, weight_scaling: WeightScaling) {
+        self.weight_scaling = weight_scaling;
+    }
+
     pub fn validate_path(&self, path: &Path) -> Result<(), MeasurementSetWriteError> {
         for entry in path.ancestors() {
             trace!("testing {:?}", entry);
@@ -131,6 +434,9 @@ impl MeasurementSetWriter {
     }
 
     /// Add additional columns / tables / keywords from `cotter::MSWriter::initialize()`
+    ///
+    /// `WEIGHT_SPECTRUM` is only added if
+    /// [`MeasurementSetWriter::set_weight_spectrum`] allows it.
     pub fn add_cotter_mods(&self, num_channels: usize) -> Result<(), MeasurementSetWriteError> {
         let comment =
             format!("added by {PKG_VERSION} {PKG_NAME}, emulating cotter::MSWriter::initialize()");
@@ -145,15 +451,16 @@ impl MeasurementSetWriter {
             false,
             false,
         )?;
-        main_table.add_array_column(
-            GlueDataType::TpFloat,
-            "WEIGHT_SPECTRUM",
-            Some(comment.as_str()),
-            Some(&data_shape),
-            false,
-            false,
-        )?;
-
+        if self.weight_spectrum {
+            main_table.add_array_column(
+                GlueDataType::TpFloat,
+                "WEIGHT_SPECTRUM",
+                Some(comment.as_str()),
+                Some(&data_shape),
+                false,
+                false,
+            )?;
+        }
         let source_table_path = self.path.join("SOURCE");
         let mut source_table = Table::open(source_table_path, TableOpenMode::ReadWrite)?;
         source_table.add_array_column(
@@ -182,6 +489,27 @@ impl MeasurementSetWriter {
         Ok(())
     }
 
+    /// Add a column of visibilities with `num_channels` channels, like `DATA`.
+    /// `DATA` itself is added by [`MeasurementSetWriter::add_cotter_mods`].
+    pub fn add_data_column(
+        &self,
+        column: MsDataColumn,
+        num_channels: usize,
+    ) -> Result<(), MeasurementSetWriteError> {
+        let comment = format!("added by {PKG_VERSION} {PKG_NAME}");
+        let mut main_table = Table::open(&self.path, TableOpenMode::ReadWrite)?;
+        let data_shape = [num_channels as _, 4];
+        main_table.add_array_column(
+            GlueDataType::TpComplex,
+            column.name(),
+            Some(comment.as_str()),
+            Some(&data_shape),
+            false,
+            false,
+        )?;
+        Ok(())
+    }
+
     /// Add additional columns / tables / keywords from `cotter::MWAMS::addMWAAntennaFields()`
     pub fn add_mwa_ant_mods(&self) -> Result<(), MeasurementSetWriteError> {
         let comment = format!(
@@ -433,6 +761,133 @@ impl MeasurementSetWriter {
         Ok(())
     }
 
+    /// Create the `WEATHER` table, with its required columns and the pressure,
+    /// humidity and temperature columns, as described in `casacore::MSWeather`.
+    pub fn add_weather_table(&self) -> Result<(), MeasurementSetWriteError> {
+        let comment = format!("added by {PKG_VERSION} {PKG_NAME}");
+
+        let mut weather_table_desc = TableDesc::new("WEATHER", TableDescCreateMode::TDM_SCRATCH)?;
+        weather_table_desc.add_scalar_column(
+            GlueDataType::TpInt,
+            "ANTENNA_ID",
+            Some(comment.as_str()),
+            false,
+            false,
+        )?;
+        Self::add_time_columns(&mut weather_table_desc, &comment)?;
+        for (col_name, unit) in [
+            ("PRESSURE", "hPa"),
+            ("REL_HUMIDITY", "%"),
+            ("TEMPERATURE", "K"),
+        ] {
+            weather_table_desc.add_scalar_column(
+                GlueDataType::TpFloat,
+                col_name,
+                Some(comment.as_str()),
+                false,
+                false,
+            )?;
+            weather_table_desc.put_column_keyword(
+                col_name,
+                "QuantumUnits",
+                &vec![unit.to_string()],
+            )?;
+            weather_table_desc.add_scalar_column(
+                GlueDataType::TpBool,
+                &format!("{col_name}_FLAG"),
+                Some(comment.as_str()),
+                false,
+                false,
+            )?;
+        }
+
+        let weather_table_path = self.path.join("WEATHER");
+        let weather_table = Table::new(
+            weather_table_path,
+            weather_table_desc,
+            0,
+            TableCreateMode::New,
+        )?;
+
+        let mut main_table = Table::open(&self.path, TableOpenMode::ReadWrite)?;
+        main_table.put_table_keyword("WEATHER", weather_table)?;
+
+        Ok(())
+    }
+
+    /// Create the `SYSCAL` table, with its required columns and the `TSYS`
+    /// columns, as described in `casacore::MSSysCal`.
+    pub fn add_syscal_table(&self) -> Result<(), MeasurementSetWriteError> {
+        let comment = format!("added by {PKG_VERSION} {PKG_NAME}");
+
+        let mut syscal_table_desc = TableDesc::new("SYSCAL", TableDescCreateMode::TDM_SCRATCH)?;
+        for col_name in ["ANTENNA_ID", "FEED_ID", "SPECTRAL_WINDOW_ID"] {
+            syscal_table_desc.add_scalar_column(
+                GlueDataType::TpInt,
+                col_name,
+                Some(comment.as_str()),
+                false,
+                false,
+            )?;
+        }
+        Self::add_time_columns(&mut syscal_table_desc, &comment)?;
+        // A system temperature per receptor.
+        syscal_table_desc.add_array_column(
+            GlueDataType::TpFloat,
+            "TSYS",
+            Some(comment.as_str()),
+            None,
+            false,
+            false,
+        )?;
+        syscal_table_desc.put_column_keyword("TSYS", "QuantumUnits", &vec!["K".to_string()])?;
+        syscal_table_desc.add_scalar_column(
+            GlueDataType::TpBool,
+            "TSYS_FLAG",
+            Some(comment.as_str()),
+            false,
+            false,
+        )?;
+
+        let syscal_table_path = self.path.join("SYSCAL");
+        let syscal_table = Table::new(
+            syscal_table_path,
+            syscal_table_desc,
+            0,
+            TableCreateMode::New,
+        )?;
+
+        let mut main_table = Table::open(&self.path, TableOpenMode::ReadWrite)?;
+        main_table.put_table_keyword("SYSCAL", syscal_table)?;
+
+        Ok(())
+    }
+
+    /// Add the `TIME` \[MJD UTC seconds\] and `INTERVAL` \[seconds\] columns
+    /// of a subtable.
+    fn add_time_columns(
+        table_desc: &mut TableDesc,
+        comment: &str,
+    ) -> Result<(), MeasurementSetWriteError> {
+        for col_name in ["TIME", "INTERVAL"] {
+            table_desc.add_scalar_column(
+                GlueDataType::TpDouble,
+                col_name,
+                Some(comment),
+                false,
+                false,
+            )?;
+            table_desc.put_column_keyword(col_name, "QuantumUnits", &vec!["s".to_string()])?;
+        }
+
+        let mut meas_info = TableRecord::new()?;
+        meas_info.put_field("type", &"epoch".to_string())?;
+        meas_info.put_field("Ref", &"UTC".to_string())?;
+        table_desc.put_column_keyword("TIME", "MEASINFO", &meas_info)?;
+
+        Ok(())
+    }
+
     /// Write out the DUT1 value as a UT1UTC key. This is not a standard key,
     /// but we don't know of an equivalent!
     pub fn add_dut1_value(&self) -> Result<(), MeasurementSetWriteError> {
@@ -1081,6 +1536,115 @@ impl MeasurementSetWriter {
         Ok(())
     }
 
+    /// Write a row into the `POINTING` table.
+    ///
+    /// - `table` - [`rubbl_casatables::Table`] object to write to.
+    /// - `idx` - row index to write to (ensure enough rows have been added)
+    /// - `antenna_id` - Antenna id
+    /// - `time` - Midpoint of the time for which this row is valid [MJD UTC seconds]
+    /// - `interval` - Interval of time for which this row is valid [seconds]
+    /// - `name` - Pointing position name
+    /// - `direction` - Antenna pointing direction (RA, DEC) [Rad, J2000]
+    /// - `target` - Target direction (RA, DEC) [Rad, J2000], e.g. the phase
+    ///     centre of the field being observed
+    /// - `tracking` - Whether the antenna is tracking `direction`
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_pointing_row(
+        &self,
+        table: &mut Table,
+        idx: u64,
+        antenna_id: i32,
+        time: f64,
+        interval: f64,
+        name: &str,
+        direction: RADec,
+        target: RADec,
+        tracking: bool,
+    ) -> Result<(), MeasurementSetWriteError> {
+        table.put_cell("ANTENNA_ID", idx, &antenna_id)?;
+        table.put_cell("TIME", idx, &time)?;
+        table.put_cell("INTERVAL", idx, &interval)?;
+        table.put_cell("NAME", idx, &name.to_string())?;
+        table.put_cell("NUM_POLY", idx, &0)?;
+        table.put_cell("TIME_ORIGIN", idx, &time)?;
+        table.put_cell("DIRECTION", idx, &array![[direction.ra, direction.dec]])?;
+        table.put_cell("TARGET", idx, &array![[target.ra, target.dec]])?;
+        table.put_cell("TRACKING", idx, &tracking)?;
+        Ok(())
+    }
+
+    /// Write a row into the `WEATHER` table (see
+    /// [`MeasurementSetWriter::add_weather_table`]).
+    ///
+    /// - `table` - [`rubbl_casatables::Table`] object to write to.
+    /// - `idx` - row index to write to (ensure enough rows have been added)
+    /// - `antenna_id` - Antenna id
+    /// - `time` - Midpoint of the time for which this row is valid [MJD UTC seconds]
+    /// - `interval` - Interval of time for which this row is valid [seconds]
+    /// - `weather` - The pressure, humidity and temperature, or `None` to
+    ///     write flagged zeros
+    pub fn write_weather_row(
+        &self,
+        table: &mut Table,
+        idx: u64,
+        antenna_id: i32,
+        time: f64,
+        interval: f64,
+        weather: Option<&Weather>,
+    ) -> Result<(), MeasurementSetWriteError> {
+        table.put_cell("ANTENNA_ID", idx, &antenna_id)?;
+        table.put_cell("TIME", idx, &time)?;
+        table.put_cell("INTERVAL", idx, &interval)?;
+        let (pressure_hpa, rel_humidity_pc, temperature_k) = match weather {
+            Some(weather) => (
+                weather.pressure_hpa as f32,
+                (weather.relative_humidity * 100.) as f32,
+                (weather.temperature_c + 273.15) as f32,
+            ),
+            None => (0., 0., 0.),
+        };
+        let flag = weather.is_none();
+        table.put_cell("PRESSURE", idx, &pressure_hpa)?;
+        table.put_cell("PRESSURE_FLAG", idx, &flag)?;
+        table.put_cell("REL_HUMIDITY", idx, &rel_humidity_pc)?;
+        table.put_cell("REL_HUMIDITY_FLAG", idx, &flag)?;
+        table.put_cell("TEMPERATURE", idx, &temperature_k)?;
+        table.put_cell("TEMPERATURE_FLAG", idx, &flag)?;
+        Ok(())
+    }
+
+    /// Write a row into the `SYSCAL` table (see
+    /// [`MeasurementSetWriter::add_syscal_table`]). System temperatures aren't
+    /// known, so `TSYS` is zero for each receptor, and flagged.
+    ///
+    /// - `table` - [`rubbl_casatables::Table`] object to write to.
+    /// - `idx` - row index to write to (ensure enough rows have been added)
+    /// - `antenna_id` - Antenna id
+    /// - `feed_id` - Feed id
+    /// - `spw_id` - Spectral window id
+    /// - `time` - Midpoint of the time for which this row is valid [MJD UTC seconds]
+    /// - `interval` - Interval of time for which this row is valid [seconds]
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_syscal_row(
+        &self,
+        table: &mut Table,
+        idx: u64,
+        antenna_id: i32,
+        feed_id: i32,
+        spw_id: i32,
+        time: f64,
+        interval: f64,
+    ) -> Result<(), MeasurementSetWriteError> {
+        table.put_cell("ANTENNA_ID", idx, &antenna_id)?;
+        table.put_cell("FEED_ID", idx, &feed_id)?;
+        table.put_cell("SPECTRAL_WINDOW_ID", idx, &spw_id)?;
+        table.put_cell("TIME", idx, &time)?;
+        table.put_cell("INTERVAL", idx, &interval)?;
+        table.put_cell("TSYS", idx, &vec![0_f32; 2])?;
+        table.put_cell("TSYS_FLAG", idx, &true)?;
+        Ok(())
+    }
+
     /// Write a row into the `MWA_TILE_POINTING` table.
     ///
     /// - `start` - start MJD of observation
@@ -1202,7 +1766,41 @@ impl MeasurementSetWriter {
             ..
         } = &obs_ctx;
 
-        self.initialize(vis_ctx, obs_ctx, history)?;
+        // Each spectral window needs a coarse channel, which may not be
+        // contiguous with the previous one.
+        let mwa_spw_start_freqs_hz = match self.coarse_chan_spws {
+            Some(chans_per_spw) => {
+                let (num_spws, _) = self.spw_dims(vis_ctx)?;
+                if coarse_chan_range.len() != num_spws {
+                    return Err(MeasurementSetWriteError::BadSpectralWindowCount {
+                        num_spws,
+                        received: coarse_chan_range.len(),
+                    });
+                }
+                let coarse_chan_width_hz = chans_per_spw as f64 * vis_ctx.freq_resolution_hz;
+                let first_coarse_chan_rec = mwa_ctx.coarse_chan_recs[coarse_chan_range.start];
+                Some(
+                    mwa_ctx.coarse_chan_recs[coarse_chan_range.clone()]
+                        .iter()
+                        .map(|&rec| {
+                            vis_ctx.start_freq_hz
+                                + (rec as f64 - first_coarse_chan_rec as f64) * coarse_chan_width_hz
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            }
+            None => None,
+        };
+
+        self.initialize_spws(
+            vis_ctx,
+            obs_ctx,
+            Some(mwa_ctx),
+            history,
+            self.spw_start_freqs_hz
+                .as_deref()
+                .or(mwa_spw_start_freqs_hz.as_deref()),
+        )?;
 
         self.add_mwa_mods()?;
 
@@ -1246,15 +1844,32 @@ impl MeasurementSetWriter {
             Table::open(self.path.join("SPECTRAL_WINDOW"), TableOpenMode::ReadWrite)?;
         let num_sel_coarse_chans = coarse_chan_range.len();
         let centre_coarse_chan_idx = coarse_chan_range.start + (num_sel_coarse_chans / 2);
-        let centre_coarse_chan_rec = mwa_ctx.coarse_chan_recs[centre_coarse_chan_idx];
-        spw_table.put_cell("MWA_CENTRE_SUBBAND_NR", 0, &(centre_coarse_chan_rec as i32))?;
+        if self.coarse_chan_spws.is_some() {
+            for (spw_idx, coarse_chan_idx) in coarse_chan_range.clone().enumerate() {
+                let coarse_chan_rec = mwa_ctx.coarse_chan_recs[coarse_chan_idx];
+                spw_table.put_cell(
+                    "MWA_CENTRE_SUBBAND_NR",
+                    spw_idx as _,
+                    &(coarse_chan_rec as i32),
+                )?;
+            }
+        } else {
+            let centre_coarse_chan_rec = mwa_ctx.coarse_chan_recs[centre_coarse_chan_idx];
+            spw_table.put_cell("MWA_CENTRE_SUBBAND_NR", 0, &(centre_coarse_chan_rec as i32))?;
+        }
 
         // ///////// //
         // MWA Field //
         // ///////// //
 
         let mut field_table = Table::open(self.path.join("FIELD"), TableOpenMode::ReadWrite)?;
-        field_table.put_cell("MWA_HAS_CALIBRATOR", 0, &mwa_ctx.has_calibrator)?;
+        for field_idx in 0..self.fields.len().max(1) {
+            field_table.put_cell(
+                "MWA_HAS_CALIBRATOR",
+                field_idx as _,
+                &mwa_ctx.has_calibrator,
+            )?;
+        }
 
         // /////////////// //
         // MWA Observation //
@@ -1320,6 +1935,26 @@ impl MeasurementSetWriter {
         vis_ctx: &VisContext,
         obs_ctx: &ObsContext,
         history: Option<&History>,
+    ) -> Result<(), MeasurementSetWriteError> {
+        self.initialize_spws(
+            vis_ctx,
+            obs_ctx,
+            None,
+            history,
+            self.spw_start_freqs_hz.as_deref(),
+        )
+    }
+
+    /// [`MeasurementSetWriter::initialize`], with the centre frequency of the
+    /// first fine channel of each spectral window, if they aren't contiguous,
+    /// and the MWA metadata used by the extra subtables.
+    fn initialize_spws(
+        &self,
+        vis_ctx: &VisContext,
+        obs_ctx: &ObsContext,
+        mwa_ctx: Option<&MwaObsContext>,
+        history: Option<&History>,
+        spw_start_freqs_hz: Option<&[f64]>,
     ) -> Result<(), MeasurementSetWriteError> {
         trace!("initialize");
 
@@ -1334,22 +1969,54 @@ impl MeasurementSetWriter {
         let sel_midpoint_timestamp = sel_start_timestamp + sel_duration / 2.;
 
         // chans
-        let num_avg_chans = vis_ctx.num_avg_chans();
         let avg_chan_width_hz = vis_ctx.avg_freq_resolution_hz();
         let avg_fine_chan_freqs_hz: Vec<f64> = vis_ctx.avg_frequencies_hz();
 
+        // spectral windows
+        let (num_spws, num_avg_chans_per_spw) = self.spw_dims(vis_ctx)?;
+        let spw_freqs_hz: Vec<Vec<f64>> = match spw_start_freqs_hz {
+            None => avg_fine_chan_freqs_hz
+                .chunks(num_avg_chans_per_spw)
+                .map(<[f64]>::to_vec)
+                .collect(),
+            Some(spw_start_freqs_hz) if spw_start_freqs_hz.len() == num_spws => {
+                // Each spectral window has the channels of the first, offset
+                // to its start frequency.
+                spw_start_freqs_hz
+                    .iter()
+                    .map(|&spw_start_freq_hz| {
+                        avg_fine_chan_freqs_hz[..num_avg_chans_per_spw]
+                            .iter()
+                            .map(|&freq_hz| freq_hz - vis_ctx.start_freq_hz + spw_start_freq_hz)
+                            .collect()
+                    })
+                    .collect()
+            }
+            Some(spw_start_freqs_hz) => {
+                return Err(MeasurementSetWriteError::BadSpectralWindowCount {
+                    num_spws,
+                    received: spw_start_freqs_hz.len(),
+                })
+            }
+        };
+
         // baselines
         let num_sel_baselines = vis_ctx.sel_baselines.len();
 
         self.decompress_default_tables()?;
         self.decompress_source_table()?;
-        self.add_cotter_mods(num_avg_chans)?;
+        self.add_cotter_mods(num_avg_chans_per_spw)?;
+        for &column in self.extra_data_columns.iter().unique() {
+            if column != MsDataColumn::Data {
+                self.add_data_column(column, num_avg_chans_per_spw)?;
+            }
+        }
 
         // //// //
         // Main //
         // //// //
 
-        let num_avg_rows = num_avg_timesteps * num_sel_baselines;
+        let num_avg_rows = num_avg_timesteps * num_sel_baselines * num_spws;
         let mut main_table = Table::open(&self.path, TableOpenMode::ReadWrite)?;
 
         // Use a UTC reference frame.
@@ -1359,7 +2026,9 @@ impl MeasurementSetWriter {
         main_table.put_column_keyword("TIME", "MEASINFO", &meas_info)?;
         main_table.put_column_keyword("TIME_CENTROID", "MEASINFO", &meas_info)?;
 
-        main_table.add_rows(num_avg_rows)?;
+        if self.streaming.is_none() {
+            main_table.add_rows(num_avg_rows)?;
+        }
 
         // /////////////// //
         // Spectral Window //
@@ -1368,27 +2037,29 @@ impl MeasurementSetWriter {
         let mut spw_table =
             Table::open(self.path.join("SPECTRAL_WINDOW"), TableOpenMode::ReadWrite)?;
 
-        let chan_info = Array2::from_shape_fn((num_avg_chans, 4), |(c, i)| {
-            if i == 0 {
-                avg_fine_chan_freqs_hz[c]
-            } else {
-                avg_chan_width_hz
-            }
-        });
+        spw_table.add_rows(num_spws)?;
 
-        let center_freq_hz = Self::get_centre_freq(&avg_fine_chan_freqs_hz);
+        for (spw_idx, spw_freqs_hz) in spw_freqs_hz.iter().enumerate() {
+            let chan_info = Array2::from_shape_fn((spw_freqs_hz.len(), 4), |(c, i)| {
+                if i == 0 {
+                    spw_freqs_hz[c]
+                } else {
+                    avg_chan_width_hz
+                }
+            });
 
-        spw_table.add_rows(1)?;
+            let center_freq_hz = Self::get_centre_freq(spw_freqs_hz);
 
-        self.write_spectral_window_row(
-            &mut spw_table,
-            0,
-            format!("MWA_BAND_{:.1}", center_freq_hz / 1_000_000.).as_str(),
-            center_freq_hz,
-            &chan_info,
-            avg_chan_width_hz * num_avg_chans as f64,
-            false,
-        )?;
+            self.write_spectral_window_row(
+                &mut spw_table,
+                spw_idx as _,
+                format!("MWA_BAND_{:.1}", center_freq_hz / 1_000_000.).as_str(),
+                center_freq_hz,
+                &chan_info,
+                avg_chan_width_hz * spw_freqs_hz.len() as f64,
+                false,
+            )?;
+        }
 
         // //////////////// //
         // Data Description //
@@ -1397,8 +2068,16 @@ impl MeasurementSetWriter {
         let mut ddesc_table =
             Table::open(self.path.join("DATA_DESCRIPTION"), TableOpenMode::ReadWrite)?;
 
-        ddesc_table.add_rows(1)?;
-        self.write_data_description_row(&mut ddesc_table, 0, 0, 0, false)?;
+        ddesc_table.add_rows(num_spws)?;
+        for spw_idx in 0..num_spws {
+            self.write_data_description_row(
+                &mut ddesc_table,
+                spw_idx as _,
+                spw_idx as _,
+                0,
+                false,
+            )?;
+        }
 
         // //////// //
         // Antennae //
@@ -1454,24 +2133,50 @@ impl MeasurementSetWriter {
         //  - `PHASE_DIR` - Direction of phase center (e.g. RA, DEC) in time
         //  - `REFERENCE_DIR` - Direction of reference center (e.g. RA, DEC) in time
 
-        let dir_info = array![
-            [[obs_ctx.phase_centre.ra, obs_ctx.phase_centre.dec]],
-            [[obs_ctx.phase_centre.ra, obs_ctx.phase_centre.dec]],
-            [[obs_ctx.phase_centre.ra, obs_ctx.phase_centre.dec]],
-        ];
+        // Without multiple fields, the field has no source, and the time origin
+        // is the scheduled start.
+        let field_name = obs_ctx.field_name.clone().unwrap_or_default();
+        let fields = if self.fields.is_empty() {
+            vec![(
+                field_name.as_str(),
+                obs_ctx.phase_centre,
+                obs_ctx.sched_start_timestamp,
+                -1,
+            )]
+        } else {
+            self.fields
+                .iter()
+                .enumerate()
+                .map(|(idx, field)| {
+                    (
+                        field.name.as_str(),
+                        field.phase_centre,
+                        field.start_timestamp,
+                        idx as i32,
+                    )
+                })
+                .collect()
+        };
 
-        field_table.add_rows(1)?;
+        field_table.add_rows(fields.len())?;
 
-        self.write_field_row(
-            &mut field_table,
-            0,
-            obs_ctx.field_name.as_ref().unwrap_or(&"".into()),
-            "",
-            obs_ctx.sched_start_timestamp.to_mjd_utc_seconds(),
-            &dir_info,
-            -1,
-            false,
-        )?;
+        for (idx, &(name, phase_centre, start_timestamp, source_id)) in fields.iter().enumerate() {
+            let dir_info = array![
+                [[phase_centre.ra, phase_centre.dec]],
+                [[phase_centre.ra, phase_centre.dec]],
+                [[phase_centre.ra, phase_centre.dec]],
+            ];
+            self.write_field_row(
+                &mut field_table,
+                idx as _,
+                name,
+                "",
+                start_timestamp.to_mjd_utc_seconds(),
+                &dir_info,
+                source_id,
+                false,
+            )?;
+        }
 
         // ////// //
         // Source //
@@ -1479,21 +2184,23 @@ impl MeasurementSetWriter {
 
         let mut source_table = Table::open(self.path.join("SOURCE"), TableOpenMode::ReadWrite)?;
 
-        source_table.add_rows(1)?;
-        self.write_source_row(
-            &mut source_table,
-            0,
-            0,
-            sel_midpoint_timestamp.to_mjd_utc_seconds(),
-            sel_duration.to_unit(Unit::Millisecond),
-            0,
-            0,
-            obs_ctx.field_name.as_ref().unwrap_or(&"".into()),
-            0,
-            "",
-            obs_ctx.phase_centre,
-            &[0., 0.],
-        )?;
+        source_table.add_rows(fields.len())?;
+        for (idx, &(name, phase_centre, _, _)) in fields.iter().enumerate() {
+            self.write_source_row(
+                &mut source_table,
+                idx as _,
+                idx as _,
+                sel_midpoint_timestamp.to_mjd_utc_seconds(),
+                sel_duration.to_unit(Unit::Millisecond),
+                0,
+                0,
+                name,
+                0,
+                "",
+                phase_centre,
+                &[0., 0.],
+            )?;
+        }
 
         // /////////// //
         // Observation //
@@ -1526,31 +2233,17 @@ impl MeasurementSetWriter {
 
         hist_table.add_rows(1)?;
 
-        let time = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_millis() as f64
-            / 1000.;
-        let default_message = format!("{PKG_NAME} {PKG_VERSION}");
-        let (cmd_line, application, message) = match history {
-            Some(History {
-                cmd_line,
-                application,
-                message,
-            }) => (
-                cmd_line.unwrap_or_default(),
-                application.unwrap_or_default(),
-                message.unwrap_or_default(),
-            ),
-            None => ("", default_message.as_str(), ""),
-        };
+        // The same fields as `History::as_history_cards`.
+        let history = history.cloned().unwrap_or_default();
+        let application = history.software();
         let params = "";
         self.write_history_row(
             &mut hist_table,
             0,
-            time,
-            cmd_line,
-            message,
-            application,
+            history.time_or_now().to_mjd_utc_seconds(),
+            history.cmd_line.unwrap_or_default(),
+            history.message.unwrap_or_default(),
+            &application,
             params,
         )?;
 
@@ -1584,6 +2277,114 @@ impl MeasurementSetWriter {
             )?;
         }
 
+        if self.extra_subtables {
+            self.write_extra_subtables(vis_ctx, obs_ctx, mwa_ctx, num_spws)?;
+        }
+
+        Ok(())
+    }
+
+    /// Populate the `POINTING`, `WEATHER` and `SYSCAL` tables (see
+    /// [`MeasurementSetWriter::set_extra_subtables`]).
+    fn write_extra_subtables(
+        &self,
+        vis_ctx: &VisContext,
+        obs_ctx: &ObsContext,
+        mwa_ctx: Option<&MwaObsContext>,
+        num_spws: usize,
+    ) -> Result<(), MeasurementSetWriteError> {
+        let num_ants = obs_ctx.num_ants();
+        let avg_int_time_s = vis_ctx.avg_int_time().to_seconds();
+        let sel_start = vis_ctx.start_timestamp.to_mjd_utc_seconds();
+        let sel_duration_s = vis_ctx.num_avg_timesteps() as f64 * avg_int_time_s;
+        let sel_midpoint = sel_start + sel_duration_s / 2.;
+
+        // //////// //
+        // Pointing //
+        // //////// //
+
+        let pointing_centre = obs_ctx.pointing_centre.unwrap_or(obs_ctx.phase_centre);
+        let mut pointing_table = Table::open(self.path.join("POINTING"), TableOpenMode::ReadWrite)?;
+        pointing_table.add_rows(vis_ctx.num_avg_timesteps() * num_ants)?;
+        for (timestep_idx, centroid) in vis_ctx.timeseries(true, true).enumerate() {
+            let (field_idx, phase_centre) = self.field_at(centroid);
+            let name = match self.fields.get(field_idx) {
+                Some(field) => field.name.clone(),
+                None => obs_ctx.field_name.clone().unwrap_or_default(),
+            };
+            let (direction, tracking) = match mwa_ctx {
+                Some(mwa_ctx) => {
+                    let prec_info = precess_time(
+                        self.array_pos.longitude_rad,
+                        self.array_pos.latitude_rad,
+                        phase_centre,
+                        centroid,
+                        self.dut1,
+                    );
+                    let direction = mwa_ctx
+                        .pointing_azel
+                        .to_hadec(prec_info.array_latitude_j2000)
+                        .to_radec(prec_info.lmst_j2000);
+                    (direction, false)
+                }
+                None => (pointing_centre, true),
+            };
+            for ant_idx in 0..num_ants {
+                self.write_pointing_row(
+                    &mut pointing_table,
+                    (timestep_idx * num_ants + ant_idx) as _,
+                    ant_idx as _,
+                    centroid.to_mjd_utc_seconds(),
+                    avg_int_time_s,
+                    &name,
+                    direction,
+                    phase_centre,
+                    tracking,
+                )?;
+            }
+        }
+
+        // /////// //
+        // Weather //
+        // /////// //
+
+        self.add_weather_table()?;
+        let mut weather_table = Table::open(self.path.join("WEATHER"), TableOpenMode::ReadWrite)?;
+        weather_table.add_rows(vis_ctx.num_avg_timesteps() * num_ants)?;
+        for (timestep_idx, centroid) in vis_ctx.timeseries(true, true).enumerate() {
+            for ant_idx in 0..num_ants {
+                self.write_weather_row(
+                    &mut weather_table,
+                    (timestep_idx * num_ants + ant_idx) as _,
+                    ant_idx as _,
+                    centroid.to_mjd_utc_seconds(),
+                    avg_int_time_s,
+                    self.weather.as_ref(),
+                )?;
+            }
+        }
+
+        // ////// //
+        // SysCal //
+        // ////// //
+
+        self.add_syscal_table()?;
+        let mut syscal_table = Table::open(self.path.join("SYSCAL"), TableOpenMode::ReadWrite)?;
+        syscal_table.add_rows(num_ants * num_spws)?;
+        for ant_idx in 0..num_ants {
+            for spw_idx in 0..num_spws {
+                self.write_syscal_row(
+                    &mut syscal_table,
+                    (ant_idx * num_spws + spw_idx) as _,
+                    ant_idx as _,
+                    0,
+                    spw_idx as _,
+                    sel_midpoint,
+                    sel_duration_s,
+                )?;
+            }
+        }
+
         Ok(())
     }
 
@@ -1598,6 +2399,8 @@ impl MeasurementSetWriter {
     /// - `antenna1` - ID of first antenna in interferometer
     /// - `antenna2` - ID of second antenna in interferometer
     /// - `data_desc_id` - The data description table index
+    /// - `field_id` - The field table index (see
+    ///     [`MeasurementSetWriter::set_fields`])
     /// - `uvw` - Vector with uvw coordinates (in meters)
     /// - `interval` - The sampling interval
     /// - `processor_id` - Id for backend processor, index in PROCESSOR table
@@ -1605,7 +2408,9 @@ impl MeasurementSetWriter {
     /// - `state_id` - ID for this observing state
     /// - `sigma` - Estimated rms noise for channel with unity bandpass response
     /// - `data` - an `[n, p]` shaped ndarray of complex visibilities, where `n`
-    ///     is the number of channels, and p is the number of polarizations
+    ///     is the number of channels, and p is the number of polarizations. This
+    ///     is written to the column set by
+    ///     [`MeasurementSetWriter::set_data_column`] (`DATA` by default).
     /// - `flags` - an `[n, p]` shaped ndarray of boolean flags.
     /// - `weights` - a `[p]` shaped ndarray of weights for each polarization
     ///
@@ -1625,6 +2430,7 @@ impl MeasurementSetWriter {
         antenna1: i32,
         antenna2: i32,
         data_desc_id: i32,
+        field_id: i32,
         // TODO: take UVW
         uvw: &Vec<f64>,
         interval: f64,
@@ -1638,6 +2444,58 @@ impl MeasurementSetWriter {
         flags: &Array2<bool>,
         weights: &Array2<f32>,
         flag_row: bool,
+    ) -> Result<(), MeasurementSetWriteError> {
+        self.write_main_row_columns(
+            table,
+            idx,
+            time,
+            time_centroid,
+            antenna1,
+            antenna2,
+            data_desc_id,
+            field_id,
+            uvw,
+            interval,
+            processor_id,
+            scan_number,
+            state_id,
+            sigma,
+            &[self.data_column],
+            std::slice::from_ref(data),
+            flags,
+            weights,
+            flag_row,
+        )
+    }
+
+    /// Like [`MeasurementSetWriter::write_main_row`], but write each of `data`
+    /// to the corresponding column of visibilities in `columns`.
+    #[allow(clippy::ptr_arg)]
+    #[allow(clippy::too_many_arguments)]
+    fn write_main_row_columns(
+        &self,
+        table: &mut Table,
+        idx: u64,
+        time: f64,
+        time_centroid: f64,
+        antenna1: i32,
+        antenna2: i32,
+        data_desc_id: i32,
+        field_id: i32,
+        // TODO: take UVW
+        uvw: &Vec<f64>,
+        interval: f64,
+        // TODO: is this not just interval?
+        // exposure: f64,
+        processor_id: i32,
+        scan_number: i32,
+        state_id: i32,
+        sigma: &Vec<f32>,
+        columns: &[MsDataColumn],
+        data: &[Array2<c32>],
+        flags: &Array2<bool>,
+        weights: &Array2<f32>,
+        flag_row: bool,
     ) -> Result<(), MeasurementSetWriteError> {
         let num_pols = 4;
 
@@ -1659,22 +2517,24 @@ impl MeasurementSetWriter {
             }));
         }
 
-        match (data.shape(), flags.shape(), weights.shape()) {
-            ([d0, d1], [f0, f1], [w0, w1])
-                if d0 == f0
-                    && f0 == w0
-                    && d1 == &num_pols
-                    && f1 == &num_pols
-                    && w1 == &num_pols => {}
-            (dsh, fsh, wsh) => {
-                return Err(MeasurementSetWriteError::BadArrayShape(BadArrayShape {
-                    argument: "data|flags|weights",
-                    function: "write_main_row",
-                    expected: format!(
-                        "[n, p]|[n, p]|[n, p] where n=num_chans, p=num_pols({num_pols})"
-                    ),
-                    received: format!("{dsh:?}|{fsh:?}|{wsh:?}"),
-                }))
+        for data in data {
+            match (data.shape(), flags.shape(), weights.shape()) {
+                ([d0, d1], [f0, f1], [w0, w1])
+                    if d0 == f0
+                        && f0 == w0
+                        && d1 == &num_pols
+                        && f1 == &num_pols
+                        && w1 == &num_pols => {}
+                (dsh, fsh, wsh) => {
+                    return Err(MeasurementSetWriteError::BadArrayShape(BadArrayShape {
+                        argument: "data|flags|weights",
+                        function: "write_main_row",
+                        expected: format!(
+                            "[n, p]|[n, p]|[n, p] where n=num_chans, p=num_pols({num_pols})"
+                        ),
+                        received: format!("{dsh:?}|{fsh:?}|{wsh:?}"),
+                    }))
+                }
             }
         }
 
@@ -1688,6 +2548,7 @@ impl MeasurementSetWriter {
         table.put_cell("ANTENNA1", idx, &antenna1)?;
         table.put_cell("ANTENNA2", idx, &antenna2)?;
         table.put_cell("DATA_DESC_ID", idx, &data_desc_id)?;
+        table.put_cell("FIELD_ID", idx, &field_id)?;
         table.put_cell("UVW", idx, uvw)?;
         table.put_cell("INTERVAL", idx, &interval)?;
         // TODO: really?
@@ -1696,8 +2557,12 @@ impl MeasurementSetWriter {
         table.put_cell("SCAN_NUMBER", idx, &scan_number)?;
         table.put_cell("STATE_ID", idx, &state_id)?;
         table.put_cell("SIGMA", idx, sigma)?;
-        table.put_cell("DATA", idx, data)?;
-        table.put_cell("WEIGHT_SPECTRUM", idx, weights)?;
+        for (column, data) in izip!(columns, data) {
+            table.put_cell(column.name(), idx, data)?;
+        }
+        if self.weight_spectrum {
+            table.put_cell("WEIGHT_SPECTRUM", idx, weights)?;
+        }
         table.put_cell("WEIGHT", idx, &weight_pol)?;
         table.put_cell("FLAG", idx, flags)?;
         table.put_cell("FLAG_ROW", idx, &flag_row)?;
@@ -1706,40 +2571,208 @@ impl MeasurementSetWriter {
     }
 }
 
-impl VisWrite for MeasurementSetWriter {
-    fn write_vis(
+/// The number of baselines whose main table rows are prepared (in parallel,
+/// with the "rayon" feature) before being written.
+///
+/// Only the averaging is parallel; the rows are written serially. casacore
+/// `Table`s aren't thread safe (table locks only arbitrate between processes,
+/// and the storage managers' buckets and caches aren't guarded), and
+/// `rubbl_casatables::Table` isn't `Send`, so a table can't be written by
+/// several threads. Writing shards to separate tables and merging them with a
+/// `ConcatTable` isn't possible either, because `rubbl_casatables` can't
+/// create concatenated tables, and copying the shards' rows back into one table
+/// would cost as much as writing them serially, so the writes themselves are
+/// not made concurrent. The `ms_write_averaging` benchmark in
+/// `benches/bench_io.rs` compares preparing the rows on the rayon pool with
+/// preparing them on a single thread; they only differ with the "rayon"
+/// feature.
+const MAIN_ROW_CHUNK_SIZE: usize = 256;
+
+/// The averaged contents of the main table rows of a single baseline (a row
+/// per spectral window), ready to be written. These are reused between
+/// baselines and timesteps, so that writing doesn't allocate.
+struct MainRowData {
+    uvw: Vec<f64>,
+    /// The data (a `[channel][pol]` array per column of visibilities), weights
+    /// and flags (dimensions `[channel][pol]`) of the row of each spectral
+    /// window.
+    spws: Vec<(Vec<Array2<c32>>, Array2<f32>, Array2<bool>)>,
+    /// Scratch space for quantising a row of visibilities and weights (see
+    /// [`MainRowData::quantise`]).
+    quantise_buf: (Vec<Jones<f32>>, Vec<f32>, Vec<f32>),
+}
+
+impl MainRowData {
+    fn new(
+        num_spws: usize,
+        num_columns: usize,
+        num_avg_chans_per_spw: usize,
+        num_vis_pols: usize,
+    ) -> Self {
+        let shape = (num_avg_chans_per_spw, num_vis_pols);
+        MainRowData {
+            uvw: vec![0.; 3],
+            spws: (0..num_spws)
+                .map(|_| {
+                    (
+                        vec![Array2::zeros(shape); num_columns],
+                        Array2::zeros(shape),
+                        Array2::from_elem(shape, false),
+                    )
+                })
+                .collect(),
+            quantise_buf: (
+                Vec::with_capacity(num_avg_chans_per_spw),
+                Vec::with_capacity(num_avg_chans_per_spw),
+                Vec::with_capacity(num_avg_chans_per_spw),
+            ),
+        }
+    }
+
+    /// Replace the visibilities of each column and the weights of these rows
+    /// with their values after a round trip through `codec`. The weights are
+    /// the same for every polarisation of a channel, so only one weight per
+    /// channel is quantised.
+    fn quantise(&mut self, codec: &DyscoCodec) -> Result<(), DyscoError> {
+        let (jones_buf, weight_buf, scratch_weights) = &mut self.quantise_buf;
+        for (data, weights, _) in self.spws.iter_mut() {
+            weight_buf.clear();
+            weight_buf.extend(weights.outer_iter().map(|w| w[0]));
+            for (column_idx, column) in data.iter_mut().enumerate() {
+                jones_buf.clear();
+                jones_buf.extend(
+                    column
+                        .outer_iter()
+                        .map(|pols| Jones::from([pols[0], pols[1], pols[2], pols[3]])),
+                );
+                // Every column shares the weights, so they're only quantised
+                // with the first column.
+                if column_idx == 0 {
+                    codec.quantise_row(jones_buf, weight_buf)?;
+                } else {
+                    scratch_weights.clone_from(weight_buf);
+                    codec.quantise_row(jones_buf, scratch_weights)?;
+                }
+                for (mut pols, jones) in column.outer_iter_mut().zip(jones_buf.iter()) {
+                    pols.assign(&ArrayView::from(jones.as_slice()));
+                }
+            }
+            for (mut w, &quantised) in weights.outer_iter_mut().zip(weight_buf.iter()) {
+                w.fill(quantised);
+            }
+        }
+        Ok(())
+    }
+
+    /// Average the visibilities of each column (dimensions
+    /// `[timestep][channel][baseline]`) and the weights of a single baseline
+    /// into these rows. Averaged weights are corrected for the number of
+    /// timesteps and channels in each chunk (the last chunks may be short) so
+    /// that they keep `weight_scaling`.
+    fn average_from(
         &mut self,
-        vis: ArrayView3<Jones<f32>>,
+        uvw: UVW,
+        vis_chunks: &[ArrayView3<Jones<f32>>],
+        baseline_idx: usize,
+        weight_chunk: ArrayView2<f32>,
+        vis_ctx: &VisContext,
+        weight_scaling: WeightScaling,
+    ) {
+        self.uvw.copy_from_slice(&[uvw.u, uvw.v, uvw.w]);
+        let mut avg_weight: f32;
+        let mut avg_flag: bool;
+
+        // The weights and flags are averaged from the weights alone, so they're
+        // the same for every column.
+        for (column_idx, vis_chunk) in vis_chunks.iter().enumerate() {
+            let vis_chunk = vis_chunk.index_axis(Axis(2), baseline_idx);
+
+            // iterate through the channel dimension of the arrays in chunks of size `avg_freq`,
+            // averaging the chunks into the row arrays (spectral window by spectral window).
+            let avg_chans = self.spws.iter_mut().flat_map(|(data, weights, flags)| {
+                izip!(
+                    data[column_idx].outer_iter_mut(),
+                    weights.outer_iter_mut(),
+                    flags.outer_iter_mut()
+                )
+            });
+            for (vis_chunk, weight_chunk, (mut data_view, mut weights_view, mut flags_view)) in izip!(
+                vis_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
+                weight_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
+                avg_chans,
+            ) {
+                avg_weight = weight_chunk[[0, 0]];
+                avg_flag = avg_weight.is_sign_negative();
+                if vis_ctx.trivial_averaging() {
+                    data_view.assign(&ArrayView::from(vis_chunk[[0, 0]].as_slice()));
+                } else {
+                    // The linter doesn't like this, but it's wrong. don't bother.
+                    average_chunk_f64!(vis_chunk, weight_chunk, data_view, avg_weight, avg_flag);
+                    avg_weight *= weight_scaling.averaging_correction(
+                        weight_chunk.len_of(Axis(0)),
+                        weight_chunk.len_of(Axis(1)),
+                    ) as f32;
+                }
+                if avg_flag {
+                    avg_weight = avg_weight.abs();
+                }
+                weights_view.fill(avg_weight);
+                flags_view.fill(avg_flag);
+            }
+        }
+    }
+}
+
+impl MeasurementSetWriter {
+    /// Write several columns of visibilities (e.g. `DATA` and `MODEL_DATA`) of
+    /// the same timesteps and baselines in a single pass, as
+    /// [`VisWrite::write_vis`] does for the column set by
+    /// [`MeasurementSetWriter::set_data_column`]. Each column's visibilities
+    /// have the same dimensions as `weights`, and the columns must have been
+    /// created (see [`MeasurementSetWriter::set_extra_data_columns`]).
+    ///
+    /// The other columns of each row (e.g. `UVW`, `FLAG` and `WEIGHT`) are
+    /// shared by all of the columns of visibilities, so they're written once,
+    /// and (when streaming) each timestep only counts once towards the scan
+    /// numbers.
+    pub fn write_vis_columns(
+        &mut self,
+        columns: &[(MsDataColumn, ArrayView3<Jones<f32>>)],
         weights: ArrayView3<f32>,
         vis_ctx: &VisContext,
     ) -> Result<(), IOError> {
         let sel_dims = vis_ctx.sel_dims();
-        if vis.dim() != sel_dims {
-            return Err(IOError::BadArrayShape(BadArrayShape {
-                argument: "vis",
-                function: "write_vis_marlu",
-                expected: format!("{sel_dims:?}"),
-                received: format!("{:?}", vis.dim()),
-            }));
+        for (_, vis) in columns {
+            if vis.dim() != sel_dims {
+                return Err(IOError::BadArrayShape(BadArrayShape {
+                    argument: "vis",
+                    function: "write_vis_columns",
+                    expected: format!("{sel_dims:?}"),
+                    received: format!("{:?}", vis.dim()),
+                }));
+            }
         }
         if weights.dim() != sel_dims {
             return Err(IOError::BadArrayShape(BadArrayShape {
                 argument: "weights",
-                function: "write_vis_marlu",
+                function: "write_vis_columns",
                 expected: format!("{sel_dims:?}"),
                 received: format!("{:?}", weights.dim()),
             }));
         }
 
         let num_avg_timesteps = vis_ctx.num_avg_timesteps();
-        let num_avg_chans = vis_ctx.num_avg_chans();
         let num_vis_pols = vis_ctx.num_vis_pols;
-        let num_avg_rows = num_avg_timesteps * vis_ctx.sel_baselines.len();
+        let num_sel_baselines = vis_ctx.sel_baselines.len();
+        let (num_spws, num_avg_chans_per_spw) = self.spw_dims(vis_ctx)?;
+        let num_avg_rows = num_avg_timesteps * num_sel_baselines * num_spws;
 
         // Open the table for writing
         let mut main_table = Table::open(&self.path, TableOpenMode::ReadWrite)?;
         let num_main_rows = main_table.n_rows();
-        if (num_main_rows - self.main_row_idx as u64) < num_avg_rows as u64 {
+        if self.streaming.is_none()
+            && (num_main_rows - self.main_row_idx as u64) < num_avg_rows as u64
+        {
             return Err(IOError::MeasurementSetWriteError(MeasurementSetFull {
                 rows_attempted: num_avg_rows,
                 rows_remaining: num_main_rows as usize - self.main_row_idx,
@@ -1747,26 +2780,43 @@ impl VisWrite for MeasurementSetWriter {
             }));
         }
 
-        let mut uvw_tmp = vec![0.; 3];
         let sigma_tmp = vec![1.; 4];
-        let mut data_tmp = Array2::zeros((num_avg_chans, num_vis_pols));
-        let mut weights_tmp = Array2::zeros((num_avg_chans, num_vis_pols));
-        let mut flags_tmp = Array2::from_elem((num_avg_chans, num_vis_pols), false);
-        let mut avg_weight: f32;
-        let mut avg_flag: bool;
+        let column_names = columns
+            .iter()
+            .map(|(column, _)| *column)
+            .collect::<Vec<_>>();
+        let mut rows = (0..MAIN_ROW_CHUNK_SIZE.min(num_sel_baselines))
+            .map(|_| MainRowData::new(num_spws, columns.len(), num_avg_chans_per_spw, num_vis_pols))
+            .collect::<Vec<_>>();
 
-        for (avg_centroid_timestamp, vis_chunk, weight_chunk) in izip!(
+        for (avg_timestep_idx, (avg_centroid_timestamp, weight_chunk)) in izip!(
             vis_ctx.timeseries(true, true),
-            vis.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
             weights.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
-        ) {
+        )
+        .enumerate()
+        {
+            let timesteps = avg_timestep_idx * vis_ctx.avg_time
+                ..((avg_timestep_idx + 1) * vis_ctx.avg_time).min(vis_ctx.num_sel_timesteps);
+            let vis_chunks = columns
+                .iter()
+                .map(|(_, vis)| vis.slice(s![timesteps.clone(), .., ..]))
+                .collect::<Vec<_>>();
+
             let scan_centroid_mjd_utc_s = avg_centroid_timestamp.to_mjd_utc_seconds();
+            let interval_s = vis_ctx.avg_int_time().to_seconds();
+            let (field_idx, phase_centre) = self.field_at(avg_centroid_timestamp);
+            let scan_number = match self.streaming.as_mut() {
+                Some(streaming) => {
+                    streaming.push_timestep(scan_centroid_mjd_utc_s, interval_s, field_idx)
+                }
+                None => 1,
+            };
 
             let (tile_xyzs, hadec): (Cow<[XyzGeodetic]>, HADec) = if self.precess_uvws {
                 let prec_info = precess_time(
                     self.array_pos.longitude_rad,
                     self.array_pos.latitude_rad,
-                    self.phase_centre,
+                    phase_centre,
                     avg_centroid_timestamp,
                     self.dut1,
                 );
@@ -1780,89 +2830,127 @@ impl VisWrite for MeasurementSetWriter {
                     avg_centroid_timestamp,
                     self.dut1,
                 );
-                let hadec = self.phase_centre.to_hadec(lmst);
+                let hadec = phase_centre.to_hadec(lmst);
                 (self.antenna_positions.as_slice().into(), hadec)
             };
 
-            for ((ant1_idx, ant2_idx), vis_chunk, weight_chunk) in izip!(
-                vis_ctx.sel_baselines.iter(),
-                vis_chunk.axis_iter(Axis(2)),
+            let baselines = izip!(
+                vis_ctx.sel_baselines.iter().copied(),
                 weight_chunk.axis_iter(Axis(2)),
-            ) {
-                let baseline_xyzs = tile_xyzs[*ant1_idx] - tile_xyzs[*ant2_idx];
-                let uvw = UVW::from_xyz(baseline_xyzs, hadec);
-
-                // copy values into temporary arrays to avoid heap allocs.
-                uvw_tmp.clone_from_slice(&[uvw.u, uvw.v, uvw.w]);
-
-                data_tmp.fill(Complex::default());
-                weights_tmp.fill(0.);
-                flags_tmp.fill(false);
-
-                // iterate through the channel dimension of the arrays in chunks of size `avg_freq`,
-                // averaging the chunks into the tmp arrays.
-                for (
-                    vis_chunk,
-                    weight_chunk,
-                    mut data_tmp_view,
-                    mut weights_tmp_view,
-                    mut flags_tmp_view,
-                ) in izip!(
-                    vis_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
-                    weight_chunk.axis_chunks_iter(Axis(1), vis_ctx.avg_freq),
-                    data_tmp.outer_iter_mut(),
-                    weights_tmp.outer_iter_mut(),
-                    flags_tmp.outer_iter_mut()
-                ) {
-                    avg_weight = weight_chunk[[0, 0]];
-                    avg_flag = avg_weight.is_sign_negative();
-                    if vis_ctx.trivial_averaging() {
-                        data_tmp_view.assign(&ArrayView::from(vis_chunk[[0, 0]].as_slice()));
-                    } else {
-                        // The linter doesn't like this, but it's wrong. don't bother.
-                        average_chunk_f64!(
-                            vis_chunk,
-                            weight_chunk,
-                            data_tmp_view,
-                            avg_weight,
-                            avg_flag
-                        );
+            )
+            .collect::<Vec<_>>();
+
+            // Rows are ordered by spectral window, then baseline.
+            let timestep_row_idx = self.main_row_idx;
+            if self.streaming.is_some() {
+                // Rows that already exist (if `main_row_idx` was set back)
+                // aren't added again.
+                let num_new_rows = (timestep_row_idx + num_sel_baselines * num_spws)
+                    .saturating_sub(main_table.n_rows() as usize);
+                main_table.add_rows(num_new_rows)?;
+            }
+
+            // casacore tables can't safely be written to from multiple threads
+            // (see `MAIN_ROW_CHUNK_SIZE`), so the rows of a chunk of baselines
+            // are prepared (averaged) first, then written serially. With the
+            // "rayon" feature, the rows are prepared in parallel.
+            for (chunk_idx, baseline_chunk) in baselines.chunks(MAIN_ROW_CHUNK_SIZE).enumerate() {
+                let chunk_rows = &mut rows[..baseline_chunk.len()];
+                let dysco = self.dysco.as_ref();
+                let weight_scaling = self.weight_scaling;
+                let prepare_row = |(i, (row, ((ant1_idx, ant2_idx), weight_chunk))): (
+                    usize,
+                    (&mut MainRowData, &((usize, usize), ArrayView2<f32>)),
+                )| {
+                    let baseline_xyzs = tile_xyzs[*ant1_idx] - tile_xyzs[*ant2_idx];
+                    let uvw = UVW::from_xyz(baseline_xyzs, hadec);
+                    row.average_from(
+                        uvw,
+                        &vis_chunks,
+                        chunk_idx * MAIN_ROW_CHUNK_SIZE + i,
+                        weight_chunk.view(),
+                        vis_ctx,
+                        weight_scaling,
+                    );
+                    match dysco {
+                        Some(codec) => row.quantise(codec),
+                        None => Ok(()),
                     }
-                    if avg_flag {
-                        avg_weight = avg_weight.abs();
+                };
+                #[cfg(feature = "rayon")]
+                chunk_rows
+                    .par_iter_mut()
+                    .zip(baseline_chunk.par_iter())
+                    .enumerate()
+                    .try_for_each(prepare_row)
+                    .map_err(MeasurementSetWriteError::Dysco)?;
+                #[cfg(not(feature = "rayon"))]
+                chunk_rows
+                    .iter_mut()
+                    .zip(baseline_chunk.iter())
+                    .enumerate()
+                    .try_for_each(prepare_row)
+                    .map_err(MeasurementSetWriteError::Dysco)?;
+
+                for (baseline_idx, ((ant1_idx, ant2_idx), _), row) in izip!(
+                    chunk_idx * MAIN_ROW_CHUNK_SIZE..,
+                    baseline_chunk,
+                    chunk_rows.iter()
+                ) {
+                    for (spw_idx, (data, weights, flags)) in row.spws.iter().enumerate() {
+                        let flag_row = flags.iter().all(|&x| x);
+                        let row_idx =
+                            (timestep_row_idx + spw_idx * num_sel_baselines + baseline_idx) as _;
+                        self.write_main_row_columns(
+                            &mut main_table,
+                            row_idx,
+                            scan_centroid_mjd_utc_s,
+                            scan_centroid_mjd_utc_s,
+                            *ant1_idx as _,
+                            *ant2_idx as _,
+                            spw_idx as _,
+                            field_idx as _,
+                            &row.uvw,
+                            interval_s,
+                            -1,
+                            scan_number,
+                            -1,
+                            &sigma_tmp,
+                            &column_names,
+                            data,
+                            flags,
+                            weights,
+                            flag_row,
+                        )?;
                     }
-                    weights_tmp_view.fill(avg_weight);
-                    flags_tmp_view.fill(avg_flag);
                 }
-
-                let flag_row = flags_tmp.iter().all(|&x| x);
-                self.write_main_row(
-                    &mut main_table,
-                    self.main_row_idx as _,
-                    scan_centroid_mjd_utc_s,
-                    scan_centroid_mjd_utc_s,
-                    *ant1_idx as _,
-                    *ant2_idx as _,
-                    0,
-                    &uvw_tmp,
-                    vis_ctx.avg_int_time().to_seconds(),
-                    -1,
-                    1,
-                    -1,
-                    &sigma_tmp,
-                    &data_tmp,
-                    &flags_tmp,
-                    &weights_tmp,
-                    flag_row,
-                )?;
-
-                self.main_row_idx += 1;
             }
+            self.main_row_idx += num_sel_baselines * num_spws;
         }
         Ok(())
     }
+}
+
+impl VisWrite for MeasurementSetWriter {
+    fn write_vis(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        self.write_vis_columns(&[(self.data_column, vis)], weights, vis_ctx)
+    }
 
     fn finalise(&mut self) -> Result<(), IOError> {
+        if let Some(StreamingState {
+            time_range: Some((start, end)),
+            ..
+        }) = self.streaming
+        {
+            let mut obs_table =
+                Table::open(self.path.join("OBSERVATION"), TableOpenMode::ReadWrite)?;
+            obs_table.put_cell("TIME_RANGE", 0, &vec![start, end])?;
+        }
         Ok(())
     }
 }
@@ -3235,6 +4323,7 @@ mod tests {
     #[cfg(feature = "mwalib")]
     const COTTER_HISTORY: History<'static> = History {
         application: Some("Cotter MWA preprocessor"),
+        version: None,
         cmd_line: Some(
             "cotter \"-m\" \"tests/data/1254670392_avg/1254670392.metafits\" \
             \"-o\" \"tests/data/1254670392_avg/1254670392.cotter.none.ms\" \
@@ -3270,6 +4359,7 @@ mod tests {
             ",
         ),
         message: Some("Preprocessed & AOFlagged"),
+        time: None,
     };
 
     /// Test data:
@@ -4788,6 +5878,7 @@ mod tests {
                         ant1 as _,
                         ant2 as _,
                         0,
+                        0,
                         &uvw,
                         2.,
                         -1,
@@ -5514,4 +6605,1065 @@ mod tests {
             Err(IOError::MeasurementSetWriteError(MeasurementSetFull { .. }))
         ));
     }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_streaming() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+
+        let vis_sel = VisSelection {
+            timestep_range: 0..2,
+            coarse_chan_range: 0..1,
+            baseline_idxs: vec![1],
+        };
+        let fine_chans_per_coarse = 2;
+
+        let mut vis_ctx = VisContext {
+            num_sel_timesteps: vis_sel.timestep_range.len(),
+            start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: vis_sel.coarse_chan_range.len() * fine_chans_per_coarse,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            sched_duration: Duration::from_f64(1., Unit::Second),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::default(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 0.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+        };
+
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::default(),
+            true,
+        );
+        ms_writer.set_streaming(true);
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+        {
+            let main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+            assert_eq!(main_table.n_rows(), 0);
+        }
+
+        let jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        let weight_array = vis_sel.allocate_weights(fine_chans_per_coarse).unwrap();
+        ms_writer
+            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx)
+            .unwrap();
+        {
+            let main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+            assert_eq!(main_table.n_rows(), 2);
+        }
+
+        // A failed write adds no rows, and doesn't affect the scan numbers.
+        let bad_weight_array = Array3::zeros((1, 2, 1));
+        assert!(matches!(
+            ms_writer.write_vis(jones_array.view(), bad_weight_array.view(), &vis_ctx),
+            Err(IOError::BadArrayShape(_))
+        ));
+        {
+            let main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+            assert_eq!(main_table.n_rows(), 2);
+        }
+
+        // Leave a gap of a timestep before the next chunk.
+        vis_ctx.start_timestamp += Duration::from_f64(3., Unit::Second);
+        ms_writer
+            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx)
+            .unwrap();
+        ms_writer.finalise().unwrap();
+
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        assert_eq!(main_table.n_rows(), 4);
+        assert_eq!(
+            main_table.get_col_as_vec::<i32>("SCAN_NUMBER").unwrap(),
+            [1, 1, 2, 2]
+        );
+        assert_eq!(
+            main_table.get_col_as_vec::<i32>("ANTENNA2").unwrap(),
+            [1, 1, 1, 1]
+        );
+
+        let mut obs_table =
+            Table::open(table_path.join("OBSERVATION"), TableOpenMode::Read).unwrap();
+        let time_range: Vec<f64> = obs_table.get_cell_as_vec("TIME_RANGE", 0).unwrap();
+        let start = Epoch::from_gpst_seconds(1254670392.).to_mjd_utc_seconds();
+        assert!(abs_diff_eq!(time_range[0], start, epsilon = 1e-3));
+        assert!(abs_diff_eq!(time_range[1], start + 5., epsilon = 1e-3));
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_coarse_chan_spws() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+
+        let vis_sel = VisSelection {
+            timestep_range: 0..2,
+            coarse_chan_range: 0..2,
+            baseline_idxs: vec![1],
+        };
+        let fine_chans_per_coarse = 2;
+
+        let vis_ctx = VisContext {
+            num_sel_timesteps: vis_sel.timestep_range.len(),
+            start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: vis_sel.coarse_chan_range.len() * fine_chans_per_coarse,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            sched_duration: Duration::from_f64(2., Unit::Second),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::default(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 0.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+        };
+
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::default(),
+            true,
+        );
+
+        // The channels can't be split into spectral windows of 3 channels.
+        ms_writer.set_coarse_chan_spws(Some(3));
+        assert!(matches!(
+            ms_writer.initialize(&vis_ctx, &obs_ctx, None),
+            Err(MeasurementSetWriteError::BadSpectralWindowChans { .. })
+        ));
+
+        ms_writer.set_coarse_chan_spws(Some(fine_chans_per_coarse));
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        let mut jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        for (chan_idx, mut jones) in jones_array.axis_iter_mut(Axis(1)).enumerate() {
+            jones.fill(Jones::identity() * chan_idx as f32);
+        }
+        let weight_array = vis_sel.allocate_weights(fine_chans_per_coarse).unwrap();
+        ms_writer
+            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx)
+            .unwrap();
+
+        let mut spw_table =
+            Table::open(table_path.join("SPECTRAL_WINDOW"), TableOpenMode::Read).unwrap();
+        assert_eq!(spw_table.n_rows(), 2);
+        let chan_freqs: Vec<f64> = spw_table.get_cell_as_vec("CHAN_FREQ", 1).unwrap();
+        assert_eq!(chan_freqs, [192020000., 192030000.]);
+        let ddesc_table =
+            Table::open(table_path.join("DATA_DESCRIPTION"), TableOpenMode::Read).unwrap();
+        assert_eq!(ddesc_table.n_rows(), 2);
+
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        assert_eq!(main_table.n_rows(), 4);
+        assert_eq!(
+            main_table.get_col_as_vec::<i32>("DATA_DESC_ID").unwrap(),
+            [0, 1, 0, 1]
+        );
+        let data: Vec<c32> = main_table.get_cell_as_vec("DATA", 3).unwrap();
+        assert_eq!(data.len(), fine_chans_per_coarse * 4);
+        assert_eq!(data[0], c32::new(2., 0.));
+        assert_eq!(data[4], c32::new(3., 0.));
+
+        // Spectral windows with a gap between them, e.g. picket fence.
+        let table_path = temp_dir.path().join("picket.ms");
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::default(),
+            true,
+        );
+        ms_writer.set_coarse_chan_spws(Some(fine_chans_per_coarse));
+
+        // There must be a frequency per spectral window.
+        ms_writer.set_spw_start_freqs_hz(Some(vec![192000000.]));
+        assert!(matches!(
+            ms_writer.initialize(&vis_ctx, &obs_ctx, None),
+            Err(MeasurementSetWriteError::BadSpectralWindowCount {
+                num_spws: 2,
+                received: 1
+            })
+        ));
+
+        ms_writer.set_spw_start_freqs_hz(Some(vec![192000000., 194560000.]));
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+        let mut spw_table =
+            Table::open(table_path.join("SPECTRAL_WINDOW"), TableOpenMode::Read).unwrap();
+        let chan_freqs: Vec<f64> = spw_table.get_cell_as_vec("CHAN_FREQ", 1).unwrap();
+        assert_eq!(chan_freqs, [194560000., 194570000.]);
+        let ref_freq: f64 = spw_table.get_cell("REF_FREQUENCY", 1).unwrap();
+        assert_eq!(ref_freq, 194565000.);
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_fields() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+
+        let vis_sel = VisSelection {
+            timestep_range: 0..4,
+            coarse_chan_range: 0..1,
+            baseline_idxs: vec![1],
+        };
+        let fine_chans_per_coarse = 2;
+
+        let start_timestamp = Epoch::from_gpst_seconds(1254670392.);
+        let vis_ctx = VisContext {
+            num_sel_timesteps: vis_sel.timestep_range.len(),
+            start_timestamp,
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: vis_sel.coarse_chan_range.len() * fine_chans_per_coarse,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: start_timestamp,
+            sched_duration: Duration::from_f64(4., Unit::Second),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::default(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 1.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+        };
+
+        let fields = vec![
+            FieldContext {
+                name: "east".into(),
+                phase_centre: RADec::from_degrees(0., -27.),
+                start_timestamp,
+            },
+            FieldContext {
+                name: "west".into(),
+                phase_centre: RADec::from_degrees(30., -27.),
+                start_timestamp: start_timestamp + Duration::from_f64(2., Unit::Second),
+            },
+        ];
+
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::default(),
+            true,
+        );
+        ms_writer.set_fields(fields.clone());
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        let jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        let weight_array = vis_sel.allocate_weights(fine_chans_per_coarse).unwrap();
+        ms_writer
+            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx)
+            .unwrap();
+
+        let mut field_table = Table::open(table_path.join("FIELD"), TableOpenMode::Read).unwrap();
+        assert_eq!(field_table.n_rows(), 2);
+        assert_eq!(
+            field_table.get_col_as_vec::<String>("NAME").unwrap(),
+            ["east", "west"]
+        );
+        let phase_dir: Vec<f64> = field_table.get_cell_as_vec("PHASE_DIR", 1).unwrap();
+        assert!(abs_diff_eq!(phase_dir[0], fields[1].phase_centre.ra));
+        let source_table = Table::open(table_path.join("SOURCE"), TableOpenMode::Read).unwrap();
+        assert_eq!(source_table.n_rows(), 2);
+
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        assert_eq!(
+            main_table.get_col_as_vec::<i32>("FIELD_ID").unwrap(),
+            [0, 0, 1, 1]
+        );
+        // The UVWs are relative to each field's phase centre.
+        let uvw_0: Vec<f64> = main_table.get_cell_as_vec("UVW", 1).unwrap();
+        let uvw_1: Vec<f64> = main_table.get_cell_as_vec("UVW", 2).unwrap();
+        assert!(!abs_diff_eq!(uvw_0[0], uvw_1[0], epsilon = 1e-2));
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_streaming_fields() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+
+        let vis_sel = VisSelection {
+            timestep_range: 0..4,
+            coarse_chan_range: 0..1,
+            baseline_idxs: vec![1],
+        };
+        let fine_chans_per_coarse = 2;
+
+        let start_timestamp = Epoch::from_gpst_seconds(1254670392.);
+        let vis_ctx = VisContext {
+            num_sel_timesteps: vis_sel.timestep_range.len(),
+            start_timestamp,
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: vis_sel.coarse_chan_range.len() * fine_chans_per_coarse,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: start_timestamp,
+            sched_duration: Duration::from_f64(4., Unit::Second),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::default(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 1.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+        };
+
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::default(),
+            true,
+        );
+        ms_writer.set_streaming(true);
+        ms_writer.set_fields(vec![
+            FieldContext {
+                name: "east".into(),
+                phase_centre: RADec::from_degrees(0., -27.),
+                start_timestamp,
+            },
+            FieldContext {
+                name: "west".into(),
+                phase_centre: RADec::from_degrees(30., -27.),
+                start_timestamp: start_timestamp + Duration::from_f64(2., Unit::Second),
+            },
+        ]);
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        let jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        let weight_array = vis_sel.allocate_weights(fine_chans_per_coarse).unwrap();
+        ms_writer
+            .write_vis(jones_array.view(), weight_array.view(), &vis_ctx)
+            .unwrap();
+        ms_writer.finalise().unwrap();
+
+        // There's no gap between the timesteps, but the field changes.
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        assert_eq!(
+            main_table.get_col_as_vec::<i32>("FIELD_ID").unwrap(),
+            [0, 0, 1, 1]
+        );
+        assert_eq!(
+            main_table.get_col_as_vec::<i32>("SCAN_NUMBER").unwrap(),
+            [1, 1, 2, 2]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_model_data() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+
+        let vis_sel = VisSelection {
+            timestep_range: 0..2,
+            coarse_chan_range: 0..1,
+            baseline_idxs: vec![1],
+        };
+        let fine_chans_per_coarse = 2;
+
+        let mut vis_ctx = VisContext {
+            num_sel_timesteps: vis_sel.timestep_range.len(),
+            start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: vis_sel.coarse_chan_range.len() * fine_chans_per_coarse,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            sched_duration: Duration::from_f64(2., Unit::Second),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::default(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 0.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+        };
+
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::default(),
+            true,
+        );
+        ms_writer
+            .set_extra_data_columns(vec![MsDataColumn::ModelData, MsDataColumn::CorrectedData]);
+        ms_writer.set_streaming(true);
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        let data = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let model = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity() * 2.);
+        let corrected = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity() * 3.);
+        let weight_array = vis_sel.allocate_weights(fine_chans_per_coarse).unwrap();
+        // Populate the data and model of the same rows at once.
+        ms_writer
+            .write_vis_columns(
+                &[
+                    (MsDataColumn::Data, data.view()),
+                    (MsDataColumn::ModelData, model.view()),
+                ],
+                weight_array.view(),
+                &vis_ctx,
+            )
+            .unwrap();
+        // Then write the corrected data of the next timesteps alone.
+        vis_ctx.start_timestamp += Duration::from_f64(2., Unit::Second);
+        ms_writer.set_data_column(MsDataColumn::CorrectedData);
+        ms_writer
+            .write_vis(corrected.view(), weight_array.view(), &vis_ctx)
+            .unwrap();
+
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        assert_eq!(main_table.n_rows(), 4);
+        // Each timestep is only counted once.
+        assert_eq!(
+            main_table.get_col_as_vec::<i32>("SCAN_NUMBER").unwrap(),
+            [1, 1, 1, 1]
+        );
+        let column_names = main_table.column_names().unwrap();
+        for column in ["DATA", "MODEL_DATA", "CORRECTED_DATA"] {
+            assert!(column_names.iter().any(|name| name == column));
+        }
+        let data_cell: Vec<c32> = main_table.get_cell_as_vec("DATA", 1).unwrap();
+        assert_eq!(data_cell[0], c32::new(1., 0.));
+        let model_cell: Vec<c32> = main_table.get_cell_as_vec("MODEL_DATA", 1).unwrap();
+        assert_eq!(model_cell[0], c32::new(2., 0.));
+        let corrected_cell: Vec<c32> = main_table.get_cell_as_vec("CORRECTED_DATA", 3).unwrap();
+        assert_eq!(corrected_cell[0], c32::new(3., 0.));
+    }
+
+    /// Check that a measurement set has everything that casacore's
+    /// `MeasurementSet` requires: the required columns of the main table (MS
+    /// v2), and the required subtables, which must also be readable.
+    fn assert_valid_ms(table_path: &Path) {
+        let mut main_table = Table::open(table_path, TableOpenMode::Read).unwrap();
+        let column_names = main_table.column_names().unwrap();
+        for column in [
+            "ANTENNA1",
+            "ANTENNA2",
+            "ARRAY_ID",
+            "DATA_DESC_ID",
+            "EXPOSURE",
+            "FEED1",
+            "FEED2",
+            "FIELD_ID",
+            "FLAG",
+            "FLAG_CATEGORY",
+            "FLAG_ROW",
+            "INTERVAL",
+            "OBSERVATION_ID",
+            "PROCESSOR_ID",
+            "SCAN_NUMBER",
+            "SIGMA",
+            "STATE_ID",
+            "TIME",
+            "TIME_CENTROID",
+            "UVW",
+            "WEIGHT",
+        ] {
+            assert!(
+                column_names.iter().any(|name| name == column),
+                "main table is missing the required column {column}"
+            );
+        }
+        let keywords = main_table.table_keyword_names().unwrap();
+        for subtable in [
+            "ANTENNA",
+            "DATA_DESCRIPTION",
+            "FEED",
+            "FIELD",
+            "FLAG_CMD",
+            "HISTORY",
+            "OBSERVATION",
+            "POINTING",
+            "POLARIZATION",
+            "PROCESSOR",
+            "SPECTRAL_WINDOW",
+            "STATE",
+        ] {
+            assert!(
+                keywords.iter().any(|name| name == subtable),
+                "main table is missing the required subtable {subtable}"
+            );
+            Table::open(table_path.join(subtable), TableOpenMode::Read).unwrap();
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_weight_spectrum() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 2,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            sched_duration: Duration::from_f64(1., Unit::Second),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::default(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 0.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+        };
+
+        let vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let weights = Array3::from_elem(vis_ctx.sel_dims(), 2.);
+        for weight_spectrum in [true, false] {
+            let temp_dir = tempdir().unwrap();
+            let table_path = temp_dir.path().join("test.ms");
+            let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+            let mut ms_writer = MeasurementSetWriter::new(
+                &table_path,
+                obs_ctx.phase_centre,
+                obs_ctx.array_pos,
+                antenna_positions,
+                Duration::default(),
+                true,
+            );
+            ms_writer.set_weight_spectrum(weight_spectrum);
+            ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+            ms_writer
+                .write_vis(vis.view(), weights.view(), &vis_ctx)
+                .unwrap();
+
+            assert_valid_ms(&table_path);
+            let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+            let column_names = main_table.column_names().unwrap();
+            assert_eq!(
+                column_names.iter().any(|name| name == "WEIGHT_SPECTRUM"),
+                weight_spectrum
+            );
+            // The weights of both channels are summed.
+            let weight_cell: Vec<f32> = main_table.get_cell_as_vec("WEIGHT", 0).unwrap();
+            approx::assert_abs_diff_eq!(weight_cell[..], [4.; 4][..]);
+            if weight_spectrum {
+                let weight_cell: Vec<f32> =
+                    main_table.get_cell_as_vec("WEIGHT_SPECTRUM", 0).unwrap();
+                approx::assert_abs_diff_eq!(weight_cell[..], [2.; 8][..]);
+            }
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_unit_weights_short_chunk() {
+        // 3 timesteps averaged by 2, so the last averaged timestep only has
+        // 1 timestep.
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 3,
+            start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 2,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 2,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            sched_duration: Duration::from_f64(3., Unit::Second),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::default(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 0.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+        };
+
+        let vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let weights = Array3::from_elem(vis_ctx.sel_dims(), 1.);
+
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::default(),
+            true,
+        );
+        ms_writer.set_weight_scaling(WeightScaling::Unit);
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+        ms_writer
+            .write_vis(vis.view(), weights.view(), &vis_ctx)
+            .unwrap();
+
+        // Unit weights stay 1 after averaging, including in the short chunk.
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        assert_eq!(main_table.n_rows(), 2);
+        for row_idx in 0..2 {
+            let weight_cell: Vec<f32> = main_table
+                .get_cell_as_vec("WEIGHT_SPECTRUM", row_idx)
+                .unwrap();
+            approx::assert_abs_diff_eq!(weight_cell[..], [1.; 8][..]);
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_vis_dysco() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 4,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            sched_duration: Duration::from_f64(1., Unit::Second),
+            name: None,
+            field_name: None,
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: None,
+            array_pos: LatLngHeight::default(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 0.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+        };
+
+        let vis = Array3::from_shape_fn(vis_ctx.sel_dims(), |(_, c, _)| {
+            Jones::from([
+                c32::new(c as f32 + 0.3, -0.7),
+                c32::new(0.1 * c as f32, 1.9),
+                c32::new(-1.3, c as f32 * 0.45),
+                c32::new(2.2 - c as f32, 0.05),
+            ])
+        });
+        let weights = Array3::from_shape_fn(vis_ctx.sel_dims(), |(_, c, _)| 1. + c as f32);
+        let config = DyscoConfig {
+            data_bit_rate: 4,
+            weight_bit_rate: 4,
+            ..Default::default()
+        };
+
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::default(),
+            true,
+        );
+        assert!(matches!(
+            ms_writer.set_dysco(Some(DyscoConfig {
+                data_bit_rate: 1,
+                ..config
+            })),
+            Err(MeasurementSetWriteError::Dysco(DyscoError::BadBitRate {
+                bits: 1
+            }))
+        ));
+        ms_writer.set_dysco(Some(config)).unwrap();
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+        ms_writer
+            .write_vis(vis.view(), weights.view(), &vis_ctx)
+            .unwrap();
+        assert_valid_ms(&table_path);
+
+        // The written values are the quantised visibilities and weights.
+        let mut expected_vis: Vec<_> = vis.iter().copied().collect();
+        let mut expected_weights: Vec<_> = weights.iter().copied().collect();
+        DyscoCodec::new(config)
+            .unwrap()
+            .quantise_row(&mut expected_vis, &mut expected_weights)
+            .unwrap();
+        assert_ne!(expected_vis[..], vis.as_slice().unwrap()[..]);
+
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        let data_cell: Vec<c32> = main_table.get_cell_as_vec("DATA", 0).unwrap();
+        let expected_data: Vec<c32> = expected_vis.iter().flat_map(|j| j.to_vec()).collect();
+        assert_eq!(data_cell, expected_data);
+        let weight_cell: Vec<f32> = main_table.get_cell_as_vec("WEIGHT_SPECTRUM", 0).unwrap();
+        let expected_weight_cell: Vec<f32> =
+            expected_weights.iter().flat_map(|&w| [w; 4]).collect();
+        assert_eq!(weight_cell, expected_weight_cell);
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_extra_subtables() {
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            int_time: Duration::from_f64(1., Unit::Second),
+            num_sel_chans: 2,
+            start_freq_hz: 192000000.,
+            freq_resolution_hz: 10000.,
+            sel_baselines: vec![(0, 1)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+
+        let pointing_centre = RADec::from_degrees(10., -27.);
+        let obs_ctx = ObsContext {
+            sched_start_timestamp: Epoch::from_gpst_seconds(1254670392.),
+            sched_duration: Duration::from_f64(2., Unit::Second),
+            name: None,
+            field_name: Some("field".into()),
+            project_id: None,
+            observer: None,
+            phase_centre: RADec::default(),
+            pointing_centre: Some(pointing_centre),
+            array_pos: LatLngHeight::default(),
+            ant_positions_enh: vec![
+                ENH::default(),
+                ENH {
+                    e: 0.,
+                    n: 1.,
+                    h: 0.,
+                },
+            ],
+            ant_names: vec!["ant0".into(), "ant1".into()],
+        };
+
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+        let antenna_positions: Vec<_> = obs_ctx.ant_positions_geodetic().collect();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            obs_ctx.phase_centre,
+            obs_ctx.array_pos,
+            antenna_positions,
+            Duration::default(),
+            true,
+        );
+        let fields = vec![
+            FieldContext {
+                name: "east".into(),
+                phase_centre: RADec::from_degrees(0., -27.),
+                start_timestamp: vis_ctx.start_timestamp,
+            },
+            FieldContext {
+                name: "west".into(),
+                phase_centre: RADec::from_degrees(30., -27.),
+                start_timestamp: vis_ctx.start_timestamp + Duration::from_f64(1., Unit::Second),
+            },
+        ];
+        ms_writer.set_fields(fields.clone());
+        ms_writer.set_coarse_chan_spws(Some(1));
+        ms_writer.set_extra_subtables(true);
+        ms_writer.set_weather(Some(Weather {
+            pressure_hpa: 1000.,
+            temperature_c: 20.,
+            relative_humidity: 0.5,
+            wavelength_um: 1e6,
+        }));
+        ms_writer.initialize(&vis_ctx, &obs_ctx, None).unwrap();
+
+        let mut main_table = Table::open(&table_path, TableOpenMode::Read).unwrap();
+        let main_table_keywords = main_table.table_keyword_names().unwrap();
+        for table_name in ["SOURCE", "POINTING", "WEATHER", "SYSCAL"] {
+            assert!(main_table_keywords.contains(&table_name.into()));
+        }
+        drop(main_table);
+
+        // A row per timestep per antenna, with the given weather.
+        let mut weather_table =
+            Table::open(table_path.join("WEATHER"), TableOpenMode::Read).unwrap();
+        assert_eq!(weather_table.n_rows(), 4);
+        let antenna_ids: Vec<i32> = weather_table.get_col_as_vec("ANTENNA_ID").unwrap();
+        assert_eq!(antenna_ids, [0, 1, 0, 1]);
+        let pressures: Vec<f32> = weather_table.get_col_as_vec("PRESSURE").unwrap();
+        assert_eq!(pressures, [1000.; 4]);
+        let rel_humidities: Vec<f32> = weather_table.get_col_as_vec("REL_HUMIDITY").unwrap();
+        assert_eq!(rel_humidities, [50.; 4]);
+        let temperatures: Vec<f32> = weather_table.get_col_as_vec("TEMPERATURE").unwrap();
+        approx::assert_abs_diff_eq!(temperatures[..], [293.15; 4][..], epsilon = 1e-4);
+        for col_name in ["PRESSURE_FLAG", "REL_HUMIDITY_FLAG", "TEMPERATURE_FLAG"] {
+            let flags: Vec<bool> = weather_table.get_col_as_vec(col_name).unwrap();
+            assert_eq!(flags, [false; 4]);
+        }
+
+        // A row per antenna per spectral window, with unknown system
+        // temperatures.
+        let mut syscal_table = Table::open(table_path.join("SYSCAL"), TableOpenMode::Read).unwrap();
+        assert_eq!(syscal_table.n_rows(), 4);
+        let spw_ids: Vec<i32> = syscal_table.get_col_as_vec("SPECTRAL_WINDOW_ID").unwrap();
+        assert_eq!(spw_ids, [0, 1, 0, 1]);
+        let intervals: Vec<f64> = syscal_table.get_col_as_vec("INTERVAL").unwrap();
+        approx::assert_abs_diff_eq!(intervals[..], [2.; 4][..]);
+        let tsys: Vec<f32> = syscal_table.get_cell_as_vec("TSYS", 3).unwrap();
+        assert_eq!(tsys, [0.; 2]);
+        let tsys_flags: Vec<bool> = syscal_table.get_col_as_vec("TSYS_FLAG").unwrap();
+        assert_eq!(tsys_flags, [true; 4]);
+
+        // A row per field.
+        let mut source_table = Table::open(table_path.join("SOURCE"), TableOpenMode::Read).unwrap();
+        assert_eq!(source_table.n_rows(), 2);
+        assert_eq!(
+            source_table.get_col_as_vec::<String>("NAME").unwrap(),
+            ["east", "west"]
+        );
+        let direction: Vec<f64> = source_table.get_cell_as_vec("DIRECTION", 1).unwrap();
+        approx::assert_abs_diff_eq!(
+            direction[..],
+            [fields[1].phase_centre.ra, fields[1].phase_centre.dec][..]
+        );
+
+        // A row per timestep per antenna, pointed at the pointing centre, and
+        // targeting the timestep's field.
+        let mut pointing_table =
+            Table::open(table_path.join("POINTING"), TableOpenMode::Read).unwrap();
+        assert_eq!(pointing_table.n_rows(), 4);
+        let antenna_ids: Vec<i32> = pointing_table.get_col_as_vec("ANTENNA_ID").unwrap();
+        assert_eq!(antenna_ids, [0, 1, 0, 1]);
+        let times: Vec<f64> = pointing_table.get_col_as_vec("TIME").unwrap();
+        approx::assert_abs_diff_eq!(times[2] - times[0], 1., epsilon = 1e-6);
+        assert_eq!(
+            pointing_table.get_col_as_vec::<String>("NAME").unwrap(),
+            ["east", "east", "west", "west"]
+        );
+        assert_eq!(
+            pointing_table.get_col_as_vec::<bool>("TRACKING").unwrap(),
+            [true; 4]
+        );
+        let direction: Vec<f64> = pointing_table.get_cell_as_vec("DIRECTION", 3).unwrap();
+        approx::assert_abs_diff_eq!(direction[..], [pointing_centre.ra, pointing_centre.dec][..]);
+        for (row, field) in [(0, &fields[0]), (3, &fields[1])] {
+            let target: Vec<f64> = pointing_table.get_cell_as_vec("TARGET", row).unwrap();
+            approx::assert_abs_diff_eq!(
+                target[..],
+                [field.phase_centre.ra, field.phase_centre.dec][..]
+            );
+        }
+    }
+
+    #[cfg(feature = "mwalib")]
+    #[test]
+    #[serial]
+    fn test_write_extra_subtables_mwa() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("test.ms");
+
+        let corr_ctx = get_mwa_avg_context();
+        let mut vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        vis_sel.timestep_range = 0..2;
+        vis_sel.baseline_idxs = vec![1];
+
+        let phase_centre = RADec::from_mwalib_phase_or_pointing(&corr_ctx.metafits_context);
+        let array_pos = LatLngHeight::mwa();
+        let mut ms_writer = MeasurementSetWriter::new(
+            &table_path,
+            phase_centre,
+            array_pos,
+            XyzGeodetic::get_tiles_mwa(&corr_ctx.metafits_context),
+            Duration::default(),
+            true,
+        );
+        ms_writer.set_extra_subtables(true);
+        ms_writer
+            .initialize_from_mwalib(
+                &corr_ctx,
+                &vis_sel.timestep_range,
+                &vis_sel.coarse_chan_range,
+                &vis_sel.baseline_idxs,
+                1,
+                1,
+                None,
+            )
+            .unwrap();
+
+        let num_ants = corr_ctx.metafits_context.num_ants;
+        let mut pointing_table =
+            Table::open(table_path.join("POINTING"), TableOpenMode::Read).unwrap();
+        assert_eq!(pointing_table.n_rows() as usize, 2 * num_ants);
+        let tracking: Vec<bool> = pointing_table.get_col_as_vec("TRACKING").unwrap();
+        assert!(tracking.iter().all(|&tracking| !tracking));
+
+        // The metafits doesn't have the weather.
+        let mut weather_table =
+            Table::open(table_path.join("WEATHER"), TableOpenMode::Read).unwrap();
+        assert_eq!(weather_table.n_rows() as usize, 2 * num_ants);
+        let temperature_flags: Vec<bool> =
+            weather_table.get_col_as_vec("TEMPERATURE_FLAG").unwrap();
+        assert!(temperature_flags.iter().all(|&flag| flag));
+        let syscal_table = Table::open(table_path.join("SYSCAL"), TableOpenMode::Read).unwrap();
+        assert_eq!(syscal_table.n_rows() as usize, num_ants);
+
+        // The tiles are fixed in azimuth and elevation, so the RA they point at
+        // increases with the sidereal time.
+        let times: Vec<f64> = pointing_table.get_col_as_vec("TIME").unwrap();
+        let direction_0: Vec<f64> = pointing_table.get_cell_as_vec("DIRECTION", 0).unwrap();
+        let direction_1: Vec<f64> = pointing_table
+            .get_cell_as_vec("DIRECTION", num_ants as _)
+            .unwrap();
+        let expected_drift_rad =
+            (times[num_ants] - times[0]) * 2. * std::f64::consts::PI / 86164.0905;
+        approx::assert_abs_diff_eq!(
+            (direction_1[0] - direction_0[0]).rem_euclid(std::f64::consts::TAU),
+            expected_drift_rad,
+            epsilon = 1e-6
+        );
+        approx::assert_abs_diff_eq!(direction_1[1], direction_0[1], epsilon = 1e-6);
+
+        // At the start of the observation, the tiles point near the metafits
+        // pointing centre.
+        let pointing_centre = RADec::from_degrees(
+            corr_ctx.metafits_context.ra_tile_pointing_degrees,
+            corr_ctx.metafits_context.dec_tile_pointing_degrees,
+        );
+        let direction_0 = RADec::from_radians(direction_0[0], direction_0[1]);
+        assert!(direction_0.separation(pointing_centre) < 1_f64.to_radians());
+    }
 }
@@ -0,0 +1,802 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Module for reading and writing AOFlagger-compatible MWAF flag files.
+//!
+//! An MWAF file holds the flags of a single coarse channel (gpubox). The
+//! primary HDU has no data, only the keys describing the flags, and the
+//! `FLAGS` binary table has a single bit-array column with a bit for each fine
+//! channel. There is a row for each timestep and baseline, in time-major order,
+//! and every baseline of the array (including autos, with `ant1 <= ant2`) has a
+//! row, whether or not it was selected.
+//!
+//! Files written by cotter (version 1.x) have the GPS time of the first scan
+//! in `GPSTIME`. Files written by Birli (version 2.x), and by
+//! [`MwafWriter`], have the observation ID in `GPSTIME` and the GPS time of
+//! the first scan in `GPSSTART`. [`MwafFlags::read`] handles both.
+
+use std::{
+    ffi::CString,
+    os::raw::c_char,
+    path::{Path, PathBuf},
+};
+
+use fitsio::errors::check_status as fits_check_status;
+use fitsio_sys;
+use itertools::izip;
+use log::trace;
+
+use super::{
+    error::{BadArrayShape, MwafReadError, MwafWriteError},
+    uvfits::{
+        create_table, fits_write_comment, fits_write_double, fits_write_int, fits_write_string,
+    },
+};
+use crate::{
+    hifitime::{Duration, Epoch},
+    ndarray::prelude::*,
+    History, VisContext,
+};
+
+/// The version of the MWAF format written, as in Birli.
+const MWAF_VERSION: &str = "2.0";
+
+/// The placeholder in a filename template which is replaced by the gpubox
+/// number of each coarse channel.
+pub const GPUBOX_PLACEHOLDER: &str = "%%";
+
+/// The index of the baseline `(ant1, ant2)` in the rows of a timestep of an
+/// MWAF file, where `ant1 <= ant2`.
+pub(crate) fn mwaf_baseline_idx(ant1: usize, ant2: usize, num_ants: usize) -> usize {
+    ant1 * (2 * num_ants - ant1 + 1) / 2 + (ant2 - ant1)
+}
+
+/// Writes a flag cube as a set of MWAF files, one for each coarse channel.
+pub struct MwafWriter {
+    /// The filename template, containing [`GPUBOX_PLACEHOLDER`].
+    template: String,
+
+    /// The gpubox number of each coarse channel in the flags.
+    gpubox_nums: Vec<usize>,
+
+    /// The number of fine channels in each coarse channel.
+    num_fine_chans_per_coarse: usize,
+
+    /// The number of antennas in the array.
+    num_ants: usize,
+
+    /// The observation ID (GPS start time of the observation).
+    obs_id: u32,
+
+    /// The software which made the flags.
+    software: String,
+
+    /// The comments describing how the flags were made.
+    comments: Vec<String>,
+}
+
+impl MwafWriter {
+    /// Create a new MWAF writer. Nothing is written until
+    /// [`MwafWriter::write_flags`] is called.
+    ///
+    /// `template` is the path of the files, where [`GPUBOX_PLACEHOLDER`] is
+    /// replaced by the (zero-padded, two-digit) gpubox number of each coarse
+    /// channel, e.g. `flags_%%.mwaf`.
+    ///
+    /// `gpubox_nums` are the gpubox numbers of the coarse channels of the
+    /// flags, in order, each with `num_fine_chans_per_coarse` fine channels.
+    ///
+    /// `num_ants` is the number of antennas in the array, and `obs_id` is the
+    /// observation ID.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`MwafWriteError`] if `template` doesn't contain
+    /// [`GPUBOX_PLACEHOLDER`].
+    pub fn new(
+        template: &str,
+        gpubox_nums: Vec<usize>,
+        num_fine_chans_per_coarse: usize,
+        num_ants: usize,
+        obs_id: u32,
+        history: Option<&History>,
+    ) -> Result<Self, MwafWriteError> {
+        if !template.contains(GPUBOX_PLACEHOLDER) {
+            return Err(MwafWriteError::InvalidTemplate {
+                template: template.to_string(),
+            });
+        }
+        let history = history.cloned().unwrap_or_default();
+        let software = history.software();
+        let comments = history.as_comments();
+        Ok(Self {
+            template: template.to_string(),
+            gpubox_nums,
+            num_fine_chans_per_coarse,
+            num_ants,
+            obs_id,
+            software,
+            comments,
+        })
+    }
+
+    /// Create a new MWAF writer for the coarse channels in `coarse_chan_range`
+    /// of an [`mwalib::CorrelatorContext`]. See [`MwafWriter::new`].
+    ///
+    /// # Errors
+    ///
+    /// See [`MwafWriter::new`].
+    #[cfg(feature = "mwalib")]
+    pub fn from_mwalib(
+        template: &str,
+        corr_ctx: &mwalib::CorrelatorContext,
+        coarse_chan_range: &std::ops::Range<usize>,
+        history: Option<&History>,
+    ) -> Result<Self, MwafWriteError> {
+        let gpubox_nums = corr_ctx.coarse_chans[coarse_chan_range.clone()]
+            .iter()
+            .map(|cc| cc.gpubox_number)
+            .collect();
+        Self::new(
+            template,
+            gpubox_nums,
+            corr_ctx.metafits_context.num_corr_fine_chans_per_coarse,
+            corr_ctx.metafits_context.num_ants,
+            corr_ctx.metafits_context.obs_id,
+            history,
+        )
+    }
+
+    /// The path of the file for each coarse channel.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.gpubox_nums
+            .iter()
+            .map(|n| {
+                PathBuf::from(
+                    self.template
+                        .replace(GPUBOX_PLACEHOLDER, &format!("{n:02}")),
+                )
+            })
+            .collect()
+    }
+
+    /// Write the flags, with dimensions `[timestep][channel][baseline]`, to a
+    /// file for each coarse channel. This will destroy any existing files at
+    /// the [`MwafWriter::paths`].
+    ///
+    /// The flags are at the selected (not averaged) resolution of `vis_ctx`,
+    /// and `vis_ctx.sel_baselines` says which baseline of the array each
+    /// baseline of the flags is. Baselines which aren't selected are written
+    /// as flagged.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`MwafWriteError`] if:
+    /// - the flags aren't the shape of `vis_ctx`'s selection.
+    /// - the channels can't be split into the coarse channels.
+    /// - a baseline has an antenna outside the array.
+    /// - a fits operation fails.
+    pub fn write_flags(
+        &self,
+        flags: ArrayView3<bool>,
+        vis_ctx: &VisContext,
+    ) -> Result<(), MwafWriteError> {
+        let sel_dims = vis_ctx.sel_dims();
+        if flags.dim() != sel_dims {
+            return Err(MwafWriteError::BadArrayShape(BadArrayShape {
+                argument: "flags",
+                function: "MwafWriter::write_flags",
+                expected: format!("{sel_dims:?}"),
+                received: format!("{:?}", flags.dim()),
+            }));
+        }
+        let num_coarse_chans = self.gpubox_nums.len();
+        if vis_ctx.num_sel_chans != num_coarse_chans * self.num_fine_chans_per_coarse {
+            return Err(MwafWriteError::BadChanCount {
+                num_chans: vis_ctx.num_sel_chans,
+                num_coarse_chans,
+                num_fine_chans_per_coarse: self.num_fine_chans_per_coarse,
+            });
+        }
+        let mwaf_baseline_idxs = vis_ctx
+            .sel_baselines
+            .iter()
+            .map(|&(ant1, ant2)| {
+                if ant1.max(ant2) >= self.num_ants {
+                    return Err(MwafWriteError::BadBaseline {
+                        ant1,
+                        ant2,
+                        num_ants: self.num_ants,
+                    });
+                }
+                Ok(mwaf_baseline_idx(
+                    ant1.min(ant2),
+                    ant1.max(ant2),
+                    self.num_ants,
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (path, &gpubox_num, coarse_flags) in izip!(
+            self.paths(),
+            &self.gpubox_nums,
+            flags.axis_chunks_iter(Axis(1), self.num_fine_chans_per_coarse.max(1)),
+        ) {
+            self.write_file(
+                &path,
+                gpubox_num,
+                coarse_flags,
+                &mwaf_baseline_idxs,
+                vis_ctx,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the flags of a single coarse channel to `path`.
+    fn write_file(
+        &self,
+        path: &Path,
+        gpubox_num: usize,
+        flags: ArrayView3<bool>,
+        mwaf_baseline_idxs: &[usize],
+        vis_ctx: &VisContext,
+    ) -> Result<(), MwafWriteError> {
+        let (num_timesteps, num_chans, _) = flags.dim();
+        let num_mwaf_baselines = self.num_ants * (self.num_ants + 1) / 2;
+
+        // Delete any file that already exists.
+        if path.exists() {
+            trace!("file {} exists, deleting", path.display());
+            std::fs::remove_file(path)?;
+        }
+
+        // Create a new fits file.
+        let mut status = 0;
+        let c_path = CString::new(path.to_str().unwrap())?;
+        let mut fptr = std::ptr::null_mut();
+        trace!("initialising fits file with fitsio_sys ({:?})", &path);
+        unsafe {
+            // ffinit = fits_create_file
+            fitsio_sys::ffinit(
+                &mut fptr,       /* O - FITS file pointer                   */
+                c_path.as_ptr(), /* I - name of file to create              */
+                &mut status,     /* IO - error status                       */
+            );
+            fits_check_status(status)?;
+            // An empty primary HDU. ffphps = fits_write_imghdr
+            fitsio_sys::ffphps(
+                fptr,                 /* I - FITS file pointer                   */
+                8,                    /* I - number of bits per data value pixel */
+                0,                    /* I - number of axes in the data array    */
+                std::ptr::null_mut(), /* I - length of each data axis            */
+                &mut status,          /* IO - error status                       */
+            );
+        }
+        fits_check_status(status)?;
+
+        fits_write_string(fptr, "VERSION", MWAF_VERSION, Some("MWAF format version"))?;
+        fits_write_int(fptr, "GPSTIME", self.obs_id as i64, Some("Observation ID"))?;
+        fits_write_double(
+            fptr,
+            "GPSSTART",
+            vis_ctx.start_timestamp.to_gpst_seconds(),
+            Some("GPS time of the first scan"),
+        )?;
+        fits_write_int(
+            fptr,
+            "NCHANS",
+            num_chans as i64,
+            Some("Number of fine channels"),
+        )?;
+        fits_write_int(
+            fptr,
+            "NANTENNA",
+            self.num_ants as i64,
+            Some("Number of antennas"),
+        )?;
+        fits_write_int(
+            fptr,
+            "NSCANS",
+            num_timesteps as i64,
+            Some("Number of scans"),
+        )?;
+        fits_write_int(fptr, "NPOLS", 1, Some("Number of polarisations"))?;
+        fits_write_int(fptr, "GPUBOXNO", gpubox_num as i64, Some("Gpubox number"))?;
+        fits_write_string(fptr, "SOFTWARE", &self.software, None)?;
+        for comment in &self.comments {
+            fits_write_comment(fptr, comment)?;
+        }
+
+        create_table(fptr, "FLAGS", &[("FLAGS", &format!("{num_chans}X"), "")])?;
+
+        // Baselines which aren't selected stay flagged.
+        let mut bits = Array2::<c_char>::ones((num_mwaf_baselines, num_chans));
+        for (timestep_idx, flags) in flags.outer_iter().enumerate() {
+            for (flags, &bl_idx) in flags.axis_iter(Axis(1)).zip(mwaf_baseline_idxs) {
+                bits.index_axis_mut(Axis(0), bl_idx)
+                    .assign(&flags.mapv(c_char::from));
+            }
+            for (bl_idx, mut row_bits) in bits.outer_iter_mut().enumerate() {
+                let row = timestep_idx * num_mwaf_baselines + bl_idx;
+                unsafe {
+                    // ffpclx = fits_write_col_bit
+                    fitsio_sys::ffpclx(
+                        fptr,                  /* I - FITS file pointer                  */
+                        1,                     /* I - number of column to write          */
+                        row as i64 + 1,        /* I - first row to write (1 = 1st row)   */
+                        1,                     /* I - first bit to write (1 = 1st)       */
+                        num_chans as _,        /* I - number of bits to write            */
+                        row_bits.as_mut_ptr(), /* I - array of logical values            */
+                        &mut status,           /* IO - error status                      */
+                    );
+                }
+                fits_check_status(status)?;
+            }
+        }
+
+        trace!("closing fits file ({})", path.display());
+        unsafe {
+            // ffclos = fits_close_file
+            fitsio_sys::ffclos(fptr, &mut status);
+        }
+        fits_check_status(status)?;
+        Ok(())
+    }
+}
+
+/// Read the bits of the (zero-indexed) row `row` of the first column of the
+/// current table into `bits`.
+fn read_row_bits(
+    fptr: *mut fitsio_sys::fitsfile,
+    row: usize,
+    bits: &mut [c_char],
+) -> Result<(), fitsio::errors::Error> {
+    let mut status = 0;
+    unsafe {
+        // ffgcx = fits_read_col_bit
+        fitsio_sys::ffgcx(
+            fptr,              /* I - FITS file pointer                 */
+            1,                 /* I - number of column to read          */
+            row as i64 + 1,    /* I - first row to read (1 = 1st row)   */
+            1,                 /* I - first bit to read (1 = 1st)       */
+            bits.len() as _,   /* I - number of bits to read            */
+            bits.as_mut_ptr(), /* O - array of logical values           */
+            &mut status,       /* IO - error status                     */
+        );
+    }
+    fits_check_status(status)
+}
+
+/// The software that wrote an MWAF file, which determines how its keys are
+/// interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MwafVersion {
+    /// Version 1.x, written by cotter.
+    Cotter,
+
+    /// Version 2.x, written by Birli (and [`MwafWriter`]).
+    Birli,
+}
+
+/// The flags of a single MWAF file.
+#[derive(Clone, Debug)]
+pub struct MwafFlags {
+    /// The format version of the file.
+    pub version: MwafVersion,
+
+    /// The gpubox number of the file's coarse channel.
+    pub gpubox_num: usize,
+
+    /// The number of antennas in the array.
+    pub num_ants: usize,
+
+    /// The GPS time of the start of the first scan.
+    pub start_timestamp: Epoch,
+
+    /// The observation ID. This isn't in cotter files.
+    pub obs_id: Option<u32>,
+
+    /// The software that wrote the file, if known.
+    pub software: Option<String>,
+
+    /// The flags, with dimensions `[scan][channel][baseline]`, where there is
+    /// a baseline for every antenna pair `ant1 <= ant2` of the array.
+    pub flags: Array3<bool>,
+}
+
+impl MwafFlags {
+    /// Read all of the flags of the MWAF file at `path`.
+    ///
+    /// The number of scans is taken from the number of rows of the `FLAGS`
+    /// table rather than `NSCANS`, which isn't always correct in cotter files.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`MwafReadError`] if:
+    /// - the file's version isn't supported.
+    /// - the number of rows isn't a multiple of the number of baselines.
+    /// - a fits operation fails.
+    pub fn read<T: AsRef<Path>>(path: T) -> Result<Self, MwafReadError> {
+        let path = path.as_ref();
+        trace!("reading MWAF file {}", path.display());
+        let mut fptr = fitsio::FitsFile::open(path)?;
+        let hdu = fptr.primary_hdu()?;
+        let version_str: String = hdu.read_key(&mut fptr, "VERSION")?;
+        let version = match version_str.split('.').next() {
+            Some("1") => MwafVersion::Cotter,
+            Some("2") => MwafVersion::Birli,
+            _ => {
+                return Err(MwafReadError::UnsupportedVersion {
+                    path: path.to_path_buf(),
+                    version: version_str,
+                })
+            }
+        };
+        let num_chans: i64 = hdu.read_key(&mut fptr, "NCHANS")?;
+        let num_ants: i64 = hdu.read_key(&mut fptr, "NANTENNA")?;
+        let gpubox_num: i64 = hdu.read_key(&mut fptr, "GPUBOXNO")?;
+        let (start_timestamp, obs_id, software) = match version {
+            MwafVersion::Cotter => {
+                let gps_time: f64 = hdu.read_key(&mut fptr, "GPSTIME")?;
+                let software = hdu
+                    .read_key::<String>(&mut fptr, "COTVER")
+                    .ok()
+                    .map(|v| format!("cotter {v}"));
+                (Epoch::from_gpst_seconds(gps_time), None, software)
+            }
+            MwafVersion::Birli => {
+                let gps_start: f64 = hdu.read_key(&mut fptr, "GPSSTART")?;
+                let obs_id: i64 = hdu.read_key(&mut fptr, "GPSTIME")?;
+                let software = hdu.read_key::<String>(&mut fptr, "SOFTWARE").ok();
+                (
+                    Epoch::from_gpst_seconds(gps_start),
+                    Some(obs_id as u32),
+                    software,
+                )
+            }
+        };
+        let (num_chans, num_ants) = (num_chans as usize, num_ants as usize);
+
+        let hdu = fptr.hdu("FLAGS")?;
+        let num_rows: i64 = hdu.read_key(&mut fptr, "NAXIS2")?;
+        let num_rows = num_rows as usize;
+        let num_baselines = num_ants * (num_ants + 1) / 2;
+        if num_baselines == 0 || num_rows % num_baselines != 0 {
+            return Err(MwafReadError::BadRowCount {
+                path: path.to_path_buf(),
+                num_rows,
+                num_baselines,
+            });
+        }
+        let num_scans = num_rows / num_baselines;
+
+        let mut flags = Array3::from_elem((num_scans, num_chans, num_baselines), false);
+        let mut bits = vec![0; num_chans];
+        let raw = unsafe { fptr.as_raw() };
+        for (scan_idx, mut flags) in flags.outer_iter_mut().enumerate() {
+            for (bl_idx, mut flags) in flags.axis_iter_mut(Axis(1)).enumerate() {
+                read_row_bits(raw, scan_idx * num_baselines + bl_idx, &mut bits)?;
+                for (flag, &bit) in flags.iter_mut().zip(&bits) {
+                    *flag = bit != 0;
+                }
+            }
+        }
+
+        Ok(Self {
+            version,
+            gpubox_num: gpubox_num as usize,
+            num_ants,
+            start_timestamp,
+            obs_id,
+            software,
+            flags,
+        })
+    }
+
+    /// The index of the scan at `timestamp`, if the file has it. `int_time` is
+    /// the time between scans.
+    pub fn scan_idx(&self, timestamp: Epoch, int_time: Duration) -> Option<usize> {
+        let offset = (timestamp - self.start_timestamp).to_seconds() / int_time.to_seconds();
+        let scan_idx = offset.round();
+        if scan_idx < 0.0 || scan_idx as usize >= self.flags.len_of(Axis(0)) {
+            None
+        } else {
+            Some(scan_idx as usize)
+        }
+    }
+
+    /// OR these flags into `flags`, with dimensions
+    /// `[timestep][channel][baseline]`, where the channels are the fine
+    /// channels of this file's coarse channel. `timestamps` are the (start)
+    /// times of the timesteps, and `sel_baselines` the antenna pairs of the
+    /// baselines. Timesteps that aren't in this file are left as they are.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`MwafReadError`] if the shape of `flags` doesn't match
+    /// `timestamps`, this file's channels and `sel_baselines`, or a baseline
+    /// has an antenna outside the array.
+    pub fn or_into(
+        &self,
+        mut flags: ArrayViewMut3<bool>,
+        timestamps: &[Epoch],
+        int_time: Duration,
+        sel_baselines: &[(usize, usize)],
+    ) -> Result<(), MwafReadError> {
+        let expected = (
+            timestamps.len(),
+            self.flags.len_of(Axis(1)),
+            sel_baselines.len(),
+        );
+        if flags.dim() != expected {
+            return Err(MwafReadError::BadArrayShape(BadArrayShape {
+                argument: "flags",
+                function: "MwafFlags::or_into",
+                expected: format!("{expected:?}"),
+                received: format!("{:?}", flags.dim()),
+            }));
+        }
+        let mwaf_baseline_idxs = sel_baselines
+            .iter()
+            .map(|&(ant1, ant2)| {
+                if ant1.max(ant2) >= self.num_ants {
+                    return Err(MwafReadError::BadBaseline {
+                        ant1,
+                        ant2,
+                        num_ants: self.num_ants,
+                    });
+                }
+                Ok(mwaf_baseline_idx(
+                    ant1.min(ant2),
+                    ant1.max(ant2),
+                    self.num_ants,
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (mut flags, &timestamp) in flags.outer_iter_mut().zip(timestamps) {
+            let scan_idx = match self.scan_idx(timestamp, int_time) {
+                Some(scan_idx) => scan_idx,
+                None => continue,
+            };
+            let mwaf_flags = self.flags.index_axis(Axis(0), scan_idx);
+            for (mut flags, &bl_idx) in flags.axis_iter_mut(Axis(1)).zip(&mwaf_baseline_idxs) {
+                flags.zip_mut_with(&mwaf_flags.index_axis(Axis(1), bl_idx), |f, &m| *f |= m);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read the MWAF files for the coarse channels of `vis_sel`, and OR their
+/// flags into `flags`, which has the shape of `vis_sel`'s flag array (see
+/// [`VisSelection::allocate_flags`]). `template` is as in [`MwafWriter::new`].
+///
+/// # Errors
+///
+/// Will return an [`MwafReadError`] if a file can't be read, or it doesn't
+/// match the observation.
+///
+/// [`VisSelection::allocate_flags`]: crate::VisSelection::allocate_flags
+#[cfg(feature = "mwalib")]
+pub fn apply_mwaf_flags(
+    template: &str,
+    corr_ctx: &mwalib::CorrelatorContext,
+    vis_sel: &crate::VisSelection,
+    mut flags: ArrayViewMut3<bool>,
+) -> Result<(), MwafReadError> {
+    let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+    let expected = vis_sel.get_shape(fine_chans_per_coarse);
+    if flags.dim() != expected {
+        return Err(MwafReadError::BadArrayShape(BadArrayShape {
+            argument: "flags",
+            function: "apply_mwaf_flags",
+            expected: format!("{expected:?}"),
+            received: format!("{:?}", flags.dim()),
+        }));
+    }
+    let timestamps = corr_ctx.timesteps[vis_sel.timestep_range.clone()]
+        .iter()
+        .map(|t| Epoch::from_gpst_seconds(t.gps_time_ms as f64 / 1e3))
+        .collect::<Vec<_>>();
+    let int_time = Duration::from_seconds(corr_ctx.metafits_context.corr_int_time_ms as f64 / 1e3);
+    let sel_baselines = vis_sel.get_ant_pairs(&corr_ctx.metafits_context);
+
+    for (coarse_chan, flags) in izip!(
+        &corr_ctx.coarse_chans[vis_sel.coarse_chan_range.clone()],
+        flags.axis_chunks_iter_mut(Axis(1), fine_chans_per_coarse),
+    ) {
+        let path = PathBuf::from(template.replace(
+            GPUBOX_PLACEHOLDER,
+            &format!("{:02}", coarse_chan.gpubox_number),
+        ));
+        let mwaf = MwafFlags::read(&path)?;
+        for (key, expected, received) in [
+            ("GPUBOXNO", coarse_chan.gpubox_number, mwaf.gpubox_num),
+            ("NCHANS", fine_chans_per_coarse, mwaf.flags.len_of(Axis(1))),
+            (
+                "NANTENNA",
+                corr_ctx.metafits_context.num_ants,
+                mwaf.num_ants,
+            ),
+        ] {
+            if expected != received {
+                return Err(MwafReadError::Mismatch {
+                    path,
+                    key,
+                    expected,
+                    received,
+                });
+            }
+        }
+        mwaf.or_into(flags, &timestamps, int_time, &sel_baselines)?;
+    }
+    Ok(())
+}
+
+/// Read the flags of the MWAF files for `vis_sel` into a new flag array with
+/// the shape of `vis_sel`'s flag array. See [`apply_mwaf_flags`].
+///
+/// # Errors
+///
+/// See [`apply_mwaf_flags`].
+#[cfg(feature = "mwalib")]
+pub fn read_mwaf_flags(
+    template: &str,
+    corr_ctx: &mwalib::CorrelatorContext,
+    vis_sel: &crate::VisSelection,
+) -> Result<Array3<bool>, MwafReadError> {
+    let shape = vis_sel.get_shape(corr_ctx.metafits_context.num_corr_fine_chans_per_coarse);
+    let mut flags = Array3::from_elem(shape, false);
+    apply_mwaf_flags(template, corr_ctx, vis_sel, flags.view_mut())?;
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fitsio::FitsFile;
+    use tempfile::tempdir;
+
+    /// Read the bits of a (zero-indexed) row of the `FLAGS` table.
+    fn read_flags_row(fptr: &mut FitsFile, row: usize, num_chans: usize) -> Vec<bool> {
+        let mut bits = vec![0; num_chans];
+        read_row_bits(unsafe { fptr.as_raw() }, row, &mut bits).unwrap();
+        bits.into_iter().map(|b| b != 0).collect()
+    }
+
+    #[test]
+    fn test_write_mwaf() {
+        let dir = tempdir().unwrap();
+        let template = dir.path().join("flags_%%.mwaf");
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 2,
+            start_timestamp: Epoch::from_gpst_seconds(1196175298.0),
+            int_time: Duration::from_seconds(2.0),
+            num_sel_chans: 4,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+        // Flag channel `t + b` of each timestep `t` and baseline `b`.
+        let flags = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| c == t + b);
+        let writer = MwafWriter::new(
+            template.to_str().unwrap(),
+            vec![9, 10],
+            2,
+            3,
+            1196175296,
+            None,
+        )
+        .unwrap();
+        writer.write_flags(flags.view(), &vis_ctx).unwrap();
+
+        let paths = writer.paths();
+        assert_eq!(paths[0], dir.path().join("flags_09.mwaf"));
+        assert_eq!(paths[1], dir.path().join("flags_10.mwaf"));
+        for (coarse_idx, path) in paths.iter().enumerate() {
+            let mut fptr = FitsFile::open(path).unwrap();
+            let hdu = fptr.primary_hdu().unwrap();
+            let version: String = hdu.read_key(&mut fptr, "VERSION").unwrap();
+            assert_eq!(version, "2.0");
+            let gpstime: i64 = hdu.read_key(&mut fptr, "GPSTIME").unwrap();
+            assert_eq!(gpstime, 1196175296);
+            let nscans: i64 = hdu.read_key(&mut fptr, "NSCANS").unwrap();
+            assert_eq!(nscans, 2);
+            let nchans: i64 = hdu.read_key(&mut fptr, "NCHANS").unwrap();
+            assert_eq!(nchans, 2);
+            let gpuboxno: i64 = hdu.read_key(&mut fptr, "GPUBOXNO").unwrap();
+            assert_eq!(gpuboxno, 9 + coarse_idx as i64);
+
+            let hdu = fptr.hdu("FLAGS").unwrap();
+            let naxis2: i64 = hdu.read_key(&mut fptr, "NAXIS2").unwrap();
+            // 6 baselines (including autos) for each of the 2 timesteps.
+            assert_eq!(naxis2, 12);
+            for t in 0..2 {
+                // Autos weren't selected, so they're flagged.
+                for ant in 0..3 {
+                    let row = t * 6 + mwaf_baseline_idx(ant, ant, 3);
+                    assert_eq!(read_flags_row(&mut fptr, row, 2), [true, true]);
+                }
+                for (b, &(ant1, ant2)) in vis_ctx.sel_baselines.iter().enumerate() {
+                    let row = t * 6 + mwaf_baseline_idx(ant1, ant2, 3);
+                    let expected = [0, 1].map(|c| coarse_idx * 2 + c == t + b);
+                    assert_eq!(read_flags_row(&mut fptr, row, 2), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mwaf_bad_template() {
+        assert!(matches!(
+            MwafWriter::new("flags.mwaf", vec![1], 2, 3, 1196175296, None),
+            Err(MwafWriteError::InvalidTemplate { .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_mwaf() {
+        let dir = tempdir().unwrap();
+        let template = dir.path().join("flags_%%.mwaf");
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 3,
+            start_timestamp: Epoch::from_gpst_seconds(1196175298.0),
+            int_time: Duration::from_seconds(2.0),
+            num_sel_chans: 2,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+        let flags = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| (t + c + b) % 3 == 0);
+        let writer =
+            MwafWriter::new(template.to_str().unwrap(), vec![1], 2, 3, 1196175296, None).unwrap();
+        writer.write_flags(flags.view(), &vis_ctx).unwrap();
+        let path = &writer.paths()[0];
+
+        let mwaf = MwafFlags::read(path).unwrap();
+        assert_eq!(mwaf.version, MwafVersion::Birli);
+        assert_eq!(mwaf.gpubox_num, 1);
+        assert_eq!(mwaf.num_ants, 3);
+        assert_eq!(mwaf.obs_id, Some(1196175296));
+        assert_eq!(mwaf.start_timestamp, vis_ctx.start_timestamp);
+        assert_eq!(mwaf.flags.dim(), (3, 2, 6));
+
+        // Read back the selected baselines, starting from the second timestep.
+        let timestamps = [1196175300.0, 1196175302.0, 1196175304.0].map(Epoch::from_gpst_seconds);
+        let mut read_flags = Array3::from_elem((3, 2, 3), false);
+        mwaf.or_into(
+            read_flags.view_mut(),
+            &timestamps,
+            vis_ctx.int_time,
+            &vis_ctx.sel_baselines,
+        )
+        .unwrap();
+        assert_eq!(
+            read_flags.slice(s![..2, .., ..]),
+            flags.slice(s![1.., .., ..])
+        );
+        // The last timestep isn't in the file.
+        assert!(read_flags.slice(s![2, .., ..]).iter().all(|&f| !f));
+
+        // A cotter file has the start time in GPSTIME.
+        {
+            let mut fptr = FitsFile::edit(path).unwrap();
+            let raw = unsafe { fptr.as_raw() };
+            fits_write_string(raw, "VERSION", "1.0", None).unwrap();
+            fits_write_int(raw, "GPSTIME", 1196175300, None).unwrap();
+        }
+        let mwaf = MwafFlags::read(path).unwrap();
+        assert_eq!(mwaf.version, MwafVersion::Cotter);
+        assert_eq!(mwaf.obs_id, None);
+        assert_eq!(mwaf.start_timestamp, timestamps[0]);
+    }
+}
@@ -28,7 +28,8 @@ use crate::{
     ndarray::{ArrayView3, Axis},
     num_complex::Complex,
     precession::{get_lmst, precess_time},
-    HADec, History, Jones, LatLngHeight, RADec, VisContext, XyzGeodetic, UVW,
+    FieldContext, HADec, History, Jones, LatLngHeight, RADec, VisContext, WeightScaling,
+    XyzGeodetic, UVW,
 };
 
 const NUM_FLOATS_PER_POL: usize = 3;
@@ -42,7 +43,7 @@ fn get_truncated_date_string(epoch: Epoch) -> String {
 }
 
 /// Helper function to convert strings into pointers of C strings.
-fn rust_strings_to_c_strings<T: AsRef<str>>(
+pub(super) fn rust_strings_to_c_strings<T: AsRef<str>>(
     strings: &[T],
 ) -> Result<Vec<*mut c_char>, std::ffi::NulError> {
     let mut c_strings = Vec::with_capacity(strings.len());
@@ -54,7 +55,7 @@ fn rust_strings_to_c_strings<T: AsRef<str>>(
     Ok(c_strings)
 }
 
-fn deallocate_rust_c_strings(c_string_ptrs: Vec<*mut c_char>) {
+pub(super) fn deallocate_rust_c_strings(c_string_ptrs: Vec<*mut c_char>) {
     unsafe {
         for ptr in c_string_ptrs {
             drop(CString::from_raw(ptr));
@@ -91,9 +92,27 @@ pub const fn decode_uvfits_baseline(bl: usize) -> (usize, usize) {
     }
 }
 
+/// The order in which random groups (rows) are written to a uvfits file.
+///
+/// Regardless of the order, the `DATE` of successive groups of a baseline always
+/// increases, and baselines are always in the same order within a timestep.
+/// Some readers (e.g. AIPS and older miriad converters) are strict about this.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UvfitsGroupOrder {
+    /// All baselines of a timestep are written before the next timestep. This is
+    /// what cotter does.
+    #[default]
+    TimeBaseline,
+
+    /// All timesteps of a baseline are written before the next baseline.
+    BaselineTime,
+}
+
 /// A helper struct to write out a uvfits file.
 ///
-/// Note: only a single contiguous spectral window is supported.
+/// Note: the channels are written as a single band, although it can be split
+/// into IFs at different frequencies (see [`UvfitsWriter::set_num_ifs`] and
+/// [`UvfitsWriter::set_if_freq_offsets_hz`]).
 pub struct UvfitsWriter {
     /// The path to the uvfits file.
     path: PathBuf,
@@ -116,6 +135,38 @@ pub struct UvfitsWriter {
     /// The number of uvfits rows that have currently been written.
     current_num_rows: usize,
 
+    /// The number of baselines in each timestep.
+    num_baselines: usize,
+
+    /// The number of (averaged) channels, across all IFs.
+    num_chans: usize,
+
+    /// The width of each channel \[Hz\].
+    fine_chan_width_hz: f64,
+
+    /// The number of IFs the channels are split into (see
+    /// [`UvfitsWriter::set_num_ifs`]).
+    num_ifs: usize,
+
+    /// The frequency offset \[Hz\] of each IF (see
+    /// [`UvfitsWriter::set_if_freq_offsets_hz`]), if the IFs aren't
+    /// contiguous.
+    if_freq_offsets_hz: Option<Vec<f64>>,
+
+    /// The order in which rows are written.
+    group_order: UvfitsGroupOrder,
+
+    /// The normalisation of the weights given to `write_vis`, which is kept
+    /// when averaging.
+    weight_scaling: WeightScaling,
+
+    /// The baselines of the visibilities written so far. These must be the
+    /// same for every call to `write_vis`.
+    written_baselines: Option<Vec<(usize, usize)>>,
+
+    /// The (centroid) timestamp of the last timestep written.
+    last_timestamp: Option<Epoch>,
+
     /// The center frequency of the center fine channel of the spectral
     /// window being written to this file. \[Hz\]
     ///
@@ -151,6 +202,12 @@ pub struct UvfitsWriter {
 
     /// Are we going to write out precessed UVWs?
     precess_uvws: bool,
+
+    /// The fields of a multi-field observation (see
+    /// [`UvfitsWriter::set_fields`]). If this is empty, there is no `SOURCE`
+    /// group parameter or `AIPS SU` table, and everything is phased to
+    /// `phase_centre`.
+    fields: Vec<FieldContext>,
 }
 
 impl UvfitsWriter {
@@ -195,6 +252,7 @@ impl UvfitsWriter {
     /// # Errors
     ///
     /// Will return an [`UvfitsWriteError`] if:
+    /// - `num_timesteps` or `num_baselines` is 0.
     /// - there is an existing file at `path` which cannot be removed.
     /// - a fits operation fails.
     ///
@@ -219,6 +277,14 @@ impl UvfitsWriter {
         precess_uvws: bool,
         history: Option<&History>,
     ) -> Result<UvfitsWriter, UvfitsWriteError> {
+        // Rows are located by dividing by the number of baselines.
+        if num_timesteps == 0 || num_baselines == 0 {
+            return Err(UvfitsWriteError::NoRows {
+                num_timesteps,
+                num_baselines,
+            });
+        }
+
         let path = path.as_ref();
         // Delete any file that already exists.
         if path.exists() {
@@ -245,10 +311,6 @@ impl UvfitsWriter {
         let num_group_params = GROUP_PARAMS.len() - if time_resolution.is_some() { 0 } else { 1 };
         let mut naxes = [0, NUM_FLOATS_PER_POL as i64, 4, num_chans as i64, 1, 1];
         let total_num_rows = num_timesteps * num_baselines;
-        assert!(
-            total_num_rows > 0,
-            "num_timesteps * num_baselines must be > 0"
-        );
         trace!("setting group params in fits file ({:?})", &path);
         unsafe {
             // ffphpr = fits_write_grphdr
@@ -350,26 +412,15 @@ impl UvfitsWriter {
         fits_write_history(fptr, "AIPS WTSCAL =  1.0")?;
 
         // Add in version information
-        let software = match history {
-            Some(History {
-                application: Some(app),
-                ..
-            }) => (*app).to_string(),
-            _ => format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
-        };
-
-        match history {
-            Some(history) => {
-                for comment in &history.as_comments() {
-                    fits_write_comment(fptr, comment)?;
-                }
-            }
-            None => {
-                fits_write_comment(fptr, &format!("Created by {software}",))?;
-            }
-        };
+        let history = history.cloned().unwrap_or_default();
+        for comment in &history.as_comments() {
+            fits_write_comment(fptr, comment)?;
+        }
+        for card in &history.as_history_cards(history.time_or_now()) {
+            fits_write_history(fptr, card)?;
+        }
 
-        fits_write_string(fptr, "SOFTWARE", &software, None)?;
+        fits_write_string(fptr, "SOFTWARE", &history.software(), None)?;
         fits_write_string(
             fptr,
             "GITLABEL",
@@ -383,6 +434,15 @@ impl UvfitsWriter {
             buffer: vec![],
             total_num_rows,
             current_num_rows: 0,
+            num_baselines,
+            num_chans,
+            fine_chan_width_hz,
+            num_ifs: 1,
+            if_freq_offsets_hz: None,
+            group_order: UvfitsGroupOrder::default(),
+            weight_scaling: WeightScaling::default(),
+            written_baselines: None,
+            last_timestamp: None,
             centre_freq: centre_freq_hz,
             start_epoch,
             phase_centre,
@@ -392,6 +452,7 @@ impl UvfitsWriter {
             dut1,
             time_res: time_resolution.map(|r| r.to_seconds()),
             precess_uvws,
+            fields: vec![],
         })
     }
 
@@ -433,6 +494,179 @@ impl UvfitsWriter {
         )
     }
 
+    /// Set the order in which rows are written. See [`UvfitsGroupOrder`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`UvfitsWriteError::GroupOrderAfterWrite`] if any rows have
+    /// already been written.
+    pub fn set_group_order(
+        &mut self,
+        group_order: UvfitsGroupOrder,
+    ) -> Result<(), UvfitsWriteError> {
+        if self.current_num_rows > 0 {
+            return Err(UvfitsWriteError::GroupOrderAfterWrite);
+        }
+        self.group_order = group_order;
+        Ok(())
+    }
+
+    /// Set the normalisation of the weights given to `write_vis`. The written
+    /// weights have the same normalisation at the averaged resolution.
+    pub fn set_weight_scaling(&mut self, weight_scaling: WeightScaling) {
+        self.weight_scaling = weight_scaling;
+    }
+
+    /// Phase each timestep to its field (see [`FieldContext::index_at`]),
+    /// rather than phasing everything to the phase centre given to
+    /// [`UvfitsWriter::new`]. This adds a `SOURCE` group parameter (the
+    /// one-indexed field of each row), and an `AIPS SU` table of the fields is
+    /// written after the antenna table.
+    ///
+    /// `fields` must be sorted by start time. The visibilities given to
+    /// `write_vis` must already be phased to the field of their timestep.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`UvfitsWriteError::FieldsAfterWrite`] if any rows have
+    /// already been written or the fields have already been set, or an error
+    /// if the group parameters can't be updated.
+    pub fn set_fields(&mut self, fields: Vec<FieldContext>) -> Result<(), UvfitsWriteError> {
+        if self.current_num_rows > 0 || !self.fields.is_empty() {
+            return Err(UvfitsWriteError::FieldsAfterWrite);
+        }
+        if fields.is_empty() {
+            return Ok(());
+        }
+
+        // Append the SOURCE group parameter, then have cfitsio re-read the
+        // structure of the (still empty) random groups.
+        let num_group_params = GROUP_PARAMS.len() - if self.time_res.is_some() { 0 } else { 1 };
+        let i = num_group_params + 1;
+        fits_write_int(self.fptr, "PCOUNT", i as i64, None)?;
+        fits_write_string(self.fptr, &format!("PTYPE{i}"), "SOURCE", None)?;
+        fits_write_double(self.fptr, &format!("PSCAL{i}"), 1.0, None)?;
+        fits_write_double(self.fptr, &format!("PZERO{i}"), 0.0, None)?;
+        // AIPS expects this of multi-source files.
+        fits_write_string(self.fptr, "OBJECT", "MULTI", None)?;
+        let mut status = 0;
+        unsafe {
+            // ffrdef = fits_set_hdustruc
+            fitsio_sys::ffrdef(self.fptr, &mut status);
+        }
+        fits_check_status(status)?;
+
+        self.fields = fields;
+        Ok(())
+    }
+
+    /// Split the channels into `num_ifs` IFs of equal width (e.g. one per
+    /// coarse channel), rather than a single flat frequency axis. This adds an
+    /// `IF` axis after the `FREQ` axis, and an `AIPS FQ` table of the IFs'
+    /// frequency offsets is written after the antenna table.
+    ///
+    /// The visibilities given to `write_vis` are unchanged; IF `i` has the
+    /// `i`th `num_chans / num_ifs` (averaged) channels. The `FREQ` axis keeps
+    /// the reference frequency and pixel given to [`UvfitsWriter::new`], so the
+    /// offset of IF `i` is `i` times the bandwidth of an IF, unless the offsets
+    /// are set with [`UvfitsWriter::set_if_freq_offsets_hz`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`UvfitsWriteError::IfsAfterWrite`] if any rows have
+    /// already been written or the IFs have already been set,
+    /// [`UvfitsWriteError::BadIfChans`] if the channels can't be split into
+    /// `num_ifs` IFs, or an error if the header can't be updated.
+    pub fn set_num_ifs(&mut self, num_ifs: usize) -> Result<(), UvfitsWriteError> {
+        if self.current_num_rows > 0 || self.num_ifs > 1 {
+            return Err(UvfitsWriteError::IfsAfterWrite);
+        }
+        if num_ifs == 0 || self.num_chans % num_ifs != 0 {
+            return Err(UvfitsWriteError::BadIfChans {
+                num_chans: self.num_chans,
+                num_ifs,
+            });
+        }
+        if num_ifs == 1 {
+            return Ok(());
+        }
+
+        // Insert the IF axis after the FREQ axis, moving the RA and DEC axes
+        // along. NAXISn keys must be in order, so NAXIS7 goes after NAXIS6.
+        let mut status = 0;
+        let naxis6 = CString::new("NAXIS6")?;
+        let naxis7 = CString::new("NAXIS7")?;
+        let mut card = [0; 81];
+        unsafe {
+            // ffgcrd = fits_read_card. This moves the header position to just
+            // after the card.
+            fitsio_sys::ffgcrd(
+                self.fptr,         /* I - FITS file pointer     */
+                naxis6.as_ptr(),   /* I - keyword name          */
+                card.as_mut_ptr(), /* O - card string           */
+                &mut status,       /* IO - error status         */
+            );
+            fits_check_status(status)?;
+            // ffikyj = fits_insert_key_lng
+            fitsio_sys::ffikyj(
+                self.fptr,        /* I - FITS file pointer     */
+                naxis7.as_ptr(),  /* I - keyword name          */
+                1,                /* I - keyword value         */
+                std::ptr::null(), /* I - keyword comment       */
+                &mut status,      /* IO - error status         */
+            );
+        }
+        fits_check_status(status)?;
+        fits_write_int(self.fptr, "NAXIS", 7, None)?;
+        fits_write_int(self.fptr, "NAXIS4", (self.num_chans / num_ifs) as i64, None)?;
+        fits_write_int(self.fptr, "NAXIS5", num_ifs as i64, None)?;
+        for (i, ctype, crval) in [
+            (5, "IF", 1.0),
+            (6, "RA", self.phase_centre.ra.to_degrees()),
+            (7, "DEC", self.phase_centre.dec.to_degrees()),
+        ] {
+            fits_write_string(self.fptr, &format!("CTYPE{i}"), ctype, None)?;
+            fits_write_double(self.fptr, &format!("CRVAL{i}"), crval, None)?;
+            fits_write_int(self.fptr, &format!("CDELT{i}"), 1, None)?;
+            fits_write_int(self.fptr, &format!("CRPIX{i}"), 1, None)?;
+        }
+        unsafe {
+            // ffrdef = fits_set_hdustruc
+            fitsio_sys::ffrdef(self.fptr, &mut status);
+        }
+        fits_check_status(status)?;
+
+        self.num_ifs = num_ifs;
+        Ok(())
+    }
+
+    /// Set the frequency offset \[Hz\] of each IF from the reference
+    /// frequency of the `FREQ` axis, which is written to the `IF FREQ` column
+    /// of the `AIPS FQ` table. This must be called after
+    /// [`UvfitsWriter::set_num_ifs`], and before [`UvfitsWriter::finalise`].
+    ///
+    /// By default, the IFs are contiguous, so the offset of IF `i` is `i`
+    /// times the bandwidth of an IF. This isn't the case for coarse channels
+    /// with gaps between them (e.g. a picket fence observation).
+    ///
+    /// # Errors
+    ///
+    /// Will return [`UvfitsWriteError::BadIfFreqs`] if there isn't an offset
+    /// per IF.
+    pub fn set_if_freq_offsets_hz(
+        &mut self,
+        if_freq_offsets_hz: Vec<f64>,
+    ) -> Result<(), UvfitsWriteError> {
+        if self.num_ifs < 2 || if_freq_offsets_hz.len() != self.num_ifs {
+            return Err(UvfitsWriteError::BadIfFreqs {
+                num_ifs: self.num_ifs,
+                received: if_freq_offsets_hz.len(),
+            });
+        }
+        self.if_freq_offsets_hz = Some(if_freq_offsets_hz);
+        Ok(())
+    }
+
     /// Write the antenna table to a uvfits file. This consumes the
     /// [`UvfitsWriter`], preventing any further modifications.
     ///
@@ -457,8 +691,20 @@ impl UvfitsWriter {
             "ANNAME", "STABXYZ", "NOSTA", "MNTSTA", "STAXOF", "POLTYA", "POLAA", "POLCALA",
             "POLTYB", "POLAB", "POLCALB",
         ];
+        // There are NOPCAL (3) pol calibration values per IF.
+        let polcal_format = format!("{}E", 3 * self.num_ifs);
         let col_formats = [
-            "8A", "3D", "1J", "1J", "1E", "1A", "1E", "3E", "1A", "1E", "3E",
+            "8A",
+            "3D",
+            "1J",
+            "1J",
+            "1E",
+            "1A",
+            "1E",
+            &polcal_format,
+            "1A",
+            "1E",
+            &polcal_format,
         ];
         let col_units = [
             "", "METERS", "", "", "METERS", "", "DEGREES", "", "", "DEGREES", "",
@@ -538,7 +784,12 @@ impl UvfitsWriter {
         fits_write_string(self.fptr, "ARRNAM", "MWA", None)?;
         fits_write_int(self.fptr, "NUMORB", 0, None)?; // number of orbital parameters in table
         fits_write_int(self.fptr, "NOPCAL", 3, None)?; // Nr pol calibration values / IF(N_pcal)
-        fits_write_int(self.fptr, "FREQID", -1, None)?; // Frequency setup number
+
+        // Frequency setup number; the FQ table has a single setup.
+        let freq_id = if self.num_ifs > 1 { 1 } else { -1 };
+        fits_write_int(self.fptr, "FREQID", freq_id, None)?;
+        // Cotter's (stale) value, kept so that these files match Cotter's.
+        // The FITS-IDI writer derives this from the date instead.
         fits_write_double(self.fptr, "IATUTC", 33.0, None)?;
 
         // -> EXTVER
@@ -557,10 +808,9 @@ impl UvfitsWriter {
         //  windows (IFs) in the data set. In the antenna file, this controls the dimension of the
         //  polarization calibration value column.
         // ---> in Cotter, this is not used.
-        // ---> since we can only deal with one spectral window at the moment,
-        //  this is fixed at 1, but this would change in
-        //  https://github.com/MWATelescope/Birli/issues/13
-        fits_write_int(self.fptr, "NO_IF", 1, None)?;
+        // ---> this is 1 unless the channels are split into IFs with
+        //  `set_num_ifs`.
+        fits_write_int(self.fptr, "NO_IF", self.num_ifs as i64, None)?;
 
         // Assume the station coordinates are "right handed".
         fits_write_string(self.fptr, "XYZHAND", "RIGHT", None)?;
@@ -708,6 +958,13 @@ impl UvfitsWriter {
             drop(CString::from_raw(y_c_str));
         }
 
+        if self.num_ifs > 1 {
+            self.write_uvfits_fq_table()?;
+        }
+        if !self.fields.is_empty() {
+            self.write_uvfits_source_table()?;
+        }
+
         // Close the fits file.
         trace!("closing fits file ({})", self.path.display());
         let mut status = 0;
@@ -720,6 +977,114 @@ impl UvfitsWriter {
         Ok(())
     }
 
+    /// Write the IFs to an `AIPS FQ` table, in a new HDU.
+    fn write_uvfits_fq_table(&mut self) -> Result<(), UvfitsWriteError> {
+        let num_ifs = self.num_ifs;
+        let if_bandwidth_hz = (self.num_chans / num_ifs) as f64 * self.fine_chan_width_hz;
+        let dbl_format = format!("{num_ifs}D");
+        let flt_format = format!("{num_ifs}E");
+        let int_format = format!("{num_ifs}J");
+        create_table(
+            self.fptr,
+            "AIPS FQ",
+            &[
+                ("FRQSEL", "1J", ""),
+                ("IF FREQ", &dbl_format, "HZ"),
+                ("CH WIDTH", &flt_format, "HZ"),
+                ("TOTAL BANDWIDTH", &flt_format, "HZ"),
+                ("SIDEBAND", &int_format, ""),
+            ],
+        )?;
+        fits_write_int(self.fptr, "EXTVER", 1, None)?;
+        fits_write_int(self.fptr, "NO_IF", num_ifs as i64, None)?;
+
+        let if_freqs = match &self.if_freq_offsets_hz {
+            Some(if_freq_offsets_hz) => if_freq_offsets_hz.clone(),
+            None => (0..num_ifs)
+                .map(|i| i as f64 * if_bandwidth_hz)
+                .collect::<Vec<_>>(),
+        };
+        write_col_int(self.fptr, 1, 0, &[1])?;
+        write_col_dbl(self.fptr, 2, 0, &if_freqs)?;
+        write_col_flt(
+            self.fptr,
+            3,
+            0,
+            &vec![self.fine_chan_width_hz as f32; num_ifs],
+        )?;
+        write_col_flt(self.fptr, 4, 0, &vec![if_bandwidth_hz as f32; num_ifs])?;
+        write_col_int(self.fptr, 5, 0, &vec![1; num_ifs])?;
+        Ok(())
+    }
+
+    /// Write the fields to an `AIPS SU` table, in a new HDU after the antenna
+    /// (and FQ) table.
+    fn write_uvfits_source_table(&mut self) -> Result<(), UvfitsWriteError> {
+        let num_ifs = self.num_ifs;
+        let dbl_format = format!("{num_ifs}D");
+        let flt_format = format!("{num_ifs}E");
+        create_table(
+            self.fptr,
+            "AIPS SU",
+            &[
+                ("ID. NO.", "1J", ""),
+                ("SOURCE", "20A", ""),
+                ("QUAL", "1J", ""),
+                ("CALCODE", "4A", ""),
+                ("IFLUX", &flt_format, "JY"),
+                ("QFLUX", &flt_format, "JY"),
+                ("UFLUX", &flt_format, "JY"),
+                ("VFLUX", &flt_format, "JY"),
+                ("FREQOFF", &dbl_format, "HZ"),
+                ("BANDWIDTH", "1D", "HZ"),
+                ("RAEPO", "1D", "DEGREES"),
+                ("DECEPO", "1D", "DEGREES"),
+                ("EPOCH", "1D", "YEARS"),
+                ("RAAPP", "1D", "DEGREES"),
+                ("DECAPP", "1D", "DEGREES"),
+                ("LSRVEL", &dbl_format, "M/SEC"),
+                ("RESTFREQ", &dbl_format, "HZ"),
+                ("PMRA", "1D", "DEG/DAY"),
+                ("PMDEC", "1D", "DEG/DAY"),
+            ],
+        )?;
+        fits_write_int(self.fptr, "EXTVER", 1, None)?;
+        fits_write_int(self.fptr, "NO_IF", num_ifs as i64, None)?;
+        fits_write_string(self.fptr, "VELTYP", "GEOCENTR", None)?;
+        fits_write_string(self.fptr, "VELDEF", "OPTICAL", None)?;
+        fits_write_int(self.fptr, "FREQID", 1, None)?;
+
+        for (row, field) in self.fields.iter().enumerate() {
+            let (ra, dec) = (
+                field.phase_centre.ra.to_degrees(),
+                field.phase_centre.dec.to_degrees(),
+            );
+            write_col_int(self.fptr, 1, row, &[row as i32 + 1])?;
+            write_col_str(self.fptr, 2, row, &field.name)?;
+            write_col_int(self.fptr, 3, row, &[0])?;
+            write_col_str(self.fptr, 4, row, "")?;
+            for col in 5..=8 {
+                write_col_flt(self.fptr, col, row, &vec![0.0; num_ifs])?;
+            }
+            write_col_dbl(self.fptr, 9, row, &vec![0.0; num_ifs])?;
+            write_col_dbl(self.fptr, 10, row, &[0.0])?;
+            write_col_dbl(self.fptr, 11, row, &[ra])?;
+            write_col_dbl(self.fptr, 12, row, &[dec])?;
+            write_col_dbl(self.fptr, 13, row, &[2000.0])?;
+            // Phase centres are J2000, and no apparent positions are
+            // calculated.
+            write_col_dbl(self.fptr, 14, row, &[ra])?;
+            write_col_dbl(self.fptr, 15, row, &[dec])?;
+            for col in 16..=17 {
+                write_col_dbl(self.fptr, col, row, &vec![0.0; num_ifs])?;
+            }
+            for col in 18..=19 {
+                write_col_dbl(self.fptr, col, row, &[0.0])?;
+            }
+        }
+        Ok(())
+    }
+
     /// Write a visibility row into the uvfits file.
     ///
     /// `tile_index1` and `tile_index2` are expected to be zero indexed; they
@@ -813,32 +1178,34 @@ impl UvfitsWriter {
 
         self.buffer.extend_from_slice(vis);
 
-        Self::write_vis_row_inner(self.fptr, &mut self.current_num_rows, &mut self.buffer)?;
+        Self::write_vis_row_inner(self.fptr, self.current_num_rows, &mut self.buffer)?;
+        self.current_num_rows += 1;
 
         self.buffer.clear();
         Ok(())
     }
 
+    /// Write a row of group parameters and visibilities to the (zero-indexed)
+    /// `row_idx`th group.
     #[inline(always)]
     fn write_vis_row_inner(
         fptr: *mut fitsio_sys::fitsfile,
-        current_num_rows: &mut usize,
+        row_idx: usize,
         vis: &mut [f32],
     ) -> Result<(), fitsio::errors::Error> {
         let mut status = 0;
         unsafe {
             // ffpgpe = fits_write_grppar_flt
             fitsio_sys::ffpgpe(
-                fptr,                         /* I - FITS file pointer                      */
-                *current_num_rows as i64 + 1, /* I - group to write(1 = 1st group)          */
-                1,                            /* I - first vector element to write(1 = 1st) */
-                vis.len() as i64,             /* I - number of values to write              */
-                vis.as_mut_ptr(),             /* I - array of values that are written       */
-                &mut status,                  /* IO - error status                          */
+                fptr,               /* I - FITS file pointer                      */
+                row_idx as i64 + 1, /* I - group to write(1 = 1st group)          */
+                1,                  /* I - first vector element to write(1 = 1st) */
+                vis.len() as i64,   /* I - number of values to write              */
+                vis.as_mut_ptr(),   /* I - array of values that are written       */
+                &mut status,        /* IO - error status                          */
             );
         }
         fits_check_status(status)?;
-        *current_num_rows += 1;
         Ok(())
     }
 
@@ -899,9 +1266,35 @@ impl VisWrite for UvfitsWriter {
             self.total_num_rows
         );
 
+        // Every chunk must have the same baselines in the same order, so that
+        // rows can be located (and readers see the same order each timestep).
+        match self.written_baselines.as_ref() {
+            Some(written) if written != &vis_ctx.sel_baselines => {
+                return Err(UvfitsWriteError::InconsistentBaselines.into());
+            }
+            Some(_) => (),
+            None => {
+                if vis_ctx.sel_baselines.len() != self.num_baselines {
+                    return Err(UvfitsWriteError::InconsistentBaselines.into());
+                }
+                self.written_baselines = Some(vis_ctx.sel_baselines.clone());
+            }
+        }
+        // The DATE of each baseline's rows must be monotonically increasing.
+        if let (Some(previous), Some(received)) =
+            (self.last_timestamp, vis_ctx.timeseries(true, true).next())
+        {
+            if received <= previous {
+                return Err(UvfitsWriteError::NonMonotonicTime { previous, received }.into());
+            }
+        }
+        let num_total_timesteps = self.total_num_rows / self.num_baselines;
+        let first_timestep_idx = self.current_num_rows / self.num_baselines;
+
         // Ensure our buffer is the correct size. Reusing the buffer means we
         // avoid a heap allocation every time this function is called.
-        let num_group_params = GROUP_PARAMS.len() - if self.time_res.is_some() { 0 } else { 1 };
+        let num_group_params = GROUP_PARAMS.len() - usize::from(self.time_res.is_none())
+            + usize::from(!self.fields.is_empty());
         self.buffer.resize(
             num_group_params + NUM_FLOATS_PER_POL * num_vis_pols * num_avg_chans,
             0.0,
@@ -952,6 +1345,8 @@ impl VisWrite for UvfitsWriter {
         let i_baseline = i_baseline.expect("is set");
         let i_date1 = i_date1.expect("is set");
         let i_date2 = i_date2.expect("is set");
+        // The SOURCE group parameter is always last.
+        let i_source = (!self.fields.is_empty()).then_some(num_group_params - 1);
 
         let mut avg_weight: f32;
         let mut avg_flag: bool;
@@ -959,11 +1354,13 @@ impl VisWrite for UvfitsWriter {
 
         let jd_trunc = Epoch::from_jde_utc(self.start_epoch.to_jde_utc_days().floor() + 0.5);
 
-        for (avg_centroid_timestamp, jones_chunk, weight_chunk) in izip!(
+        for (timestep_idx, avg_centroid_timestamp, jones_chunk, weight_chunk) in izip!(
+            first_timestep_idx..,
             vis_ctx.timeseries(true, true),
             vis.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
             weights.axis_chunks_iter(Axis(0), vis_ctx.avg_time),
         ) {
+            self.last_timestamp = Some(avg_centroid_timestamp);
             let jd_frac = avg_centroid_timestamp - jd_trunc;
             let jd_frac_f32 = jd_frac.to_unit(Unit::Day) as f32;
             let jd_remainder_f32 =
@@ -972,11 +1369,20 @@ impl VisWrite for UvfitsWriter {
             self.buffer[i_date1] = jd_frac_f32;
             self.buffer[i_date2] = jd_remainder_f32;
 
+            let phase_centre = match i_source {
+                Some(i_source) => {
+                    let field_idx = FieldContext::index_at(&self.fields, avg_centroid_timestamp);
+                    self.buffer[i_source] = (field_idx + 1) as f32;
+                    self.fields[field_idx].phase_centre
+                }
+                None => self.phase_centre,
+            };
+
             let (tile_xyzs, hadec): (Cow<[XyzGeodetic]>, HADec) = if self.precess_uvws {
                 let prec_info = precess_time(
                     self.array_pos.longitude_rad,
                     self.array_pos.latitude_rad,
-                    self.phase_centre,
+                    phase_centre,
                     avg_centroid_timestamp,
                     self.dut1,
                 );
@@ -990,11 +1396,12 @@ impl VisWrite for UvfitsWriter {
                     avg_centroid_timestamp,
                     self.dut1,
                 );
-                let hadec = self.phase_centre.to_hadec(lmst);
+                let hadec = phase_centre.to_hadec(lmst);
                 (self.antenna_positions.as_slice().into(), hadec)
             };
 
-            for ((ant1_idx, ant2_idx), jones_chunk, weight_chunk) in izip!(
+            for (baseline_idx, (ant1_idx, ant2_idx), jones_chunk, weight_chunk) in izip!(
+                0..,
                 vis_ctx.sel_baselines.iter().copied(),
                 jones_chunk.axis_iter(Axis(2)),
                 weight_chunk.axis_iter(Axis(2)),
@@ -1027,6 +1434,12 @@ impl VisWrite for UvfitsWriter {
                             avg_weight,
                             avg_flag
                         );
+                        // The last chunks of timesteps and channels may be
+                        // short.
+                        avg_weight *= self.weight_scaling.averaging_correction(
+                            weight_chunk.len_of(Axis(0)),
+                            weight_chunk.len_of(Axis(1)),
+                        ) as f32;
                     }
 
                     // vis_chunk has 12 elements if num_vis_pols is 4, but, it
@@ -1054,7 +1467,16 @@ impl VisWrite for UvfitsWriter {
                         });
                 }
 
-                Self::write_vis_row_inner(self.fptr, &mut self.current_num_rows, &mut self.buffer)?;
+                let row_idx = match self.group_order {
+                    UvfitsGroupOrder::TimeBaseline => {
+                        timestep_idx * self.num_baselines + baseline_idx
+                    }
+                    UvfitsGroupOrder::BaselineTime => {
+                        baseline_idx * num_total_timesteps + timestep_idx
+                    }
+                };
+                Self::write_vis_row_inner(self.fptr, row_idx, &mut self.buffer)?;
+                self.current_num_rows += 1;
             }
         }
 
@@ -1067,7 +1489,7 @@ impl VisWrite for UvfitsWriter {
     }
 }
 
-fn fits_write_int(
+pub(super) fn fits_write_int(
     fptr: *mut fitsio_sys::fitsfile,
     keyname: &str,
     value: i64,
@@ -1093,7 +1515,7 @@ fn fits_write_int(
     Ok(())
 }
 
-fn fits_write_double(
+pub(super) fn fits_write_double(
     fptr: *mut fitsio_sys::fitsfile,
     keyname: &str,
     value: f64,
@@ -1121,7 +1543,7 @@ fn fits_write_double(
     Ok(())
 }
 
-fn fits_write_string(
+pub(super) fn fits_write_string(
     fptr: *mut fitsio_sys::fitsfile,
     keyname: &str,
     value: &str,
@@ -1148,7 +1570,7 @@ fn fits_write_string(
     Ok(())
 }
 
-fn fits_write_comment(
+pub(super) fn fits_write_comment(
     fptr: *mut fitsio_sys::fitsfile,
     comment: &str,
 ) -> Result<(), FitsioOrCStringError> {
@@ -1166,7 +1588,144 @@ fn fits_write_comment(
     Ok(())
 }
 
-fn fits_write_history(
+/// Create a binary table (with `(name, format, unit)` columns) in a new HDU,
+/// which becomes the current HDU.
+pub(super) fn create_table(
+    fptr: *mut fitsio_sys::fitsfile,
+    extname: &str,
+    columns: &[(&str, &str, &str)],
+) -> Result<(), FitsioOrCStringError> {
+    let names: Vec<&str> = columns.iter().map(|c| c.0).collect();
+    let formats: Vec<&str> = columns.iter().map(|c| c.1).collect();
+    let units: Vec<&str> = columns.iter().map(|c| c.2).collect();
+    let mut c_names = rust_strings_to_c_strings(&names)?;
+    let mut c_formats = rust_strings_to_c_strings(&formats)?;
+    let mut c_units = rust_strings_to_c_strings(&units)?;
+    let extname = CString::new(extname)?;
+
+    let mut status = 0;
+    unsafe {
+        // ffcrtb = fits_create_tbl. BINARY_TBL is 2.
+        fitsio_sys::ffcrtb(
+            fptr,                   /* I - FITS file pointer                        */
+            2,                      /* I - type of table to create                  */
+            0,                      /* I - number of rows in the table              */
+            columns.len() as _,     /* I - number of columns in the table           */
+            c_names.as_mut_ptr(),   /* I - name of each column                      */
+            c_formats.as_mut_ptr(), /* I - value of TFORMn keyword for each column  */
+            c_units.as_mut_ptr(),   /* I - value of TUNITn keyword for each column  */
+            extname.as_ptr(),       /* I - value of EXTNAME keyword, if any         */
+            &mut status,            /* IO - error status                            */
+        );
+    }
+    deallocate_rust_c_strings(c_names);
+    deallocate_rust_c_strings(c_formats);
+    deallocate_rust_c_strings(c_units);
+    fits_check_status(status)?;
+    Ok(())
+}
+
+/// Write `values` to the (one-indexed) column `col` of the (zero-indexed) row
+/// `row` of the current table.
+pub(super) fn write_col_dbl(
+    fptr: *mut fitsio_sys::fitsfile,
+    col: i32,
+    row: usize,
+    values: &[f64],
+) -> Result<(), fitsio::errors::Error> {
+    let mut values = values.to_vec();
+    let mut status = 0;
+    unsafe {
+        // ffpcld = fits_write_col_dbl
+        fitsio_sys::ffpcld(
+            fptr,                /* I - FITS file pointer                       */
+            col,                 /* I - number of column to write (1 = 1st col) */
+            row as i64 + 1,      /* I - first row to write (1 = 1st row)        */
+            1,                   /* I - first vector element to write (1 = 1st) */
+            values.len() as i64, /* I - number of values to write               */
+            values.as_mut_ptr(), /* I - array of values to write                */
+            &mut status,         /* IO - error status                           */
+        );
+    }
+    fits_check_status(status)
+}
+
+/// See [`write_col_dbl`].
+pub(super) fn write_col_flt(
+    fptr: *mut fitsio_sys::fitsfile,
+    col: i32,
+    row: usize,
+    values: &[f32],
+) -> Result<(), fitsio::errors::Error> {
+    let mut values = values.to_vec();
+    let mut status = 0;
+    unsafe {
+        // ffpcle = fits_write_col_flt
+        fitsio_sys::ffpcle(
+            fptr,                /* I - FITS file pointer                       */
+            col,                 /* I - number of column to write (1 = 1st col) */
+            row as i64 + 1,      /* I - first row to write (1 = 1st row)        */
+            1,                   /* I - first vector element to write (1 = 1st) */
+            values.len() as i64, /* I - number of values to write               */
+            values.as_mut_ptr(), /* I - array of values to write                */
+            &mut status,         /* IO - error status                           */
+        );
+    }
+    fits_check_status(status)
+}
+
+/// See [`write_col_dbl`].
+pub(super) fn write_col_int(
+    fptr: *mut fitsio_sys::fitsfile,
+    col: i32,
+    row: usize,
+    values: &[i32],
+) -> Result<(), fitsio::errors::Error> {
+    let mut values = values.to_vec();
+    let mut status = 0;
+    unsafe {
+        // ffpclk = fits_write_col_int
+        fitsio_sys::ffpclk(
+            fptr,                /* I - FITS file pointer                       */
+            col,                 /* I - number of column to write (1 = 1st col) */
+            row as i64 + 1,      /* I - first row to write (1 = 1st row)        */
+            1,                   /* I - first vector element to write (1 = 1st) */
+            values.len() as i64, /* I - number of values to write               */
+            values.as_mut_ptr(), /* I - array of values to write                */
+            &mut status,         /* IO - error status                           */
+        );
+    }
+    fits_check_status(status)
+}
+
+/// Write a string to the (one-indexed) column `col` of the (zero-indexed) row
+/// `row` of the current table.
+pub(super) fn write_col_str(
+    fptr: *mut fitsio_sys::fitsfile,
+    col: i32,
+    row: usize,
+    value: &str,
+) -> Result<(), FitsioOrCStringError> {
+    let mut c_value = CString::new(value)?.into_raw();
+    let mut status = 0;
+    unsafe {
+        // ffpcls = fits_write_col_str
+        fitsio_sys::ffpcls(
+            fptr,           /* I - FITS file pointer                       */
+            col,            /* I - number of column to write (1 = 1st col) */
+            row as i64 + 1, /* I - first row to write (1 = 1st row)        */
+            1,              /* I - first vector element to write (1 = 1st) */
+            1,              /* I - number of strings to write              */
+            &mut c_value,   /* I - array of pointers to strings            */
+            &mut status,    /* IO - error status                           */
+        );
+        drop(CString::from_raw(c_value));
+    }
+    fits_check_status(status)?;
+    Ok(())
+}
+
+pub(super) fn fits_write_history(
     fptr: *mut fitsio_sys::fitsfile,
     history: &str,
 ) -> Result<(), FitsioOrCStringError> {
@@ -2465,7 +3024,7 @@ mod tests {
                 /1196175296_20171201145540_gpubox01_01.fits\" \"tests/data/1196175296_mwa_\
                 ord/1196175296_20171201145540_gpubox02_01.fits\""
             ),
-            message: None
+            ..Default::default()
         };
 
         let (names, positions): (Vec<String>, Vec<XyzGeodetic>) = corr_ctx
@@ -2830,4 +3389,400 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_new_uvfits_no_rows() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let names: Vec<String> = vec!["Tile1".into(), "Tile2".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+        for (num_timesteps, num_baselines) in [(0, 1), (1, 0)] {
+            let result = UvfitsWriter::new(
+                tmp_uvfits_file.path(),
+                num_timesteps,
+                num_baselines,
+                2,
+                Epoch::from_gpst_seconds(1065880128.0),
+                None,
+                40e3,
+                170e6,
+                1,
+                RADec::from_degrees(0.0, 60.0),
+                Some("test"),
+                LatLngHeight::mwa(),
+                names.clone(),
+                positions.clone(),
+                Duration::default(),
+                false,
+                None,
+            );
+            assert!(matches!(result, Err(UvfitsWriteError::NoRows { .. })));
+        }
+    }
+
+    #[test]
+    fn test_group_order_baseline_time() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let num_timesteps = 2;
+        let num_baselines = 3;
+        let num_chans = 2;
+        let corr_ctx = get_mwa_legacy_context();
+        let vis_ctxs: Vec<VisContext> = (0..num_timesteps)
+            .map(|t| VisContext::from_mwalib(&corr_ctx, &(t..t + 1), &(0..1), &[0, 1, 2], 1, 1))
+            .collect();
+        let start_epoch = vis_ctxs[0].start_timestamp;
+        let names = vec!["Tile1".into(), "Tile2".into(), "Tile3".into()];
+        let positions = vec![XyzGeodetic::default(); names.len()];
+        let new_writer = |path: &Path| {
+            UvfitsWriter::new(
+                path,
+                num_timesteps,
+                num_baselines,
+                num_chans,
+                start_epoch,
+                None,
+                40e3,
+                170e6,
+                1,
+                RADec::from_degrees(0.0, 60.0),
+                Some("test"),
+                LatLngHeight::mwa(),
+                names.clone(),
+                positions.clone(),
+                Duration::default(),
+                false,
+                None,
+            )
+            .unwrap()
+        };
+
+        let vis = Array3::<Jones<f32>>::default((1, num_chans, num_baselines));
+        let weights = Array3::<f32>::ones((1, num_chans, num_baselines));
+
+        let mut u = new_writer(tmp_uvfits_file.path());
+        u.set_group_order(UvfitsGroupOrder::BaselineTime).unwrap();
+        for vis_ctx in &vis_ctxs {
+            u.write_vis(vis.view(), weights.view(), vis_ctx).unwrap();
+        }
+        assert!(matches!(
+            u.set_group_order(UvfitsGroupOrder::TimeBaseline),
+            Err(UvfitsWriteError::GroupOrderAfterWrite)
+        ));
+        u.finalise().unwrap();
+
+        // Each baseline's timesteps should be contiguous, and in time order.
+        let mut fptr = fits_open!(tmp_uvfits_file.path()).unwrap();
+        let mut group_params: Vec<Vec<f32>> = vec![];
+        for row_idx in 0..num_timesteps * num_baselines {
+            let mut params = vec![0.0; GROUP_PARAMS.len() - 1];
+            let mut status = 0;
+            unsafe {
+                // ffggpe = fits_read_grppar_flt
+                fitsio_sys::ffggpe(
+                    fptr.as_raw(),       /* I - FITS file pointer                       */
+                    1 + row_idx as i64,  /* I - group to read (1 = 1st group)           */
+                    1,                   /* I - first vector element to read (1 = 1st)  */
+                    params.len() as i64, /* I - number of values to read                */
+                    params.as_mut_ptr(), /* O - array of values that are returned       */
+                    &mut status,         /* IO - error status                           */
+                );
+            }
+            fits_check_status(status).unwrap();
+            group_params.push(params);
+        }
+        let (i_baseline, i_date) = (3, 4);
+        for (baseline_idx, rows) in group_params.chunks(num_timesteps).enumerate() {
+            let (ant1, ant2) = vis_ctxs[0].sel_baselines[baseline_idx];
+            for row in rows {
+                assert_abs_diff_eq!(
+                    row[i_baseline],
+                    encode_uvfits_baseline(ant1 + 1, ant2 + 1) as f32
+                );
+            }
+            assert!(rows[1][i_date] > rows[0][i_date]);
+        }
+
+        // Writing timesteps out of order is an error.
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let mut u = new_writer(tmp_uvfits_file.path());
+        u.write_vis(vis.view(), weights.view(), &vis_ctxs[1])
+            .unwrap();
+        assert!(matches!(
+            u.write_vis(vis.view(), weights.view(), &vis_ctxs[0]),
+            Err(IOError::UvfitsWriteError(
+                UvfitsWriteError::NonMonotonicTime { .. }
+            ))
+        ));
+        u.close().unwrap();
+    }
+
+    #[test]
+    fn test_write_fields() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let num_timesteps = 2;
+        let num_baselines = 3;
+        let num_chans = 2;
+        let corr_ctx = get_mwa_legacy_context();
+        let vis_ctx = VisContext::from_mwalib(&corr_ctx, &(0..2), &(0..1), &[0, 1, 2], 1, 1);
+        let start_epoch = vis_ctx.start_timestamp;
+        let names = vec!["Tile1".into(), "Tile2".into(), "Tile3".into()];
+        let positions = vec![
+            XyzGeodetic::default(),
+            XyzGeodetic {
+                x: 1.,
+                y: 2.,
+                z: 0.,
+            },
+            XyzGeodetic {
+                x: 3.,
+                y: 0.,
+                z: 1.,
+            },
+        ];
+        let mut u = UvfitsWriter::new(
+            tmp_uvfits_file.path(),
+            num_timesteps,
+            num_baselines,
+            num_chans,
+            start_epoch,
+            None,
+            40e3,
+            170e6,
+            1,
+            RADec::from_degrees(0.0, -27.0),
+            Some("test"),
+            LatLngHeight::mwa(),
+            names,
+            positions,
+            Duration::default(),
+            false,
+            None,
+        )
+        .unwrap();
+        let fields = vec![
+            FieldContext {
+                name: "east".into(),
+                phase_centre: RADec::from_degrees(0.0, -27.0),
+                start_timestamp: start_epoch,
+            },
+            FieldContext {
+                name: "west".into(),
+                phase_centre: RADec::from_degrees(30.0, -27.0),
+                start_timestamp: start_epoch + vis_ctx.int_time,
+            },
+        ];
+        u.set_fields(fields).unwrap();
+
+        let vis = Array3::<Jones<f32>>::default(vis_ctx.sel_dims());
+        let weights = Array3::<f32>::ones(vis_ctx.sel_dims());
+        u.write_vis(vis.view(), weights.view(), &vis_ctx).unwrap();
+        assert!(matches!(
+            u.set_fields(vec![]),
+            Err(UvfitsWriteError::FieldsAfterWrite)
+        ));
+        u.finalise().unwrap();
+
+        let mut fptr = fits_open!(tmp_uvfits_file.path()).unwrap();
+        let hdu = fits_open_hdu!(&mut fptr, 0).unwrap();
+        let pcount: usize = get_required_fits_key!(&mut fptr, &hdu, "PCOUNT").unwrap();
+        assert_eq!(pcount, GROUP_PARAMS.len());
+        let ptype: String =
+            get_required_fits_key!(&mut fptr, &hdu, format!("PTYPE{pcount}").as_str()).unwrap();
+        assert_eq!(ptype, "SOURCE");
+        let object: String = get_required_fits_key!(&mut fptr, &hdu, "OBJECT").unwrap();
+        assert_eq!(object, "MULTI");
+
+        let mut sources = vec![];
+        let mut uus = vec![];
+        for row_idx in 0..num_timesteps * num_baselines {
+            let mut params = vec![0.0; pcount];
+            let mut status = 0;
+            unsafe {
+                // ffggpe = fits_read_grppar_flt
+                fitsio_sys::ffggpe(
+                    fptr.as_raw(),       /* I - FITS file pointer                       */
+                    1 + row_idx as i64,  /* I - group to read (1 = 1st group)           */
+                    1,                   /* I - first vector element to read (1 = 1st)  */
+                    params.len() as i64, /* I - number of values to read                */
+                    params.as_mut_ptr(), /* O - array of values that are returned       */
+                    &mut status,         /* IO - error status                           */
+                );
+            }
+            fits_check_status(status).unwrap();
+            uus.push(params[0]);
+            sources.push(params[pcount - 1]);
+        }
+        assert_eq!(sources, [1.0, 1.0, 1.0, 2.0, 2.0, 2.0]);
+        // The UVWs are relative to each field's phase centre.
+        assert!(!abs_diff_eq!(uus[0], uus[3], epsilon = 1e-10));
+
+        let su_hdu = fits_open_hdu!(&mut fptr, 2).unwrap();
+        let su_names: Vec<String> = get_fits_col!(&mut fptr, &su_hdu, "SOURCE").unwrap();
+        assert_eq!(su_names, ["east", "west"]);
+        let su_ras: Vec<f64> = get_fits_col!(&mut fptr, &su_hdu, "RAEPO").unwrap();
+        assert_abs_diff_eq!(su_ras[1], 30.0);
+    }
+
+    #[test]
+    fn test_write_ifs() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let corr_ctx = get_mwa_legacy_context();
+        let vis_ctx = VisContext::from_mwalib(&corr_ctx, &(0..1), &(0..2), &[0, 1, 2], 1, 1);
+        let (num_timesteps, num_chans, num_baselines) = vis_ctx.sel_dims();
+        let num_ifs = 2;
+        let chans_per_if = num_chans / num_ifs;
+        let fine_chan_width_hz = vis_ctx.freq_resolution_hz;
+        let names = vec!["Tile1".into(), "Tile2".into(), "Tile3".into()];
+        let positions = vec![XyzGeodetic::default(); 3];
+        let mut u = UvfitsWriter::new(
+            tmp_uvfits_file.path(),
+            num_timesteps,
+            num_baselines,
+            num_chans,
+            vis_ctx.start_timestamp,
+            None,
+            fine_chan_width_hz,
+            170e6,
+            0,
+            RADec::from_degrees(0.0, -27.0),
+            Some("test"),
+            LatLngHeight::mwa(),
+            names,
+            positions,
+            Duration::default(),
+            false,
+            None,
+        )
+        .unwrap();
+        assert!(matches!(
+            u.set_num_ifs(num_chans + 1),
+            Err(UvfitsWriteError::BadIfChans { .. })
+        ));
+        u.set_num_ifs(num_ifs).unwrap();
+        assert!(matches!(
+            u.set_num_ifs(num_ifs),
+            Err(UvfitsWriteError::IfsAfterWrite)
+        ));
+
+        let vis = Array3::<Jones<f32>>::default(vis_ctx.sel_dims());
+        let weights = Array3::<f32>::ones(vis_ctx.sel_dims());
+        u.write_vis(vis.view(), weights.view(), &vis_ctx).unwrap();
+        u.finalise().unwrap();
+
+        let mut fptr = fits_open!(tmp_uvfits_file.path()).unwrap();
+        let hdu = fits_open_hdu!(&mut fptr, 0).unwrap();
+        let naxis: usize = get_required_fits_key!(&mut fptr, &hdu, "NAXIS").unwrap();
+        assert_eq!(naxis, 7);
+        let naxis4: usize = get_required_fits_key!(&mut fptr, &hdu, "NAXIS4").unwrap();
+        assert_eq!(naxis4, chans_per_if);
+        let naxis5: usize = get_required_fits_key!(&mut fptr, &hdu, "NAXIS5").unwrap();
+        assert_eq!(naxis5, num_ifs);
+        let naxis7: usize = get_required_fits_key!(&mut fptr, &hdu, "NAXIS7").unwrap();
+        assert_eq!(naxis7, 1);
+        for (key, expected) in [("CTYPE5", "IF"), ("CTYPE6", "RA"), ("CTYPE7", "DEC")] {
+            let ctype: String = get_required_fits_key!(&mut fptr, &hdu, key).unwrap();
+            assert_eq!(ctype, expected);
+        }
+
+        let an_hdu = fits_open_hdu!(&mut fptr, 1).unwrap();
+        let no_if: usize = get_required_fits_key!(&mut fptr, &an_hdu, "NO_IF").unwrap();
+        assert_eq!(no_if, num_ifs);
+
+        let fq_hdu = fits_open_hdu!(&mut fptr, 2).unwrap();
+        let extname: String = get_required_fits_key!(&mut fptr, &fq_hdu, "EXTNAME").unwrap();
+        assert_eq!(extname, "AIPS FQ");
+        let mut if_freqs = vec![0.0; num_ifs];
+        let mut status = 0;
+        unsafe {
+            // ffgcvd = fits_read_col_dbl
+            fitsio_sys::ffgcvd(
+                fptr.as_raw(),         /* I - FITS file pointer                       */
+                2,                     /* I - number of column to read (1 = 1st col)  */
+                1,                     /* I - first row to read (1 = 1st row)         */
+                1,                     /* I - first vector element to read (1 = 1st)  */
+                num_ifs as i64,        /* I - number of values to read                */
+                0.0,                   /* I - value for null pixels                   */
+                if_freqs.as_mut_ptr(), /* O - array of values that are read           */
+                std::ptr::null_mut(),  /* O - set to 1 if any values are null; else 0 */
+                &mut status,           /* IO - error status                           */
+            );
+        }
+        fits_check_status(status).unwrap();
+        assert_abs_diff_eq!(if_freqs[0], 0.0);
+        assert_abs_diff_eq!(
+            if_freqs[1],
+            chans_per_if as f64 * fine_chan_width_hz,
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn test_write_if_freq_offsets() {
+        let tmp_uvfits_file = NamedTempFile::new().unwrap();
+        let corr_ctx = get_mwa_legacy_context();
+        let vis_ctx = VisContext::from_mwalib(&corr_ctx, &(0..1), &(0..2), &[0, 1, 2], 1, 1);
+        let (num_timesteps, num_chans, num_baselines) = vis_ctx.sel_dims();
+        let names = vec!["Tile1".into(), "Tile2".into(), "Tile3".into()];
+        let positions = vec![XyzGeodetic::default(); 3];
+        let mut u = UvfitsWriter::new(
+            tmp_uvfits_file.path(),
+            num_timesteps,
+            num_baselines,
+            num_chans,
+            vis_ctx.start_timestamp,
+            None,
+            vis_ctx.freq_resolution_hz,
+            170e6,
+            0,
+            RADec::from_degrees(0.0, -27.0),
+            Some("test"),
+            LatLngHeight::mwa(),
+            names,
+            positions,
+            Duration::default(),
+            false,
+            None,
+        )
+        .unwrap();
+        // The IFs must be set first.
+        assert!(matches!(
+            u.set_if_freq_offsets_hz(vec![0.0, 2.56e6]),
+            Err(UvfitsWriteError::BadIfFreqs { .. })
+        ));
+        u.set_num_ifs(2).unwrap();
+        assert!(matches!(
+            u.set_if_freq_offsets_hz(vec![0.0]),
+            Err(UvfitsWriteError::BadIfFreqs {
+                num_ifs: 2,
+                received: 1
+            })
+        ));
+        // A gap of a coarse channel between the IFs.
+        u.set_if_freq_offsets_hz(vec![0.0, 2.56e6]).unwrap();
+
+        let vis = Array3::<Jones<f32>>::default(vis_ctx.sel_dims());
+        let weights = Array3::<f32>::ones(vis_ctx.sel_dims());
+        u.write_vis(vis.view(), weights.view(), &vis_ctx).unwrap();
+        u.finalise().unwrap();
+
+        let mut fptr = fits_open!(tmp_uvfits_file.path()).unwrap();
+        let _fq_hdu = fits_open_hdu!(&mut fptr, 2).unwrap();
+        let mut if_freqs = vec![0.0; 2];
+        let mut status = 0;
+        unsafe {
+            // ffgcvd = fits_read_col_dbl
+            fitsio_sys::ffgcvd(
+                fptr.as_raw(),         /* I - FITS file pointer                       */
+                2,                     /* I - number of column to read (1 = 1st col)  */
+                1,                     /* I - first row to read (1 = 1st row)         */
+                1,                     /* I - first vector element to read (1 = 1st)  */
+                2,                     /* I - number of values to read                */
+                0.0,                   /* I - value for null pixels                   */
+                if_freqs.as_mut_ptr(), /* O - array of values that are read           */
+                std::ptr::null_mut(),  /* O - set to 1 if any values are null; else 0 */
+                &mut status,           /* IO - error status                           */
+            );
+        }
+        fits_check_status(status).unwrap();
+        assert_abs_diff_eq!(if_freqs[..], [0.0, 2.56e6][..]);
+    }
 }
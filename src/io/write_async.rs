@@ -0,0 +1,320 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Write visibilities on a separate I/O thread, so that writing doesn't
+//! serialise against computation.
+//!
+//! [`VisWriteAsync`] wraps any [`VisWrite`] implementor. Each call to
+//! [`VisWrite::write_vis`] copies the chunk into a buffer and hands it to the
+//! I/O thread, returning as soon as the thread is ready for it; so while the
+//! I/O thread flushes one buffer, the caller can fill the next. Buffers are
+//! recycled, so steady-state writing doesn't allocate.
+//!
+//! Writers that use cfitsio (e.g. [`crate::io::UvfitsWriter`]) aren't [`Send`],
+//! because a cfitsio built without reentrant support shares global state
+//! between all of its files; the I/O thread's cfitsio calls would then race
+//! with any made by the caller (e.g. reading a metafits with mwalib). They can
+//! be written asynchronously by wrapping them in a [`ReentrantFitsWriter`].
+
+use std::{
+    sync::mpsc::{channel, sync_channel, Receiver, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+use ndarray::prelude::*;
+
+use super::{error::IOError, VisWrite};
+use crate::{context::VisContext, Jones};
+
+/// A chunk of visibilities and weights, with its context.
+type Chunk = (Array3<Jones<f32>>, Array3<f32>, VisContext);
+
+/// Writes visibilities with a [`VisWrite`] implementor on a separate thread.
+pub struct VisWriteAsync<W: VisWrite + Send + 'static> {
+    /// Sends chunks to the I/O thread. This is `None` once finalised.
+    sender: Option<SyncSender<Chunk>>,
+
+    /// Buffers that the I/O thread has finished with.
+    recycled: Receiver<(Array3<Jones<f32>>, Array3<f32>)>,
+
+    /// The I/O thread, which gives back the writer when it's done.
+    handle: Option<JoinHandle<Result<W, IOError>>>,
+
+    /// The writer, once the I/O thread has finished.
+    writer: Option<W>,
+}
+
+impl<W: VisWrite + Send + 'static> VisWriteAsync<W> {
+    /// Start an I/O thread that writes with `writer`.
+    pub fn new(mut writer: W) -> Self {
+        // A rendezvous channel; a chunk is only handed over once the I/O thread
+        // has finished writing the previous one.
+        let (sender, receiver) = sync_channel::<Chunk>(0);
+        let (recycle_sender, recycled) = channel();
+        let handle = thread::spawn(move || {
+            for (vis, weights, vis_ctx) in receiver {
+                writer.write_vis(vis.view(), weights.view(), &vis_ctx)?;
+                // The caller may have gone away; then the buffers aren't
+                // needed.
+                let _ = recycle_sender.send((vis, weights));
+            }
+            Ok(writer)
+        });
+        VisWriteAsync {
+            sender: Some(sender),
+            recycled,
+            handle: Some(handle),
+            writer: None,
+        }
+    }
+
+    /// Wait for the I/O thread to write all of the chunks it has been given,
+    /// and get the writer back.
+    ///
+    /// If a write failed, its error is returned the first time, and
+    /// [`IOError::AsyncWriteFailed`] after that.
+    fn join(&mut self) -> Result<&mut W, IOError> {
+        // Dropping the sender ends the I/O thread's loop.
+        self.sender = None;
+        if let Some(handle) = self.handle.take() {
+            match handle.join() {
+                Ok(result) => self.writer = Some(result?),
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+        self.writer.as_mut().ok_or(IOError::AsyncWriteFailed)
+    }
+
+    /// Wait for all of the chunks to be written, and get the writer back. The
+    /// writer isn't finalised by this.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`IOError`] if any of the writes failed
+    /// ([`IOError::AsyncWriteFailed`] if that error has already been
+    /// returned).
+    pub fn into_inner(mut self) -> Result<W, IOError> {
+        self.join()?;
+        self.writer.take().ok_or(IOError::AsyncWriteFailed)
+    }
+}
+
+impl<W: VisWrite + Send + 'static> VisWrite for VisWriteAsync<W> {
+    /// Copy a chunk of visibilities and hand it to the I/O thread. This blocks
+    /// until the I/O thread has finished writing the previous chunk.
+    ///
+    /// An error from writing a previous chunk is returned by the next call
+    /// after it happens, and [`IOError::AsyncWriteFailed`] by any calls after
+    /// that.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is called after a successful `finalise`.
+    fn write_vis(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        let (mut vis_buf, mut weight_buf) = match self.recycled.try_recv() {
+            Ok((v, w)) if v.dim() == vis.dim() && w.dim() == weights.dim() => (v, w),
+            _ => (Array3::zeros(vis.dim()), Array3::zeros(weights.dim())),
+        };
+        vis_buf.assign(&vis);
+        weight_buf.assign(&weights);
+
+        let sender = match (self.sender.as_ref(), self.writer.as_ref()) {
+            (Some(sender), _) => sender,
+            (None, Some(_)) => panic!("VisWriteAsync::write_vis was called after finalise"),
+            (None, None) => return Err(IOError::AsyncWriteFailed),
+        };
+        if sender.send((vis_buf, weight_buf, vis_ctx.clone())).is_err() {
+            // The I/O thread has stopped, which only happens if it failed.
+            self.join()?;
+        }
+        Ok(())
+    }
+
+    /// Wait for all of the chunks to be written, then finalise the writer. No
+    /// more chunks can be written after this.
+    fn finalise(&mut self) -> Result<(), IOError> {
+        self.join()?.finalise()
+    }
+}
+
+/// A [`VisWrite`] implementor that uses cfitsio, which can be moved to another
+/// thread (e.g. by [`VisWriteAsync`]) because cfitsio was built to be
+/// reentrant.
+#[cfg(feature = "cfitsio")]
+pub struct ReentrantFitsWriter<W: VisWrite>(W);
+
+// SAFETY: The writer's FITS file pointers are only used through `&mut self`,
+// and a reentrant cfitsio has no global state shared between FITS files, so
+// its calls on another thread can't race with those of this one. This is only
+// constructed if cfitsio is reentrant, or the caller promises that no other
+// thread uses cfitsio while it's written to.
+#[cfg(feature = "cfitsio")]
+unsafe impl<W: VisWrite> Send for ReentrantFitsWriter<W> {}
+
+#[cfg(feature = "cfitsio")]
+impl<W: VisWrite> ReentrantFitsWriter<W> {
+    /// Wrap a writer that uses cfitsio, so that it can be moved to another
+    /// thread.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`IOError::CfitsioNotReentrant`] if cfitsio wasn't built to
+    /// be reentrant (i.e. configured with `--enable-reentrant`).
+    pub fn new(writer: W) -> Result<Self, IOError> {
+        // fits_is_reentrant = 1 if cfitsio was built with thread safety
+        if unsafe { fitsio_sys::fits_is_reentrant() } == 0 {
+            return Err(IOError::CfitsioNotReentrant);
+        }
+        Ok(Self(writer))
+    }
+
+    /// Wrap a writer that uses cfitsio, so that it can be moved to another
+    /// thread, whether or not cfitsio is reentrant.
+    ///
+    /// # Safety
+    ///
+    /// If cfitsio isn't reentrant, no other thread (including the caller's,
+    /// e.g. through mwalib or another FITS writer) may make cfitsio calls while
+    /// the wrapped writer is being written to or finalised.
+    pub unsafe fn new_unchecked(writer: W) -> Self {
+        Self(writer)
+    }
+
+    /// Get the wrapped writer back.
+    pub fn into_inner(self) -> W {
+        self.0
+    }
+}
+
+#[cfg(feature = "cfitsio")]
+impl<W: VisWrite> VisWrite for ReentrantFitsWriter<W> {
+    fn write_vis(
+        &mut self,
+        vis: ArrayView3<Jones<f32>>,
+        weights: ArrayView3<f32>,
+        vis_ctx: &VisContext,
+    ) -> Result<(), IOError> {
+        self.0.write_vis(vis, weights, vis_ctx)
+    }
+
+    fn finalise(&mut self) -> Result<(), IOError> {
+        self.0.finalise()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        hifitime::{Duration, Epoch},
+        io::error::BadArrayShape,
+    };
+
+    /// A writer that records the first visibility of each chunk, and fails on
+    /// an empty chunk.
+    #[derive(Default)]
+    struct RecordingWriter {
+        written: Arc<Mutex<Vec<Jones<f32>>>>,
+        finalised: bool,
+    }
+
+    impl VisWrite for RecordingWriter {
+        fn write_vis(
+            &mut self,
+            vis: ArrayView3<Jones<f32>>,
+            _weights: ArrayView3<f32>,
+            _vis_ctx: &VisContext,
+        ) -> Result<(), IOError> {
+            match vis.first() {
+                Some(&j) => {
+                    self.written.lock().unwrap().push(j);
+                    Ok(())
+                }
+                None => Err(IOError::BadArrayShape(BadArrayShape {
+                    argument: "vis",
+                    function: "RecordingWriter::write_vis",
+                    expected: "non-empty".into(),
+                    received: format!("{:?}", vis.dim()),
+                })),
+            }
+        }
+
+        fn finalise(&mut self) -> Result<(), IOError> {
+            self.finalised = true;
+            Ok(())
+        }
+    }
+
+    fn vis_ctx() -> VisContext {
+        VisContext {
+            num_sel_timesteps: 1,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_seconds(1.),
+            num_sel_chans: 2,
+            start_freq_hz: 128_000_000.,
+            freq_resolution_hz: 10_000.,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        }
+    }
+
+    #[test]
+    fn test_write_async() {
+        let writer = RecordingWriter::default();
+        let written = writer.written.clone();
+        let mut async_writer = VisWriteAsync::new(writer);
+        let vis_ctx = vis_ctx();
+        let weights = Array3::ones(vis_ctx.sel_dims());
+        for i in 0..5 {
+            let vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity() * i as f32);
+            async_writer
+                .write_vis(vis.view(), weights.view(), &vis_ctx)
+                .unwrap();
+        }
+        async_writer.finalise().unwrap();
+        let writer = async_writer.into_inner().unwrap();
+        assert!(writer.finalised);
+        let written = written.lock().unwrap();
+        assert_eq!(written.len(), 5);
+        for (i, j) in written.iter().enumerate() {
+            assert_eq!(*j, Jones::identity() * i as f32);
+        }
+    }
+
+    #[test]
+    fn test_write_async_error() {
+        let mut async_writer = VisWriteAsync::new(RecordingWriter::default());
+        let vis_ctx = vis_ctx();
+        let vis = Array3::<Jones<f32>>::zeros((0, 2, 3));
+        let weights = Array3::<f32>::zeros((0, 2, 3));
+        async_writer
+            .write_vis(vis.view(), weights.view(), &vis_ctx)
+            .unwrap();
+        // The error is found when the I/O thread is next needed.
+        assert!(matches!(
+            async_writer.finalise(),
+            Err(IOError::BadArrayShape(_))
+        ));
+        // After that, writing fails rather than panicking.
+        let vis = Array3::from_elem(vis_ctx.sel_dims(), Jones::identity());
+        let weights = Array3::ones(vis_ctx.sel_dims());
+        assert!(matches!(
+            async_writer.write_vis(vis.view(), weights.view(), &vis_ctx),
+            Err(IOError::AsyncWriteFailed)
+        ));
+        assert!(matches!(
+            async_writer.into_inner(),
+            Err(IOError::AsyncWriteFailed)
+        ));
+    }
+}
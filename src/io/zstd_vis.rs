@@ -0,0 +1,510 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A simple chunked container of visibilities, weights and flags, with each
+//! chunk compressed with Zstandard.
+//!
+//! This is intended as a fast cache between pipeline stages, where the
+//! overheads of measurement sets and uvfits files are unnecessary; it has no
+//! metadata beyond the shape of the visibilities. All values are little
+//! endian. The layout is:
+//!
+//! - a header: the magic bytes `MARLUZVS`, the format version (`u32`), the
+//!   number of pols (`u32`, always 4), and the number of channels and
+//!   baselines (`u64`s);
+//! - the blocks: each is a zstd frame of a [`VisArray`]'s visibilities
+//!   (`[timestep][channel][baseline]`, as 8 `f32`s), weights
+//!   (`[timestep][channel][baseline][pol]` `f32`s) and flags (the same, as
+//!   `u8`s);
+//! - the index: the number of blocks (`u64`), then the offset, compressed
+//!   length, first timestep and number of timesteps of each block (`u64`s);
+//! - a footer: the offset of the index (`u64`) and the magic bytes.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+};
+
+use ndarray::prelude::*;
+
+use super::error::ZstdVisError;
+use crate::{vis_array::VisArray, Jones};
+
+/// The bytes at the start and end of every file.
+const MAGIC: &[u8; 8] = b"MARLUZVS";
+
+/// The version of the format written.
+const VERSION: u32 = 1;
+
+/// The size of the header \[bytes\].
+const HEADER_SIZE: u64 = 32;
+
+/// The size of the footer \[bytes\].
+const FOOTER_SIZE: u64 = 16;
+
+/// The size of each block's entry in the index \[bytes\].
+const BLOCK_INFO_SIZE: u64 = 32;
+
+/// The size of each visibility in a decompressed block: 8 `f32`s, 4 `f32`
+/// weights and 4 `u8` flags \[bytes\].
+const VIS_SIZE: usize = 8 * 4 + 4 * 4 + 4;
+
+/// The location of a block in the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BlockInfo {
+    offset: u64,
+    compressed_len: u64,
+    first_timestep: u64,
+    num_timesteps: u64,
+}
+
+/// Writes chunks of visibilities to a zstd-compressed container.
+pub struct ZstdVisWriter {
+    file: BufWriter<File>,
+
+    /// The current offset into the file \[bytes\].
+    offset: u64,
+
+    num_chans: usize,
+    num_baselines: usize,
+
+    /// The zstd compression level.
+    level: i32,
+
+    blocks: Vec<BlockInfo>,
+}
+
+impl ZstdVisWriter {
+    /// Create a new container at `path`, which will hold visibilities with
+    /// `num_chans` channels and `num_baselines` baselines. `level` is the
+    /// zstd compression level (1 to 22); low levels are fast, which is usually
+    /// what's wanted for a cache.
+    ///
+    /// This will destroy any existing file at that path.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`ZstdVisError::StdIo`] if the file can't be created.
+    pub fn create<T: AsRef<Path>>(
+        path: T,
+        num_chans: usize,
+        num_baselines: usize,
+        level: i32,
+    ) -> Result<ZstdVisWriter, ZstdVisError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.write_all(&4_u32.to_le_bytes())?;
+        file.write_all(&(num_chans as u64).to_le_bytes())?;
+        file.write_all(&(num_baselines as u64).to_le_bytes())?;
+        Ok(ZstdVisWriter {
+            file,
+            offset: HEADER_SIZE,
+            num_chans,
+            num_baselines,
+            level,
+            blocks: vec![],
+        })
+    }
+
+    /// Compress a chunk of visibilities into a new block. Chunks may have any
+    /// number of timesteps, and are read back in the order they're written.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`ZstdVisError::BadChunkShape`] if the chunk doesn't have
+    /// this container's channels and baselines, or an error if compression or
+    /// writing fails.
+    pub fn write_chunk(&mut self, chunk: &VisArray) -> Result<(), ZstdVisError> {
+        let (num_timesteps, num_chans, num_baselines) = chunk.dim();
+        if (num_chans, num_baselines) != (self.num_chans, self.num_baselines) {
+            return Err(ZstdVisError::BadChunkShape {
+                expected: format!("(_, {}, {})", self.num_chans, self.num_baselines),
+                received: format!("{:?}", chunk.dim()),
+            });
+        }
+
+        let num_vis = chunk.jones().len();
+        let mut bytes = Vec::with_capacity(num_vis * (8 * 4 + 4 * 4 + 4));
+        for jones in chunk.jones() {
+            for c in jones.iter() {
+                bytes.extend_from_slice(&c.re.to_le_bytes());
+                bytes.extend_from_slice(&c.im.to_le_bytes());
+            }
+        }
+        for weight in chunk.weights() {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        bytes.extend(chunk.flags().iter().map(|&f| u8::from(f)));
+        let compressed = zstd::encode_all(bytes.as_slice(), self.level)?;
+        self.file.write_all(&compressed)?;
+
+        let first_timestep = self
+            .blocks
+            .last()
+            .map_or(0, |b| b.first_timestep + b.num_timesteps);
+        self.blocks.push(BlockInfo {
+            offset: self.offset,
+            compressed_len: compressed.len() as u64,
+            first_timestep,
+            num_timesteps: num_timesteps as u64,
+        });
+        self.offset += compressed.len() as u64;
+        Ok(())
+    }
+
+    /// Write the index, and close the file.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`ZstdVisError::StdIo`] if writing fails.
+    pub fn finalise(mut self) -> Result<(), ZstdVisError> {
+        let index_offset = self.offset;
+        self.file
+            .write_all(&(self.blocks.len() as u64).to_le_bytes())?;
+        for block in &self.blocks {
+            for value in [
+                block.offset,
+                block.compressed_len,
+                block.first_timestep,
+                block.num_timesteps,
+            ] {
+                self.file.write_all(&value.to_le_bytes())?;
+            }
+        }
+        self.file.write_all(&index_offset.to_le_bytes())?;
+        self.file.write_all(MAGIC)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads chunks of visibilities from a container written by a
+/// [`ZstdVisWriter`].
+pub struct ZstdVisReader {
+    file: BufReader<File>,
+    num_chans: usize,
+    num_baselines: usize,
+    blocks: Vec<BlockInfo>,
+}
+
+impl ZstdVisReader {
+    /// Open a container, and read its index.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`ZstdVisError::BadMagic`] if the file isn't a (complete)
+    /// container, [`ZstdVisError::UnsupportedVersion`] if it was written by a
+    /// newer version of this format, [`ZstdVisError::BadIndex`] if the index
+    /// doesn't fit the file, or an error if reading fails.
+    pub fn open<T: AsRef<Path>>(path: T) -> Result<ZstdVisReader, ZstdVisError> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut file = BufReader::new(file);
+        let mut header = [0; HEADER_SIZE as usize];
+        file.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(ZstdVisError::BadMagic);
+        }
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(ZstdVisError::UnsupportedVersion { version });
+        }
+        let num_chans = u64::from_le_bytes(header[16..24].try_into().unwrap()) as usize;
+        let num_baselines = u64::from_le_bytes(header[24..32].try_into().unwrap()) as usize;
+
+        let mut footer = [0; FOOTER_SIZE as usize];
+        file.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+        file.read_exact(&mut footer)?;
+        if &footer[8..] != MAGIC {
+            return Err(ZstdVisError::BadMagic);
+        }
+        let index_offset = u64::from_le_bytes(footer[..8].try_into().unwrap());
+        let bad_index = |message: String| ZstdVisError::BadIndex { message };
+        // the index sits between the blocks and the footer.
+        let index_len = file_len
+            .checked_sub(FOOTER_SIZE)
+            .and_then(|end| end.checked_sub(index_offset))
+            .filter(|&len| index_offset >= HEADER_SIZE && len >= 8)
+            .ok_or_else(|| {
+                bad_index(format!(
+                    "index offset {index_offset} is outside of the {file_len} byte file"
+                ))
+            })?;
+        file.seek(SeekFrom::Start(index_offset))?;
+        let num_blocks = read_u64(&mut file)?;
+        if num_blocks.checked_mul(BLOCK_INFO_SIZE) != Some(index_len - 8) {
+            return Err(bad_index(format!(
+                "{num_blocks} blocks don't fit in an index of {index_len} bytes"
+            )));
+        }
+        let bytes_per_timestep = num_chans
+            .checked_mul(num_baselines)
+            .and_then(|n| n.checked_mul(VIS_SIZE))
+            .ok_or_else(|| {
+                bad_index(format!(
+                    "{num_chans} channels and {num_baselines} baselines are too many"
+                ))
+            })?;
+        let mut blocks = Vec::with_capacity(num_blocks as usize);
+        let mut num_timesteps: u64 = 0;
+        for block_idx in 0..num_blocks {
+            let block = BlockInfo {
+                offset: read_u64(&mut file)?,
+                compressed_len: read_u64(&mut file)?,
+                first_timestep: read_u64(&mut file)?,
+                num_timesteps: read_u64(&mut file)?,
+            };
+            if block.offset < HEADER_SIZE
+                || !matches!(
+                    block.offset.checked_add(block.compressed_len),
+                    Some(end) if end <= index_offset
+                )
+            {
+                return Err(bad_index(format!(
+                    "block {block_idx} at offset {} with {} bytes is outside of the blocks",
+                    block.offset, block.compressed_len
+                )));
+            }
+            if block.first_timestep != num_timesteps {
+                return Err(bad_index(format!(
+                    "block {block_idx} starts at timestep {}, but the previous block ended at {num_timesteps}",
+                    block.first_timestep
+                )));
+            }
+            num_timesteps = num_timesteps
+                .checked_add(block.num_timesteps)
+                .filter(|&n| (n as usize).checked_mul(bytes_per_timestep).is_some())
+                .ok_or_else(|| bad_index(format!("block {block_idx} has too many timesteps")))?;
+            blocks.push(block);
+        }
+
+        Ok(ZstdVisReader {
+            file,
+            num_chans,
+            num_baselines,
+            blocks,
+        })
+    }
+
+    /// The number of blocks in this container.
+    pub fn num_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// The total number of timesteps in this container.
+    pub fn num_timesteps(&self) -> usize {
+        self.blocks
+            .last()
+            .map_or(0, |b| (b.first_timestep + b.num_timesteps) as usize)
+    }
+
+    /// The `[timestep][channel][baseline]` dimensions of all of the
+    /// visibilities in this container.
+    pub fn dim(&self) -> (usize, usize, usize) {
+        (self.num_timesteps(), self.num_chans, self.num_baselines)
+    }
+
+    /// The timesteps of a block.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_idx` isn't less than [`ZstdVisReader::num_blocks`].
+    pub fn block_timesteps(&self, block_idx: usize) -> Range<usize> {
+        let block = self.blocks[block_idx];
+        let first = block.first_timestep as usize;
+        first..first + block.num_timesteps as usize
+    }
+
+    /// Decompress a block.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`ZstdVisError::NoSuchBlock`] if `block_idx` is out of
+    /// range, [`ZstdVisError::BadBlockLength`] if the block doesn't decompress
+    /// to the expected size, or an error if reading fails.
+    pub fn read_block(&mut self, block_idx: usize) -> Result<VisArray, ZstdVisError> {
+        let block = *self
+            .blocks
+            .get(block_idx)
+            .ok_or(ZstdVisError::NoSuchBlock {
+                block_idx,
+                num_blocks: self.blocks.len(),
+            })?;
+        self.file.seek(SeekFrom::Start(block.offset))?;
+        let mut compressed = vec![0; block.compressed_len as usize];
+        self.file.read_exact(&mut compressed)?;
+        let bytes = zstd::decode_all(compressed.as_slice())?;
+
+        let dims = (
+            block.num_timesteps as usize,
+            self.num_chans,
+            self.num_baselines,
+        );
+        let num_vis = dims.0 * dims.1 * dims.2;
+        let expected = num_vis * VIS_SIZE;
+        if bytes.len() != expected {
+            return Err(ZstdVisError::BadBlockLength {
+                block_idx,
+                expected,
+                received: bytes.len(),
+            });
+        }
+        let (jones_bytes, rest) = bytes.split_at(num_vis * 8 * 4);
+        let (weight_bytes, flag_bytes) = rest.split_at(num_vis * 4 * 4);
+        let floats = |bytes: &[u8]| -> Vec<f32> {
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect()
+        };
+        let jones: Vec<Jones<f32>> = floats(jones_bytes)
+            .chunks_exact(8)
+            .map(|f| Jones::from([f[0], f[1], f[2], f[3], f[4], f[5], f[6], f[7]]))
+            .collect();
+        let pol_dims = (dims.0, dims.1, dims.2, 4);
+        let vis = VisArray::new(
+            Array3::from_shape_vec(dims, jones).expect("length checked"),
+            Array4::from_shape_vec(pol_dims, floats(weight_bytes)).expect("length checked"),
+            Array4::from_shape_vec(pol_dims, flag_bytes.iter().map(|&f| f != 0).collect())
+                .expect("length checked"),
+        )
+        .expect("shapes match");
+        Ok(vis)
+    }
+
+    /// Decompress all of the blocks into a single [`VisArray`].
+    ///
+    /// # Errors
+    ///
+    /// See [`ZstdVisReader::read_block`].
+    pub fn read_all(&mut self) -> Result<VisArray, ZstdVisError> {
+        let mut all = VisArray::flagged(self.dim());
+        for block_idx in 0..self.num_blocks() {
+            let timesteps = self.block_timesteps(block_idx);
+            let block = self.read_block(block_idx)?;
+            let (mut jones, mut weights, mut flags) = all.views_mut();
+            jones
+                .slice_mut(s![timesteps.clone(), .., ..])
+                .assign(&block.jones());
+            weights
+                .slice_mut(s![timesteps.clone(), .., .., ..])
+                .assign(&block.weights());
+            flags
+                .slice_mut(s![timesteps, .., .., ..])
+                .assign(&block.flags());
+        }
+        Ok(all)
+    }
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, std::io::Error> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn synthesize_chunk(num_timesteps: usize, offset: usize) -> VisArray {
+        let dims = (num_timesteps, 3, 2);
+        let jones = Array3::from_shape_fn(dims, |(t, c, b)| {
+            let t = (t + offset) as f32;
+            Jones::from([t, c as f32, b as f32, 1.0, -t, 0.5, 2.0, t * 2.0])
+        });
+        let weights = Array4::from_shape_fn((dims.0, dims.1, dims.2, 4), |(t, c, b, p)| {
+            (t + offset + c + b + p) as f32
+        });
+        let flags = Array4::from_shape_fn((dims.0, dims.1, dims.2, 4), |(t, c, b, p)| {
+            (t + offset + c + b + p) % 3 == 0
+        });
+        VisArray::new(jones, weights, flags).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.zvis");
+
+        let chunks = [synthesize_chunk(2, 0), synthesize_chunk(1, 2)];
+        let mut writer = ZstdVisWriter::create(&path, 3, 2, 1).unwrap();
+        for chunk in &chunks {
+            writer.write_chunk(chunk).unwrap();
+        }
+        assert!(matches!(
+            writer.write_chunk(&VisArray::flagged((1, 4, 2))),
+            Err(ZstdVisError::BadChunkShape { .. })
+        ));
+        writer.finalise().unwrap();
+
+        let mut reader = ZstdVisReader::open(&path).unwrap();
+        assert_eq!(reader.num_blocks(), 2);
+        assert_eq!(reader.dim(), (3, 3, 2));
+        assert_eq!(reader.block_timesteps(1), 2..3);
+        assert_eq!(reader.read_block(1).unwrap(), chunks[1]);
+        assert!(matches!(
+            reader.read_block(2),
+            Err(ZstdVisError::NoSuchBlock {
+                block_idx: 2,
+                num_blocks: 2
+            })
+        ));
+        assert_eq!(reader.read_all().unwrap(), synthesize_chunk(3, 0));
+    }
+
+    #[test]
+    fn test_bad_index() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.zvis");
+        let mut writer = ZstdVisWriter::create(&path, 3, 2, 1).unwrap();
+        writer.write_chunk(&synthesize_chunk(2, 0)).unwrap();
+        writer.write_chunk(&synthesize_chunk(1, 2)).unwrap();
+        writer.finalise().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let index_offset = bytes.len() - FOOTER_SIZE as usize - 8 - 2 * BLOCK_INFO_SIZE as usize;
+
+        // overwrite a u64 of the file, and check that it can't be opened.
+        let corrupt = |offset: usize, value: u64| {
+            let mut corrupted = bytes.clone();
+            corrupted[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            std::fs::write(&path, corrupted).unwrap();
+            assert!(
+                matches!(
+                    ZstdVisReader::open(&path),
+                    Err(ZstdVisError::BadIndex { .. })
+                ),
+                "offset {offset}, value {value}"
+            );
+        };
+        // the index offset in the footer.
+        corrupt(bytes.len() - FOOTER_SIZE as usize, u64::MAX);
+        corrupt(bytes.len() - FOOTER_SIZE as usize, 0);
+        // the number of blocks.
+        corrupt(index_offset, u64::MAX);
+        corrupt(index_offset, 3);
+        // the compressed length of the first block.
+        corrupt(index_offset + 16, u64::MAX);
+        corrupt(index_offset + 16, bytes.len() as u64);
+        // the first timestep of the second block.
+        corrupt(index_offset + 8 + 32 + 16, 1);
+        // the number of timesteps of the second block.
+        corrupt(index_offset + 8 + 32 + 24, u64::MAX);
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("not_a_cache");
+        std::fs::write(&path, [0; 64]).unwrap();
+        assert!(matches!(
+            ZstdVisReader::open(&path),
+            Err(ZstdVisError::BadMagic)
+        ));
+    }
+}
@@ -13,13 +13,33 @@
 
 use std::ops::{Add, AddAssign, Deref, DerefMut, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
 
-use crate::Complex;
+use crate::{pol::Pol, Complex};
 use num_traits::{float::FloatCore, Float, Num, NumAssign, Zero};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JonesError {
+    /// The Jones matrix can't be inverted.
+    #[error("the Jones matrix is singular")]
+    Singular,
+}
 
 #[repr(transparent)]
 #[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Jones<F: Float + Num>([Complex<F>; 4]);
 
+// SAFETY: `Jones` is `repr(transparent)` over an array of `Complex`, which is
+// `repr(C)` over two floats, so it has no padding and any bit pattern is valid.
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Zeroable for Jones<f32> {}
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Pod for Jones<f32> {}
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Zeroable for Jones<f64> {}
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Pod for Jones<f64> {}
+
 impl<F: Float> Jones<F> {
     /// Return an identity matrix. All imaginary parts are zero.
     #[inline]
@@ -44,6 +64,33 @@ impl<F: Float> Jones<F> {
         ])
     }
 
+    /// Get the outer product `a b^H` of two (e.g. `[X, Y]`) field vectors, i.e.
+    /// the coherency matrix `[a_X b_X*, a_X b_Y*, a_Y b_X*, a_Y b_Y*]`. The
+    /// visibility of a source with electric field `e` is `Jones::outer(e, e)`.
+    #[inline]
+    pub fn outer(a: [Complex<F>; 2], b: [Complex<F>; 2]) -> Self {
+        Self::from([
+            a[0] * b[0].conj(),
+            a[0] * b[1].conj(),
+            a[1] * b[0].conj(),
+            a[1] * b[1].conj(),
+        ])
+    }
+
+    /// Get the element of a visibility (coherency) matrix for a polarisation
+    /// product, e.g. `XY` is the same as `self[1]`.
+    #[inline]
+    pub fn get_pol(self, pol: Pol) -> Complex<F> {
+        self.0[pol.jones_index()]
+    }
+
+    /// Set the element of a visibility (coherency) matrix for a polarisation
+    /// product.
+    #[inline]
+    pub fn set_pol(&mut self, pol: Pol, value: Complex<F>) {
+        self.0[pol.jones_index()] = value;
+    }
+
     /// From an input Jones matrix, get a copy that has been Hermitian
     /// conjugated (`J^H`).
     #[inline]
@@ -56,12 +103,160 @@ impl<F: Float> Jones<F> {
         ])
     }
 
+    /// Multiply by another Jones matrix and add a third (`A B + C`). With the
+    /// `simd` feature on a target with FMA instructions, this is done with
+    /// fused multiply-adds.
+    #[inline]
+    pub fn mul_add(self, b: Self, c: Self) -> Self {
+        Self(mul_add_2x2(&self.0, &b.0, &c.0))
+    }
+
     /// Multiply by a Jones matrix which gets Hermitian conjugated (`J^H`).
     #[inline]
     pub fn mul_hermitian(self, b: Self) -> Self {
         self * b.h()
     }
 
+    /// Hermitian conjugate this Jones matrix (`J^H`) and multiply it by
+    /// another.
+    #[inline]
+    pub fn hermitian_mul(self, b: Self) -> Self {
+        self.h() * b
+    }
+
+    /// Convert a visibility (coherency) matrix from a linear (`[XX, XY, YX,
+    /// YY]`) to a circular (`[RR, RL, LR, LL]`) basis, with `R = (X + iY) /
+    /// √2` and `L = (X - iY) / √2`. This is consistent with the IAU Stokes
+    /// conventions in [`crate::stokes`].
+    pub fn to_circular_basis(self) -> Self {
+        let t = Self::linear_to_circular();
+        t * self * t.h()
+    }
+
+    /// Convert a visibility (coherency) matrix from a circular to a linear
+    /// basis; the inverse of [`Jones::to_circular_basis`].
+    pub fn to_linear_basis(self) -> Self {
+        let t = Self::linear_to_circular();
+        t.h() * self * t
+    }
+
+    /// The (unitary) matrix that takes linear fields to circular fields.
+    fn linear_to_circular() -> Self {
+        let a = F::one() / (F::one() + F::one()).sqrt();
+        Self::from([
+            Complex::new(a, F::zero()),
+            Complex::new(F::zero(), a),
+            Complex::new(a, F::zero()),
+            Complex::new(F::zero(), -a),
+        ])
+    }
+
+    /// Get the determinant of the Jones matrix.
+    #[inline]
+    pub fn det(self) -> Complex<F> {
+        self[0] * self[3] - self[1] * self[2]
+    }
+
+    /// Get the Frobenius norm of the Jones matrix, i.e. the square root of the
+    /// sum of the squared magnitudes of its elements.
+    #[inline]
+    pub fn norm(self) -> F {
+        let [a, b, c, d] = self.norm_sqr();
+        (a + b + c + d).sqrt()
+    }
+
+    /// Get the condition number of the Jones matrix, i.e. the ratio of its
+    /// largest and smallest singular values. This is 1 for unitary matrices
+    /// and infinity for singular matrices; large values mean that the inverse
+    /// is sensitive to errors in the matrix.
+    pub fn cond(self) -> F {
+        // The squares of the singular values sum to the squared Frobenius norm,
+        // and their product is the magnitude of the determinant.
+        let two = F::one() + F::one();
+        let norm_sqr = self.norm().powi(2);
+        let abs_det = self.det().norm();
+        if abs_det.is_zero() {
+            return F::infinity();
+        }
+        let discriminant = (norm_sqr * norm_sqr - two * two * abs_det * abs_det)
+            .max(F::zero())
+            .sqrt();
+        (norm_sqr + discriminant) / (two * abs_det)
+    }
+
+    /// Get the inverse of the Jones matrix (`J^I`), checking that it isn't
+    /// singular (unlike [`Jones::inv`]).
+    ///
+    /// # Errors
+    ///
+    /// Will return [`JonesError::Singular`] if the matrix is singular to
+    /// within floating-point precision, or contains NaNs or infinities.
+    pub fn try_inv(self) -> Result<Self, JonesError> {
+        let abs_det = self.det().norm();
+        let norm_sqr = self.norm().powi(2);
+        if abs_det.is_nan() || abs_det.is_infinite() || abs_det <= F::epsilon() * norm_sqr {
+            return Err(JonesError::Singular);
+        }
+        Ok(self.inv())
+    }
+
+    /// Get the principal square root of the Jones matrix, i.e. the matrix `S`
+    /// with `S S = J` whose eigenvalues have non-negative real parts. Negative
+    /// real eigenvalues (on the branch cut) get roots with positive imaginary
+    /// parts, regardless of the sign of the zero imaginary part.
+    ///
+    /// Some matrices (e.g. `[[0, 1], [0, 0]]`) have no square root; the
+    /// contents of the result are then all NaN.
+    pub fn sqrt(self) -> Self {
+        let zero = F::zero();
+        let two = F::one() + F::one();
+        let half_trace = (self[0] + self[3]) / two;
+        let discriminant = (half_trace * half_trace - self.det()).sqrt();
+        let root = |eigenvalue: Complex<F>| {
+            if eigenvalue.im.is_zero() {
+                Complex::new(eigenvalue.re, zero).sqrt()
+            } else {
+                eigenvalue.sqrt()
+            }
+        };
+        let r1 = root(half_trace + discriminant);
+        let r2 = root(half_trace - discriminant);
+        // By Cayley-Hamilton, S S - (r1 + r2) S + r1 r2 I = 0.
+        let trace = r1 + r2;
+        if trace.is_zero() {
+            // Both eigenvalues are 0; only the zero matrix has a root.
+            return if self.iter().all(Zero::is_zero) {
+                self
+            } else {
+                Self::nan()
+            };
+        }
+        let det = r1 * r2;
+        Self::from([
+            (self[0] + det) / trace,
+            self[1] / trace,
+            self[2] / trace,
+            (self[3] + det) / trace,
+        ])
+    }
+
+    /// Get the (right) polar decomposition `J = U P` of the Jones matrix,
+    /// where `U` is unitary and `P = sqrt(J^H J)` is Hermitian and positive
+    /// definite. `P` describes the amplitudes of a gain and `U` its phases
+    /// (including any rotation); e.g. a diagonal gain with amplitudes `a` and
+    /// phases `φ` has `P = diag(a)` and `U = diag(exp(iφ))`. The result is
+    /// `(U, P)`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`JonesError::Singular`] if the matrix is singular, in
+    /// which case `U` isn't unique.
+    pub fn polar(self) -> Result<(Self, Self), JonesError> {
+        let p = self.hermitian_mul(self).sqrt();
+        let u = self * p.try_inv()?;
+        Ok((u, p))
+    }
+
     /// Get the inverse of the Jones matrix (`J^I`).
     ///
     /// Ideally, `J^I . J = I`. However it's possible that `J` is singular, in
@@ -129,18 +324,74 @@ impl<F: Float + FloatCore> Jones<F> {
 impl<F: Float + NumAssign> Jones<F> {
     #[inline]
     pub fn plus_axb(c: &mut Self, a: Self, b: Self) {
-        c[0] += a[0] * b[0] + a[1] * b[2];
-        c[1] += a[0] * b[1] + a[1] * b[3];
-        c[2] += a[2] * b[0] + a[3] * b[2];
-        c[3] += a[2] * b[1] + a[3] * b[3];
+        c.0 = mul_add_2x2(&a.0, &b.0, &c.0);
     }
 
     #[inline]
     pub fn plus_ahxb(c: &mut Self, a: Self, b: Self) {
-        c[0] += a[0].conj() * b[0] + a[2].conj() * b[2];
-        c[1] += a[0].conj() * b[1] + a[2].conj() * b[3];
-        c[2] += a[1].conj() * b[0] + a[3].conj() * b[2];
-        c[3] += a[1].conj() * b[1] + a[3].conj() * b[3];
+        c.0 = mul_add_2x2(&a.h().0, &b.0, &c.0);
+    }
+}
+
+/// Multiply two 2x2 complex matrices.
+///
+/// Jones products dominate the runtime of correction and calibration loops, and
+/// the compiler won't fuse the multiplies and adds of the complex products on
+/// its own (it isn't allowed to change the rounding). With the `simd` feature
+/// on a target with FMA instructions (e.g. `-C target-cpu=native` on most x86
+/// CPUs), they're done with fused multiply-adds, which is faster and slightly
+/// more accurate. Without FMA instructions, `mul_add` would be a (slow) library
+/// call, so the plain arithmetic is used.
+#[inline(always)]
+fn mul_2x2<F: Float>(a: &[Complex<F>; 4], b: &[Complex<F>; 4]) -> [Complex<F>; 4] {
+    #[cfg(all(feature = "simd", target_feature = "fma"))]
+    {
+        let zero = Complex::new(F::zero(), F::zero());
+        mul_add_2x2(a, b, &[zero; 4])
+    }
+    #[cfg(not(all(feature = "simd", target_feature = "fma")))]
+    {
+        [
+            a[0] * b[0] + a[1] * b[2],
+            a[0] * b[1] + a[1] * b[3],
+            a[2] * b[0] + a[3] * b[2],
+            a[2] * b[1] + a[3] * b[3],
+        ]
+    }
+}
+
+/// Multiply two 2x2 complex matrices and add a third (`A B + C`). See
+/// [`mul_2x2`].
+#[inline(always)]
+fn mul_add_2x2<F: Float>(
+    a: &[Complex<F>; 4],
+    b: &[Complex<F>; 4],
+    c: &[Complex<F>; 4],
+) -> [Complex<F>; 4] {
+    #[cfg(all(feature = "simd", target_feature = "fma"))]
+    {
+        // acc + x y, as four fused multiply-adds.
+        let fma = |acc: Complex<F>, x: Complex<F>, y: Complex<F>| {
+            Complex::new(
+                x.re.mul_add(y.re, (-x.im).mul_add(y.im, acc.re)),
+                x.re.mul_add(y.im, x.im.mul_add(y.re, acc.im)),
+            )
+        };
+        [
+            fma(fma(c[0], a[0], b[0]), a[1], b[2]),
+            fma(fma(c[1], a[0], b[1]), a[1], b[3]),
+            fma(fma(c[2], a[2], b[0]), a[3], b[2]),
+            fma(fma(c[3], a[2], b[1]), a[3], b[3]),
+        ]
+    }
+    #[cfg(not(all(feature = "simd", target_feature = "fma")))]
+    {
+        [
+            c[0] + (a[0] * b[0] + a[1] * b[2]),
+            c[1] + (a[0] * b[1] + a[1] * b[3]),
+            c[2] + (a[2] * b[0] + a[3] * b[2]),
+            c[3] + (a[2] * b[1] + a[3] * b[3]),
+        ]
     }
 }
 
@@ -298,12 +549,7 @@ impl<F: Float> Mul<Jones<F>> for Jones<F> {
 
     #[inline]
     fn mul(self, rhs: Self) -> Self {
-        Self::from([
-            self[0] * rhs[0] + self[1] * rhs[2],
-            self[0] * rhs[1] + self[1] * rhs[3],
-            self[2] * rhs[0] + self[3] * rhs[2],
-            self[2] * rhs[1] + self[3] * rhs[3],
-        ])
+        Self(mul_2x2(&self.0, &rhs.0))
     }
 }
 
@@ -312,12 +558,7 @@ impl<F: Float> Mul<&Jones<F>> for Jones<F> {
 
     #[inline]
     fn mul(self, rhs: &Self) -> Self {
-        Self::from([
-            self[0] * rhs[0] + self[1] * rhs[2],
-            self[0] * rhs[1] + self[1] * rhs[3],
-            self[2] * rhs[0] + self[3] * rhs[2],
-            self[2] * rhs[1] + self[3] * rhs[3],
-        ])
+        Self(mul_2x2(&self.0, &rhs.0))
     }
 }
 
@@ -344,24 +585,14 @@ impl<F: Float + NumAssign> MulAssign<Complex<F>> for Jones<F> {
 impl<F: Float + NumAssign> MulAssign<Jones<F>> for Jones<F> {
     #[inline]
     fn mul_assign(&mut self, rhs: Self) {
-        self.0 = [
-            self[0] * rhs[0] + self[1] * rhs[2],
-            self[0] * rhs[1] + self[1] * rhs[3],
-            self[2] * rhs[0] + self[3] * rhs[2],
-            self[2] * rhs[1] + self[3] * rhs[3],
-        ];
+        self.0 = mul_2x2(&self.0, &rhs.0);
     }
 }
 
 impl<F: Float + NumAssign> MulAssign<&Jones<F>> for Jones<F> {
     #[inline]
     fn mul_assign(&mut self, rhs: &Self) {
-        self.0 = [
-            self[0] * rhs[0] + self[1] * rhs[2],
-            self[0] * rhs[1] + self[1] * rhs[3],
-            self[2] * rhs[0] + self[3] * rhs[2],
-            self[2] * rhs[1] + self[3] * rhs[3],
-        ];
+        self.0 = mul_2x2(&self.0, &rhs.0);
     }
 }
 
@@ -803,6 +1034,185 @@ mod tests {
             c64::new(4.0, 0.0),
         ]);
         assert!(a.inv().any_nan());
+        assert_eq!(a.try_inv(), Err(JonesError::Singular));
+        assert_eq!(Jones::<f64>::default().try_inv(), Err(JonesError::Singular));
+        assert_eq!(Jones::<f64>::nan().try_inv(), Err(JonesError::Singular));
+        // Nearly singular.
+        let b = Jones([
+            c64::new(1.0, 0.0),
+            c64::new(2.0, 0.0),
+            c64::new(2.0, 0.0),
+            c64::new(4.0 + 1e-15, 0.0),
+        ]);
+        assert_eq!(b.try_inv(), Err(JonesError::Singular));
+    }
+
+    #[test]
+    fn test_try_inv() {
+        let a = one_through_eight();
+        let result = a.try_inv().unwrap() * a;
+        assert_abs_diff_eq!(result, Jones::identity(), epsilon = 1e-10);
+        assert_abs_diff_eq!(a.try_inv().unwrap(), a.inv());
+    }
+
+    #[test]
+    fn test_det_norm_cond() {
+        let a = one_through_eight();
+        // (1+2i)(7+8i) - (3+4i)(5+6i)
+        assert_abs_diff_eq!(a.det().re, 0.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(a.det().im, -16.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(a.norm(), 204_f64.sqrt(), epsilon = 1e-10);
+
+        // Unitary matrices are perfectly conditioned.
+        assert_abs_diff_eq!(Jones::<f64>::identity().cond(), 1.0, epsilon = 1e-12);
+        let (s, c) = 0.3_f64.sin_cos();
+        let rotation = Jones::from([c, 0.0, -s, 0.0, s, 0.0, c, 0.0]) * c64::new(0.0, 1.0);
+        assert_abs_diff_eq!(rotation.cond(), 1.0, epsilon = 1e-12);
+        // The singular values of a diagonal matrix are its magnitudes.
+        let diag = Jones::from([
+            c64::new(0.0, 4.0),
+            c64::default(),
+            c64::default(),
+            c64::new(0.5, 0.0),
+        ]);
+        assert_abs_diff_eq!(diag.cond(), 8.0, epsilon = 1e-12);
+        assert!(Jones::<f64>::default().cond().is_infinite());
+
+        // (A^H B) = (B^H A)^H
+        let b = Jones([
+            c64::new(-1.0, 0.5),
+            c64::new(2.0, 0.0),
+            c64::new(0.0, 3.0),
+            c64::new(1.0, 1.0),
+        ]);
+        assert_abs_diff_eq!(a.hermitian_mul(b), a.h() * b);
+        assert_abs_diff_eq!(a.hermitian_mul(b), b.hermitian_mul(a).h(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_outer() {
+        let e_x = c64::new(1.0, 2.0);
+        let e_y = c64::new(-0.5, 0.3);
+        let vis = Jones::outer([e_x, e_y], [e_x, e_y]);
+        assert_abs_diff_eq!(vis.get_pol(Pol::XX), c64::new(5.0, 0.0));
+        assert_abs_diff_eq!(vis.get_pol(Pol::XY), e_x * e_y.conj());
+        assert_abs_diff_eq!(vis.get_pol(Pol::YX), vis.get_pol(Pol::XY).conj());
+        assert_abs_diff_eq!(vis.get_pol(Pol::YY), c64::new(0.34, 0.0), epsilon = 1e-15);
+        // A single field vector gives a rank-1 matrix.
+        assert_abs_diff_eq!(vis.det(), c64::default(), epsilon = 1e-15);
+
+        let mut j = Jones::<f64>::identity();
+        j.set_pol(Pol::YX, c64::new(0.0, 1.0));
+        assert_abs_diff_eq!(j[2], c64::new(0.0, 1.0));
+        assert_abs_diff_eq!(j.get_pol(Pol::YY), c64::new(1.0, 0.0));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let j = Jones::from([
+            c32::new(1.0, 2.0),
+            c32::new(3.0, 4.0),
+            c32::new(5.0, 6.0),
+            c32::new(7.0, 8.0),
+        ]);
+        let json = serde_json::to_string(&j).unwrap();
+        assert_eq!(json, "[[1.0,2.0],[3.0,4.0],[5.0,6.0],[7.0,8.0]]");
+        let j2: Jones<f32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(j, j2);
+    }
+
+    #[test]
+    #[cfg(feature = "bytemuck")]
+    fn test_bytemuck() {
+        let floats = [1.0_f64, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let j: &[Jones<f64>] = bytemuck::cast_slice(&floats);
+        assert_eq!(j, &[Jones::from(floats)]);
+
+        let bytes: &[u8] = bytemuck::cast_slice(j);
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(bytemuck::cast_slice::<u8, f64>(bytes), &floats);
+        assert_eq!(bytemuck::Zeroable::zeroed(), Jones::<f32>::default());
+    }
+
+    #[test]
+    fn test_sqrt() {
+        let j = Jones::from([
+            c64::new(1.0, 2.0),
+            c64::new(-0.5, 0.1),
+            c64::new(3.0, -1.0),
+            c64::new(0.2, 0.7),
+        ]);
+        let s = j.sqrt();
+        assert_abs_diff_eq!(s * s, j, epsilon = 1e-12);
+
+        assert_abs_diff_eq!(Jones::<f64>::identity().sqrt(), Jones::identity());
+        assert_abs_diff_eq!(Jones::<f64>::default().sqrt(), Jones::default());
+
+        // Negative eigenvalues are on the branch cut.
+        let j = Jones::from([
+            c64::new(-1.0, -0.0),
+            c64::default(),
+            c64::default(),
+            c64::new(-4.0, 0.0),
+        ]);
+        let expected = Jones::from([
+            c64::new(0.0, 1.0),
+            c64::default(),
+            c64::default(),
+            c64::new(0.0, 2.0),
+        ]);
+        assert_abs_diff_eq!(j.sqrt(), expected, epsilon = 1e-12);
+        // A repeated negative eigenvalue.
+        assert_abs_diff_eq!(
+            (Jones::<f64>::identity() * -9.0).sqrt(),
+            Jones::identity() * c64::new(0.0, 3.0),
+            epsilon = 1e-12
+        );
+
+        // No square root.
+        let j = Jones::from([
+            c64::default(),
+            c64::new(1.0, 0.0),
+            c64::default(),
+            c64::default(),
+        ]);
+        assert!(j.sqrt().any_nan());
+    }
+
+    #[test]
+    fn test_polar() {
+        let j = Jones::from([
+            c64::new(1.0, 2.0),
+            c64::new(-0.5, 0.1),
+            c64::new(3.0, -1.0),
+            c64::new(0.2, 0.7),
+        ]);
+        let (u, p) = j.polar().unwrap();
+        assert_abs_diff_eq!(u * p, j, epsilon = 1e-12);
+        assert_abs_diff_eq!(u.hermitian_mul(u), Jones::identity(), epsilon = 1e-12);
+        assert_abs_diff_eq!(p.h(), p, epsilon = 1e-12);
+
+        // A diagonal gain.
+        let j = Jones::from([
+            c64::from_polar(2.0, 0.3),
+            c64::default(),
+            c64::default(),
+            c64::from_polar(0.5, -1.2),
+        ]);
+        let (u, p) = j.polar().unwrap();
+        assert_abs_diff_eq!(p[0], c64::new(2.0, 0.0), epsilon = 1e-12);
+        assert_abs_diff_eq!(p[3], c64::new(0.5, 0.0), epsilon = 1e-12);
+        assert_abs_diff_eq!(u[0], c64::from_polar(1.0, 0.3), epsilon = 1e-12);
+        assert_abs_diff_eq!(u[3], c64::from_polar(1.0, -1.2), epsilon = 1e-12);
+
+        let singular = Jones::from([
+            c64::new(1.0, 0.0),
+            c64::new(2.0, 0.0),
+            c64::new(2.0, 0.0),
+            c64::new(4.0, 0.0),
+        ]);
+        assert_eq!(singular.polar(), Err(JonesError::Singular));
     }
 
     #[test]
@@ -856,6 +1266,37 @@ mod tests {
         assert_abs_diff_eq!(c, expected_c, epsilon = 1e-10);
     }
 
+    #[test]
+    fn test_mul_add() {
+        let a = Jones::from([
+            c64::new(1.0, 2.0),
+            c64::new(-0.5, 0.1),
+            c64::new(3.0, -1.0),
+            c64::new(0.2, 0.7),
+        ]);
+        let b = Jones::from([
+            c64::new(0.3, -2.0),
+            c64::new(1.5, 1.0),
+            c64::new(-1.0, 0.0),
+            c64::new(0.0, 4.0),
+        ]);
+        let c = Jones::from([
+            c64::new(1.0, 1.0),
+            c64::new(2.0, 2.0),
+            c64::new(3.0, 3.0),
+            c64::new(4.0, 4.0),
+        ]);
+        // a * b, done by hand.
+        let expected = Jones::from([
+            c64::new(4.8, -1.5),
+            c64::new(-0.9, 2.0),
+            c64::new(-1.3, -7.0),
+            c64::new(2.7, 2.3),
+        ]);
+        assert_abs_diff_eq!(a * b, expected, epsilon = 1e-12);
+        assert_abs_diff_eq!(a.mul_add(b, c), expected + c, epsilon = 1e-12);
+    }
+
     #[test]
     fn test_plus_axb() {
         let a = one_through_eight();
@@ -30,43 +30,80 @@ pub type c32 = num_complex::Complex<f32>;
 pub type c64 = num_complex::Complex<f64>;
 
 pub mod averaging;
+pub mod calibration;
 pub mod constants;
 pub mod context;
+pub mod diff;
+pub mod flags;
+pub mod freq;
 pub mod jones;
 pub mod math;
+pub mod mueller;
+pub mod pad;
+pub mod pol;
+pub mod partition;
 pub mod pos;
+pub mod reflection;
+pub mod rephase;
 pub mod selection;
 pub mod sexagesimal;
+pub mod stokes;
+pub mod time;
+pub mod vis_array;
 
 pub mod io;
 #[cfg(feature = "ms")]
 pub use io::ms;
 #[cfg(feature = "cfitsio")]
+pub use io::fits_idi;
+#[cfg(feature = "cfitsio")]
 pub use io::uvfits;
-pub use io::VisWrite;
+#[cfg(feature = "miriad")]
+pub use io::miriad;
+pub use io::{VisWrite, VisWriteAsync};
+#[cfg(feature = "cfitsio")]
+pub use io::ReentrantFitsWriter;
 
 // Re-exports.
-pub use context::{History, MwaObsContext, ObsContext, VisContext};
-pub use jones::Jones;
+pub use context::{FieldContext, History, MwaObsContext, ObsContext, VisContext, WeightScaling};
+pub use jones::{Jones, JonesError};
+pub use mueller::Mueller;
+pub use pol::{Pol, PolOrder};
 pub use pos::{
-    azel::AzEl,
+    azel::{AirmassModel, AzEl},
+    beamformer::SweetSpot,
     earth::LatLngHeight,
+    ecliptic::Ecliptic,
     enh::ENH,
+    eop::{EarthOrientation, EopTable},
+    galactic::Galactic,
     hadec::HADec,
+    healpix::{HealpixError, HealpixScheme},
+    ionosphere::ThinShell,
     lmn::{LmnRime, LMN},
-    pal, precession,
+    observed::Weather,
+    pal,
+    pol_frame::PolFrame,
+    precession,
     radec::RADec,
+    sky_cell::SkyCell,
     uvw::UVW,
     xyz::{XyzGeocentric, XyzGeodetic},
 };
 pub use selection::{SelectionError, VisSelection};
+pub use stokes::{PolBasis, Stokes};
+pub use vis_array::VisArray;
 
+// Re-export the crates that appear in marlu's public API, so that downstream
+// crates can construct arguments (e.g. arrays of any `ndarray::Data` storage)
+// with exactly the same versions that marlu uses.
 pub use erfa;
 pub use hifitime;
 pub use ndarray;
 pub use num_complex;
 pub use num_complex::Complex;
 pub use num_traits;
+#[cfg(feature = "rayon")]
 pub use rayon;
 
 // Include the generated built.rs code into our library
@@ -85,7 +122,12 @@ cfg_if::cfg_if! {
 }
 
 #[cfg(feature = "cfitsio")]
-pub use io::{UvfitsWriteError, UvfitsWriter};
+pub use io::{
+    FitsIdiWriteError, FitsIdiWriter, MwafFlags, MwafReadError, MwafWriteError, MwafWriter,
+    UvfitsWriteError, UvfitsWriter,
+};
+#[cfg(feature = "miriad")]
+pub use io::{MiriadWriteError, MiriadWriter};
 
 // If "ms" is enabled, re-export rubbl_casatables here.
 cfg_if::cfg_if! {
@@ -0,0 +1,248 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Mueller matrices, which describe how an instrument (e.g. a beam or
+//! polarisation leakage) transforms the polarised brightness of a source.
+//!
+//! A [`Mueller`] is stored in the coherency basis; it acts on visibilities
+//! flattened to `[XX, XY, YX, YY]`. [`Mueller::to_stokes_basis`] gives the
+//! equivalent (real) matrix acting on `[I, Q, U, V]`.
+
+use std::ops::{Index, IndexMut, Mul};
+
+use num_traits::Float;
+
+use crate::{Complex, Jones};
+
+/// A 4x4 complex Mueller matrix in the coherency (`[XX, XY, YX, YY]`) basis.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Mueller<F: Float>([[Complex<F>; 4]; 4]);
+
+impl<F: Float> Mueller<F> {
+    /// Return an identity matrix.
+    pub fn identity() -> Self {
+        let mut m = [[Complex::new(F::zero(), F::zero()); 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = Complex::new(F::one(), F::zero());
+        }
+        Self(m)
+    }
+
+    /// Apply this Mueller matrix to a visibility (coherency) matrix.
+    pub fn apply(&self, vis: Jones<F>) -> Jones<F> {
+        let mut out = Jones::from([Complex::new(F::zero(), F::zero()); 4]);
+        for (o, row) in out.iter_mut().zip(self.0.iter()) {
+            *o = row
+                .iter()
+                .zip(vis.iter())
+                .fold(Complex::new(F::zero(), F::zero()), |acc, (&m, &v)| {
+                    acc + m * v
+                });
+        }
+        out
+    }
+
+    /// Get the equivalent Mueller matrix acting on Stokes `[I, Q, U, V]`, where
+    /// `XX = I + Q`, `XY = U + iV`, `YX = U - iV` and `YY = I - Q`. Only the
+    /// real parts are returned; the imaginary parts are zero for physical
+    /// Mueller matrices, e.g. those from a [`Jones`] matrix.
+    pub fn to_stokes_basis(&self) -> [[F; 4]; 4] {
+        let zero = F::zero();
+        let one = F::one();
+        let half = one / (one + one);
+        let c = |re, im| Complex::new(re, im);
+        // The coherencies of unit Stokes parameters.
+        let a = [
+            [c(one, zero), c(one, zero), c(zero, zero), c(zero, zero)],
+            [c(zero, zero), c(zero, zero), c(one, zero), c(zero, one)],
+            [c(zero, zero), c(zero, zero), c(one, zero), c(zero, -one)],
+            [c(one, zero), c(-one, zero), c(zero, zero), c(zero, zero)],
+        ];
+        // The inverse of `a` is half of its Hermitian conjugate.
+        let mut out = [[zero; 4]; 4];
+        for (i, out_row) in out.iter_mut().enumerate() {
+            for (j, out) in out_row.iter_mut().enumerate() {
+                let sum = a
+                    .iter()
+                    .zip(self.0.iter())
+                    .fold(c(zero, zero), |acc, (a_k, m_k)| {
+                        m_k.iter()
+                            .zip(a.iter())
+                            .fold(acc, |acc, (&m_kl, a_l)| acc + a_k[i].conj() * m_kl * a_l[j])
+                    });
+                *out = sum.re * half;
+            }
+        }
+        out
+    }
+}
+
+impl<F: Float> From<Jones<F>> for Mueller<F> {
+    /// The Mueller matrix `J ⊗ J*` of a [`Jones`] matrix `J`, so that applying
+    /// it to a visibility `V` is the same as `J V J^H`.
+    fn from(j: Jones<F>) -> Self {
+        let mut m = [[Complex::new(F::zero(), F::zero()); 4]; 4];
+        for (r, row) in m.iter_mut().enumerate() {
+            let (i, k) = (r / 2, r % 2);
+            for (c, element) in row.iter_mut().enumerate() {
+                let (j2, l) = (c / 2, c % 2);
+                *element = j[2 * i + j2] * j[2 * k + l].conj();
+            }
+        }
+        Self(m)
+    }
+}
+
+impl<F: Float> From<[[Complex<F>; 4]; 4]> for Mueller<F> {
+    fn from(m: [[Complex<F>; 4]; 4]) -> Self {
+        Self(m)
+    }
+}
+
+impl<F: Float> Index<(usize, usize)> for Mueller<F> {
+    type Output = Complex<F>;
+
+    fn index(&self, (row, col): (usize, usize)) -> &Complex<F> {
+        &self.0[row][col]
+    }
+}
+
+impl<F: Float> IndexMut<(usize, usize)> for Mueller<F> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut Complex<F> {
+        &mut self.0[row][col]
+    }
+}
+
+impl<F: Float> Mul<Mueller<F>> for Mueller<F> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let mut m = [[Complex::new(F::zero(), F::zero()); 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, element) in row.iter_mut().enumerate() {
+                *element = (0..4).fold(Complex::new(F::zero(), F::zero()), |acc, k| {
+                    acc + self.0[i][k] * rhs.0[k][j]
+                });
+            }
+        }
+        Self(m)
+    }
+}
+
+impl<F: Float> Mul<F> for Mueller<F> {
+    type Output = Self;
+
+    fn mul(self, rhs: F) -> Self {
+        Self(self.0.map(|row| row.map(|e| e * rhs)))
+    }
+}
+
+impl<F: Float> Mul<Jones<F>> for Mueller<F> {
+    type Output = Jones<F>;
+
+    /// See [`Mueller::apply`].
+    fn mul(self, rhs: Jones<F>) -> Jones<F> {
+        self.apply(rhs)
+    }
+}
+
+#[cfg(any(test, feature = "approx"))]
+impl<F: Float + approx::AbsDiffEq> approx::AbsDiffEq for Mueller<F>
+where
+    F::Epsilon: Copy,
+{
+    type Epsilon = F::Epsilon;
+
+    #[inline]
+    fn default_epsilon() -> F::Epsilon {
+        F::default_epsilon()
+    }
+
+    #[inline]
+    fn abs_diff_eq(&self, other: &Self, epsilon: F::Epsilon) -> bool {
+        self.0
+            .iter()
+            .flatten()
+            .zip(other.0.iter().flatten())
+            .all(|(s, o)| {
+                F::abs_diff_eq(&s.re, &o.re, epsilon) && F::abs_diff_eq(&s.im, &o.im, epsilon)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c64;
+    use approx::assert_abs_diff_eq;
+
+    fn jones_a() -> Jones<f64> {
+        Jones::from([
+            c64::new(1.0, 0.2),
+            c64::new(0.1, -0.3),
+            c64::new(-0.2, 0.1),
+            c64::new(0.9, 0.4),
+        ])
+    }
+
+    fn jones_b() -> Jones<f64> {
+        Jones::from([
+            c64::new(0.5, -1.0),
+            c64::new(0.0, 0.7),
+            c64::new(1.2, 0.0),
+            c64::new(-0.3, 0.2),
+        ])
+    }
+
+    #[test]
+    fn test_from_jones() {
+        let j = jones_a();
+        let vis = jones_b();
+        let m = Mueller::from(j);
+        assert_abs_diff_eq!(m.apply(vis), j * vis * j.h(), epsilon = 1e-12);
+        assert_abs_diff_eq!(m * vis, j * vis * j.h(), epsilon = 1e-12);
+
+        assert_abs_diff_eq!(Mueller::from(Jones::<f64>::identity()), Mueller::identity());
+    }
+
+    #[test]
+    fn test_mul() {
+        // The Mueller matrix of a product of Jones matrices is the product of
+        // their Mueller matrices.
+        let a = jones_a();
+        let b = jones_b();
+        assert_abs_diff_eq!(
+            Mueller::from(a) * Mueller::from(b),
+            Mueller::from(a * b),
+            epsilon = 1e-12
+        );
+        let m = Mueller::from(a) * 2.0;
+        assert_abs_diff_eq!(m[(1, 2)], Mueller::from(a)[(1, 2)] * 2.0);
+    }
+
+    #[test]
+    fn test_to_stokes_basis() {
+        let identity = Mueller::<f64>::identity().to_stokes_basis();
+        for (i, row) in identity.iter().enumerate() {
+            for (j, &e) in row.iter().enumerate() {
+                assert_abs_diff_eq!(e, if i == j { 1.0 } else { 0.0 });
+            }
+        }
+
+        // Leakage of Q into I from a gain difference between X and Y.
+        let j = Jones::from([
+            c64::new(2.0, 0.0),
+            c64::default(),
+            c64::default(),
+            c64::new(1.0, 0.0),
+        ]);
+        let m = Mueller::from(j).to_stokes_basis();
+        assert_abs_diff_eq!(m[0][0], 2.5);
+        assert_abs_diff_eq!(m[0][1], 1.5);
+        assert_abs_diff_eq!(m[1][0], 1.5);
+        assert_abs_diff_eq!(m[1][1], 2.5);
+        assert_abs_diff_eq!(m[2][2], 2.0);
+        assert_abs_diff_eq!(m[3][3], 2.0);
+    }
+}
@@ -0,0 +1,366 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Padding and trimming visibilities along an axis, while keeping the
+//! accompanying [`VisContext`] consistent.
+//!
+//! This is useful for adapting data to fixed-shape GPU kernels or external
+//! tools. Padding is always zero-valued, zero-weighted and flagged.
+
+use std::ops::Range;
+
+use ndarray::prelude::*;
+use thiserror::Error;
+
+use crate::{freq::FilledVis, Jones, VisContext};
+
+#[derive(Error, Debug)]
+pub enum PadError {
+    #[error("bad array shape supplied to argument {argument} of function {function}. expected {expected}, received {received}")]
+    BadArrayShape {
+        argument: &'static str,
+        function: &'static str,
+        expected: String,
+        received: String,
+    },
+
+    #[error("can't trim the {axis:?} axis of length {len} to {range:?}")]
+    BadRange {
+        axis: VisAxis,
+        range: Range<usize>,
+        len: usize,
+    },
+
+    #[error("can't pad the {axis:?} axis of length {len} to {target}, which is shorter")]
+    TooLong {
+        axis: VisAxis,
+        len: usize,
+        target: usize,
+    },
+}
+
+/// An axis of a `[timestep][channel][baseline]` visibility array.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VisAxis {
+    Time,
+    Freq,
+    Baseline,
+}
+
+impl VisAxis {
+    /// The ndarray [`Axis`] of this axis.
+    pub fn axis(self) -> Axis {
+        match self {
+            Self::Time => Axis(0),
+            Self::Freq => Axis(1),
+            Self::Baseline => Axis(2),
+        }
+    }
+}
+
+fn check_dims(
+    function: &'static str,
+    vis_ctx: &VisContext,
+    jones: &ArrayView3<Jones<f32>>,
+    weights: &ArrayView3<f32>,
+    flags: &ArrayView3<bool>,
+) -> Result<(), PadError> {
+    let sel_dims = vis_ctx.sel_dims();
+    for (argument, received) in [
+        ("jones", jones.dim()),
+        ("weights", weights.dim()),
+        ("flags", flags.dim()),
+    ] {
+        if received != sel_dims {
+            return Err(PadError::BadArrayShape {
+                argument,
+                function,
+                expected: format!("{sel_dims:?}"),
+                received: format!("{received:?}"),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Pad visibilities with `before` and `after` flagged elements along `axis`.
+///
+/// The start time or frequency of the returned context is moved back by
+/// `before` integration times or channels. Padding baselines are given the
+/// tile pair `fill_baseline`, e.g. an autocorrelation.
+#[allow(clippy::too_many_arguments)]
+pub fn pad_vis(
+    vis_ctx: &VisContext,
+    axis: VisAxis,
+    before: usize,
+    after: usize,
+    fill_baseline: (usize, usize),
+    jones: ArrayView3<Jones<f32>>,
+    weights: ArrayView3<f32>,
+    flags: ArrayView3<bool>,
+) -> Result<FilledVis, PadError> {
+    check_dims("pad_vis", vis_ctx, &jones, &weights, &flags)?;
+
+    let mut padded_ctx = vis_ctx.clone();
+    match axis {
+        VisAxis::Time => {
+            padded_ctx.start_timestamp =
+                vis_ctx.start_timestamp - vis_ctx.int_time * (before as i64);
+            padded_ctx.num_sel_timesteps += before + after;
+        }
+        VisAxis::Freq => {
+            padded_ctx.start_freq_hz -= before as f64 * vis_ctx.freq_resolution_hz;
+            padded_ctx.num_sel_chans += before + after;
+        }
+        VisAxis::Baseline => {
+            padded_ctx.sel_baselines = std::iter::repeat(fill_baseline)
+                .take(before)
+                .chain(vis_ctx.sel_baselines.iter().copied())
+                .chain(std::iter::repeat(fill_baseline).take(after))
+                .collect();
+        }
+    }
+    let padded_dims = padded_ctx.sel_dims();
+
+    let mut padded = FilledVis {
+        jones: Array3::zeros(padded_dims),
+        weights: Array3::zeros(padded_dims),
+        flags: Array3::from_elem(padded_dims, true),
+        vis_ctx: padded_ctx,
+    };
+    let len = jones.len_of(axis.axis());
+    let dst = before..before + len;
+    padded
+        .jones
+        .slice_axis_mut(axis.axis(), dst.clone().into())
+        .assign(&jones);
+    padded
+        .weights
+        .slice_axis_mut(axis.axis(), dst.clone().into())
+        .assign(&weights);
+    padded
+        .flags
+        .slice_axis_mut(axis.axis(), dst.into())
+        .assign(&flags);
+
+    Ok(padded)
+}
+
+/// Pad visibilities at the end of `axis` so that it has length `target`. See
+/// [`pad_vis`].
+pub fn pad_vis_to(
+    vis_ctx: &VisContext,
+    axis: VisAxis,
+    target: usize,
+    fill_baseline: (usize, usize),
+    jones: ArrayView3<Jones<f32>>,
+    weights: ArrayView3<f32>,
+    flags: ArrayView3<bool>,
+) -> Result<FilledVis, PadError> {
+    let len = jones.len_of(axis.axis());
+    if len > target {
+        return Err(PadError::TooLong { axis, len, target });
+    }
+    pad_vis(
+        vis_ctx,
+        axis,
+        0,
+        target - len,
+        fill_baseline,
+        jones,
+        weights,
+        flags,
+    )
+}
+
+/// The context of visibilities trimmed along `axis` to the indices in
+/// `range`. The start time or frequency is moved forward to the first timestep
+/// or channel in `range`.
+///
+/// # Panics
+///
+/// Panics if `range` is out of bounds of the baseline axis.
+pub fn trim_vis_ctx(vis_ctx: &VisContext, axis: VisAxis, range: Range<usize>) -> VisContext {
+    let mut trimmed_ctx = vis_ctx.clone();
+    match axis {
+        VisAxis::Time => {
+            trimmed_ctx.start_timestamp =
+                vis_ctx.start_timestamp + vis_ctx.int_time * (range.start as i64);
+            trimmed_ctx.num_sel_timesteps = range.len();
+        }
+        VisAxis::Freq => {
+            trimmed_ctx.start_freq_hz += range.start as f64 * vis_ctx.freq_resolution_hz;
+            trimmed_ctx.num_sel_chans = range.len();
+        }
+        VisAxis::Baseline => {
+            trimmed_ctx.sel_baselines = vis_ctx.sel_baselines[range].to_vec();
+        }
+    }
+    trimmed_ctx
+}
+
+/// Trim visibilities along `axis` to the indices in `range`. See
+/// [`trim_vis_ctx`].
+pub fn trim_vis(
+    vis_ctx: &VisContext,
+    axis: VisAxis,
+    range: Range<usize>,
+    jones: ArrayView3<Jones<f32>>,
+    weights: ArrayView3<f32>,
+    flags: ArrayView3<bool>,
+) -> Result<FilledVis, PadError> {
+    check_dims("trim_vis", vis_ctx, &jones, &weights, &flags)?;
+    let len = jones.len_of(axis.axis());
+    if range.start > range.end || range.end > len {
+        return Err(PadError::BadRange { axis, range, len });
+    }
+
+    let trimmed_ctx = trim_vis_ctx(vis_ctx, axis, range.clone());
+
+    Ok(FilledVis {
+        vis_ctx: trimmed_ctx,
+        jones: jones
+            .slice_axis(axis.axis(), range.clone().into())
+            .to_owned(),
+        weights: weights
+            .slice_axis(axis.axis(), range.clone().into())
+            .to_owned(),
+        flags: flags.slice_axis(axis.axis(), range.into()).to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use hifitime::{Duration, Epoch};
+
+    use super::*;
+
+    fn get_vis_ctx() -> VisContext {
+        VisContext {
+            num_sel_timesteps: 3,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_seconds(2.),
+            num_sel_chans: 4,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        }
+    }
+
+    #[test]
+    fn test_pad_and_trim_vis() {
+        let vis_ctx = get_vis_ctx();
+        let dims = vis_ctx.sel_dims();
+        let jones = Array3::from_elem(dims, Jones::identity());
+        let weights = Array3::from_elem(dims, 1.0);
+        let flags = Array3::from_elem(dims, false);
+
+        let padded = pad_vis(
+            &vis_ctx,
+            VisAxis::Freq,
+            1,
+            3,
+            (0, 0),
+            jones.view(),
+            weights.view(),
+            flags.view(),
+        )
+        .unwrap();
+        assert_eq!(padded.jones.dim(), (3, 8, 3));
+        assert_eq!(padded.vis_ctx.num_sel_chans, 8);
+        assert_abs_diff_eq!(padded.vis_ctx.start_freq_hz, 150e6 - 40e3);
+        assert!(padded.flags.slice(s![.., 0, ..]).iter().all(|&f| f));
+        assert!(padded.flags.slice(s![.., 1..5, ..]).iter().all(|&f| !f));
+        assert!(padded
+            .weights
+            .slice(s![.., 5.., ..])
+            .iter()
+            .all(|&w| w <= 0.));
+
+        // trimming the padding off again gives back the original.
+        let trimmed = trim_vis(
+            &padded.vis_ctx,
+            VisAxis::Freq,
+            1..5,
+            padded.jones.view(),
+            padded.weights.view(),
+            padded.flags.view(),
+        )
+        .unwrap();
+        assert_abs_diff_eq!(trimmed.vis_ctx.start_freq_hz, vis_ctx.start_freq_hz);
+        assert_eq!(trimmed.vis_ctx.frequencies_hz(), vis_ctx.frequencies_hz());
+        assert_eq!(trimmed.flags, flags);
+
+        let padded = pad_vis_to(
+            &vis_ctx,
+            VisAxis::Baseline,
+            4,
+            (2, 2),
+            jones.view(),
+            weights.view(),
+            flags.view(),
+        )
+        .unwrap();
+        assert_eq!(
+            padded.vis_ctx.sel_baselines,
+            vec![(0, 1), (0, 2), (1, 2), (2, 2)]
+        );
+        assert!(padded.flags.slice(s![.., .., 3]).iter().all(|&f| f));
+
+        let padded = pad_vis(
+            &vis_ctx,
+            VisAxis::Time,
+            2,
+            0,
+            (0, 0),
+            jones.view(),
+            weights.view(),
+            flags.view(),
+        )
+        .unwrap();
+        assert_eq!(
+            padded.vis_ctx.start_timestamp,
+            vis_ctx.start_timestamp - Duration::from_seconds(4.)
+        );
+        let trimmed = trim_vis(
+            &padded.vis_ctx,
+            VisAxis::Time,
+            2..4,
+            padded.jones.view(),
+            padded.weights.view(),
+            padded.flags.view(),
+        )
+        .unwrap();
+        assert_eq!(trimmed.vis_ctx.start_timestamp, vis_ctx.start_timestamp);
+        assert_eq!(trimmed.vis_ctx.num_sel_timesteps, 2);
+
+        assert!(matches!(
+            trim_vis(
+                &vis_ctx,
+                VisAxis::Baseline,
+                2..4,
+                jones.view(),
+                weights.view(),
+                flags.view(),
+            ),
+            Err(PadError::BadRange { len: 3, .. })
+        ));
+        assert!(matches!(
+            pad_vis_to(
+                &vis_ctx,
+                VisAxis::Time,
+                2,
+                (0, 0),
+                jones.view(),
+                weights.view(),
+                flags.view(),
+            ),
+            Err(PadError::TooLong { .. })
+        ));
+    }
+}
@@ -0,0 +1,322 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Deterministic partitioning of visibilities into shards for distributed
+//! processing (e.g. MPI ranks or slurm array jobs), and merging the outputs of
+//! each shard back together.
+//!
+//! Every job can compute the full partition independently from the same
+//! [`VisContext`], so only the shard index needs to be communicated (e.g.
+//! `SLURM_ARRAY_TASK_ID`). Shard boundaries on the time and frequency axes are
+//! aligned to the averaging factors, so averaged outputs of each shard can be
+//! concatenated.
+
+use std::ops::Range;
+
+use ndarray::{concatenate, prelude::*, RemoveAxis};
+use thiserror::Error;
+
+use crate::{
+    pad::{trim_vis_ctx, VisAxis},
+    VisContext,
+};
+
+#[derive(Error, Debug)]
+pub enum PartitionError {
+    #[error("can't partition into zero shards")]
+    NoShards,
+
+    #[error("can't partition {num_units} elements of the {axis:?} axis into {num_shards} shards")]
+    TooManyShards {
+        axis: VisAxis,
+        num_units: usize,
+        num_shards: usize,
+    },
+
+    #[error("requested shard {shard_idx}, but there are only {num_shards} shards")]
+    NoSuchShard { shard_idx: usize, num_shards: usize },
+
+    #[error("expected outputs for {expected} shards, received {received}")]
+    WrongNumberOfOutputs { expected: usize, received: usize },
+
+    #[error("the output of shard {shard_idx} has shape {received:?}, which doesn't fit with the other shards")]
+    BadOutputShape {
+        shard_idx: usize,
+        received: Vec<usize>,
+    },
+}
+
+/// A contiguous part of a partitioned selection of visibilities.
+#[derive(Debug, Clone)]
+pub struct Shard {
+    /// The index of this shard.
+    pub shard_idx: usize,
+
+    /// The total number of shards in the partition.
+    pub num_shards: usize,
+
+    /// The axis that was partitioned.
+    pub axis: VisAxis,
+
+    /// The range of (pre-averaging) indices into `axis` of the original
+    /// visibilities that belong to this shard.
+    pub range: Range<usize>,
+
+    /// The range of post-averaging indices into `axis` of the original
+    /// averaged visibilities that belong to this shard.
+    pub avg_range: Range<usize>,
+
+    /// The context of the visibilities in this shard.
+    pub vis_ctx: VisContext,
+}
+
+/// Split the visibilities described by `vis_ctx` into `num_shards` contiguous
+/// shards along `axis`.
+///
+/// The shards are as even as possible; earlier shards are one element larger
+/// than later shards if the axis doesn't divide evenly. On the time and
+/// frequency axes, an "element" is a chunk of `avg_time` timesteps or
+/// `avg_freq` channels.
+///
+/// # Errors
+///
+/// Will return [`PartitionError`] if `num_shards` is zero, or larger than the
+/// number of elements of `axis`.
+pub fn partition(
+    vis_ctx: &VisContext,
+    axis: VisAxis,
+    num_shards: usize,
+) -> Result<Vec<Shard>, PartitionError> {
+    let (len, chunk_size) = match axis {
+        VisAxis::Time => (vis_ctx.num_sel_timesteps, vis_ctx.avg_time),
+        VisAxis::Freq => (vis_ctx.num_sel_chans, vis_ctx.avg_freq),
+        VisAxis::Baseline => (vis_ctx.sel_baselines.len(), 1),
+    };
+    let num_units = (len + chunk_size - 1) / chunk_size;
+    if num_shards == 0 {
+        return Err(PartitionError::NoShards);
+    }
+    if num_shards > num_units {
+        return Err(PartitionError::TooManyShards {
+            axis,
+            num_units,
+            num_shards,
+        });
+    }
+
+    let base = num_units / num_shards;
+    let remainder = num_units % num_shards;
+    let mut avg_start = 0;
+    Ok((0..num_shards)
+        .map(|shard_idx| {
+            let avg_len = base + usize::from(shard_idx < remainder);
+            let avg_range = avg_start..avg_start + avg_len;
+            avg_start += avg_len;
+            let range = avg_range.start * chunk_size..(avg_range.end * chunk_size).min(len);
+            Shard {
+                shard_idx,
+                num_shards,
+                axis,
+                vis_ctx: trim_vis_ctx(vis_ctx, axis, range.clone()),
+                range,
+                avg_range,
+            }
+        })
+        .collect())
+}
+
+/// Get a single shard of a partition. See [`partition`].
+///
+/// # Errors
+///
+/// Will return [`PartitionError::NoSuchShard`] if `shard_idx` is not less than
+/// `num_shards`, or any error from [`partition`].
+pub fn get_shard(
+    vis_ctx: &VisContext,
+    axis: VisAxis,
+    num_shards: usize,
+    shard_idx: usize,
+) -> Result<Shard, PartitionError> {
+    if shard_idx >= num_shards {
+        return Err(PartitionError::NoSuchShard {
+            shard_idx,
+            num_shards,
+        });
+    }
+    let mut shards = partition(vis_ctx, axis, num_shards)?;
+    Ok(shards.swap_remove(shard_idx))
+}
+
+fn check_num_outputs(shards: &[Shard], received: usize) -> Result<(), PartitionError> {
+    if shards.len() != received {
+        return Err(PartitionError::WrongNumberOfOutputs {
+            expected: shards.len(),
+            received,
+        });
+    }
+    Ok(())
+}
+
+/// Merge the per-shard outputs of a partition (e.g. flags or averaged
+/// visibilities) by concatenating them along the partitioned axis, in shard
+/// order. `outputs` must have the same dimensions as the original visibilities,
+/// e.g. `[timestep][channel][baseline]` or `[timestep][channel][baseline][pol]`.
+///
+/// # Errors
+///
+/// Will return [`PartitionError`] if there isn't exactly one output per shard,
+/// or if the outputs can't be concatenated.
+pub fn merge_shards<A, D>(
+    shards: &[Shard],
+    outputs: &[ArrayView<A, D>],
+) -> Result<Array<A, D>, PartitionError>
+where
+    A: Clone,
+    D: RemoveAxis,
+{
+    check_num_outputs(shards, outputs.len())?;
+    let axis = match shards.first() {
+        Some(shard) => shard.axis.axis(),
+        None => return Err(PartitionError::NoShards),
+    };
+    concatenate(axis, outputs).map_err(|_| {
+        // Find the first output that doesn't match the first one.
+        let first = outputs[0].shape();
+        let shard_idx = outputs
+            .iter()
+            .position(|o| {
+                o.ndim() != first.len()
+                    || o.shape()
+                        .iter()
+                        .zip(first.iter())
+                        .enumerate()
+                        .any(|(i, (a, b))| i != axis.index() && a != b)
+            })
+            .unwrap_or(0);
+        PartitionError::BadOutputShape {
+            shard_idx,
+            received: outputs[shard_idx].shape().to_vec(),
+        }
+    })
+}
+
+/// Merge per-shard outputs which are accumulated over the partitioned axis
+/// (e.g. QA statistics such as per-baseline flag counts) by summing them.
+///
+/// # Errors
+///
+/// Will return [`PartitionError`] if there isn't exactly one output per shard,
+/// or if the outputs don't all have the same shape.
+pub fn sum_shards<A, D>(
+    shards: &[Shard],
+    outputs: &[ArrayView<A, D>],
+) -> Result<Array<A, D>, PartitionError>
+where
+    A: Clone + std::ops::AddAssign,
+    D: Dimension,
+{
+    check_num_outputs(shards, outputs.len())?;
+    let (first, rest) = outputs.split_first().ok_or(PartitionError::NoShards)?;
+    let mut result = first.to_owned();
+    for (shard_idx, output) in rest.iter().enumerate() {
+        if output.shape() != result.shape() {
+            return Err(PartitionError::BadOutputShape {
+                shard_idx: shard_idx + 1,
+                received: output.shape().to_vec(),
+            });
+        }
+        result.zip_mut_with(output, |r, o| *r += o.clone());
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use hifitime::{Duration, Epoch};
+
+    use super::*;
+
+    fn get_vis_ctx() -> VisContext {
+        VisContext {
+            num_sel_timesteps: 10,
+            start_timestamp: Epoch::from_gpst_seconds(1090008640.),
+            int_time: Duration::from_seconds(1.),
+            num_sel_chans: 8,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1), (0, 2), (1, 2)],
+            avg_time: 3,
+            avg_freq: 2,
+            num_vis_pols: 4,
+        }
+    }
+
+    #[test]
+    fn test_partition_time() {
+        let vis_ctx = get_vis_ctx();
+        // 10 timesteps averaged by 3 -> 4 averaged timesteps.
+        let shards = partition(&vis_ctx, VisAxis::Time, 3).unwrap();
+        assert_eq!(
+            shards.iter().map(|s| s.range.clone()).collect::<Vec<_>>(),
+            vec![0..6, 6..9, 9..10]
+        );
+        assert_eq!(
+            shards
+                .iter()
+                .map(|s| s.avg_range.clone())
+                .collect::<Vec<_>>(),
+            vec![0..2, 2..3, 3..4]
+        );
+        assert_eq!(
+            shards[1].vis_ctx.start_timestamp,
+            vis_ctx.start_timestamp + Duration::from_seconds(6.)
+        );
+        assert_eq!(
+            shards
+                .iter()
+                .map(|s| s.vis_ctx.num_avg_timesteps())
+                .sum::<usize>(),
+            vis_ctx.num_avg_timesteps()
+        );
+
+        let shard = get_shard(&vis_ctx, VisAxis::Time, 3, 2).unwrap();
+        assert_eq!(shard.range, 9..10);
+
+        assert!(matches!(
+            partition(&vis_ctx, VisAxis::Time, 5),
+            Err(PartitionError::TooManyShards { num_units: 4, .. })
+        ));
+        assert!(matches!(
+            partition(&vis_ctx, VisAxis::Time, 0),
+            Err(PartitionError::NoShards)
+        ));
+        assert!(matches!(
+            get_shard(&vis_ctx, VisAxis::Time, 3, 3),
+            Err(PartitionError::NoSuchShard { .. })
+        ));
+    }
+
+    #[test]
+    fn test_partition_and_merge_baselines() {
+        let vis_ctx = get_vis_ctx();
+        let shards = partition(&vis_ctx, VisAxis::Baseline, 2).unwrap();
+        assert_eq!(shards[0].vis_ctx.sel_baselines, vec![(0, 1), (0, 2)]);
+        assert_eq!(shards[1].vis_ctx.sel_baselines, vec![(1, 2)]);
+
+        let flags = Array3::from_shape_fn(vis_ctx.sel_dims(), |(t, c, b)| (t + c + b) % 2 == 0);
+        let outputs: Vec<_> = shards
+            .iter()
+            .map(|s| flags.slice_axis(Axis(2), s.range.clone().into()))
+            .collect();
+        assert_eq!(merge_shards(&shards, &outputs).unwrap(), flags);
+        assert!(matches!(
+            merge_shards(&shards, &outputs[..1]),
+            Err(PartitionError::WrongNumberOfOutputs { .. })
+        ));
+
+        let counts = [array![1_u32, 2, 3], array![10, 20, 30]];
+        let counts: Vec<_> = counts.iter().map(|c| c.view()).collect();
+        assert_eq!(sum_shards(&shards, &counts).unwrap(), array![11, 22, 33]);
+    }
+}
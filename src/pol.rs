@@ -0,0 +1,205 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Linear polarisation products and the orders they're stored in.
+//!
+//! [`Jones`](crate::Jones) matrices (and measurement sets) store visibilities
+//! as `[XX, XY, YX, YY]`, whereas uvfits files (and pyuvdata) use
+//! `[XX, YY, XY, YX]`, labelled with the AIPS codes -5 to -8.
+
+use ndarray::{prelude::*, RemoveAxis};
+
+/// A linear polarisation product.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Pol {
+    XX,
+    XY,
+    YX,
+    YY,
+}
+
+impl Pol {
+    /// The index of this product in a [`Jones`](crate::Jones) matrix.
+    pub fn jones_index(self) -> usize {
+        match self {
+            Pol::XX => 0,
+            Pol::XY => 1,
+            Pol::YX => 2,
+            Pol::YY => 3,
+        }
+    }
+
+    /// The AIPS (and pyuvdata) code of this product, e.g. -5 for XX.
+    pub fn aips_code(self) -> i32 {
+        match self {
+            Pol::XX => -5,
+            Pol::YY => -6,
+            Pol::XY => -7,
+            Pol::YX => -8,
+        }
+    }
+
+    /// Get the product with an AIPS (and pyuvdata) code, if it's a linear
+    /// product.
+    pub fn from_aips_code(code: i32) -> Option<Pol> {
+        match code {
+            -5 => Some(Pol::XX),
+            -6 => Some(Pol::YY),
+            -7 => Some(Pol::XY),
+            -8 => Some(Pol::YX),
+            _ => None,
+        }
+    }
+
+    /// The `CORR_TYPE` of this product in a measurement set, e.g. 9 for XX.
+    pub fn ms_corr_type(self) -> i32 {
+        match self {
+            Pol::XX => 9,
+            Pol::XY => 10,
+            Pol::YX => 11,
+            Pol::YY => 12,
+        }
+    }
+
+    /// Get the product with a measurement set `CORR_TYPE`, if it's a linear
+    /// product.
+    pub fn from_ms_corr_type(corr_type: i32) -> Option<Pol> {
+        match corr_type {
+            9 => Some(Pol::XX),
+            10 => Some(Pol::XY),
+            11 => Some(Pol::YX),
+            12 => Some(Pol::YY),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Pol {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// An order of the four linear polarisation products.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolOrder {
+    /// `[XX, XY, YX, YY]`, as in [`Jones`](crate::Jones) matrices and
+    /// measurement sets.
+    Jones,
+
+    /// `[XX, YY, XY, YX]`, i.e. AIPS codes `[-5, -6, -7, -8]`, as in uvfits
+    /// files and pyuvdata.
+    Aips,
+}
+
+impl PolOrder {
+    /// The products in this order.
+    pub fn pols(self) -> [Pol; 4] {
+        match self {
+            PolOrder::Jones => [Pol::XX, Pol::XY, Pol::YX, Pol::YY],
+            PolOrder::Aips => [Pol::XX, Pol::YY, Pol::XY, Pol::YX],
+        }
+    }
+
+    /// The AIPS codes of the products in this order.
+    pub fn aips_codes(self) -> [i32; 4] {
+        self.pols().map(Pol::aips_code)
+    }
+
+    /// Get the order of a list of AIPS codes (e.g. from a uvfits header or
+    /// pyuvdata's `polarization_array`), if it's one of the known orders.
+    pub fn from_aips_codes(codes: &[i32]) -> Option<PolOrder> {
+        [PolOrder::Jones, PolOrder::Aips]
+            .into_iter()
+            .find(|order| order.aips_codes() == codes)
+    }
+
+    /// The indices into values in this order that put them in the `to` order,
+    /// i.e. `to_values[i] = values[permutation[i]]`.
+    pub fn permutation_to(self, to: PolOrder) -> [usize; 4] {
+        let from = self.pols();
+        to.pols().map(|pol| {
+            from.iter()
+                .position(|&p| p == pol)
+                .expect("all orders contain every product")
+        })
+    }
+
+    /// Reorder four values from this order to the `to` order.
+    pub fn reorder<T: Copy>(self, to: PolOrder, values: [T; 4]) -> [T; 4] {
+        self.permutation_to(to).map(|i| values[i])
+    }
+}
+
+/// Reorder the polarisation axis `axis` of `array` (which must have length 4)
+/// from the `from` order to the `to` order.
+///
+/// # Panics
+///
+/// Panics if `axis` isn't an axis of `array`, or doesn't have length 4.
+#[allow(clippy::needless_pass_by_value)]
+pub fn reorder_pol_axis<T: Clone, D: RemoveAxis>(
+    array: ArrayView<T, D>,
+    axis: Axis,
+    from: PolOrder,
+    to: PolOrder,
+) -> Array<T, D> {
+    assert_eq!(
+        array.len_of(axis),
+        4,
+        "the polarisation axis must have 4 elements"
+    );
+    array.select(axis, &from.permutation_to(to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pol_codes() {
+        for pol in PolOrder::Jones.pols() {
+            assert_eq!(Pol::from_aips_code(pol.aips_code()), Some(pol));
+            assert_eq!(Pol::from_ms_corr_type(pol.ms_corr_type()), Some(pol));
+            assert_eq!(PolOrder::Jones.pols()[pol.jones_index()], pol);
+        }
+        assert_eq!(Pol::from_aips_code(-1), None);
+        assert_eq!(Pol::from_ms_corr_type(5), None);
+        assert_eq!(Pol::YX.to_string(), "YX");
+
+        assert_eq!(PolOrder::Aips.aips_codes(), [-5, -6, -7, -8]);
+        assert_eq!(
+            PolOrder::from_aips_codes(&[-5, -6, -7, -8]),
+            Some(PolOrder::Aips)
+        );
+        assert_eq!(
+            PolOrder::from_aips_codes(&[-5, -7, -8, -6]),
+            Some(PolOrder::Jones)
+        );
+        assert_eq!(PolOrder::from_aips_codes(&[-5, -6]), None);
+    }
+
+    #[test]
+    fn test_reorder() {
+        let jones_order = ["XX", "XY", "YX", "YY"];
+        let aips_order = PolOrder::Jones.reorder(PolOrder::Aips, jones_order);
+        assert_eq!(aips_order, ["XX", "YY", "XY", "YX"]);
+        assert_eq!(
+            PolOrder::Aips.reorder(PolOrder::Jones, aips_order),
+            jones_order
+        );
+        assert_eq!(
+            PolOrder::Aips.reorder(PolOrder::Aips, aips_order),
+            aips_order
+        );
+
+        // [baseline][pol]
+        let array = array![[0, 1, 2, 3], [10, 11, 12, 13]];
+        let reordered = reorder_pol_axis(array.view(), Axis(1), PolOrder::Jones, PolOrder::Aips);
+        assert_eq!(reordered, array![[0, 3, 1, 2], [10, 13, 11, 12]]);
+        let back = reorder_pol_axis(reordered.view(), Axis(1), PolOrder::Aips, PolOrder::Jones);
+        assert_eq!(back, array);
+    }
+}
@@ -5,18 +5,47 @@
 //! Handle (azimuth, elevation) coordinates (also known as horizontal
 //! coordinates).
 
-use std::f64::consts::FRAC_PI_2;
+use std::f64::consts::{FRAC_PI_2, TAU};
 
 use erfa::aliases::eraAe2hd;
+use hifitime::{Duration, Epoch};
+use ndarray::prelude::*;
 
+use super::earth::LatLngHeight;
 use super::hadec::HADec;
+use super::precession::precess_time;
+use super::radec::RADec;
+#[cfg(feature = "serde")]
+use super::{degrees_to_radians, radians_to_degrees};
+use super::{map_array, position_angle, vincenty_separation};
+
+/// A model of the atmosphere used to calculate airmass.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AirmassModel {
+    /// A flat atmosphere, i.e. `1 / sin(el)`. This is accurate to ~1% above
+    /// 20° elevation.
+    PlaneParallel,
+
+    /// The formula of Kasten & Young (1989), which remains accurate down to
+    /// the horizon (where the airmass is ~38).
+    #[default]
+    KastenYoung,
+}
 
 /// A struct containing an Azimuth and Elevation. All units are in radians.
+///
+/// Note that the serialised units are degrees and are automatically converted
+/// when serialising/deserialising.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AzEl {
-    /// Hour angle \[radians\]
+    /// Azimuth \[radians\]
+    #[cfg_attr(feature = "serde", serde(serialize_with = "radians_to_degrees"))]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "degrees_to_radians"))]
     pub az: f64,
-    /// Declination \[radians\]
+    /// Elevation \[radians\]
+    #[cfg_attr(feature = "serde", serde(serialize_with = "radians_to_degrees"))]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "degrees_to_radians"))]
     pub el: f64,
 }
 
@@ -57,6 +86,55 @@ impl AzEl {
         FRAC_PI_2 - self.el
     }
 
+    /// Get the unit vector pointing at these coordinates, in the (east, north,
+    /// up) frame of the observer.
+    pub fn to_unit_vector(self) -> [f64; 3] {
+        let (s_az, c_az) = self.az.sin_cos();
+        let (s_el, c_el) = self.el.sin_cos();
+        [c_el * s_az, c_el * c_az, s_el]
+    }
+
+    /// Get the coordinates that an (east, north, up) vector points at. The
+    /// vector doesn't need to be normalised. The azimuth is in the range
+    /// `[0, 2π)`. See [`AzEl::to_unit_vector`].
+    pub fn from_unit_vector(v: [f64; 3]) -> AzEl {
+        let [e, n, u] = v;
+        Self::from_radians(e.atan2(n).rem_euclid(TAU), u.atan2(e.hypot(n)))
+    }
+
+    /// Calculate the distance between two sets of coordinates \[radians\].
+    pub fn separation(self, b: Self) -> f64 {
+        vincenty_separation(self.az, self.el, b.az, b.el)
+    }
+
+    /// Calculate the position angle of `b` from these coordinates, measured
+    /// from the direction of the zenith towards increasing azimuth, in the
+    /// range `[0, 2π)` \[radians\].
+    pub fn position_angle(self, b: Self) -> f64 {
+        position_angle(self.az, self.el, b.az, b.el)
+    }
+
+    /// The relative airmass in the direction of these coordinates, i.e. 1 at
+    /// the zenith. Directions where `model` isn't defined (below the horizon
+    /// for [`AirmassModel::PlaneParallel`], or more than ~6° below it for
+    /// [`AirmassModel::KastenYoung`]) give infinity.
+    pub fn airmass(self, model: AirmassModel) -> f64 {
+        match model {
+            AirmassModel::PlaneParallel if self.el > 0.0 => 1.0 / self.el.sin(),
+            AirmassModel::KastenYoung if self.za().to_degrees() < 96.07995 => {
+                let za_deg = self.za().to_degrees();
+                1.0 / (self.za().cos() + 0.50572 * (96.07995 - za_deg).powf(-1.6364))
+            }
+            _ => f64::INFINITY,
+        }
+    }
+
+    /// Whether these coordinates are at or above `min_elevation_rad`, e.g. 0
+    /// for the horizon.
+    pub fn is_above_horizon(self, min_elevation_rad: f64) -> bool {
+        self.el >= min_elevation_rad
+    }
+
     /// Convert the horizon coordinates to equatorial coordinates (Hour Angle
     /// and Declination), given the local latitude on Earth.
     pub fn to_hadec(self, latitude_rad: f64) -> HADec {
@@ -69,6 +147,75 @@ impl AzEl {
     pub fn to_hadec_mwa(self) -> HADec {
         self.to_hadec(crate::constants::MWA_LAT_RAD)
     }
+
+    /// Convert the horizon coordinates to equatorial coordinates (Hour Angle
+    /// and Declination) for an array at `site`.
+    pub fn to_hadec_for_site(self, site: LatLngHeight) -> HADec {
+        self.to_hadec(site.latitude_rad)
+    }
+}
+
+/// Convert many horizon coordinates to equatorial coordinates (Hour Angle and
+/// Declination), given the local latitude on Earth. See [`AzEl::to_hadec`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn to_hadec_array(azels: ArrayView1<AzEl>, latitude_rad: f64) -> Array1<HADec> {
+    map_array(azels, |azel| azel.to_hadec(latitude_rad))
+}
+
+/// The airmass in the direction of many horizon coordinates. See
+/// [`AzEl::airmass`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn airmass_array(azels: ArrayView1<AzEl>, model: AirmassModel) -> Array1<f64> {
+    map_array(azels, |azel| azel.airmass(model))
+}
+
+/// Whether each of many horizon coordinates is at or above
+/// `min_elevation_rad`. See [`AzEl::is_above_horizon`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn above_horizon_array(azels: ArrayView1<AzEl>, min_elevation_rad: f64) -> Array1<bool> {
+    map_array(azels, |azel| azel.is_above_horizon(min_elevation_rad))
+}
+
+/// The azimuths and elevations of a source over an observation, e.g. for
+/// flagging timesteps where a sky-model source is below the horizon.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AzElTrack {
+    /// The position of the source at each timestep.
+    pub azels: Array1<AzEl>,
+}
+
+impl AzElTrack {
+    /// Track `radec` (J2000) from `site` at each of `epochs`. The coordinates
+    /// are precessed to each epoch, like [`precess_time`], and `dut1` is UT1 -
+    /// UTC.
+    pub fn new(radec: RADec, site: LatLngHeight, epochs: &[Epoch], dut1: Duration) -> AzElTrack {
+        let azels = map_array(ArrayView1::from(epochs), |epoch| {
+            let precession_info =
+                precess_time(site.longitude_rad, site.latitude_rad, radec, epoch, dut1);
+            precession_info
+                .hadec_j2000
+                .to_azel(precession_info.array_latitude_j2000)
+        });
+        Self { azels }
+    }
+
+    /// The lowest elevation of the source over the observation \[radians\],
+    /// or [`None`] if there are no timesteps.
+    pub fn min_elevation(&self) -> Option<f64> {
+        self.azels.iter().map(|azel| azel.el).reduce(f64::min)
+    }
+
+    /// The highest elevation of the source over the observation \[radians\],
+    /// or [`None`] if there are no timesteps.
+    pub fn max_elevation(&self) -> Option<f64> {
+        self.azels.iter().map(|azel| azel.el).reduce(f64::max)
+    }
+
+    /// Whether the source is below `min_elevation_rad` at each timestep.
+    pub fn below(&self, min_elevation_rad: f64) -> Array1<bool> {
+        self.azels
+            .map(|azel| !azel.is_above_horizon(min_elevation_rad))
+    }
 }
 
 impl std::fmt::Display for AzEl {
@@ -141,10 +288,155 @@ mod tests {
         assert_abs_diff_eq!(result, expected, epsilon = 1e-10);
     }
 
+    #[test]
+    fn test_array_transforms() {
+        let latitude = -0.497600;
+        let azels = Array1::from_shape_fn(50, |i| {
+            AzEl::from_degrees(i as f64 * 7.0, 10.0 + i as f64 * 1.5)
+        });
+        let hadecs = to_hadec_array(azels.view(), latitude);
+        for (azel, hadec) in azels.iter().zip(hadecs.iter()) {
+            assert_abs_diff_eq!(*hadec, azel.to_hadec(latitude));
+        }
+        let round_trip = crate::pos::hadec::to_azel_array(hadecs.view(), latitude);
+        for (azel, result) in azels.iter().zip(round_trip.iter()) {
+            assert_abs_diff_eq!(*azel, *result, epsilon = 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_azel_track() {
+        let site = LatLngHeight::mwa();
+        let start = Epoch::from_gregorian_utc(2022, 6, 1, 12, 0, 0, 0);
+        let epochs: Vec<Epoch> = (0..24)
+            .map(|h| start + Duration::from_seconds(f64::from(h) * 3600.0))
+            .collect();
+        let radec = RADec::from_degrees(60.0, -30.0);
+        let track = AzElTrack::new(radec, site, &epochs, Duration::from_seconds(0.0));
+        assert_eq!(track.azels.len(), 24);
+
+        // Over a day, the source culminates 3.3° from the zenith, and is at
+        // its lowest 33.3° below the horizon.
+        let max_el = track.max_elevation().unwrap().to_degrees();
+        let min_el = track.min_elevation().unwrap().to_degrees();
+        assert!(max_el > 80.0 && max_el < 87.0, "{max_el}");
+        assert!(min_el < -26.0 && min_el > -34.0, "{min_el}");
+        let below = track.below(0.0);
+        assert!(below.iter().any(|&b| b) && below.iter().any(|&b| !b));
+
+        let empty = AzElTrack::new(radec, site, &[], Duration::from_seconds(0.0));
+        assert!(empty.max_elevation().is_none());
+    }
+
+    #[test]
+    fn test_unit_vectors() {
+        assert_abs_diff_eq!(
+            AzEl::from_degrees(90.0, 0.0).to_unit_vector()[0],
+            1.0,
+            epsilon = 1e-15
+        );
+        let azel = AzEl::from_degrees(300.0, 30.0);
+        assert_abs_diff_eq!(
+            AzEl::from_unit_vector(azel.to_unit_vector()),
+            azel,
+            epsilon = 1e-12
+        );
+
+        let hadec = HADec::from_degrees(-30.0, -60.0);
+        assert_abs_diff_eq!(
+            HADec::from_unit_vector(hadec.to_unit_vector()),
+            hadec,
+            epsilon = 1e-12
+        );
+        // Angles between vectors don't depend on the frame.
+        let lat = -0.497600;
+        let other = HADec::from_degrees(10.0, -20.0);
+        assert_abs_diff_eq!(
+            crate::pos::dot(hadec.to_unit_vector(), other.to_unit_vector()),
+            crate::pos::dot(
+                hadec.to_azel(lat).to_unit_vector(),
+                other.to_azel(lat).to_unit_vector()
+            ),
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn test_airmass() {
+        let zenith = AzEl::from_degrees(0.0, 90.0);
+        assert_abs_diff_eq!(zenith.airmass(AirmassModel::PlaneParallel), 1.0);
+        assert_abs_diff_eq!(
+            zenith.airmass(AirmassModel::KastenYoung),
+            1.0,
+            epsilon = 1e-3
+        );
+
+        let azel = AzEl::from_degrees(0.0, 30.0);
+        assert_abs_diff_eq!(
+            azel.airmass(AirmassModel::PlaneParallel),
+            2.0,
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(
+            azel.airmass(AirmassModel::KastenYoung),
+            1.994,
+            epsilon = 1e-3
+        );
+
+        let horizon = AzEl::from_degrees(0.0, 0.0);
+        assert!(horizon.airmass(AirmassModel::PlaneParallel).is_infinite());
+        assert_abs_diff_eq!(
+            horizon.airmass(AirmassModel::KastenYoung),
+            37.92,
+            epsilon = 0.1
+        );
+        assert!(AzEl::from_degrees(0.0, -10.0)
+            .airmass(AirmassModel::default())
+            .is_infinite());
+
+        let azels = array![zenith, azel, horizon, AzEl::from_degrees(0.0, -1.0)];
+        assert_eq!(
+            above_horizon_array(azels.view(), 0.0),
+            array![true, true, true, false]
+        );
+        let airmasses = airmass_array(azels.view(), AirmassModel::PlaneParallel);
+        assert_abs_diff_eq!(airmasses[1], 2.0, epsilon = 1e-12);
+    }
+
     #[test]
     fn test_za() {
         let ae = AzEl::from_radians(0.261700, 0.785400);
         let za = ae.za();
         assert_abs_diff_eq!(za, 0.7853963268, epsilon = 1e-10);
     }
+
+    #[test]
+    fn test_separation_and_position_angle() {
+        let ae1 = AzEl::from_degrees(10.0, 45.0);
+        let ae2 = AzEl::from_degrees(10.0, 50.0);
+        assert_abs_diff_eq!(ae1.separation(ae2).to_degrees(), 5.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(ae1.position_angle(ae2), 0.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(ae2.position_angle(ae1).to_degrees(), 180.0, epsilon = 1e-10);
+        // the separation is the same in any frame.
+        let lat = -0.497600;
+        assert_abs_diff_eq!(
+            ae1.separation(ae2),
+            ae1.to_hadec(lat).separation(ae2.to_hadec(lat)),
+            epsilon = 1e-10
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let azel = AzEl::from_degrees(120.0, 45.0);
+        let json = serde_json::to_string(&azel).unwrap();
+        let azel2: AzEl = serde_json::from_str(&json).unwrap();
+        assert_abs_diff_eq!(azel, azel2, epsilon = 1e-15);
+
+        // The serialised units are degrees.
+        let azel: AzEl = serde_json::from_str("{\"az\": 180.0, \"el\": 90.0}").unwrap();
+        assert_abs_diff_eq!(azel.az, std::f64::consts::PI);
+        assert_abs_diff_eq!(azel.el, std::f64::consts::FRAC_PI_2);
+    }
 }
@@ -0,0 +1,274 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Pointings of the MWA analogue beamformers.
+//!
+//! A tile is pointed by delaying the signals of its 16 dipoles by whole
+//! multiples of [`MWA_DELAY_STEP_S`]. For most pointings the ideal delays
+//! aren't whole multiples, and the quantised delays distort the beam. The
+//! "sweet spots" are the pointings whose ideal delays are exactly whole
+//! multiples; observations are usually made at these.
+
+use std::f64::consts::TAU;
+
+use super::{azel::AzEl, hadec::HADec};
+use crate::constants::{
+    MWA_DEAD_DIPOLE_DELAY, MWA_DELAY_STEP_S, MWA_DIPOLE_SEPARATION_M, MWA_MAX_DELAY,
+    MWA_NUM_DIPOLES, VEL_C,
+};
+
+/// Sweet spots below this elevation aren't used \[degrees\].
+const MIN_SWEET_SPOT_ELEVATION_DEG: f64 = 15.0;
+
+/// The change in a direction cosine (east or north) that changes the delay
+/// between adjacent dipoles by one step.
+fn direction_cosine_per_step() -> f64 {
+    VEL_C * MWA_DELAY_STEP_S / MWA_DIPOLE_SEPARATION_M
+}
+
+/// The (row, column) of a dipole in its tile, with row 0 in the north and
+/// column 0 in the west.
+fn dipole_row_col(i: usize) -> (f64, f64) {
+    ((i / 4) as f64, (i % 4) as f64)
+}
+
+/// A pointing of the MWA analogue beamformers without any quantisation error.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweetSpot {
+    /// The pointing centre.
+    pub azel: AzEl,
+
+    /// The delay steps of each dipole.
+    pub delays: [u32; MWA_NUM_DIPOLES],
+}
+
+impl SweetSpot {
+    /// Make a sweet spot from the number of delay steps between adjacent
+    /// dipoles towards the east and north. Returns `None` if the pointing is
+    /// below the horizon or the delays aren't achievable.
+    fn from_gradient(east_steps: i32, north_steps: i32) -> Option<SweetSpot> {
+        let l = f64::from(east_steps) * direction_cosine_per_step();
+        let m = f64::from(north_steps) * direction_cosine_per_step();
+        let cos_el = l.hypot(m);
+        if cos_el >= 1.0 {
+            return None;
+        }
+
+        // Dipoles nearer the source receive its signal first, so they need
+        // more delay.
+        let raw: [i32; MWA_NUM_DIPOLES] = std::array::from_fn(|i| {
+            let (row, col) = ((i / 4) as i32, (i % 4) as i32);
+            east_steps * col - north_steps * row
+        });
+        let min = raw.iter().min().copied().unwrap_or_default();
+        let delays = raw.map(|d| (d - min) as u32);
+        if delays.iter().any(|&d| d > MWA_MAX_DELAY) {
+            return None;
+        }
+
+        Some(SweetSpot {
+            azel: AzEl::from_radians(l.atan2(m).rem_euclid(TAU), cos_el.acos()),
+            delays,
+        })
+    }
+}
+
+/// Get all of the MWA sweet spots above 15° elevation, ordered by decreasing
+/// elevation and then increasing azimuth. The first is the zenith.
+pub fn sweet_spots() -> Vec<SweetSpot> {
+    let max_steps = (MWA_MAX_DELAY / 3) as i32;
+    let min_el = MIN_SWEET_SPOT_ELEVATION_DEG.to_radians();
+    let mut sweet_spots: Vec<(i32, SweetSpot)> = (-max_steps..=max_steps)
+        .flat_map(|e| (-max_steps..=max_steps).map(move |n| (e, n)))
+        .filter_map(|(e, n)| Some((e * e + n * n, SweetSpot::from_gradient(e, n)?)))
+        .filter(|(_, s)| s.azel.el >= min_el)
+        .collect();
+    // Sort on the integer gradients so that equal elevations compare equal.
+    sweet_spots
+        .sort_by(|(a_len, a), (b_len, b)| a_len.cmp(b_len).then(a.azel.az.total_cmp(&b.azel.az)));
+    sweet_spots.into_iter().map(|(_, s)| s).collect()
+}
+
+/// Get the sweet spot with the given dipole delays, if there is one.
+pub fn sweet_spot_from_delays(delays: &[u32; MWA_NUM_DIPOLES]) -> Option<SweetSpot> {
+    sweet_spots().into_iter().find(|s| &s.delays == delays)
+}
+
+/// Get the nominal pointing centre of MWA dipole delays. The delays are fitted
+/// with a plane, so quantised delays (e.g. from [`AzEl::to_mwa_delays`]) give
+/// a pointing close to the one they were made for. Dead dipoles (with a delay
+/// of [`MWA_DEAD_DIPOLE_DELAY`]) are ignored.
+///
+/// Returns `None` if there are fewer than 3 live dipoles to fit. Delays that
+/// are too steep for any real pointing give a pointing on the horizon.
+pub fn delays_to_azel(delays: &[u32; MWA_NUM_DIPOLES]) -> Option<AzEl> {
+    // Least-squares fit of `delay = a col + b row + c`, via the normal
+    // equations with centred coordinates.
+    let live: Vec<(f64, f64, f64)> = delays
+        .iter()
+        .enumerate()
+        .filter(|(_, &d)| d != MWA_DEAD_DIPOLE_DELAY)
+        .map(|(i, &d)| {
+            let (row, col) = dipole_row_col(i);
+            (row, col, f64::from(d))
+        })
+        .collect();
+    if live.len() < 3 {
+        return None;
+    }
+    let n = live.len() as f64;
+    let mean = |f: fn(&(f64, f64, f64)) -> f64| live.iter().map(f).sum::<f64>() / n;
+    let (row0, col0, d0) = (mean(|x| x.0), mean(|x| x.1), mean(|x| x.2));
+    let (mut s_cc, mut s_rr, mut s_cr, mut s_cd, mut s_rd) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for &(row, col, d) in &live {
+        let (row, col, d) = (row - row0, col - col0, d - d0);
+        s_cc += col * col;
+        s_rr += row * row;
+        s_cr += col * row;
+        s_cd += col * d;
+        s_rd += row * d;
+    }
+    let det = s_cc * s_rr - s_cr * s_cr;
+    if det.abs() < 1e-9 {
+        return None;
+    }
+    let a = (s_cd * s_rr - s_rd * s_cr) / det;
+    let b = (s_rd * s_cc - s_cd * s_cr) / det;
+
+    // Rows increase towards the south.
+    let l = a * direction_cosine_per_step();
+    let m = -b * direction_cosine_per_step();
+    let cos_el = l.hypot(m).min(1.0);
+    Some(AzEl::from_radians(
+        l.atan2(m).rem_euclid(TAU),
+        cos_el.acos(),
+    ))
+}
+
+/// Get the nominal pointing centre of MWA dipole delays as an [`HADec`], for a
+/// tile at `latitude_rad`. See [`delays_to_azel`].
+pub fn delays_to_hadec(delays: &[u32; MWA_NUM_DIPOLES], latitude_rad: f64) -> Option<HADec> {
+    delays_to_azel(delays).map(|azel| azel.to_hadec(latitude_rad))
+}
+
+impl AzEl {
+    /// Get the MWA dipole delays that point closest to this pointing; the
+    /// ideal delays are rounded to whole steps, and the smallest delay is 0.
+    ///
+    /// Returns `None` if the pointing is below the horizon, or too low for
+    /// the delays to be achieved (i.e. a delay would be more than
+    /// [`MWA_MAX_DELAY`]).
+    pub fn to_mwa_delays(self) -> Option<[u32; MWA_NUM_DIPOLES]> {
+        if self.el < 0.0 {
+            return None;
+        }
+        let (s_az, c_az) = self.az.sin_cos();
+        let cos_el = self.el.cos();
+        // The delay steps between adjacent dipoles towards the east and north.
+        let east = s_az * cos_el / direction_cosine_per_step();
+        let north = c_az * cos_el / direction_cosine_per_step();
+        let ideal: [f64; MWA_NUM_DIPOLES] = std::array::from_fn(|i| {
+            let (row, col) = dipole_row_col(i);
+            east * col - north * row
+        });
+        let min = ideal.iter().copied().fold(f64::INFINITY, f64::min);
+        let delays = ideal.map(|d| (d - min).round() as u32);
+        if delays.iter().any(|&d| d > MWA_MAX_DELAY) {
+            return None;
+        }
+        Some(delays)
+    }
+
+    /// Get the MWA sweet spot (see [`sweet_spots`]) closest to this pointing.
+    pub fn nearest_sweet_spot(self) -> SweetSpot {
+        sweet_spots()
+            .into_iter()
+            .min_by(|a, b| self.separation(a.azel).total_cmp(&self.separation(b.azel)))
+            .expect("there are always sweet spots")
+    }
+}
+
+impl HADec {
+    /// Get the MWA dipole delays that point closest to this pointing, for a
+    /// tile at `latitude_rad`. See [`AzEl::to_mwa_delays`].
+    pub fn to_mwa_delays(self, latitude_rad: f64) -> Option<[u32; MWA_NUM_DIPOLES]> {
+        self.to_azel(latitude_rad).to_mwa_delays()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MWA_LAT_RAD;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_sweet_spots() {
+        let sweet_spots = sweet_spots();
+        assert_eq!(sweet_spots.len(), 197);
+        assert_eq!(sweet_spots[0].delays, [0; 16]);
+        assert_abs_diff_eq!(sweet_spots[0].azel.el, 90_f64.to_radians());
+        for pair in sweet_spots.windows(2) {
+            assert!(pair[0].azel.el >= pair[1].azel.el - 1e-12);
+        }
+
+        // One step between adjacent dipoles towards the east.
+        let east = sweet_spots
+            .iter()
+            .find(|s| s.delays[..4] == [0, 1, 2, 3])
+            .unwrap();
+        assert_abs_diff_eq!(east.azel.az, 90_f64.to_radians(), epsilon = 1e-12);
+        assert_abs_diff_eq!(east.azel.el.to_degrees(), 83.19, epsilon = 0.01);
+        assert_eq!(east.delays[4..8], [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_nearest_sweet_spot() {
+        let sweet_spots = sweet_spots();
+        for s in &sweet_spots {
+            assert_eq!(s.azel.nearest_sweet_spot(), *s);
+            assert_eq!(sweet_spot_from_delays(&s.delays), Some(*s));
+        }
+        assert_eq!(
+            AzEl::from_degrees(123.0, 89.0).nearest_sweet_spot(),
+            sweet_spots[0]
+        );
+
+        let mut delays = sweet_spots[1].delays;
+        delays[0] += 1;
+        assert_eq!(sweet_spot_from_delays(&delays), None);
+    }
+
+    #[test]
+    fn test_delays_to_pointing() {
+        // Sweet spots are exact.
+        for s in sweet_spots() {
+            assert_eq!(s.azel.to_mwa_delays(), Some(s.delays));
+            let azel = delays_to_azel(&s.delays).unwrap();
+            assert_abs_diff_eq!(azel.separation(s.azel), 0.0, epsilon = 1e-7);
+        }
+
+        // Other pointings are close.
+        let azel = AzEl::from_degrees(200.0, 60.0);
+        let delays = azel.to_mwa_delays().unwrap();
+        let fitted = delays_to_azel(&delays).unwrap();
+        assert!(fitted.separation(azel).to_degrees() < 1.0);
+
+        // Dead dipoles are ignored.
+        let mut dead = delays;
+        dead[5] = MWA_DEAD_DIPOLE_DELAY;
+        dead[10] = MWA_DEAD_DIPOLE_DELAY;
+        assert!(delays_to_azel(&dead).unwrap().separation(azel).to_degrees() < 1.0);
+        assert_eq!(delays_to_azel(&[MWA_DEAD_DIPOLE_DELAY; 16]), None);
+
+        // Low pointings can't be achieved.
+        assert_eq!(AzEl::from_degrees(45.0, 5.0).to_mwa_delays(), None);
+        assert_eq!(AzEl::from_degrees(45.0, -5.0).to_mwa_delays(), None);
+
+        let hadec = HADec::from_degrees(10.0, -40.0);
+        let delays = hadec.to_mwa_delays(MWA_LAT_RAD).unwrap();
+        let fitted = delays_to_hadec(&delays, MWA_LAT_RAD).unwrap();
+        assert!(fitted.separation(hadec).to_degrees() < 1.0);
+    }
+}
@@ -7,13 +7,17 @@
 use std::fmt::Display;
 
 use erfa::Ellipsoid;
+use ndarray::prelude::*;
 
 use crate::{
     constants::{MWA_HEIGHT_M, MWA_LAT_RAD, MWA_LONG_RAD},
     XyzGeocentric,
 };
 
+use super::map_array;
+
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// An earth position: Latitude, Longitude and Height [radians, meters]
 pub struct LatLngHeight {
     /// Longitude \[radians\]
@@ -62,6 +66,32 @@ impl LatLngHeight {
     pub fn to_geocentric_wgs84(self) -> XyzGeocentric {
         self.to_geocentric(Ellipsoid::WGS84)
     }
+
+    /// Get a [`LatLngHeight`] from geocentric (e.g. ITRF) coordinates with the
+    /// specified [`Ellipsoid`]. See [`XyzGeocentric::to_earth`].
+    pub fn from_geocentric(xyz: XyzGeocentric, ellipsoid: Ellipsoid) -> LatLngHeight {
+        xyz.to_earth(ellipsoid)
+    }
+}
+
+/// Convert many [`LatLngHeight`]s (e.g. the antennas of a telescope
+/// configuration) to geocentric coordinates with the specified [`Ellipsoid`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn geodetics_to_geocentrics(
+    positions: ArrayView1<LatLngHeight>,
+    ellipsoid: Ellipsoid,
+) -> Array1<XyzGeocentric> {
+    map_array(positions, |position| position.to_geocentric(ellipsoid))
+}
+
+/// Convert many geocentric (e.g. ITRF) coordinates to [`LatLngHeight`]s with
+/// the specified [`Ellipsoid`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn geocentrics_to_geodetics(
+    xyzs: ArrayView1<XyzGeocentric>,
+    ellipsoid: Ellipsoid,
+) -> Array1<LatLngHeight> {
+    map_array(xyzs, |xyz| xyz.to_earth(ellipsoid))
 }
 
 impl Display for LatLngHeight {
@@ -148,6 +178,33 @@ mod tests {
         assert!(!result.is_empty());
     }
 
+    #[test]
+    fn test_geocentric_arrays() {
+        let positions = Array1::from_shape_fn(5, |i| LatLngHeight {
+            longitude_rad: (i as f64 * 50.0).to_radians(),
+            latitude_rad: (-60.0 + i as f64 * 30.0).to_radians(),
+            height_metres: i as f64 * 100.0,
+        });
+        for ellipsoid in [Ellipsoid::WGS84, Ellipsoid::GRS80] {
+            let xyzs = geodetics_to_geocentrics(positions.view(), ellipsoid);
+            assert_abs_diff_eq!(
+                LatLngHeight::from_geocentric(xyzs[1], ellipsoid),
+                positions[1],
+                epsilon = 1e-8
+            );
+            let round_trip = geocentrics_to_geodetics(xyzs.view(), ellipsoid);
+            for (position, result) in positions.iter().zip(round_trip.iter()) {
+                assert_abs_diff_eq!(*position, *result, epsilon = 1e-8);
+            }
+        }
+
+        // The ellipsoids differ by less than a millimetre, but they do differ.
+        let wgs84 = positions[0].to_geocentric(Ellipsoid::WGS84);
+        let grs80 = LatLngHeight::from_geocentric(wgs84, Ellipsoid::GRS80);
+        let height_diff = (grs80.height_metres - positions[0].height_metres).abs();
+        assert!(height_diff > 0.0 && height_diff < 1e-3, "{height_diff}");
+    }
+
     #[test]
     fn test_abs_diff_eq() {
         let latlngheight = LatLngHeight {
@@ -158,4 +215,13 @@ mod tests {
 
         assert_abs_diff_eq!(latlngheight, LatLngHeight::mwa(), epsilon = 1e-7);
     }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let mwa = LatLngHeight::mwa();
+        let json = serde_json::to_string(&mwa).unwrap();
+        let mwa2: LatLngHeight = serde_json::from_str(&json).unwrap();
+        assert_eq!(mwa, mwa2);
+    }
 }
@@ -0,0 +1,188 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Handle ecliptic (longitude, latitude) coordinates, and the position of the
+//! Sun.
+
+use std::f64::consts::TAU;
+
+use erfa::transform::{cartesian_to_spherical, spherical_to_cartesian};
+use hifitime::Epoch;
+
+use super::radec::RADec;
+
+/// The mean obliquity of the ecliptic at J2000 (IAU 2006) \[radians\].
+const OBLIQUITY_J2000_RAD: f64 = 84381.406 / 3600.0 * std::f64::consts::PI / 180.0;
+
+/// The MJD of the J2000 epoch.
+pub(crate) const MJD_J2000: f64 = 51544.5;
+
+/// A struct containing an ecliptic longitude and latitude, relative to the
+/// mean ecliptic and equinox of J2000. All units are in radians.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Ecliptic {
+    /// Ecliptic longitude \[radians\]
+    pub lon: f64,
+    /// Ecliptic latitude \[radians\]
+    pub lat: f64,
+}
+
+impl Ecliptic {
+    /// Make a new [`Ecliptic`] struct from values in radians.
+    pub fn from_radians(lon: f64, lat: f64) -> Ecliptic {
+        Self { lon, lat }
+    }
+
+    /// Make a new [`Ecliptic`] struct from values in degrees.
+    pub fn from_degrees(lon: f64, lat: f64) -> Ecliptic {
+        Self {
+            lon: lon.to_radians(),
+            lat: lat.to_radians(),
+        }
+    }
+
+    /// Convert J2000 equatorial coordinates to ecliptic coordinates. The
+    /// longitude is in the range `[0, 2π)`.
+    pub fn from_radec(radec: RADec) -> Ecliptic {
+        let [x, y, z] = spherical_to_cartesian(radec.ra, radec.dec);
+        let (s_eps, c_eps) = OBLIQUITY_J2000_RAD.sin_cos();
+        let (lon, lat) = cartesian_to_spherical([x, c_eps * y + s_eps * z, -s_eps * y + c_eps * z]);
+        Self::from_radians(lon.rem_euclid(TAU), lat)
+    }
+
+    /// Convert the ecliptic coordinates to J2000 equatorial coordinates. The
+    /// right ascension is in the range `[0, 2π)`.
+    pub fn to_radec(self) -> RADec {
+        let [x, y, z] = spherical_to_cartesian(self.lon, self.lat);
+        let (s_eps, c_eps) = OBLIQUITY_J2000_RAD.sin_cos();
+        let (ra, dec) = cartesian_to_spherical([x, c_eps * y - s_eps * z, s_eps * y + c_eps * z]);
+        RADec::from_radians(ra.rem_euclid(TAU), dec)
+    }
+}
+
+impl From<RADec> for Ecliptic {
+    fn from(radec: RADec) -> Self {
+        Self::from_radec(radec)
+    }
+}
+
+impl From<Ecliptic> for RADec {
+    fn from(ecliptic: Ecliptic) -> Self {
+        ecliptic.to_radec()
+    }
+}
+
+/// The J2000 position of the Sun at `epoch`.
+///
+/// This uses the low-precision solar coordinates of the Astronomical Almanac,
+/// which are good to about 0.01° between 1950 and 2050; plenty for avoiding
+/// the Sun, but not for pointing at it.
+pub fn sun_radec(epoch: Epoch) -> RADec {
+    let days = epoch.to_mjd_utc_days() - MJD_J2000;
+    let mean_lon_deg = 280.460 + 0.985_647_4 * days;
+    let mean_anomaly = (357.528 + 0.985_600_3 * days).to_radians();
+    let lon_of_date_deg =
+        mean_lon_deg + 1.915 * mean_anomaly.sin() + 0.020 * (2.0 * mean_anomaly).sin();
+    // Undo the general precession in longitude (50.29" per year) to get back
+    // to the equinox of J2000.
+    let lon_deg = lon_of_date_deg - 50.29 / 3600.0 * days / 365.25;
+    Ecliptic::from_degrees(lon_deg, 0.0).to_radec()
+}
+
+/// For each of `epochs`, whether the Sun is within `radius_rad` \[radians\] of
+/// `pointing`, e.g. to reject sections of an observation with the Sun in the
+/// primary beam.
+pub fn sun_within_radius(pointing: RADec, epochs: &[Epoch], radius_rad: f64) -> Vec<bool> {
+    epochs
+        .iter()
+        .map(|&epoch| pointing.separation_from_sun(epoch) < radius_rad)
+        .collect()
+}
+
+impl std::fmt::Display for Ecliptic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "(λ {:.4}°, β {:.4}°)",
+            self.lon.to_degrees(),
+            self.lat.to_degrees()
+        )
+    }
+}
+
+#[cfg(any(test, feature = "approx"))]
+impl approx::AbsDiffEq for Ecliptic {
+    type Epsilon = f64;
+
+    fn default_epsilon() -> f64 {
+        f64::EPSILON
+    }
+
+    fn abs_diff_eq(&self, other: &Self, epsilon: f64) -> bool {
+        f64::abs_diff_eq(&self.lon, &other.lon, epsilon)
+            && f64::abs_diff_eq(&self.lat, &other.lat, epsilon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_ecliptic_to_radec() {
+        let radec = Ecliptic::from_degrees(0.0, 0.0).to_radec();
+        assert_abs_diff_eq!(radec, RADec::from_degrees(0.0, 0.0), epsilon = 1e-12);
+
+        let radec = Ecliptic::from_degrees(90.0, 0.0).to_radec();
+        assert_abs_diff_eq!(radec.ra.to_degrees(), 90.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(radec.dec.to_degrees(), 23.4392794, epsilon = 1e-7);
+
+        // the north ecliptic pole.
+        let radec = Ecliptic::from_degrees(0.0, 90.0).to_radec();
+        assert_abs_diff_eq!(radec.ra.to_degrees(), 270.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(radec.dec.to_degrees(), 90.0 - 23.4392794, epsilon = 1e-7);
+    }
+
+    #[test]
+    fn test_ecliptic_round_trip() {
+        for radec in [
+            RADec::from_degrees(10.0, -27.0),
+            RADec::from_degrees(200.0, 45.0),
+        ] {
+            let ecliptic = Ecliptic::from_radec(radec);
+            assert_abs_diff_eq!(ecliptic.to_radec(), radec, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_sun_radec() {
+        // the March equinox of 2020.
+        let epoch = Epoch::from_gregorian_utc(2020, 3, 20, 3, 50, 0, 0);
+        // the Sun is on the equinox of date, which has precessed from J2000.
+        let sun = Ecliptic::from_radec(sun_radec(epoch));
+        assert_abs_diff_eq!(sun.lon.to_degrees(), 360.0 - 0.28, epsilon = 0.05);
+        assert_abs_diff_eq!(sun.lat, 0.0, epsilon = 1e-12);
+
+        // the June solstice of 2020.
+        let epoch = Epoch::from_gregorian_utc(2020, 6, 20, 21, 44, 0, 0);
+        let sun = sun_radec(epoch);
+        assert_abs_diff_eq!(sun.dec.to_degrees(), 23.44, epsilon = 0.05);
+
+        let pointing = RADec::from_degrees(90.0, -27.0);
+        assert_abs_diff_eq!(
+            pointing.separation_from_sun(epoch).to_degrees(),
+            50.44,
+            epsilon = 0.1
+        );
+        assert_eq!(
+            sun_within_radius(pointing, &[epoch], 60_f64.to_radians()),
+            vec![true]
+        );
+        assert_eq!(
+            sun_within_radius(pointing, &[epoch], 40_f64.to_radians()),
+            vec![false]
+        );
+    }
+}
@@ -5,10 +5,11 @@
 //! Handle East, North and Height coordinates (typically associated with MWA
 //! tiles).
 
-use crate::{constants::MWA_LAT_RAD, XyzGeodetic};
+use crate::{constants::MWA_LAT_RAD, LatLngHeight, XyzGeodetic};
 
 /// East, North and Height coordinates.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::upper_case_acronyms)]
 pub struct ENH {
     /// East \[metres\]
@@ -53,6 +54,12 @@ impl ENH {
     pub fn to_xyz_mwa(self) -> XyzGeodetic {
         self.to_xyz(MWA_LAT_RAD)
     }
+
+    /// Convert [`ENH`] coordinates to [`XyzGeodetic`] for an array centred at
+    /// `site`.
+    pub fn to_xyz_for_site(self, site: LatLngHeight) -> XyzGeodetic {
+        self.to_xyz(site.latitude_rad)
+    }
 }
 
 #[cfg(any(test, feature = "approx"))]
@@ -118,4 +125,24 @@ mod tests {
             epsilon = 1e-10
         );
     }
+
+    #[test]
+    fn test_for_site() {
+        let enh = ENH {
+            n: -101.530,
+            e: -585.675,
+            h: 375.212,
+        };
+        assert_abs_diff_eq!(enh.to_xyz_for_site(LatLngHeight::mwa()), enh.to_xyz_mwa());
+
+        // e.g. a LOFAR station in the northern hemisphere.
+        let site = LatLngHeight {
+            longitude_rad: 6.869837_f64.to_radians(),
+            latitude_rad: 52.915122_f64.to_radians(),
+            height_metres: 0.0,
+        };
+        let xyz = enh.to_xyz_for_site(site);
+        assert_abs_diff_eq!(xyz.to_enh_for_site(site), enh, epsilon = 1e-10);
+        assert!((xyz.x - enh.to_xyz_mwa().x).abs() > 1.0);
+    }
 }
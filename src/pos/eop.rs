@@ -0,0 +1,266 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Earth orientation parameters (DUT1 and polar motion), and a reader for the
+//! IERS "finals" files (e.g. `finals2000A.all`) that publish them.
+//!
+//! The file format is described here:
+//! <https://datacenter.iers.org/versionMetadata.php?filename=latestVersionMeta/10_FINALS.DATA_IAU2000_V2013_0110.txt>
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use hifitime::{Duration, Epoch};
+use thiserror::Error;
+
+/// Arcseconds to radians.
+const ARCSEC_TO_RAD: f64 = std::f64::consts::PI / (180.0 * 3600.0);
+
+#[derive(Error, Debug)]
+pub enum EopError {
+    #[error("line {line} of the IERS finals file has an invalid {field}: '{value}'")]
+    Parse {
+        line: usize,
+        field: &'static str,
+        value: String,
+    },
+
+    #[error("the IERS finals file doesn't contain any Earth orientation parameters")]
+    Empty,
+
+    #[error("MJD {mjd} is outside the range of the IERS finals file ({first} to {last})")]
+    OutOfRange { mjd: f64, first: f64, last: f64 },
+
+    /// An IO error.
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+}
+
+/// The orientation of the Earth at some time, beyond what is predicted by the
+/// IAU models. All values default to zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EarthOrientation {
+    /// UT1 - UTC
+    pub dut1: Duration,
+
+    /// The (x, y) coordinates of the celestial intermediate pole with respect
+    /// to the terrestrial reference frame \[radians\]
+    pub polar_motion: (f64, f64),
+}
+
+impl EarthOrientation {
+    /// Get the longitude and latitude \[radians\] of a site with respect to the
+    /// celestial pole, i.e. after correcting for polar motion.
+    pub fn apply_polar_motion(&self, longitude_rad: f64, latitude_rad: f64) -> (f64, f64) {
+        let (xp, yp) = self.polar_motion;
+        let (s_long, c_long) = longitude_rad.sin_cos();
+        (
+            longitude_rad + (xp * s_long + yp * c_long) * latitude_rad.tan(),
+            latitude_rad + xp * c_long - yp * s_long,
+        )
+    }
+}
+
+impl Default for EarthOrientation {
+    fn default() -> Self {
+        Self {
+            dut1: Duration::from_seconds(0.0),
+            polar_motion: (0.0, 0.0),
+        }
+    }
+}
+
+/// Daily Earth orientation parameters read from an IERS finals file.
+#[derive(Clone, Debug)]
+pub struct EopTable {
+    /// The MJD (UTC) of each row.
+    mjds: Vec<f64>,
+    /// UT1 - UTC \[seconds\]
+    dut1s: Vec<f64>,
+    /// Polar motion (x, y) \[arcseconds\]
+    polar_motions: Vec<(f64, f64)>,
+}
+
+impl EopTable {
+    /// Read the IERS Bulletin A values of a finals file. Reading stops at the
+    /// first row without DUT1 or polar motion values, i.e. the end of the
+    /// predictions.
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`EopError`] if a value can't be parsed or there are no
+    /// values at all.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, EopError> {
+        let mut table = Self {
+            mjds: vec![],
+            dut1s: vec![],
+            polar_motions: vec![],
+        };
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let field = |name: &'static str, columns: std::ops::Range<usize>| {
+                let value = line.get(columns).map(str::trim).unwrap_or_default();
+                if value.is_empty() {
+                    return Ok(None);
+                }
+                value.parse::<f64>().map(Some).map_err(|_| EopError::Parse {
+                    line: i + 1,
+                    field: name,
+                    value: value.to_string(),
+                })
+            };
+            let (Some(mjd), Some(pm_x), Some(pm_y), Some(dut1)) = (
+                field("MJD", 7..15)?,
+                field("PM-x", 18..27)?,
+                field("PM-y", 37..46)?,
+                field("UT1-UTC", 58..68)?,
+            ) else {
+                break;
+            };
+            table.mjds.push(mjd);
+            table.dut1s.push(dut1);
+            table.polar_motions.push((pm_x, pm_y));
+        }
+        if table.mjds.is_empty() {
+            return Err(EopError::Empty);
+        }
+        Ok(table)
+    }
+
+    /// Read the IERS Bulletin A values of the finals file at `path`. See
+    /// [`EopTable::from_reader`].
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`EopError`] if the file can't be read or parsed.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, EopError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Get the Earth orientation parameters at `epoch`, linearly interpolated
+    /// between the daily values. Interpolation accounts for leap seconds,
+    /// which make DUT1 jump by one second at the end of a UTC day.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`EopError::OutOfRange`] if `epoch` isn't covered by the
+    /// table.
+    pub fn get(&self, epoch: Epoch) -> Result<EarthOrientation, EopError> {
+        let mjd = epoch.to_mjd_utc_days();
+        let first = self.mjds[0];
+        let last = self.mjds[self.mjds.len() - 1];
+        if !(first..=last).contains(&mjd) {
+            return Err(EopError::OutOfRange { mjd, first, last });
+        }
+
+        let j = self
+            .mjds
+            .partition_point(|&m| m <= mjd)
+            .min(self.mjds.len() - 1);
+        let i = j.saturating_sub(1);
+        let frac = if j == i {
+            0.0
+        } else {
+            (mjd - self.mjds[i]) / (self.mjds[j] - self.mjds[i])
+        };
+        let lerp = |a: f64, b: f64| a + (b - a) * frac;
+
+        // A leap second happened at the end of row i's day; DUT1 is continuous
+        // until then.
+        let mut next_dut1 = self.dut1s[j];
+        if next_dut1 - self.dut1s[i] < -0.5 {
+            next_dut1 += 1.0;
+        } else if next_dut1 - self.dut1s[i] > 0.5 {
+            next_dut1 -= 1.0;
+        }
+        let (x1, y1) = self.polar_motions[i];
+        let (x2, y2) = self.polar_motions[j];
+        Ok(EarthOrientation {
+            dut1: Duration::from_seconds(lerp(self.dut1s[i], next_dut1)),
+            polar_motion: (lerp(x1, x2) * ARCSEC_TO_RAD, lerp(y1, y2) * ARCSEC_TO_RAD),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    // Rows of finals2000A.all around the leap second at the end of 2016, with
+    // the trailing Bulletin B columns removed.
+    const FINALS: &str = "\
+161230 57752.00 I  0.117896 0.000091  0.289055 0.000091  I 0.4028890 0.0000088
+161231 57753.00 I  0.116352 0.000091  0.288938 0.000091  I 0.4023005 0.0000088
+17 1 1 57754.00 I  0.114807 0.000091  0.288896 0.000091  I-0.5981360 0.0000088
+17 1 2 57755.00 I  0.113480 0.000091  0.288949 0.000091  I-0.5985900 0.0000088
+17 1 3 57756.00
+";
+
+    #[test]
+    fn test_eop_table() {
+        let table = EopTable::from_reader(FINALS.as_bytes()).unwrap();
+        assert_eq!(table.mjds.len(), 4);
+
+        let eop = table.get(Epoch::from_mjd_utc(57752.0)).unwrap();
+        assert_abs_diff_eq!(eop.dut1.to_seconds(), 0.4028890, epsilon = 1e-6);
+        assert_abs_diff_eq!(
+            eop.polar_motion.0,
+            0.117896 * ARCSEC_TO_RAD,
+            epsilon = 1e-12
+        );
+
+        // Midway through the last day before the leap second.
+        let eop = table.get(Epoch::from_mjd_utc(57753.5)).unwrap();
+        assert_abs_diff_eq!(
+            eop.dut1.to_seconds(),
+            (0.4023005 + 0.4018640) / 2.0,
+            epsilon = 1e-6
+        );
+        assert_abs_diff_eq!(
+            eop.polar_motion.1,
+            (0.288938 + 0.288896) / 2.0 * ARCSEC_TO_RAD,
+            epsilon = 1e-12
+        );
+
+        let eop = table.get(Epoch::from_mjd_utc(57755.0)).unwrap();
+        assert_abs_diff_eq!(eop.dut1.to_seconds(), -0.5985900, epsilon = 1e-6);
+
+        assert!(matches!(
+            table.get(Epoch::from_mjd_utc(57755.5)),
+            Err(EopError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            EopTable::from_reader("".as_bytes()),
+            Err(EopError::Empty)
+        ));
+        assert!(matches!(
+            EopTable::from_reader(FINALS.replace("0.4028890", "0.40x8890").as_bytes()),
+            Err(EopError::Parse {
+                line: 1,
+                field: "UT1-UTC",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_apply_polar_motion() {
+        let (longitude, latitude) = EarthOrientation::default().apply_polar_motion(1.0, -0.5);
+        assert_abs_diff_eq!(longitude, 1.0);
+        assert_abs_diff_eq!(latitude, -0.5);
+
+        // At a longitude of 0, x moves the pole towards the site.
+        let eop = EarthOrientation {
+            polar_motion: (1e-6, 0.0),
+            ..EarthOrientation::default()
+        };
+        let (longitude, latitude) = eop.apply_polar_motion(0.0, -0.5);
+        assert_abs_diff_eq!(longitude, 0.0);
+        assert_abs_diff_eq!(latitude, -0.5 + 1e-6, epsilon = 1e-15);
+    }
+}
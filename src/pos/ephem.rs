@@ -0,0 +1,282 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Approximate positions of the Sun, Moon and bright planets.
+//!
+//! The positions are from analytic theories and are good to about an
+//! arcminute between 1800 and 2050; enough to avoid (or flag) solar and lunar
+//! contamination, but not for pointing at these bodies. All positions are
+//! relative to the mean equator and equinox of J2000, and neither light-time
+//! nor aberration is accounted for.
+
+use std::f64::consts::TAU;
+
+use erfa::transform::{cartesian_to_spherical, spherical_to_cartesian};
+use hifitime::{Duration, Epoch};
+
+use super::{
+    earth::LatLngHeight,
+    ecliptic::{Ecliptic, MJD_J2000},
+    precession::get_lmst,
+    radec::RADec,
+};
+
+/// The astronomical unit \[metres\].
+const AU_M: f64 = 149_597_870_700.0;
+
+/// A solar system body with an approximate position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Body {
+    Sun,
+    Moon,
+    Mercury,
+    Venus,
+    Mars,
+    Jupiter,
+    Saturn,
+}
+
+/// Keplerian elements (relative to the mean ecliptic and equinox of J2000) and
+/// their rates per Julian century, from Table 1 of Standish's "Keplerian
+/// Elements for Approximate Positions of the Major Planets" (JPL), valid
+/// between 1800 and 2050. The elements are the semi-major axis \[au\],
+/// eccentricity, inclination, mean longitude, longitude of perihelion and
+/// longitude of the ascending node \[degrees\].
+#[rustfmt::skip]
+const EARTH_MOON_BARYCENTRE: [[f64; 6]; 2] = [
+    [1.00000261, 0.01671123, -0.00001531, 100.46457166, 102.93768193, 0.0],
+    [0.00000562, -0.00004392, -0.01294668, 35999.37244981, 0.32327364, 0.0],
+];
+#[rustfmt::skip]
+const MERCURY: [[f64; 6]; 2] = [
+    [0.38709927, 0.20563593, 7.00497902, 252.2503235, 77.45779628, 48.33076593],
+    [0.00000037, 0.00001906, -0.00594749, 149472.67411175, 0.16047689, -0.12534081],
+];
+#[rustfmt::skip]
+const VENUS: [[f64; 6]; 2] = [
+    [0.72333566, 0.00677672, 3.39467605, 181.9790995, 131.60246718, 76.67984255],
+    [0.0000039, -0.00004107, -0.0007889, 58517.81538729, 0.00268329, -0.27769418],
+];
+#[rustfmt::skip]
+const MARS: [[f64; 6]; 2] = [
+    [1.52371034, 0.0933941, 1.84969142, -4.55343205, -23.94362959, 49.55953891],
+    [0.00001847, 0.00007882, -0.00813131, 19140.30268499, 0.44441088, -0.29257343],
+];
+#[rustfmt::skip]
+const JUPITER: [[f64; 6]; 2] = [
+    [5.202887, 0.04838624, 1.30439695, 34.39644051, 14.72847983, 100.47390909],
+    [-0.00011607, -0.00013253, -0.00183714, 3034.74612775, 0.21252668, 0.20469106],
+];
+#[rustfmt::skip]
+const SATURN: [[f64; 6]; 2] = [
+    [9.53667594, 0.05386179, 2.48599187, 49.95424423, 92.59887831, 113.66242448],
+    [-0.0012506, -0.00050991, 0.00193609, 1222.49362201, -0.41897216, -0.28867794],
+];
+
+impl Body {
+    /// The position of this body as seen from the centre of the Earth at
+    /// `epoch`.
+    pub fn geocentric_radec(self, epoch: Epoch) -> RADec {
+        let (ra, dec) = cartesian_to_spherical(self.geocentric_xyz(epoch));
+        RADec::from_radians(ra.rem_euclid(TAU), dec)
+    }
+
+    /// The position of this body as seen from `site` at `epoch`. This differs
+    /// from [`Body::geocentric_radec`] by up to ~1° for the Moon, and by a
+    /// few arcseconds at most for everything else. `dut1` is UT1 - UTC; zero
+    /// can be used if it isn't known.
+    pub fn topocentric_radec(self, epoch: Epoch, site: LatLngHeight, dut1: Duration) -> RADec {
+        // Rotate the site into the equatorial frame.
+        let site = site.to_geocentric_wgs84();
+        let (s_gmst, c_gmst) = get_lmst(0.0, epoch, dut1).sin_cos();
+        let site = [
+            (site.x * c_gmst - site.y * s_gmst) / AU_M,
+            (site.x * s_gmst + site.y * c_gmst) / AU_M,
+            site.z / AU_M,
+        ];
+
+        let geocentric = self.geocentric_xyz(epoch);
+        let (ra, dec) = cartesian_to_spherical([
+            geocentric[0] - site[0],
+            geocentric[1] - site[1],
+            geocentric[2] - site[2],
+        ]);
+        RADec::from_radians(ra.rem_euclid(TAU), dec)
+    }
+
+    /// The Keplerian elements of this body, if it's a planet.
+    fn elements(self) -> Option<&'static [[f64; 6]; 2]> {
+        match self {
+            Body::Sun | Body::Moon => None,
+            Body::Mercury => Some(&MERCURY),
+            Body::Venus => Some(&VENUS),
+            Body::Mars => Some(&MARS),
+            Body::Jupiter => Some(&JUPITER),
+            Body::Saturn => Some(&SATURN),
+        }
+    }
+
+    /// The equatorial position of this body relative to the centre of the
+    /// Earth \[au\].
+    fn geocentric_xyz(self, epoch: Epoch) -> [f64; 3] {
+        let centuries = (epoch.to_mjd_tt_days() - MJD_J2000) / 36525.0;
+        let ecliptic_xyz = match self {
+            Body::Moon => {
+                let (ecliptic, distance_m) = moon_ecliptic(centuries);
+                let [x, y, z] = spherical_to_cartesian(ecliptic.lon, ecliptic.lat);
+                let distance = distance_m / AU_M;
+                [x * distance, y * distance, z * distance]
+            }
+            _ => {
+                let earth = heliocentric_xyz(&EARTH_MOON_BARYCENTRE, centuries);
+                let body = match self.elements() {
+                    Some(elements) => heliocentric_xyz(elements, centuries),
+                    None => [0.0; 3],
+                };
+                [body[0] - earth[0], body[1] - earth[1], body[2] - earth[2]]
+            }
+        };
+
+        // Rotate from the ecliptic to the equator, keeping the distance.
+        let distance = ecliptic_xyz.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let (lon, lat) = cartesian_to_spherical(ecliptic_xyz);
+        let radec = Ecliptic::from_radians(lon, lat).to_radec();
+        let [x, y, z] = spherical_to_cartesian(radec.ra, radec.dec);
+        [x * distance, y * distance, z * distance]
+    }
+}
+
+/// The heliocentric ecliptic position of a planet with the given Keplerian
+/// `elements` \[au\], `centuries` after J2000.
+fn heliocentric_xyz(elements: &[[f64; 6]; 2], centuries: f64) -> [f64; 3] {
+    let [a, e, incl, mean_lon, lon_peri, lon_node]: [f64; 6] =
+        std::array::from_fn(|i| elements[0][i] + elements[1][i] * centuries);
+    let arg_peri = (lon_peri - lon_node).to_radians();
+    let mean_anomaly = (mean_lon - lon_peri).to_radians().rem_euclid(TAU);
+    let (incl, lon_node) = (incl.to_radians(), lon_node.to_radians());
+
+    // Solve Kepler's equation for the eccentric anomaly.
+    let mut ecc_anomaly = mean_anomaly + e * mean_anomaly.sin();
+    for _ in 0..10 {
+        ecc_anomaly -=
+            (ecc_anomaly - e * ecc_anomaly.sin() - mean_anomaly) / (1.0 - e * ecc_anomaly.cos());
+    }
+
+    // The position in the orbital plane, then rotated to the ecliptic.
+    let x = a * (ecc_anomaly.cos() - e);
+    let y = a * (1.0 - e * e).sqrt() * ecc_anomaly.sin();
+    let (s_w, c_w) = arg_peri.sin_cos();
+    let (s_o, c_o) = lon_node.sin_cos();
+    let (s_i, c_i) = incl.sin_cos();
+    [
+        (c_w * c_o - s_w * s_o * c_i) * x + (-s_w * c_o - c_w * s_o * c_i) * y,
+        (c_w * s_o + s_w * c_o * c_i) * x + (-s_w * s_o + c_w * c_o * c_i) * y,
+        s_w * s_i * x + c_w * s_i * y,
+    ]
+}
+
+/// The geocentric ecliptic position (J2000) and distance \[metres\] of the
+/// Moon, `centuries` after J2000.
+///
+/// This is the `MiniMoon` series of Montenbruck & Pfleger (Astronomy on the
+/// Personal Computer), which is good to a few arcminutes, with the leading
+/// terms of the distance from the Astronomical Almanac.
+fn moon_ecliptic(centuries: f64) -> (Ecliptic, f64) {
+    const ARCSEC: f64 = 3600.0 * 180.0 / std::f64::consts::PI;
+    let t = centuries;
+    let frac = |x: f64| x - x.floor();
+
+    // Mean elements [revolutions, radians].
+    let mean_lon = frac(0.606433 + 1336.855225 * t);
+    let l = TAU * frac(0.374897 + 1325.55241 * t);
+    let ls = TAU * frac(0.993133 + 99.997361 * t);
+    let d = TAU * frac(0.827361 + 1236.853086 * t);
+    let f = TAU * frac(0.259086 + 1342.227825 * t);
+
+    // Perturbations in longitude [arcseconds].
+    let d_lon = 22640.0 * l.sin() - 4586.0 * (l - 2.0 * d).sin()
+        + 2370.0 * (2.0 * d).sin()
+        + 769.0 * (2.0 * l).sin()
+        - 668.0 * ls.sin()
+        - 412.0 * (2.0 * f).sin()
+        - 212.0 * (2.0 * l - 2.0 * d).sin()
+        - 206.0 * (l + ls - 2.0 * d).sin()
+        + 192.0 * (l + 2.0 * d).sin()
+        - 165.0 * (ls - 2.0 * d).sin()
+        - 125.0 * d.sin()
+        - 110.0 * (l + ls).sin()
+        + 148.0 * (l - ls).sin()
+        - 55.0 * (2.0 * f - 2.0 * d).sin();
+
+    // Latitude.
+    let s = f + (d_lon + 412.0 * (2.0 * f).sin() + 541.0 * ls.sin()) / ARCSEC;
+    let h = f - 2.0 * d;
+    let n = -526.0 * h.sin() + 44.0 * (l + h).sin() - 31.0 * (h - l).sin() - 23.0 * (ls + h).sin()
+        + 11.0 * (h - ls).sin()
+        - 25.0 * (f - 2.0 * l).sin()
+        + 21.0 * (f - l).sin();
+
+    let distance_km = 385_000.56
+        - 20905.36 * l.cos()
+        - 3699.11 * (2.0 * d - l).cos()
+        - 2955.97 * (2.0 * d).cos()
+        - 569.93 * (2.0 * l).cos();
+
+    // The series is for the equinox of date; undo the general precession in
+    // longitude (50.29" per year) to get back to J2000.
+    let lon = TAU * frac(mean_lon + d_lon / 1_296_000.0) - 50.29 * 100.0 * t / ARCSEC;
+    let lat = (18520.0 * s.sin() + n) / ARCSEC;
+    (Ecliptic::from_radians(lon, lat), distance_km * 1e3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pos::ecliptic::sun_radec;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_moon() {
+        // Example 47.a of Meeus' Astronomical Algorithms (1992 April 12, 0h
+        // TD), for the mean equinox of date.
+        let centuries = (2448724.5 - 2451545.0) / 36525.0;
+        let (ecliptic, distance_m) = moon_ecliptic(centuries);
+        let precession = 50.29 * 100.0 * centuries / 3600.0;
+        assert_abs_diff_eq!(
+            ecliptic.lon.to_degrees() + precession,
+            133.158,
+            epsilon = 0.02
+        );
+        assert_abs_diff_eq!(ecliptic.lat.to_degrees(), -3.229, epsilon = 0.02);
+        assert_abs_diff_eq!(distance_m / 1e3, 368409.7, epsilon = 200.0);
+    }
+
+    #[test]
+    fn test_planets_and_sun() {
+        // Example 33.a of Meeus' Astronomical Algorithms (1992 December 20,
+        // 0h TD), precessed back to J2000.
+        let epoch = Epoch::from_gregorian_utc_at_midnight(1992, 12, 20);
+        let venus = Body::Venus.geocentric_radec(epoch);
+        assert_abs_diff_eq!(venus.ra.to_degrees(), 316.272, epsilon = 0.02);
+        assert_abs_diff_eq!(venus.dec.to_degrees(), -18.860, epsilon = 0.02);
+
+        // Consistent with the Almanac's solar coordinates.
+        let sun = Body::Sun.geocentric_radec(epoch);
+        assert!(sun.separation(sun_radec(epoch)).to_degrees() < 0.02);
+
+        // Parallax only really matters for the Moon.
+        let site = LatLngHeight::mwa();
+        let dut1 = Duration::from_seconds(0.0);
+        for body in [Body::Sun, Body::Mars, Body::Saturn] {
+            let geocentric = body.geocentric_radec(epoch);
+            let topocentric = body.topocentric_radec(epoch, site, dut1);
+            assert!(geocentric.separation(topocentric).to_degrees() < 0.01);
+        }
+        let moon = Body::Moon.geocentric_radec(epoch);
+        let separation = moon
+            .separation(Body::Moon.topocentric_radec(epoch, site, dut1))
+            .to_degrees();
+        assert!(separation < 1.03, "{separation}");
+    }
+}
@@ -0,0 +1,134 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Convert between FK5 (J2000) and ICRS coordinates.
+//!
+//! The two frames differ by tens of milliarcseconds, which is often ignored,
+//! but matters when reconciling catalogues (e.g. of calibrators) given in
+//! different frames. The conversions are ports of ERFA's `eraFk5hz` and
+//! `eraHfk5z`, which treat Hipparcos coordinates as ICRS.
+
+use std::f64::consts::TAU;
+
+use erfa::transform::{cartesian_to_spherical, spherical_to_cartesian};
+use hifitime::Epoch;
+
+use super::{ecliptic::MJD_J2000, radec::RADec};
+
+/// Arcseconds to radians.
+const ARCSEC_TO_RAD: f64 = std::f64::consts::PI / (180.0 * 3600.0);
+
+/// The orientation of FK5 with respect to Hipparcos, as a rotation vector
+/// \[arcseconds\].
+const FK5_ORIENTATION_ARCSEC: [f64; 3] = [-19.9e-3, -9.1e-3, 22.9e-3];
+
+/// The spin of FK5 with respect to Hipparcos \[arcseconds per Julian year\].
+const FK5_SPIN_ARCSEC_PER_YEAR: [f64; 3] = [-0.30e-3, 0.60e-3, 0.70e-3];
+
+impl RADec {
+    /// Convert FK5 (J2000) coordinates to ICRS, assuming that the source has
+    /// no proper motion in the ICRS. `epoch` is the epoch of observation; FK5
+    /// slowly rotates with respect to the ICRS, so it affects the result at
+    /// the level of ~1 mas per decade.
+    ///
+    /// This is a port of ERFA's `eraFk5hz`. The right ascension is in the
+    /// range `[0, 2π)`.
+    pub fn fk5_to_icrs(self, epoch: Epoch) -> RADec {
+        let years = (epoch.to_mjd_tt_days() - MJD_J2000) / 365.25;
+        let orientation = rotation_matrix(FK5_ORIENTATION_ARCSEC.map(|a| a * ARCSEC_TO_RAD));
+        let spin = rotation_matrix(FK5_SPIN_ARCSEC_PER_YEAR.map(|s| -s * years * ARCSEC_TO_RAD));
+
+        // Undo the FK5 spin accumulated since J2000, then rotate into the
+        // ICRS.
+        let v = spherical_to_cartesian(self.ra, self.dec);
+        let v = mul_transpose(&spin, v);
+        let (ra, dec) = cartesian_to_spherical(mul(&orientation, v));
+        RADec::from_radians(ra.rem_euclid(TAU), dec)
+    }
+
+    /// Convert ICRS coordinates to FK5 (J2000), assuming that the source has
+    /// no proper motion in the ICRS. `epoch` is the epoch of observation; see
+    /// [`RADec::fk5_to_icrs`].
+    ///
+    /// This is a port of ERFA's `eraHfk5z`. The right ascension is in the
+    /// range `[0, 2π)`.
+    pub fn icrs_to_fk5(self, epoch: Epoch) -> RADec {
+        let years = (epoch.to_mjd_tt_days() - MJD_J2000) / 365.25;
+        let orientation = rotation_matrix(FK5_ORIENTATION_ARCSEC.map(|a| a * ARCSEC_TO_RAD));
+        let spin = rotation_matrix(FK5_SPIN_ARCSEC_PER_YEAR.map(|s| s * years * ARCSEC_TO_RAD));
+
+        // The product of the orientation and the accumulated spin takes FK5 to
+        // the ICRS; apply its transpose.
+        let mut fk5_to_icrs = [[0.0; 3]; 3];
+        for (i, row) in fk5_to_icrs.iter_mut().enumerate() {
+            for (j, element) in row.iter_mut().enumerate() {
+                *element = (0..3).map(|k| orientation[i][k] * spin[k][j]).sum();
+            }
+        }
+        let v = spherical_to_cartesian(self.ra, self.dec);
+        let (ra, dec) = cartesian_to_spherical(mul_transpose(&fk5_to_icrs, v));
+        RADec::from_radians(ra.rem_euclid(TAU), dec)
+    }
+}
+
+/// The rotation matrix of a rotation vector \[radians\], like ERFA's
+/// `eraRv2m`.
+fn rotation_matrix(w: [f64; 3]) -> [[f64; 3]; 3] {
+    let phi = (w[0] * w[0] + w[1] * w[1] + w[2] * w[2]).sqrt();
+    let (s, c) = phi.sin_cos();
+    let f = 1.0 - c;
+    let [x, y, z] = if phi > 0.0 { w.map(|a| a / phi) } else { w };
+    [
+        [x * x * f + c, x * y * f + z * s, x * z * f - y * s],
+        [y * x * f - z * s, y * y * f + c, y * z * f + x * s],
+        [z * x * f + y * s, z * y * f - x * s, z * z * f + c],
+    ]
+}
+
+fn mul(r: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    [
+        r[0][0] * v[0] + r[0][1] * v[1] + r[0][2] * v[2],
+        r[1][0] * v[0] + r[1][1] * v[1] + r[1][2] * v[2],
+        r[2][0] * v[0] + r[2][1] * v[1] + r[2][2] * v[2],
+    ]
+}
+
+fn mul_transpose(r: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    [
+        r[0][0] * v[0] + r[1][0] * v[1] + r[2][0] * v[2],
+        r[0][1] * v[0] + r[1][1] * v[1] + r[2][1] * v[2],
+        r[0][2] * v[0] + r[1][2] * v[1] + r[2][2] * v[2],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_fk5_to_icrs() {
+        // The values of ERFA's t_fk5hz test.
+        let epoch = Epoch::from_mjd_utc(54479.0);
+        let result = RADec::from_radians(1.76779433, -0.2917517103).fk5_to_icrs(epoch);
+        let expected = RADec::from_radians(1.767794191464424, -0.29175160016798846);
+        assert_abs_diff_eq!(result, expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_icrs_to_fk5() {
+        // The values of ERFA's t_hfk5z test.
+        let epoch = Epoch::from_mjd_utc(54479.0);
+        let result = RADec::from_radians(1.767794352, -0.2917512594).icrs_to_fk5(epoch);
+        let expected = RADec::from_radians(1.767794490535581, -0.2917513695320114);
+        assert_abs_diff_eq!(result, expected, epsilon = 1e-12);
+
+        // The frames differ by tens of milliarcseconds.
+        let radec = RADec::from_degrees(60.0, -27.0);
+        let fk5 = radec.icrs_to_fk5(epoch);
+        let separation = radec.separation(fk5) / ARCSEC_TO_RAD;
+        assert!(separation > 0.01 && separation < 0.05, "{separation}");
+        assert_abs_diff_eq!(fk5.fk5_to_icrs(epoch), radec, epsilon = 1e-14);
+    }
+}
@@ -0,0 +1,188 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Handle Galactic (longitude, latitude) coordinates.
+
+use std::f64::consts::TAU;
+
+use erfa::transform::{cartesian_to_spherical, spherical_to_cartesian};
+
+use super::radec::RADec;
+
+/// The rotation matrix from ICRS to Galactic coordinates, as used by ERFA's
+/// `eraIcrs2g` and `eraG2icrs` (from the Hipparcos catalogue's definition of
+/// the Galactic pole and centre).
+#[rustfmt::skip]
+const ICRS_TO_GALACTIC: [[f64; 3]; 3] = [
+    [-0.05487556041621537, -0.873437090234885, -0.4838350155487132],
+    [0.49410942787558365, -0.4448296299600112, 0.7469822444972188],
+    [-0.8676661490190047, -0.19807637343120152, 0.4559837761750669],
+];
+
+/// A struct containing a Galactic longitude and latitude. All units are in
+/// radians.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Galactic {
+    /// Galactic longitude \[radians\]
+    pub l: f64,
+    /// Galactic latitude \[radians\]
+    pub b: f64,
+}
+
+impl Galactic {
+    /// Make a new [`Galactic`] struct from values in radians.
+    pub fn from_radians(l: f64, b: f64) -> Galactic {
+        Self { l, b }
+    }
+
+    /// Make a new [`Galactic`] struct from values in degrees.
+    pub fn from_degrees(l: f64, b: f64) -> Galactic {
+        Self {
+            l: l.to_radians(),
+            b: b.to_radians(),
+        }
+    }
+
+    /// Convert ICRS equatorial coordinates to Galactic coordinates. The
+    /// longitude is in the range `[0, 2π)`.
+    pub fn from_radec(radec: RADec) -> Galactic {
+        let v = spherical_to_cartesian(radec.ra, radec.dec);
+        let r = &ICRS_TO_GALACTIC;
+        let (l, b) = cartesian_to_spherical([
+            r[0][0] * v[0] + r[0][1] * v[1] + r[0][2] * v[2],
+            r[1][0] * v[0] + r[1][1] * v[1] + r[1][2] * v[2],
+            r[2][0] * v[0] + r[2][1] * v[1] + r[2][2] * v[2],
+        ]);
+        Self::from_radians(l.rem_euclid(TAU), b)
+    }
+
+    /// Convert the Galactic coordinates to ICRS equatorial coordinates. The
+    /// right ascension is in the range `[0, 2π)`.
+    pub fn to_radec(self) -> RADec {
+        let v = spherical_to_cartesian(self.l, self.b);
+        // The inverse of a rotation is its transpose.
+        let r = &ICRS_TO_GALACTIC;
+        let (ra, dec) = cartesian_to_spherical([
+            r[0][0] * v[0] + r[1][0] * v[1] + r[2][0] * v[2],
+            r[0][1] * v[0] + r[1][1] * v[1] + r[2][1] * v[2],
+            r[0][2] * v[0] + r[1][2] * v[1] + r[2][2] * v[2],
+        ]);
+        RADec::from_radians(ra.rem_euclid(TAU), dec)
+    }
+}
+
+impl From<RADec> for Galactic {
+    fn from(radec: RADec) -> Self {
+        Self::from_radec(radec)
+    }
+}
+
+impl From<Galactic> for RADec {
+    fn from(galactic: Galactic) -> Self {
+        galactic.to_radec()
+    }
+}
+
+/// Convert many ICRS [`RADec`]s to [`Galactic`] coordinates.
+pub fn radecs_to_galactics(radecs: &[RADec]) -> Vec<Galactic> {
+    radecs.iter().copied().map(Galactic::from_radec).collect()
+}
+
+/// Convert many [`Galactic`] coordinates to ICRS [`RADec`]s.
+pub fn galactics_to_radecs(galactics: &[Galactic]) -> Vec<RADec> {
+    galactics.iter().copied().map(Galactic::to_radec).collect()
+}
+
+impl std::fmt::Display for Galactic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "(l {:.4}°, b {:.4}°)",
+            self.l.to_degrees(),
+            self.b.to_degrees()
+        )
+    }
+}
+
+#[cfg(any(test, feature = "approx"))]
+impl approx::AbsDiffEq for Galactic {
+    type Epsilon = f64;
+
+    fn default_epsilon() -> f64 {
+        f64::EPSILON
+    }
+
+    fn abs_diff_eq(&self, other: &Self, epsilon: f64) -> bool {
+        f64::abs_diff_eq(&self.l, &other.l, epsilon) && f64::abs_diff_eq(&self.b, &other.b, epsilon)
+    }
+}
+
+#[cfg(any(test, feature = "approx"))]
+impl approx::RelativeEq for Galactic {
+    #[inline]
+    fn default_max_relative() -> f64 {
+        f64::EPSILON
+    }
+
+    #[inline]
+    fn relative_eq(&self, other: &Self, epsilon: f64, max_relative: f64) -> bool {
+        f64::relative_eq(&self.l, &other.l, epsilon, max_relative)
+            && f64::relative_eq(&self.b, &other.b, epsilon, max_relative)
+    }
+
+    #[inline]
+    fn relative_ne(
+        &self,
+        other: &Self,
+        epsilon: Self::Epsilon,
+        max_relative: Self::Epsilon,
+    ) -> bool {
+        !Self::relative_eq(self, other, epsilon, max_relative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_icrs_to_galactic() {
+        // The values of ERFA's t_icrs2g test.
+        let radec = RADec::from_radians(5.933807430222719, -1.1784870613579945);
+        let result = Galactic::from_radec(radec);
+        let expected = Galactic::from_radians(5.585053606381854, -0.7853981633974483);
+        assert_abs_diff_eq!(result, expected, epsilon = 1e-14);
+    }
+
+    #[test]
+    fn test_galactic_to_icrs() {
+        // The values of ERFA's t_g2icrs test.
+        let galactic = Galactic::from_radians(5.585053606381854, -0.7853981633974483);
+        let result = galactic.to_radec();
+        let expected = RADec::from_radians(5.933807430222719, -1.1784870613579945);
+        assert_abs_diff_eq!(result, expected, epsilon = 1e-14);
+    }
+
+    #[test]
+    fn test_galactic_centre() {
+        let centre = Galactic::from_degrees(0.0, 0.0).to_radec();
+        assert_abs_diff_eq!(centre.ra.to_degrees(), 266.40498829, epsilon = 1e-5);
+        assert_abs_diff_eq!(centre.dec.to_degrees(), -28.93617776, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_galactic_round_trip() {
+        let radecs = [
+            RADec::from_degrees(0.0, -27.0),
+            RADec::from_degrees(60.0, -30.0),
+            RADec::from_degrees(350.0, 80.0),
+        ];
+        let galactics = radecs_to_galactics(&radecs);
+        assert!(galactics.iter().all(|g| (0.0..TAU).contains(&g.l)));
+        for (radec, result) in radecs.iter().zip(galactics_to_radecs(&galactics)) {
+            assert_abs_diff_eq!(*radec, result, epsilon = 1e-12);
+        }
+    }
+}
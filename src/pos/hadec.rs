@@ -4,17 +4,34 @@
 
 //! Handle (hour angle, declination) coordinates.
 
-use erfa::aliases::{eraHd2ae, eraHd2pa, eraSeps};
+use erfa::{
+    aliases::{eraHd2ae, eraHd2pa},
+    transform::{cartesian_to_spherical, spherical_to_cartesian},
+};
+use ndarray::prelude::*;
 
-use crate::{constants::MWA_LAT_RAD, AzEl, RADec};
+use hifitime::{Duration, Epoch};
+
+#[cfg(feature = "serde")]
+use super::{degrees_to_radians, radians_to_degrees};
+use super::{map_array, position_angle, vincenty_separation, zip_map_arrays};
+use crate::{constants::MWA_LAT_RAD, precession::get_lmst, AzEl, LatLngHeight, RADec};
 
 /// A struct containing an Hour Angle and Declination. All units are in radians.
+///
+/// Note that the serialised units are degrees and are automatically converted
+/// when serialising/deserialising.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::upper_case_acronyms)]
 pub struct HADec {
     /// Hour angle \[radians\]
+    #[cfg_attr(feature = "serde", serde(serialize_with = "radians_to_degrees"))]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "degrees_to_radians"))]
     pub ha: f64,
     /// Declination \[radians\]
+    #[cfg_attr(feature = "serde", serde(serialize_with = "radians_to_degrees"))]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "degrees_to_radians"))]
     pub dec: f64,
 }
 
@@ -75,9 +92,37 @@ impl HADec {
         self.to_azel(MWA_LAT_RAD)
     }
 
+    /// Convert the equatorial coordinates to horizon coordinates (azimuth and
+    /// elevation) for an array at `site`.
+    pub fn to_azel_for_site(self, site: LatLngHeight) -> AzEl {
+        self.to_azel(site.latitude_rad)
+    }
+
+    /// Get the unit vector pointing at these coordinates. x points towards
+    /// (HA 0, Dec 0), y towards (HA 6h, Dec 0), i.e. west, and z towards the
+    /// north celestial pole.
+    pub fn to_unit_vector(self) -> [f64; 3] {
+        spherical_to_cartesian(self.ha, self.dec)
+    }
+
+    /// Get the coordinates that a vector points at. The vector doesn't need to
+    /// be normalised. The hour angle is in the range `(-π, π]`. See
+    /// [`HADec::to_unit_vector`].
+    pub fn from_unit_vector(v: [f64; 3]) -> HADec {
+        let (ha, dec) = cartesian_to_spherical(v);
+        Self::from_radians(ha, dec)
+    }
+
     /// Calculate the distance between two sets of coordinates.
     pub fn separation(self, b: Self) -> f64 {
-        eraSeps(self.ha, self.dec, b.ha, b.dec)
+        vincenty_separation(self.ha, self.dec, b.ha, b.dec)
+    }
+
+    /// Calculate the position angle of `b` from these coordinates, measured
+    /// from north through east (decreasing hour angle), in the range
+    /// `[0, 2π)` \[radians\].
+    pub fn position_angle(self, b: Self) -> f64 {
+        position_angle(-self.ha, self.dec, -b.ha, b.dec)
     }
 
     /// Get the [parallactic
@@ -92,6 +137,47 @@ impl HADec {
     pub fn get_parallactic_angle_mwa(self) -> f64 {
         self.get_parallactic_angle(MWA_LAT_RAD)
     }
+
+    /// Get the [parallactic
+    /// angle](https://en.wikipedia.org/wiki/Parallactic_angle) for an array at
+    /// `site`.
+    pub fn get_parallactic_angle_for_site(self, site: LatLngHeight) -> f64 {
+        self.get_parallactic_angle(site.latitude_rad)
+    }
+}
+
+/// Convert many equatorial coordinates to horizon coordinates (azimuth and
+/// elevation), given the local latitude on Earth. See [`HADec::to_azel`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn to_azel_array(hadecs: ArrayView1<HADec>, latitude_rad: f64) -> Array1<AzEl> {
+    map_array(hadecs, |hadec| hadec.to_azel(latitude_rad))
+}
+
+/// Convert many [`HADec`]s to [`RADec`]s, given a local sidereal time. See
+/// [`HADec::to_radec`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn to_radec_array(hadecs: ArrayView1<HADec>, lst_rad: f64) -> Array1<RADec> {
+    map_array(hadecs, |hadec| hadec.to_radec(lst_rad))
+}
+
+/// Convert each of `hadecs` to a [`RADec`] at the corresponding epoch of
+/// `epochs` (UTC), as seen from `site`. This is the inverse of
+/// [`crate::pos::radec::to_hadecs_at_epochs`], and uses the same (mean)
+/// sidereal time.
+///
+/// # Panics
+///
+/// Panics if `hadecs` and `epochs` have different lengths.
+#[allow(clippy::needless_pass_by_value)]
+pub fn to_radecs_at_epochs(
+    hadecs: ArrayView1<HADec>,
+    epochs: ArrayView1<Epoch>,
+    site: LatLngHeight,
+    dut1: Duration,
+) -> Array1<RADec> {
+    zip_map_arrays(hadecs, epochs, |hadec, epoch| {
+        hadec.to_radec(get_lmst(site.longitude_rad, epoch, dut1))
+    })
 }
 
 impl std::fmt::Display for HADec {
@@ -141,8 +227,40 @@ impl approx::RelativeEq for HADec {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pos::radec::to_hadecs_at_epochs;
     use approx::assert_abs_diff_eq;
 
+    #[test]
+    fn test_conversions_at_epochs() {
+        let site = LatLngHeight::mwa();
+        let dut1 = Duration::from_seconds(-0.1);
+        let start = Epoch::from_gpst_seconds(1090008640.0);
+        let epochs = Array1::from_shape_fn(5, |i| start + Duration::from_seconds(i as f64 * 600.0));
+        let radec = RADec::from_degrees(60.0, -27.0);
+
+        let hadecs = to_hadecs_at_epochs(radec, epochs.view(), site, dut1);
+        for (hadec, &epoch) in hadecs.iter().zip(epochs.iter()) {
+            let lmst = get_lmst(site.longitude_rad, epoch, dut1);
+            assert_abs_diff_eq!(*hadec, radec.to_hadec(lmst), epsilon = 1e-15);
+        }
+        // Ten minutes of solar time is a little more than 2.5° of hour angle.
+        assert_abs_diff_eq!(
+            (hadecs[1].ha - hadecs[0].ha).to_degrees(),
+            2.5068,
+            epsilon = 1e-4
+        );
+
+        let radecs = to_radecs_at_epochs(hadecs.view(), epochs.view(), site, dut1);
+        for result in &radecs {
+            assert_abs_diff_eq!(
+                result.ra.rem_euclid(std::f64::consts::TAU),
+                radec.ra,
+                epsilon = 1e-12
+            );
+            assert_abs_diff_eq!(result.dec, radec.dec, epsilon = 1e-15);
+        }
+    }
+
     #[test]
     fn to_azel() {
         let hd = HADec::from_degrees(1.0, -35.0);
@@ -190,4 +308,22 @@ mod tests {
         let result = hd1.separation(hd2);
         assert_abs_diff_eq!(result, 0.0, epsilon = 1e-10);
     }
+
+    #[test]
+    fn position_angle() {
+        let hd = HADec::from_degrees(0.0, -27.0);
+        // east of a source is towards smaller hour angles.
+        let east = HADec::from_degrees(-1.0, -27.0);
+        let west = HADec::from_degrees(1.0, -27.0);
+        let north = HADec::from_degrees(0.0, -26.0);
+        assert_abs_diff_eq!(hd.position_angle(north), 0.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(hd.position_angle(east).to_degrees(), 90.0, epsilon = 0.5);
+        assert_abs_diff_eq!(hd.position_angle(west).to_degrees(), 270.0, epsilon = 0.5);
+        let radec = hd.to_radec(1.0);
+        assert_abs_diff_eq!(
+            hd.position_angle(east),
+            radec.position_angle(east.to_radec(1.0)),
+            epsilon = 1e-10
+        );
+    }
 }
@@ -0,0 +1,326 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! HEALPix pixel indexing of sky positions.
+//!
+//! This is a small port of the `ang2pix` and `pix2ang` functions of the
+//! HEALPix C library, so that positions can be binned without depending on a
+//! HEALPix crate. The algorithms are described in Górski et al. (2005),
+//! <https://doi.org/10.1086/427976>.
+
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+use thiserror::Error;
+
+use super::radec::RADec;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum HealpixError {
+    #[error("HEALPix nside must be positive")]
+    ZeroNside,
+
+    #[error("HEALPix nside must be a power of 2 for the nested scheme, but got {nside}")]
+    NestedNsideNotPowerOf2 { nside: u32 },
+
+    #[error("HEALPix pixel {pixel} is out of range for nside {nside}")]
+    PixelOutOfRange { pixel: u64, nside: u32 },
+}
+
+/// The ordering of HEALPix pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealpixScheme {
+    /// Pixels are numbered along rings of constant declination, from the
+    /// north pole.
+    Ring,
+
+    /// Pixels are numbered hierarchically within each of the 12 base pixels.
+    /// `nside` must be a power of 2.
+    Nested,
+}
+
+/// The index of the first ring of each base pixel, in units of `nside`.
+const JRLL: [i64; 12] = [2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4];
+
+/// The longitude of each base pixel, in units of π/4.
+const JPLL: [i64; 12] = [1, 3, 5, 7, 0, 2, 4, 6, 1, 3, 5, 7];
+
+/// The number of pixels of a HEALPix map with resolution `nside`.
+pub fn num_healpix_pixels(nside: u32) -> u64 {
+    12 * u64::from(nside) * u64::from(nside)
+}
+
+impl RADec {
+    /// Get the index of the HEALPix pixel with resolution `nside` that
+    /// contains these coordinates.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `nside` is 0, or isn't a power of 2 for the
+    /// [`HealpixScheme::Nested`] scheme.
+    pub fn to_healpix(self, nside: u32, scheme: HealpixScheme) -> Result<u64, HealpixError> {
+        check_nside(nside, scheme)?;
+        let nside = i64::from(nside);
+        let z = self.dec.sin();
+        let za = z.abs();
+        // The longitude in units of π/2, in the range [0, 4).
+        let tt = self.ra.rem_euclid(TAU) / FRAC_PI_2;
+        let pixel = match scheme {
+            HealpixScheme::Ring => {
+                if za <= 2.0 / 3.0 {
+                    // The equatorial region.
+                    let temp1 = nside as f64 * (0.5 + tt);
+                    let temp2 = nside as f64 * z * 0.75;
+                    let jp = (temp1 - temp2) as i64;
+                    let jm = (temp1 + temp2) as i64;
+                    let ir = nside + 1 + jp - jm;
+                    let kshift = 1 - (ir & 1);
+                    let ip = ((jp + jm - nside + kshift + 1) / 2).rem_euclid(4 * nside);
+                    2 * nside * (nside - 1) + (ir - 1) * 4 * nside + ip
+                } else {
+                    // The polar caps.
+                    let tp = tt.fract();
+                    let tmp = nside as f64 * (3.0 * (1.0 - za)).sqrt();
+                    let jp = (tp * tmp) as i64;
+                    let jm = ((1.0 - tp) * tmp) as i64;
+                    let ir = jp + jm + 1;
+                    let ip = ((tt * ir as f64) as i64).rem_euclid(4 * ir);
+                    if z > 0.0 {
+                        2 * ir * (ir - 1) + ip
+                    } else {
+                        12 * nside * nside - 2 * ir * (ir + 1) + ip
+                    }
+                }
+            }
+            HealpixScheme::Nested => {
+                let order = nside.trailing_zeros();
+                let (face, ix, iy) = if za <= 2.0 / 3.0 {
+                    let temp1 = nside as f64 * (0.5 + tt);
+                    let temp2 = nside as f64 * z * 0.75;
+                    let jp = (temp1 - temp2) as i64;
+                    let jm = (temp1 + temp2) as i64;
+                    let ifp = jp >> order;
+                    let ifm = jm >> order;
+                    let face = match ifp.cmp(&ifm) {
+                        std::cmp::Ordering::Equal => ifp | 4,
+                        std::cmp::Ordering::Less => ifp,
+                        std::cmp::Ordering::Greater => ifm + 8,
+                    };
+                    (face, jm & (nside - 1), nside - (jp & (nside - 1)) - 1)
+                } else {
+                    let ntt = (tt as i64).min(3);
+                    let tp = tt - ntt as f64;
+                    let tmp = nside as f64 * (3.0 * (1.0 - za)).sqrt();
+                    let jp = ((tp * tmp) as i64).min(nside - 1);
+                    let jm = (((1.0 - tp) * tmp) as i64).min(nside - 1);
+                    if z >= 0.0 {
+                        (ntt, nside - jm - 1, nside - jp - 1)
+                    } else {
+                        (ntt + 8, jp, jm)
+                    }
+                };
+                face * nside * nside + spread_bits(ix) + (spread_bits(iy) << 1)
+            }
+        };
+        Ok(pixel as u64)
+    }
+
+    /// Get the centre of the HEALPix pixel `pixel` of a map with resolution
+    /// `nside`. The right ascension is in the range `[0, 2π)`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `nside` is 0, or isn't a power of 2 for the
+    /// [`HealpixScheme::Nested`] scheme, or if `pixel` isn't less than
+    /// [`num_healpix_pixels`].
+    pub fn from_healpix(
+        pixel: u64,
+        nside: u32,
+        scheme: HealpixScheme,
+    ) -> Result<RADec, HealpixError> {
+        check_nside(nside, scheme)?;
+        let num_pixels = num_healpix_pixels(nside);
+        if pixel >= num_pixels {
+            return Err(HealpixError::PixelOutOfRange { pixel, nside });
+        }
+        let num_pixels = num_pixels as i64;
+        let pixel = pixel as i64;
+        let nside = i64::from(nside);
+        let fact2 = 4.0 / num_pixels as f64;
+        let fact1 = (2 * nside) as f64 * fact2;
+
+        let (z, phi) = match scheme {
+            HealpixScheme::Ring => {
+                let ncap = 2 * nside * (nside - 1);
+                if pixel < ncap {
+                    // The north polar cap.
+                    let ir = (1 + isqrt(1 + 2 * pixel)) >> 1;
+                    let iphi = pixel + 1 - 2 * ir * (ir - 1);
+                    (
+                        1.0 - (ir * ir) as f64 * fact2,
+                        (iphi as f64 - 0.5) * FRAC_PI_2 / ir as f64,
+                    )
+                } else if pixel < num_pixels - ncap {
+                    // The equatorial region.
+                    let ip = pixel - ncap;
+                    let ir = ip / (4 * nside) + nside;
+                    let iphi = ip % (4 * nside) + 1;
+                    let fodd = if (ir + nside) & 1 == 1 { 1.0 } else { 0.5 };
+                    (
+                        (2 * nside - ir) as f64 * fact1,
+                        (iphi as f64 - fodd) * PI / (2 * nside) as f64,
+                    )
+                } else {
+                    // The south polar cap.
+                    let ip = num_pixels - pixel;
+                    let ir = (1 + isqrt(2 * ip - 1)) >> 1;
+                    let iphi = 4 * ir + 1 - (ip - 2 * ir * (ir - 1));
+                    (
+                        (ir * ir) as f64 * fact2 - 1.0,
+                        (iphi as f64 - 0.5) * FRAC_PI_2 / ir as f64,
+                    )
+                }
+            }
+            HealpixScheme::Nested => {
+                let order = nside.trailing_zeros();
+                let face = (pixel >> (2 * order)) as usize;
+                let pixel = pixel & (nside * nside - 1);
+                let ix = compress_bits(pixel);
+                let iy = compress_bits(pixel >> 1);
+
+                // The ring index, counted from the north pole.
+                let jr = (JRLL[face] << order) - ix - iy - 1;
+                let (nr, z, kshift) = if jr < nside {
+                    (jr, 1.0 - (jr * jr) as f64 * fact2, 0)
+                } else if jr > 3 * nside {
+                    let nr = 4 * nside - jr;
+                    (nr, (nr * nr) as f64 * fact2 - 1.0, 0)
+                } else {
+                    (nside, (2 * nside - jr) as f64 * fact1, (jr - nside) & 1)
+                };
+                let mut jp = (JPLL[face] * nr + ix - iy + 1 + kshift) / 2;
+                if jp > 4 * nside {
+                    jp -= 4 * nside;
+                }
+                if jp < 1 {
+                    jp += 4 * nside;
+                }
+                (
+                    z,
+                    (jp as f64 - (kshift + 1) as f64 * 0.5) * FRAC_PI_2 / nr as f64,
+                )
+            }
+        };
+        Ok(RADec::from_radians(phi.rem_euclid(TAU), z.asin()))
+    }
+}
+
+fn check_nside(nside: u32, scheme: HealpixScheme) -> Result<(), HealpixError> {
+    if nside == 0 {
+        return Err(HealpixError::ZeroNside);
+    }
+    if scheme == HealpixScheme::Nested && !nside.is_power_of_two() {
+        return Err(HealpixError::NestedNsideNotPowerOf2 { nside });
+    }
+    Ok(())
+}
+
+fn isqrt(v: i64) -> i64 {
+    let mut root = (v as f64).sqrt() as i64;
+    // Correct any rounding of the float square root.
+    while root * root > v {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= v {
+        root += 1;
+    }
+    root
+}
+
+/// Interleave the bits of `v` with zeros, e.g. 0b111 -> 0b10101.
+fn spread_bits(v: i64) -> i64 {
+    (0..32).fold(0, |acc, bit| acc | (((v >> bit) & 1) << (2 * bit)))
+}
+
+/// The inverse of [`spread_bits`], taking every second bit of `v`.
+fn compress_bits(v: i64) -> i64 {
+    (0..32).fold(0, |acc, bit| acc | (((v >> (2 * bit)) & 1) << bit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_to_healpix() {
+        // The poles.
+        let north = RADec::from_degrees(0.0, 90.0);
+        let south = RADec::from_degrees(0.0, -90.0);
+        for scheme in [HealpixScheme::Ring, HealpixScheme::Nested] {
+            assert_eq!(north.to_healpix(1, scheme).unwrap(), 0);
+            assert_eq!(south.to_healpix(1, scheme).unwrap(), 8);
+        }
+
+        // Just north of the equator; the same as healpy's
+        // `ang2pix(16, np.pi / 2, 0)`.
+        let radec = RADec::from_radians(0.0, 1e-8);
+        assert_eq!(radec.to_healpix(16, HealpixScheme::Ring).unwrap(), 1440);
+        assert_eq!(radec.to_healpix(16, HealpixScheme::Nested).unwrap(), 1216);
+
+        let radec = RADec::from_degrees(60.0, -27.0);
+        assert_eq!(radec.to_healpix(64, HealpixScheme::Ring).unwrap(), 35754);
+        assert_eq!(radec.to_healpix(64, HealpixScheme::Nested).unwrap(), 34746);
+        // Negative RAs are wrapped.
+        assert_eq!(
+            RADec::from_degrees(-300.0, -27.0)
+                .to_healpix(64, HealpixScheme::Ring)
+                .unwrap(),
+            35754
+        );
+    }
+
+    #[test]
+    fn test_from_healpix() {
+        for nside in [1, 2, 4, 8] {
+            for pixel in 0..num_healpix_pixels(nside) {
+                for scheme in [HealpixScheme::Ring, HealpixScheme::Nested] {
+                    let centre = RADec::from_healpix(pixel, nside, scheme).unwrap();
+                    assert_eq!(centre.to_healpix(nside, scheme).unwrap(), pixel);
+                }
+
+                // The schemes agree on where the pixels are.
+                let centre = RADec::from_healpix(pixel, nside, HealpixScheme::Ring).unwrap();
+                let nested = centre.to_healpix(nside, HealpixScheme::Nested).unwrap();
+                assert_abs_diff_eq!(
+                    RADec::from_healpix(nested, nside, HealpixScheme::Nested)
+                        .unwrap()
+                        .separation(centre),
+                    0.0,
+                    epsilon = 1e-12
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_bad_healpix() {
+        let radec = RADec::from_degrees(0.0, 0.0);
+        assert_eq!(
+            radec.to_healpix(3, HealpixScheme::Nested),
+            Err(HealpixError::NestedNsideNotPowerOf2 { nside: 3 })
+        );
+        // Any nside is fine for the ring scheme.
+        assert!(radec.to_healpix(3, HealpixScheme::Ring).is_ok());
+        for scheme in [HealpixScheme::Ring, HealpixScheme::Nested] {
+            assert_eq!(radec.to_healpix(0, scheme), Err(HealpixError::ZeroNside));
+            assert_eq!(
+                RADec::from_healpix(12, 1, scheme),
+                Err(HealpixError::PixelOutOfRange {
+                    pixel: 12,
+                    nside: 1
+                })
+            );
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A thin-shell model of the ionosphere, for first-order corrections and
+//! simulations of the refraction of sources.
+//!
+//! All of the free electrons are assumed to be in a thin spherical shell at a
+//! fixed height above the Earth, with a uniform vertical total electron
+//! content (TEC). A line of sight crosses the shell at its "pierce point", at a
+//! zenith angle that is smaller than the zenith angle at the observer, so the
+//! slant TEC increases more slowly than `sec(za)`. The curvature of the shell
+//! means that the excess phase path differs across an array, so sources appear
+//! shifted towards the zenith.
+
+use super::{azel::AzEl, radec::RADec};
+
+/// The mean radius of the Earth \[metres\].
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// The ionospheric constant; the excess phase path through a TEC of 1
+/// electron/m² at 1 Hz is `-IONO_CONST` metres.
+const IONO_CONST: f64 = 40.3;
+
+/// One TEC unit \[electrons/m²\].
+const TECU: f64 = 1e16;
+
+/// A thin shell of ionospheric electrons.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThinShell {
+    /// The height of the shell above the surface of the Earth \[metres\]
+    pub height_m: f64,
+
+    /// The vertical total electron content of the shell \[TECU\]
+    pub vtec_tecu: f64,
+}
+
+impl ThinShell {
+    /// The zenith angle \[radians\] at the pierce point of a line of sight
+    /// with zenith angle `za_rad` at the observer.
+    pub fn pierce_point_za(&self, za_rad: f64) -> f64 {
+        (EARTH_RADIUS_M / (EARTH_RADIUS_M + self.height_m) * za_rad.sin()).asin()
+    }
+
+    /// The ratio of the slant TEC along a line of sight with zenith angle
+    /// `za_rad` to the vertical TEC.
+    pub fn slant_factor(&self, za_rad: f64) -> f64 {
+        1.0 / self.pierce_point_za(za_rad).cos()
+    }
+
+    /// The excess phase path \[metres\] along a line of sight with zenith
+    /// angle `za_rad` at `freq_hz`. This is negative, as the phase velocity in
+    /// a plasma is greater than the speed of light.
+    pub fn excess_path_m(&self, za_rad: f64, freq_hz: f64) -> f64 {
+        -IONO_CONST * self.vtec_tecu * TECU / (freq_hz * freq_hz) * self.slant_factor(za_rad)
+    }
+
+    /// The change in zenith angle \[radians\] of a source at zenith angle
+    /// `za_rad`, as seen at `freq_hz` by an interferometer. This is negative
+    /// (the source appears higher), and is zero at the zenith.
+    pub fn za_offset(&self, za_rad: f64, freq_hz: f64) -> f64 {
+        // Moving along the ground tilts the local vertical by 1 / R radians
+        // per metre, and so changes the excess path through the shell.
+        let za_pp = self.pierce_point_za(za_rad);
+        let (s_za_pp, c_za_pp) = za_pp.sin_cos();
+        -IONO_CONST * self.vtec_tecu * TECU / (freq_hz * freq_hz) * s_za_pp
+            / ((EARTH_RADIUS_M + self.height_m) * c_za_pp.powi(3))
+    }
+
+    /// The apparent position of `radec` at `freq_hz`, as seen from
+    /// `latitude_rad` at the local sidereal time `lst_rad`. The source is
+    /// moved towards the zenith by [`ThinShell::za_offset`].
+    ///
+    /// The model is meaningless for sources below the horizon, which are
+    /// returned unchanged.
+    pub fn apparent_radec(
+        &self,
+        radec: RADec,
+        lst_rad: f64,
+        latitude_rad: f64,
+        freq_hz: f64,
+    ) -> RADec {
+        let azel = radec.to_hadec(lst_rad).to_azel(latitude_rad);
+        if azel.el <= 0.0 {
+            return radec;
+        }
+        let offset = self.za_offset(azel.za(), freq_hz);
+        AzEl::from_radians(azel.az, azel.el - offset)
+            .to_hadec(latitude_rad)
+            .to_radec(lst_rad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MWA_LAT_RAD;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_thin_shell() {
+        let shell = ThinShell {
+            height_m: 300e3,
+            vtec_tecu: 10.0,
+        };
+        assert_abs_diff_eq!(shell.slant_factor(0.0), 1.0);
+        assert_abs_diff_eq!(shell.za_offset(0.0, 150e6), 0.0);
+        // The slant factor at the pierce point is less than sec(za).
+        let za = 60_f64.to_radians();
+        assert!(shell.slant_factor(za) < 2.0);
+        assert!(shell.pierce_point_za(za) < za);
+
+        // ~180 m of excess path at 150 MHz.
+        assert_abs_diff_eq!(shell.excess_path_m(0.0, 150e6), -179.1, epsilon = 0.1);
+
+        // A few arcseconds of offset at 45° zenith angle, scaling with f⁻².
+        let offset = shell.za_offset(45_f64.to_radians(), 150e6);
+        assert_abs_diff_eq!(offset.to_degrees() * 3600.0, -9.3, epsilon = 0.1);
+        assert_abs_diff_eq!(
+            shell.za_offset(45_f64.to_radians(), 300e6),
+            offset / 4.0,
+            epsilon = 1e-15
+        );
+
+        // The offset is the derivative of the excess path with respect to
+        // position on the ground.
+        let d_za = 1e-6;
+        let derivative = (shell.excess_path_m(za + d_za, 150e6)
+            - shell.excess_path_m(za - d_za, 150e6))
+            / (2.0 * d_za);
+        assert_abs_diff_eq!(
+            shell.za_offset(za, 150e6),
+            derivative / (EARTH_RADIUS_M * za.cos()),
+            epsilon = 1e-10
+        );
+    }
+
+    #[test]
+    fn test_apparent_radec() {
+        let shell = ThinShell {
+            height_m: 300e3,
+            vtec_tecu: 20.0,
+        };
+        let lst = 1.0;
+        let zenith = RADec::from_radians(lst, MWA_LAT_RAD);
+        let radec = RADec::from_degrees(40.0, -10.0);
+        let apparent = shell.apparent_radec(radec, lst, MWA_LAT_RAD, 100e6);
+        // The source moves straight towards the zenith.
+        let offset = shell.za_offset(zenith.separation(radec), 100e6);
+        assert_abs_diff_eq!(
+            zenith.separation(apparent) - zenith.separation(radec),
+            offset,
+            epsilon = 1e-10
+        );
+        assert_abs_diff_eq!(
+            zenith.position_angle(apparent),
+            zenith.position_angle(radec),
+            epsilon = 1e-8
+        );
+
+        let below_horizon = RADec::from_radians(lst + std::f64::consts::PI, 0.0);
+        assert_eq!(
+            shell.apparent_radec(below_horizon, lst, MWA_LAT_RAD, 100e6),
+            below_horizon
+        );
+    }
+}
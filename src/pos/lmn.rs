@@ -10,7 +10,9 @@
 
 use std::f64::consts::TAU;
 
-use super::uvw::UVW;
+use ndarray::prelude::*;
+
+use super::{map_array, radec::RADec, uvw::UVW};
 
 /// (l,m,n) direction-cosine coordinates. There are no units (i.e.
 /// dimensionless).
@@ -19,6 +21,7 @@ use super::uvw::UVW;
 /// Synthesis in Radio Astronomy, Third Edition, Section 3: Analysis of the
 /// Interferometer Response.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::upper_case_acronyms)]
 pub struct LMN {
     /// l coordinate \[dimensionless\]
@@ -49,6 +52,78 @@ impl LMN {
     }
 }
 
+/// The projection used to get (l,m) coordinates of sky positions relative to a
+/// phase centre.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Projection {
+    /// The orthographic (SIN) projection, i.e. true direction cosines. See
+    /// [`RADec::to_lmn`].
+    #[default]
+    Sin,
+
+    /// The NCP projection, used by East-West arrays (e.g. WSRT). This is a SIN
+    /// projection onto a plane parallel to the celestial equator, stretched
+    /// in m by `1 / sin(dec)` of the phase centre, so it's undefined for a
+    /// phase centre on the equator.
+    Ncp,
+}
+
+impl LMN {
+    /// Make an [`LMN`] from (l,m), with `n = sqrt(1 - l^2 - m^2)`.
+    pub fn from_lm(l: f64, m: f64) -> LMN {
+        LMN {
+            l,
+            m,
+            n: (1.0 - l * l - m * m).sqrt(),
+        }
+    }
+
+    /// Get the (l,m,n) of `radec` relative to `phase_centre` with the given
+    /// [`Projection`].
+    pub fn from_radec(radec: RADec, phase_centre: RADec, projection: Projection) -> LMN {
+        match projection {
+            Projection::Sin => radec.to_lmn(phase_centre),
+            Projection::Ncp => {
+                let (s_d_ra, c_d_ra) = (radec.ra - phase_centre.ra).sin_cos();
+                let c_dec = radec.dec.cos();
+                let (pc_s_dec, pc_c_dec) = phase_centre.dec.sin_cos();
+                Self::from_lm(c_dec * s_d_ra, (pc_c_dec - c_dec * c_d_ra) / pc_s_dec)
+            }
+        }
+    }
+
+    /// Get `n - 1`, i.e. the w-term, without the loss of precision of
+    /// subtracting 1 from `n` when (l,m) are small. This assumes that `n` is
+    /// `sqrt(1 - l^2 - m^2)`.
+    pub fn n_minus_one(self) -> f64 {
+        -(self.l * self.l + self.m * self.m) / (1.0 + self.n)
+    }
+
+    /// Like [`LMN::prepare_for_rime`], but using [`LMN::n_minus_one`] for the
+    /// w-term, which is more precise close to the phase centre.
+    pub fn prepare_for_rime_precise(self) -> LmnRime {
+        LmnRime {
+            l: TAU * self.l,
+            m: TAU * self.m,
+            n: TAU * self.n_minus_one(),
+        }
+    }
+}
+
+/// Get the [`LmnRime`]s of many [`RADec`]s (e.g. a source catalogue) relative
+/// to a phase centre, with the given [`Projection`]. The w-terms are from
+/// [`LMN::n_minus_one`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn radecs_to_lmn_rimes(
+    radecs: ArrayView1<RADec>,
+    phase_centre: RADec,
+    projection: Projection,
+) -> Array1<LmnRime> {
+    map_array(radecs, |radec| {
+        LMN::from_radec(radec, phase_centre, projection).prepare_for_rime_precise()
+    })
+}
+
 #[cfg(any(test, feature = "approx"))]
 impl approx::AbsDiffEq for LMN {
     type Epsilon = f64;
@@ -213,6 +288,36 @@ mod tests {
         assert_abs_diff_eq!(lmn.dot(uvw), 3.621);
     }
 
+    #[test]
+    fn test_projections_and_w_terms() {
+        let phase_centre = RADec::from_degrees(60.0, -27.0);
+        let radecs = array![
+            phase_centre,
+            RADec::from_degrees(60.01, -27.01),
+            RADec::from_degrees(70.0, -40.0),
+        ];
+        let sin = radecs.map(|&r| LMN::from_radec(r, phase_centre, Projection::Sin));
+        let ncp = radecs.map(|&r| LMN::from_radec(r, phase_centre, Projection::Ncp));
+        assert_abs_diff_eq!(sin[0], LMN::from_lm(0.0, 0.0), epsilon = 1e-15);
+        assert_abs_diff_eq!(ncp[0], LMN::from_lm(0.0, 0.0), epsilon = 1e-15);
+        // The projections agree in l, and only differ in m far from the phase
+        // centre.
+        for (sin, ncp) in sin.iter().zip(ncp.iter()) {
+            assert_abs_diff_eq!(sin.l, ncp.l);
+        }
+        assert_abs_diff_eq!(sin[1].m, ncp[1].m, epsilon = 1e-7);
+        assert!((sin[2].m - ncp[2].m).abs() > 0.05);
+
+        // n - 1 keeps its precision close to the phase centre.
+        let lmn = LMN::from_lm(1e-9, 0.0);
+        assert_abs_diff_eq!(lmn.n - 1.0, 0.0);
+        assert_abs_diff_eq!(lmn.n_minus_one(), -5e-19, epsilon = 1e-30);
+        assert_abs_diff_eq!(sin[2].n_minus_one(), sin[2].n - 1.0, epsilon = 1e-15);
+
+        let lmn_rimes = radecs_to_lmn_rimes(radecs.view(), phase_centre, Projection::Sin);
+        assert_abs_diff_eq!(lmn_rimes[2], sin[2].prepare_for_rime(), epsilon = 1e-14);
+    }
+
     #[test]
     fn test_lmn_rime_to_lmn() {
         let lmn = LMN {
@@ -5,12 +5,125 @@
 //! Super module for all positional code.
 
 pub mod azel;
+pub mod beamformer;
 pub mod earth;
+pub mod ecliptic;
 pub mod enh;
+pub mod eop;
+pub mod ephem;
+pub mod fk5;
+pub mod galactic;
 pub mod hadec;
+pub mod healpix;
+pub mod ionosphere;
 pub mod lmn;
+pub mod observed;
 pub mod pal;
+pub mod pol_frame;
 pub mod precession;
 pub mod radec;
+pub mod sky_cell;
 pub mod uvw;
+pub mod velocity;
 pub mod xyz;
+
+use ndarray::prelude::*;
+
+/// Serialise an angle in radians as degrees.
+#[cfg(feature = "serde")]
+pub(crate) fn radians_to_degrees<S: serde::Serializer>(num: &f64, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(num.to_degrees())
+}
+
+/// Deserialise an angle in degrees as radians.
+#[cfg(feature = "serde")]
+pub(crate) fn degrees_to_radians<'de, D>(d: D) -> Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let num: f64 = serde::Deserialize::deserialize(d)?;
+    Ok(num.to_radians())
+}
+
+/// Apply `f` to every element of `array`. This is done in parallel if the
+/// `rayon` feature is enabled.
+pub(crate) fn map_array<A, B, F>(array: ArrayView1<A>, f: F) -> Array1<B>
+where
+    A: Copy + Sync,
+    B: Send,
+    F: Fn(A) -> B + Send + Sync,
+{
+    #[cfg(feature = "rayon")]
+    {
+        Zip::from(&array).par_map_collect(|&a| f(a))
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        array.map(|&a| f(a))
+    }
+}
+
+/// Apply `f` to every pair of elements of `a` and `b`. This is done in parallel
+/// if the `rayon` feature is enabled.
+///
+/// # Panics
+///
+/// Panics if `a` and `b` have different lengths.
+pub(crate) fn zip_map_arrays<A, B, C, F>(a: ArrayView1<A>, b: ArrayView1<B>, f: F) -> Array1<C>
+where
+    A: Copy + Sync,
+    B: Copy + Sync,
+    C: Send,
+    F: Fn(A, B) -> C + Send + Sync,
+{
+    #[cfg(feature = "rayon")]
+    {
+        Zip::from(&a).and(&b).par_map_collect(|&a, &b| f(a, b))
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        Zip::from(&a).and(&b).map_collect(|&a, &b| f(a, b))
+    }
+}
+
+/// The dot product of two (e.g. unit) vectors, such as those from
+/// [`RADec::to_unit_vector`](radec::RADec::to_unit_vector). For unit vectors,
+/// this is the cosine of the angle between them.
+pub fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// The cross product of two (e.g. unit) vectors, such as those from
+/// [`RADec::to_unit_vector`](radec::RADec::to_unit_vector).
+pub fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// The great-circle distance between two points on a sphere, given their
+/// longitudes and latitudes \[radians\]. This uses the Vincenty formula, which
+/// is accurate for all separations, unlike the arccos of a dot product, which
+/// loses precision for small separations.
+pub(crate) fn vincenty_separation(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (s_dlon, c_dlon) = (lon2 - lon1).sin_cos();
+    let (s_lat1, c_lat1) = lat1.sin_cos();
+    let (s_lat2, c_lat2) = lat2.sin_cos();
+    let num1 = c_lat2 * s_dlon;
+    let num2 = c_lat1 * s_lat2 - s_lat1 * c_lat2 * c_dlon;
+    let denominator = s_lat1 * s_lat2 + c_lat1 * c_lat2 * c_dlon;
+    num1.hypot(num2).atan2(denominator)
+}
+
+/// The position angle of the second point from the first, measured from the
+/// direction of increasing latitude (e.g. north) towards increasing longitude
+/// (e.g. east), in the range `[0, 2π)` \[radians\].
+pub(crate) fn position_angle(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (s_dlon, c_dlon) = (lon2 - lon1).sin_cos();
+    let (s_lat1, c_lat1) = lat1.sin_cos();
+    let (s_lat2, c_lat2) = lat2.sin_cos();
+    let pa = (s_dlon * c_lat2).atan2(c_lat1 * s_lat2 - s_lat1 * c_lat2 * c_dlon);
+    pa.rem_euclid(std::f64::consts::TAU)
+}
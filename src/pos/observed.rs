@@ -0,0 +1,204 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The full transformation from ICRS (J2000) coordinates to the azimuth and
+//! elevation seen by an observer, including refraction.
+//!
+//! [`crate::precession`] only handles the part of this chain needed to
+//! generate UVWs; [`RADec::to_azel_observed`] does all of it in one call.
+
+use std::f64::consts::TAU;
+
+use erfa::{
+    aliases::eraGst06a,
+    constants::ERFA_DJM0,
+    transform::{cartesian_to_spherical, spherical_to_cartesian},
+};
+use hifitime::{Duration, Epoch};
+
+use super::{
+    azel::AzEl, earth::LatLngHeight, eop::EarthOrientation, hadec::HADec, pal,
+    precession::aber_radec_rad, radec::RADec,
+};
+
+/// Atmospheric conditions at an observing site, used to determine the
+/// refraction of incoming light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weather {
+    /// Pressure at the observer \[hPa\]. No refraction is applied if this is
+    /// zero.
+    pub pressure_hpa: f64,
+    /// Ambient temperature at the observer \[°C\]
+    pub temperature_c: f64,
+    /// Relative humidity at the observer, in the range `[0, 1]`
+    pub relative_humidity: f64,
+    /// The observing wavelength \[μm\]. Wavelengths longer than 100 μm are
+    /// treated as radio.
+    pub wavelength_um: f64,
+}
+
+impl Weather {
+    /// No atmosphere, i.e. no refraction.
+    pub fn none() -> Weather {
+        Self {
+            pressure_hpa: 0.0,
+            temperature_c: 0.0,
+            relative_humidity: 0.0,
+            wavelength_um: 1e6,
+        }
+    }
+
+    /// The `A` and `B` coefficients \[radians\] of the refraction model
+    /// `ΔZ = A tan Z + B tan³ Z`, where `Z` is the unrefracted zenith angle.
+    ///
+    /// This is a port of ERFA's `eraRefco`; inputs are clamped to the same
+    /// ranges.
+    pub fn refraction_constants(&self) -> (f64, f64) {
+        let optical = self.wavelength_um <= 100.0;
+        let t = self.temperature_c.clamp(-150.0, 200.0);
+        let p = self.pressure_hpa.clamp(0.0, 10000.0);
+        let r = self.relative_humidity.clamp(0.0, 1.0);
+        let w = self.wavelength_um.clamp(0.1, 1e6);
+
+        // Water vapour pressure at the observer.
+        let pw = if p > 0.0 {
+            let ps = 10_f64.powf((0.7859 + 0.03477 * t) / (1.0 + 0.00412 * t))
+                * (1.0 + p * (4.5e-6 + 6e-10 * t * t));
+            r * ps / (1.0 - (1.0 - r) * ps / p)
+        } else {
+            0.0
+        };
+
+        // Refractive index minus 1 at the observer.
+        let tk = t + 273.15;
+        let gamma = if optical {
+            let wlsq = w * w;
+            ((77.53484e-6 + (4.39108e-7 + 3.666e-9 / wlsq) / wlsq) * p - 11.2684e-6 * pw) / tk
+        } else {
+            (77.6890e-6 * p - (6.3938e-6 - 0.375463 / tk) * pw) / tk
+        };
+
+        // Formula for beta from Stone, with empirical adjustments.
+        let mut beta = 4.4474e-6 * tk;
+        if !optical {
+            beta -= 0.0074 * pw * beta;
+        }
+
+        (gamma * (1.0 - beta), -gamma * (beta - gamma / 2.0))
+    }
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl RADec {
+    /// Get the observed azimuth and elevation of these ICRS (J2000)
+    /// coordinates from `site` at `epoch` (UTC).
+    ///
+    /// The transformation applies annual aberration, IAU 2006/2000A precession
+    /// and nutation, Earth rotation (Greenwich apparent sidereal time), polar
+    /// motion and refraction (see [`Weather::refraction_constants`]). `dut1` is
+    /// UT1 - UTC and `polar_motion` is the (x, y) coordinates of the pole
+    /// \[radians\]; both are published by the IERS, and zero can be used if
+    /// they aren't known.
+    ///
+    /// Diurnal aberration (< 0.32") and light deflection by the Sun are not
+    /// applied, and the refraction model degrades below ~3° elevation.
+    pub fn to_azel_observed(
+        self,
+        epoch: Epoch,
+        site: LatLngHeight,
+        dut1: Duration,
+        polar_motion: (f64, f64),
+        weather: Weather,
+    ) -> AzEl {
+        let mjd_tt = epoch.to_mjd_tt_days();
+        let mjd_ut1 = (epoch + dut1).to_mjd_utc_days();
+
+        // Aberrate, then rotate to the true equator and equinox of date.
+        let aberrated = aber_radec_rad(2000.0, mjd_tt, self);
+        let mut rmatpn = [[0.0; 3]; 3];
+        unsafe { pal::palPrenut(2000.0, mjd_tt, rmatpn.as_mut_ptr()) };
+        let v = spherical_to_cartesian(aberrated.ra, aberrated.dec);
+        let (ra, dec) = cartesian_to_spherical([
+            rmatpn[0][0] * v[0] + rmatpn[0][1] * v[1] + rmatpn[0][2] * v[2],
+            rmatpn[1][0] * v[0] + rmatpn[1][1] * v[1] + rmatpn[1][2] * v[2],
+            rmatpn[2][0] * v[0] + rmatpn[2][1] * v[1] + rmatpn[2][2] * v[2],
+        ]);
+
+        // Polar motion moves the site with respect to the celestial pole.
+        let (longitude, latitude) = EarthOrientation { dut1, polar_motion }
+            .apply_polar_motion(site.longitude_rad, site.latitude_rad);
+
+        let gast = eraGst06a(ERFA_DJM0, mjd_ut1, ERFA_DJM0, mjd_tt);
+        let ha = (gast + longitude - ra).rem_euclid(TAU);
+        let topocentric = HADec::from_radians(ha, dec).to_azel(latitude);
+
+        // Refraction. Like ERFA, limit tan Z near the horizon.
+        let (refa, refb) = weather.refraction_constants();
+        let tan_za = topocentric.el.cos() / topocentric.el.sin().max(0.05);
+        let refraction = (refa + refb * tan_za * tan_za) * tan_za;
+        AzEl::from_radians(topocentric.az, topocentric.el + refraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::precession::get_lmst;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_refraction_constants() {
+        // The values of ERFA's t_refco test.
+        let weather = Weather {
+            pressure_hpa: 800.0,
+            temperature_c: 10.0,
+            relative_humidity: 0.9,
+            wavelength_um: 0.4,
+        };
+        let (refa, refb) = weather.refraction_constants();
+        assert_abs_diff_eq!(refa, 0.2264949956241415e-3, epsilon = 1e-15);
+        assert_abs_diff_eq!(refb, -0.2598658261729344e-6, epsilon = 1e-18);
+
+        let (refa, refb) = Weather::none().refraction_constants();
+        assert_abs_diff_eq!(refa, 0.0);
+        assert_abs_diff_eq!(refb, 0.0);
+    }
+
+    #[test]
+    fn test_to_azel_observed() {
+        let site = LatLngHeight::mwa();
+        let epoch = Epoch::from_gregorian_utc(2020, 1, 1, 12, 0, 0, 0);
+        let dut1 = Duration::from_seconds(0.0);
+        let radec = RADec::from_degrees(60.0, -30.0);
+
+        // Without refraction, the observed position differs from the
+        // geometric one by precession since J2000 (~0.3°), nutation and
+        // aberration.
+        let geometric = radec
+            .to_hadec(get_lmst(site.longitude_rad, epoch, dut1))
+            .to_azel(site.latitude_rad);
+        let observed = radec.to_azel_observed(epoch, site, dut1, (0.0, 0.0), Weather::none());
+        let separation = observed.separation(geometric).to_degrees();
+        assert!(separation > 0.1 && separation < 0.5, "{separation}");
+
+        // Refraction only raises the source.
+        let weather = Weather {
+            pressure_hpa: 1000.0,
+            temperature_c: 20.0,
+            relative_humidity: 0.5,
+            wavelength_um: 2e5,
+        };
+        let refracted = radec.to_azel_observed(epoch, site, dut1, (0.0, 0.0), weather);
+        assert_abs_diff_eq!(refracted.az, observed.az, epsilon = 1e-12);
+        let (refa, _) = weather.refraction_constants();
+        let expected = refa / observed.el.tan();
+        assert_abs_diff_eq!(refracted.el - observed.el, expected, epsilon = 1e-6);
+        assert!(refracted.el > observed.el);
+    }
+}
@@ -0,0 +1,173 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Frames in which the two polarisations of a signal can be described, and the
+//! Jones matrices that convert between them.
+//!
+//! Every frame is a pair of axes (X, Y) on the sky in the direction of a
+//! source. A [`Jones`] matrix `J` from [`PolFrame::jones_to`] converts the
+//! electric field `e` of the source as `J e`, and so converts visibilities
+//! (coherency matrices) `V` as `J V J^H`.
+
+use erfa::aliases::eraHd2ae;
+use ndarray::prelude::*;
+
+use super::{cross, dot, hadec::HADec, map_array};
+use crate::{c64, Jones};
+
+/// A frame in which polarisations are described.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolFrame {
+    /// The IAU convention: X points towards the north celestial pole and Y
+    /// points east.
+    Sky,
+
+    /// Feeds fixed to an alt-az mount: X points towards the zenith and Y is
+    /// parallel to the horizon. This is the [`PolFrame::Sky`] frame rotated by
+    /// the parallactic angle.
+    AltAzMount,
+
+    /// Ideal MWA dipoles: X is the east-west dipole and Y is the north-south
+    /// dipole, both fixed to the ground. Away from the zenith, the projections
+    /// of the dipoles onto the sky aren't orthogonal, so conversions to and
+    /// from this frame aren't pure rotations.
+    Instrumental,
+}
+
+impl PolFrame {
+    /// The X and Y axes of this frame for a source at `hadec`, seen from
+    /// `latitude_rad`, as (east, north, up) vectors.
+    fn axes(self, hadec: HADec, latitude_rad: f64) -> [[f64; 3]; 2] {
+        let (az, el) = eraHd2ae(hadec.ha, hadec.dec, latitude_rad);
+        let (s_az, c_az) = az.sin_cos();
+        let (s_el, c_el) = el.sin_cos();
+        let dir = [c_el * s_az, c_el * c_az, s_el];
+        match self {
+            PolFrame::Sky => {
+                let pole = [0.0, latitude_rad.cos(), latitude_rad.sin()];
+                let east = normalise(cross(pole, dir));
+                [cross(dir, east), east]
+            }
+            PolFrame::AltAzMount => {
+                // The zenith projected onto the sky.
+                let up = [-s_el * dir[0], -s_el * dir[1], c_el * c_el];
+                let up = normalise(up);
+                [up, cross(up, dir)]
+            }
+            PolFrame::Instrumental => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        }
+    }
+
+    /// The (real) matrix that takes the components of a field in the
+    /// [`PolFrame::Sky`] frame to this frame.
+    fn matrix_from_sky(self, hadec: HADec, latitude_rad: f64) -> [[f64; 2]; 2] {
+        let [north, east] = PolFrame::Sky.axes(hadec, latitude_rad);
+        let [x, y] = self.axes(hadec, latitude_rad);
+        [[dot(x, north), dot(x, east)], [dot(y, north), dot(y, east)]]
+    }
+
+    /// The [`Jones`] matrix that converts a field described in this frame to
+    /// the `to` frame, for a source at `hadec` seen from `latitude_rad`.
+    ///
+    /// Conversions from the [`PolFrame::Instrumental`] frame are singular at
+    /// the horizon.
+    pub fn jones_to(self, to: PolFrame, hadec: HADec, latitude_rad: f64) -> Jones<f64> {
+        let [[a, b], [c, d]] = self.matrix_from_sky(hadec, latitude_rad);
+        let [[e, f], [g, h]] = to.matrix_from_sky(hadec, latitude_rad);
+        // `to` multiplied by the inverse of `self`.
+        let inv_det = 1.0 / (a * d - b * c);
+        Jones::from([
+            c64::new((e * d - f * c) * inv_det, 0.0),
+            c64::new((f * a - e * b) * inv_det, 0.0),
+            c64::new((g * d - h * c) * inv_det, 0.0),
+            c64::new((h * a - g * b) * inv_det, 0.0),
+        ])
+    }
+}
+
+/// The [`Jones`] matrices that convert fields described in the `from` frame to
+/// the `to` frame, for each of `hadecs` (e.g. a source over the timesteps of an
+/// observation, or many sources at one timestep). See [`PolFrame::jones_to`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn jones_between_frames(
+    from: PolFrame,
+    to: PolFrame,
+    hadecs: ArrayView1<HADec>,
+    latitude_rad: f64,
+) -> Array1<Jones<f64>> {
+    map_array(hadecs, |hadec| from.jones_to(to, hadec, latitude_rad))
+}
+
+fn normalise(a: [f64; 3]) -> [f64; 3] {
+    let norm = dot(a, a).sqrt();
+    [a[0] / norm, a[1] / norm, a[2] / norm]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MWA_LAT_RAD;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_sky_to_mount_is_parallactic_rotation() {
+        let hadec = HADec::from_degrees(-20.0, -40.0);
+        let q = hadec.get_parallactic_angle(MWA_LAT_RAD);
+        let (s_q, c_q) = q.sin_cos();
+        let jones = PolFrame::Sky.jones_to(PolFrame::AltAzMount, hadec, MWA_LAT_RAD);
+        let expected = Jones::from([
+            c64::new(c_q, 0.0),
+            c64::new(s_q, 0.0),
+            c64::new(-s_q, 0.0),
+            c64::new(c_q, 0.0),
+        ]);
+        assert_abs_diff_eq!(jones, expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_jones_between_frames() {
+        let hadecs = array![
+            HADec::from_degrees(-30.0, -27.0),
+            HADec::from_degrees(0.0, -10.0),
+            HADec::from_degrees(45.0, -60.0),
+        ];
+        let frames = [PolFrame::Sky, PolFrame::AltAzMount, PolFrame::Instrumental];
+        for &hadec in &hadecs {
+            for from in frames {
+                let identity = from.jones_to(from, hadec, MWA_LAT_RAD);
+                assert_abs_diff_eq!(identity, Jones::identity(), epsilon = 1e-12);
+                for to in frames {
+                    // Converting there and back again does nothing.
+                    let there = from.jones_to(to, hadec, MWA_LAT_RAD);
+                    let back = to.jones_to(from, hadec, MWA_LAT_RAD);
+                    assert_abs_diff_eq!(back * there, Jones::identity(), epsilon = 1e-10);
+                }
+            }
+        }
+
+        let jones = jones_between_frames(
+            PolFrame::Sky,
+            PolFrame::Instrumental,
+            hadecs.view(),
+            MWA_LAT_RAD,
+        );
+        assert_eq!(jones.len(), 3);
+        assert_abs_diff_eq!(
+            jones[1],
+            PolFrame::Sky.jones_to(PolFrame::Instrumental, hadecs[1], MWA_LAT_RAD),
+            epsilon = 1e-15
+        );
+
+        // On the meridian, north of the zenith in the southern hemisphere, the
+        // east-west dipole only sees the eastern component of the field.
+        let jones = PolFrame::Sky.jones_to(
+            PolFrame::Instrumental,
+            HADec::from_degrees(0.0, -10.0),
+            MWA_LAT_RAD,
+        );
+        assert_abs_diff_eq!(jones[0].re, 0.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(jones[1].re, 1.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(jones[3].re, 0.0, epsilon = 1e-12);
+    }
+}
@@ -17,6 +17,7 @@ use std::f64::consts::TAU;
 
 use hifitime::{Duration, Epoch};
 
+use super::eop::EarthOrientation;
 use crate::{pal, HADec, RADec, XyzGeodetic};
 
 #[derive(Debug, Clone, Copy)]
@@ -81,6 +82,44 @@ pub fn get_lmst(array_longitude_rad: f64, time: Epoch, dut1: Duration) -> f64 {
     (gmst + array_longitude_rad) % TAU
 }
 
+/// Get the local mean sidereal time, with UT1 - UTC and polar motion from
+/// `eop`. Use [`EarthOrientation::default`] if they aren't known, in which case
+/// this is the same as [`get_lmst`].
+pub fn get_lmst_eop(
+    array_longitude_rad: f64,
+    array_latitude_rad: f64,
+    time: Epoch,
+    eop: EarthOrientation,
+) -> f64 {
+    let (longitude, _) = eop.apply_polar_motion(array_longitude_rad, array_latitude_rad);
+    get_lmst(longitude, time, eop.dut1)
+}
+
+/// Get the Earth rotation angle (IAU 2000) at Greenwich \[radians\], in the
+/// range `[0, 2π)`. `time` should be in the UTC frame, and `dut1` is UT1 - UTC
+/// (which can be 0 seconds if it isn't known).
+///
+/// This is the same as ERFA's `eraEra00`.
+pub fn get_era(time: Epoch, dut1: Duration) -> f64 {
+    let ut1 = (time + dut1).to_mjd_utc_days();
+    // Days since J2000, and the fraction of the (Julian) day.
+    let days = ut1 - 51544.5;
+    let frac = ut1.fract() + 0.5;
+    (TAU * (frac + 0.779_057_273_264 + 0.002_737_811_911_354_48 * days)).rem_euclid(TAU)
+}
+
+/// Get the local Earth rotation angle \[radians\] of an array, in the range
+/// `[0, 2π)`, with UT1 - UTC and polar motion from `eop`. See [`get_era`].
+pub fn get_local_era(
+    array_longitude_rad: f64,
+    array_latitude_rad: f64,
+    time: Epoch,
+    eop: EarthOrientation,
+) -> f64 {
+    let (longitude, _) = eop.apply_polar_motion(array_longitude_rad, array_latitude_rad);
+    (get_era(time, eop.dut1) + longitude).rem_euclid(TAU)
+}
+
 /// Obtain precessed coordinate information. `time` should be in the UTC frame,
 /// and `dut1` (i.e. UT1 - UTC) provides a better estimate of the LMST. If DUT1
 /// isn't known, then a [`Duration`] of 0 seconds can be used; the results are
@@ -128,7 +167,7 @@ pub fn precess_time(
 }
 
 // Blatently stolen from cotter.
-fn aber_radec_rad(eq: f64, mjd: f64, radec: RADec) -> RADec {
+pub(crate) fn aber_radec_rad(eq: f64, mjd: f64, radec: RADec) -> RADec {
     let mut v1 = [0.0; 3];
     let mut v2 = [0.0; 3];
 
@@ -268,6 +307,67 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_era() {
+        // The value of ERFA's t_era00 test.
+        let epoch = Epoch::from_mjd_utc(54388.0);
+        let dut1 = Duration::from_seconds(0.0);
+        assert_abs_diff_eq!(get_era(epoch, dut1), 0.4022837240028158, epsilon = 1e-9);
+
+        // The ERA and GMST differ by the accumulated precession in RA (and the
+        // equation of the origins), a few arcminutes in 2007.
+        let diff = get_lmst(0.0, epoch, dut1) - get_era(epoch, dut1);
+        assert!(diff > 1e-3 && diff < 3e-3, "{diff}");
+
+        let eop = EarthOrientation {
+            dut1: Duration::from_seconds(0.4),
+            polar_motion: (1e-6, 0.0),
+        };
+        assert_abs_diff_eq!(
+            get_local_era(
+                MWA_LONG_RAD,
+                MWA_LAT_RAD,
+                epoch,
+                EarthOrientation::default()
+            ),
+            (get_era(epoch, dut1) + MWA_LONG_RAD).rem_euclid(TAU),
+            epsilon = 1e-12
+        );
+        let era = get_local_era(MWA_LONG_RAD, MWA_LAT_RAD, epoch, eop);
+        let expected = get_local_era(
+            MWA_LONG_RAD,
+            MWA_LAT_RAD,
+            epoch + eop.dut1,
+            EarthOrientation::default(),
+        );
+        assert_abs_diff_ne!(era, expected, epsilon = 1e-12);
+        assert_abs_diff_eq!(era, expected, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_get_lmst_eop() {
+        let epoch = Epoch::from_gpst_seconds(1090008642.0);
+        let dut1 = Duration::from_f64(-0.31295757, Unit::Second);
+        let eop = EarthOrientation {
+            dut1,
+            ..EarthOrientation::default()
+        };
+        assert_abs_diff_eq!(
+            get_lmst_eop(MWA_LONG_RAD, MWA_LAT_RAD, epoch, eop),
+            get_lmst(MWA_LONG_RAD, epoch, dut1),
+            epsilon = 1e-15
+        );
+
+        // Polar motion of 0.3" moves the local meridian by a similar amount.
+        let eop = EarthOrientation {
+            dut1,
+            polar_motion: (0.0, 0.3 / 3600.0_f64.to_degrees()),
+        };
+        let diff = get_lmst_eop(MWA_LONG_RAD, MWA_LAT_RAD, epoch, eop)
+            - get_lmst(MWA_LONG_RAD, epoch, dut1);
+        assert!(diff.abs() > 1e-7 && diff.abs() < 3e-6, "{diff}");
+    }
+
     #[test]
     // TODO: reduce cognitive complexity
     #[allow(clippy::cognitive_complexity)]
@@ -6,15 +6,24 @@
 
 use std::f64::consts::{FRAC_PI_2, PI, TAU};
 
-use erfa::{
-    aliases::eraSeps,
-    transform::{cartesian_to_spherical, spherical_to_cartesian},
-};
+use erfa::transform::{cartesian_to_spherical, spherical_to_cartesian};
+
+use hifitime::{Duration, Epoch};
+use ndarray::prelude::*;
 
-use crate::sexagesimal::{degrees_to_sexagesimal_dms, degrees_to_sexagesimal_hms};
+use crate::{
+    constants::{DAYSEC, EARTH_ROTATION_RAD_S},
+    precession::get_lmst,
+    sexagesimal::{degrees_to_sexagesimal_dms, degrees_to_sexagesimal_hms},
+    LatLngHeight,
+};
 
+use super::ecliptic::{sun_radec, MJD_J2000};
 use super::hadec::HADec;
 use super::lmn::LMN;
+#[cfg(feature = "serde")]
+use super::{degrees_to_radians, radians_to_degrees};
+use super::{map_array, position_angle, vincenty_separation};
 
 /// A struct containing a Right Ascension and Declination. All units are in
 /// radians.
@@ -37,20 +46,6 @@ pub struct RADec {
     pub dec: f64,
 }
 
-#[cfg(feature = "serde")]
-fn radians_to_degrees<S: serde::Serializer>(num: &f64, s: S) -> Result<S::Ok, S::Error> {
-    s.serialize_f64(num.to_degrees())
-}
-
-#[cfg(feature = "serde")]
-fn degrees_to_radians<'de, D>(d: D) -> Result<f64, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let num: f64 = serde::Deserialize::deserialize(d)?;
-    Ok(num.to_radians())
-}
-
 impl RADec {
     /// Make a new [`RADec`] struct from values in radians.
     pub fn from_radians(ra: f64, dec: f64) -> RADec {
@@ -182,9 +177,172 @@ impl RADec {
         }
     }
 
+    /// Get the unit vector pointing at these coordinates. x points towards
+    /// (RA 0, Dec 0), y towards (RA 90°, Dec 0) and z towards the north
+    /// celestial pole.
+    pub fn to_unit_vector(self) -> [f64; 3] {
+        spherical_to_cartesian(self.ra, self.dec)
+    }
+
+    /// Get the coordinates that a vector points at. The vector doesn't need to
+    /// be normalised. The right ascension is in the range `[0, 2π)`. See
+    /// [`RADec::to_unit_vector`].
+    pub fn from_unit_vector(v: [f64; 3]) -> RADec {
+        let (ra, dec) = cartesian_to_spherical(v);
+        Self::from_radians(ra.rem_euclid(TAU), dec)
+    }
+
+    /// Get the position a fraction `t` of the way from these coordinates to
+    /// `other` along the great circle joining them (spherical linear
+    /// interpolation). `t` of 0 gives these coordinates and 1 gives `other`;
+    /// values outside `[0, 1]` extrapolate along the great circle.
+    ///
+    /// The great circle between antipodal points isn't unique, so this returns
+    /// NaNs in that case.
+    pub fn interpolate(&self, other: Self, t: f64) -> RADec {
+        let a = self.to_unit_vector();
+        let b = other.to_unit_vector();
+        let omega = self.separation(other);
+        let (wa, wb) = if omega < 1e-9 {
+            (1.0 - t, t)
+        } else {
+            let s_omega = omega.sin();
+            (
+                ((1.0 - t) * omega).sin() / s_omega,
+                (t * omega).sin() / s_omega,
+            )
+        };
+        RADec::from_unit_vector([
+            wa * a[0] + wb * b[0],
+            wa * a[1] + wb * b[1],
+            wa * a[2] + wb * b[2],
+        ])
+    }
+
+    /// Get `n` positions evenly spaced along the great circle between these
+    /// coordinates and `other`, not including either end. e.g. `n` of 1 gives
+    /// the midpoint. See [`RADec::interpolate`].
+    pub fn great_circle_path(&self, other: Self, n: usize) -> Vec<RADec> {
+        (1..=n)
+            .map(|i| self.interpolate(other, i as f64 / (n + 1) as f64))
+            .collect()
+    }
+
+    /// Compare these coordinates with `other`, by declination and then right
+    /// ascension, using [`f64::total_cmp`]. This is a total ordering, so it can
+    /// be used to sort positions (e.g. with [`slice::sort_by`]), but
+    /// positions that are the same point on the sky (e.g. with RAs differing
+    /// by 2π) don't necessarily compare as equal.
+    pub fn total_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.dec
+            .total_cmp(&other.dec)
+            .then(self.ra.total_cmp(&other.ra))
+    }
+
     /// Calculate the distance between two sets of coordinates \[radians\].
     pub fn separation(&self, b: Self) -> f64 {
-        eraSeps(self.ra, self.dec, b.ra, b.dec)
+        vincenty_separation(self.ra, self.dec, b.ra, b.dec)
+    }
+
+    /// Calculate the position angle of `b` from these coordinates, measured
+    /// from north through east, in the range `[0, 2π)` \[radians\].
+    pub fn position_angle(&self, b: Self) -> f64 {
+        position_angle(self.ra, self.dec, b.ra, b.dec)
+    }
+
+    /// Calculate the distance between these (J2000) coordinates and the Sun at
+    /// `epoch` \[radians\]. See [`super::ecliptic::sun_radec`].
+    pub fn separation_from_sun(&self, epoch: Epoch) -> f64 {
+        self.separation(sun_radec(epoch))
+    }
+
+    /// Propagate these (J2000) catalogue coordinates to `epoch` by applying
+    /// space motion.
+    ///
+    /// Like ERFA's `eraPmsafe`, `pm_ra` is dRA/dt (i.e. not multiplied by
+    /// cos(dec)) and `pm_dec` is dDec/dt \[radians/year\], `parallax` is in
+    /// \[arcseconds\] and `rv` is the radial velocity \[km/s, positive when
+    /// receding\]. A zero (or negative) parallax is treated as a very distant
+    /// star, so that the radial velocity has no effect.
+    ///
+    /// The star is moved in a straight line; unlike `eraPmsafe`, the (tiny)
+    /// light-time correction is ignored.
+    pub fn at_epoch(self, pm_ra: f64, pm_dec: f64, parallax: f64, rv: f64, epoch: Epoch) -> RADec {
+        // Astronomical unit [km] and days per Julian year.
+        const AU_KM: f64 = 149_597_870.7;
+        const DAYS_PER_YEAR: f64 = 365.25;
+        // ERFA's minimum parallax [arcseconds].
+        const MIN_PARALLAX: f64 = 1e-7;
+
+        // Distance [au] and velocities [au/day, radians/day].
+        let distance = 1.0 / (parallax.max(MIN_PARALLAX) / 3600.0).to_radians();
+        let radial_velocity = rv * DAYSEC / AU_KM;
+        let ra_rate = pm_ra / DAYS_PER_YEAR;
+        let dec_rate = pm_dec / DAYS_PER_YEAR;
+
+        let (s_ra, c_ra) = self.ra.sin_cos();
+        let (s_dec, c_dec) = self.dec.sin_cos();
+        let unit = [c_dec * c_ra, c_dec * s_ra, s_dec];
+        let east = [-s_ra, c_ra, 0.0];
+        let north = [-s_dec * c_ra, -s_dec * s_ra, c_dec];
+
+        let days = epoch.to_mjd_tt_days() - MJD_J2000;
+        let mut position = [0.0; 3];
+        for (i, p) in position.iter_mut().enumerate() {
+            let velocity = radial_velocity * unit[i]
+                + distance * (ra_rate * c_dec * east[i] + dec_rate * north[i]);
+            *p = distance * unit[i] + velocity * days;
+        }
+        let (ra, dec) = cartesian_to_spherical(position);
+        RADec::from_radians(ra.rem_euclid(TAU), dec)
+    }
+
+    /// The first time at or after `date` that these coordinates cross the
+    /// meridian of `site`.
+    ///
+    /// Precession, refraction and DUT1 are ignored, so the result can be
+    /// wrong by a few seconds.
+    pub fn transit_time(self, site: LatLngHeight, date: Epoch) -> Epoch {
+        self.next_hour_angle(site, date, 0.0)
+    }
+
+    /// The first time at or after `date` that these coordinates rise above
+    /// `min_elevation_rad` at `site`, and the time that they then set below
+    /// it. [`None`] is returned if the coordinates never rise above, or never
+    /// set below, `min_elevation_rad`.
+    ///
+    /// Precession, refraction and DUT1 are ignored, so the results can be
+    /// wrong by a few seconds.
+    pub fn rise_set_times(
+        self,
+        site: LatLngHeight,
+        min_elevation_rad: f64,
+        date: Epoch,
+    ) -> Option<(Epoch, Epoch)> {
+        let (s_lat, c_lat) = site.latitude_rad.sin_cos();
+        let (s_dec, c_dec) = self.dec.sin_cos();
+        // The hour angle at which the elevation is `min_elevation_rad`.
+        let c_ha = (min_elevation_rad.sin() - s_lat * s_dec) / (c_lat * c_dec);
+        if !(-1.0..=1.0).contains(&c_ha) {
+            return None;
+        }
+        let ha = c_ha.acos();
+        let rise = self.next_hour_angle(site, date, -ha);
+        let set = self.next_hour_angle(site, rise, ha);
+        Some((rise, set))
+    }
+
+    /// The first time at or after `date` that these coordinates are at the
+    /// hour angle `ha_rad` at `site`.
+    fn next_hour_angle(self, site: LatLngHeight, date: Epoch, ha_rad: f64) -> Epoch {
+        let lmst = get_lmst(site.longitude_rad, date, Duration::from_seconds(0.0));
+        let d_ha = (ha_rad - (lmst - self.ra)).rem_euclid(TAU);
+        let estimate = date + Duration::from_seconds(d_ha / EARTH_ROTATION_RAD_S);
+
+        // The sidereal rate is approximate; correct the estimate with its LMST.
+        let lmst = get_lmst(site.longitude_rad, estimate, Duration::from_seconds(0.0));
+        let correction = (ha_rad - (lmst - self.ra) + PI).rem_euclid(TAU) - PI;
+        estimate + Duration::from_seconds(correction / EARTH_ROTATION_RAD_S)
     }
 
     /// Given an [`mwalib::MetafitsContext`], make an [`Option<RADec>`] from the
@@ -223,6 +381,36 @@ impl RADec {
     }
 }
 
+/// Get the [LMN] direction cosines of many [`RADec`]s (e.g. a source
+/// catalogue) relative to a phase centre. See [`RADec::to_lmn`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn to_lmn_array(radecs: ArrayView1<RADec>, phase_centre: RADec) -> Array1<LMN> {
+    map_array(radecs, |radec| radec.to_lmn(phase_centre))
+}
+
+/// Convert many [`RADec`]s to [`HADec`]s, given a local sidereal time. See
+/// [`RADec::to_hadec`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn to_hadec_array(radecs: ArrayView1<RADec>, lst_rad: f64) -> Array1<HADec> {
+    map_array(radecs, |radec| radec.to_hadec(lst_rad))
+}
+
+/// Convert `radec` to [`HADec`]s at each of `epochs` (UTC), as seen from
+/// `site`. The local sidereal time of each epoch is the mean sidereal time from
+/// [`get_lmst`], with `dut1` (UT1 - UTC); this is the convention used
+/// throughout marlu, e.g. by [`crate::precession::precess_time`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn to_hadecs_at_epochs(
+    radec: RADec,
+    epochs: ArrayView1<Epoch>,
+    site: LatLngHeight,
+    dut1: Duration,
+) -> Array1<HADec> {
+    map_array(epochs, |epoch| {
+        radec.to_hadec(get_lmst(site.longitude_rad, epoch, dut1))
+    })
+}
+
 impl std::fmt::Display for RADec {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
@@ -292,6 +480,133 @@ mod tests {
         assert_abs_diff_eq!(lmn, expected, epsilon = 1e-10);
     }
 
+    #[test]
+    fn test_separation_and_position_angle() {
+        let radec = RADec::from_degrees(60.0, -27.0);
+        assert_abs_diff_eq!(
+            radec
+                .separation(RADec::from_degrees(60.0, -26.0))
+                .to_degrees(),
+            1.0,
+            epsilon = 1e-10
+        );
+        // tiny separations are still accurate.
+        let tiny = RADec::from_radians(radec.ra, radec.dec + 1e-12);
+        assert_abs_diff_eq!(radec.separation(tiny), 1e-12, epsilon = 1e-15);
+
+        assert_abs_diff_eq!(
+            radec.position_angle(RADec::from_degrees(60.0, -26.0)),
+            0.0,
+            epsilon = 1e-10
+        );
+        assert_abs_diff_eq!(
+            radec
+                .position_angle(RADec::from_degrees(60.1, -27.0))
+                .to_degrees(),
+            90.0,
+            epsilon = 0.1
+        );
+        assert_abs_diff_eq!(
+            radec
+                .position_angle(RADec::from_degrees(60.0, -28.0))
+                .to_degrees(),
+            180.0,
+            epsilon = 1e-10
+        );
+        assert_abs_diff_eq!(
+            radec
+                .position_angle(RADec::from_degrees(59.9, -27.0))
+                .to_degrees(),
+            270.0,
+            epsilon = 0.1
+        );
+    }
+
+    #[test]
+    fn test_to_lmn_array() {
+        let phase_centre = RADec::from_degrees(60.0, -27.0);
+        let radecs = Array1::from_shape_fn(100, |i| {
+            RADec::from_degrees(55.0 + i as f64 * 0.1, -32.0 + i as f64 * 0.1)
+        });
+        let lmns = to_lmn_array(radecs.view(), phase_centre);
+        for (radec, lmn) in radecs.iter().zip(lmns.iter()) {
+            assert_abs_diff_eq!(*lmn, radec.to_lmn(phase_centre));
+        }
+
+        let lst = 1.0;
+        let hadecs = to_hadec_array(radecs.view(), lst);
+        let round_trip = crate::pos::hadec::to_radec_array(hadecs.view(), lst);
+        for (radec, result) in radecs.iter().zip(round_trip.iter()) {
+            assert_abs_diff_eq!(*radec, *result, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_rise_set_transit() {
+        let site = LatLngHeight::mwa();
+        let date = Epoch::from_gregorian_utc(2022, 6, 1, 12, 0, 0, 0);
+        let dut1 = Duration::from_seconds(0.0);
+        let radec = RADec::from_degrees(60.0, -30.0);
+
+        let transit = radec.transit_time(site, date);
+        assert!(transit >= date && transit - date < Duration::from_seconds(DAYSEC));
+        let lmst = get_lmst(site.longitude_rad, transit, dut1);
+        assert_abs_diff_eq!(
+            (lmst - radec.ra + PI).rem_euclid(TAU) - PI,
+            0.0,
+            epsilon = 1e-7
+        );
+
+        let min_el = 30_f64.to_radians();
+        let (rise, set) = radec.rise_set_times(site, min_el, date).unwrap();
+        assert!(rise >= date && rise < set);
+        assert!(rise < transit && transit < set);
+        for time in [rise, set] {
+            let lmst = get_lmst(site.longitude_rad, time, dut1);
+            let azel = radec.to_hadec(lmst).to_azel(site.latitude_rad);
+            assert_abs_diff_eq!(azel.el, min_el, epsilon = 1e-6);
+        }
+
+        // Circumpolar, and never rises.
+        assert!(RADec::from_degrees(60.0, -80.0)
+            .rise_set_times(site, 0.0, date)
+            .is_none());
+        assert!(RADec::from_degrees(60.0, 70.0)
+            .rise_set_times(site, 0.0, date)
+            .is_none());
+    }
+
+    #[test]
+    fn test_at_epoch() {
+        let mas_to_rad = |mas: f64| (mas / 3.6e6).to_radians();
+
+        // Barnard's star.
+        let radec = RADec::from_degrees(269.452_076_25, 4.693_364_17);
+        let pm_ra = mas_to_rad(-802.803) / radec.dec.cos();
+        let pm_dec = mas_to_rad(10362.542);
+        let epoch = Epoch::from_gregorian_utc(2020, 1, 1, 12, 0, 0, 0);
+        let moved = radec.at_epoch(pm_ra, pm_dec, 0.548_31, -110.6, epoch);
+        // 20 years of proper motion, plus ~0.26" of perspective acceleration
+        // as the star approaches.
+        assert_abs_diff_eq!(
+            (moved.dec - radec.dec).to_degrees() * 3600.0,
+            207.51,
+            epsilon = 0.01
+        );
+        assert_abs_diff_eq!(
+            (moved.ra - radec.ra).to_degrees() * 3600.0 * radec.dec.cos(),
+            -16.08,
+            epsilon = 0.01
+        );
+
+        // No motion.
+        let moved = radec.at_epoch(0.0, 0.0, 0.0, 0.0, epoch);
+        assert_abs_diff_eq!(moved, radec, epsilon = 1e-12);
+        // Without a parallax, the radial velocity does nothing.
+        let moved = radec.at_epoch(0.0, 0.0, 0.0, 1000.0, epoch);
+        assert_abs_diff_eq!(moved, radec, epsilon = 1e-12);
+    }
+
     #[test]
     fn test_weighted_pos() {
         // Simple case: both components have a weight of 1.0.
@@ -427,6 +742,82 @@ mod tests {
         assert!(RADec::weighted_average(&radecs, &vec![1.0; radecs.len()]).is_none());
     }
 
+    #[test]
+    fn test_unit_vectors() {
+        let x = RADec::from_degrees(0.0, 0.0).to_unit_vector();
+        let y = RADec::from_degrees(90.0, 0.0).to_unit_vector();
+        assert_abs_diff_eq!(
+            RADec::from_unit_vector(crate::pos::cross(x, y)),
+            RADec::from_degrees(0.0, 90.0),
+            epsilon = 1e-12
+        );
+
+        let a = RADec::from_degrees(350.0, -27.0);
+        let b = RADec::from_degrees(10.0, -40.0);
+        assert_abs_diff_eq!(
+            RADec::from_unit_vector(a.to_unit_vector()),
+            a,
+            epsilon = 1e-12
+        );
+        // Vectors don't need to be normalised.
+        let v = a.to_unit_vector().map(|c| c * 3.0);
+        assert_abs_diff_eq!(RADec::from_unit_vector(v), a, epsilon = 1e-12);
+        assert_abs_diff_eq!(
+            crate::pos::dot(a.to_unit_vector(), b.to_unit_vector()).acos(),
+            a.separation(b),
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let a = RADec::from_degrees(350.0, 0.0);
+        let b = RADec::from_degrees(20.0, 0.0);
+        assert_abs_diff_eq!(a.interpolate(b, 0.0), a, epsilon = 1e-12);
+        assert_abs_diff_eq!(a.interpolate(b, 1.0), b, epsilon = 1e-12);
+        // Interpolation takes the short way around the RA branch cut.
+        assert_abs_diff_eq!(
+            a.interpolate(b, 0.5),
+            RADec::from_degrees(5.0, 0.0),
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(a.interpolate(a, 0.3), a, epsilon = 1e-12);
+
+        let a = RADec::from_degrees(10.0, -27.0);
+        let b = RADec::from_degrees(60.0, -70.0);
+        let path = a.great_circle_path(b, 4);
+        assert_eq!(path.len(), 4);
+        // The positions are evenly spaced along the great circle.
+        let step = a.separation(b) / 5.0;
+        assert_abs_diff_eq!(a.separation(path[0]), step, epsilon = 1e-12);
+        for pair in path.windows(2) {
+            assert_abs_diff_eq!(pair[0].separation(pair[1]), step, epsilon = 1e-12);
+        }
+        assert_abs_diff_eq!(path[3].separation(b), step, epsilon = 1e-12);
+        assert!(a.great_circle_path(b, 0).is_empty());
+    }
+
+    #[test]
+    fn test_total_cmp() {
+        let mut radecs = vec![
+            RADec::from_degrees(10.0, -27.0),
+            RADec::from_degrees(5.0, -27.0),
+            RADec::from_degrees(0.0, 45.0),
+            RADec::from_degrees(180.0, -80.0),
+        ];
+        radecs.sort_by(RADec::total_cmp);
+        assert_eq!(
+            radecs,
+            vec![
+                RADec::from_degrees(180.0, -80.0),
+                RADec::from_degrees(5.0, -27.0),
+                RADec::from_degrees(10.0, -27.0),
+                RADec::from_degrees(0.0, 45.0),
+            ]
+        );
+        assert_eq!(radecs[0].total_cmp(&radecs[0]), std::cmp::Ordering::Equal);
+    }
+
     #[test]
     fn test_display_radec() {
         let radec = RADec { ra: 0.0, dec: 0.0 };
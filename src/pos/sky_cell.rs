@@ -0,0 +1,171 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Binning and deduplication of sky positions.
+//!
+//! [`RADec`]s contain raw floats, so they can't be used as keys of maps or be
+//! compared for equality in a meaningful way. A [`SkyCell`] is a hashable,
+//! ordered key for a region of the sky that can be used instead.
+
+use std::{
+    collections::HashMap,
+    f64::consts::{FRAC_PI_2, PI, TAU},
+};
+
+use super::radec::RADec;
+
+/// A cell of a grid on the sky; see [`SkyCell::from_radec`]. Cells have
+/// roughly the same area; they are arranged in declination bands, and the
+/// number of cells in each band is proportional to the cosine of its
+/// declination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SkyCell {
+    /// The index of the declination band, starting at the south pole.
+    pub dec_band: u32,
+
+    /// The index of the cell in the band, starting at RA 0.
+    pub ra_index: u32,
+}
+
+impl SkyCell {
+    /// Get the cell containing `radec` in a grid with cells of (about)
+    /// `cell_size_rad` on a side.
+    pub fn from_radec(radec: RADec, cell_size_rad: f64) -> SkyCell {
+        let num_bands = num_dec_bands(cell_size_rad);
+        let dec_band =
+            (((radec.dec + FRAC_PI_2) / cell_size_rad).floor().max(0.0) as u32).min(num_bands - 1);
+        let num_cells = num_ra_cells(dec_band, cell_size_rad);
+        let ra_index =
+            ((radec.ra.rem_euclid(TAU) / TAU * num_cells as f64) as u32).min(num_cells - 1);
+        SkyCell { dec_band, ra_index }
+    }
+
+    /// Get the centre of this cell in a grid with cells of (about)
+    /// `cell_size_rad` on a side.
+    pub fn centre(self, cell_size_rad: f64) -> RADec {
+        let num_cells = num_ra_cells(self.dec_band, cell_size_rad);
+        RADec::from_radians(
+            (self.ra_index as f64 + 0.5) / num_cells as f64 * TAU,
+            band_centre_dec(self.dec_band, cell_size_rad),
+        )
+    }
+}
+
+fn num_dec_bands(cell_size_rad: f64) -> u32 {
+    ((PI / cell_size_rad).ceil() as u32).max(1)
+}
+
+fn band_centre_dec(dec_band: u32, cell_size_rad: f64) -> f64 {
+    (-FRAC_PI_2 + (dec_band as f64 + 0.5) * cell_size_rad).min(FRAC_PI_2)
+}
+
+fn num_ra_cells(dec_band: u32, cell_size_rad: f64) -> u32 {
+    let circumference = TAU * band_centre_dec(dec_band, cell_size_rad).cos();
+    ((circumference / cell_size_rad).ceil() as u32).max(1)
+}
+
+/// Group the indices of `radecs` by the [`SkyCell`] that contains them, in a
+/// grid with cells of (about) `cell_size_rad` on a side.
+pub fn bin_radecs(radecs: &[RADec], cell_size_rad: f64) -> HashMap<SkyCell, Vec<usize>> {
+    let mut bins: HashMap<SkyCell, Vec<usize>> = HashMap::new();
+    for (i, &radec) in radecs.iter().enumerate() {
+        bins.entry(SkyCell::from_radec(radec, cell_size_rad))
+            .or_default()
+            .push(i);
+    }
+    bins
+}
+
+/// Get the indices of `radecs` that remain after removing positions within
+/// `tolerance_rad` of an earlier position, e.g. to remove duplicates from a
+/// source catalogue. The indices are in ascending order, so they can be used
+/// to deduplicate data associated with the positions.
+///
+/// Note that this isn't transitive; if B is close to A and C is close to B
+/// (but not A), then only B is removed.
+pub fn dedup_radecs(radecs: &[RADec], tolerance_rad: f64) -> Vec<usize> {
+    // Only positions in neighbouring declination bands can be within the
+    // tolerance of each other.
+    let band = |radec: RADec| (radec.dec / tolerance_rad).floor() as i64;
+    let mut kept_by_band: HashMap<i64, Vec<usize>> = HashMap::new();
+    let mut kept = vec![];
+    for (i, &radec) in radecs.iter().enumerate() {
+        let b = band(radec);
+        let duplicate = (b - 1..=b + 1)
+            .filter_map(|b| kept_by_band.get(&b))
+            .flatten()
+            .any(|&j| radecs[j].separation(radec) <= tolerance_rad);
+        if !duplicate {
+            kept_by_band.entry(b).or_default().push(i);
+            kept.push(i);
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_sky_cell() {
+        let cell_size = 1_f64.to_radians();
+        let radec = RADec::from_degrees(10.2, -27.3);
+        let cell = SkyCell::from_radec(radec, cell_size);
+        assert_eq!(cell.dec_band, 62);
+        // The centre is in the same cell, and close to the position.
+        let centre = cell.centre(cell_size);
+        assert_eq!(SkyCell::from_radec(centre, cell_size), cell);
+        assert!(centre.separation(radec) < cell_size);
+
+        // Negative RAs are wrapped.
+        assert_eq!(
+            SkyCell::from_radec(RADec::from_degrees(-0.1, 0.0), cell_size),
+            SkyCell::from_radec(RADec::from_degrees(359.9, 0.0), cell_size)
+        );
+
+        // The last band contains the pole, and has few cells.
+        let pole = SkyCell::from_radec(RADec::from_degrees(123.0, 90.0), cell_size);
+        assert_eq!(pole.dec_band, 179);
+        assert_eq!(pole.ra_index, 1);
+        assert_abs_diff_eq!(
+            pole.centre(cell_size).dec,
+            89.5_f64.to_radians(),
+            epsilon = 1e-12
+        );
+
+        let radecs = [
+            RADec::from_degrees(10.2, -27.3),
+            RADec::from_degrees(50.0, 10.0),
+            RADec::from_degrees(10.3, -27.4),
+        ];
+        let bins = bin_radecs(&radecs, cell_size);
+        assert_eq!(bins.len(), 2);
+        assert_eq!(bins[&cell], vec![0, 2]);
+    }
+
+    #[test]
+    fn test_dedup_radecs() {
+        let arcsec = 1_f64.to_radians() / 3600.0;
+        let radecs = [
+            RADec::from_degrees(10.0, -27.0),
+            RADec::from_degrees(50.0, 10.0),
+            RADec::from_radians(10_f64.to_radians() + 0.5 * arcsec, -27_f64.to_radians()),
+            // Either side of RA 0.
+            RADec::from_radians(0.2 * arcsec, 0.0),
+            RADec::from_radians(TAU - 0.2 * arcsec, 0.0),
+            // Either side of a declination band.
+            RADec::from_radians(1.0, -0.1 * arcsec),
+            RADec::from_radians(1.0, 0.1 * arcsec),
+            RADec::from_degrees(50.0, 10.0),
+        ];
+        assert_eq!(dedup_radecs(&radecs, arcsec), vec![0, 1, 3, 5]);
+        assert_eq!(
+            dedup_radecs(&radecs, 1e-3 * arcsec),
+            vec![0, 1, 2, 3, 4, 5, 6]
+        );
+        assert!(dedup_radecs(&[], arcsec).is_empty());
+    }
+}
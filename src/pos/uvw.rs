@@ -10,6 +10,7 @@ use super::xyz::XyzGeodetic;
 /// The (u,v,w) coordinates of a baseline. All units are in terms of wavelength,
 /// with units of metres.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::upper_case_acronyms)]
 pub struct UVW {
     /// u coordinate \[meters\]
@@ -0,0 +1,240 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Doppler corrections for spectral-line work: the velocity of an observer
+//! relative to the barycentric and LSRK frames, and conversions between
+//! frequencies and velocities.
+
+use erfa::{
+    aliases::eraEpv00,
+    constants::{ERFA_AULT, ERFA_DJM0},
+};
+use hifitime::{Duration, Epoch};
+
+use super::{dot, earth::LatLngHeight, precession::get_lmst, radec::RADec};
+use crate::constants::{DAYSEC, EARTH_ROTATION_RAD_S, VEL_C};
+
+/// The speed of the Sun relative to the kinematic local standard of rest
+/// \[m/s\].
+const SOLAR_MOTION_M_S: f64 = 20e3;
+
+/// The (J2000) direction of the Sun's motion relative to the kinematic local
+/// standard of rest: 18h, +30° in B1900 coordinates, precessed to J2000
+/// \[degrees\].
+const SOLAR_APEX_DEG: (f64, f64) = (270.959_54, 30.004_67);
+
+/// A frame of rest for velocities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VelocityFrame {
+    /// The observer's frame.
+    Topocentric,
+
+    /// The solar system barycentre.
+    Barycentric,
+
+    /// The kinematic local standard of rest; the Sun moves at 20 km/s towards
+    /// (B1900) RA 18h, Dec +30° relative to it.
+    Lsrk,
+}
+
+/// The definition used to convert between frequencies and velocities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VelocityConvention {
+    /// `v = c (1 - f / f0)`
+    Radio,
+
+    /// `v = c (f0 / f - 1)`
+    Optical,
+
+    /// `v = c (f0² - f²) / (f0² + f²)`
+    Relativistic,
+}
+
+/// The velocity \[m/s\] of an observer at `site`, relative to `frame`, towards
+/// `radec` at `epoch` (UTC). `dut1` is UT1 - UTC. Positive velocities mean the
+/// observer is approaching the source, i.e. the source appears blueshifted.
+///
+/// The Earth's barycentric velocity is from ERFA's `eraEpv00`, and the
+/// rotation of the Earth adds up to ~0.46 km/s. Precession of the site between
+/// J2000 and `epoch` is ignored; this affects the result by much less than
+/// 1 m/s.
+pub fn observer_velocity_towards(
+    radec: RADec,
+    site: LatLngHeight,
+    epoch: Epoch,
+    dut1: Duration,
+    frame: VelocityFrame,
+) -> f64 {
+    let source = radec.to_unit_vector();
+    match frame {
+        VelocityFrame::Topocentric => 0.0,
+        VelocityFrame::Barycentric => {
+            let (_, _, pvb) = eraEpv00(ERFA_DJM0, epoch.to_mjd_tt_days());
+            // AU/day to m/s.
+            let au_day_to_m_s = ERFA_AULT * VEL_C / DAYSEC;
+            let orbital = dot(pvb[1], source) * au_day_to_m_s;
+
+            // The observer moves east with the rotating Earth.
+            let xyz = site.to_geocentric_wgs84();
+            let speed = EARTH_ROTATION_RAD_S * xyz.x.hypot(xyz.y);
+            let lmst = get_lmst(site.longitude_rad, epoch, dut1);
+            let (s_lmst, c_lmst) = lmst.sin_cos();
+            let diurnal = speed * dot([-s_lmst, c_lmst, 0.0], source);
+
+            orbital + diurnal
+        }
+        VelocityFrame::Lsrk => {
+            let apex = RADec::from_degrees(SOLAR_APEX_DEG.0, SOLAR_APEX_DEG.1);
+            observer_velocity_towards(radec, site, epoch, dut1, VelocityFrame::Barycentric)
+                + SOLAR_MOTION_M_S * dot(apex.to_unit_vector(), source)
+        }
+    }
+}
+
+/// Convert a frequency observed by an observer moving at `velocity_m_s`
+/// towards the source (e.g. from [`observer_velocity_towards`]) to the
+/// frequency that would be observed at rest in that frame.
+pub fn frequency_in_frame(freq_hz: f64, velocity_m_s: f64) -> f64 {
+    let beta = velocity_m_s / VEL_C;
+    freq_hz * ((1.0 - beta) / (1.0 + beta)).sqrt()
+}
+
+/// Convert a frequency to a velocity \[m/s\] relative to a line with rest
+/// frequency `rest_freq_hz`. Positive velocities are receding.
+pub fn freq_to_velocity(freq_hz: f64, rest_freq_hz: f64, convention: VelocityConvention) -> f64 {
+    match convention {
+        VelocityConvention::Radio => VEL_C * (1.0 - freq_hz / rest_freq_hz),
+        VelocityConvention::Optical => VEL_C * (rest_freq_hz / freq_hz - 1.0),
+        VelocityConvention::Relativistic => {
+            let f0_sq = rest_freq_hz * rest_freq_hz;
+            let f_sq = freq_hz * freq_hz;
+            VEL_C * (f0_sq - f_sq) / (f0_sq + f_sq)
+        }
+    }
+}
+
+/// Convert a velocity \[m/s\] relative to a line with rest frequency
+/// `rest_freq_hz` to a frequency. This is the inverse of
+/// [`freq_to_velocity`].
+pub fn velocity_to_freq(
+    velocity_m_s: f64,
+    rest_freq_hz: f64,
+    convention: VelocityConvention,
+) -> f64 {
+    let beta = velocity_m_s / VEL_C;
+    match convention {
+        VelocityConvention::Radio => rest_freq_hz * (1.0 - beta),
+        VelocityConvention::Optical => rest_freq_hz / (1.0 + beta),
+        VelocityConvention::Relativistic => rest_freq_hz * ((1.0 - beta) / (1.0 + beta)).sqrt(),
+    }
+}
+
+/// Convert the topocentric frequencies of channels to velocities \[m/s\] in
+/// `frame` relative to a line with rest frequency `rest_freq_hz`. See
+/// [`observer_velocity_towards`] for the other arguments.
+#[allow(clippy::too_many_arguments)]
+pub fn channel_velocities(
+    freqs_hz: &[f64],
+    rest_freq_hz: f64,
+    convention: VelocityConvention,
+    radec: RADec,
+    site: LatLngHeight,
+    epoch: Epoch,
+    dut1: Duration,
+    frame: VelocityFrame,
+) -> Vec<f64> {
+    let velocity = observer_velocity_towards(radec, site, epoch, dut1, frame);
+    freqs_hz
+        .iter()
+        .map(|&f| freq_to_velocity(frequency_in_frame(f, velocity), rest_freq_hz, convention))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pos::ecliptic::sun_radec, Ecliptic};
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_observer_velocity_towards() {
+        let site = LatLngHeight::mwa();
+        let epoch = Epoch::from_gregorian_utc(2022, 3, 20, 12, 0, 0, 0);
+        let dut1 = Duration::from_seconds(0.0);
+
+        // The Earth moves towards the ecliptic longitude 90° behind the Sun at
+        // ~29.8 km/s.
+        let sun = Ecliptic::from_radec(sun_radec(epoch));
+        let apex = Ecliptic::from_radians(sun.lon - 90_f64.to_radians(), 0.0).to_radec();
+        let v = observer_velocity_towards(apex, site, epoch, dut1, VelocityFrame::Barycentric);
+        assert_abs_diff_eq!(v, 29.8e3, epsilon = 1e3);
+        // The velocity towards the opposite direction is opposite.
+        let anti_apex = RADec::from_unit_vector(apex.to_unit_vector().map(|c| -c));
+        let v2 =
+            observer_velocity_towards(anti_apex, site, epoch, dut1, VelocityFrame::Barycentric);
+        assert_abs_diff_eq!(v + v2, 0.0, epsilon = 1e-6);
+
+        // Towards the ecliptic pole, only the (small) diurnal term is left.
+        let pole = Ecliptic::from_degrees(0.0, 90.0).to_radec();
+        let v = observer_velocity_towards(pole, site, epoch, dut1, VelocityFrame::Barycentric);
+        assert!(v.abs() < 500.0, "{v}");
+
+        let apex = RADec::from_degrees(SOLAR_APEX_DEG.0, SOLAR_APEX_DEG.1);
+        assert_abs_diff_eq!(
+            observer_velocity_towards(apex, site, epoch, dut1, VelocityFrame::Lsrk)
+                - observer_velocity_towards(apex, site, epoch, dut1, VelocityFrame::Barycentric),
+            20e3,
+            epsilon = 1e-6
+        );
+        assert_abs_diff_eq!(
+            observer_velocity_towards(apex, site, epoch, dut1, VelocityFrame::Topocentric),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_freq_velocity_conversions() {
+        // The 21 cm line.
+        let rest_freq_hz = 1420.405751768e6;
+        let freq_hz = 1420.0e6;
+        for convention in [
+            VelocityConvention::Radio,
+            VelocityConvention::Optical,
+            VelocityConvention::Relativistic,
+        ] {
+            let v = freq_to_velocity(freq_hz, rest_freq_hz, convention);
+            assert!(v > 0.0);
+            assert_abs_diff_eq!(
+                velocity_to_freq(v, rest_freq_hz, convention),
+                freq_hz,
+                epsilon = 1e-6
+            );
+        }
+        assert_abs_diff_eq!(
+            freq_to_velocity(freq_hz, rest_freq_hz, VelocityConvention::Radio),
+            85_638.4,
+            epsilon = 0.1
+        );
+        // An observer receding from the source at the relativistic velocity
+        // of the line sees it at its rest frequency in the source's frame.
+        let v = freq_to_velocity(freq_hz, rest_freq_hz, VelocityConvention::Relativistic);
+        assert_abs_diff_eq!(
+            frequency_in_frame(freq_hz, -v),
+            rest_freq_hz,
+            epsilon = 1e-6
+        );
+
+        let velocities = channel_velocities(
+            &[rest_freq_hz],
+            rest_freq_hz,
+            VelocityConvention::Radio,
+            RADec::from_degrees(0.0, -27.0),
+            LatLngHeight::mwa(),
+            Epoch::from_gregorian_utc(2022, 3, 20, 12, 0, 0, 0),
+            Duration::from_seconds(0.0),
+            VelocityFrame::Topocentric,
+        );
+        assert_abs_diff_eq!(velocities[0], 0.0, epsilon = 1e-9);
+    }
+}
@@ -15,6 +15,8 @@
 // TODO: Account for northing and eastings. Australia drifts by ~7cm/year, and
 // the ellipsoid model probably need to be changed too!
 
+use std::f64::consts::PI;
+
 use erfa::Ellipsoid;
 
 use crate::{constants::MWA_LAT_RAD, HADec, LatLngHeight, ENH, UVW};
@@ -26,6 +28,7 @@ use crate::{constants::MWA_LAT_RAD, HADec, LatLngHeight, ENH, UVW};
 /// Synthesis in Radio Astronomy, Third Edition, Section 4: Geometrical
 /// Relationships, Polarimetry, and the Measurement Equation.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct XyzGeodetic {
     /// x-coordinate \[meters\]
     pub x: f64,
@@ -36,6 +39,38 @@ pub struct XyzGeodetic {
 }
 
 impl XyzGeodetic {
+    /// The length of these coordinates as a vector, e.g. the length of a
+    /// baseline \[metres\].
+    pub fn length(self) -> f64 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+
+    /// For a baseline, the largest |w| \[metres\] when tracking a phase centre
+    /// at declination `dec_rad` over the hour angles from `ha_min_rad` to
+    /// `ha_max_rad`.
+    pub fn max_abs_w(self, dec_rad: f64, ha_min_rad: f64, ha_max_rad: f64) -> f64 {
+        // w = a cos(HA) + b sin(HA) + c, which is extremal at HA = atan2(b, a)
+        // (+ kπ), or at the ends of the range.
+        let (s_dec, c_dec) = dec_rad.sin_cos();
+        let a = c_dec * self.x;
+        let b = -c_dec * self.y;
+        let c = s_dec * self.z;
+        let abs_w = |ha: f64| {
+            let (s_ha, c_ha) = ha.sin_cos();
+            (a * c_ha + b * s_ha + c).abs()
+        };
+
+        let mut max = abs_w(ha_min_rad).max(abs_w(ha_max_rad));
+        let turning_point = b.atan2(a);
+        let first = ((ha_min_rad - turning_point) / PI).ceil() as i64;
+        let last = ((ha_max_rad - turning_point) / PI).floor() as i64;
+        // Only two turning points are distinct.
+        for k in first..=last.min(first + 1) {
+            max = max.max(abs_w(turning_point + k as f64 * PI));
+        }
+        max
+    }
+
     /// Convert [`XyzGeodetic`] coordinates at a latitude to [`ENH`]
     /// coordinates.
     pub fn to_enh(self, latitude: f64) -> ENH {
@@ -61,6 +96,12 @@ impl XyzGeodetic {
         self.to_enh(MWA_LAT_RAD)
     }
 
+    /// Convert [`XyzGeodetic`] coordinates of an array centred at `site` to
+    /// [`ENH`] coordinates.
+    pub fn to_enh_for_site(self, site: LatLngHeight) -> ENH {
+        self.to_enh(site.latitude_rad)
+    }
+
     /// Convert a [`XyzGeodetic`] coordinate to [`XyzGeocentric`].
     pub fn to_geocentric(self, earth_pos: LatLngHeight) -> XyzGeocentric {
         let (sin_longitude, cos_longitude) = earth_pos.longitude_rad.sin_cos();
@@ -169,6 +210,108 @@ pub fn xyzs_to_cross_uvws(xyzs: &[XyzGeodetic], phase_centre: HADec) -> Vec<UVW>
     bl_uvws
 }
 
+/// The geometry of a baseline, as seen from the array.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BaselineGeometry {
+    /// The length of the baseline \[metres\]
+    pub length: f64,
+    /// The length of the baseline projected onto the east-west axis \[metres\]
+    pub east_west: f64,
+    /// The length of the baseline projected onto the north-south axis
+    /// \[metres\]
+    pub north_south: f64,
+}
+
+/// Get the [`BaselineGeometry`] of each cross-correlation baseline of
+/// [`XyzGeodetic`] tile coordinates for an array at `latitude_rad`. Baselines
+/// are in the same order as [`xyzs_to_cross_uvws`].
+pub fn xyzs_to_cross_baseline_geometries(
+    xyzs: &[XyzGeodetic],
+    latitude_rad: f64,
+) -> Vec<BaselineGeometry> {
+    let (s_lat, c_lat) = latitude_rad.sin_cos();
+    let num_tiles = xyzs.len();
+    let mut geometries = Vec::with_capacity((num_tiles * num_tiles.saturating_sub(1)) / 2);
+    for (i, t1) in xyzs.iter().enumerate() {
+        for t2 in xyzs.iter().skip(i + 1) {
+            let baseline = *t1 - *t2;
+            let enh = baseline.to_enh_inner(s_lat, c_lat);
+            geometries.push(BaselineGeometry {
+                length: baseline.length(),
+                east_west: enh.e.abs(),
+                north_south: enh.n.abs(),
+            });
+        }
+    }
+    geometries
+}
+
+/// Get the largest |w| \[metres\] of each cross-correlation baseline of
+/// [`XyzGeodetic`] tile coordinates, when tracking a phase centre at
+/// declination `dec_rad` over the hour angles from `ha_min_rad` to
+/// `ha_max_rad`. Baselines are in the same order as [`xyzs_to_cross_uvws`].
+/// See [`XyzGeodetic::max_abs_w`].
+pub fn xyzs_to_cross_max_abs_ws(
+    xyzs: &[XyzGeodetic],
+    dec_rad: f64,
+    ha_min_rad: f64,
+    ha_max_rad: f64,
+) -> Vec<f64> {
+    let num_tiles = xyzs.len();
+    let mut max_abs_ws = Vec::with_capacity((num_tiles * num_tiles.saturating_sub(1)) / 2);
+    for (i, t1) in xyzs.iter().enumerate() {
+        for t2 in xyzs.iter().skip(i + 1) {
+            max_abs_ws.push((*t1 - *t2).max_abs_w(dec_rad, ha_min_rad, ha_max_rad));
+        }
+    }
+    max_abs_ws
+}
+
+/// Move each of the [`XyzGeodetic`] tile coordinates by the corresponding
+/// [`ENH`] offset, for an array at `latitude_rad`. This can be used to apply
+/// corrections from a new survey of tile positions, or to test the sensitivity
+/// of something to position errors.
+///
+/// # Panics
+///
+/// Panics if `xyzs` and `offsets` have different lengths.
+pub fn apply_enh_offsets(
+    xyzs: &[XyzGeodetic],
+    offsets: &[ENH],
+    latitude_rad: f64,
+) -> Vec<XyzGeodetic> {
+    assert_eq!(
+        xyzs.len(),
+        offsets.len(),
+        "the number of offsets must match the number of tiles"
+    );
+    let (s_lat, c_lat) = latitude_rad.sin_cos();
+    xyzs.iter()
+        .zip(offsets.iter())
+        .map(|(&xyz, offset)| xyz + offset.to_xyz_inner(s_lat, c_lat))
+        .collect()
+}
+
+/// Get the displacement of each tile from its position in `old` to its
+/// position in `new`, as [`ENH`] offsets for an array at `latitude_rad`. This
+/// is the inverse of [`apply_enh_offsets`].
+///
+/// # Panics
+///
+/// Panics if `old` and `new` have different lengths.
+pub fn enh_displacements(old: &[XyzGeodetic], new: &[XyzGeodetic], latitude_rad: f64) -> Vec<ENH> {
+    assert_eq!(
+        old.len(),
+        new.len(),
+        "both sets of positions must have the same number of tiles"
+    );
+    let (s_lat, c_lat) = latitude_rad.sin_cos();
+    old.iter()
+        .zip(new.iter())
+        .map(|(&old, &new)| (new - old).to_enh_inner(s_lat, c_lat))
+        .collect()
+}
+
 #[deprecated = "use `xyzs_to_uvws` instead"]
 pub fn xyzs_to_uvws_parallel(xyzs: &[XyzGeodetic], phase_centre: HADec) -> Vec<UVW> {
     xyzs_to_uvws(xyzs, phase_centre)
@@ -179,6 +322,18 @@ pub fn xyzs_to_cross_uvws_parallel(xyzs: &[XyzGeodetic], phase_centre: HADec) ->
     xyzs_to_cross_uvws(xyzs, phase_centre)
 }
 
+impl std::ops::Add<XyzGeodetic> for XyzGeodetic {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        XyzGeodetic {
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+            z: self.z + rhs.z,
+        }
+    }
+}
+
 impl std::ops::Sub<XyzGeodetic> for XyzGeodetic {
     type Output = Self;
 
@@ -238,6 +393,7 @@ impl approx::RelativeEq for XyzGeodetic {
 /// Synthesis in Radio Astronomy, Third Edition, Section 4: Geometrical
 /// Relationships, Polarimetry, and the Measurement Equation.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct XyzGeocentric {
     /// x-coordinate \[meters\]
     pub x: f64,
@@ -383,6 +539,104 @@ mod tests {
         COTTER_MWA_HEIGHT_METRES, COTTER_MWA_LATITUDE_RADIANS, COTTER_MWA_LONGITUDE_RADIANS,
     };
 
+    #[test]
+    fn test_baseline_geometries() {
+        let xyzs = [
+            ENH {
+                e: 0.0,
+                n: 0.0,
+                h: 0.0,
+            },
+            ENH {
+                e: 30.0,
+                n: -40.0,
+                h: 0.0,
+            },
+            ENH {
+                e: -100.0,
+                n: 0.0,
+                h: 5.0,
+            },
+        ]
+        .map(|enh| enh.to_xyz(MWA_LAT_RAD));
+        let geometries = xyzs_to_cross_baseline_geometries(&xyzs, MWA_LAT_RAD);
+        assert_eq!(geometries.len(), 3);
+        assert_abs_diff_eq!(geometries[0].length, 50.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(geometries[0].east_west, 30.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(geometries[0].north_south, 40.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(geometries[2].east_west, 130.0, epsilon = 1e-10);
+        assert_abs_diff_eq!(geometries[2].north_south, 40.0, epsilon = 1e-10);
+
+        // Compare the maximum |w| with a brute-force search.
+        let dec = -0.6;
+        let (ha_min, ha_max) = (-0.5, 1.2);
+        let max_abs_ws = xyzs_to_cross_max_abs_ws(&xyzs, dec, ha_min, ha_max);
+        let uvws: Vec<Vec<UVW>> = (0..=10000)
+            .map(|i| {
+                let ha = ha_min + (ha_max - ha_min) * i as f64 / 1e4;
+                xyzs_to_cross_uvws(&xyzs, HADec::from_radians(ha, dec))
+            })
+            .collect();
+        for (i_bl, &max_abs_w) in max_abs_ws.iter().enumerate() {
+            let expected = uvws
+                .iter()
+                .map(|uvws| uvws[i_bl].w.abs())
+                .fold(0.0, f64::max);
+            assert_abs_diff_eq!(max_abs_w, expected, epsilon = 1e-4);
+        }
+
+        // Over a full day, the maximum |w| of an east-west baseline at the
+        // equator is its length.
+        let xyz = ENH {
+            e: 100.0,
+            n: 0.0,
+            h: 0.0,
+        }
+        .to_xyz(MWA_LAT_RAD);
+        assert_abs_diff_eq!(xyz.max_abs_w(0.0, -PI, PI), 100.0, epsilon = 1e-10);
+    }
+
+    #[test]
+    fn test_enh_offsets() {
+        let xyzs = [
+            XyzGeodetic {
+                x: 100.0,
+                y: -200.0,
+                z: 50.0,
+            },
+            XyzGeodetic {
+                x: -30.0,
+                y: 10.0,
+                z: 500.0,
+            },
+        ];
+        let offsets = [
+            ENH {
+                e: 0.01,
+                n: -0.02,
+                h: 0.0,
+            },
+            ENH {
+                e: 0.0,
+                n: 0.0,
+                h: 0.0,
+            },
+        ];
+        let moved = apply_enh_offsets(&xyzs, &offsets, MWA_LAT_RAD);
+        // The tile moves by the offset in the local frame.
+        assert_abs_diff_eq!(
+            moved[0].to_enh(MWA_LAT_RAD).e - xyzs[0].to_enh(MWA_LAT_RAD).e,
+            0.01,
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(moved[1], xyzs[1]);
+
+        let displacements = enh_displacements(&xyzs, &moved, MWA_LAT_RAD);
+        for (displacement, offset) in displacements.iter().zip(offsets.iter()) {
+            assert_abs_diff_eq!(displacement, offset, epsilon = 1e-12);
+        }
+    }
+
     #[test]
     fn test_geocentric_to_geodetic() {
         // Do everything manually.
@@ -558,4 +812,21 @@ mod tests {
         let xyz2 = earth.to_geocentric_wgs84();
         assert_abs_diff_eq!(xyz, xyz2, epsilon = 1e-9);
     }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let xyz = XyzGeodetic {
+            x: 289.5692922664971,
+            y: -585.6749877929688,
+            z: -259.3106530519151,
+        };
+        let json = serde_json::to_string(&xyz).unwrap();
+        let xyz2: XyzGeodetic = serde_json::from_str(&json).unwrap();
+        assert_eq!(xyz, xyz2);
+
+        let json = "{\"x\": -2559454.079, \"y\": 5095372.144, \"z\": -2849057.185}";
+        let xyz: XyzGeocentric = serde_json::from_str(json).unwrap();
+        assert_abs_diff_eq!(xyz.z, -2849057.185);
+    }
 }
@@ -0,0 +1,328 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Frequency-dependent cable reflection (standing wave) modelling.
+//!
+//! An impedance mismatch at either end of a tile's cable reflects part of the
+//! signal back and forth along it. The reflected signal arrives later by the
+//! round-trip delay of the cable, which imprints a sinusoidal ripple on the
+//! tile's bandpass:
+//!
+//! `g(ν) = g₀ (1 + c exp(-2πiντ))`
+//!
+//! where `τ` is the round-trip delay and `c` is the complex reflection
+//! coefficient. Given `τ` (known from the cable's electrical length, or found
+//! with [`search_standing_wave`]), `g₀` and `c` can be found with a linear
+//! least-squares fit.
+
+use ndarray::{prelude::*, Data, DataMut};
+use num_traits::Zero;
+use std::f64::consts::TAU;
+use thiserror::Error;
+
+use crate::{c64, constants::VEL_C, MwaObsContext};
+
+#[derive(Error, Debug)]
+pub enum ReflectionError {
+    #[error("bad array shape supplied to argument {argument} of function {function}. expected {expected}, received {received}")]
+    BadArrayShape {
+        argument: &'static str,
+        function: &'static str,
+        expected: String,
+        received: String,
+    },
+
+    #[error("the bandpass is unconstrained at a delay of {delay_s}s; there are too few unflagged channels or the frequency range is too narrow")]
+    Unconstrained { delay_s: f64 },
+
+    #[error("no delays were provided to search over")]
+    NoDelays,
+}
+
+/// The round-trip delay \[seconds\] of a reflection in a cable with the given
+/// electrical length \[metres\].
+///
+/// MWA metafits cable lengths are electrical lengths, i.e. the velocity factor
+/// of the cable has already been applied.
+#[inline]
+pub fn reflection_delay_s(electrical_length_m: f64) -> f64 {
+    2.0 * electrical_length_m / VEL_C
+}
+
+/// The expected round-trip reflection delay \[seconds\] of each tile's cable,
+/// with dimensions `[ant_idx][pol]`.
+pub fn nominal_reflection_delays(mwa_ctx: &MwaObsContext) -> Array2<f64> {
+    mwa_ctx.ant_cable_lengths.mapv(reflection_delay_s)
+}
+
+/// A sinusoidal standing-wave ripple in a bandpass.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StandingWave {
+    /// The round-trip delay of the reflection \[seconds\]
+    pub delay_s: f64,
+
+    /// The complex reflection coefficient, relative to the unrippled bandpass.
+    pub coefficient: c64,
+
+    /// The unrippled (mean) bandpass gain.
+    pub gain: c64,
+}
+
+impl StandingWave {
+    /// The multiplicative ripple at the given frequency \[Hz\], i.e. `1 + c
+    /// exp(-2πiντ)`.
+    #[inline]
+    pub fn ripple(&self, freq_hz: f64) -> c64 {
+        1.0 + self.coefficient * c64::cis(-TAU * freq_hz * self.delay_s)
+    }
+
+    /// The modelled bandpass at the given frequency \[Hz\], including the
+    /// unrippled gain.
+    #[inline]
+    pub fn model(&self, freq_hz: f64) -> c64 {
+        self.gain * self.ripple(freq_hz)
+    }
+
+    /// The fractional amplitude of the ripple.
+    #[inline]
+    pub fn amplitude(&self) -> f64 {
+        self.coefficient.norm()
+    }
+}
+
+fn check_lengths(
+    function: &'static str,
+    num_chans: usize,
+    weights_len: usize,
+    freqs_len: usize,
+) -> Result<(), ReflectionError> {
+    if weights_len != num_chans {
+        return Err(ReflectionError::BadArrayShape {
+            argument: "weights",
+            function,
+            expected: format!("({num_chans},)"),
+            received: format!("({weights_len},)"),
+        });
+    }
+    if freqs_len != num_chans {
+        return Err(ReflectionError::BadArrayShape {
+            argument: "freqs_hz",
+            function,
+            expected: format!("({num_chans},)"),
+            received: format!("({freqs_len},)"),
+        });
+    }
+    Ok(())
+}
+
+/// Fit a standing wave with a known round-trip delay (`delay_s`) to a complex
+/// bandpass.
+///
+/// `bandpass` - the complex gain of a single tile and pol for each channel.
+///
+/// `weights` - the weight of each channel. Channels with non-positive or
+///     non-finite weights, or non-finite gains, are ignored (i.e. flagged).
+///
+/// `freqs_hz` - the centre frequency of each channel \[Hz\].
+///
+/// The model `g₀ + g₀c exp(-2πiντ)` is linear in `g₀` and `g₀c`, so this is a
+/// weighted linear least-squares fit.
+pub fn fit_standing_wave<S, SW>(
+    bandpass: &ArrayBase<S, Ix1>,
+    weights: &ArrayBase<SW, Ix1>,
+    freqs_hz: &[f64],
+    delay_s: f64,
+) -> Result<StandingWave, ReflectionError>
+where
+    S: Data<Elem = c64>,
+    SW: Data<Elem = f64>,
+{
+    check_lengths(
+        "fit_standing_wave",
+        bandpass.len(),
+        weights.len(),
+        freqs_hz.len(),
+    )?;
+
+    // Accumulate the normal equations
+    // [ S   E ] [a0]   [ B ]
+    // [ E*  S ] [a1] = [ C ]
+    let mut sum_w = 0.0;
+    let mut sum_we = c64::new(0.0, 0.0);
+    let mut sum_wb = c64::new(0.0, 0.0);
+    let mut sum_web = c64::new(0.0, 0.0);
+    for ((&b, &w), &freq) in bandpass.iter().zip(weights.iter()).zip(freqs_hz.iter()) {
+        if !(w.is_finite() && w > 0.0 && b.is_finite()) {
+            continue;
+        }
+        let e = c64::cis(-TAU * freq * delay_s);
+        sum_w += w;
+        sum_we += e * w;
+        sum_wb += b * w;
+        sum_web += e.conj() * b * w;
+    }
+
+    let det = sum_w * sum_w - sum_we.norm_sqr();
+    if !det.is_finite() || det <= f64::EPSILON * sum_w * sum_w {
+        return Err(ReflectionError::Unconstrained { delay_s });
+    }
+    let a0 = (sum_wb * sum_w - sum_we * sum_web) / det;
+    let a1 = (sum_web * sum_w - sum_we.conj() * sum_wb) / det;
+    if a0.is_zero() || !a0.is_finite() {
+        return Err(ReflectionError::Unconstrained { delay_s });
+    }
+
+    Ok(StandingWave {
+        delay_s,
+        coefficient: a1 / a0,
+        gain: a0,
+    })
+}
+
+/// Fit a standing wave at each of the candidate `delays_s`, and return the fit
+/// with the largest ripple amplitude. This is useful when the electrical
+/// length of a cable is not precisely known.
+///
+/// Candidate delays that can't be constrained by the data are skipped.
+pub fn search_standing_wave<S, SW>(
+    bandpass: &ArrayBase<S, Ix1>,
+    weights: &ArrayBase<SW, Ix1>,
+    freqs_hz: &[f64],
+    delays_s: &[f64],
+) -> Result<StandingWave, ReflectionError>
+where
+    S: Data<Elem = c64>,
+    SW: Data<Elem = f64>,
+{
+    if delays_s.is_empty() {
+        return Err(ReflectionError::NoDelays);
+    }
+
+    let mut best: Option<StandingWave> = None;
+    let mut last_error = None;
+    for &delay_s in delays_s {
+        match fit_standing_wave(bandpass, weights, freqs_hz, delay_s) {
+            Ok(fit) => match best {
+                Some(b) if b.amplitude() >= fit.amplitude() => (),
+                _ => best = Some(fit),
+            },
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    match (best, last_error) {
+        (Some(best), _) => Ok(best),
+        (None, Some(e)) => Err(e),
+        (None, None) => unreachable!(),
+    }
+}
+
+/// Remove a standing wave from a bandpass in place, by dividing each channel
+/// by the ripple at that channel's frequency. The unrippled gain is preserved.
+pub fn remove_standing_wave<S>(
+    bandpass: &mut ArrayBase<S, Ix1>,
+    freqs_hz: &[f64],
+    wave: &StandingWave,
+) -> Result<(), ReflectionError>
+where
+    S: DataMut<Elem = c64>,
+{
+    if freqs_hz.len() != bandpass.len() {
+        return Err(ReflectionError::BadArrayShape {
+            argument: "freqs_hz",
+            function: "remove_standing_wave",
+            expected: format!("({},)", bandpass.len()),
+            received: format!("({},)", freqs_hz.len()),
+        });
+    }
+    for (b, &freq) in bandpass.iter_mut().zip(freqs_hz.iter()) {
+        *b /= wave.ripple(freq);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    fn mwa_freqs() -> Vec<f64> {
+        // 24 coarse channels of 32 x 40kHz fine channels.
+        (0..768).map(|i| 167.68e6 + i as f64 * 40e3).collect()
+    }
+
+    #[test]
+    fn test_reflection_delay() {
+        assert_abs_diff_eq!(reflection_delay_s(VEL_C / 2.0), 1.0);
+        assert_abs_diff_eq!(reflection_delay_s(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_fit_and_remove_standing_wave() {
+        let freqs = mwa_freqs();
+        let truth = StandingWave {
+            delay_s: reflection_delay_s(320.0),
+            coefficient: c64::new(0.02, -0.01),
+            gain: c64::new(1.5, 0.5),
+        };
+        let mut bandpass: Array1<c64> = freqs.iter().map(|&f| truth.model(f)).collect();
+        let mut weights = Array1::from_elem(freqs.len(), 1.0);
+        // flagged channels should not affect the fit, even if they are garbage.
+        bandpass[10] = c64::new(f64::NAN, 0.0);
+        bandpass[11] = c64::new(1e6, 1e6);
+        weights[11] = 0.0;
+
+        let fit = fit_standing_wave(&bandpass, &weights, &freqs, truth.delay_s).unwrap();
+        assert_abs_diff_eq!(fit.coefficient, truth.coefficient, epsilon = 1e-10);
+        assert_abs_diff_eq!(fit.gain, truth.gain, epsilon = 1e-10);
+
+        remove_standing_wave(&mut bandpass, &freqs, &fit).unwrap();
+        for (i, &b) in bandpass.iter().enumerate() {
+            if i != 10 && i != 11 {
+                assert_abs_diff_eq!(b, truth.gain, epsilon = 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_standing_wave() {
+        let freqs = mwa_freqs();
+        let truth = StandingWave {
+            delay_s: reflection_delay_s(150.0),
+            coefficient: c64::new(0.0, 0.05),
+            gain: c64::new(1.0, 0.0),
+        };
+        let bandpass: Array1<c64> = freqs.iter().map(|&f| truth.model(f)).collect();
+        let weights = Array1::from_elem(freqs.len(), 1.0);
+        let delays: Vec<f64> = [90.0, 150.0, 230.0, 320.0, 400.0, 524.0]
+            .iter()
+            .map(|&l| reflection_delay_s(l))
+            .collect();
+
+        let best = search_standing_wave(&bandpass, &weights, &freqs, &delays).unwrap();
+        assert_abs_diff_eq!(best.delay_s, truth.delay_s);
+        assert_abs_diff_eq!(best.coefficient, truth.coefficient, epsilon = 1e-10);
+
+        assert!(matches!(
+            search_standing_wave(&bandpass, &weights, &freqs, &[]),
+            Err(ReflectionError::NoDelays)
+        ));
+    }
+
+    #[test]
+    fn test_fit_standing_wave_unconstrained() {
+        let freqs = mwa_freqs();
+        let bandpass = Array1::from_elem(freqs.len(), c64::new(1.0, 0.0));
+        let weights = Array1::from_elem(freqs.len(), 0.0);
+        assert!(matches!(
+            fit_standing_wave(&bandpass, &weights, &freqs, 1e-6),
+            Err(ReflectionError::Unconstrained { .. })
+        ));
+        assert!(matches!(
+            fit_standing_wave(&bandpass, &weights, &freqs[1..], 1e-6),
+            Err(ReflectionError::BadArrayShape { .. })
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Shift the phase centre of visibilities.
+//!
+//! The visibilities are assumed to follow the convention of measurement sets,
+//! i.e. `V(u, v, w) = ∫ I(l, m) exp(-2πi (ul + vm + w(n - 1))) dl dm`.
+
+use std::f64::consts::TAU;
+
+use ndarray::prelude::*;
+use num_complex::Complex;
+use thiserror::Error;
+
+use crate::{constants::VEL_C, HADec, Jones, RADec, XyzGeodetic, UVW};
+
+#[derive(Error, Debug)]
+pub enum RephaseError {
+    #[error("bad array shape supplied to argument {argument} of function rephase_visibilities. expected {expected}, received {received}")]
+    BadArrayShape {
+        argument: &'static str,
+        expected: String,
+        received: String,
+    },
+}
+
+/// Rotate a [`UVW`] \[metres\] phased to `old_centre` so that it's phased to
+/// `new_centre`. The hour angles of the centres aren't needed, as the rotation
+/// only depends on the difference of their right ascensions.
+pub fn rotate_uvw(uvw: UVW, old_centre: RADec, new_centre: RADec) -> UVW {
+    // Undo the UVW projection with the old centre at an hour angle of 0 (i.e.
+    // an LST equal to its RA)...
+    let (s_dec, c_dec) = old_centre.dec.sin_cos();
+    let xyz = XyzGeodetic {
+        x: -s_dec * uvw.v + c_dec * uvw.w,
+        y: uvw.u,
+        z: c_dec * uvw.v + s_dec * uvw.w,
+    };
+    // ... and re-project with the new centre at the same LST.
+    UVW::from_xyz(
+        xyz,
+        HADec::from_radians(old_centre.ra - new_centre.ra, new_centre.dec),
+    )
+}
+
+/// Shift the phase centre of visibilities from `old_centre` to `new_centre`
+/// in-place. The `uvws` \[metres\] (`[timestep][baseline]`) are rotated to the
+/// new centre, and each visibility (`[timestep][channel][baseline]`) is given
+/// the phase corresponding to the change in its `w` at its frequency.
+/// Timesteps are rephased in parallel.
+///
+/// # Errors
+///
+/// Will return [`RephaseError::BadArrayShape`] if the dimensions of `uvws` or
+/// `freqs_hz` don't match `jones`.
+#[allow(clippy::needless_pass_by_value)]
+pub fn rephase_visibilities(
+    mut jones: ArrayViewMut3<Jones<f32>>,
+    mut uvws: ArrayViewMut2<UVW>,
+    old_centre: RADec,
+    new_centre: RADec,
+    freqs_hz: &[f64],
+) -> Result<(), RephaseError> {
+    let (num_timesteps, num_chans, num_baselines) = jones.dim();
+    for (argument, expected, received) in [
+        (
+            "uvws",
+            format!("{:?}", (num_timesteps, num_baselines)),
+            format!("{:?}", uvws.dim()),
+        ),
+        (
+            "freqs_hz",
+            format!("{num_chans}"),
+            format!("{}", freqs_hz.len()),
+        ),
+    ] {
+        if expected != received {
+            return Err(RephaseError::BadArrayShape {
+                argument,
+                expected,
+                received,
+            });
+        }
+    }
+
+    Zip::from(jones.outer_iter_mut())
+        .and(uvws.outer_iter_mut())
+        .par_for_each(|mut jones, mut uvws| {
+            for (mut jones, uvw) in jones.axis_iter_mut(Axis(1)).zip(uvws.iter_mut()) {
+                let new_uvw = rotate_uvw(*uvw, old_centre, new_centre);
+                let d_w = new_uvw.w - uvw.w;
+                *uvw = new_uvw;
+                for (jones, &freq_hz) in jones.iter_mut().zip(freqs_hz) {
+                    let (s, c) = (TAU * d_w * freq_hz / VEL_C).sin_cos();
+                    *jones *= Complex::new(c as f32, s as f32);
+                }
+            }
+        });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_rotate_uvw() {
+        let xyz = XyzGeodetic {
+            x: 100.0,
+            y: -200.0,
+            z: 50.0,
+        };
+        let lst = 0.3;
+        let old_centre = RADec::from_degrees(10.0, -27.0);
+        let new_centre = RADec::from_degrees(20.0, -40.0);
+        let old_uvw = UVW::from_xyz(xyz, old_centre.to_hadec(lst));
+        let new_uvw = UVW::from_xyz(xyz, new_centre.to_hadec(lst));
+        assert_abs_diff_eq!(
+            rotate_uvw(old_uvw, old_centre, new_centre),
+            new_uvw,
+            epsilon = 1e-10
+        );
+    }
+
+    #[test]
+    fn test_rephase_visibilities() {
+        let xyzs = [
+            XyzGeodetic {
+                x: 100.0,
+                y: -200.0,
+                z: 50.0,
+            },
+            XyzGeodetic {
+                x: -30.0,
+                y: 10.0,
+                z: 500.0,
+            },
+        ];
+        let lst = 0.3;
+        let freqs_hz = [150e6, 200e6];
+        let old_centre = RADec::from_degrees(10.0, -27.0);
+        let source = RADec::from_degrees(12.0, -30.0);
+
+        // A unit point source at `source`, phased to `centre`.
+        let model = |centre: RADec| {
+            let uvws = Array2::from_shape_fn((1, 2), |(_, b)| {
+                UVW::from_xyz(xyzs[b], centre.to_hadec(lst))
+            });
+            let lmn = source.to_lmn(centre);
+            let jones = Array3::from_shape_fn((1, 2, 2), |(t, c, b)| {
+                let phase = -lmn.dot(uvws[(t, b)] * freqs_hz[c] / VEL_C);
+                let (s_phase, c_phase) = phase.sin_cos();
+                Jones::identity() * Complex::new(c_phase as f32, s_phase as f32)
+            });
+            (jones, uvws)
+        };
+
+        // Rephasing to the source makes its visibilities real.
+        let (mut jones, mut uvws) = model(old_centre);
+        rephase_visibilities(
+            jones.view_mut(),
+            uvws.view_mut(),
+            old_centre,
+            source,
+            &freqs_hz,
+        )
+        .unwrap();
+        let (expected_jones, expected_uvws) = model(source);
+        assert_abs_diff_eq!(uvws, expected_uvws, epsilon = 1e-9);
+        assert_abs_diff_eq!(jones, expected_jones, epsilon = 1e-4);
+        assert_abs_diff_eq!(jones[(0, 1, 1)], Jones::identity(), epsilon = 1e-4);
+
+        assert!(matches!(
+            rephase_visibilities(
+                jones.view_mut(),
+                uvws.view_mut(),
+                old_centre,
+                source,
+                &freqs_hz[..1],
+            ),
+            Err(RephaseError::BadArrayShape {
+                argument: "freqs_hz",
+                ..
+            })
+        ));
+    }
+}
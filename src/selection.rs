@@ -108,6 +108,20 @@ pub enum SelectionError {
     #[cfg(feature = "mwalib")]
     #[error(transparent)]
     Mwalib(#[from] mwalib::GpuboxError),
+
+    #[cfg(feature = "mwalib")]
+    #[error("reading {version} gpubox files with {function} isn't supported")]
+    /// Error for a correlator version that a reader can't read
+    UnsupportedMwaVersion {
+        /// The function name
+        function: String,
+        /// The correlator version
+        version: String,
+    },
+
+    #[cfg(feature = "mmap")]
+    #[error(transparent)]
+    FitsMmap(#[from] crate::io::FitsMmapError),
 }
 
 /// Keep track of which mwalib indices the values in a jones array, its' weights and its' flags
@@ -314,6 +328,7 @@ impl VisSelection {
     ) -> Result<(), SelectionError> {
         use itertools::izip;
         use ndarray::prelude::*;
+        #[cfg(feature = "rayon")]
         use rayon::prelude::*;
 
         let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
@@ -360,10 +375,12 @@ impl VisSelection {
         let floats_per_baseline = floats_per_chan * fine_chans_per_coarse;
         let floats_per_hdu = floats_per_baseline * corr_ctx.metafits_context.num_baselines;
 
-        // Load HDUs from each coarse channel. arrays: [timestep][chan][baseline]
-        jones_array
-            .axis_chunks_iter_mut(Axis(1), fine_chans_per_coarse)
-            .into_par_iter()
+        // Load HDUs from each coarse channel, in parallel with the "rayon"
+        // feature. arrays: [timestep][chan][baseline]
+        let coarse_chan_chunks = jones_array.axis_chunks_iter_mut(Axis(1), fine_chans_per_coarse);
+        #[cfg(feature = "rayon")]
+        let coarse_chan_chunks = coarse_chan_chunks.into_par_iter();
+        coarse_chan_chunks
             .zip(flag_array.axis_chunks_iter_mut(Axis(1), fine_chans_per_coarse))
             .zip(self.coarse_chan_range.clone())
             .try_for_each(|((mut jones_array, mut flag_array), coarse_chan_idx)| {
@@ -422,6 +439,144 @@ impl VisSelection {
 
         Ok(())
     }
+
+    /// Read the visibilities of the selection out of MWAX gpubox files,
+    /// like [`CorrelatorContext::read_by_baseline_into_buffer`], but by
+    /// memory-mapping the files (see [`crate::io::MmapFits`]) rather than with
+    /// cfitsio. Each gpubox file is only mapped once. Missing HDUs are flagged.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`SelectionError::UnsupportedMwaVersion`] for legacy MWA
+    /// observations, whose visibilities have to be reordered by mwalib, or an
+    /// error if an array has the wrong shape or a gpubox file can't be read.
+    #[cfg(all(feature = "mwalib", feature = "mmap"))]
+    pub fn read_mwalib_mmap(
+        &self,
+        corr_ctx: &CorrelatorContext,
+        mut jones_array: ndarray::ArrayViewMut3<Jones<f32>>,
+        mut flag_array: ndarray::ArrayViewMut3<bool>,
+    ) -> Result<(), SelectionError> {
+        use std::collections::{hash_map::Entry, HashMap};
+
+        use itertools::izip;
+        use ndarray::prelude::*;
+
+        use crate::io::MmapFits;
+
+        let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+        let shape = self.get_shape(fine_chans_per_coarse);
+        for (argument, received) in [
+            ("jones_array", jones_array.dim()),
+            ("flag_array", flag_array.dim()),
+        ] {
+            if received != shape {
+                return Err(SelectionError::BadArrayShape {
+                    argument: argument.to_string(),
+                    function: "VisSelection::read_mwalib_mmap".to_string(),
+                    expected: format!("{shape:?}"),
+                    received: format!("{received:?}"),
+                });
+            }
+        }
+
+        let max_bl_idx = corr_ctx.metafits_context.baselines.len();
+        if self.baseline_idxs.iter().any(|&idx| idx >= max_bl_idx) {
+            return Err(SelectionError::BadBaselineIdx {
+                function: "VisSelection::read_mwalib_mmap".to_string(),
+                expected: format!(" < {max_bl_idx}"),
+                received: format!("{:?}", self.baseline_idxs.clone()),
+            });
+        }
+
+        // Only MWAX visibilities are stored in the order they're read in:
+        // baseline,frequency,pol,r,i
+        if !matches!(corr_ctx.mwa_version, mwalib::MWAVersion::CorrMWAXv2) {
+            return Err(SelectionError::UnsupportedMwaVersion {
+                function: "VisSelection::read_mwalib_mmap".to_string(),
+                version: format!("{:?}", corr_ctx.mwa_version),
+            });
+        }
+
+        let floats_per_chan = 8;
+        assert_eq!(
+            corr_ctx.metafits_context.num_visibility_pols * 2,
+            floats_per_chan
+        );
+        let floats_per_baseline = floats_per_chan * fine_chans_per_coarse;
+        let floats_per_hdu = floats_per_baseline * corr_ctx.metafits_context.num_baselines;
+        let mut hdu_buffer: Vec<f32> = vec![0.0; floats_per_hdu];
+        let mut gpubox_files: HashMap<&str, MmapFits> = HashMap::new();
+
+        // arrays: [timestep][chan][baseline]
+        for (mut jones_array, mut flag_array, coarse_chan_idx) in izip!(
+            jones_array.axis_chunks_iter_mut(Axis(1), fine_chans_per_coarse),
+            flag_array.axis_chunks_iter_mut(Axis(1), fine_chans_per_coarse),
+            self.coarse_chan_range.clone(),
+        ) {
+            let channel_identifier = corr_ctx.coarse_chans[coarse_chan_idx].gpubox_number;
+            // arrays: [chan][baseline]
+            for (mut jones_array, mut flag_array, timestep_idx) in izip!(
+                jones_array.outer_iter_mut(),
+                flag_array.outer_iter_mut(),
+                self.timestep_range.clone(),
+            ) {
+                let unix_time_ms = corr_ctx.timesteps[timestep_idx].unix_time_ms;
+                let hdu = corr_ctx
+                    .gpubox_time_map
+                    .get(&unix_time_ms)
+                    .and_then(|chans| chans.get(&channel_identifier))
+                    .and_then(|&(batch_idx, hdu_idx)| {
+                        corr_ctx
+                            .gpubox_batches
+                            .get(batch_idx)?
+                            .gpubox_files
+                            .iter()
+                            .find(|f| f.channel_identifier == channel_identifier)
+                            .map(|f| (f.filename.as_str(), hdu_idx))
+                    });
+                let (filename, hdu_idx) = match hdu {
+                    Some(hdu) => hdu,
+                    None => {
+                        flag_array.fill(true);
+                        continue;
+                    }
+                };
+                let fits = match gpubox_files.entry(filename) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(MmapFits::open(filename)?),
+                };
+                fits.read_image_f32_into(hdu_idx, &mut hdu_buffer)?;
+
+                // arrays: [chan]
+                for (mut jones_array, baseline_idx) in izip!(
+                    jones_array.axis_iter_mut(Axis(1)),
+                    self.baseline_idxs.iter()
+                ) {
+                    // buffer: [chan][pol][complex]
+                    let hdu_baseline_chunk =
+                        &hdu_buffer[baseline_idx * floats_per_baseline..][..floats_per_baseline];
+                    for (jones, hdu_chan_chunk) in izip!(
+                        jones_array.iter_mut(),
+                        hdu_baseline_chunk.chunks_exact(floats_per_chan)
+                    ) {
+                        *jones = Jones::from([
+                            hdu_chan_chunk[0],
+                            hdu_chan_chunk[1],
+                            hdu_chan_chunk[2],
+                            hdu_chan_chunk[3],
+                            hdu_chan_chunk[4],
+                            hdu_chan_chunk[5],
+                            hdu_chan_chunk[6],
+                            hdu_chan_chunk[7],
+                        ]);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -660,6 +815,42 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_read_mwalib_mmap_matches_mwalib() {
+        let corr_ctx = get_mwax_context();
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+        let mut flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+        let mut jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        vis_sel
+            .read_mwalib(&corr_ctx, jones_array.view_mut(), flag_array.view_mut())
+            .unwrap();
+
+        let mut mmap_flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+        let mut mmap_jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        vis_sel
+            .read_mwalib_mmap(
+                &corr_ctx,
+                mmap_jones_array.view_mut(),
+                mmap_flag_array.view_mut(),
+            )
+            .unwrap();
+        assert_eq!(mmap_jones_array, jones_array);
+        assert_eq!(mmap_flag_array, flag_array);
+
+        // Legacy MWA visibilities need to be reordered by mwalib.
+        let corr_ctx = get_mwa_legacy_context();
+        let vis_sel = VisSelection::from_mwalib(&corr_ctx).unwrap();
+        let fine_chans_per_coarse = corr_ctx.metafits_context.num_corr_fine_chans_per_coarse;
+        let mut flag_array = vis_sel.allocate_flags(fine_chans_per_coarse).unwrap();
+        let mut jones_array = vis_sel.allocate_jones(fine_chans_per_coarse).unwrap();
+        assert!(matches!(
+            vis_sel.read_mwalib_mmap(&corr_ctx, jones_array.view_mut(), flag_array.view_mut()),
+            Err(SelectionError::UnsupportedMwaVersion { .. })
+        ));
+    }
+
     #[test]
     fn test_read_mwalib_bad_baseline_idxs() {
         let corr_ctx = get_mwax_context();
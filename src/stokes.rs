@@ -0,0 +1,222 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Conversions between visibilities and Stokes parameters.
+//!
+//! Visibilities are [`Jones`] matrices of correlation products, either in a
+//! linear (`[XX, XY, YX, YY]`) or circular (`[RR, RL, LR, LL]`) basis. The IAU
+//! conventions are used:
+//!
+//! - `XX = I + Q`, `XY = U + iV`, `YX = U - iV`, `YY = I - Q`
+//! - `RR = I + V`, `RL = Q + iU`, `LR = Q - iU`, `LL = I - V`
+//!
+//! Note that there's no factor of 0.5 in the forward direction; e.g. an
+//! unpolarised source with `I = 1` has `XX = YY = 1`.
+
+use ndarray::prelude::*;
+use num_traits::Float;
+
+use crate::{Complex, Jones};
+
+/// The basis of the polarisations of a visibility.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolBasis {
+    /// `[XX, XY, YX, YY]`
+    Linear,
+
+    /// `[RR, RL, LR, LL]`
+    Circular,
+}
+
+/// The (complex) Stokes parameters of a visibility.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stokes<F: Float> {
+    /// Total intensity
+    pub i: Complex<F>,
+    /// Linear polarisation
+    pub q: Complex<F>,
+    /// Linear polarisation, at 45° to `q`
+    pub u: Complex<F>,
+    /// Circular polarisation
+    pub v: Complex<F>,
+}
+
+impl<F: Float> Stokes<F> {
+    /// Get the Stokes parameters of a visibility in the given basis.
+    pub fn from_jones(vis: Jones<F>, basis: PolBasis) -> Stokes<F> {
+        let half = F::one() / (F::one() + F::one());
+        let [a, b, c, d] = vis.to_complex_array();
+        // Dividing by 2i.
+        let div_2i = |z: Complex<F>| Complex::new(z.im * half, -z.re * half);
+        match basis {
+            PolBasis::Linear => Stokes {
+                i: (a + d) * half,
+                q: (a - d) * half,
+                u: (b + c) * half,
+                v: div_2i(b - c),
+            },
+            PolBasis::Circular => Stokes {
+                i: (a + d) * half,
+                q: (b + c) * half,
+                u: div_2i(b - c),
+                v: (a - d) * half,
+            },
+        }
+    }
+
+    /// Get the visibility with these Stokes parameters in the given basis.
+    pub fn to_jones(self, basis: PolBasis) -> Jones<F> {
+        let mul_i = |z: Complex<F>| Complex::new(-z.im, z.re);
+        let Stokes { i, q, u, v } = self;
+        match basis {
+            PolBasis::Linear => Jones::from([i + q, u + mul_i(v), u - mul_i(v), i - q]),
+            PolBasis::Circular => Jones::from([i + v, q + mul_i(u), q - mul_i(u), i - v]),
+        }
+    }
+}
+
+/// Convert an array of visibilities (e.g. `[time][chan][baseline]`) to Stokes
+/// parameters. This is done in parallel if the `rayon` feature is enabled.
+#[allow(clippy::needless_pass_by_value)]
+pub fn jones_to_stokes_array<D: Dimension>(
+    vis: ArrayView<Jones<f32>, D>,
+    basis: PolBasis,
+) -> Array<Stokes<f32>, D> {
+    #[cfg(feature = "rayon")]
+    {
+        Zip::from(&vis).par_map_collect(|&j| Stokes::from_jones(j, basis))
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        Zip::from(&vis).map_collect(|&j| Stokes::from_jones(j, basis))
+    }
+}
+
+/// Convert an array of Stokes parameters back to visibilities. This is done in
+/// parallel if the `rayon` feature is enabled.
+#[allow(clippy::needless_pass_by_value)]
+pub fn stokes_to_jones_array<D: Dimension>(
+    stokes: ArrayView<Stokes<f32>, D>,
+    basis: PolBasis,
+) -> Array<Jones<f32>, D> {
+    #[cfg(feature = "rayon")]
+    {
+        Zip::from(&stokes).par_map_collect(|&s| s.to_jones(basis))
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        Zip::from(&stokes).map_collect(|&s| s.to_jones(basis))
+    }
+}
+
+/// Convert an array of visibilities from the `from` basis to the `to` basis in
+/// place. See [`Jones::to_circular_basis`]. This is done in parallel if the
+/// `rayon` feature is enabled.
+pub fn convert_pol_basis<D: Dimension>(
+    mut vis: ArrayViewMut<Jones<f32>, D>,
+    from: PolBasis,
+    to: PolBasis,
+) {
+    let convert = match (from, to) {
+        (PolBasis::Linear, PolBasis::Circular) => Jones::to_circular_basis,
+        (PolBasis::Circular, PolBasis::Linear) => Jones::to_linear_basis,
+        _ => return,
+    };
+    #[cfg(feature = "rayon")]
+    vis.par_map_inplace(|j| *j = convert(*j));
+    #[cfg(not(feature = "rayon"))]
+    vis.map_inplace(|j| *j = convert(*j));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c32;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_stokes() {
+        // An unpolarised source.
+        let vis = Jones::from([
+            c32::new(1.0, 0.0),
+            c32::new(0.0, 0.0),
+            c32::new(0.0, 0.0),
+            c32::new(1.0, 0.0),
+        ]);
+        for basis in [PolBasis::Linear, PolBasis::Circular] {
+            let stokes = Stokes::from_jones(vis, basis);
+            assert_abs_diff_eq!(stokes.i, c32::new(1.0, 0.0));
+            assert_abs_diff_eq!(stokes.q, c32::new(0.0, 0.0));
+            assert_abs_diff_eq!(stokes.u, c32::new(0.0, 0.0));
+            assert_abs_diff_eq!(stokes.v, c32::new(0.0, 0.0));
+        }
+
+        // Circularly polarised.
+        let stokes = Stokes {
+            i: c32::new(1.0, 0.0),
+            v: c32::new(0.5, 0.0),
+            ..Stokes::default()
+        };
+        let linear = stokes.to_jones(PolBasis::Linear);
+        assert_abs_diff_eq!(linear[1], c32::new(0.0, 0.5));
+        assert_abs_diff_eq!(linear[2], c32::new(0.0, -0.5));
+        let circular = stokes.to_jones(PolBasis::Circular);
+        assert_abs_diff_eq!(circular[0], c32::new(1.5, 0.0));
+        assert_abs_diff_eq!(circular[3], c32::new(0.5, 0.0));
+
+        // Round trips.
+        let vis = Jones::from([
+            c32::new(1.0, 2.0),
+            c32::new(3.0, 4.0),
+            c32::new(5.0, 6.0),
+            c32::new(7.0, 8.0),
+        ]);
+        for basis in [PolBasis::Linear, PolBasis::Circular] {
+            let stokes = Stokes::from_jones(vis, basis);
+            assert_abs_diff_eq!(stokes.to_jones(basis), vis, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_stokes_arrays() {
+        let vis = Array3::from_shape_fn((2, 3, 4), |(t, c, b)| {
+            Jones::from([
+                c32::new(t as f32, 1.0),
+                c32::new(c as f32, -1.0),
+                c32::new(b as f32, 0.5),
+                c32::new(1.0, (t + c + b) as f32),
+            ])
+        });
+        let stokes = jones_to_stokes_array(vis.view(), PolBasis::Linear);
+        assert_eq!(stokes.dim(), (2, 3, 4));
+        assert_eq!(
+            stokes[(1, 2, 3)],
+            Stokes::from_jones(vis[(1, 2, 3)], PolBasis::Linear)
+        );
+        let vis2 = stokes_to_jones_array(stokes.view(), PolBasis::Linear);
+        assert_abs_diff_eq!(vis2, vis, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_convert_pol_basis() {
+        let stokes = Stokes {
+            i: c32::new(2.0, 0.1),
+            q: c32::new(0.3, -0.2),
+            u: c32::new(-0.4, 0.0),
+            v: c32::new(0.1, 0.05),
+        };
+        let linear = stokes.to_jones(PolBasis::Linear);
+        let circular = stokes.to_jones(PolBasis::Circular);
+        assert_abs_diff_eq!(linear.to_circular_basis(), circular, epsilon = 1e-6);
+        assert_abs_diff_eq!(circular.to_linear_basis(), linear, epsilon = 1e-6);
+
+        let mut vis = Array2::from_elem((3, 2), linear);
+        convert_pol_basis(vis.view_mut(), PolBasis::Linear, PolBasis::Linear);
+        assert_abs_diff_eq!(vis[(2, 1)], linear);
+        convert_pol_basis(vis.view_mut(), PolBasis::Linear, PolBasis::Circular);
+        assert_abs_diff_eq!(vis[(2, 1)], circular, epsilon = 1e-6);
+        convert_pol_basis(vis.view_mut(), PolBasis::Circular, PolBasis::Linear);
+        assert_abs_diff_eq!(vis[(0, 0)], linear, epsilon = 1e-6);
+    }
+}
@@ -0,0 +1,259 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Time axis bookkeeping.
+//!
+//! Averaging, the writers and anything that aligns data by LST assume that the
+//! time axis is regular, i.e. each timestep is exactly one integration time
+//! after the last. Missing HDUs (e.g. dropped gpubox files) break this
+//! assumption, so the helpers here detect gaps and insert fully flagged
+//! placeholder timesteps to make the axis regular again.
+
+use std::ops::Range;
+
+use hifitime::{Duration, Epoch};
+use ndarray::prelude::*;
+use thiserror::Error;
+
+use crate::{freq::FilledVis, Jones, VisContext};
+
+#[derive(Error, Debug)]
+pub enum TimeError {
+    #[error("bad array shape supplied to argument {argument} of function {function}. expected {expected}, received {received}")]
+    BadArrayShape {
+        argument: &'static str,
+        function: &'static str,
+        expected: String,
+        received: String,
+    },
+
+    #[error("no timestamps were provided")]
+    NoTimestamps,
+
+    #[error("timestamps must be unique and in ascending order, but timestamp {idx} ({timestamp}) is not after the previous one")]
+    Unsorted { idx: usize, timestamp: Epoch },
+
+    #[error("timestamp {idx} ({timestamp}) is not a whole number of integration times ({int_time}) after the first timestamp")]
+    Misaligned {
+        idx: usize,
+        timestamp: Epoch,
+        int_time: Duration,
+    },
+}
+
+/// The index of each timestamp on a regular time grid with spacing `int_time`
+/// starting at the first timestamp.
+///
+/// Timestamps may be up to a quarter of an integration time from a grid point.
+pub fn timestep_grid_indices(
+    timestamps: &[Epoch],
+    int_time: Duration,
+) -> Result<Vec<usize>, TimeError> {
+    let first = *timestamps.first().ok_or(TimeError::NoTimestamps)?;
+    let int_time_s = int_time.to_seconds();
+    let mut result = Vec::with_capacity(timestamps.len());
+    for (idx, &timestamp) in timestamps.iter().enumerate() {
+        let steps = (timestamp - first).to_seconds() / int_time_s;
+        let grid_idx = steps.round();
+        if (steps - grid_idx).abs() > 0.25 {
+            return Err(TimeError::Misaligned {
+                idx,
+                timestamp,
+                int_time,
+            });
+        }
+        let grid_idx = grid_idx as usize;
+        if idx > 0 && grid_idx <= result[idx - 1] {
+            return Err(TimeError::Unsorted { idx, timestamp });
+        }
+        result.push(grid_idx);
+    }
+    Ok(result)
+}
+
+/// Find the timesteps missing from `timestamps`, as ranges of indices into the
+/// regular time grid described by [`timestep_grid_indices`].
+///
+/// e.g. timestamps at `[0s, 2s, 3s, 6s]` with 1s integrations -> `[1..2, 4..6]`
+pub fn find_timestep_gaps(
+    timestamps: &[Epoch],
+    int_time: Duration,
+) -> Result<Vec<Range<usize>>, TimeError> {
+    let grid_idxs = timestep_grid_indices(timestamps, int_time)?;
+    Ok(grid_idxs
+        .windows(2)
+        .filter(|w| w[1] > w[0] + 1)
+        .map(|w| w[0] + 1..w[1])
+        .collect())
+}
+
+/// Insert fully flagged, zero-weighted placeholder timesteps into visibilities
+/// so that their time axis is regular.
+///
+/// `timestamps` is the start time of each timestep in the time axis of `jones`,
+/// `weights` and `flags`, which have dimensions `[timestep][channel][baseline]`
+/// matching [`VisContext::sel_dims`]. The spacing of the regular axis is
+/// [`VisContext::int_time`].
+pub fn fill_timestep_gaps(
+    vis_ctx: &VisContext,
+    timestamps: &[Epoch],
+    jones: ArrayView3<Jones<f32>>,
+    weights: ArrayView3<f32>,
+    flags: ArrayView3<bool>,
+) -> Result<FilledVis, TimeError> {
+    let sel_dims = vis_ctx.sel_dims();
+    for (argument, received) in [
+        ("jones", jones.dim()),
+        ("weights", weights.dim()),
+        ("flags", flags.dim()),
+    ] {
+        if received != sel_dims {
+            return Err(TimeError::BadArrayShape {
+                argument,
+                function: "fill_timestep_gaps",
+                expected: format!("{sel_dims:?}"),
+                received: format!("{received:?}"),
+            });
+        }
+    }
+    if timestamps.len() != sel_dims.0 {
+        return Err(TimeError::BadArrayShape {
+            argument: "timestamps",
+            function: "fill_timestep_gaps",
+            expected: format!("({},)", sel_dims.0),
+            received: format!("({},)", timestamps.len()),
+        });
+    }
+    let grid_idxs = timestep_grid_indices(timestamps, vis_ctx.int_time)?;
+
+    let mut filled_ctx = vis_ctx.clone();
+    filled_ctx.start_timestamp = timestamps[0];
+    filled_ctx.num_sel_timesteps = grid_idxs[grid_idxs.len() - 1] + 1;
+    let filled_dims = filled_ctx.sel_dims();
+
+    let mut filled = FilledVis {
+        jones: Array3::zeros(filled_dims),
+        weights: Array3::zeros(filled_dims),
+        flags: Array3::from_elem(filled_dims, true),
+        vis_ctx: filled_ctx,
+    };
+    for (timestep_idx, &grid_idx) in grid_idxs.iter().enumerate() {
+        filled
+            .jones
+            .index_axis_mut(Axis(0), grid_idx)
+            .assign(&jones.index_axis(Axis(0), timestep_idx));
+        filled
+            .weights
+            .index_axis_mut(Axis(0), grid_idx)
+            .assign(&weights.index_axis(Axis(0), timestep_idx));
+        filled
+            .flags
+            .index_axis_mut(Axis(0), grid_idx)
+            .assign(&flags.index_axis(Axis(0), timestep_idx));
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn epochs(offsets_s: &[f64]) -> Vec<Epoch> {
+        offsets_s
+            .iter()
+            .map(|&o| Epoch::from_gpst_seconds(1090008640. + o))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_timestep_gaps() {
+        let int_time = Duration::from_seconds(1.);
+        let timestamps = epochs(&[0., 2., 3., 6.]);
+        assert_eq!(
+            timestep_grid_indices(&timestamps, int_time).unwrap(),
+            vec![0, 2, 3, 6]
+        );
+        assert_eq!(
+            find_timestep_gaps(&timestamps, int_time).unwrap(),
+            vec![1..2, 4..6]
+        );
+        assert!(find_timestep_gaps(&epochs(&[0., 1., 2.]), int_time)
+            .unwrap()
+            .is_empty());
+
+        assert!(matches!(
+            find_timestep_gaps(&[], int_time),
+            Err(TimeError::NoTimestamps)
+        ));
+        assert!(matches!(
+            find_timestep_gaps(&epochs(&[0., 2., 1.]), int_time),
+            Err(TimeError::Unsorted { idx: 2, .. })
+        ));
+        assert!(matches!(
+            find_timestep_gaps(&epochs(&[0., 1.5]), int_time),
+            Err(TimeError::Misaligned { idx: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_fill_timestep_gaps() {
+        let timestamps = epochs(&[0., 2., 3.]);
+        let vis_ctx = VisContext {
+            num_sel_timesteps: 3,
+            start_timestamp: timestamps[0],
+            int_time: Duration::from_seconds(1.),
+            num_sel_chans: 2,
+            start_freq_hz: 150e6,
+            freq_resolution_hz: 40e3,
+            sel_baselines: vec![(0, 1), (0, 2)],
+            avg_time: 1,
+            avg_freq: 1,
+            num_vis_pols: 4,
+        };
+        let dims = vis_ctx.sel_dims();
+        let jones = Array3::from_elem(dims, Jones::identity());
+        let weights = Array3::from_elem(dims, 1.0);
+        let flags = Array3::from_elem(dims, false);
+
+        let filled = fill_timestep_gaps(
+            &vis_ctx,
+            &timestamps,
+            jones.view(),
+            weights.view(),
+            flags.view(),
+        )
+        .unwrap();
+
+        assert_eq!(filled.vis_ctx.num_sel_timesteps, 4);
+        assert_eq!(filled.jones.dim(), (4, 2, 2));
+        for timestep_idx in [0, 2, 3] {
+            assert!(filled
+                .flags
+                .index_axis(Axis(0), timestep_idx)
+                .iter()
+                .all(|&f| !f));
+        }
+        assert!(filled.flags.index_axis(Axis(0), 1).iter().all(|&f| f));
+        assert!(filled
+            .weights
+            .index_axis(Axis(0), 1)
+            .iter()
+            .all(|&w| w <= 0.));
+
+        assert!(matches!(
+            fill_timestep_gaps(
+                &vis_ctx,
+                &timestamps[..2],
+                jones.view(),
+                weights.view(),
+                flags.view(),
+            ),
+            Err(TimeError::BadArrayShape {
+                argument: "timestamps",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Visibilities bundled with their weights and flags.
+//!
+//! The loose tuples returned by e.g. [`crate::averaging::average_visibilities`]
+//! ([`VisData344`]) don't guarantee that their arrays match, so a mismatch is
+//! only found deep inside whichever kernel uses them. A [`VisArray`] checks its
+//! shapes once, when it's constructed.
+
+use ndarray::prelude::*;
+use thiserror::Error;
+
+use crate::{
+    averaging::{average_visibilities, AveragingError, VisData344},
+    flags::PolFlagPolicy,
+    Jones,
+};
+
+#[derive(Error, Debug)]
+pub enum VisArrayError {
+    #[error("bad array shape supplied to argument {argument} of VisArray::new. expected {expected}, received {received}")]
+    BadArrayShape {
+        argument: &'static str,
+        expected: String,
+        received: String,
+    },
+}
+
+/// Visibilities, `[timestep][channel][baseline]`, with their weights and flags,
+/// `[timestep][channel][baseline][pol]`. The arrays are guaranteed to have the
+/// same timestep, channel and baseline dimensions, with 4 pols.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisArray {
+    jones: Array3<Jones<f32>>,
+    weights: Array4<f32>,
+    flags: Array4<bool>,
+}
+
+impl VisArray {
+    /// Bundle visibilities with their weights and flags.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`VisArrayError::BadArrayShape`] if `weights` or `flags`
+    /// don't have the dimensions of `jones`, with 4 pols.
+    pub fn new(
+        jones: Array3<Jones<f32>>,
+        weights: Array4<f32>,
+        flags: Array4<bool>,
+    ) -> Result<Self, VisArrayError> {
+        let (num_timesteps, num_chans, num_baselines) = jones.dim();
+        let expected = (num_timesteps, num_chans, num_baselines, 4);
+        for (argument, received) in [("weights", weights.dim()), ("flags", flags.dim())] {
+            if received != expected {
+                return Err(VisArrayError::BadArrayShape {
+                    argument,
+                    expected: format!("{expected:?}"),
+                    received: format!("{received:?}"),
+                });
+            }
+        }
+        Ok(Self {
+            jones,
+            weights,
+            flags,
+        })
+    }
+
+    /// Zero visibilities with the given `[timestep][channel][baseline]`
+    /// dimensions, zero-weighted and flagged.
+    pub fn flagged(dims: (usize, usize, usize)) -> Self {
+        let pol_dims = (dims.0, dims.1, dims.2, 4);
+        Self {
+            jones: Array3::zeros(dims),
+            weights: Array4::zeros(pol_dims),
+            flags: Array4::from_elem(pol_dims, true),
+        }
+    }
+
+    /// The `[timestep][channel][baseline]` dimensions of the visibilities.
+    pub fn dim(&self) -> (usize, usize, usize) {
+        self.jones.dim()
+    }
+
+    /// The visibilities, `[timestep][channel][baseline]`.
+    pub fn jones(&self) -> ArrayView3<Jones<f32>> {
+        self.jones.view()
+    }
+
+    /// The weights, `[timestep][channel][baseline][pol]`.
+    pub fn weights(&self) -> ArrayView4<f32> {
+        self.weights.view()
+    }
+
+    /// The flags, `[timestep][channel][baseline][pol]`.
+    pub fn flags(&self) -> ArrayView4<bool> {
+        self.flags.view()
+    }
+
+    /// Mutable views of the visibilities, weights and flags. Their shapes
+    /// can't be changed through the views.
+    pub fn views_mut(
+        &mut self,
+    ) -> (
+        ArrayViewMut3<Jones<f32>>,
+        ArrayViewMut4<f32>,
+        ArrayViewMut4<bool>,
+    ) {
+        (
+            self.jones.view_mut(),
+            self.weights.view_mut(),
+            self.flags.view_mut(),
+        )
+    }
+
+    /// Unbundle the visibilities, weights and flags.
+    pub fn into_parts(self) -> VisData344 {
+        (self.jones, self.weights, self.flags)
+    }
+
+    /// Average the visibilities in time and frequency, like
+    /// [`average_visibilities`].
+    ///
+    /// # Errors
+    ///
+    /// Will return an [`AveragingError`] if the visibilities can't be averaged.
+    pub fn average(&self, avg_time: usize, avg_freq: usize) -> Result<Self, AveragingError> {
+        let (jones, weights, flags) = average_visibilities(
+            self.jones.view(),
+            self.weights.view(),
+            self.flags.view(),
+            avg_time,
+            avg_freq,
+        )?;
+        Ok(Self {
+            jones,
+            weights,
+            flags,
+        })
+    }
+
+    /// The weights given to [`crate::VisWrite::write_vis`], with a single
+    /// weight per visibility, `[timestep][channel][baseline]`. This is the
+    /// weight of the first pol, negated if the visibility is flagged according
+    /// to `policy`.
+    pub fn writer_weights(&self, policy: PolFlagPolicy) -> Array3<f32> {
+        Array3::from_shape_fn(self.dim(), |(t, c, b)| {
+            let weight = self.weights[(t, c, b, 0)].abs();
+            if policy.collapse(self.flags.slice(s![t, c, b, ..])) {
+                -weight
+            } else {
+                weight
+            }
+        })
+    }
+}
+
+impl TryFrom<VisData344> for VisArray {
+    type Error = VisArrayError;
+
+    fn try_from((jones, weights, flags): VisData344) -> Result<Self, Self::Error> {
+        Self::new(jones, weights, flags)
+    }
+}
+
+impl From<VisArray> for VisData344 {
+    fn from(vis: VisArray) -> Self {
+        vis.into_parts()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_vis_array() {
+        let jones = Array3::from_elem((2, 2, 3), Jones::identity());
+        let weights = Array4::from_elem((2, 2, 3, 4), 1.0);
+        let mut flags = Array4::from_elem((2, 2, 3, 4), false);
+        flags[(0, 0, 1, 2)] = true;
+
+        assert!(matches!(
+            VisArray::new(
+                jones.clone(),
+                weights.clone(),
+                Array4::from_elem((2, 2, 2, 4), false)
+            ),
+            Err(VisArrayError::BadArrayShape {
+                argument: "flags",
+                ..
+            })
+        ));
+        assert!(VisArray::try_from((
+            jones.clone(),
+            Array4::from_elem((2, 2, 3, 2), 1.0),
+            flags.clone()
+        ))
+        .is_err());
+
+        let vis = VisArray::new(jones, weights, flags).unwrap();
+        assert_eq!(vis.dim(), (2, 2, 3));
+
+        let writer_weights = vis.writer_weights(PolFlagPolicy::Any);
+        assert_abs_diff_eq!(writer_weights[(0, 0, 1)], -1.0);
+        assert_abs_diff_eq!(writer_weights[(1, 0, 1)], 1.0);
+        let writer_weights = vis.writer_weights(PolFlagPolicy::ParallelHand);
+        assert_abs_diff_eq!(writer_weights[(0, 0, 1)], 1.0);
+
+        let averaged = vis.average(2, 2).unwrap();
+        assert_eq!(averaged.dim(), (1, 1, 3));
+        assert_abs_diff_eq!(averaged.jones()[(0, 0, 0)], Jones::identity());
+        assert_abs_diff_eq!(averaged.weights()[(0, 0, 1, 2)], 3.0);
+
+        let flagged = VisArray::flagged((1, 2, 3));
+        assert!(flagged.flags().iter().all(|&f| f));
+        let (jones, _, _) = flagged.into_parts();
+        assert_eq!(jones.dim(), (1, 2, 3));
+    }
+}